@@ -0,0 +1,209 @@
+//! Throughput benchmarks for each of our transfer paths; run against a loopback device, such
+//! as an FX2 running bulkloop firmware, which echoes what's written to its OUT endpoint back
+//! out of its IN endpoint.
+//!
+//! Needs real hardware; so it's behind the `loopback-bench` feature, and configured through
+//! the environment:
+//!
+//! - `USRS_BENCH_DEVICE`: the device's VID:PID, in hex. Required.
+//! - `USRS_BENCH_INTERFACE`: the interface to claim. Defaults to 0.
+//! - `USRS_BENCH_IN_ENDPOINT` / `USRS_BENCH_OUT_ENDPOINT`: the bulk endpoints to use.
+//!   Default to 0x86 and 0x02, as bulkloop uses.
+//! - `USRS_BENCH_TRANSFER_SIZE`: the size of each transfer. Defaults to 16KiB.
+//! - `USRS_BENCH_SECONDS`: how long to run each benchmark. Defaults to 3.
+//!
+//! Run with e.g. `USRS_BENCH_DEVICE=04b4:1004 cargo bench --features loopback-bench`.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use usrs::device::Device;
+use usrs::{open_first, BufferPool, DeviceSelector, Transfer, UsbResult};
+
+/// How long each read we issue may take, before we decide the device has stopped talking.
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The queue depths we measure the queued paths at.
+const QUEUE_DEPTHS: [usize; 3] = [1, 4, 16];
+
+/// Our benchmark's configuration, as read from the environment.
+struct Config {
+    selector: DeviceSelector,
+    interface: u8,
+    in_endpoint: u8,
+    out_endpoint: u8,
+    transfer_size: usize,
+    duration: Duration,
+}
+
+impl Config {
+    /// Reads our configuration; or returns None if no device was specified.
+    fn from_env() -> Option<Self> {
+        let device = std::env::var("USRS_BENCH_DEVICE").ok()?;
+        let (vendor_id, product_id) = device.split_once(':')?;
+
+        Some(Self {
+            selector: DeviceSelector {
+                vendor_id: Some(u16::from_str_radix(vendor_id, 16).ok()?),
+                product_id: Some(u16::from_str_radix(product_id, 16).ok()?),
+                ..Default::default()
+            },
+            interface: env_number("USRS_BENCH_INTERFACE", 0) as u8,
+            in_endpoint: env_number("USRS_BENCH_IN_ENDPOINT", 0x86) as u8,
+            out_endpoint: env_number("USRS_BENCH_OUT_ENDPOINT", 0x02) as u8,
+            transfer_size: env_number("USRS_BENCH_TRANSFER_SIZE", 16384),
+            duration: Duration::from_secs(env_number("USRS_BENCH_SECONDS", 3) as u64),
+        })
+    }
+}
+
+/// Reads a number from the environment, in decimal or 0x-prefixed hex.
+fn env_number(name: &str, default: usize) -> usize {
+    let Ok(value) = std::env::var(name) else {
+        return default;
+    };
+
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.unwrap_or_else(|_| panic!("{name} should be a number, not {value:?}"))
+}
+
+fn main() -> UsbResult<()> {
+    env_logger::init();
+
+    let Some(config) = Config::from_env() else {
+        eprintln!("USRS_BENCH_DEVICE isn't set to a VID:PID; skipping throughput benchmarks.");
+        return Ok(());
+    };
+
+    let mut device = open_first(&config.selector)?;
+    device.claim_interface(config.interface)?;
+
+    // Our loopback device only has data to give us once we've written it; so each benchmark
+    // writes as much as it reads, and counts what comes back.
+    report(
+        "blocking write + read",
+        bench_blocking(&mut device, &config)?,
+    );
+    for depth in QUEUE_DEPTHS {
+        let name = format!("queued transfers, depth {depth}");
+        report(&name, bench_transfers(&mut device, &config, depth)?);
+    }
+    for depth in QUEUE_DEPTHS {
+        let name = format!("async reads from a pool, depth {depth}");
+        report(&name, bench_async(&mut device, &config, depth)?);
+    }
+
+    Ok(())
+}
+
+/// Prints a benchmark's result.
+fn report(name: &str, (bytes, elapsed): (usize, Duration)) {
+    let rate = bytes as f64 / elapsed.as_secs_f64() / 1_000_000.0;
+    println!("{name:40} {rate:10.2} MB/s");
+}
+
+/// Builds the data we loop through the device.
+fn pattern(length: usize) -> Vec<u8> {
+    (0..length).map(|index| index as u8).collect()
+}
+
+/// Measures one blocking write and read at a time; our slowest, simplest path.
+fn bench_blocking(device: &mut Device, config: &Config) -> UsbResult<(usize, Duration)> {
+    let data = pattern(config.transfer_size);
+    let mut buffer = vec![0; config.transfer_size];
+
+    let start = Instant::now();
+    let mut total = 0;
+    while start.elapsed() < config.duration {
+        device.write(config.out_endpoint, &data, Some(TRANSFER_TIMEOUT))?;
+        total += device.read(config.in_endpoint, &mut buffer, Some(TRANSFER_TIMEOUT))?;
+    }
+
+    Ok((total, start.elapsed()))
+}
+
+/// Measures a ring of reusable [Transfer]s in each direction; our recommended fast path.
+fn bench_transfers(
+    device: &mut Device,
+    config: &Config,
+    depth: usize,
+) -> UsbResult<(usize, Duration)> {
+    let data = pattern(config.transfer_size);
+    let mut writes = VecDeque::new();
+    let mut reads = VecDeque::new();
+
+    // Prime both rings...
+    for _ in 0..depth {
+        let mut write = Transfer::new_write(config.out_endpoint, data.clone());
+        write.set_timeout(Some(TRANSFER_TIMEOUT));
+        write.submit(device)?;
+        writes.push_back(write);
+
+        let mut read = Transfer::new_read(config.in_endpoint, config.transfer_size);
+        read.set_timeout(Some(TRANSFER_TIMEOUT));
+        read.submit(device)?;
+        reads.push_back(read);
+    }
+
+    // ... and then keep resubmitting each transfer as soon as it completes.
+    let start = Instant::now();
+    let mut total = 0;
+    while start.elapsed() < config.duration {
+        let mut write = writes.pop_front().unwrap();
+        write.wait()?;
+        write.submit(device)?;
+        writes.push_back(write);
+
+        let mut read = reads.pop_front().unwrap();
+        total += read.wait()?;
+        read.submit(device)?;
+        reads.push_back(read);
+    }
+    let elapsed = start.elapsed();
+
+    // Drain what's left in flight, so the next benchmark starts from a quiet device.
+    for mut transfer in writes.into_iter().chain(reads) {
+        _ = transfer.wait();
+    }
+
+    Ok((total, elapsed))
+}
+
+/// Measures async reads into pooled buffers, with the writes that feed them.
+fn bench_async(device: &mut Device, config: &Config, depth: usize) -> UsbResult<(usize, Duration)> {
+    let data = std::sync::Arc::new(pattern(config.transfer_size));
+    let pool = BufferPool::new(config.transfer_size, depth);
+    let mut in_flight = VecDeque::new();
+
+    let start = Instant::now();
+    let mut total = 0;
+    while start.elapsed() < config.duration {
+        // Keep our queue topped up; each buffer goes back to the pool once we're done with it.
+        while in_flight.len() < depth {
+            let write =
+                device.write_async(config.out_endpoint, data.clone(), Some(TRANSFER_TIMEOUT))?;
+            let buffer = pool.get();
+            let read = device.read_async(
+                config.in_endpoint,
+                buffer.read_buffer(),
+                Some(TRANSFER_TIMEOUT),
+            )?;
+            in_flight.push_back((write, read, buffer));
+        }
+
+        let (write, read, _buffer) = in_flight.pop_front().unwrap();
+        smol::block_on(write)?;
+        total += smol::block_on(read)?.length;
+    }
+    let elapsed = start.elapsed();
+
+    for (write, read, _buffer) in in_flight {
+        _ = smol::block_on(write);
+        _ = smol::block_on(read);
+    }
+
+    Ok((total, elapsed))
+}
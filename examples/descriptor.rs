@@ -1,22 +1,17 @@
 //! Example that reads USB descriptors from a specified device.
 
-use std::sync::Arc;
-
 use usrs::request::DescriptorType;
-use usrs::{device, open, DeviceSelector};
+use usrs::{open_first, DeviceSelector};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
-    // Find some device we're interested in working with...
-    let device_info = device(&DeviceSelector {
+    // Find some device we're interested in working with, and open it.
+    let mut device = open_first(&DeviceSelector {
         vendor_id: Some(0x1d50),
         product_id: Some(0x615c),
         ..Default::default()
     })?;
-
-    // ... open it ...
-    let mut device = open(&device_info)?;
     println!("\nOpened a device:");
     dbg!(&device);
 
@@ -33,18 +28,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     //
 
     let buffer = usrs::create_read_buffer(1024);
-    let size_read = smol::block_on(device.read_standard_descriptor_async(
+    let completion = smol::block_on(device.read_standard_descriptor_async(
         DescriptorType::Device,
         0,
-        Arc::clone(&buffer),
+        buffer,
     )?)?;
 
-    // Extract our buffer from its async encapsulation...
-    let mut buffer = buffer.write().unwrap();
-
-    // ... and print it.
+    // Extract our data from its async encapsulation, and print it.
     println!("\n\nIts device descriptor, read asynchronously:");
-    dbg!(&buffer.as_mut()[0..size_read]);
+    dbg!(completion.to_vec());
 
     Ok(())
 }
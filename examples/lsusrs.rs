@@ -6,7 +6,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
     // Create a "usb host" object, which is the top-level interface for working with USB devices.
-    let mut host = Host::new()?;
+    let host = Host::new()?;
 
     // Print each device attached to our system.
     for device in host.all_devices()? {
@@ -0,0 +1,110 @@
+//! Example that measures bulk IN throughput; using a ring of reusable transfers, which is the
+//! fastest way to stream data from a device.
+//!
+//! Usage: throughput VID:PID [endpoint] [transfer size] [queue depth]
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use usrs::{open_first, DeviceSelector, Transfer};
+
+/// How long we'll measure for.
+const MEASUREMENT_TIME: Duration = Duration::from_secs(10);
+
+/// How often we report our progress.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().collect();
+    let Some((vendor_id, product_id)) = args.get(1).and_then(|id| parse_id(id)) else {
+        eprintln!(
+            "usage: {} VID:PID [endpoint] [transfer size] [queue depth]",
+            args[0]
+        );
+        std::process::exit(1);
+    };
+    let endpoint = args.get(2).map_or(Ok(0x86), |arg| parse_number(arg))? as u8;
+    let transfer_size = args.get(3).map_or(Ok(16384), |arg| parse_number(arg))?;
+    let queue_depth = args.get(4).map_or(Ok(8), |arg| parse_number(arg))?;
+
+    // Find the device we're measuring, and claim the interface its endpoint lives on.
+    let mut device = open_first(&DeviceSelector {
+        vendor_id: Some(vendor_id),
+        product_id: Some(product_id),
+        ..Default::default()
+    })?;
+    device.claim_interface(0)?;
+
+    //
+    // Keep a ring of transfers in flight; so the host controller always has somewhere to put
+    // the next packet, even while we're busy counting the last one.
+    //
+
+    let mut queue = VecDeque::new();
+    for _ in 0..queue_depth {
+        let mut transfer = Transfer::new_read(endpoint, transfer_size);
+        transfer.submit(&mut device)?;
+        queue.push_back(transfer);
+    }
+
+    let start = Instant::now();
+    let mut last_report = start;
+    let (mut total, mut since_report) = (0, 0);
+
+    while start.elapsed() < MEASUREMENT_TIME {
+        // Transfers complete in the order we submitted them; so we only wait on the oldest,
+        // and then send it straight back out.
+        let mut transfer = queue.pop_front().unwrap();
+        let length = transfer.wait()?;
+        transfer.submit(&mut device)?;
+        queue.push_back(transfer);
+
+        total += length;
+        since_report += length;
+
+        if last_report.elapsed() >= REPORT_INTERVAL {
+            println!(
+                "{:8.2} MB/s",
+                megabytes_per_second(since_report, last_report.elapsed())
+            );
+            last_report = Instant::now();
+            since_report = 0;
+        }
+    }
+
+    // Let our last transfers land before we report our overall rate.
+    for mut transfer in queue {
+        total += transfer.wait().unwrap_or(0);
+    }
+    println!(
+        "\n{total} bytes in {:.1?}; {:.2} MB/s overall.",
+        start.elapsed(),
+        megabytes_per_second(total, start.elapsed())
+    );
+
+    Ok(())
+}
+
+/// Parses a VID:PID pair, in hex.
+fn parse_id(id: &str) -> Option<(u16, u16)> {
+    let (vendor_id, product_id) = id.split_once(':')?;
+    Some((
+        u16::from_str_radix(vendor_id, 16).ok()?,
+        u16::from_str_radix(product_id, 16).ok()?,
+    ))
+}
+
+/// Parses a number given in decimal, or in hex with a leading 0x.
+fn parse_number(number: &str) -> Result<usize, std::num::ParseIntError> {
+    match number.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => number.parse(),
+    }
+}
+
+/// Converts a byte count over a period of time into a rate.
+fn megabytes_per_second(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / elapsed.as_secs_f64() / 1_000_000.0
+}
@@ -2,66 +2,343 @@
 //! Backends can (and will) contain unsafe code, but they expose a safe interface here.
 
 use std::any::Any;
+use std::io::{IoSlice, IoSliceMut};
+use std::mem::MaybeUninit;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+#[cfg(any(feature = "callbacks", feature = "async"))]
+use std::sync::Mutex;
+#[cfg(feature = "async")]
+use std::task::Waker;
 use std::time::{Duration, SystemTime};
 
-use crate::device::{Device, DeviceInformation};
-use crate::error::UsbResult;
+use log::error;
+
+use crate::device::{
+    DeviceInformation, DeviceSelector, EndpointPolicy, EnumerationOptions, ExtraPowerKind,
+    OpenMode, OpenOptions, RawHandle, ReenumerateOptions, TransferTimeout, WriteOptions,
+};
+use crate::diagnostics::AccessProblem;
+use crate::error::{Error, UsbResult};
+#[cfg(feature = "async")]
+use crate::iso::{IsoCallback, IsoOptions};
 use crate::{ReadBuffer, WriteBuffer};
 
+#[cfg(target_os = "android")]
+mod android;
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+mod bsd;
 #[cfg(target_os = "macos")]
 mod macos;
 
-/// Trait that collects methods provided by backend USB-device information.
-pub trait BackendDevice: std::fmt::Debug + std::marker::Send + std::marker::Sync {
-    fn as_mut_any(&mut self) -> &mut dyn Any;
-    fn as_any(&self) -> &dyn Any;
+pub mod conformance;
+pub mod mock;
+pub mod prelude;
+pub mod record;
+
+/// Environment variable that, if set, names the backend a [crate::Host] should use;
+/// e.g. `USRS_BACKEND=mock`. Handy for forcing a particular backend while debugging.
+pub const BACKEND_ENVIRONMENT_VARIABLE: &str = "USRS_BACKEND";
+
+/// Environment variable that, if set, names a log for the `mock` backend to replay; see
+/// [mock::MockBackend::from_log]. The mock backend is only available when this is set.
+pub const MOCK_SCRIPT_ENVIRONMENT_VARIABLE: &str = "USRS_MOCK_SCRIPT";
+
+/// Environment variable that, if set, names a file for the `record` backend to log to; see
+/// [record::RecordingBackend]. The record backend wraps the platform's backend, and is only
+/// available when this is set.
+pub const RECORD_LOG_ENVIRONMENT_VARIABLE: &str = "USRS_RECORD_LOG";
+
+/// Called once an asynchronous transfer completes; with the number of bytes moved, or why the
+/// transfer failed. Backends may call it from whichever thread notices the completion.
+pub type TransferCallback = Box<dyn FnOnce(UsbResult<usize>) + Send>;
+
+/// Memory a backend has set aside for transfers on one of its devices; e.g. memory the OS can
+/// transfer into directly. See [BackendDeviceOps::allocate_transfer_memory].
+pub trait TransferMemory: AsRef<[u8]> + AsMut<[u8]> + Send + Sync {}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]> + Send + Sync> TransferMemory for T {}
+
+/// Iterator over the devices present on the system; see [Backend::devices_iter].
+pub type DeviceInformationIterator<'a> =
+    Box<dyn Iterator<Item = UsbResult<DeviceInformation>> + 'a>;
+
+/// A subscription to the OS's notifications of devices arriving and leaving; see
+/// [Backend::watch_devices]. Notifications stop once this is dropped.
+pub struct DeviceWatch {
+    /// Bumped by the backend each time a device is connected or disconnected.
+    generation: Arc<AtomicU64>,
+
+    /// Whatever the backend needs to keep alive for notifications to keep arriving.
+    _registration: Box<dyn Any + Send + Sync>,
 }
 
-/// Trait that unifies all of our OS-specific backends.
+impl DeviceWatch {
+    /// Creates a watch whose backend will bump `generation` on every change, for as long as
+    /// `registration` is kept alive.
+    pub fn new(generation: Arc<AtomicU64>, registration: Box<dyn Any + Send + Sync>) -> Self {
+        Self {
+            generation,
+            _registration: registration,
+        }
+    }
+
+    /// Returns a number that changes each time the set of connected devices does.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+}
+
+impl std::fmt::Debug for DeviceWatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceWatch")
+            .field("generation", &self.generation())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Someone waiting to hear that a device has gone away.
+#[cfg(any(feature = "callbacks", feature = "async"))]
+enum DisconnectWaiter {
+    /// A callback to run; see [crate::device::Device::on_disconnect].
+    #[cfg(feature = "callbacks")]
+    Callback(Box<dyn FnOnce() + Send>),
+
+    /// A task to wake; see [crate::device::Device::disconnected]. Identified by the future
+    /// that registered it; so a future that's polled again replaces its old waker, and one
+    /// that's dropped can take its waker back out.
+    #[cfg(feature = "async")]
+    Waker { id: u64, waker: Waker },
+}
+
+/// Tracks whether an open device has gone away; and tells everyone waiting, once it has.
 ///
-/// See [Device] for more detailed documentation for many of these methods,
-/// as their signatures are very close to the same.
-pub trait Backend: std::fmt::Debug + std::marker::Send + std::marker::Sync {
-    /// Returns a collection of device information for all devices present on the system.
-    fn get_devices(&self) -> UsbResult<Vec<DeviceInformation>>;
+/// Each [crate::device::Device] has one, which it hands its backend device through
+/// [BackendDevice::set_disconnect_signal]; backends with removal notifications should
+/// [notify](DisconnectSignal::notify) it when the OS says the device is gone.
+#[derive(Default)]
+pub struct DisconnectSignal {
+    /// Set once the device is gone.
+    disconnected: AtomicBool,
 
-    /// Opens a raw USB device, and returns a backend-specific wrapper around the device.
-    fn open(&self, information: &DeviceInformation) -> UsbResult<Box<dyn BackendDevice>>;
+    /// Everyone who's waiting to hear about it.
+    #[cfg(any(feature = "callbacks", feature = "async"))]
+    waiters: Mutex<Vec<DisconnectWaiter>>,
+
+    /// The ID we'll hand the next future that waits on us.
+    #[cfg(feature = "async")]
+    next_waiter_id: AtomicU64,
+}
+
+impl DisconnectSignal {
+    /// Marks the device as gone, and tells everyone waiting. Only the first call does anything.
+    pub fn notify(&self) {
+        if !self.disconnected.swap(true, Ordering::AcqRel) {
+            self.wake_waiters();
+        }
+    }
+
+    /// Runs each waiting callback, and wakes each waiting task.
+    fn wake_waiters(&self) {
+        // Take our waiters before running anything; so callbacks are free to e.g. drop the device.
+        #[cfg(any(feature = "callbacks", feature = "async"))]
+        let waiters = std::mem::take(&mut *self.waiters.lock().unwrap());
+        #[cfg(any(feature = "callbacks", feature = "async"))]
+        for waiter in waiters {
+            match waiter {
+                #[cfg(feature = "callbacks")]
+                DisconnectWaiter::Callback(callback) => callback(),
+                #[cfg(feature = "async")]
+                DisconnectWaiter::Waker { waker, .. } => waker.wake(),
+            }
+        }
+    }
+
+    /// Returns true once the device is known to be gone.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Acquire)
+    }
+
+    /// Arranges for the callback to run once the device is gone; or runs it right away, if
+    /// it already is.
+    #[cfg(feature = "callbacks")]
+    pub(crate) fn subscribe(&self, callback: Box<dyn FnOnce() + Send>) {
+        let mut waiters = self.waiters.lock().unwrap();
+
+        // We check under the lock, so we can't miss a notification that lands in between.
+        if !self.is_disconnected() {
+            waiters.push(DisconnectWaiter::Callback(callback));
+            return;
+        }
+
+        drop(waiters);
+        callback()
+    }
+
+    /// Returns a new ID for a future to register its wakers under.
+    #[cfg(feature = "async")]
+    pub(crate) fn waiter_id(&self) -> u64 {
+        self.next_waiter_id.fetch_add(1, Ordering::Relaxed)
+    }
 
-    /// Releases the kernel driver associated with the given device, if possible.
-    fn release_kernel_driver(&self, device: &mut Device, interface: u8) -> UsbResult<()>;
+    /// Arranges for the waker to be woken once the device is gone; replacing any waker
+    /// registered before under the same ID. Returns true, without keeping the waker, if
+    /// the device already is gone.
+    #[cfg(feature = "async")]
+    pub(crate) fn register_waker(&self, id: u64, waker: &Waker) -> bool {
+        let mut waiters = self.waiters.lock().unwrap();
+        if self.is_disconnected() {
+            return true;
+        }
 
-    /// Attempts to claim an interface on the given device.
-    fn claim_interface(&self, device: &mut Device, interface: u8) -> UsbResult<()>;
+        // A future may be polled many times, possibly from different tasks; it only needs
+        // its most recent waker woken.
+        let existing = waiters.iter_mut().find_map(|waiter| match waiter {
+            DisconnectWaiter::Waker {
+                id: existing,
+                waker,
+            } if *existing == id => Some(waker),
+            _ => None,
+        });
+        match existing {
+            Some(existing) => existing.clone_from(waker),
+            None => waiters.push(DisconnectWaiter::Waker {
+                id,
+                waker: waker.clone(),
+            }),
+        }
+        false
+    }
+
+    /// Forgets the waker registered under the given ID, if there is one; for futures that
+    /// are dropped before the device goes away.
+    #[cfg(feature = "async")]
+    pub(crate) fn deregister_waker(&self, id: u64) {
+        self.waiters.lock().unwrap().retain(
+            |waiter| !matches!(waiter, DisconnectWaiter::Waker { id: existing, .. } if *existing == id),
+        );
+    }
+}
+
+impl std::fmt::Debug for DisconnectSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DisconnectSignal")
+            .field("disconnected", &self.is_disconnected())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Trait that collects the operations performed on an open device.
+///
+/// Implemented by the object [Backend::open] returns, which owns whatever state the backend
+/// keeps for the device. See [crate::device::Device] for more detailed documentation for many of these
+/// methods, as their signatures are very close to the same.
+pub trait BackendDeviceOps {
+    /// Releases the kernel driver associated with the device, if possible.
+    fn release_kernel_driver(&mut self, interface: u8) -> UsbResult<()>;
+
+    /// Re-attaches the kernel driver to an interface whose driver we previously released.
+    fn attach_kernel_driver(&mut self, _interface: u8) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns the name of the kernel driver bound to the given interface; or None if no driver
+    /// is holding it.
+    fn kernel_driver_name(&self, _interface: u8) -> UsbResult<Option<String>> {
+        Err(Error::Unsupported)
+    }
+
+    /// Attempts to claim an interface on the device.
+    fn claim_interface(&mut self, interface: u8) -> UsbResult<()>;
 
     /// Attempts to release the claim held over a given interface.
-    fn unclaim_interface(&self, device: &mut Device, interface: u8) -> UsbResult<()>;
+    fn unclaim_interface(&mut self, interface: u8) -> UsbResult<()>;
+
+    /// Returns the bConfigurationValue of the active configuration, as a standard GET_CONFIGURATION
+    /// would; or 0 if the device is unconfigured. This is _not_ the configuration's index; the two
+    /// often differ, as configuration values usually start at 1.
+    fn active_configuration(&self) -> UsbResult<u8>;
+
+    /// Returns the number of configurations the device has, as the OS knows it.
+    fn num_configurations(&self) -> UsbResult<u8> {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns the OS's copy of one of the device's configuration descriptors, by index;
+    /// without asking the device for it.
+    fn cached_configuration_descriptor(&self, _configuration_index: u8) -> UsbResult<Vec<u8>> {
+        Err(Error::Unsupported)
+    }
+
+    /// Attempts to select the active configuration for the device, by its bConfigurationValue.
+    fn set_active_configuration(&mut self, configuration_value: u8) -> UsbResult<()>;
+
+    /// Attempts to bus reset the device.
+    fn reset_device(&self) -> UsbResult<()>;
+
+    /// Places the device into suspend, or brings it back out.
+    fn set_suspended(&self, _suspended: bool) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns the power the device's port can supply, in milliamps.
+    fn bus_power_available(&self) -> UsbResult<u32> {
+        Err(Error::Unsupported)
+    }
+
+    /// Asks the host for power beyond the USB limits; returning the milliamps granted.
+    fn request_extra_power(&self, _kind: ExtraPowerKind, _milliamps: u32) -> UsbResult<u32> {
+        Err(Error::Unsupported)
+    }
+
+    /// Hands power granted by [request_extra_power] back to the host.
+    fn return_extra_power(&self, _kind: ExtraPowerKind, _milliamps: u32) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
 
-    /// Returns the index of the active configuration, or 0 if the device is unconfigured.
-    fn active_configuration(&self, device: &Device) -> UsbResult<u8>;
+    /// Returns the bus bandwidth still available to the device, in bytes per (micro)frame.
+    fn bandwidth_available(&self) -> UsbResult<u32> {
+        Err(Error::Unsupported)
+    }
 
-    /// Attempts to select the active configuration for the device.
-    fn set_active_configuration(&self, device: &Device, configuration_index: u8) -> UsbResult<()>;
+    /// Asks the OS to drop and rediscover the device.
+    fn reenumerate(&mut self, _options: &ReenumerateOptions) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
 
-    /// Attempts to bus reset the given device.
-    fn reset_device(&self, device: &Device) -> UsbResult<()>;
+    /// Aborts every transfer in flight on a given endpoint address; including address 0,
+    /// the control endpoint.
+    fn abort_endpoint(&self, _endpoint_address: u8) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
 
-    /// Attempts to clear the halt condition on a given endpoint address.
-    fn clear_stall(&self, device: &Device, endpoint_address: u8) -> UsbResult<()>;
+    /// Attempts to clear the halt condition on a given endpoint address; both on the host's
+    /// side, and on the device, via CLEAR_FEATURE(ENDPOINT_HALT).
+    fn clear_stall(&self, endpoint_address: u8) -> UsbResult<()>;
+
+    /// Applies the parts of an endpoint policy that the OS supports natively. Backends whose
+    /// OS has no such knobs should return [Error::Unsupported].
+    fn set_endpoint_policy(
+        &self,
+        _endpoint_address: u8,
+        _policy: &EndpointPolicy,
+    ) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
 
     /// Configures an interface into an alternate setting.
-    fn set_alternate_setting(&self, device: &Device, interface: u8, setting: u8) -> UsbResult<()>;
+    fn set_alternate_setting(&mut self, interface: u8, setting: u8) -> UsbResult<()>;
 
     /// Returns the current USB frame number, and time at which it occurred.
     /// Precision will vary between backends.
-    fn current_bus_frame(&self, device: &Device) -> UsbResult<(u64, SystemTime)>;
+    fn current_bus_frame(&self) -> UsbResult<(u64, SystemTime)>;
 
     /// Performs an IN control request.
     /// Returns the amount actually read.
     fn control_read(
         &self,
-        device: &Device,
         request_type: u8,
         request_number: u8,
         value: u16,
@@ -73,20 +350,18 @@ pub trait Backend: std::fmt::Debug + std::marker::Send + std::marker::Sync {
     /// Performs an IN control request.
     fn control_read_nonblocking(
         &self,
-        device: &Device,
         request_type: u8,
         request_number: u8,
         value: u16,
         index: u16,
         target: ReadBuffer,
-        callback: Box<dyn FnOnce(UsbResult<usize>)>,
+        callback: TransferCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()>;
 
     /// Performs an OUT control request.
     fn control_write(
         &self,
-        device: &Device,
         request_type: u8,
         request_number: u8,
         value: u16,
@@ -98,56 +373,371 @@ pub trait Backend: std::fmt::Debug + std::marker::Send + std::marker::Sync {
     /// Performs an IN control request.
     fn control_write_nonblocking(
         &self,
-        device: &Device,
         request_type: u8,
         request_number: u8,
         value: u16,
         index: u16,
         data: WriteBuffer,
-        callback: Box<dyn FnOnce(UsbResult<usize>)>,
+        callback: TransferCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()>;
 
     /// Reads from an endpoint, for e.g. bulk reads.
-    fn read(
+    fn read(&self, endpoint: u8, buffer: &mut [u8], timeout: Option<Duration>) -> UsbResult<usize>;
+
+    /// Writes to an endpoint, for e.g. bulk writes. Returns the number of bytes actually written.
+    fn write(&self, endpoint: u8, data: &[u8], timeout: Option<Duration>) -> UsbResult<usize>;
+
+    /// Reads from an endpoint into a buffer that may not have been initialized.
+    /// Returns the number of bytes read; which are initialized once this returns.
+    ///
+    /// By default, zeroes the buffer and performs a normal read; backends that hand the OS a
+    /// raw pointer anyway should override this, to skip the zeroing.
+    fn read_uninit(
         &self,
-        device: &Device,
         endpoint: u8,
-        buffer: &mut [u8],
+        buffer: &mut [MaybeUninit<u8>],
         timeout: Option<Duration>,
-    ) -> UsbResult<usize>;
+    ) -> UsbResult<usize> {
+        buffer.fill(MaybeUninit::new(0));
+
+        // Safety: we've just initialized every byte of the buffer.
+        let buffer =
+            unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, buffer.len()) };
+        self.read(endpoint, buffer, timeout)
+    }
+
+    /// Reads from an endpoint into several buffers, filling each in turn.
+    ///
+    /// By default, reads into a single buffer and copies the data out. None of our OSes take a
+    /// scatter list from userspace (usbfs URBs, for one, each point at a single buffer); a
+    /// backend for one that does can override this.
+    fn read_vectored(
+        &self,
+        endpoint: u8,
+        buffers: &mut [IoSliceMut],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let mut coalesced = vec![0; buffers.iter().map(|buffer| buffer.len()).sum()];
+        let length = self.read(endpoint, &mut coalesced, timeout)?;
+
+        let mut remaining = &coalesced[..length];
+        for buffer in buffers {
+            let chunk = remaining.len().min(buffer.len());
+            buffer[..chunk].copy_from_slice(&remaining[..chunk]);
+            remaining = &remaining[chunk..];
+        }
+
+        Ok(length)
+    }
+
+    /// Writes the contents of several buffers to an endpoint, as a single transfer.
+    ///
+    /// By default, copies the buffers together and writes the result. As with
+    /// [BackendDeviceOps::read_vectored], a backend for an OS that can gather a transfer from
+    /// several buffers can override this.
+    fn write_vectored(
+        &self,
+        endpoint: u8,
+        data: &[IoSlice],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let coalesced: Vec<u8> = data
+            .iter()
+            .flat_map(|slice| slice.iter().copied())
+            .collect();
+        self.write(endpoint, &coalesced, timeout)
+    }
 
-    /// Writes to an endpoint, for e.g. bulk writes.
-    fn write(
+    /// Reads from an endpoint, with separate no-data and completion timeouts.
+    fn read_with_timeouts(
+        &self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        timeout: TransferTimeout,
+    ) -> UsbResult<usize> {
+        self.read(endpoint, buffer, timeout.combined())
+    }
+
+    /// Writes to an endpoint, with separate no-data and completion timeouts.
+    fn write_with_timeouts(
         &self,
-        device: &Device,
         endpoint: u8,
         data: &[u8],
+        timeout: TransferTimeout,
+    ) -> UsbResult<usize> {
+        self.write(endpoint, data, timeout.combined())
+    }
+
+    /// Writes to an endpoint, with the provided options.
+    ///
+    /// Backends that can't honor an option natively should return [Error::Unsupported];
+    /// the device will then do its best to emulate it.
+    fn write_with_options(
+        &self,
+        endpoint: u8,
+        data: &[u8],
+        options: &WriteOptions,
         timeout: Option<Duration>,
-    ) -> UsbResult<()>;
+    ) -> UsbResult<usize> {
+        if options.append_zlp {
+            return Err(Error::Unsupported);
+        }
+
+        self.write(endpoint, data, timeout)
+    }
+
+    /// Allocates `length` bytes of memory for transfers on the given endpoint; in whatever form
+    /// this backend can move data to and from most cheaply. Used by [crate::BufferPool].
+    /// By default, this is ordinary heap memory.
+    fn allocate_transfer_memory(
+        &self,
+        _endpoint: u8,
+        length: usize,
+    ) -> UsbResult<Box<dyn TransferMemory>> {
+        Ok(Box::new(vec![0; length]))
+    }
+
+    /// Allocates up to `count` USB3 bulk streams on each of the provided endpoints; which must
+    /// all belong to claimed interfaces. Returns the number of streams actually allocated.
+    /// Backends that can't do streams (e.g. macOS, for now) leave these unimplemented.
+    fn alloc_streams(&self, _endpoints: &[u8], _count: u32) -> UsbResult<u32> {
+        Err(Error::Unsupported)
+    }
+
+    /// Frees the streams previously allocated on the provided endpoints.
+    fn free_streams(&self, _endpoints: &[u8]) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Reads from a single stream of a stream-capable bulk endpoint.
+    fn read_stream(
+        &self,
+        _endpoint: u8,
+        _stream_id: u32,
+        _buffer: &mut [u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        Err(Error::Unsupported)
+    }
+
+    /// Writes to a single stream of a stream-capable bulk endpoint.
+    fn write_stream(
+        &self,
+        _endpoint: u8,
+        _stream_id: u32,
+        _data: &[u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        Err(Error::Unsupported)
+    }
 
     /// Reads from an endpoint, for e.g. bulk reads. Async.
     fn read_nonblocking(
         &self,
-        device: &Device,
         endpoint: u8,
         buffer: ReadBuffer,
-        callback: Box<dyn FnOnce(UsbResult<usize>)>,
+        callback: TransferCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()>;
 
     /// Writes to an endpoint, for e.g. bulk writes. Async.
     fn write_nonblocking(
         &self,
-        device: &Device,
         endpoint: u8,
         data: WriteBuffer,
-        callback: Box<dyn FnOnce(UsbResult<usize>)>,
+        callback: TransferCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()>;
 
-    // TODO:
-    // - Isochronous???
+    /// Reads from an isochronous endpoint; one packet per entry of `packet_lengths`, scheduled
+    /// per the provided options. Backends without a low-latency path ignore that option. Async.
+    #[cfg(feature = "async")]
+    fn read_isochronous_nonblocking(
+        &self,
+        _endpoint: u8,
+        _buffer: ReadBuffer,
+        _packet_lengths: &[usize],
+        _options: &IsoOptions,
+        _callback: IsoCallback,
+    ) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Writes to an isochronous endpoint; see [read_isochronous_nonblocking]. Async.
+    #[cfg(feature = "async")]
+    fn write_isochronous_nonblocking(
+        &self,
+        _endpoint: u8,
+        _data: WriteBuffer,
+        _packet_lengths: &[usize],
+        _options: &IsoOptions,
+        _callback: IsoCallback,
+    ) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
+}
+
+/// Trait that collects methods provided by backend USB-device information.
+pub trait BackendDevice:
+    BackendDeviceOps + std::fmt::Debug + std::marker::Send + std::marker::Sync
+{
+    fn as_mut_any(&mut self) -> &mut dyn Any;
+    fn as_any(&self) -> &dyn Any;
+
+    /// Returns true if the backend has been told (e.g. by a hotplug notification) that this
+    /// device has been removed. Backends without removal notifications can leave this be.
+    fn is_disconnected(&self) -> bool {
+        false
+    }
+
+    /// Hands the backend the signal of the [crate::device::Device] that's using it; which it should notify
+    /// when it's told the device has been removed. Backends without removal notifications
+    /// can leave this be; the device notices removal when its transfers fail.
+    fn set_disconnect_signal(&mut self, _signal: Arc<DisconnectSignal>) {}
+
+    /// Returns the OS handle used for the device as a whole, if there is one.
+    fn raw_handle(&self) -> Option<RawHandle> {
+        None
+    }
+
+    /// Returns the OS handle used for the given claimed interface, if there is one.
+    fn raw_interface_handle(&self, _interface: u8) -> Option<RawHandle> {
+        None
+    }
+}
+
+/// Trait that unifies all of our OS-specific backends.
+///
+/// Backends find devices, and open them; everything done with an open device goes through
+/// the [BackendDevice] they return.
+pub trait Backend: std::fmt::Debug + std::marker::Send + std::marker::Sync {
+    /// Returns a short name for this backend, which can be used to select it at runtime.
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    /// Returns a collection of device information for all devices present on the system.
+    fn get_devices(&self) -> UsbResult<Vec<DeviceInformation>>;
+
+    /// Returns an iterator over the devices present on the system. Where the OS allows,
+    /// each device's information is only gathered once the iterator reaches it; so callers
+    /// that stop early don't pay to query every device.
+    ///
+    /// The selector is a hint: backends whose OS can filter devices (e.g. by VID/PID) should
+    /// pass it along, and may skip devices that can't match. Callers still check the results.
+    ///
+    /// Backends that honor [EnumerationOptions::defers_strings] must still read any strings
+    /// the selector matches on, and should mark the devices whose strings they skipped.
+    fn devices_iter(
+        &self,
+        _selector: &DeviceSelector,
+        _options: &EnumerationOptions,
+    ) -> UsbResult<DeviceInformationIterator<'_>> {
+        Ok(Box::new(self.get_devices()?.into_iter().map(Ok)))
+    }
+
+    /// Reads the serial, vendor, and product strings of a device enumerated without them.
+    fn fetch_device_strings(&self, _information: &mut DeviceInformation) -> UsbResult<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Subscribes to the OS's notifications of devices being connected and disconnected.
+    /// Backends without hotplug notifications should return [Error::Unsupported].
+    fn watch_devices(&self) -> UsbResult<DeviceWatch> {
+        Err(Error::Unsupported)
+    }
+
+    /// Opens a raw USB device, and returns a backend-specific wrapper around the device.
+    fn open(&self, information: &DeviceInformation) -> UsbResult<Box<dyn BackendDevice>>;
+
+    /// Opens a raw USB device with the given options.
+    ///
+    /// Backends that only know how to open devices normally can leave this as-is.
+    fn open_with(
+        &self,
+        information: &DeviceInformation,
+        options: &OpenOptions,
+    ) -> UsbResult<Box<dyn BackendDevice>> {
+        match options.mode() {
+            OpenMode::Normal => self.open(information),
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// Wraps a file descriptor that's already been opened for us, e.g. by Android's UsbManager,
+    /// and returns a backend-specific wrapper around the device.
+    ///
+    /// Only backends whose OS hands out such descriptors support this.
+    #[cfg(unix)]
+    fn device_from_fd(&self, _fd: RawFd) -> UsbResult<Box<dyn BackendDevice>> {
+        Err(Error::Unsupported)
+    }
+
+    /// Opens the device behind an IOKit `io_service_t` we were handed, e.g. by a privileged
+    /// helper, and returns a backend-specific wrapper around it. The caller keeps its own
+    /// reference to the service.
+    #[cfg(target_os = "macos")]
+    fn device_from_service(&self, _service: u32) -> UsbResult<Box<dyn BackendDevice>> {
+        Err(Error::Unsupported)
+    }
+
+    /// Looks for anything on the system that would keep us from accessing the given device.
+    ///
+    /// Backends that don't know what to look for can leave this as-is.
+    fn diagnose_access(&self, _information: &DeviceInformation) -> UsbResult<Vec<AccessProblem>> {
+        Ok(vec![])
+    }
+}
+
+/// Creates each of the backends built in for the current platform, in priority order.
+///
+/// The platform's own backend always comes first. The `record` and `mock` backends follow it,
+/// if [RECORD_LOG_ENVIRONMENT_VARIABLE] and [MOCK_SCRIPT_ENVIRONMENT_VARIABLE] say where their
+/// logs live; so they can be picked with [BACKEND_ENVIRONMENT_VARIABLE].
+pub fn create_default_backends() -> UsbResult<Vec<Arc<dyn Backend>>> {
+    let mut backends: Vec<Arc<dyn Backend>> = vec![];
+
+    let platform = create_default_backend();
+    if let Ok(platform) = &platform {
+        backends.push(Arc::clone(platform));
+
+        if let Some(path) = std::env::var_os(RECORD_LOG_ENVIRONMENT_VARIABLE) {
+            let recorder = record::RecordingBackend::new(Arc::clone(platform), path)?;
+            backends.push(Arc::new(recorder));
+        }
+    }
+
+    if let Some(path) = std::env::var_os(MOCK_SCRIPT_ENVIRONMENT_VARIABLE) {
+        backends.push(Arc::new(mock::MockBackend::from_log(path)?));
+    }
+
+    // A mock can stand in for a platform backend that won't start; but if there's nothing
+    // else, report why the platform's didn't.
+    if backends.is_empty() {
+        platform?;
+    }
+
+    Ok(backends)
+}
+
+/// Picks a backend from a list of candidates, which are given in priority order.
+///
+/// If a preferred backend is named, it's used; otherwise, we'll take the first candidate.
+pub fn select_backend(
+    candidates: Vec<Arc<dyn Backend>>,
+    preferred: Option<&str>,
+) -> UsbResult<Arc<dyn Backend>> {
+    match preferred {
+        Some(name) => candidates
+            .into_iter()
+            .find(|backend| backend.name() == name)
+            .ok_or_else(|| {
+                error!("backend `{name}` was requested, but isn't available");
+                Error::Unsupported
+            }),
+        None => candidates.into_iter().next().ok_or(Error::Unsupported),
+    }
 }
 
 /// Creates a default backend implementation for MacOS machines.
@@ -155,3 +745,20 @@ pub trait Backend: std::fmt::Debug + std::marker::Send + std::marker::Sync {
 pub fn create_default_backend() -> UsbResult<Arc<dyn Backend>> {
     Ok(Arc::new(macos::MacOsBackend::new()?))
 }
+
+/// Creates a default backend implementation for Android devices.
+#[cfg(target_os = "android")]
+pub fn create_default_backend() -> UsbResult<Arc<dyn Backend>> {
+    Ok(Arc::new(android::AndroidBackend::new()?))
+}
+
+/// Creates a default backend implementation for FreeBSD and OpenBSD machines.
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+pub fn create_default_backend() -> UsbResult<Arc<dyn Backend>> {
+    Ok(Arc::new(bsd::BsdBackend::new()?))
+}
+
+#[cfg(target_os = "linux")]
+pub fn create_default_backend() -> UsbResult<Arc<dyn Backend>> {
+    Err(Error::Unsupported)
+}
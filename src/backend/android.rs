@@ -0,0 +1,1037 @@
+//! Backend for Android, which drives devices opened on our behalf by the Java side of the app.
+//!
+//! Android apps can't enumerate or open `/dev/bus/usb` themselves; instead, the app asks
+//! `UsbManager.openDevice()` for a connection, and hands us the file descriptor from
+//! `UsbDeviceConnection.getFileDescriptor()`. That descriptor speaks the normal Linux usbfs
+//! ioctl interface, which is what we use here.
+//!
+//! The file descriptor remains owned by the UsbDeviceConnection; which must outlive the Device.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    ffi::c_void,
+    mem::MaybeUninit,
+    os::unix::io::RawFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use libc::c_uint;
+use log::{error, warn};
+use parking_lot::RwLock;
+
+use self::usbfs::{
+    error_from_errno, last_errno, read_cached_descriptors, streams_request, usbfs_ioctl,
+    BulkTransfer, CtrlTransfer, GetDriver, IoctlRequest, SetInterface, Urb, SETUP_PACKET_SIZE,
+    USBDEVFS_ALLOC_STREAMS, USBDEVFS_BULK, USBDEVFS_CLAIMINTERFACE, USBDEVFS_CLEAR_HALT,
+    USBDEVFS_CONNECT, USBDEVFS_CONTROL, USBDEVFS_DISCARDURB, USBDEVFS_DISCONNECT,
+    USBDEVFS_FREE_STREAMS, USBDEVFS_GETDRIVER, USBDEVFS_IOCTL, USBDEVFS_MAXDRIVERNAME,
+    USBDEVFS_REAPURBNDELAY, USBDEVFS_RELEASEINTERFACE, USBDEVFS_RESET, USBDEVFS_SETCONFIGURATION,
+    USBDEVFS_SETINTERFACE, USBDEVFS_SUBMITURB, USBDEVFS_URB_TYPE_BULK, USBDEVFS_URB_TYPE_CONTROL,
+    USBDEVFS_URB_ZERO_PACKET,
+};
+use super::{
+    Backend, BackendDevice, BackendDeviceOps, DeviceInformation, TransferCallback, TransferMemory,
+};
+use crate::{
+    descriptors::DeviceDescriptor,
+    device::{RawHandle, WriteOptions},
+    request::{SetupPacket, StandardDeviceRequest, STANDARD_IN_FROM_DEVICE},
+    Error, ReadBuffer, ReadBufferGuard, UsbResult, WriteBuffer,
+};
+
+mod usbfs;
+
+/// How long our event thread waits for completions before checking whether it should exit.
+const EVENT_LOOP_GRANULARITY_MS: libc::c_int = 100;
+
+/// Helper that converts a timeout into the form usbfs wants: milliseconds, with 0 meaning forever.
+fn to_usbfs_timeout(timeout: Option<Duration>) -> c_uint {
+    match timeout {
+        Some(timeout) => timeout.as_millis().clamp(1, c_uint::MAX as u128) as c_uint,
+        None => 0,
+    }
+}
+
+/// Context for a URB in flight. The URB must come first; usbfs hands us back a pointer
+/// to the URB on completion, and we convert that back into a pointer to its context.
+#[repr(C)]
+struct PendingTransfer {
+    /// The URB itself; which the kernel owns until it's reaped.
+    urb: Urb,
+
+    /// The buffer the URB points into. For control transfers, this starts with the setup packet.
+    buffer: Vec<u8>,
+
+    /// For reads, where the data should end up once the transfer is complete.
+    target: Option<ReadBuffer>,
+
+    /// For reads straight into usbfs memory, our hold on the caller's buffer; which the URB
+    /// points into, and so must outlive it.
+    mapped: Option<ReadBufferGuard>,
+
+    /// The callback to be issued on completion.
+    callback: TransferCallback,
+
+    /// When the transfer should be discarded for taking too long, if ever.
+    deadline: Option<Instant>,
+}
+
+/// Transfers that have been submitted, but not yet reaped; keyed by the address of their URB.
+type PendingTransfers = Arc<Mutex<HashMap<usize, Option<Instant>>>>;
+
+/// The usbfs memory we've mapped for a device; as lengths, keyed by address.
+type Mappings = Arc<Mutex<HashMap<usize, usize>>>;
+
+/// Memory mapped from usbfs; which the kernel can transfer into directly, rather than through
+/// a buffer of our own. The mapping outlives the file descriptor; it's unmapped when dropped.
+struct UsbfsMemory {
+    /// Where the memory is mapped.
+    address: *mut u8,
+
+    /// The size of the mapping, in bytes.
+    length: usize,
+
+    /// The device's list of mappings; which we remove ourselves from when dropped.
+    mappings: Mappings,
+}
+
+// The mapping is plain memory, which is only ever touched through &self or &mut self.
+unsafe impl Send for UsbfsMemory {}
+unsafe impl Sync for UsbfsMemory {}
+
+impl AsRef<[u8]> for UsbfsMemory {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.address, self.length) }
+    }
+}
+
+impl AsMut<[u8]> for UsbfsMemory {
+    fn as_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.address, self.length) }
+    }
+}
+
+impl Drop for UsbfsMemory {
+    fn drop(&mut self) {
+        self.mappings
+            .lock()
+            .unwrap()
+            .remove(&(self.address as usize));
+        unsafe { libc::munmap(self.address as *mut c_void, self.length) };
+    }
+}
+
+/// Per-device data for the Android backend.
+#[derive(Debug)]
+pub(crate) struct AndroidDevice {
+    /// The usbfs file descriptor we were handed.
+    fd: RawFd,
+
+    /// The transfers we currently have in flight.
+    pending: PendingTransfers,
+
+    /// The usbfs memory we've handed out; see [UsbfsMemory].
+    mappings: Mappings,
+
+    /// Flag used to indicate when this device is being dropped, and thus its thread should die.
+    termination_flag: Arc<AtomicBool>,
+
+    /// The thread that reaps our completed transfers; which uses our file descriptor until it
+    /// exits, and so must be joined before we let go of it.
+    event_thread: Option<JoinHandle<()>>,
+}
+
+impl AndroidDevice {
+    /// Wraps a usbfs file descriptor, and starts the thread that handles its completions.
+    fn from_fd(fd: RawFd) -> UsbResult<Self> {
+        if fd < 0 {
+            return Err(Error::InvalidArgument);
+        }
+
+        let pending: PendingTransfers = Arc::default();
+        let termination_flag = Arc::new(AtomicBool::new(false));
+
+        // Spin up a thread to reap our completed transfers.
+        let event_thread = {
+            let pending = Arc::clone(&pending);
+            let termination_flag = Arc::clone(&termination_flag);
+            std::thread::spawn(move || run_event_loop(fd, pending, termination_flag))
+        };
+
+        Ok(Self {
+            fd,
+            pending,
+            mappings: Arc::default(),
+            termination_flag,
+            event_thread: Some(event_thread),
+        })
+    }
+
+    /// Submits a URB, handing ownership of its context to the kernel until it's reaped.
+    fn submit(&self, transfer: Box<PendingTransfer>) -> UsbResult<()> {
+        let deadline = transfer.deadline;
+        let transfer = Box::into_raw(transfer);
+
+        unsafe {
+            // Point the URB back at its own context, so we can find it once it's reaped...
+            (*transfer).urb.usercontext = transfer as *mut c_void;
+            let urb = std::ptr::addr_of_mut!((*transfer).urb);
+
+            // ... mark it as pending, so our event thread will know to look for it...
+            let mut pending = self.pending.lock().unwrap();
+            pending.insert(urb as usize, deadline);
+
+            // ... and hand it to the kernel. If the kernel doesn't take it, it's ours again.
+            if let Err(error) = usbfs_ioctl(self.fd, USBDEVFS_SUBMITURB, urb as *mut c_void) {
+                pending.remove(&(urb as usize));
+                drop(Box::from_raw(transfer));
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Helper for issuing ioctls that take a single unsigned int argument.
+    fn ioctl_with_uint(&self, request: libc::c_ulong, value: c_uint) -> UsbResult<()> {
+        let mut value = value;
+        unsafe {
+            usbfs_ioctl(self.fd, request, &mut value as *mut c_uint as *mut c_void)?;
+        }
+        Ok(())
+    }
+
+    /// Helper for issuing synchronous control requests; whose data stage is the
+    /// `setup.wLength` bytes at `data`.
+    fn control(
+        &self,
+        setup: &SetupPacket,
+        data: *mut c_void,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let mut request = CtrlTransfer {
+            request_type: setup.bmRequestType,
+            request: setup.bRequest,
+            value: setup.wValue,
+            index: setup.wIndex,
+            length: setup.wLength,
+            timeout: to_usbfs_timeout(timeout),
+            data,
+        };
+
+        unsafe {
+            usbfs_ioctl(
+                self.fd,
+                USBDEVFS_CONTROL,
+                &mut request as *mut CtrlTransfer as *mut c_void,
+            )
+        }
+    }
+
+    /// Helper for issuing synchronous bulk (or interrupt) transfers.
+    fn bulk(
+        &self,
+        address: u8,
+        data: *mut c_void,
+        length: usize,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let mut request = BulkTransfer {
+            endpoint: address as c_uint,
+            length: c_uint::try_from(length).map_err(|_| Error::Overrun)?,
+            timeout: to_usbfs_timeout(timeout),
+            data,
+        };
+
+        unsafe {
+            usbfs_ioctl(
+                self.fd,
+                USBDEVFS_BULK,
+                &mut request as *mut BulkTransfer as *mut c_void,
+            )
+        }
+    }
+
+    /// Helper for submitting asynchronous control requests.
+    fn control_nonblocking(
+        &self,
+        setup: &SetupPacket,
+        data: &[u8],
+        target: Option<ReadBuffer>,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        // usbfs expects control buffers to start with the setup packet.
+        let mut buffer = Vec::with_capacity(SETUP_PACKET_SIZE + data.len());
+        buffer.extend_from_slice(&setup.to_bytes());
+        buffer.extend_from_slice(data);
+
+        self.submit_urb(
+            USBDEVFS_URB_TYPE_CONTROL,
+            0,
+            buffer,
+            target,
+            callback,
+            timeout,
+        )
+    }
+
+    /// Submits a bulk URB on one of an endpoint's streams, and waits for it to complete.
+    fn transfer_on_stream(
+        &self,
+        address: u8,
+        stream_id: u32,
+        mut buffer: Vec<u8>,
+        target: Option<ReadBuffer>,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let mut urb = Urb::new(USBDEVFS_URB_TYPE_BULK, address, &mut buffer);
+        urb.number_of_packets = stream_id as libc::c_int;
+
+        self.submit_and_wait(urb, buffer, target, timeout)
+    }
+
+    /// Submits a URB, and blocks until our event thread completes it.
+    ///
+    /// Completion callbacks run on the event thread; so if we're in one, the transfer could
+    /// never complete while we wait. There, we fail with [Error::Unsupported] instead.
+    fn submit_and_wait(
+        &self,
+        urb: Urb,
+        buffer: Vec<u8>,
+        target: Option<ReadBuffer>,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        if self.on_event_thread() {
+            error!("can't wait for a URB from a completion callback; it would never complete");
+            return Err(Error::Unsupported);
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let transfer = Box::new(PendingTransfer {
+            urb,
+            buffer,
+            target,
+            mapped: None,
+            callback: Box::new(move |result| _ = sender.send(result)),
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+        });
+        self.submit(transfer)?;
+
+        // If our event thread goes away without completing the transfer, it's been aborted.
+        receiver.recv().unwrap_or(Err(Error::Aborted))
+    }
+
+    /// Returns true iff we're running on our event thread; e.g. in a completion callback.
+    fn on_event_thread(&self) -> bool {
+        self.event_thread
+            .as_ref()
+            .is_some_and(|thread| thread.thread().id() == std::thread::current().id())
+    }
+
+    /// Returns true if the given memory lies entirely within usbfs memory we've mapped.
+    fn is_mapped(&self, memory: &[u8]) -> bool {
+        let start = memory.as_ptr() as usize;
+        self.mappings
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(&address, &length)| start >= address && start + memory.len() <= address + length)
+    }
+
+    /// Helper that packages up a URB, and submits it.
+    fn submit_urb(
+        &self,
+        urb_type: libc::c_uchar,
+        address: u8,
+        mut buffer: Vec<u8>,
+        target: Option<ReadBuffer>,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let urb = Urb::new(urb_type, address, &mut buffer);
+
+        // Moving the Vec into the box doesn't move its heap allocation; so the URB's
+        // buffer pointer remains valid.
+        let transfer = Box::new(PendingTransfer {
+            urb,
+            buffer,
+            target,
+            mapped: None,
+            callback,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+        });
+
+        self.submit(transfer)
+    }
+}
+
+impl BackendDevice for AndroidDevice {
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn raw_handle(&self) -> Option<RawHandle> {
+        Some(RawHandle::FileDescriptor(self.fd))
+    }
+}
+
+impl Drop for AndroidDevice {
+    fn drop(&mut self) {
+        // Let our event thread know it can stop running; it'll clean up anything still in flight.
+        self.termination_flag.store(true, Ordering::Relaxed);
+
+        // The file descriptor belongs to our caller, who's free to close it once we're gone;
+        // so wait for our thread to finish with it. That's up to one poll interval, plus
+        // however long cleanup takes. If we're being dropped from a completion callback, we're
+        // on that thread; it'll exit once the callback returns.
+        if !self.on_event_thread() {
+            if let Some(event_thread) = self.event_thread.take() {
+                _ = event_thread.join();
+            }
+        }
+    }
+}
+
+/// Completes a URB that's been reaped from the kernel; issuing its callback.
+///
+/// # Safety
+/// The URB must be one we submitted via [AndroidDevice::submit], and must have been reaped.
+unsafe fn complete_transfer(urb: *mut Urb, timed_out: bool) {
+    let mut transfer = Box::from_raw((*urb).usercontext as *mut PendingTransfer);
+
+    let result = match transfer.urb.status {
+        0 => Ok(transfer.urb.actual_length as usize),
+        _ if timed_out => Err(Error::TimedOut),
+        status => Err(error_from_errno(-status)),
+    };
+
+    // If the kernel read straight into our caller's buffer, it's theirs again...
+    drop(transfer.mapped.take());
+
+    // ... and if we read into a buffer of our own, copy the data to where our caller wants it.
+    if let (Ok(length), Some(target)) = (&result, transfer.target.take()) {
+        let offset = match transfer.urb.urb_type {
+            USBDEVFS_URB_TYPE_CONTROL => SETUP_PACKET_SIZE,
+            _ => 0,
+        };
+
+        let mut target = target.write();
+        let target = target.as_mut();
+        let length = (*length).min(target.len());
+        target[..length].copy_from_slice(&transfer.buffer[offset..offset + length]);
+    }
+
+    (transfer.callback)(result);
+}
+
+/// Reaps every URB the kernel has finished with; returning false if the device has gone away.
+fn reap_completed(fd: RawFd, pending: &PendingTransfers, timed_out: &mut Vec<usize>) -> bool {
+    loop {
+        let mut urb: *mut Urb = std::ptr::null_mut();
+        let result = unsafe {
+            usbfs_ioctl(
+                fd,
+                USBDEVFS_REAPURBNDELAY,
+                &mut urb as *mut *mut Urb as *mut c_void,
+            )
+        };
+
+        match result {
+            Ok(_) => {
+                pending.lock().unwrap().remove(&(urb as usize));
+
+                let was_timed_out = timed_out.contains(&(urb as usize));
+                timed_out.retain(|&address| address != urb as usize);
+
+                unsafe { complete_transfer(urb, was_timed_out) };
+            }
+            Err(Error::Disconnected) => return false,
+            Err(_) => return true,
+        }
+    }
+}
+
+/// Discards each transfer in the given list, so the kernel hands them back to us.
+fn discard_transfers(fd: RawFd, transfers: &[usize]) {
+    for &urb in transfers {
+        // If the discard fails, the transfer has already completed; and we'll reap it normally.
+        _ = unsafe { usbfs_ioctl(fd, USBDEVFS_DISCARDURB, urb as *mut c_void) };
+    }
+}
+
+/// Event loop for a single device; reaps completed transfers, and enforces their timeouts.
+fn run_event_loop(fd: RawFd, pending: PendingTransfers, termination_flag: Arc<AtomicBool>) {
+    let mut timed_out: Vec<usize> = vec![];
+
+    while !termination_flag.load(Ordering::Relaxed) {
+        // Wait for the kernel to tell us something's completed; usbfs signals this as writability...
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLOUT,
+            revents: 0,
+        };
+        let rc = unsafe { libc::poll(&mut poll_fd, 1, EVENT_LOOP_GRANULARITY_MS) };
+        if rc < 0 && last_errno() != libc::EINTR {
+            error!("android backend: poll failed; errno {}", last_errno());
+        }
+
+        // ... reap anything that's done...
+        if !reap_completed(fd, &pending, &mut timed_out) {
+            // The device is gone; everything in flight has been handed back, so there's
+            // nothing left for us to do but wait to be told to exit.
+            std::thread::sleep(Duration::from_millis(EVENT_LOOP_GRANULARITY_MS as u64));
+            continue;
+        }
+
+        // ... and discard anything that's overstayed its welcome.
+        let now = Instant::now();
+        let expired: Vec<usize> = pending
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(urb, deadline)| {
+                deadline.is_some_and(|deadline| deadline <= now) && !timed_out.contains(urb)
+            })
+            .map(|(&urb, _)| urb)
+            .collect();
+        discard_transfers(fd, &expired);
+        timed_out.extend(expired);
+    }
+
+    // We're shutting down; the kernel still owns any buffers it hasn't handed back, so we need
+    // to discard everything in flight and reap it before our transfer contexts can be freed.
+    let outstanding: Vec<usize> = pending.lock().unwrap().keys().copied().collect();
+    discard_transfers(fd, &outstanding);
+
+    for _ in 0..10 {
+        if pending.lock().unwrap().is_empty() || !reap_completed(fd, &pending, &mut timed_out) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let leaked = pending.lock().unwrap().len();
+    if leaked != 0 {
+        warn!(
+            "android backend: {leaked} transfer(s) never came back from the kernel; leaking them"
+        );
+    }
+}
+
+/// Backend for Android; which works only with devices opened via UsbManager.
+#[derive(Debug)]
+pub struct AndroidBackend {}
+
+impl AndroidBackend {
+    pub fn new() -> UsbResult<AndroidBackend> {
+        Ok(AndroidBackend {})
+    }
+}
+
+/// Builds the setup packet for a control request; or fails with [Error::Overrun] if its data
+/// stage is too long to describe.
+fn setup_packet(
+    request_type: u8,
+    request_number: u8,
+    value: u16,
+    index: u16,
+    length: usize,
+) -> UsbResult<SetupPacket> {
+    Ok(SetupPacket {
+        bmRequestType: request_type,
+        bRequest: request_number,
+        wValue: value,
+        wIndex: index,
+        wLength: u16::try_from(length).map_err(|_| Error::Overrun)?,
+    })
+}
+
+impl Backend for AndroidBackend {
+    fn name(&self) -> &'static str {
+        "android"
+    }
+
+    fn get_devices(&self) -> UsbResult<Vec<DeviceInformation>> {
+        // Android doesn't let apps enumerate devices themselves; that has to happen
+        // on the Java side, via UsbManager.getDeviceList().
+        Err(Error::Unsupported)
+    }
+
+    fn open(&self, _information: &DeviceInformation) -> UsbResult<Box<dyn BackendDevice>> {
+        // Likewise, opening happens via UsbManager; see [crate::Host::device_from_fd].
+        Err(Error::Unsupported)
+    }
+
+    fn device_from_fd(&self, fd: RawFd) -> UsbResult<Box<dyn BackendDevice>> {
+        Ok(Box::new(AndroidDevice::from_fd(fd)?))
+    }
+}
+
+impl BackendDeviceOps for AndroidDevice {
+    fn release_kernel_driver(&mut self, interface: u8) -> UsbResult<()> {
+        let mut request = IoctlRequest {
+            interface: interface as libc::c_int,
+            ioctl_code: USBDEVFS_DISCONNECT as libc::c_int,
+            data: std::ptr::null_mut(),
+        };
+
+        let result = unsafe {
+            usbfs_ioctl(
+                self.fd,
+                USBDEVFS_IOCTL,
+                &mut request as *mut IoctlRequest as *mut c_void,
+            )
+        };
+
+        // If there's no driver bound to the interface, there's nothing to release.
+        match result {
+            Err(Error::OsError(errno)) if errno == libc::ENODATA as i64 => Ok(()),
+            other => other.map(|_| ()),
+        }
+    }
+
+    fn attach_kernel_driver(&mut self, interface: u8) -> UsbResult<()> {
+        let mut request = IoctlRequest {
+            interface: interface as libc::c_int,
+            ioctl_code: USBDEVFS_CONNECT as libc::c_int,
+            data: std::ptr::null_mut(),
+        };
+
+        unsafe {
+            usbfs_ioctl(
+                self.fd,
+                USBDEVFS_IOCTL,
+                &mut request as *mut IoctlRequest as *mut c_void,
+            )
+        }
+        .map(|_| ())
+    }
+
+    fn kernel_driver_name(&self, interface: u8) -> UsbResult<Option<String>> {
+        let mut request = GetDriver {
+            interface: interface as c_uint,
+            driver: [0; USBDEVFS_MAXDRIVERNAME + 1],
+        };
+
+        let result = unsafe {
+            usbfs_ioctl(
+                self.fd,
+                USBDEVFS_GETDRIVER,
+                &mut request as *mut GetDriver as *mut c_void,
+            )
+        };
+
+        match result {
+            // If there's no driver bound at all, there's no name to report...
+            Err(Error::OsError(errno)) if errno == libc::ENODATA as i64 => Ok(None),
+            Err(other) => Err(other),
+            Ok(_) => {
+                let length = request
+                    .driver
+                    .iter()
+                    .position(|&b| b == 0)
+                    .unwrap_or(request.driver.len());
+                let name = String::from_utf8_lossy(&request.driver[..length]).into_owned();
+
+                // ... and if the driver is usbfs, it's just us, having claimed the interface.
+                Ok((name != "usbfs").then_some(name))
+            }
+        }
+    }
+
+    fn claim_interface(&mut self, interface: u8) -> UsbResult<()> {
+        self.ioctl_with_uint(USBDEVFS_CLAIMINTERFACE, interface as c_uint)
+    }
+
+    fn unclaim_interface(&mut self, interface: u8) -> UsbResult<()> {
+        self.ioctl_with_uint(USBDEVFS_RELEASEINTERFACE, interface as c_uint)
+    }
+
+    fn active_configuration(&self) -> UsbResult<u8> {
+        // usbfs doesn't have a way to ask, so we'll just ask the device.
+        let mut configuration: u8 = 0;
+        let setup = setup_packet(
+            STANDARD_IN_FROM_DEVICE.into(),
+            StandardDeviceRequest::GetConfiguration.into(),
+            0,
+            0,
+            1,
+        )?;
+        self.control(
+            &setup,
+            &mut configuration as *mut u8 as *mut c_void,
+            Some(Duration::from_secs(1)),
+        )?;
+
+        Ok(configuration)
+    }
+
+    fn num_configurations(&self) -> UsbResult<u8> {
+        let descriptors = read_cached_descriptors(self.fd)?;
+        let device_descriptor = descriptors
+            .get(..DeviceDescriptor::LENGTH)
+            .ok_or(Error::MalformedDescriptor)?;
+
+        Ok(DeviceDescriptor::parse(device_descriptor)?.num_configurations)
+    }
+
+    fn cached_configuration_descriptor(&self, configuration_index: u8) -> UsbResult<Vec<u8>> {
+        let descriptors = read_cached_descriptors(self.fd)?;
+
+        // Skip the device descriptor, and then every configuration before the one we want...
+        let mut remaining = descriptors
+            .get(DeviceDescriptor::LENGTH..)
+            .ok_or(Error::MalformedDescriptor)?;
+        for _ in 0..configuration_index {
+            remaining = remaining
+                .get(configuration_total_length(remaining)?..)
+                .ok_or(Error::MalformedDescriptor)?;
+        }
+
+        // ... and hand back just the one we want.
+        let length = configuration_total_length(remaining)?;
+        remaining
+            .get(..length)
+            .map(<[u8]>::to_vec)
+            .ok_or(Error::MalformedDescriptor)
+    }
+
+    fn set_active_configuration(&mut self, configuration_value: u8) -> UsbResult<()> {
+        self.ioctl_with_uint(USBDEVFS_SETCONFIGURATION, configuration_value as c_uint)
+    }
+
+    fn reset_device(&self) -> UsbResult<()> {
+        unsafe {
+            usbfs_ioctl(self.fd, USBDEVFS_RESET, std::ptr::null_mut())?;
+        }
+        Ok(())
+    }
+
+    fn abort_endpoint(&self, endpoint_address: u8) -> UsbResult<()> {
+        // Hold the pending list while we look through it, so nothing we find can be reaped (and
+        // freed) out from under us. The kernel hands discarded transfers back to our event
+        // thread, which completes them with Error::Aborted.
+        let pending = self.pending.lock().unwrap();
+        let targets: Vec<usize> = pending
+            .keys()
+            .copied()
+            .filter(|&urb| unsafe { (*(urb as *const Urb)).endpoint } == endpoint_address)
+            .collect();
+
+        discard_transfers(self.fd, &targets);
+        Ok(())
+    }
+
+    fn clear_stall(&self, endpoint_address: u8) -> UsbResult<()> {
+        self.ioctl_with_uint(USBDEVFS_CLEAR_HALT, endpoint_address as c_uint)
+    }
+
+    fn set_alternate_setting(&mut self, interface: u8, setting: u8) -> UsbResult<()> {
+        let mut request = SetInterface {
+            interface: interface as c_uint,
+            alternate_setting: setting as c_uint,
+        };
+
+        unsafe {
+            usbfs_ioctl(
+                self.fd,
+                USBDEVFS_SETINTERFACE,
+                &mut request as *mut SetInterface as *mut c_void,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn current_bus_frame(&self) -> UsbResult<(u64, SystemTime)> {
+        // usbfs only exposes frame numbers via completed isochronous transfers.
+        Err(Error::Unsupported)
+    }
+
+    fn control_read(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let setup = setup_packet(request_type, request_number, value, index, target.len())?;
+        self.control(&setup, target.as_mut_ptr() as *mut c_void, timeout)
+    }
+
+    fn control_read_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: ReadBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        // The setup packet carries the request's length; and a ReadBuffer only lends out its
+        // size through the write lock.
+        let length = target.write().as_mut().len();
+        let setup = setup_packet(request_type, request_number, value, index, length)?;
+
+        self.control_nonblocking(&setup, &vec![0; length], Some(target), callback, timeout)
+    }
+
+    fn control_write(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        // usbfs wants a mutable pointer, but won't write through it for OUT requests.
+        let setup = setup_packet(request_type, request_number, value, index, data.len())?;
+        self.control(&setup, data.as_ptr() as *mut c_void, timeout)?;
+        Ok(())
+    }
+
+    fn control_write_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let data = (*data).as_ref();
+        let setup = setup_packet(request_type, request_number, value, index, data.len())?;
+
+        self.control_nonblocking(&setup, data, None, callback, timeout)
+    }
+
+    fn read(&self, endpoint: u8, buffer: &mut [u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        self.bulk(
+            endpoint | 0x80,
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.len(),
+            timeout,
+        )
+    }
+
+    fn read_uninit(
+        &self,
+        endpoint: u8,
+        buffer: &mut [MaybeUninit<u8>],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        // The kernel only ever writes to this buffer; so it's fine for it to start uninitialized.
+        self.bulk(
+            endpoint | 0x80,
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.len(),
+            timeout,
+        )
+    }
+
+    fn write(&self, endpoint: u8, data: &[u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        self.bulk(endpoint, data.as_ptr() as *mut c_void, data.len(), timeout)
+    }
+
+    fn write_with_options(
+        &self,
+        endpoint: u8,
+        data: &[u8],
+        options: &WriteOptions,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        if !options.append_zlp {
+            return self.write(endpoint, data, timeout);
+        }
+
+        // USBDEVFS_BULK has no way to ask for a ZLP; so we'll submit the transfer as a URB
+        // that does, and wait for it to complete.
+        let mut buffer = data.to_vec();
+        let mut urb = Urb::new(USBDEVFS_URB_TYPE_BULK, endpoint, &mut buffer);
+        urb.flags = USBDEVFS_URB_ZERO_PACKET;
+
+        self.submit_and_wait(urb, buffer, None, timeout)
+    }
+
+    fn alloc_streams(&self, endpoints: &[u8], count: u32) -> UsbResult<u32> {
+        let mut request = streams_request(count, endpoints);
+
+        // On success, usbfs tells us how many streams it actually managed to allocate.
+        let allocated = unsafe {
+            usbfs_ioctl(
+                self.fd,
+                USBDEVFS_ALLOC_STREAMS,
+                request.as_mut_ptr() as *mut c_void,
+            )?
+        };
+        Ok(allocated as u32)
+    }
+
+    fn free_streams(&self, endpoints: &[u8]) -> UsbResult<()> {
+        let mut request = streams_request(0, endpoints);
+
+        unsafe {
+            usbfs_ioctl(
+                self.fd,
+                USBDEVFS_FREE_STREAMS,
+                request.as_mut_ptr() as *mut c_void,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn read_stream(
+        &self,
+        endpoint: u8,
+        stream_id: u32,
+        buffer: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        // The URB's data lands in a buffer of our own; which we'll copy out once it's done.
+        let target = Arc::new(RwLock::new(vec![0; buffer.len()]));
+        let length = self.transfer_on_stream(
+            endpoint | 0x80,
+            stream_id,
+            vec![0; buffer.len()],
+            Some(target.clone()),
+            timeout,
+        )?;
+
+        buffer[..length].copy_from_slice(&target.read()[..length]);
+        Ok(length)
+    }
+
+    fn write_stream(
+        &self,
+        endpoint: u8,
+        stream_id: u32,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        self.transfer_on_stream(endpoint & 0x7f, stream_id, data.to_vec(), None, timeout)
+    }
+
+    fn allocate_transfer_memory(
+        &self,
+        _endpoint: u8,
+        length: usize,
+    ) -> UsbResult<Box<dyn TransferMemory>> {
+        if length == 0 {
+            return Ok(Box::new(vec![]));
+        }
+
+        let address = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                length,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                self.fd,
+                0,
+            )
+        };
+
+        // Kernels before 4.6 can't map usbfs memory; plain memory works there, with a copy.
+        if address == libc::MAP_FAILED {
+            return match last_errno() {
+                libc::ENODEV => Ok(Box::new(vec![0; length])),
+                errno => Err(error_from_errno(errno)),
+            };
+        }
+
+        self.mappings
+            .lock()
+            .unwrap()
+            .insert(address as usize, length);
+        Ok(Box::new(UsbfsMemory {
+            address: address as *mut u8,
+            length,
+            mappings: Arc::clone(&self.mappings),
+        }))
+    }
+
+    fn read_nonblocking(
+        &self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        // If the caller's buffer is usbfs memory, e.g. from a BufferPool, the kernel can read
+        // straight into it; we just need to keep it alive until the URB comes back.
+        // We hold the buffer's lock until the URB is reaped.
+        let mut guard = buffer.write_arc();
+        let memory = guard.as_mut();
+        let length = memory.len();
+        if self.is_mapped(memory) {
+            let urb = Urb::new(USBDEVFS_URB_TYPE_BULK, endpoint | 0x80, memory);
+            let transfer = Box::new(PendingTransfer {
+                urb,
+                buffer: vec![],
+                target: None,
+                mapped: Some(guard),
+                callback,
+                deadline: timeout.map(|timeout| Instant::now() + timeout),
+            });
+            return self.submit(transfer);
+        }
+
+        // Otherwise, we read into a buffer of our own, and copy out on completion; so the
+        // kernel never holds a pointer into memory we don't control.
+        drop(guard);
+
+        // Bulk URBs work for interrupt endpoints, too; usbfs figures out the real type.
+        self.submit_urb(
+            USBDEVFS_URB_TYPE_BULK,
+            endpoint | 0x80,
+            vec![0; length],
+            Some(buffer),
+            callback,
+            timeout,
+        )
+    }
+
+    fn write_nonblocking(
+        &self,
+        endpoint: u8,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        self.submit_urb(
+            USBDEVFS_URB_TYPE_BULK,
+            endpoint,
+            (*data).as_ref().to_vec(),
+            None,
+            callback,
+            timeout,
+        )
+    }
+}
+
+/// Returns the wTotalLength of the configuration descriptor at the start of the given data;
+/// or [Error::InvalidArgument] if there's no configuration there.
+fn configuration_total_length(raw: &[u8]) -> UsbResult<usize> {
+    match raw {
+        [_, _, low, high, ..] => match u16::from_le_bytes([*low, *high]) as usize {
+            0 => Err(Error::MalformedDescriptor),
+            length => Ok(length),
+        },
+        _ => Err(Error::InvalidArgument),
+    }
+}
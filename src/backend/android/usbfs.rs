@@ -0,0 +1,245 @@
+//! Raw bindings to the Linux usbfs ioctl interface; which is what the file descriptors handed
+//! out by Android's `UsbManager.openDevice()` speak.
+//!
+//! Structures and request numbers come from `<linux/usbdevice_fs.h>`.
+
+use std::{ffi::c_void, os::unix::io::RawFd};
+
+use libc::{c_int, c_uchar, c_uint, c_ulong};
+
+use crate::{Error, UsbResult};
+
+//
+// Request number encoding, per <asm-generic/ioctl.h>.
+//
+
+const IOC_NONE: c_ulong = 0;
+const IOC_WRITE: c_ulong = 1;
+const IOC_READ: c_ulong = 2;
+
+/// Builds a usbfs ioctl request number.
+const fn ioc(direction: c_ulong, number: c_ulong, size: usize) -> c_ulong {
+    (direction << 30) | ((size as c_ulong) << 16) | ((b'U' as c_ulong) << 8) | number
+}
+
+pub(crate) const USBDEVFS_CONTROL: c_ulong =
+    ioc(IOC_READ | IOC_WRITE, 0, std::mem::size_of::<CtrlTransfer>());
+pub(crate) const USBDEVFS_BULK: c_ulong =
+    ioc(IOC_READ | IOC_WRITE, 2, std::mem::size_of::<BulkTransfer>());
+pub(crate) const USBDEVFS_SETINTERFACE: c_ulong =
+    ioc(IOC_READ, 4, std::mem::size_of::<SetInterface>());
+pub(crate) const USBDEVFS_SETCONFIGURATION: c_ulong =
+    ioc(IOC_READ, 5, std::mem::size_of::<c_uint>());
+pub(crate) const USBDEVFS_GETDRIVER: c_ulong = ioc(IOC_WRITE, 8, std::mem::size_of::<GetDriver>());
+pub(crate) const USBDEVFS_SUBMITURB: c_ulong = ioc(IOC_READ, 10, std::mem::size_of::<Urb>());
+pub(crate) const USBDEVFS_DISCARDURB: c_ulong = ioc(IOC_NONE, 11, 0);
+pub(crate) const USBDEVFS_REAPURBNDELAY: c_ulong =
+    ioc(IOC_WRITE, 13, std::mem::size_of::<*mut c_void>());
+pub(crate) const USBDEVFS_CLAIMINTERFACE: c_ulong =
+    ioc(IOC_READ, 15, std::mem::size_of::<c_uint>());
+pub(crate) const USBDEVFS_RELEASEINTERFACE: c_ulong =
+    ioc(IOC_READ, 16, std::mem::size_of::<c_uint>());
+pub(crate) const USBDEVFS_IOCTL: c_ulong = ioc(
+    IOC_READ | IOC_WRITE,
+    18,
+    std::mem::size_of::<IoctlRequest>(),
+);
+pub(crate) const USBDEVFS_RESET: c_ulong = ioc(IOC_NONE, 20, 0);
+pub(crate) const USBDEVFS_CLEAR_HALT: c_ulong = ioc(IOC_READ, 21, std::mem::size_of::<c_uint>());
+pub(crate) const USBDEVFS_ALLOC_STREAMS: c_ulong =
+    ioc(IOC_READ, 28, std::mem::size_of::<StreamsHeader>());
+pub(crate) const USBDEVFS_FREE_STREAMS: c_ulong =
+    ioc(IOC_READ, 29, std::mem::size_of::<StreamsHeader>());
+
+/// Inner ioctl (issued via USBDEVFS_IOCTL) that detaches the kernel driver from an interface.
+pub(crate) const USBDEVFS_DISCONNECT: c_ulong = ioc(IOC_NONE, 22, 0);
+
+/// Inner ioctl (issued via USBDEVFS_IOCTL) that re-attaches the kernel driver to an interface.
+pub(crate) const USBDEVFS_CONNECT: c_ulong = ioc(IOC_NONE, 23, 0);
+
+//
+// URB types.
+//
+
+pub(crate) const USBDEVFS_URB_TYPE_CONTROL: c_uchar = 2;
+pub(crate) const USBDEVFS_URB_TYPE_BULK: c_uchar = 3;
+
+/// The size of the setup packet that starts the buffer of every control URB.
+pub(crate) const SETUP_PACKET_SIZE: usize = 8;
+
+//
+// URB flags.
+//
+
+/// Asks the kernel to follow an OUT transfer that ends on a packet boundary with a ZLP.
+pub(crate) const USBDEVFS_URB_ZERO_PACKET: c_uint = 0x40;
+
+//
+// Structures.
+//
+
+/// struct usbdevfs_ctrltransfer
+#[repr(C)]
+pub(crate) struct CtrlTransfer {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+    pub timeout: u32,
+    pub data: *mut c_void,
+}
+
+/// struct usbdevfs_bulktransfer
+#[repr(C)]
+pub(crate) struct BulkTransfer {
+    pub endpoint: c_uint,
+    pub length: c_uint,
+    pub timeout: c_uint,
+    pub data: *mut c_void,
+}
+
+/// struct usbdevfs_setinterface
+#[repr(C)]
+pub(crate) struct SetInterface {
+    pub interface: c_uint,
+    pub alternate_setting: c_uint,
+}
+
+/// The longest driver name usbfs will report; not counting its terminator.
+pub(crate) const USBDEVFS_MAXDRIVERNAME: usize = 255;
+
+/// struct usbdevfs_getdriver
+#[repr(C)]
+pub(crate) struct GetDriver {
+    pub interface: c_uint,
+    pub driver: [u8; USBDEVFS_MAXDRIVERNAME + 1],
+}
+
+/// struct usbdevfs_ioctl
+#[repr(C)]
+pub(crate) struct IoctlRequest {
+    pub interface: c_int,
+    pub ioctl_code: c_int,
+    pub data: *mut c_void,
+}
+
+/// struct usbdevfs_urb; without its trailing isochronous packet descriptors.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Urb {
+    pub urb_type: c_uchar,
+    pub endpoint: c_uchar,
+    pub status: c_int,
+    pub flags: c_uint,
+    pub buffer: *mut c_void,
+    pub buffer_length: c_int,
+    pub actual_length: c_int,
+    pub start_frame: c_int,
+    /// Shares its storage with the stream ID; which is what bulk URBs use it for.
+    pub number_of_packets: c_int,
+    pub error_count: c_int,
+    pub signr: c_uint,
+    pub usercontext: *mut c_void,
+}
+
+impl Urb {
+    /// Creates a URB for the given transfer type and endpoint; pointing at the provided buffer.
+    pub(crate) fn new(urb_type: c_uchar, endpoint: u8, buffer: &mut [u8]) -> Self {
+        Self {
+            urb_type,
+            endpoint,
+            status: 0,
+            flags: 0,
+            buffer: buffer.as_mut_ptr() as *mut c_void,
+            buffer_length: buffer.len() as c_int,
+            actual_length: 0,
+            start_frame: 0,
+            number_of_packets: 0,
+            error_count: 0,
+            signr: 0,
+            usercontext: std::ptr::null_mut(),
+        }
+    }
+}
+
+/// struct usbdevfs_streams; without its trailing array of endpoint addresses.
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct StreamsHeader {
+    pub num_streams: c_uint,
+    pub num_eps: c_uint,
+}
+
+/// Builds the argument for USBDEVFS_ALLOC_STREAMS or USBDEVFS_FREE_STREAMS: a
+/// struct usbdevfs_streams, followed by the addresses of the endpoints it applies to.
+pub(crate) fn streams_request(num_streams: u32, endpoints: &[u8]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(std::mem::size_of::<StreamsHeader>() + endpoints.len());
+    request.extend_from_slice(&num_streams.to_ne_bytes());
+    request.extend_from_slice(&(endpoints.len() as c_uint).to_ne_bytes());
+    request.extend_from_slice(endpoints);
+    request
+}
+
+//
+// Error handling.
+//
+
+/// Converts a (positive) errno value from usbfs into a USRs error.
+pub(crate) fn error_from_errno(errno: c_int) -> Error {
+    match errno {
+        libc::ETIMEDOUT => Error::TimedOut,
+        libc::EPIPE => Error::Stalled,
+        libc::EOVERFLOW => Error::Babble,
+        libc::EPROTO | libc::EILSEQ => Error::ProtocolError,
+        libc::ETIME => Error::NotResponding,
+        libc::ENOSR | libc::ECOMM => Error::Underrun,
+        libc::ENOENT | libc::ECONNRESET => Error::Aborted,
+        libc::ENODEV | libc::ESHUTDOWN => Error::Disconnected,
+        libc::EACCES | libc::EPERM => Error::PermissionDenied,
+        libc::EBUSY => Error::DeviceReserved,
+        libc::EINVAL => Error::InvalidArgument,
+        errno => Error::OsError(errno as i64),
+    }
+}
+
+/// Returns the errno value left by the last failing libc call.
+pub(crate) fn last_errno() -> c_int {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// The most descriptor data we'll read back from usbfs; far more than any real device has.
+const MAX_DESCRIPTOR_DATA: usize = 64 * 1024;
+
+/// Reads the kernel's copy of a device's descriptors; which usbfs hands back when its file
+/// descriptor is read. This is the device descriptor, followed by each configuration in full.
+pub(crate) fn read_cached_descriptors(fd: RawFd) -> UsbResult<Vec<u8>> {
+    let mut raw = vec![0u8; MAX_DESCRIPTOR_DATA];
+
+    let length = unsafe { libc::pread(fd, raw.as_mut_ptr() as *mut c_void, raw.len(), 0) };
+    if length < 0 {
+        return Err(error_from_errno(last_errno()));
+    }
+
+    raw.truncate(length as usize);
+    Ok(raw)
+}
+
+/// Issues a usbfs ioctl, converting its result into a UsbResult.
+///
+/// # Safety
+/// The argument must be valid for the given request; as must anything it points to.
+pub(crate) unsafe fn usbfs_ioctl(
+    fd: RawFd,
+    request: c_ulong,
+    argument: *mut c_void,
+) -> UsbResult<usize> {
+    // The type of the request argument varies between libcs; bionic uses an int.
+    let rc = libc::ioctl(fd, request as _, argument);
+
+    if rc < 0 {
+        Err(error_from_errno(last_errno()))
+    } else {
+        Ok(rc as usize)
+    }
+}
@@ -0,0 +1,931 @@
+//! Backend for FreeBSD and OpenBSD, which talks to devices via the ugen(4) driver.
+//!
+//! Each device attached to ugen has a control node (`/dev/ugenB.A` on FreeBSD, and
+//! `/dev/ugenN.00` on OpenBSD), which we use for enumeration and control requests; and a
+//! node per endpoint, which we read and write for everything else.
+//!
+//! Neither OS offers an asynchronous interface to ugen; so this backend performs each
+//! "nonblocking" transfer synchronously, on a thread of its own, and issues its callback
+//! from there once it's done.
+//!
+//! ugen keeps a single timeout per endpoint node; so transfers on the same endpoint take turns,
+//! each setting its own timeout once it's reached the front of the queue. Aborting an endpoint
+//! fails the transfers still queued on it; but ugen can't interrupt the one it's performing,
+//! which runs until it completes or times out.
+//!
+//! Writes to an endpoint node can't ask for a trailing zero-length packet; so
+//! [crate::device::WriteOptions::append_zlp] is emulated by the device, with a separate,
+//! empty write.
+
+use std::{
+    any::Any,
+    collections::{hash_map::Entry, HashMap},
+    ffi::c_void,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    mem::MaybeUninit,
+    os::unix::io::{AsRawFd, RawFd},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use libc::c_int;
+
+use self::ugen::{
+    error_from_io, string_from_field, ugen_ioctl, AltInterface, CtlRequest, DeviceInfo,
+    DeviceRequest, FullDescriptor, USB_DO_REQUEST, USB_GET_DEVICEINFO, USB_GET_DEVICE_DESC,
+    USB_GET_FULL_DESC, USB_SET_ALTINTERFACE, USB_SET_CONFIG, USB_SET_RX_TIMEOUT,
+    USB_SET_SHORT_XFER, USB_SET_TX_TIMEOUT, USB_SHORT_XFER_OK,
+};
+use super::{
+    Backend, BackendDevice, BackendDeviceOps, DeviceInformation, DeviceInformationIterator,
+    TransferCallback,
+};
+use crate::{
+    descriptors::{ConfigurationDescriptor, DeviceDescriptor},
+    device::{
+        DeviceSelector, DeviceSpeed, EnumerationOptions, InterfaceClass, OpenMode,
+        OpenOptions as DeviceOpenOptions, RawHandle,
+    },
+    diagnostics::AccessProblem,
+    request::{
+        Direction, FeatureSelector, Recipient, RequestType, StandardDeviceRequest, Type,
+        STANDARD_IN_FROM_DEVICE,
+    },
+    topology::PortPath,
+    Error, ReadBuffer, UsbResult, WriteBuffer,
+};
+
+#[cfg(target_os = "freebsd")]
+use self::ugen::{
+    GenDescriptor, USB_DEVICEENUMERATE, USB_GET_IFACE_DRIVER, USB_IFACE_DRIVER_ACTIVE,
+    USB_IFACE_DRIVER_DETACH,
+};
+#[cfg(target_os = "freebsd")]
+use crate::{device::ReenumerateOptions, request::DescriptorType};
+
+mod ugen;
+
+/// The directory in which ugen creates its device nodes.
+const DEVICE_DIRECTORY: &str = "/dev";
+
+/// The timeout we use for requests the caller hasn't given us one for; ugen treats 0 as "forever".
+const NO_TIMEOUT: c_int = 0;
+
+/// Helper that converts a timeout into the form ugen wants: milliseconds, with 0 meaning forever.
+fn to_ugen_timeout(timeout: Option<Duration>) -> c_int {
+    match timeout {
+        Some(timeout) => timeout.as_millis().clamp(1, c_int::MAX as u128) as c_int,
+        None => NO_TIMEOUT,
+    }
+}
+
+/// Returns true iff the given file name is a ugen control node.
+fn is_control_node(name: &str) -> bool {
+    let Some(suffix) = name.strip_prefix("ugen") else {
+        return false;
+    };
+
+    // FreeBSD names control nodes ugen<bus>.<address>; OpenBSD names them ugen<unit>.00.
+    match suffix.split_once('.') {
+        Some((first, second)) => {
+            let numeric = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+            numeric(first) && numeric(second) && (cfg!(target_os = "freebsd") || second == "00")
+        }
+        None => false,
+    }
+}
+
+/// Returns the path to the node for the given endpoint number, from the path to its control node.
+fn endpoint_node_path(control_path: &str, bus: u8, address: u8, endpoint_number: u8) -> String {
+    if cfg!(target_os = "freebsd") {
+        // FreeBSD keeps its endpoint nodes in /dev/usb/<bus>.<address>.<endpoint>.
+        format!("{DEVICE_DIRECTORY}/usb/{bus}.{address}.{endpoint_number}")
+    } else {
+        // OpenBSD keeps them alongside the control node; as ugen<unit>.<endpoint>.
+        let unit = control_path.trim_end_matches(".00");
+        format!("{unit}.{endpoint_number:02}")
+    }
+}
+
+/// Per-device data for the BSD backend.
+#[derive(Debug)]
+pub(crate) struct BsdDevice {
+    /// The path to our device's control node.
+    path: String,
+
+    /// Our device's control node; used for control requests and configuration.
+    control: Arc<File>,
+
+    /// The bus number our device lives on.
+    bus: u8,
+
+    /// Our device's address on its bus.
+    address: u8,
+
+    /// The endpoint nodes we've opened so far; keyed by endpoint address.
+    endpoints: Mutex<HashMap<u8, Arc<EndpointNode>>>,
+}
+
+/// An endpoint's node; along with what we need to share it between transfers.
+#[derive(Debug)]
+struct EndpointNode {
+    /// The node itself.
+    file: File,
+
+    /// Held for the duration of each transfer on the node; since the node's timeout is
+    /// shared, no transfer can set its own until the one before it has finished.
+    busy: Mutex<()>,
+
+    /// The number of times the endpoint has been aborted; so transfers queued before an
+    /// abort know to give up.
+    aborts: AtomicU64,
+}
+
+impl EndpointNode {
+    /// Returns a ticket for a transfer being queued now; see [EndpointNode::transfer].
+    fn ticket(&self) -> u64 {
+        self.aborts.load(Ordering::SeqCst)
+    }
+
+    /// Performs a transfer on the node, once every transfer ahead of it has finished; setting
+    /// the node's timeout with the given request first. Fails with [Error::Aborted] if the
+    /// endpoint was aborted after the transfer's ticket was issued.
+    fn transfer(
+        &self,
+        ticket: u64,
+        timeout_request: libc::c_ulong,
+        timeout: Option<Duration>,
+        perform: impl FnOnce(&File) -> UsbResult<usize>,
+    ) -> UsbResult<usize> {
+        let _busy = self.busy.lock().unwrap();
+        if self.aborts.load(Ordering::SeqCst) != ticket {
+            return Err(Error::Aborted);
+        }
+
+        set_int(
+            self.file.as_raw_fd(),
+            timeout_request,
+            to_ugen_timeout(timeout),
+        )?;
+        perform(&self.file)
+    }
+
+    /// Fails every transfer queued on the node, but not yet started.
+    fn abort(&self) {
+        self.aborts.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Reads from the node, which must be an IN endpoint's.
+    fn read(&self, ticket: u64, buffer: &mut [u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        self.transfer(ticket, USB_SET_RX_TIMEOUT, timeout, |file| {
+            (&*file).read(buffer).map_err(error_from_io)
+        })
+    }
+
+    /// Writes all of the provided data to the node, which must be an OUT endpoint's.
+    fn write(&self, ticket: u64, data: &[u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        self.transfer(ticket, USB_SET_TX_TIMEOUT, timeout, |file| {
+            (&*file).write_all(data).map_err(error_from_io)?;
+            Ok(data.len())
+        })
+    }
+}
+
+impl BsdDevice {
+    /// Returns the node for the given endpoint; opening it if necessary.
+    ///
+    /// We only hold our lock long enough to find the node; so a transfer that blocks on one
+    /// endpoint doesn't hold up the others.
+    fn endpoint(&self, address: u8) -> UsbResult<Arc<EndpointNode>> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+
+        let endpoint = match endpoints.entry(address) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Arc::new(self.open_endpoint(address)?)),
+        };
+
+        Ok(Arc::clone(endpoint))
+    }
+
+    /// Helper that fetches our control node's file descriptor.
+    fn fd(&self) -> RawFd {
+        self.control.as_raw_fd()
+    }
+
+    /// Helper for issuing synchronous control requests; see [control_request].
+    fn control(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: *mut c_void,
+        length: usize,
+    ) -> UsbResult<usize> {
+        control_request(
+            &self.control,
+            request_type,
+            request_number,
+            value,
+            index,
+            data,
+            length,
+        )
+    }
+
+    /// Helper that issues a standard GET_DESCRIPTOR request to the device.
+    #[cfg(target_os = "freebsd")]
+    fn get_descriptor(
+        &self,
+        descriptor_type: DescriptorType,
+        index: u8,
+        target: &mut [u8],
+    ) -> UsbResult<usize> {
+        self.control(
+            STANDARD_IN_FROM_DEVICE.into(),
+            StandardDeviceRequest::GetDescriptor.into(),
+            ((descriptor_type as u16) << 8) | (index as u16),
+            0,
+            target.as_mut_ptr() as *mut c_void,
+            target.len(),
+        )
+    }
+
+    /// Converts a configuration value into the configuration index FreeBSD's ugen expects.
+    #[cfg(target_os = "freebsd")]
+    fn configuration_index_for(&self, value: u8) -> UsbResult<c_int> {
+        // Configuration zero means "unconfigured"; which FreeBSD spells as index 0xFF.
+        if value == 0 {
+            return Ok(0xFF);
+        }
+
+        // Find out how many configurations the device has...
+        let mut device_descriptor = [0u8; 18];
+        self.get_descriptor(DescriptorType::Device, 0, &mut device_descriptor)?;
+        let num_configurations = device_descriptor[17];
+
+        // ... and find the one with the matching value.
+        for index in 0..num_configurations {
+            let mut header = [0u8; 9];
+            self.get_descriptor(DescriptorType::Configuration, index, &mut header)?;
+
+            if header[5] == value {
+                return Ok(index as c_int);
+            }
+        }
+
+        Err(Error::InvalidArgument)
+    }
+
+    /// Opens the node for a given endpoint, in the direction its address implies.
+    fn open_endpoint(&self, address: u8) -> UsbResult<EndpointNode> {
+        let is_in = (address & 0x80) != 0;
+
+        let path = endpoint_node_path(&self.path, self.bus, self.address, address & 0x7F);
+
+        let endpoint = OpenOptions::new()
+            .read(is_in)
+            .write(!is_in)
+            .open(path)
+            .map_err(error_from_io)?;
+
+        // By default, ugen treats a short packet on an IN endpoint as an error; we'd rather just
+        // report how much we got, like every other backend.
+        if is_in {
+            set_int(endpoint.as_raw_fd(), USB_SET_SHORT_XFER, 1)?;
+        }
+
+        Ok(EndpointNode {
+            file: endpoint,
+            busy: Mutex::new(()),
+            aborts: AtomicU64::new(0),
+        })
+    }
+}
+
+impl BackendDevice for BsdDevice {
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn raw_handle(&self) -> Option<RawHandle> {
+        Some(RawHandle::FileDescriptor(self.control.as_raw_fd()))
+    }
+}
+
+/// Issues a synchronous control request via the given control node.
+///
+/// ugen applies its own, fixed timeout to control requests; so we can't honor the caller's.
+fn control_request(
+    control: &File,
+    request_type: u8,
+    request_number: u8,
+    value: u16,
+    index: u16,
+    data: *mut c_void,
+    length: usize,
+) -> UsbResult<usize> {
+    let length = u16::try_from(length).map_err(|_| Error::Overrun)?;
+
+    let request = DeviceRequest {
+        request_type,
+        request: request_number,
+        value: value.to_le_bytes(),
+        index: index.to_le_bytes(),
+        length: length.to_le_bytes(),
+    };
+    let mut request = CtlRequest::new(request, data, USB_SHORT_XFER_OK);
+
+    unsafe {
+        ugen_ioctl(
+            control.as_raw_fd(),
+            USB_DO_REQUEST,
+            &mut request as *mut CtlRequest as *mut c_void,
+        )?;
+    }
+
+    Ok(request.actual_length())
+}
+
+/// Performs a transfer on a thread of its own, and then calls back with its result.
+fn in_background(
+    transfer: impl FnOnce() -> UsbResult<usize> + Send + 'static,
+    callback: TransferCallback,
+) -> UsbResult<()> {
+    std::thread::Builder::new()
+        .name("usrs-ugen-transfer".into())
+        .spawn(move || callback(transfer()))
+        .map_err(error_from_io)?;
+
+    Ok(())
+}
+
+/// Helper for issuing ioctls that take a single int argument.
+fn set_int(fd: RawFd, request: libc::c_ulong, value: c_int) -> UsbResult<()> {
+    let mut value = value;
+    unsafe { ugen_ioctl(fd, request, &mut value as *mut c_int as *mut c_void) }
+}
+
+/// Opens a ugen control node, and asks the kernel about the device behind it.
+fn open_control_node(path: &str) -> UsbResult<(File, DeviceInfo)> {
+    let control = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(error_from_io)?;
+
+    let mut info = DeviceInfo::zeroed();
+    unsafe {
+        ugen_ioctl(
+            control.as_raw_fd(),
+            USB_GET_DEVICEINFO,
+            &mut info as *mut DeviceInfo as *mut c_void,
+        )?;
+    }
+
+    Ok((control, info))
+}
+
+/// Works out where a device is plugged in, by following its chain of parent hubs up to the
+/// root hub; or returns None if we can't see some hub along the way.
+#[cfg(target_os = "freebsd")]
+fn port_path(info: &DeviceInfo) -> Option<PortPath> {
+    // USB allows at most seven tiers, counting the root hub; we stop there, in case the
+    // nodes change beneath us.
+    const MAX_TIERS: usize = 7;
+
+    let mut ports = vec![];
+    let (mut hub_address, mut hub_port) = (info.hub_address, info.hub_port);
+
+    // Root hubs have no parent; everything else's parent has its own control node.
+    while hub_address != 0 {
+        if ports.len() >= MAX_TIERS {
+            return None;
+        }
+        ports.push(hub_port);
+
+        let hub_path = format!("{DEVICE_DIRECTORY}/ugen{}.{hub_address}", info.bus);
+        let (_, hub) = open_control_node(&hub_path).ok()?;
+        (hub_address, hub_port) = (hub.hub_address, hub.hub_port);
+    }
+
+    ports.reverse();
+    Some(PortPath::new(info.bus, ports))
+}
+
+/// OpenBSD doesn't tell us which hub a device is plugged into; only which port.
+#[cfg(target_os = "openbsd")]
+fn port_path(_info: &DeviceInfo) -> Option<PortPath> {
+    None
+}
+
+/// Decodes the kernel's USB_SPEED_* value; which both BSDs number the same way.
+fn device_speed(speed: u8) -> Option<DeviceSpeed> {
+    match speed {
+        1 => Some(DeviceSpeed::Low),
+        2 => Some(DeviceSpeed::Full),
+        3 => Some(DeviceSpeed::High),
+        4 => Some(DeviceSpeed::Super),
+        _ => None,
+    }
+}
+
+/// Fetches the copy of a device's device descriptor that the kernel read during enumeration.
+fn read_cached_device_descriptor(control: &File) -> UsbResult<DeviceDescriptor> {
+    let mut raw = [0u8; DeviceDescriptor::LENGTH];
+    unsafe {
+        ugen_ioctl(
+            control.as_raw_fd(),
+            USB_GET_DEVICE_DESC,
+            raw.as_mut_ptr() as *mut c_void,
+        )?;
+    }
+
+    DeviceDescriptor::parse(&raw)
+}
+
+/// Fetches the copy of one of a device's configuration descriptors that the kernel read during
+/// enumeration; including its interfaces, endpoints, and everything else beneath it.
+fn read_cached_configuration_descriptor(fd: RawFd, configuration_index: u8) -> UsbResult<Vec<u8>> {
+    let mut raw = vec![0u8; u16::MAX as usize];
+    let mut request = FullDescriptor::for_configuration(configuration_index, &mut raw);
+
+    unsafe {
+        ugen_ioctl(
+            fd,
+            USB_GET_FULL_DESC,
+            &mut request as *mut FullDescriptor as *mut c_void,
+        )?;
+    }
+
+    // The kernel doesn't tell us how much it copied in a portable way; but the descriptor does.
+    let length = match raw[..] {
+        [_, _, low, high, ..] => u16::from_le_bytes([low, high]) as usize,
+        _ => return Err(Error::MalformedDescriptor),
+    };
+    raw.truncate(length);
+    Ok(raw)
+}
+
+/// Returns the class codes of each interface in a device's first configuration; which is the
+/// one the kernel selects for it, unless someone's changed it.
+fn read_interface_classes(control: &File) -> UsbResult<Vec<InterfaceClass>> {
+    let raw = read_cached_configuration_descriptor(control.as_raw_fd(), 0)?;
+    let configuration = ConfigurationDescriptor::parse(&raw)?;
+
+    Ok(configuration
+        .interfaces
+        .iter()
+        .map(InterfaceClass::of)
+        .collect())
+}
+
+/// Backend for the BSDs; which uses ugen for everything.
+#[derive(Debug)]
+pub struct BsdBackend {}
+
+impl BsdBackend {
+    pub fn new() -> UsbResult<BsdBackend> {
+        Ok(BsdBackend {})
+    }
+}
+
+impl Backend for BsdBackend {
+    fn name(&self) -> &'static str {
+        "bsd"
+    }
+
+    fn get_devices(&self) -> UsbResult<Vec<DeviceInformation>> {
+        self.devices_iter(&DeviceSelector::default(), &EnumerationOptions::default())?
+            .collect()
+    }
+
+    fn devices_iter(
+        &self,
+        _selector: &DeviceSelector,
+        _options: &EnumerationOptions,
+    ) -> UsbResult<DeviceInformationIterator<'_>> {
+        // ugen only tells us who a device is once we've opened it; so we can't filter any
+        // sooner than our caller can. Its strings come along for free, so we never defer them.
+        let entries = std::fs::read_dir(DEVICE_DIRECTORY).map_err(error_from_io)?;
+
+        // Opening each node is the expensive part; so we'll only do it as each one is reached.
+        let devices = entries.flatten().filter_map(|entry| {
+            let name = entry.file_name();
+            let name = name.to_str()?;
+            if !is_control_node(name) {
+                return None;
+            }
+
+            // Nodes we can't open are either unattached (OpenBSD creates them all ahead of time),
+            // or off-limits to us; either way, we can't use them, so we'll skip them.
+            let path = format!("{DEVICE_DIRECTORY}/{name}");
+            let (control, info) = open_control_node(&path).ok()?;
+
+            Some(Ok(DeviceInformation {
+                vendor_id: info.vendor_id,
+                product_id: info.product_id,
+                serial: string_from_field(&info.serial),
+                vendor: string_from_field(&info.vendor),
+                product: string_from_field(&info.product),
+                backend_string_location: Some(path),
+                device_descriptor: read_cached_device_descriptor(&control).ok(),
+                port_path: port_path(&info),
+                speed: device_speed(info.speed),
+                device_address: Some(info.address),
+                device_class: Some(info.class),
+                device_subclass: Some(info.subclass),
+                device_protocol: Some(info.protocol),
+                interface_classes: read_interface_classes(&control).ok(),
+                ..Default::default()
+            }))
+        });
+
+        Ok(Box::new(devices))
+    }
+
+    fn open(&self, information: &DeviceInformation) -> UsbResult<Box<dyn BackendDevice>> {
+        // Devices we didn't enumerate have no control node for us to open.
+        let path = information
+            .backend_string_location
+            .as_ref()
+            .ok_or(Error::InvalidArgument)?;
+
+        let (control, info) = open_control_node(path)?;
+
+        // Make sure the node still refers to the device we enumerated; and not something
+        // that's since been plugged in at the same address.
+        if (info.vendor_id, info.product_id) != (information.vendor_id, information.product_id) {
+            return Err(Error::DeviceNotFound);
+        }
+
+        Ok(Box::new(BsdDevice {
+            path: path.clone(),
+            control: Arc::new(control),
+            bus: info.bus,
+            address: info.address,
+            endpoints: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    fn open_with(
+        &self,
+        information: &DeviceInformation,
+        options: &DeviceOpenOptions,
+    ) -> UsbResult<Box<dyn BackendDevice>> {
+        match options.mode() {
+            // ugen has no way to read descriptors without opening the control node, so a
+            // descriptors-only open is just a normal one; and no way to take a device from
+            // someone else who has it open.
+            OpenMode::Normal | OpenMode::DescriptorsOnly => self.open(information),
+            OpenMode::Exclusive => Err(Error::Unsupported),
+        }
+    }
+
+    fn diagnose_access(&self, information: &DeviceInformation) -> UsbResult<Vec<AccessProblem>> {
+        let Some(path) = information.backend_string_location.as_ref() else {
+            return Ok(vec![]);
+        };
+
+        // Everything goes through the control node; so if we can open that, we're in.
+        match open_control_node(path) {
+            Err(Error::PermissionDenied) => Ok(vec![AccessProblem::DeviceNodePermissions {
+                path: path.clone(),
+            }]),
+            _ => Ok(vec![]),
+        }
+    }
+}
+
+impl BackendDeviceOps for BsdDevice {
+    #[cfg(target_os = "freebsd")]
+    fn release_kernel_driver(&mut self, interface: u8) -> UsbResult<()> {
+        set_int(self.fd(), USB_IFACE_DRIVER_DETACH, interface as c_int)
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn kernel_driver_name(&self, interface: u8) -> UsbResult<Option<String>> {
+        let fd = self.fd();
+
+        // First, check whether there's a driver at all; the kernel tells us there isn't with ENXIO.
+        let mut value = interface as c_int;
+        if unsafe { libc::ioctl(fd, USB_IFACE_DRIVER_ACTIVE as _, &mut value as *mut c_int) } < 0 {
+            let error = std::io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::ENXIO) => Ok(None),
+                _ => Err(error_from_io(error)),
+            };
+        }
+
+        // If there is, ask for its name.
+        let mut name = [0u8; 128];
+        let mut request = GenDescriptor::for_interface(interface, &mut name);
+        unsafe {
+            ugen_ioctl(
+                fd,
+                USB_GET_IFACE_DRIVER,
+                &mut request as *mut GenDescriptor as *mut c_void,
+            )?;
+        }
+
+        Ok(Some(string_from_field(&name).unwrap_or_default()))
+    }
+
+    #[cfg(not(target_os = "freebsd"))]
+    fn release_kernel_driver(&mut self, _interface: u8) -> UsbResult<()> {
+        // OpenBSD only attaches ugen to devices no other driver wants; and has no way
+        // for us to detach those other drivers.
+        Err(Error::Unsupported)
+    }
+
+    fn claim_interface(&mut self, _interface: u8) -> UsbResult<()> {
+        // Opening the control node already gives us the whole device; there's nothing to claim.
+        Ok(())
+    }
+
+    fn unclaim_interface(&mut self, _interface: u8) -> UsbResult<()> {
+        // We don't track interfaces; but we can at least close any endpoints we've opened,
+        // so they're available to whomever uses the device next.
+        self.endpoints.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn active_configuration(&self) -> UsbResult<u8> {
+        let mut configuration: u8 = 0;
+        self.control(
+            STANDARD_IN_FROM_DEVICE.into(),
+            StandardDeviceRequest::GetConfiguration.into(),
+            0,
+            0,
+            &mut configuration as *mut u8 as *mut c_void,
+            1,
+        )?;
+
+        Ok(configuration)
+    }
+
+    fn cached_configuration_descriptor(&self, configuration_index: u8) -> UsbResult<Vec<u8>> {
+        read_cached_configuration_descriptor(self.fd(), configuration_index)
+    }
+
+    fn set_active_configuration(&mut self, configuration_value: u8) -> UsbResult<()> {
+        // FreeBSD wants the index of the configuration; OpenBSD wants its value.
+        #[cfg(target_os = "freebsd")]
+        let configuration = self.configuration_index_for(configuration_value)?;
+        #[cfg(not(target_os = "freebsd"))]
+        let configuration = configuration_value as c_int;
+
+        // Changing configurations invalidates our endpoint nodes.
+        self.endpoints.lock().unwrap().clear();
+        set_int(self.fd(), USB_SET_CONFIG, configuration)
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn reset_device(&self) -> UsbResult<()> {
+        self.endpoints.lock().unwrap().clear();
+        set_int(self.fd(), USB_DEVICEENUMERATE, 0)
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn reenumerate(&mut self, options: &ReenumerateOptions) -> UsbResult<()> {
+        // FreeBSD always hands re-enumerated devices back to their drivers.
+        if options.capture {
+            return Err(Error::Unsupported);
+        }
+
+        // This is the same request we use for resets; since on FreeBSD, that's what a reset is.
+        self.reset_device()
+    }
+
+    #[cfg(not(target_os = "freebsd"))]
+    fn reset_device(&self) -> UsbResult<()> {
+        // OpenBSD's ugen doesn't provide a way to reset devices.
+        Err(Error::Unsupported)
+    }
+
+    fn abort_endpoint(&self, endpoint_address: u8) -> UsbResult<()> {
+        // Endpoints we've never opened have nothing queued on them.
+        if let Some(node) = self.endpoints.lock().unwrap().get(&endpoint_address) {
+            node.abort();
+        }
+
+        Ok(())
+    }
+
+    fn clear_stall(&self, endpoint_address: u8) -> UsbResult<()> {
+        let request_type = RequestType {
+            direction: Direction::Out,
+            request_type: Type::Standard,
+            recipient: Recipient::Endpoint,
+        };
+
+        // ugen notices this request go by, and resets its own endpoint state to match.
+        self.control(
+            request_type.into(),
+            StandardDeviceRequest::ClearFeature.into(),
+            FeatureSelector::EndpointHalt.into(),
+            endpoint_address as u16,
+            std::ptr::null_mut(),
+            0,
+        )?;
+        Ok(())
+    }
+
+    fn set_alternate_setting(&mut self, interface: u8, setting: u8) -> UsbResult<()> {
+        let mut request = AltInterface::new(interface, setting);
+
+        // Changing alternate settings can change which endpoints exist.
+        self.endpoints.lock().unwrap().clear();
+        unsafe {
+            ugen_ioctl(
+                self.fd(),
+                USB_SET_ALTINTERFACE,
+                &mut request as *mut AltInterface as *mut c_void,
+            )
+        }
+    }
+
+    fn current_bus_frame(&self) -> UsbResult<(u64, SystemTime)> {
+        // ugen doesn't expose frame numbers.
+        Err(Error::Unsupported)
+    }
+
+    fn control_read(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: &mut [u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        self.control(
+            request_type,
+            request_number,
+            value,
+            index,
+            target.as_mut_ptr() as *mut c_void,
+            target.len(),
+        )
+    }
+
+    fn control_read_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: ReadBuffer,
+        callback: TransferCallback,
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let control = Arc::clone(&self.control);
+        in_background(
+            move || {
+                let mut target = target.write();
+                let target = target.as_mut();
+                control_request(
+                    &control,
+                    request_type,
+                    request_number,
+                    value,
+                    index,
+                    target.as_mut_ptr() as *mut c_void,
+                    target.len(),
+                )
+            },
+            callback,
+        )
+    }
+
+    fn control_write(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        // ugen wants a mutable pointer, but won't write through it for OUT requests.
+        self.control(
+            request_type,
+            request_number,
+            value,
+            index,
+            data.as_ptr() as *mut c_void,
+            data.len(),
+        )?;
+        Ok(())
+    }
+
+    fn control_write_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let control = Arc::clone(&self.control);
+        in_background(
+            move || {
+                // ugen wants a mutable pointer, but won't write through it for OUT requests.
+                let data = (*data).as_ref();
+                control_request(
+                    &control,
+                    request_type,
+                    request_number,
+                    value,
+                    index,
+                    data.as_ptr() as *mut c_void,
+                    data.len(),
+                )?;
+                Ok(data.len())
+            },
+            callback,
+        )
+    }
+
+    fn read(&self, endpoint: u8, buffer: &mut [u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        let node = self.endpoint(endpoint | 0x80)?;
+        node.read(node.ticket(), buffer, timeout)
+    }
+
+    fn read_uninit(
+        &self,
+        endpoint: u8,
+        buffer: &mut [MaybeUninit<u8>],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let node = self.endpoint(endpoint | 0x80)?;
+        node.transfer(node.ticket(), USB_SET_RX_TIMEOUT, timeout, |file| {
+            // read(2) only ever writes to our buffer; so it's fine for it to start uninitialized.
+            let length = unsafe {
+                libc::read(
+                    file.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut c_void,
+                    buffer.len(),
+                )
+            };
+            if length < 0 {
+                return Err(error_from_io(std::io::Error::last_os_error()));
+            }
+
+            Ok(length as usize)
+        })
+    }
+
+    fn write(&self, endpoint: u8, data: &[u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        let node = self.endpoint(endpoint)?;
+        node.write(node.ticket(), data, timeout)
+    }
+
+    fn read_nonblocking(
+        &self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        // We only lock the buffer once it's our turn on the endpoint; so a transfer stuck in
+        // the queue doesn't keep its buffer from its owner.
+        let node = self.endpoint(endpoint | 0x80)?;
+        let ticket = node.ticket();
+        in_background(
+            move || {
+                node.transfer(ticket, USB_SET_RX_TIMEOUT, timeout, |file| {
+                    (&*file)
+                        .read(buffer.write().as_mut())
+                        .map_err(error_from_io)
+                })
+            },
+            callback,
+        )
+    }
+
+    fn write_nonblocking(
+        &self,
+        endpoint: u8,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let node = self.endpoint(endpoint)?;
+        let ticket = node.ticket();
+        in_background(
+            move || node.write(ticket, (*data).as_ref(), timeout),
+            callback,
+        )
+    }
+}
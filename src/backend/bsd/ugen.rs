@@ -0,0 +1,343 @@
+//! Raw bindings to the ugen(4) ioctl interface shared (mostly) by FreeBSD and OpenBSD.
+//!
+//! The request numbers match between the two, but several of the structures don't; those
+//! are defined separately for each OS, from `<dev/usb/usb_ioctl.h>` on FreeBSD and
+//! `<dev/usb/usb.h>` on OpenBSD.
+
+use std::{ffi::c_void, os::unix::io::RawFd};
+
+use libc::{c_int, c_ulong};
+
+use crate::{descriptors::DeviceDescriptor, Error, UsbResult};
+
+//
+// Request number encoding, per <sys/ioccom.h>.
+//
+
+const IOCPARM_MASK: c_ulong = 0x1fff;
+const IOC_OUT: c_ulong = 0x4000_0000;
+const IOC_IN: c_ulong = 0x8000_0000;
+
+/// Builds a ugen ioctl request number.
+const fn ioc(direction: c_ulong, number: c_ulong, size: usize) -> c_ulong {
+    direction | (((size as c_ulong) & IOCPARM_MASK) << 16) | ((b'U' as c_ulong) << 8) | number
+}
+
+pub(crate) const USB_SET_CONFIG: c_ulong = ioc(IOC_IN, 101, std::mem::size_of::<c_int>());
+pub(crate) const USB_SET_ALTINTERFACE: c_ulong =
+    ioc(IOC_IN | IOC_OUT, 103, std::mem::size_of::<AltInterface>());
+pub(crate) const USB_GET_DEVICE_DESC: c_ulong = ioc(IOC_OUT, 105, DeviceDescriptor::LENGTH);
+pub(crate) const USB_GET_FULL_DESC: c_ulong =
+    ioc(IOC_IN | IOC_OUT, 109, std::mem::size_of::<FullDescriptor>());
+pub(crate) const USB_DO_REQUEST: c_ulong =
+    ioc(IOC_IN | IOC_OUT, 111, std::mem::size_of::<CtlRequest>());
+pub(crate) const USB_GET_DEVICEINFO: c_ulong = ioc(IOC_OUT, 112, std::mem::size_of::<DeviceInfo>());
+
+/// Allows short transfers on an IN endpoint node; rather than treating them as errors.
+pub(crate) const USB_SET_SHORT_XFER: c_ulong = ioc(IOC_IN, 113, std::mem::size_of::<c_int>());
+
+/// Sets the timeout used for reads from an endpoint node, in milliseconds.
+pub(crate) const USB_SET_RX_TIMEOUT: c_ulong = ioc(IOC_IN, 114, std::mem::size_of::<c_int>());
+
+/// Sets the timeout used for writes to an endpoint node, in milliseconds.
+///
+/// OpenBSD uses a single timeout for both directions.
+#[cfg(target_os = "freebsd")]
+pub(crate) const USB_SET_TX_TIMEOUT: c_ulong = ioc(IOC_IN, 137, std::mem::size_of::<c_int>());
+#[cfg(target_os = "openbsd")]
+pub(crate) const USB_SET_TX_TIMEOUT: c_ulong = USB_SET_RX_TIMEOUT;
+
+/// Re-enumerates (and thus resets) a device. FreeBSD only.
+#[cfg(target_os = "freebsd")]
+pub(crate) const USB_DEVICEENUMERATE: c_ulong = ioc(IOC_IN, 6, std::mem::size_of::<c_int>());
+
+/// Fetches the name of the kernel driver attached to an interface. FreeBSD only.
+#[cfg(target_os = "freebsd")]
+pub(crate) const USB_GET_IFACE_DRIVER: c_ulong =
+    ioc(IOC_IN | IOC_OUT, 121, std::mem::size_of::<GenDescriptor>());
+
+/// Checks whether a kernel driver is attached to an interface; failing with ENXIO if not.
+/// FreeBSD only.
+#[cfg(target_os = "freebsd")]
+pub(crate) const USB_IFACE_DRIVER_ACTIVE: c_ulong = ioc(IOC_IN, 124, std::mem::size_of::<c_int>());
+
+/// Detaches the kernel driver from an interface. FreeBSD only.
+#[cfg(target_os = "freebsd")]
+pub(crate) const USB_IFACE_DRIVER_DETACH: c_ulong = ioc(IOC_IN, 125, std::mem::size_of::<c_int>());
+
+/// Flag that allows a control request to return less data than requested.
+pub(crate) const USB_SHORT_XFER_OK: u16 = 0x0004;
+
+//
+// Structures.
+//
+
+/// struct usb_device_request; whose multi-byte fields are stored as little-endian byte pairs.
+#[repr(C)]
+pub(crate) struct DeviceRequest {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: [u8; 2],
+    pub index: [u8; 2],
+    pub length: [u8; 2],
+}
+
+/// struct usb_ctl_request
+#[cfg(target_os = "freebsd")]
+#[repr(C)]
+pub(crate) struct CtlRequest {
+    pub data: *mut c_void,
+    pub flags: u16,
+    pub actual_length: u16,
+    pub address: u8,
+    pub request: DeviceRequest,
+}
+
+/// struct usb_ctl_request
+#[cfg(target_os = "openbsd")]
+#[repr(C)]
+pub(crate) struct CtlRequest {
+    pub address: c_int,
+    pub request: DeviceRequest,
+    pub data: *mut c_void,
+    pub flags: c_int,
+    pub actual_length: c_int,
+}
+
+impl CtlRequest {
+    /// Creates a control request targeting the provided buffer.
+    pub(crate) fn new(request: DeviceRequest, data: *mut c_void, flags: u16) -> Self {
+        Self {
+            address: 0,
+            request,
+            data,
+            flags: flags as _,
+            actual_length: 0,
+        }
+    }
+
+    /// Returns the amount of data actually transferred by a completed request.
+    pub(crate) fn actual_length(&self) -> usize {
+        self.actual_length as usize
+    }
+}
+
+/// struct usb_alt_interface
+#[cfg(target_os = "freebsd")]
+#[repr(C)]
+pub(crate) struct AltInterface {
+    pub interface_index: u8,
+    pub alt_index: u8,
+}
+
+/// struct usb_alt_interface
+#[cfg(target_os = "openbsd")]
+#[repr(C)]
+pub(crate) struct AltInterface {
+    pub config_index: c_int,
+    pub interface_index: c_int,
+    pub alt_index: c_int,
+}
+
+impl AltInterface {
+    /// Creates a request to select an alternate setting on the active configuration.
+    pub(crate) fn new(interface: u8, setting: u8) -> Self {
+        Self {
+            #[cfg(target_os = "openbsd")]
+            config_index: -1,
+            interface_index: interface as _,
+            alt_index: setting as _,
+        }
+    }
+}
+
+/// struct usb_gen_descriptor
+#[cfg(target_os = "freebsd")]
+#[repr(C)]
+pub(crate) struct GenDescriptor {
+    pub data: *mut c_void,
+    pub lang_id: u16,
+    pub max_length: u16,
+    pub actual_length: u16,
+    pub offset: u16,
+    pub config_index: u8,
+    pub string_index: u8,
+    pub interface_index: u8,
+    pub alt_index: u8,
+    pub endpoint_index: u8,
+    pub report_type: u8,
+    pub reserved: [u8; 8],
+}
+
+#[cfg(target_os = "freebsd")]
+impl GenDescriptor {
+    /// Creates a request for information about an interface, to be placed in the given buffer.
+    pub(crate) fn for_interface(interface: u8, buffer: &mut [u8]) -> Self {
+        Self {
+            data: buffer.as_mut_ptr() as *mut c_void,
+            lang_id: 0,
+            max_length: buffer.len() as u16,
+            actual_length: 0,
+            offset: 0,
+            config_index: 0,
+            string_index: 0,
+            interface_index: interface,
+            alt_index: 0,
+            endpoint_index: 0,
+            report_type: 0,
+            reserved: [0; 8],
+        }
+    }
+}
+
+/// The argument to USB_GET_FULL_DESC; which FreeBSD shares with its other descriptor requests.
+#[cfg(target_os = "freebsd")]
+pub(crate) type FullDescriptor = GenDescriptor;
+
+/// struct usb_full_desc
+#[cfg(target_os = "openbsd")]
+#[repr(C)]
+pub(crate) struct FullDescriptor {
+    pub config_index: c_int,
+    pub size: libc::c_uint,
+    pub data: *mut u8,
+}
+
+impl FullDescriptor {
+    /// Creates a request for a full configuration descriptor, to be placed in the given buffer.
+    #[cfg(target_os = "freebsd")]
+    pub(crate) fn for_configuration(index: u8, buffer: &mut [u8]) -> Self {
+        Self {
+            config_index: index,
+            ..GenDescriptor::for_interface(0, buffer)
+        }
+    }
+
+    /// Creates a request for a full configuration descriptor, to be placed in the given buffer.
+    #[cfg(target_os = "openbsd")]
+    pub(crate) fn for_configuration(index: u8, buffer: &mut [u8]) -> Self {
+        Self {
+            config_index: index as c_int,
+            size: buffer.len() as libc::c_uint,
+            data: buffer.as_mut_ptr(),
+        }
+    }
+}
+
+/// struct usb_device_info
+#[cfg(target_os = "freebsd")]
+#[repr(C)]
+pub(crate) struct DeviceInfo {
+    pub product_id: u16,
+    pub vendor_id: u16,
+    pub release: u16,
+    pub power: u16,
+    pub bus: u8,
+    pub address: u8,
+    pub index: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub config_number: u8,
+    pub config_index: u8,
+    pub speed: u8,
+    pub mode: u8,
+    pub num_ports: u8,
+    pub hub_address: u8,
+    pub hub_index: u8,
+    pub hub_port: u8,
+    pub power_mode: u8,
+    pub suspended: u8,
+    pub reserved: u16,
+    pub product: [u8; 128],
+    pub vendor: [u8; 128],
+    pub serial: [u8; 64],
+    pub release_string: [u8; 8],
+}
+
+/// struct usb_device_info
+#[cfg(target_os = "openbsd")]
+#[repr(C)]
+pub(crate) struct DeviceInfo {
+    pub bus: u8,
+    pub address: u8,
+    pub product: [u8; 127],
+    pub vendor: [u8; 127],
+    pub release_string: [u8; 8],
+    pub product_id: u16,
+    pub vendor_id: u16,
+    pub release: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub config: u8,
+    pub speed: u8,
+    pub power: c_int,
+    pub num_ports: c_int,
+    pub device_names: [[u8; 16]; 4],
+    pub ports: [u32; 8],
+    pub serial: [u8; 127],
+    pub port: u8,
+}
+
+impl DeviceInfo {
+    /// Creates an all-zeroes device info structure, ready to be filled in by the kernel.
+    pub(crate) fn zeroed() -> Self {
+        // Safety: this is a plain-old-data structure, for which all zeroes is a valid value.
+        unsafe { std::mem::zeroed() }
+    }
+}
+
+/// Converts one of the kernel's NUL-terminated string fields into a String; or None if it's empty.
+pub(crate) fn string_from_field(field: &[u8]) -> Option<String> {
+    let length = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+
+    match length {
+        0 => None,
+        length => Some(String::from_utf8_lossy(&field[..length]).into_owned()),
+    }
+}
+
+//
+// Error handling.
+//
+
+/// Converts an errno value from ugen into a USRs error.
+pub(crate) fn error_from_errno(errno: c_int) -> Error {
+    match errno {
+        libc::ETIMEDOUT => Error::TimedOut,
+        libc::EPIPE => Error::Stalled,
+        libc::EOVERFLOW => Error::Overrun,
+        libc::EINTR => Error::Aborted,
+        libc::ENXIO | libc::ENODEV => Error::Disconnected,
+        libc::ENOENT => Error::DeviceNotFound,
+        libc::EACCES | libc::EPERM => Error::PermissionDenied,
+        libc::EBUSY => Error::DeviceReserved,
+        libc::EINVAL => Error::InvalidArgument,
+        errno => Error::OsError(errno as i64),
+    }
+}
+
+/// Converts a std::io error (e.g. from reading an endpoint node) into a USRs error.
+pub(crate) fn error_from_io(error: std::io::Error) -> Error {
+    match error.raw_os_error() {
+        Some(errno) => error_from_errno(errno),
+        None => Error::UnspecifiedOsError,
+    }
+}
+
+/// Issues a ugen ioctl, converting its result into a UsbResult.
+///
+/// # Safety
+/// The argument must be valid for the given request; as must anything it points to.
+pub(crate) unsafe fn ugen_ioctl(
+    fd: RawFd,
+    request: c_ulong,
+    argument: *mut c_void,
+) -> UsbResult<()> {
+    if libc::ioctl(fd, request as _, argument) < 0 {
+        Err(error_from_io(std::io::Error::last_os_error()))
+    } else {
+        Ok(())
+    }
+}
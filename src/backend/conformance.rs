@@ -0,0 +1,474 @@
+//! Conformance checks for backend implementations; see [run_conformance_tests].
+//!
+//! Where [crate::compliance] checks a device against the USB specification, these check a
+//! backend against what the rest of the library expects of it: that enumeration is consistent,
+//! that transfers report their lengths honestly, that asynchronous completions arrive, and that
+//! mistakes come back as errors rather than hangs or panics. They're written against the
+//! [Backend] traits directly; so they exercise the backend, rather than our wrappers around it.
+//!
+//! Every backend -- ours, or anyone else's -- should pass the same checks against the same
+//! device; which keeps code written against one backend working on the others. Where a check
+//! expects a particular [Error], that's the variant the rest of the library matches on.
+
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use parking_lot::RwLock;
+
+use super::{Backend, BackendDevice};
+use crate::{
+    compliance::{CheckResult, ComplianceReport, TestOutcome},
+    descriptors::{ConfigurationDescriptor, DeviceDescriptor, TransferType},
+    device::{DeviceInformation, DeviceSelector, EnumerationOptions},
+    request::{DescriptorType, Direction, StandardDeviceRequest, STANDARD_IN_FROM_DEVICE},
+    Error, UsbResult,
+};
+
+/// How long we'll wait for any single request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long we'll wait for an asynchronous completion before declaring it lost.
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An IN endpoint that few devices have; which reads from an unclaimed interface can't reach.
+const UNUSED_ENDPOINT: u8 = 0x0f;
+
+/// An interface number no real device gets anywhere near.
+const UNUSED_INTERFACE: u8 = 0xfe;
+
+/// A request number that Chapter 9 leaves reserved; which devices refuse with a STALL.
+const RESERVED_REQUEST: u8 = 2;
+
+/// The timeout we give reads that we expect to time out.
+const SHORT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The active configuration's descriptor; or why we couldn't get it.
+type ConfigurationResult = Result<ConfigurationDescriptor, String>;
+
+/// Runs every conformance check against the provided backend; returning a report of how each went.
+///
+/// The enumeration checks look at every device the backend can see. The rest open the first
+/// device matching the selector, and only issue standard requests to it; but they do claim
+/// its interfaces, so it's best to point them at a device under test, rather than one other
+/// software is using.
+pub fn run_conformance_tests(
+    backend: &Arc<dyn Backend>,
+    selector: &DeviceSelector,
+) -> ComplianceReport {
+    let mut report = ComplianceReport::default();
+
+    report.record("name", check_name(backend.as_ref()));
+
+    // Most checks need a device; and we need enumeration to find one.
+    let devices = match backend.get_devices() {
+        Ok(devices) => {
+            report.record("get_devices", Ok(TestOutcome::Passed));
+            devices
+        }
+        Err(Error::Unsupported) => {
+            let reason = "backend can't enumerate devices".to_string();
+            report.record("get_devices", Ok(TestOutcome::Skipped(reason)));
+            return report;
+        }
+        Err(error) => {
+            report.record("get_devices", Err(format!("get_devices failed: {error}")));
+            return report;
+        }
+    };
+    report.record(
+        "devices_iter",
+        check_devices_iter(backend.as_ref(), &devices),
+    );
+
+    let Some(information) = devices.iter().find(|device| selector.matches(device)) else {
+        report.record(
+            "open",
+            Ok(TestOutcome::Skipped(
+                "no device matches the selector".into(),
+            )),
+        );
+        return report;
+    };
+    let mut device = match backend.open(information) {
+        Ok(device) => {
+            report.record("open", Ok(TestOutcome::Passed));
+            device
+        }
+        Err(error) => {
+            report.record("open", Err(format!("open failed: {error}")));
+            return report;
+        }
+    };
+
+    report.record(
+        "control_read",
+        check_control_read(device.as_ref(), information),
+    );
+    report.record(
+        "short_control_read",
+        check_short_control_read(device.as_ref()),
+    );
+    report.record(
+        "control_read_nonblocking",
+        check_control_read_nonblocking(device.as_ref()),
+    );
+    report.record(
+        "active_configuration",
+        check_active_configuration(device.as_ref()),
+    );
+    report.record(
+        "unused_endpoint_fails",
+        check_unused_endpoint(device.as_ref()),
+    );
+    report.record("not_disconnected", check_not_disconnected(device.as_ref()));
+
+    // The remaining checks need interfaces to claim; which we find in the active configuration.
+    let configuration = active_configuration_descriptor(device.as_ref());
+    report.record(
+        "claim_unclaim",
+        check_claim_unclaim(device.as_mut(), &configuration),
+    );
+    report.record(
+        "read_timeout",
+        check_read_timeout(device.as_mut(), &configuration),
+    );
+    report.record(
+        "invalid_interface_error",
+        check_invalid_interface(device.as_mut()),
+    );
+    report.record("stall_error", check_stall_error(device.as_ref()));
+
+    report
+}
+
+/// Checks the backend's name is usable with [super::BACKEND_ENVIRONMENT_VARIABLE].
+fn check_name(backend: &dyn Backend) -> CheckResult {
+    let name = backend.name();
+
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("{name:?} can't be selected by name"));
+    }
+
+    Ok(TestOutcome::Passed)
+}
+
+/// Checks iterating over the devices finds the same devices as listing them.
+fn check_devices_iter(backend: &dyn Backend, devices: &[DeviceInformation]) -> CheckResult {
+    let iterated = backend
+        .devices_iter(&DeviceSelector::default(), &EnumerationOptions::default())
+        .and_then(|iterator| iterator.collect::<UsbResult<Vec<_>>>())
+        .map_err(|error| format!("devices_iter failed: {error}"))?;
+
+    if iterated.len() != devices.len() {
+        return Err(format!(
+            "devices_iter found {} devices; get_devices found {}",
+            iterated.len(),
+            devices.len()
+        ));
+    }
+
+    let missing = devices
+        .iter()
+        .filter(|device| {
+            !iterated.iter().any(|other| {
+                other.vendor_id == device.vendor_id && other.product_id == device.product_id
+            })
+        })
+        .count();
+    match missing {
+        0 => Ok(TestOutcome::Passed),
+        _ => Err(format!(
+            "{missing} devices from get_devices weren't found by devices_iter"
+        )),
+    }
+}
+
+/// Issues a GET_DESCRIPTOR request, with a wLength of the target's length.
+fn read_descriptor(
+    device: &dyn BackendDevice,
+    descriptor_type: DescriptorType,
+    index: u8,
+    target: &mut [u8],
+) -> UsbResult<usize> {
+    device.control_read(
+        STANDARD_IN_FROM_DEVICE.into(),
+        StandardDeviceRequest::GetDescriptor.into(),
+        ((descriptor_type as u16) << 8) | index as u16,
+        0,
+        target,
+        Some(REQUEST_TIMEOUT),
+    )
+}
+
+/// Issues a GET_DESCRIPTOR(Device) request of the given length.
+fn read_device_descriptor(device: &dyn BackendDevice, target: &mut [u8]) -> UsbResult<usize> {
+    read_descriptor(device, DescriptorType::Device, 0, target)
+}
+
+/// Checks a control read returns a device descriptor matching the one we enumerated.
+fn check_control_read(device: &dyn BackendDevice, information: &DeviceInformation) -> CheckResult {
+    let mut raw = [0; DeviceDescriptor::LENGTH];
+    let length = read_device_descriptor(device, &mut raw)
+        .map_err(|error| format!("GET_DESCRIPTOR(Device) failed: {error}"))?;
+
+    if length != raw.len() {
+        return Err(format!("GET_DESCRIPTOR(Device) returned {length} bytes"));
+    }
+    let descriptor = DeviceDescriptor::parse(&raw).map_err(|error| format!("parsing: {error}"))?;
+
+    if descriptor.vendor_id != information.vendor_id
+        || descriptor.product_id != information.product_id
+    {
+        return Err("descriptor's VID/PID differ from the enumerated ones".into());
+    }
+    if information
+        .cached_device_descriptor()
+        .is_some_and(|cached| *cached != descriptor)
+    {
+        return Err("descriptor differs from the one cached during enumeration".into());
+    }
+
+    Ok(TestOutcome::Passed)
+}
+
+/// Checks a control read shorter than the descriptor reports only what it read.
+fn check_short_control_read(device: &dyn BackendDevice) -> CheckResult {
+    let mut raw = [0; 8];
+    let length = read_device_descriptor(device, &mut raw)
+        .map_err(|error| format!("GET_DESCRIPTOR(Device) with wLength 8 failed: {error}"))?;
+
+    match length {
+        8 => Ok(TestOutcome::Passed),
+        _ => Err(format!("an 8-byte read reported {length} bytes")),
+    }
+}
+
+/// Checks a nonblocking control read calls back with the same data as a blocking one.
+fn check_control_read_nonblocking(device: &dyn BackendDevice) -> CheckResult {
+    let mut expected = [0; DeviceDescriptor::LENGTH];
+    read_device_descriptor(device, &mut expected)
+        .map_err(|error| format!("GET_DESCRIPTOR(Device) failed: {error}"))?;
+
+    let buffer = Arc::new(RwLock::new(vec![0u8; DeviceDescriptor::LENGTH]));
+    let (sender, receiver) = mpsc::channel();
+    device
+        .control_read_nonblocking(
+            STANDARD_IN_FROM_DEVICE.into(),
+            StandardDeviceRequest::GetDescriptor.into(),
+            (DescriptorType::Device as u16) << 8,
+            0,
+            buffer.clone(),
+            Box::new(move |result| {
+                let _ = sender.send(result);
+            }),
+            Some(REQUEST_TIMEOUT),
+        )
+        .map_err(|error| format!("submission failed: {error}"))?;
+
+    // Wait for the backend to tell us it's done.
+    let length = receiver
+        .recv_timeout(COMPLETION_TIMEOUT)
+        .map_err(|_| "the callback was never called".to_string())?
+        .map_err(|error| format!("the transfer failed: {error}"))?;
+
+    if length != expected.len() {
+        return Err(format!("the callback reported {length} bytes"));
+    }
+    if buffer.read()[..] != expected[..] {
+        return Err("the buffer doesn't hold what a blocking read returned".into());
+    }
+
+    Ok(TestOutcome::Passed)
+}
+
+/// Checks the backend can tell us which configuration is active.
+fn check_active_configuration(device: &dyn BackendDevice) -> CheckResult {
+    device
+        .active_configuration()
+        .map_err(|error| format!("active_configuration failed: {error}"))?;
+
+    Ok(TestOutcome::Passed)
+}
+
+/// Checks reading from an endpoint we haven't claimed fails promptly, rather than succeeding.
+fn check_unused_endpoint(device: &dyn BackendDevice) -> CheckResult {
+    let mut buffer = [0; 64];
+
+    match device.read(UNUSED_ENDPOINT, &mut buffer, Some(REQUEST_TIMEOUT)) {
+        Ok(length) => Err(format!("read {length} bytes from an unclaimed endpoint")),
+        Err(_) => Ok(TestOutcome::Passed),
+    }
+}
+
+/// Checks a freshly-opened device doesn't claim to have gone away.
+fn check_not_disconnected(device: &dyn BackendDevice) -> CheckResult {
+    match device.is_disconnected() {
+        true => Err("device reports being disconnected".into()),
+        false => Ok(TestOutcome::Passed),
+    }
+}
+
+/// Reads and parses the descriptor of the device's active configuration.
+fn active_configuration_descriptor(device: &dyn BackendDevice) -> ConfigurationResult {
+    let active = device
+        .active_configuration()
+        .map_err(|error| format!("active_configuration failed: {error}"))?;
+
+    let mut raw = [0; DeviceDescriptor::LENGTH];
+    read_device_descriptor(device, &mut raw)
+        .map_err(|error| format!("GET_DESCRIPTOR(Device) failed: {error}"))?;
+    let device_descriptor =
+        DeviceDescriptor::parse(&raw).map_err(|error| format!("parsing: {error}"))?;
+
+    for index in 0..device_descriptor.num_configurations {
+        // Read the header first, for the configuration's total length; then the whole thing.
+        let mut header = [0; ConfigurationDescriptor::LENGTH];
+        read_descriptor(device, DescriptorType::Configuration, index, &mut header)
+            .map_err(|error| format!("GET_DESCRIPTOR(Configuration) failed: {error}"))?;
+        let mut raw = vec![0; u16::from_le_bytes([header[2], header[3]]) as usize];
+        let length = read_descriptor(device, DescriptorType::Configuration, index, &mut raw)
+            .map_err(|error| format!("GET_DESCRIPTOR(Configuration) failed: {error}"))?;
+
+        let configuration = ConfigurationDescriptor::parse(&raw[..length])
+            .map_err(|error| format!("parsing configuration {index}: {error}"))?;
+        if configuration.configuration_value == active {
+            return Ok(configuration);
+        }
+    }
+
+    Err(format!("no configuration has value {active}"))
+}
+
+/// Claims an interface for a check; or returns how the check went, if we can't.
+fn claim_for_check(device: &mut dyn BackendDevice, interface: u8) -> Result<(), CheckResult> {
+    match device.claim_interface(interface) {
+        Ok(()) => Ok(()),
+        Err(error @ (Error::PermissionDenied | Error::DeviceReserved)) => Err(Ok(
+            TestOutcome::Skipped(format!("interface {interface} is in use: {error}")),
+        )),
+        Err(error) => Err(Err(format!(
+            "claiming interface {interface} failed: {error}"
+        ))),
+    }
+}
+
+/// Checks an interface can be claimed and released; and that releasing it twice fails.
+fn check_claim_unclaim(
+    device: &mut dyn BackendDevice,
+    configuration: &ConfigurationResult,
+) -> CheckResult {
+    let configuration = match configuration {
+        Ok(configuration) => configuration,
+        Err(reason) => return Ok(TestOutcome::Skipped(reason.clone())),
+    };
+    let Some(interface) = configuration.interfaces.first() else {
+        return Ok(TestOutcome::Skipped(
+            "configuration has no interfaces".into(),
+        ));
+    };
+    let interface = interface.interface_number;
+
+    if let Err(result) = claim_for_check(device, interface) {
+        return result;
+    }
+    device
+        .unclaim_interface(interface)
+        .map_err(|error| format!("releasing interface {interface} failed: {error}"))?;
+
+    // Once it's released, there's nothing left to release.
+    match device.unclaim_interface(interface) {
+        Ok(()) => Err(format!("releasing interface {interface} twice succeeded")),
+        Err(_) => Ok(TestOutcome::Passed),
+    }
+}
+
+/// Checks a read that gets no data gives up after its timeout, and says so with
+/// [Error::TimedOut].
+fn check_read_timeout(
+    device: &mut dyn BackendDevice,
+    configuration: &ConfigurationResult,
+) -> CheckResult {
+    let configuration = match configuration {
+        Ok(configuration) => configuration,
+        Err(reason) => return Ok(TestOutcome::Skipped(reason.clone())),
+    };
+
+    // Interrupt endpoints are the likeliest to sit idle; but any IN endpoint will do.
+    let candidates = configuration
+        .interfaces
+        .iter()
+        .filter(|interface| interface.alternate_setting == 0);
+    let found = [TransferType::Interrupt, TransferType::Bulk]
+        .into_iter()
+        .find_map(|transfer_type| {
+            candidates.clone().find_map(|interface| {
+                let endpoint = interface.find_endpoint(transfer_type, Direction::In)?;
+                Some((interface.interface_number, endpoint))
+            })
+        });
+    let Some((interface, endpoint)) = found else {
+        return Ok(TestOutcome::Skipped(
+            "no interrupt or bulk IN endpoints".into(),
+        ));
+    };
+
+    if let Err(result) = claim_for_check(device, interface) {
+        return result;
+    }
+
+    let mut buffer = vec![0; endpoint.packet_size().max(1)];
+    let start = Instant::now();
+    let result = device.read(endpoint.number(), &mut buffer, Some(SHORT_TIMEOUT));
+    let elapsed = start.elapsed();
+    let _ = device.unclaim_interface(interface);
+
+    match result {
+        Ok(_) => Ok(TestOutcome::Skipped(format!(
+            "endpoint {:#04x} had data waiting",
+            endpoint.address
+        ))),
+        Err(Error::TimedOut) if elapsed < SHORT_TIMEOUT / 2 => Err(format!(
+            "a {SHORT_TIMEOUT:?} timeout expired after {elapsed:?}"
+        )),
+        Err(Error::TimedOut) if elapsed > SHORT_TIMEOUT + REQUEST_TIMEOUT => {
+            Err(format!("a {SHORT_TIMEOUT:?} timeout took {elapsed:?}"))
+        }
+        Err(Error::TimedOut) => Ok(TestOutcome::Passed),
+        Err(error) => Err(format!("a timed-out read failed with {error:?}")),
+    }
+}
+
+/// Checks claiming an interface the device doesn't have fails with [Error::InvalidInterface].
+fn check_invalid_interface(device: &mut dyn BackendDevice) -> CheckResult {
+    match device.claim_interface(UNUSED_INTERFACE) {
+        Ok(()) => {
+            let _ = device.unclaim_interface(UNUSED_INTERFACE);
+            Err(format!("claiming interface {UNUSED_INTERFACE} succeeded"))
+        }
+        Err(Error::InvalidInterface) => Ok(TestOutcome::Passed),
+        Err(error) => Err(format!(
+            "claiming interface {UNUSED_INTERFACE} failed with {error:?}"
+        )),
+    }
+}
+
+/// Checks a request the device refuses with a STALL fails with [Error::Stalled].
+fn check_stall_error(device: &dyn BackendDevice) -> CheckResult {
+    let mut buffer = [0; 2];
+    let result = device.control_read(
+        STANDARD_IN_FROM_DEVICE.into(),
+        RESERVED_REQUEST,
+        0,
+        0,
+        &mut buffer,
+        Some(REQUEST_TIMEOUT),
+    );
+
+    match result {
+        Err(Error::Stalled) => Ok(TestOutcome::Passed),
+        Ok(_) => Ok(TestOutcome::Skipped(
+            "the device accepted a reserved request".into(),
+        )),
+        Err(error) => Err(format!("a stalled request failed with {error:?}")),
+    }
+}
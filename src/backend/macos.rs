@@ -1,22 +1,48 @@
 //! Core, low-level functionality for macOS.
+//!
+//! IOKit's pipe writes have no way to ask for a trailing zero-length packet; so
+//! [crate::device::WriteOptions::append_zlp] is emulated by the device, with a separate,
+//! empty write.
 
 use std::{
     ffi::c_void,
-    time::{Duration, SystemTime},
+    sync::{atomic::AtomicU64, Arc, Mutex, Weak},
+    time::{Duration, Instant, SystemTime},
 };
 
 use self::{
-    callback::{delegate_iousb_callback, CallbackRefconType},
-    device::{open_usb_device, MacOsDevice},
+    callback::{delegate_iousb_callback, holding_buffer, CallbackRefconType},
+    device::{find_device_service, open_usb_device, open_usb_device_from_service, MacOsDevice},
     endpoint::{address_for_in_endpoint, address_for_out_endpoint},
-    iokit::{leak_to_iokit, to_iokit_timeout, OsDevice, OsInterface},
-    iokit_c::IOUSBDevRequest,
+    iokit::{
+        absolute_time_to_duration, attached_driver_name, child_services,
+        get_iokit_numeric_device_property, process_can_capture_devices, to_iokit_timeout,
+        DeviceChangeNotification, IOKitEmptyResultExtension, LowLatencyBuffer, OsInterface,
+        DEVICE_ACCESS_ENTITLEMENT,
+    },
+    iokit_c::{
+        kUSBLowLatencyFrameListBuffer, kUSBLowLatencyReadBuffer, kUSBLowLatencyWriteBuffer,
+        kUSBPowerDuringSleep, kUSBPowerDuringWake, kUSBReEnumerateCaptureDeviceMask,
+        kUSBReEnumerateReleaseDeviceMask, AbsoluteTime, IOUSBDevRequest, IOUSBIsocFrame,
+        IOUSBLowLatencyIsocFrame,
+    },
+    reactor::{EventReactor, EventRegistration},
 };
 
-use super::{Backend, BackendDevice, DeviceInformation};
+use log::debug;
+
+use super::{
+    Backend, BackendDevice, BackendDeviceOps, DeviceInformation, DeviceInformationIterator,
+    DeviceWatch, TransferCallback, TransferMemory,
+};
 use crate::{
-    backend::macos::iokit_c::IOUSBDevRequestTO, device::Device, error::UsbResult, Error,
-    ReadBuffer, WriteBuffer,
+    backend::macos::iokit_c::IOUSBDevRequestTO,
+    device::{poll_until, DeviceSelector, EndpointPolicy, EnumerationOptions},
+    diagnostics::AccessProblem,
+    error::UsbResult,
+    iso::{IsoCallback, IsoOptions, IsoPacketStatus},
+    Error, ExtraPowerKind, OpenMode, OpenOptions, ReadBuffer, ReenumerateOptions, TransferTimeout,
+    WriteBuffer,
 };
 
 mod callback;
@@ -26,288 +52,377 @@ mod enumeration;
 mod interface;
 mod iokit;
 mod iokit_c;
+mod reactor;
+
+/// How long we'll wait for a device to come back after capturing it from its kernel drivers.
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many frames ahead of the bus we start isochronous transfers that asked for "as soon
+/// as possible"; IOKit wants an explicit frame, and needs a little time to get the transfer in.
+const ISOCHRONOUS_ASAP_LATENCY_FRAMES: u64 = 4;
+
+/// Keeps a device watch's notifications flowing. Our fields drop in order; so the
+/// notification's source leaves the reactor before the notification is torn down.
+struct DeviceChangeRegistration {
+    _registration: EventRegistration,
+    _notification: DeviceChangeNotification,
+}
 
 /// Per-OS data for the MacOS backend.
 #[derive(Debug)]
-pub struct MacOsBackend {}
+pub struct MacOsBackend {
+    /// The reactor that runs event callbacks for our open devices, if any are open.
+    reactor: Mutex<Weak<EventReactor>>,
+}
 
 impl MacOsBackend {
     pub fn new() -> UsbResult<MacOsBackend> {
-        Ok(MacOsBackend {})
+        Ok(MacOsBackend {
+            reactor: Mutex::new(Weak::new()),
+        })
     }
 
-    /// Helper that fetches the MacOsBackend for the relevant device.
-    unsafe fn device_backend<'a>(&self, device: &'a Device) -> &'a MacOsDevice {
-        device
-            .backend_data()
-            .as_any()
-            .downcast_ref()
-            .expect("internal consistency: tried to open a type from another backend?")
+    /// Helper that fetches our event reactor; starting it if no devices are using it, yet.
+    fn reactor(&self) -> UsbResult<Arc<EventReactor>> {
+        let mut reactor = self.reactor.lock().unwrap();
+
+        match reactor.upgrade() {
+            Some(running) => Ok(running),
+            None => {
+                let started = EventReactor::start()?;
+                *reactor = Arc::downgrade(&started);
+                Ok(started)
+            }
+        }
     }
+}
 
-    /// Helper that fetches the MacOsBackend for the relevant device.
-    unsafe fn device_backend_mut<'a>(&self, device: &'a mut Device) -> &'a mut MacOsDevice {
-        device
-            .backend_data_mut()
-            .as_mut_any()
-            .downcast_mut()
-            .expect("internal consistency: tried to open a type from another backend?")
+impl Backend for MacOsBackend {
+    fn name(&self) -> &'static str {
+        "macos"
     }
 
-    /// Helper that fetches the MacOsBackend for the relevant device.
-    unsafe fn os_device_for<'a>(&self, device: &'a Device) -> &'a OsDevice {
-        &self.device_backend(device).device
+    fn get_devices(&self) -> UsbResult<Vec<DeviceInformation>> {
+        enumeration::enumerate_devices()
     }
 
-    /// Helper for issuing control requests.
-    unsafe fn control(
+    fn devices_iter(
         &self,
-        device: &Device,
-        request_type: u8,
-        request_number: u8,
-        value: u16,
-        index: u16,
-        data: *mut c_void,
-        length: u16,
-        timeout: Option<Duration>,
-    ) -> UsbResult<usize> {
-        // Unpack the raw OS device from inside of our USRs device.
-        let device = self.os_device_for(device);
+        selector: &DeviceSelector,
+        options: &EnumerationOptions,
+    ) -> UsbResult<DeviceInformationIterator<'_>> {
+        Ok(Box::new(enumeration::DeviceInformationIter::new(
+            selector, options,
+        )?))
+    }
 
-        // If we have a timeout, use the *TO request function.
-        if let Some(timeout) = timeout {
-            let timeout_ms = to_iokit_timeout(timeout);
+    fn fetch_device_strings(&self, information: &mut DeviceInformation) -> UsbResult<()> {
+        let location = information
+            .backend_numeric_location
+            .ok_or(Error::DeviceNotFound)?;
+        let service = find_device_service(location)?;
 
-            // Populate the request-with-TimeOut structure, which will be passed to macOS.
-            let mut request_struct = IOUSBDevRequestTO {
-                bmRequestType: request_type,
-                bRequest: request_number,
-                wValue: value,
-                wIndex: index,
-                wLength: length,
-                pData: data,
-                wLenDone: 0,
-                noDataTimeout: timeout_ms,
-                completionTimeout: timeout_ms,
-            };
+        let (serial, vendor, product) = enumeration::get_device_strings(service.get())?;
+        information.serial = serial;
+        information.vendor = vendor;
+        information.product = product;
+        Ok(())
+    }
 
-            // And finally, perform the request.
-            device.device_request_with_timeout(&mut request_struct)?;
-            Ok(request_struct.wLenDone as usize)
-        } else {
-            // Populate the (no timeout) request structure, which will be passed to macOS.
-            let mut request_struct = IOUSBDevRequest {
-                bmRequestType: request_type,
-                bRequest: request_number,
-                wValue: value,
-                wIndex: index,
-                wLength: length,
-                pData: data,
-                wLenDone: 0,
-            };
+    fn watch_devices(&self) -> UsbResult<DeviceWatch> {
+        let generation = Arc::new(AtomicU64::new(0));
 
-            // And finally, perform the request.
-            device.device_request(&mut request_struct)?;
-            Ok(request_struct.wLenDone as usize)
-        }
+        // Subscribe to devices coming and going, and have our reactor deliver the events.
+        let (notification, source) = DeviceChangeNotification::new(&generation)?;
+        let registration = self.reactor()?.register(vec![source]);
+
+        Ok(DeviceWatch::new(
+            generation,
+            Box::new(DeviceChangeRegistration {
+                _registration: registration,
+                _notification: notification,
+            }),
+        ))
     }
 
-    /// Helper for issuing async control requests.
-    unsafe fn control_nonblocking(
+    fn open(&self, information: &DeviceInformation) -> UsbResult<Box<dyn BackendDevice>> {
+        Ok(open_usb_device(
+            information,
+            &self.reactor()?,
+            OpenMode::Normal,
+        )?)
+    }
+
+    fn open_with(
         &self,
-        device: &Device,
-        request_type: u8,
-        request_number: u8,
-        value: u16,
-        index: u16,
-        data: *mut c_void,
-        length: u16,
-        callback: Box<CallbackRefconType>,
-        timeout: Option<Duration>,
-    ) -> UsbResult<()> {
-        // Unpack the raw OS device from inside of our USRs device.
-        let device = self.os_device_for(device);
+        information: &DeviceInformation,
+        options: &OpenOptions,
+    ) -> UsbResult<Box<dyn BackendDevice>> {
+        Ok(open_usb_device(
+            information,
+            &self.reactor()?,
+            options.mode(),
+        )?)
+    }
 
-        // If we have a timeout, use the *TO request function.
-        if let Some(timeout) = timeout {
-            let timeout_ms = to_iokit_timeout(timeout);
+    fn device_from_service(&self, service: u32) -> UsbResult<Box<dyn BackendDevice>> {
+        Ok(open_usb_device_from_service(
+            service,
+            &self.reactor()?,
+            OpenMode::Normal,
+        )?)
+    }
 
-            // Populate the request-with-TimeOut structure, which will be passed to macOS.
-            let mut request_struct = IOUSBDevRequestTO {
-                bmRequestType: request_type,
-                bRequest: request_number,
-                wValue: value,
-                wIndex: index,
-                wLength: length,
-                pData: data,
-                wLenDone: 0,
-                noDataTimeout: timeout_ms,
-                completionTimeout: timeout_ms,
-            };
+    fn diagnose_access(&self, information: &DeviceInformation) -> UsbResult<Vec<AccessProblem>> {
+        let location = information
+            .backend_numeric_location
+            .ok_or(Error::DeviceNotFound)?;
+        let service = find_device_service(location)?;
 
-            // And finally, perform the request.
-            device.device_request_nonblocking_with_timeout(
-                &mut request_struct,
-                delegate_iousb_callback,
-                leak_to_iokit(callback),
-            )?;
-            Ok(())
-        } else {
-            // Populate the (no timeout) request structure, which will be passed to macOS.
-            let mut request_struct = IOUSBDevRequest {
-                bmRequestType: request_type,
-                bRequest: request_number,
-                wValue: value,
-                wIndex: index,
-                wLength: length,
-                pData: data,
-                wLenDone: 0,
+        // Check each of the device's interfaces for a kernel driver...
+        let mut problems = vec![];
+        for child in child_services(service.get())? {
+            let Ok(interface) = get_iokit_numeric_device_property(child.get(), "bInterfaceNumber")
+            else {
+                continue;
             };
 
-            // And finally, perform the request.
-            device.device_request_nonblocking(
-                &mut request_struct,
-                delegate_iousb_callback,
-                leak_to_iokit(callback),
-            )?;
-            Ok(())
+            if let Some(driver) = attached_driver_name(child.get())? {
+                problems.push(AccessProblem::KernelDriverBound { interface, driver });
+            }
         }
+
+        // ... and if there are any, check whether we'd be allowed to take the device from them.
+        if !problems.is_empty() && !process_can_capture_devices() {
+            problems.push(AccessProblem::MissingEntitlement {
+                entitlement: DEVICE_ACCESS_ENTITLEMENT,
+            });
+        }
+
+        Ok(problems)
     }
+}
 
-    // Helper that converts an endpoint address into a interface + pipeRef.
-    unsafe fn resources_for_endpoint<'a>(
-        &self,
-        device: &'a Device,
-        address: u8,
-    ) -> UsbResult<(u8, &'a OsInterface)> {
-        // Unpack the raw OS device from inside of our USRs device.
-        let backend_device = self.device_backend(device);
+impl BackendDeviceOps for MacOsDevice {
+    fn release_kernel_driver(&mut self, _interface: u8) -> UsbResult<()> {
+        // macOS doesn't detach drivers from individual interfaces; instead, we can *capture* the
+        // whole device, which detaches every kernel driver bound to it. Once we've done that,
+        // there's nothing left to release.
+        if self.captured {
+            return Ok(());
+        }
 
-        // Find the endpoint metadata for the relevant endpoint...
-        let endpoint_info = backend_device
-            .endpoint_metadata
-            .get(&address)
-            .ok_or(Error::InvalidEndpoint)?;
+        // Capturing is only allowed for root, and for binaries signed with the
+        // `com.apple.vm.device-access` entitlement. For anyone else, it's not something we can do.
+        if !process_can_capture_devices() {
+            debug!("can't release kernel drivers: we're not root, and lack the device-access entitlement");
+            return Err(Error::Unsupported);
+        }
 
-        // ... and get the associated interface.
-        let interface = backend_device
-            .interfaces
-            .get(&endpoint_info.interface_number)
-            .expect("endpoint points to an invalid interface");
+        // Capturing re-enumerates the device, tearing down our handle; so we'll need to know
+        // where it's plugged in to find it again.
+        let location = self.location_id.ok_or(Error::Unsupported)?;
 
-        Ok((endpoint_info.pipe_ref, interface))
+        // The old handle is about to go away on purpose; so we'll keep its removal from our
+        // Device, unless we fail to get the device back.
+        let signal = self.disconnected.replace_signal(None);
+
+        let recapture = || {
+            // Ask macOS to hand the device to us...
+            self.device.reenumerate(kUSBReEnumerateCaptureDeviceMask)?;
+
+            // ... wait for the old device to go away ...
+            let deadline = Some(Instant::now() + CAPTURE_TIMEOUT);
+            poll_until(deadline, || Ok(self.is_disconnected().then_some(())))?;
+
+            // ... and then for it to come back, with us holding it.
+            let information = DeviceInformation {
+                backend_numeric_location: Some(location),
+                ..Default::default()
+            };
+            let reactor = self.reactor().ok_or(Error::DeviceNotOpen)?;
+            poll_until(deadline, || {
+                match open_usb_device(&information, &reactor, OpenMode::Normal) {
+                    Ok(device) => Ok(Some(device)),
+                    Err(Error::DeviceNotFound) => Ok(None),
+                    Err(other) => Err(other),
+                }
+            })
+        };
+
+        let mut captured = match recapture() {
+            Ok(captured) => captured,
+            Err(error) => {
+                // If the old handle went away regardless, our Device needs to hear about it.
+                if let Some(signal) = signal.as_ref().filter(|_| self.is_disconnected()) {
+                    signal.notify();
+                }
+                self.disconnected.replace_signal(signal);
+                return Err(error);
+            }
+        };
+        captured.captured = true;
+
+        // Finally, swap the new device in for the old one; and pass on our Device's signal.
+        // Anything claimed through the old handle went away with it; so interfaces will need
+        // to be claimed anew.
+        *self = *captured;
+        self.disconnected.replace_signal(signal);
+        Ok(())
     }
 
-    // Helper that converts an IN endpoint number into a interface + pipeRef.
-    unsafe fn resources_for_in_endpoint<'a>(
-        &self,
-        device: &'a Device,
-        number: u8,
-    ) -> UsbResult<(u8, &'a OsInterface)> {
-        self.resources_for_endpoint(device, address_for_in_endpoint(number))
+    fn attach_kernel_driver(&mut self, _interface: u8) -> UsbResult<()> {
+        // If we never captured the device, its drivers were never released.
+        if !self.captured {
+            return Ok(());
+        }
+
+        // Otherwise, hand the whole device back to the kernel. This re-enumerates the device,
+        // so this handle is done for; the device will need to be re-opened.
+        self.device.reenumerate(kUSBReEnumerateReleaseDeviceMask)?;
+        self.captured = false;
+
+        Ok(())
     }
 
-    // Helper that converts an OUT endpoint number into a interface + pipeRef.
-    unsafe fn resources_for_out_endpoint<'a>(
-        &self,
-        device: &'a Device,
-        number: u8,
-    ) -> UsbResult<(u8, &'a OsInterface)> {
-        self.resources_for_endpoint(device, address_for_out_endpoint(number))
+    fn set_suspended(&self, suspended: bool) -> UsbResult<()> {
+        self.device.suspend(suspended)
     }
-}
 
-impl Backend for MacOsBackend {
-    fn get_devices(&self) -> UsbResult<Vec<DeviceInformation>> {
-        enumeration::enumerate_devices()
+    fn bus_power_available(&self) -> UsbResult<u32> {
+        self.device.bus_power_available()
     }
 
-    fn open(&self, information: &DeviceInformation) -> UsbResult<Box<dyn BackendDevice>> {
-        open_usb_device(information)
+    fn request_extra_power(&self, kind: ExtraPowerKind, milliamps: u32) -> UsbResult<u32> {
+        self.device.request_extra_power(power_type(kind), milliamps)
     }
 
-    fn release_kernel_driver(&self, _device: &mut Device, _interface: u8) -> UsbResult<()> {
-        // We don't currently have a way of making macOS release kernel drivers.
-        //
-        // Theoretically, if the target binary is signed with the `com.apple.vm.device-access`
-        // entitlement, we'd be able to do this; but this isn't something we yet support.
-        Err(Error::Unsupported)
+    fn return_extra_power(&self, kind: ExtraPowerKind, milliamps: u32) -> UsbResult<()> {
+        self.device.return_extra_power(power_type(kind), milliamps)
     }
 
-    fn claim_interface(&self, device: &mut Device, interface: u8) -> UsbResult<()> {
-        unsafe {
-            // Unpack the raw OS device from inside of our USRs device.
-            let backend_device = self.device_backend_mut(device);
+    fn bandwidth_available(&self) -> UsbResult<u32> {
+        self.device.bandwidth_available()
+    }
 
-            // If we don't have a handle on that interface, error out.
-            let interface = backend_device
-                .interfaces
-                .get_mut(&interface)
-                .ok_or(Error::InvalidArgument)?;
+    fn reenumerate(&mut self, options: &ReenumerateOptions) -> UsbResult<()> {
+        // Capturing is subject to the same restrictions as releasing kernel drivers.
+        let mask = if options.capture {
+            if !process_can_capture_devices() {
+                return Err(Error::PermissionDenied);
+            }
+            kUSBReEnumerateCaptureDeviceMask
+        } else {
+            0
+        };
+
+        self.device.reenumerate(mask)?;
 
-            // Otherwise, open the relevant interface, claiming it.
-            interface.open()
+        // Whatever we'd captured has gone away with the old device; so there's nothing to
+        // hand back to the kernel when we're dropped.
+        self.captured = false;
+        Ok(())
+    }
+
+    fn kernel_driver_name(&self, interface: u8) -> UsbResult<Option<String>> {
+        // Once we've captured the device, macOS has detached all of its drivers.
+        if self.captured {
+            return Ok(None);
         }
+
+        let service = self.interface_service(interface)?;
+        attached_driver_name(service.get())
     }
 
-    fn unclaim_interface(&self, device: &mut Device, interface: u8) -> UsbResult<()> {
-        unsafe {
-            // Unpack the raw OS device from inside of our USRs device.
-            let backend_device = self.device_backend_mut(device);
+    fn claim_interface(&mut self, interface: u8) -> UsbResult<()> {
+        // If we don't have a handle on that interface, error out.
+        let interface = self
+            .interfaces
+            .get_mut(&interface)
+            .ok_or(Error::InvalidArgument)?;
 
-            // If we don't have a handle on that interface, error out.
-            let interface = backend_device
-                .interfaces
-                .get_mut(&interface)
-                .ok_or(Error::InvalidArgument)?;
+        // Otherwise, open the relevant interface, claiming it.
+        interface.open()
+    }
 
-            // Otherwise, close the relevant interface, releasing our claim.
-            interface.close();
-            Ok(())
-        }
+    fn unclaim_interface(&mut self, interface: u8) -> UsbResult<()> {
+        // If we don't have a handle on that interface, error out.
+        let interface = self
+            .interfaces
+            .get_mut(&interface)
+            .ok_or(Error::InvalidArgument)?;
+
+        // Otherwise, close the relevant interface, releasing our claim.
+        interface.close();
+        Ok(())
     }
 
-    fn active_configuration(&self, device: &Device) -> UsbResult<u8> {
-        unsafe {
-            let backend_device = self.os_device_for(device);
-            backend_device.get_configuration()
-        }
+    fn active_configuration(&self) -> UsbResult<u8> {
+        self.device.get_configuration()
     }
 
-    fn set_active_configuration(&self, device: &Device, configuration_index: u8) -> UsbResult<()> {
-        unsafe {
-            let backend_device = self.os_device_for(device);
-            backend_device.set_configuration(configuration_index)
-        }
+    fn num_configurations(&self) -> UsbResult<u8> {
+        self.device.num_configurations()
     }
 
-    fn reset_device(&self, device: &Device) -> UsbResult<()> {
-        unsafe {
-            let backend_device = self.os_device_for(device);
-            backend_device.reset()
-        }
+    fn cached_configuration_descriptor(&self, configuration_index: u8) -> UsbResult<Vec<u8>> {
+        self.device.configuration_descriptor(configuration_index)
     }
 
-    fn clear_stall(&self, device: &Device, endpoint_address: u8) -> UsbResult<()> {
-        unsafe {
-            let (pipe_ref, interface) = self.resources_for_endpoint(device, endpoint_address)?;
-            interface.clear_stall(pipe_ref)
+    fn set_active_configuration(&mut self, configuration_value: u8) -> UsbResult<()> {
+        self.device.set_configuration(configuration_value)?;
+
+        // The new configuration has its own interfaces; so our old pipe refs are now stale.
+        self.repopulate_interfaces()
+    }
+
+    fn reset_device(&self) -> UsbResult<()> {
+        self.device.reset()
+    }
+
+    fn abort_endpoint(&self, endpoint_address: u8) -> UsbResult<()> {
+        // The control endpoint belongs to the device, rather than to any interface.
+        if endpoint_address & 0x7f == 0 {
+            return self.device.abort_ep0();
         }
+
+        let (pipe_ref, interface) = self.resources_for_endpoint(endpoint_address)?;
+        interface.abort_pipe(pipe_ref)
     }
 
-    fn set_alternate_setting(&self, device: &Device, interface: u8, setting: u8) -> UsbResult<()> {
-        unsafe {
-            let backend_data = self.device_backend(device);
-            let interface = backend_data
-                .interfaces
-                .get(&interface)
-                .ok_or(Error::InvalidInterface)?;
+    fn clear_stall(&self, endpoint_address: u8) -> UsbResult<()> {
+        let (pipe_ref, interface) = self.resources_for_endpoint(endpoint_address)?;
+        interface.clear_stall(pipe_ref)
+    }
 
-            interface.set_alternate_setting(setting)
+    fn set_endpoint_policy(&self, endpoint_address: u8, policy: &EndpointPolicy) -> UsbResult<()> {
+        // The only knob macOS gives us is the bandwidth reserved for periodic endpoints.
+        if policy.max_packet_size.is_none() && policy.interval.is_none() {
+            return Err(Error::Unsupported);
         }
+
+        let (pipe_ref, interface) = self.resources_for_endpoint(endpoint_address)?;
+
+        // SetPipePolicy takes both values at once; so fill in whichever wasn't given.
+        let properties = interface.endpoint_properties(pipe_ref)?;
+        interface.set_pipe_policy(
+            pipe_ref,
+            policy.max_packet_size.unwrap_or(properties.max_packet_size),
+            policy.interval.unwrap_or(properties.interval),
+        )
+    }
+
+    fn set_alternate_setting(&mut self, interface: u8, setting: u8) -> UsbResult<()> {
+        self.interfaces
+            .get(&interface)
+            .ok_or(Error::InvalidInterface)?
+            .set_alternate_setting(setting)?;
+
+        // The new alternate setting has its own endpoints; so refresh their pipe refs.
+        self.repopulate_endpoint_metadata(interface)
     }
 
-    fn current_bus_frame(&self, _device: &Device) -> UsbResult<(u64, SystemTime)> {
+    fn current_bus_frame(&self) -> UsbResult<(u64, SystemTime)> {
         // In theory, this should be easy. We call get_frame_number, which gives us
         // the u64 frame number and the AbsoluteTime. In practice, I currently have no
         // idea _which_ macOS absolute time that its, and I'm worried it's a mach absolute time,
@@ -318,7 +433,6 @@ impl Backend for MacOsBackend {
 
     fn control_read(
         &self,
-        device: &Device,
         request_type: u8,
         request_number: u8,
         value: u16,
@@ -332,7 +446,6 @@ impl Backend for MacOsBackend {
 
         unsafe {
             self.control(
-                device,
                 request_type,
                 request_number,
                 value,
@@ -346,7 +459,6 @@ impl Backend for MacOsBackend {
 
     fn control_write(
         &self,
-        device: &Device,
         request_type: u8,
         request_number: u8,
         value: u16,
@@ -360,7 +472,6 @@ impl Backend for MacOsBackend {
 
         unsafe {
             self.control(
-                device,
                 request_type,
                 request_number,
                 value,
@@ -375,7 +486,6 @@ impl Backend for MacOsBackend {
 
     fn control_read_nonblocking(
         &self,
-        device: &Device,
         request_type: u8,
         request_number: u8,
         value: u16,
@@ -385,24 +495,25 @@ impl Backend for MacOsBackend {
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
         unsafe {
-            // Extract the data we were passed from the user, so we can pass it to IOKit.
-            let mut data_dyn = (*target).write().unwrap();
-            let data = data_dyn.as_mut();
+            // Extract the data we were passed from the user, so we can pass it to IOKit; holding
+            // the buffer's lock until IOKit's done writing to it.
+            let mut guard = target.write_arc();
+            let data = guard.as_mut();
+            let (pointer, length) = (data.as_mut_ptr(), data.len());
 
             // If the data is too long for a control request, error out.
-            if data.len() > (u16::MAX as usize) {
+            if length > (u16::MAX as usize) {
                 return Err(Error::Overrun);
             }
 
             self.control_nonblocking(
-                device,
                 request_type,
                 request_number,
                 value,
                 index,
-                data.as_ptr() as *mut c_void,
-                data.len() as u16,
-                callback,
+                pointer as *mut c_void,
+                length as u16,
+                holding_buffer(guard, callback),
                 timeout,
             )?;
             Ok(())
@@ -411,7 +522,6 @@ impl Backend for MacOsBackend {
 
     fn control_write_nonblocking(
         &self,
-        device: &Device,
         request_type: u8,
         request_number: u8,
         value: u16,
@@ -421,6 +531,7 @@ impl Backend for MacOsBackend {
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
         unsafe {
+            let callback = holding_buffer(Arc::clone(&data), callback);
             let data = (*data).as_ref();
 
             // If the data is too long for a control request, error out.
@@ -429,7 +540,6 @@ impl Backend for MacOsBackend {
             }
 
             self.control_nonblocking(
-                device,
                 request_type,
                 request_number,
                 value,
@@ -443,99 +553,135 @@ impl Backend for MacOsBackend {
         }
     }
 
-    fn read(
+    fn read(&self, endpoint: u8, buffer: &mut [u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        let (pipe_ref, interface) = self.resources_for_in_endpoint(endpoint)?;
+
+        if let Some(timeout) = timeout {
+            let timeout = to_iokit_timeout(timeout);
+            interface.read_with_timeout(pipe_ref, buffer, timeout, timeout)
+        } else {
+            interface.read(pipe_ref, buffer)
+        }
+    }
+
+    fn write(&self, endpoint: u8, data: &[u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        let (pipe_ref, interface) = self.resources_for_out_endpoint(endpoint)?;
+
+        // IOKit doesn't report how much a synchronous write moved; it either sends
+        // everything, or fails.
+        if let Some(timeout) = timeout {
+            let timeout = to_iokit_timeout(timeout);
+            interface.write_with_timeout(pipe_ref, data, timeout, timeout)?;
+        } else {
+            interface.write(pipe_ref, data)?;
+        }
+
+        Ok(data.len())
+    }
+
+    fn read_with_timeouts(
         &self,
-        device: &Device,
         endpoint: u8,
         buffer: &mut [u8],
-        timeout: Option<Duration>,
+        timeout: TransferTimeout,
     ) -> UsbResult<usize> {
-        unsafe {
-            let (pipe_ref, interface) = self.resources_for_in_endpoint(device, endpoint)?;
+        let (pipe_ref, interface) = self.resources_for_in_endpoint(endpoint)?;
+        let (no_data, completion) = to_iokit_timeouts(timeout);
 
-            if let Some(timeout) = timeout {
-                interface.read_with_timeout(pipe_ref, buffer, to_iokit_timeout(timeout))
-            } else {
-                interface.read(pipe_ref, buffer)
-            }
-        }
+        interface.read_with_timeout(pipe_ref, buffer, no_data, completion)
     }
 
-    fn write(
+    fn write_with_timeouts(
         &self,
-        device: &Device,
         endpoint: u8,
         data: &[u8],
-        timeout: Option<Duration>,
-    ) -> UsbResult<()> {
-        unsafe {
-            let (pipe_ref, interface) = self.resources_for_out_endpoint(device, endpoint)?;
+        timeout: TransferTimeout,
+    ) -> UsbResult<usize> {
+        let (pipe_ref, interface) = self.resources_for_out_endpoint(endpoint)?;
+        let (no_data, completion) = to_iokit_timeouts(timeout);
 
-            if let Some(timeout) = timeout {
-                interface.write_with_timeout(pipe_ref, data, to_iokit_timeout(timeout))
-            } else {
-                interface.write(pipe_ref, data)
-            }
-        }
+        interface.write_with_timeout(pipe_ref, data, no_data, completion)?;
+        Ok(data.len())
+    }
+
+    fn allocate_transfer_memory(
+        &self,
+        endpoint: u8,
+        length: usize,
+    ) -> UsbResult<Box<dyn TransferMemory>> {
+        let (_, interface) = self.resources_for_endpoint(endpoint)?;
+
+        // IOKit keeps low-latency buffers wired down and mapped for the interface; so it can
+        // skip preparing the memory for each transfer.
+        let kind = if endpoint & 0x80 != 0 {
+            kUSBLowLatencyReadBuffer
+        } else {
+            kUSBLowLatencyWriteBuffer
+        };
+        Ok(Box::new(interface.create_transfer_buffer(length, kind)?))
     }
 
     fn read_nonblocking(
         &self,
-        device: &Device,
         endpoint: u8,
         buffer: ReadBuffer,
-        callback: Box<dyn FnOnce(UsbResult<usize>)>,
+        callback: TransferCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
-        unsafe {
-            let (pipe_ref, interface) = self.resources_for_in_endpoint(device, endpoint)?;
+        let (pipe_ref, interface) = self.resources_for_in_endpoint(endpoint)?;
 
-            // Extract the data we were passed from the user, so we can pass it to IOKit.
-            let mut data_dyn = (*buffer).write().unwrap();
-            let data = data_dyn.as_mut();
+        // Extract the data we were passed from the user, so we can pass it to IOKit; holding
+        // the buffer's lock until IOKit's done writing to it.
+        let mut guard = buffer.write_arc();
+        let data = guard.as_mut();
+        let (pointer, length) = (data.as_mut_ptr(), data.len());
 
+        let callbacks = &self.callbacks;
+        let callback = holding_buffer(guard, callback);
+        callbacks.submit(callback, |refcon| {
             if let Some(timeout) = timeout {
                 interface.read_with_timeout_nonblocking(
                     pipe_ref,
-                    data.as_mut_ptr() as *mut c_void,
-                    data.len() as u32,
+                    pointer as *mut c_void,
+                    length as u32,
                     delegate_iousb_callback,
-                    leak_to_iokit(callback),
+                    refcon,
                     to_iokit_timeout(timeout),
                 )
             } else {
                 interface.read_nonblocking(
                     pipe_ref,
-                    data.as_mut_ptr() as *mut c_void,
-                    data.len() as u32,
+                    pointer as *mut c_void,
+                    length as u32,
                     delegate_iousb_callback,
-                    leak_to_iokit(callback),
+                    refcon,
                 )
             }
-        }
+        })
     }
 
     fn write_nonblocking(
         &self,
-        device: &Device,
         endpoint: u8,
         data: WriteBuffer,
-        callback: Box<dyn FnOnce(UsbResult<usize>)>,
+        callback: TransferCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
-        unsafe {
-            let (pipe_ref, interface) = self.resources_for_out_endpoint(device, endpoint)?;
+        let (pipe_ref, interface) = self.resources_for_out_endpoint(endpoint)?;
 
-            // Extract the data we were passed from the user, so we can pass it to IOKit.
-            let data = (*data).as_ref();
+        // Extract the data we were passed from the user, so we can pass it to IOKit.
+        let callback = holding_buffer(Arc::clone(&data), callback);
+        let data = (*data).as_ref();
 
+        let callbacks = &self.callbacks;
+        callbacks.submit(callback, |refcon| {
             if let Some(timeout) = timeout {
                 interface.write_with_timeout_nonblocking(
                     pipe_ref,
                     data.as_ptr() as *mut c_void,
                     data.len() as u32,
                     delegate_iousb_callback,
-                    leak_to_iokit(callback),
+                    refcon,
                     to_iokit_timeout(timeout),
                 )
             } else {
@@ -544,11 +690,409 @@ impl Backend for MacOsBackend {
                     data.as_ptr() as *mut c_void,
                     data.len() as u32,
                     delegate_iousb_callback,
-                    leak_to_iokit(callback),
+                    refcon,
                 )
             }
+        })
+    }
+
+    fn read_isochronous_nonblocking(
+        &self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        packet_lengths: &[usize],
+        options: &IsoOptions,
+        callback: IsoCallback,
+    ) -> UsbResult<()> {
+        unsafe {
+            let (pipe_ref, interface) = self.resources_for_in_endpoint(endpoint)?;
+            let start_frame = self.isochronous_start_frame(options.start_frame)?;
+            let callbacks = &self.callbacks;
+
+            // For low-latency transfers, we read into a buffer IOKit already has mapped, and
+            // copy the data out once it's landed.
+            if options.low_latency {
+                let length = packet_lengths.iter().sum();
+                let mut data =
+                    interface.create_low_latency_buffer(length, kUSBLowLatencyReadBuffer)?;
+                let mut frames = low_latency_frames(interface, packet_lengths)?;
+                let frame_list = frames.as_mut_slice::<IOUSBLowLatencyIsocFrame>() as *mut [_];
+                let raw_data = data.as_mut_ptr();
+
+                let completion = low_latency_completion(frames, data, Some(buffer), callback);
+                return callbacks.submit(completion, |refcon| {
+                    interface.read_isochronous_low_latency(
+                        pipe_ref,
+                        raw_data,
+                        start_frame,
+                        &mut *frame_list,
+                        delegate_iousb_callback,
+                        refcon,
+                    )
+                });
+            }
+
+            // IOKit fills in our frame list as the packets arrive; so it needs to stay put
+            // until the transfer completes.
+            let mut frames = isochronous_frames(packet_lengths)?;
+            let frame_list = &mut *frames as *mut [IOUSBIsocFrame];
+            let mut guard = buffer.write_arc();
+            let data = guard.as_mut().as_mut_ptr();
+
+            callbacks.submit(isochronous_completion(frames, guard, callback), |refcon| {
+                interface.read_isochronous_nonblocking(
+                    pipe_ref,
+                    data as *mut c_void,
+                    start_frame,
+                    &mut *frame_list,
+                    delegate_iousb_callback,
+                    refcon,
+                )
+            })
+        }
+    }
+
+    fn write_isochronous_nonblocking(
+        &self,
+        endpoint: u8,
+        data: WriteBuffer,
+        packet_lengths: &[usize],
+        options: &IsoOptions,
+        callback: IsoCallback,
+    ) -> UsbResult<()> {
+        unsafe {
+            let (pipe_ref, interface) = self.resources_for_out_endpoint(endpoint)?;
+            let start_frame = self.isochronous_start_frame(options.start_frame)?;
+            let callbacks = &self.callbacks;
+
+            // For low-latency transfers, we stage the data in a buffer IOKit already has mapped.
+            if options.low_latency {
+                let length = packet_lengths.iter().sum();
+                let mut staging =
+                    interface.create_low_latency_buffer(length, kUSBLowLatencyWriteBuffer)?;
+                staging
+                    .as_mut_slice::<u8>()
+                    .copy_from_slice(&data.as_ref().as_ref()[..length]);
+                let mut frames = low_latency_frames(interface, packet_lengths)?;
+                let frame_list = frames.as_mut_slice::<IOUSBLowLatencyIsocFrame>() as *mut [_];
+                let raw_data = staging.as_mut_ptr();
+
+                let completion = low_latency_completion(frames, staging, None, callback);
+                return callbacks.submit(completion, |refcon| {
+                    interface.write_isochronous_low_latency(
+                        pipe_ref,
+                        raw_data,
+                        start_frame,
+                        &mut *frame_list,
+                        delegate_iousb_callback,
+                        refcon,
+                    )
+                });
+            }
+
+            let mut frames = isochronous_frames(packet_lengths)?;
+            let frame_list = &mut *frames as *mut [IOUSBIsocFrame];
+            let raw_data = data.as_ref().as_ref().as_ptr();
+
+            callbacks.submit(isochronous_completion(frames, data, callback), |refcon| {
+                interface.write_isochronous_nonblocking(
+                    pipe_ref,
+                    raw_data as *mut c_void,
+                    start_frame,
+                    &mut *frame_list,
+                    delegate_iousb_callback,
+                    refcon,
+                )
+            })
         }
     }
 }
 
 unsafe impl Send for MacOsBackend {}
+
+impl MacOsDevice {
+    /// Helper for issuing control requests.
+    unsafe fn control(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: *mut c_void,
+        length: u16,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let device = &self.device;
+
+        // If we have a timeout, use the *TO request function.
+        if let Some(timeout) = timeout {
+            let timeout_ms = to_iokit_timeout(timeout);
+
+            // Populate the request-with-TimeOut structure, which will be passed to macOS.
+            let mut request_struct = IOUSBDevRequestTO {
+                bmRequestType: request_type,
+                bRequest: request_number,
+                wValue: value,
+                wIndex: index,
+                wLength: length,
+                pData: data,
+                wLenDone: 0,
+                noDataTimeout: timeout_ms,
+                completionTimeout: timeout_ms,
+            };
+
+            // And finally, perform the request.
+            device.device_request_with_timeout(&mut request_struct)?;
+            Ok(request_struct.wLenDone as usize)
+        } else {
+            // Populate the (no timeout) request structure, which will be passed to macOS.
+            let mut request_struct = IOUSBDevRequest {
+                bmRequestType: request_type,
+                bRequest: request_number,
+                wValue: value,
+                wIndex: index,
+                wLength: length,
+                pData: data,
+                wLenDone: 0,
+            };
+
+            // And finally, perform the request.
+            device.device_request(&mut request_struct)?;
+            Ok(request_struct.wLenDone as usize)
+        }
+    }
+
+    /// Helper for issuing async control requests.
+    unsafe fn control_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: *mut c_void,
+        length: u16,
+        callback: Box<CallbackRefconType>,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let callbacks = &self.callbacks;
+        let device = &self.device;
+
+        // If we have a timeout, use the *TO request function.
+        if let Some(timeout) = timeout {
+            let timeout_ms = to_iokit_timeout(timeout);
+
+            // Populate the request-with-TimeOut structure, which will be passed to macOS.
+            let mut request_struct = IOUSBDevRequestTO {
+                bmRequestType: request_type,
+                bRequest: request_number,
+                wValue: value,
+                wIndex: index,
+                wLength: length,
+                pData: data,
+                wLenDone: 0,
+                noDataTimeout: timeout_ms,
+                completionTimeout: timeout_ms,
+            };
+
+            // And finally, perform the request.
+            callbacks.submit(callback, |refcon| {
+                device.device_request_nonblocking_with_timeout(
+                    &mut request_struct,
+                    delegate_iousb_callback,
+                    refcon,
+                )
+            })
+        } else {
+            // Populate the (no timeout) request structure, which will be passed to macOS.
+            let mut request_struct = IOUSBDevRequest {
+                bmRequestType: request_type,
+                bRequest: request_number,
+                wValue: value,
+                wIndex: index,
+                wLength: length,
+                pData: data,
+                wLenDone: 0,
+            };
+
+            // And finally, perform the request.
+            callbacks.submit(callback, |refcon| {
+                device.device_request_nonblocking(
+                    &mut request_struct,
+                    delegate_iousb_callback,
+                    refcon,
+                )
+            })
+        }
+    }
+
+    // Helper that picks the frame an isochronous transfer should start in.
+    fn isochronous_start_frame(&self, start_frame: Option<u64>) -> UsbResult<u64> {
+        match start_frame {
+            Some(frame) => Ok(frame),
+            None => {
+                let (frame, _) = self.device.get_frame_number()?;
+                Ok(frame + ISOCHRONOUS_ASAP_LATENCY_FRAMES)
+            }
+        }
+    }
+
+    // Helper that converts an endpoint address into a interface + pipeRef.
+    fn resources_for_endpoint(&self, address: u8) -> UsbResult<(u8, &OsInterface)> {
+        // Find the endpoint metadata for the relevant endpoint...
+        let endpoint_info = self
+            .endpoint_metadata
+            .get(&address)
+            .ok_or(Error::InvalidEndpoint)?;
+
+        // ... and get the associated interface.
+        let interface = self
+            .interfaces
+            .get(&endpoint_info.interface_number)
+            .expect("endpoint points to an invalid interface");
+
+        Ok((endpoint_info.pipe_ref, interface))
+    }
+
+    // Helper that converts an IN endpoint number into a interface + pipeRef.
+    fn resources_for_in_endpoint(&self, number: u8) -> UsbResult<(u8, &OsInterface)> {
+        self.resources_for_endpoint(address_for_in_endpoint(number))
+    }
+
+    // Helper that converts an OUT endpoint number into a interface + pipeRef.
+    fn resources_for_out_endpoint(&self, number: u8) -> UsbResult<(u8, &OsInterface)> {
+        self.resources_for_endpoint(address_for_out_endpoint(number))
+    }
+}
+
+/// Builds the frame list for an isochronous transfer; one frame per packet.
+fn isochronous_frames(packet_lengths: &[usize]) -> UsbResult<Box<[IOUSBIsocFrame]>> {
+    packet_lengths
+        .iter()
+        .map(|&length| {
+            Ok(IOUSBIsocFrame {
+                frStatus: 0,
+                frReqCount: length.try_into().map_err(|_| Error::InvalidArgument)?,
+                frActCount: 0,
+            })
+        })
+        .collect()
+}
+
+/// Wraps an isochronous callback, so it's called with the outcome of each frame once IOKit
+/// has filled them in. Holds onto the frame list and the transfer's buffer until then.
+fn isochronous_completion<B: Send + 'static>(
+    frames: Box<[IOUSBIsocFrame]>,
+    buffer: B,
+    callback: IsoCallback,
+) -> Box<CallbackRefconType> {
+    Box::new(move |result| {
+        // IOKit is done with the buffer; and for reads, the callback may want to lock it.
+        drop(buffer);
+
+        // Short packets are business as usual for isochronous endpoints; so IOKit's underrun
+        // errors just mean some frames came up short.
+        let result = match result {
+            Ok(_) | Err(Error::Underrun) => Ok(frames
+                .iter()
+                .map(|frame| packet_status(frame.frStatus, frame.frActCount, None))
+                .collect()),
+            Err(error) => Err(error),
+        };
+
+        callback(result)
+    })
+}
+
+/// Builds the frame list for a low-latency isochronous transfer, in a buffer IOKit has mapped.
+fn low_latency_frames(
+    interface: &OsInterface,
+    packet_lengths: &[usize],
+) -> UsbResult<LowLatencyBuffer> {
+    let size = packet_lengths.len() * std::mem::size_of::<IOUSBLowLatencyIsocFrame>();
+    let mut buffer = interface.create_low_latency_buffer(size, kUSBLowLatencyFrameListBuffer)?;
+
+    // Nothing has been handed to IOKit yet; so the list is ours to fill in.
+    let frames = unsafe { buffer.as_mut_slice::<IOUSBLowLatencyIsocFrame>() };
+    for (frame, &length) in frames.iter_mut().zip(packet_lengths) {
+        *frame = IOUSBLowLatencyIsocFrame {
+            frStatus: 0,
+            frReqCount: length.try_into().map_err(|_| Error::InvalidArgument)?,
+            frActCount: 0,
+            frTimeStamp: AbsoluteTime { lo: 0, hi: 0 },
+        };
+    }
+
+    Ok(buffer)
+}
+
+/// Wraps an isochronous callback for a low-latency transfer; which copies any data read out
+/// of IOKit's buffer, and reports each frame's outcome and timestamp. Holds onto IOKit's
+/// buffers until then.
+fn low_latency_completion(
+    mut frames: LowLatencyBuffer,
+    mut data: LowLatencyBuffer,
+    destination: Option<ReadBuffer>,
+    callback: IsoCallback,
+) -> Box<CallbackRefconType> {
+    Box::new(move |result| {
+        let result = match result {
+            Ok(_) | Err(Error::Underrun) => {
+                // The transfer is over; so IOKit is done with both of our buffers.
+                let frames = unsafe { frames.as_mut_slice::<IOUSBLowLatencyIsocFrame>() };
+                if let Some(destination) = destination {
+                    let mut destination = destination.write();
+                    let destination = destination.as_mut();
+                    let source = unsafe { data.as_mut_slice::<u8>() };
+
+                    let length = source.len().min(destination.len());
+                    destination[..length].copy_from_slice(&source[..length]);
+                }
+
+                Ok(frames
+                    .iter()
+                    .map(|frame| {
+                        // Frames that never went out are never stamped.
+                        let time = frame.frTimeStamp;
+                        let timestamp =
+                            (time.lo != 0 || time.hi != 0).then(|| absolute_time_to_duration(time));
+                        packet_status(frame.frStatus, frame.frActCount, timestamp)
+                    })
+                    .collect())
+            }
+            Err(error) => Err(error),
+        };
+
+        callback(result)
+    })
+}
+
+/// Converts what IOKit recorded for an isochronous frame into the status of its packet.
+fn packet_status(status: i32, actual_length: u16, timestamp: Option<Duration>) -> IsoPacketStatus {
+    IsoPacketStatus {
+        // Short packets are business as usual for isochronous endpoints; so IOKit's underrun
+        // errors just mean the frame came up short.
+        status: match UsbResult::from_io_return(status) {
+            Err(Error::Underrun) => Ok(()),
+            status => status,
+        },
+        actual_length: actual_length as usize,
+        timestamp,
+    }
+}
+
+/// Converts a kind of extra power into the type IOKit expects.
+fn power_type(kind: ExtraPowerKind) -> u32 {
+    match kind {
+        ExtraPowerKind::Wake => kUSBPowerDuringWake,
+        ExtraPowerKind::Sleep => kUSBPowerDuringSleep,
+    }
+}
+
+/// Converts a pair of timeouts into IOKit's (no-data, completion) representation; in which
+/// zero means there's no limit.
+fn to_iokit_timeouts(timeout: TransferTimeout) -> (u32, u32) {
+    (
+        timeout.no_data.map(to_iokit_timeout).unwrap_or(0),
+        timeout.completion.map(to_iokit_timeout).unwrap_or(0),
+    )
+}
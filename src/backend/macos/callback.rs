@@ -1,27 +1,200 @@
 //! Helper for working with C callbacks for async USB functions.
 
-use std::ffi::c_void;
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    sync::{Arc, Condvar, Mutex, MutexGuard, Weak},
+    time::{Duration, Instant},
+};
 
 use io_kit_sys::ret::IOReturn;
 
-use crate::{backend::macos::iokit::unleak_from_iokit, UsbResult};
+use crate::{
+    backend::macos::iokit::{leak_to_iokit, unleak_from_iokit},
+    backend::TransferCallback,
+    Error, UsbResult,
+};
 
 use super::iokit::IOKitResultExtension;
 
-pub(crate) type CallbackRefconType = dyn FnOnce(UsbResult<usize>);
+pub(crate) type CallbackRefconType = dyn FnOnce(UsbResult<usize>) + Send;
+
+/// The refcon we hand to IOKit for each transfer; which lets us find its callback again.
+struct CallbackContext {
+    /// The registry holding our callback; which is gone if its device has been dropped.
+    registry: Weak<CallbackRegistry>,
+
+    /// The key under which our callback is registered.
+    id: usize,
+}
+
+/// A callback that's been handed to IOKit, along with the refcon IOKit will call it with.
+struct PendingCallback {
+    /// The callback itself.
+    callback: Box<CallbackRefconType>,
+
+    /// The address of the CallbackContext we leaked to IOKit; which we free ourselves, if IOKit
+    /// never calls back.
+    refcon: usize,
+}
+
+/// The callbacks a registry is waiting on, and the ID it'll give the next one.
+#[derive(Default)]
+struct RegistryState {
+    next_id: usize,
+    pending: HashMap<usize, PendingCallback>,
+}
+
+/// Per-device registry of callbacks that have been handed to IOKit, but not yet called.
+///
+/// IOKit only calls back if the device's event sources are still attached to a running
+/// runloop; if a device is dropped with transfers in flight, their callbacks would otherwise
+/// never run (or be freed). Keeping them here lets us wait for them, and complete any IOKit
+/// never gets to ourselves.
+#[derive(Default)]
+pub(crate) struct CallbackRegistry {
+    /// The callbacks we're waiting on.
+    state: Mutex<RegistryState>,
+
+    /// Signaled each time a callback is taken out of the registry.
+    taken: Condvar,
+}
+
+impl CallbackRegistry {
+    /// Registers a callback, and returns the refcon to hand to IOKit alongside
+    /// [delegate_iousb_callback].
+    pub(crate) fn register(self: &Arc<Self>, callback: Box<CallbackRefconType>) -> *mut c_void {
+        let mut state = self.state();
+
+        let id = state.next_id;
+        state.next_id = id.wrapping_add(1);
+
+        let refcon = leak_to_iokit(CallbackContext {
+            registry: Arc::downgrade(self),
+            id,
+        });
+        state.pending.insert(
+            id,
+            PendingCallback {
+                callback,
+                refcon: refcon as usize,
+            },
+        );
+
+        refcon
+    }
+
+    /// Registers a callback, and submits a transfer that will call it back.
+    ///
+    /// If the submission fails, IOKit will never call us back; so we'll drop the callback
+    /// here, rather than leaving it registered.
+    pub(crate) fn submit(
+        self: &Arc<Self>,
+        callback: Box<CallbackRefconType>,
+        submission: impl FnOnce(*mut c_void) -> UsbResult<()>,
+    ) -> UsbResult<()> {
+        let refcon = self.register(callback);
+
+        let result = submission(refcon);
+        if result.is_err() {
+            let context: CallbackContext = unleak_from_iokit(refcon);
+            self.take(context.id);
+        }
+
+        result
+    }
+
+    /// Removes a callback from the registry, if it's still there.
+    fn take(&self, id: usize) -> Option<Box<CallbackRefconType>> {
+        let pending = self.state().pending.remove(&id);
+        self.taken.notify_all();
+
+        pending.map(|pending| pending.callback)
+    }
+
+    /// Waits until IOKit has called back every outstanding callback, or the timeout passes.
+    /// Returns true iff nothing is left outstanding.
+    pub(crate) fn wait_for_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state();
+
+        while !state.pending.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            state = self.taken.wait_timeout(state, remaining).unwrap().0;
+        }
+
+        true
+    }
+
+    /// Completes every outstanding callback with [Error::Aborted], and frees the refcons IOKit
+    /// was holding for them.
+    ///
+    /// # Safety
+    /// IOKit must no longer be able to call us back; i.e. the device's event sources must have
+    /// been detached, and its transfers aborted, so no completion can arrive with a freed refcon.
+    pub(crate) unsafe fn abort_all(&self) {
+        // Pull the callbacks out before calling them, so they're free to submit new transfers.
+        let aborted: Vec<PendingCallback> = self
+            .state()
+            .pending
+            .drain()
+            .map(|(_, pending)| pending)
+            .collect();
+
+        for pending in aborted {
+            let _context: CallbackContext = unleak_from_iokit(pending.refcon as *mut c_void);
+            (pending.callback)(Err(Error::Aborted));
+        }
+    }
+
+    /// Locks our state.
+    fn state(&self) -> MutexGuard<'_, RegistryState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl std::fmt::Debug for CallbackRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallbackRegistry")
+            .field("outstanding", &self.state().pending.len())
+            .finish()
+    }
+}
+
+/// Wraps a callback so it holds onto the buffer IOKit is using until the transfer has completed;
+/// which keeps the buffer alive even if everyone else lets go of it first. For reads, that's
+/// the buffer's write guard; so it's let go of before the callback runs.
+pub(crate) fn holding_buffer<B: Send + 'static>(
+    buffer: B,
+    callback: TransferCallback,
+) -> Box<CallbackRefconType> {
+    Box::new(move |result| {
+        drop(buffer);
+        callback(result)
+    })
+}
 
 /// Terrifying bridge helper that allows IOKit to call a Rust callback.
 pub(crate) unsafe extern "C" fn delegate_iousb_callback(
-    callback: *mut c_void, // Actually a Box<CallbackRefconType>.
+    refcon: *mut c_void, // Actually a CallbackContext.
     result: IOReturn,
     total_length: *mut c_void,
 ) {
     // Demangle our type information, since IOKit's mangled it real nicely for us.
     let total_length = total_length as usize;
-    let callback: Box<CallbackRefconType> = unleak_from_iokit(callback);
+    let context: CallbackContext = unleak_from_iokit(refcon);
 
-    let callback_raw = Box::into_raw(callback);
-    let callback = Box::from_raw(callback_raw);
+    // Find our callback. If it's not there, its device was dropped, and it's already been
+    // completed on our behalf.
+    let Some(registry) = context.registry.upgrade() else {
+        return;
+    };
+    let Some(callback) = registry.take(context.id) else {
+        return;
+    };
 
     // Finally, call back the callback we were passed.
     callback(UsbResult::from_io_return_and_value(result, total_length));
@@ -2,42 +2,47 @@
 
 use std::{
     collections::HashMap,
-    ffi::c_void,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
+    ffi::{c_char, c_void},
+    sync::Arc,
     time,
 };
 
 use core_foundation_sys::base::SInt32;
 use io_kit_sys::{
     ret::{kIOReturnNoResources, kIOReturnSuccess},
-    IOIteratorNext,
+    types::io_service_t,
+    usb::lib::kIOUSBDeviceClassName,
+    IOIteratorNext, IOObjectConformsTo, IOObjectRetain,
 };
 use log::{debug, error};
 
 use crate::{
-    backend::macos::enumeration::get_device_iterator, backend::BackendDevice, DeviceInformation,
-    Error, UsbResult,
+    backend::macos::enumeration::get_device_iterator,
+    backend::{BackendDevice, DisconnectSignal},
+    DeviceInformation, Error, OpenMode, RawHandle, UsbResult,
 };
 
 use super::{
+    callback::CallbackRegistry,
     endpoint::{address_for_in_endpoint, address_for_out_endpoint},
     interface::interface_from_service,
     iokit::{
         self, get_iokit_numeric_device_property, usb_device_type_id, IoObject, NotificationSource,
-        OsDevice, OsInterface, PluginInterface,
+        OsDevice, OsInterface, PluginInterface, RemovalNotification, RemovalState,
     },
     iokit_c::{
-        kIOCFPlugInInterfaceID, kIOUsbDeviceUserClientTypeID, IOCFPlugInInterface,
-        IOCreatePlugInInterfaceForService,
+        kIOCFPlugInInterfaceID, kIOUsbDeviceUserClientTypeID, kUSBReEnumerateReleaseDeviceMask,
+        IOCFPlugInInterface, IOCreatePlugInInterfaceForService,
     },
+    reactor::{EventReactor, EventRegistration},
 };
 
 /// Type alias to make it clear when our u32 handle is an IoService.
 type IoService = IoObject;
 
+/// How long a device being dropped waits for IOKit to deliver its aborted transfers.
+const ABORT_DRAIN_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
 /// Metadata for a given endpoint; used for working with the endpoint
 /// in a macOS interface context.
 #[derive(Debug)]
@@ -67,8 +72,27 @@ pub(crate) struct MacOsDevice {
     /// Contains the information necessary to work with an endpoint.
     pub(crate) endpoint_metadata: HashMap<u8, EndpointInformation>,
 
-    /// Flag used to indicate when this device is being dropped, and thus its thread should die.
-    pub(crate) termination_flag: Arc<AtomicBool>,
+    /// Our registration with the backend's event reactor, which runs our event callbacks.
+    pub(crate) events: Option<EventRegistration>,
+
+    /// The registration for our interfaces' event sources; which are replaced whenever the
+    /// configuration changes.
+    pub(crate) interface_events: Option<EventRegistration>,
+
+    /// The callbacks for transfers we have in flight.
+    pub(crate) callbacks: Arc<CallbackRegistry>,
+
+    /// Set once IOKit tells us the device has been unplugged.
+    pub(crate) disconnected: Arc<RemovalState>,
+
+    /// Our subscription to the device's removal notifications.
+    pub(crate) removal_notification: Option<RemovalNotification>,
+
+    /// True iff we've captured the device from its kernel drivers.
+    pub(crate) captured: bool,
+
+    /// The device's IOKit location ID, which identifies the port it's plugged into.
+    pub(crate) location_id: Option<u64>,
 }
 
 unsafe impl Send for MacOsDevice {}
@@ -139,15 +163,27 @@ impl MacOsDevice {
         // internal metadata field for the control endpoint. Fun.
         let pipe_ref_count = interface.endpoint_count()?;
 
+        // We'll temporarily open the interface, in order to get its endpoint data,
+        // as MacOS won't let us get that information without it. We'll then close the
+        // interface until we're ready to actually use it; unless it was already claimed.
+        let was_open = interface.is_open();
+        if pipe_ref_count > 0 {
+            interface.open()?;
+        }
+
         // Next, we'll need to iterate over the pipe refs.
         // Remember, they're one indexed. Yes. One indexed.
         for pipe_ref in 1..=pipe_ref_count {
-            // We'll temporarily open the interface, in order to get its endpoint data,
-            // as MacOS won't let us get that information without it. We'll then close the
-            // interface until we're ready to actually use it.
-            interface.open()?;
-            let endpoint_metadata = interface.endpoint_properties(pipe_ref)?;
-            interface.close();
+            let endpoint_metadata = interface.endpoint_properties(pipe_ref);
+            let endpoint_metadata = match endpoint_metadata {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    if !was_open {
+                        interface.close();
+                    }
+                    return Err(e);
+                }
+            };
 
             // Once we know the endpoint number, we can construct the part we really want:
             // the endpoint address.
@@ -167,8 +203,72 @@ impl MacOsDevice {
             );
         }
 
+        if !was_open {
+            interface.close();
+        }
+        Ok(())
+    }
+
+    /// Returns the reactor that runs our event callbacks; or None if we never registered with one.
+    pub(crate) fn reactor(&self) -> Option<Arc<EventReactor>> {
+        self.events
+            .as_ref()
+            .map(|events| Arc::clone(events.reactor()))
+    }
+
+    /// Rebuilds our interfaces and endpoint metadata from scratch; for use after the device's
+    /// configuration changes, which replaces all of its interfaces.
+    pub(crate) fn repopulate_interfaces(&mut self) -> UsbResult<()> {
+        // Detach our old interfaces' event sources, and let go of the interfaces themselves...
+        let reactor = self.reactor();
+        self.interface_events.take();
+        self.interfaces.clear();
+        self.endpoint_metadata.clear();
+
+        // ... and then find the new ones.
+        let mut notification_sources: Vec<NotificationSource> = vec![];
+        self.populate_interfaces(&mut notification_sources)?;
+
+        if let Some(reactor) = reactor {
+            self.interface_events = Some(reactor.register(notification_sources));
+        }
         Ok(())
     }
+
+    /// Rebuilds the endpoint metadata for a single interface; for use after its alternate
+    /// setting changes, which changes its endpoints (and thus its pipe refs).
+    pub(crate) fn repopulate_endpoint_metadata(&mut self, interface_number: u8) -> UsbResult<()> {
+        self.endpoint_metadata
+            .retain(|_, endpoint| endpoint.interface_number != interface_number);
+
+        // Take the interface out of our map while we work with it, so we can update our metadata.
+        let mut interface = self
+            .interfaces
+            .remove(&interface_number)
+            .ok_or(Error::InvalidInterface)?;
+        let result = self.populate_endpoint_metadata(&mut interface);
+        self.interfaces.insert(interface_number, interface);
+
+        result
+    }
+
+    /// Finds the IOKit service that represents the given interface.
+    pub(crate) fn interface_service(&self, interface_number: u8) -> UsbResult<IoService> {
+        let interface_iterator = self.device.create_interface_iterator()?;
+
+        loop {
+            let service = IoService::new(unsafe { IOIteratorNext(interface_iterator.get()) });
+            if service.is_invalid() {
+                return Err(Error::InvalidInterface);
+            }
+
+            let number: UsbResult<u8> =
+                get_iokit_numeric_device_property(service.get(), "bInterfaceNumber");
+            if number == Ok(interface_number) {
+                return Ok(service);
+            }
+        }
+    }
 }
 
 impl BackendDevice for MacOsDevice {
@@ -183,17 +283,72 @@ impl BackendDevice for MacOsDevice {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
+
+    fn is_disconnected(&self) -> bool {
+        self.disconnected.is_disconnected()
+    }
+
+    fn set_disconnect_signal(&mut self, signal: Arc<DisconnectSignal>) {
+        self.disconnected.replace_signal(Some(signal));
+    }
+
+    fn raw_handle(&self) -> Option<RawHandle> {
+        Some(RawHandle::IoKitDevice(self.device.raw().cast()))
+    }
+
+    fn raw_interface_handle(&self, interface: u8) -> Option<RawHandle> {
+        // Interfaces we were denied access to have no handle to give out.
+        let raw = self.interfaces.get(&interface)?.raw();
+        (!raw.is_null()).then(|| RawHandle::IoKitInterface(raw.cast()))
+    }
 }
 
 impl Drop for MacOsDevice {
     fn drop(&mut self) {
-        // Let our event thread know it can stop running, as we're no longer sending it events.
-        self.termination_flag.store(true, Ordering::Relaxed);
+        // Abort everything we have in flight; once this returns, the controller is done with
+        // our buffers...
+        for endpoint in self.endpoint_metadata.values() {
+            if let Some(interface) = self.interfaces.get(&endpoint.interface_number) {
+                _ = interface.abort_pipe(endpoint.pipe_ref);
+            }
+        }
+        _ = self.device.abort_ep0();
+
+        // ... and give IOKit a chance to deliver the aborted transfers' completions, so their
+        // callbacks run with IOKit's own results. We can't wait on the reactor's thread; it's
+        // the one that would deliver them.
+        let on_reactor = self
+            .reactor()
+            .is_some_and(|reactor| reactor.is_current_thread());
+        if !on_reactor {
+            self.callbacks.wait_for_idle(ABORT_DRAIN_TIMEOUT);
+        }
+
+        // If we took the device from its kernel drivers, give it back...
+        if self.captured {
+            _ = self.device.reenumerate(kUSBReEnumerateReleaseDeviceMask);
+        }
+
+        // ... detach our event sources from the reactor, and close our interfaces...
+        self.interface_events.take();
+        self.events.take();
+        self.interfaces.clear();
+
+        // ... and since IOKit will no longer be able to call back anything still in flight,
+        // complete those callbacks ourselves.
+        unsafe { self.callbacks.abort_all() };
+
+        // Our removal notification's source is detached, now; so it's safe to tear down.
+        self.removal_notification.take();
     }
 }
 
 /// Converts an IOIteratorNext result into a backend USB device.
-fn open_usb_device_from_io_device(device_service: IoService) -> UsbResult<Box<dyn BackendDevice>> {
+fn open_usb_device_from_io_device(
+    device_service: IoService,
+    reactor: &Arc<EventReactor>,
+    mode: OpenMode,
+) -> UsbResult<Box<MacOsDevice>> {
     if device_service.is_invalid() {
         panic!("internal inconsistency: got a 0 io-object-handle");
     }
@@ -277,24 +432,42 @@ fn open_usb_device_from_io_device(device_service: IoService) -> UsbResult<Box<dy
                 device: OsDevice::new(raw_device),
                 interfaces: HashMap::new(),
                 endpoint_metadata: HashMap::new(),
-                termination_flag: Arc::new(AtomicBool::new(false)),
+                events: None,
+                interface_events: None,
+                callbacks: Arc::new(CallbackRegistry::default()),
+                disconnected: Arc::default(),
+                removal_notification: None,
+                captured: false,
+                location_id: get_iokit_numeric_device_property(device_service.get(), "locationID")
+                    .ok(),
             });
 
-            // .. open the device, since we said we'd do so...
-            backend_device.device.open()?;
+            // .. open the device, since we said we'd do so; unless we've been asked not to, in
+            // which case macOS will still let us make control requests...
+            match mode {
+                OpenMode::Normal => backend_device.device.open()?,
+                OpenMode::Exclusive => backend_device.device.open_seize()?,
+                OpenMode::DescriptorsOnly => (),
+            }
 
             // .. subscribe to per-device asynchronous events ...
             let mut notification_sources: Vec<NotificationSource> = vec![];
             notification_sources.push(backend_device.device.notification_source()?);
 
-            // ... ask it to populate its interfaces, and endpoint metadata ...
-            backend_device.populate_interfaces(&mut notification_sources)?;
+            // ... watch for the device being unplugged ...
+            let (removal_notification, removal_source) =
+                RemovalNotification::new(device_service.get(), &backend_device.disconnected)?;
+            notification_sources.push(removal_source);
+            backend_device.removal_notification = Some(removal_notification);
 
-            // ... spin up a thread to handle its events ...
-            let termination_condition = Arc::clone(&backend_device.termination_flag);
-            std::thread::spawn(move || {
-                NotificationSource::run_event_loop(notification_sources, termination_condition)
-            });
+            // ... hand its events to the reactor ...
+            backend_device.events = Some(reactor.register(notification_sources));
+
+            // ... ask it to populate its interfaces, and endpoint metadata; whose events are
+            // registered separately, since they change along with the configuration ...
+            let mut interface_sources: Vec<NotificationSource> = vec![];
+            backend_device.populate_interfaces(&mut interface_sources)?;
+            backend_device.interface_events = Some(reactor.register(interface_sources));
 
             // ... and return it.
             return Ok(backend_device);
@@ -304,14 +477,8 @@ fn open_usb_device_from_io_device(device_service: IoService) -> UsbResult<Box<dy
     Err(Error::DeviceNotFound)
 }
 
-/// Opens a device given the information acquired during enumeration.
-pub(crate) fn open_usb_device(
-    information: &DeviceInformation,
-) -> UsbResult<Box<dyn BackendDevice>> {
-    let target_location_id = information
-        .backend_numeric_location
-        .expect("invalid device_id; did you make this yourself?");
-
+/// Finds the IOKit service for the device with the given location ID.
+pub(crate) fn find_device_service(target_location_id: u64) -> UsbResult<IoService> {
     // NOTE(ktemkin): this process is -strictly- more than is necessary;
     // as macOS offers an ability to open a device by its LocationID. However,
     // at this point, it seems more valuable to me to interface with the least
@@ -323,13 +490,13 @@ pub(crate) fn open_usb_device(
 
         let mut device;
         while {
-            device = IOIteratorNext(device_iterator.get());
-            device != 0
+            device = IoService::new(IOIteratorNext(device_iterator.get()));
+            !device.is_invalid()
         } {
             // Find the macOS location ID for the given device, which uniquely identifies a given
             // device...
             let location_id: UsbResult<u32> =
-                get_iokit_numeric_device_property(device, "locationID");
+                get_iokit_numeric_device_property(device.get(), "locationID");
 
             // Skip any devices that don't have location IDs; as they're not real devices macOS
             // will let us work with -- they're e.g. root hubs or internal-only devices.
@@ -342,9 +509,47 @@ pub(crate) fn open_usb_device(
                 continue;
             }
 
-            return open_usb_device_from_io_device(IoService::new(device));
+            return Ok(device);
         }
 
         Err(Error::DeviceNotFound)
     }
 }
+
+/// Opens a device given the information acquired during enumeration.
+pub(crate) fn open_usb_device(
+    information: &DeviceInformation,
+    reactor: &Arc<EventReactor>,
+    mode: OpenMode,
+) -> UsbResult<Box<MacOsDevice>> {
+    let target_location_id = information
+        .backend_numeric_location
+        .expect("invalid device_id; did you make this yourself?");
+
+    open_usb_device_from_io_device(find_device_service(target_location_id)?, reactor, mode)
+}
+
+/// Opens the device behind an io_service_t someone else found for us; taking our own
+/// reference to it, so theirs stays valid.
+pub(crate) fn open_usb_device_from_service(
+    service: io_service_t,
+    reactor: &Arc<EventReactor>,
+    mode: OpenMode,
+) -> UsbResult<Box<MacOsDevice>> {
+    if service == 0 {
+        return Err(Error::InvalidArgument);
+    }
+
+    // Make sure we were actually handed a USB device, rather than e.g. one of its interfaces.
+    let class = kIOUSBDeviceClassName as *mut c_char;
+    if unsafe { IOObjectConformsTo(service, class) } == 0 {
+        return Err(Error::InvalidArgument);
+    }
+
+    let rc = unsafe { IOObjectRetain(service) };
+    if rc != kIOReturnSuccess {
+        return Err(Error::OsError(rc as i64));
+    }
+
+    open_usb_device_from_io_device(IoService::new(service), reactor, mode)
+}
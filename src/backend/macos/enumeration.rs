@@ -1,20 +1,38 @@
 //! Routines for querying IOKit for USB devices.
 
+use std::ffi::{c_void, CString};
+
 use super::iokit::{
-    get_iokit_numeric_device_property, get_iokit_string_device_property, IoIterator, IoObject,
+    cfstr, child_services, class_name, get_iokit_numeric_device_property,
+    get_iokit_string_device_property, IoIterator, IoObject,
 };
 use crate::{
+    descriptors::DeviceDescriptor,
+    device::InterfaceClass,
     error::{Error, UsbResult},
-    DeviceInformation,
+    topology::PortPath,
+    DeviceInformation, DeviceSelector, DeviceSpeed, EnumerationOptions,
+};
+
+use core_foundation_sys::{
+    base::{kCFAllocatorDefault, CFRelease, CFTypeRef},
+    dictionary::CFDictionarySetValue,
+    number::{kCFNumberSInt32Type, CFNumberCreate},
 };
 
 use io_kit_sys::{kIOMasterPortDefault, IOIteratorNext, IOServiceMatching};
 use io_kit_sys::{ret::kIOReturnSuccess, usb::lib::kIOUSBDeviceClassName};
-use io_kit_sys::{types::io_iterator_t, IOServiceGetMatchingServices};
+use io_kit_sys::{types::io_iterator_t, IOServiceGetMatchingServices, CFSTR};
 use log::debug;
 
 /// IOKit iterator object that walks all connected USB devices.
 pub(crate) fn get_device_iterator() -> UsbResult<IoIterator> {
+    get_matching_device_iterator(&DeviceSelector::default())
+}
+
+/// IOKit iterator object that walks the connected USB devices with the selector's VID and PID.
+/// IOKit can't match serial numbers for us; so those are left for our caller to check.
+pub(crate) fn get_matching_device_iterator(selector: &DeviceSelector) -> UsbResult<IoIterator> {
     unsafe {
         // Create a dictionary containing the object-type we want to match...
         let matcher = IOServiceMatching(kIOUSBDeviceClassName);
@@ -22,6 +40,30 @@ pub(crate) fn get_device_iterator() -> UsbResult<IoIterator> {
             panic!("could not allocate an IOKit object; OOM");
         }
 
+        // ... have IOKit skip any devices with the wrong IDs, so we never look at them ...
+        let properties = [
+            ("idVendor", selector.vendor_id),
+            ("idProduct", selector.product_id),
+        ];
+        for (property, value) in properties {
+            let Some(value) = value else {
+                continue;
+            };
+
+            let value = value as i32;
+            let number = CFNumberCreate(
+                kCFAllocatorDefault,
+                kCFNumberSInt32Type,
+                &value as *const i32 as *const c_void,
+            );
+            CFDictionarySetValue(
+                matcher,
+                cfstr!(property) as *const c_void,
+                number as *const c_void,
+            );
+            CFRelease(number as CFTypeRef);
+        }
+
         // ... and convert that dictionary into a match-iterator.
         let mut raw_device_iterator: io_iterator_t = 0;
         let rc =
@@ -38,7 +80,13 @@ pub(crate) fn get_device_iterator() -> UsbResult<IoIterator> {
 }
 
 /// Fetches the IOKit information for a given device without opening it.
-fn get_device_information(device: io_iterator_t) -> UsbResult<DeviceInformation> {
+///
+/// If `defer_strings` is set, we skip the device's strings; as each is a separate IORegistry
+/// lookup, these dominate enumeration time on busy systems.
+fn get_device_information(
+    device: io_iterator_t,
+    defer_strings: bool,
+) -> UsbResult<DeviceInformation> {
     // NOTE(ktemkin): While generically, we should only use Official (TM) macOS
     // documented properties, you can get a general idea of what properties are
     // available on each device by running `ioreg -p IOUSB -l`; `ioreg` being the
@@ -48,10 +96,12 @@ fn get_device_information(device: io_iterator_t) -> UsbResult<DeviceInformation>
     let vendor_id: u16 = get_iokit_numeric_device_property(device, "idVendor")?;
     let product_id: u16 = get_iokit_numeric_device_property(device, "idProduct")?;
 
-    // ... its string properties, where we can get them ...
-    let serial = get_iokit_string_device_property(device, "USB Serial Number")?;
-    let vendor = get_iokit_string_device_property(device, "USB Vendor Name")?;
-    let product = get_iokit_string_device_property(device, "USB Product Name")?;
+    // ... its string properties, where we can get them and they're wanted ...
+    let (serial, vendor, product) = if defer_strings {
+        (None, None, None)
+    } else {
+        get_device_strings(device)?
+    };
 
     // ... and its internal identifier, for easy opening.
     let location_id: UsbResult<u32> = get_iokit_numeric_device_property(device, "locationID");
@@ -68,47 +118,161 @@ fn get_device_information(device: io_iterator_t) -> UsbResult<DeviceInformation>
         return Err(Error::DeviceNotReal);
     }
 
+    let location_id = location_id.unwrap();
     Ok(DeviceInformation {
         vendor_id,
         product_id,
         serial,
         vendor,
         product,
-        backend_numeric_location: Some(location_id.unwrap() as u64),
+        backend_numeric_location: Some(location_id as u64),
+        port_path: Some(port_path_from_location(location_id)),
+        device_address: get_iokit_numeric_device_property(device, "USB Address").ok(),
+        speed: get_iokit_numeric_device_property(device, "Device Speed")
+            .ok()
+            .and_then(device_speed),
+        device_class: get_iokit_numeric_device_property(device, "bDeviceClass").ok(),
+        device_subclass: get_iokit_numeric_device_property(device, "bDeviceSubClass").ok(),
+        device_protocol: get_iokit_numeric_device_property(device, "bDeviceProtocol").ok(),
+        interface_classes: get_interface_classes(device).ok(),
+        device_descriptor: get_cached_device_descriptor(device).ok(),
+        strings_deferred: defer_strings,
         ..Default::default()
     })
 }
 
+/// Decodes a macOS location ID into a port path.
+///
+/// The location ID's top byte is the bus number; each of the following nibbles is a port
+/// number, from the root hub downwards, until the first zero nibble.
+fn port_path_from_location(location_id: u32) -> PortPath {
+    let bus = (location_id >> 24) as u8;
+    let ports = (0..6)
+        .map(|index| ((location_id >> (20 - index * 4)) & 0xf) as u8)
+        .take_while(|&port| port != 0)
+        .collect();
+
+    PortPath::new(bus, ports)
+}
+
+/// Decodes IOKit's kUSBDeviceSpeed* value.
+fn device_speed(speed: u8) -> Option<DeviceSpeed> {
+    match speed {
+        0 => Some(DeviceSpeed::Low),
+        1 => Some(DeviceSpeed::Full),
+        2 => Some(DeviceSpeed::High),
+        3 => Some(DeviceSpeed::Super),
+        4 | 5 => Some(DeviceSpeed::SuperPlus),
+        _ => None,
+    }
+}
+
+/// Fetches a device's serial, vendor, and product strings, where IOKit has them.
+pub(crate) fn get_device_strings(
+    device: io_iterator_t,
+) -> UsbResult<(Option<String>, Option<String>, Option<String>)> {
+    Ok((
+        get_iokit_string_device_property(device, "USB Serial Number")?,
+        get_iokit_string_device_property(device, "USB Vendor Name")?,
+        get_iokit_string_device_property(device, "USB Product Name")?,
+    ))
+}
+
+/// Rebuilds a device's device descriptor from the copy of its fields that IOKit keeps in
+/// the IORegistry; which lets us provide it without opening the device.
+fn get_cached_device_descriptor(device: io_iterator_t) -> UsbResult<DeviceDescriptor> {
+    Ok(DeviceDescriptor {
+        usb_version: get_iokit_numeric_device_property(device, "bcdUSB")?,
+        device_class: get_iokit_numeric_device_property(device, "bDeviceClass")?,
+        device_subclass: get_iokit_numeric_device_property(device, "bDeviceSubClass")?,
+        device_protocol: get_iokit_numeric_device_property(device, "bDeviceProtocol")?,
+        max_packet_size_ep0: get_iokit_numeric_device_property(device, "bMaxPacketSize0")?,
+        vendor_id: get_iokit_numeric_device_property(device, "idVendor")?,
+        product_id: get_iokit_numeric_device_property(device, "idProduct")?,
+        device_version: get_iokit_numeric_device_property(device, "bcdDevice")?,
+        manufacturer_string_index: get_iokit_numeric_device_property(device, "iManufacturer")?,
+        product_string_index: get_iokit_numeric_device_property(device, "iProduct")?,
+        serial_string_index: get_iokit_numeric_device_property(device, "iSerialNumber")?,
+        num_configurations: get_iokit_numeric_device_property(device, "bNumConfigurations")?,
+    })
+}
+
+/// Reads the class codes of each of a device's interfaces from the IORegistry; where macOS
+/// publishes a service for each interface of the active configuration, beneath the device's.
+fn get_interface_classes(device: io_iterator_t) -> UsbResult<Vec<InterfaceClass>> {
+    let mut classes = vec![];
+
+    for child in child_services(device)? {
+        // The device's other children are e.g. user clients, which have no class codes.
+        if !class_name(child.get())?.contains("Interface") {
+            continue;
+        }
+
+        classes.push(InterfaceClass {
+            class: get_iokit_numeric_device_property(child.get(), "bInterfaceClass")?,
+            subclass: get_iokit_numeric_device_property(child.get(), "bInterfaceSubClass")?,
+            protocol: get_iokit_numeric_device_property(child.get(), "bInterfaceProtocol")?,
+        });
+    }
+
+    Ok(classes)
+}
+
 /// Attempts to gather device information from all devices connected to the system.
 pub(crate) fn enumerate_devices() -> UsbResult<Vec<DeviceInformation>> {
-    let mut devices: Vec<DeviceInformation> = vec![];
+    DeviceInformationIter::new(&DeviceSelector::default(), &EnumerationOptions::default())?
+        .collect()
+}
 
-    unsafe {
-        // Fetch an IOKit iterator over all devices.
-        let device_iterator = get_device_iterator();
-        if device_iterator.as_ref().err() == Some(&Error::DeviceNotFound) {
-            return Ok(devices);
-        }
-        let device_iterator = device_iterator?;
-
-        let mut device;
-        while {
-            device = IOIteratorNext(device_iterator.get());
-            device != 0
-        } {
-            let device_info = get_device_information(device);
-            match device_info {
+/// Lazily gathers device information from each device connected to the system.
+pub(crate) struct DeviceInformationIter {
+    /// The IOKit iterator over matching devices; or None, if there are no devices to iterate over.
+    device_iterator: Option<IoIterator>,
+
+    /// True iff we should skip reading each device's strings.
+    defer_strings: bool,
+}
+
+impl DeviceInformationIter {
+    /// Starts a walk over the connected USB devices that could match the given selector.
+    pub(crate) fn new(selector: &DeviceSelector, options: &EnumerationOptions) -> UsbResult<Self> {
+        let device_iterator = match get_matching_device_iterator(selector) {
+            Err(Error::DeviceNotFound) => None,
+            other => Some(other?),
+        };
+
+        // If we're matching on serial, we'll need the strings regardless.
+        let defer_strings = options.defers_strings() && selector.serial.is_none();
+
+        Ok(Self {
+            device_iterator,
+            defer_strings,
+        })
+    }
+}
+
+impl Iterator for DeviceInformationIter {
+    type Item = UsbResult<DeviceInformation>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let device_iterator = self.device_iterator.as_ref()?;
+
+        loop {
+            let device = unsafe { IOIteratorNext(device_iterator.get()) };
+            if device == 0 {
+                return None;
+            }
+            let device = IoObject::new(device);
+
+            match get_device_information(device.get(), self.defer_strings) {
                 // If the device isn't real to the operating system, we won't consider it.
                 // (Root) hub devices, in particular, wind up enumerated to macOS, but aren't
                 // accessible in any other way. We'll skip them.
-                Err(Error::DeviceNotReal) => (),
+                Err(Error::DeviceNotReal) => continue,
 
-                // Otherwise, either capture the device, or propagate the error.
-                Ok(device_info) => devices.push(device_info),
-                Err(other) => return Err(other),
+                // Otherwise, either provide the device, or propagate the error.
+                other => return Some(other),
             }
         }
     }
-
-    Ok(devices)
 }
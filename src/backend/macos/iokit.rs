@@ -1,36 +1,52 @@
 //! Helpers for working with IOKit.
 
 use std::{
-    ffi::{c_void, CStr, CString},
+    ffi::{c_char, c_void, CStr, CString},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
     },
     time::Duration,
 };
 
 use core_foundation_sys::{
-    number::{kCFNumberSInt64Type, CFNumberGetValue, CFNumberRef},
+    base::{kCFAllocatorDefault, CFGetTypeID, CFRelease},
+    number::{
+        kCFNumberSInt64Type, CFBooleanGetTypeID, CFBooleanGetValue, CFBooleanRef, CFNumberGetValue,
+        CFNumberRef,
+    },
     runloop::{
-        kCFRunLoopDefaultMode, CFRunLoopAddSource, CFRunLoopGetCurrent, CFRunLoopRunInMode,
-        CFRunLoopSourceRef,
+        kCFRunLoopDefaultMode, kCFRunLoopRunFinished, CFRunLoopAddSource, CFRunLoopGetCurrent,
+        CFRunLoopRef, CFRunLoopRunInMode, CFRunLoopSourceRef, CFRunLoopStop, CFRunLoopWakeUp,
     },
     string::{kCFStringEncodingUTF8, CFStringGetCStringPtr, CFStringRef},
     uuid::CFUUIDBytes,
 };
 use io_kit_sys::{
-    kIORegistryIterateParents, kIORegistryIterateRecursively, keys::kIOServicePlane, ret::*,
-    types::io_iterator_t, IOAsyncCallback1, IOObjectRelease, IORegistryEntrySearchCFProperty,
-    CFSTR,
+    kIOMasterPortDefault, kIORegistryIterateParents, kIORegistryIterateRecursively,
+    keys::{
+        kIOFirstMatchNotification, kIOGeneralInterest, kIOServicePlane, kIOTerminatedNotification,
+    },
+    ret::*,
+    types::{io_iterator_t, io_object_t, io_service_t},
+    usb::lib::kIOUSBDeviceClassName,
+    IOAsyncCallback1, IOIteratorNext, IONotificationPortCreate, IONotificationPortDestroy,
+    IONotificationPortGetRunLoopSource, IONotificationPortRef, IOObjectGetClass, IOObjectRelease,
+    IORegistryEntryGetChildIterator, IORegistryEntrySearchCFProperty,
+    IOServiceAddInterestNotification, IOServiceAddMatchingNotification, IOServiceMatching, CFSTR,
 };
 use log::{error, warn};
 
 use super::iokit_c::{
-    self, kIOUSBFindInterfaceDontCare, kIOUSBNoAsyncPortErr, kIOUSBPipeStalled,
-    kIOUSBTransactionTimeout, kIOUSBUnknownPipeErr, AbsoluteTime, CFUUIDGetUUIDBytes,
-    IOCFPlugInInterface, IOUSBDevRequest, IOUSBDevRequestTO, IOUSBFindInterfaceRequest, UInt16,
+    self, kIOUSBBitstufErr, kIOUSBBufferOverrunErr, kIOUSBBufferUnderrunErr, kIOUSBCRCErr,
+    kIOUSBDataToggleErr, kIOUSBFindInterfaceDontCare, kIOUSBLinkErr, kIOUSBNoAsyncPortErr,
+    kIOUSBNotSent1Err, kIOUSBNotSent2Err, kIOUSBPIDCheckErr, kIOUSBPipeStalled,
+    kIOUSBTransactionTimeout, kIOUSBUnknownPipeErr, kIOUSBWrongPIDErr, AbsoluteTime,
+    CFUUIDGetUUIDBytes, IOCFPlugInInterface, IOUSBConfigurationDescriptorPtr, IOUSBDevRequest,
+    IOUSBDevRequestTO, IOUSBFindInterfaceRequest, IOUSBIsocFrame, IOUSBLowLatencyIsocFrame, UInt16,
     UInt32, UInt64, UInt8,
 };
+use crate::backend::DisconnectSignal;
 use crate::error::{self, Error, UsbResult};
 
 //
@@ -156,9 +172,11 @@ impl NotificationSource {
     }
 
     /// Creates a run-loop that will run call-backs for this notification-source.
+    ///
+    /// The run-loop runs until it's stopped via the provided handle.
     pub(crate) fn run_event_loop(
         notification_sources: Vec<NotificationSource>,
-        termination_flag: Arc<AtomicBool>,
+        handle: Arc<EventLoopHandle>,
     ) -> UsbResult<()> {
         unsafe {
             // Add each of our notification sources to our event loop...
@@ -167,19 +185,22 @@ impl NotificationSource {
                 CFRunLoopAddSource(runloop, source.source(), kCFRunLoopDefaultMode);
             }
 
-            // ... and run it.
-            loop {
-                // Let the runloop run for our specified "stop granularity", after which it'll
-                // pop back here to  check the termination condition.
-                const RUNLOOP_STOP_GRANULARITY: Duration = Duration::from_secs(1);
-                CFRunLoopRunInMode(
-                    kCFRunLoopDefaultMode,
-                    RUNLOOP_STOP_GRANULARITY.as_secs_f64(),
-                    false as u8,
-                );
+            // ... let our handle know where to find us, so it can stop us...
+            if !handle.attach(runloop) {
+                return Ok(());
+            }
 
-                // If our device is no longer around, we won't be getting any events -- so we can
-                if termination_flag.load(Ordering::Relaxed) {
+            // ... and run until we're stopped.
+            loop {
+                // We'll wait (effectively) forever; our handle will wake us when it's time to stop.
+                const RUNLOOP_FOREVER_SECONDS: f64 = 1.0e10;
+                let reason =
+                    CFRunLoopRunInMode(kCFRunLoopDefaultMode, RUNLOOP_FOREVER_SECONDS, false as u8);
+
+                // If our device is no longer around, we won't be getting any events; and if our
+                // sources have all gone away, we can't get any. Either way, we're done.
+                if handle.is_stopped() || reason == kCFRunLoopRunFinished {
+                    handle.detach();
                     return Ok(());
                 }
             }
@@ -187,8 +208,303 @@ impl NotificationSource {
     }
 }
 
+/// Handle that allows another thread to stop an event loop immediately, rather than waiting
+/// for the loop to wake up and notice it's no longer needed.
+#[derive(Debug, Default)]
+pub(crate) struct EventLoopHandle {
+    state: Mutex<EventLoopState>,
+}
+
+/// The shared state behind an [EventLoopHandle].
+#[derive(Debug, Default)]
+struct EventLoopState {
+    /// The runloop we're controlling, if it's currently running.
+    runloop: Option<CFRunLoopRef>,
+
+    /// True once we've been asked to stop.
+    stopped: bool,
+}
+
+impl EventLoopHandle {
+    /// Asks the event loop to stop; waking it up if it's currently waiting for events.
+    pub(crate) fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stopped = true;
+
+        // CFRunLoopStop is safe to call from other threads; and if the loop is between runs,
+        // CoreFoundation remembers the request, and applies it to the next run.
+        if let Some(runloop) = state.runloop {
+            unsafe {
+                CFRunLoopStop(runloop);
+                CFRunLoopWakeUp(runloop);
+            }
+        }
+    }
+
+    /// Returns true iff we've been asked to stop.
+    fn is_stopped(&self) -> bool {
+        self.state.lock().unwrap().stopped
+    }
+
+    /// Records the runloop we're controlling; returning false if we've already been stopped.
+    fn attach(&self, runloop: CFRunLoopRef) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.stopped {
+            return false;
+        }
+
+        state.runloop = Some(runloop);
+        true
+    }
+
+    /// Forgets our runloop, which is about to go away along with its thread.
+    fn detach(&self) {
+        self.state.lock().unwrap().runloop = None;
+    }
+}
+
+// The runloop reference is only ever used for CFRunLoopStop and CFRunLoopWakeUp,
+// both of which are thread-safe.
+unsafe impl Send for EventLoopHandle {}
+unsafe impl Sync for EventLoopHandle {}
+
 unsafe impl Send for NotificationSource {}
 
+// We only ever pass our source to CFRunLoop functions, which are safe to call from any thread.
+unsafe impl Sync for NotificationSource {}
+
+/// Message sent to interest notifications when their service is terminated; i.e. when the
+/// device has been unplugged. This is iokit_common_msg(0x010), from IOMessage.h.
+#[allow(non_upper_case_globals)]
+const kIOMessageServiceIsTerminated: u32 = 0xe000_0010;
+
+/// What our removal notifications update; shared between a device and its subscription.
+#[derive(Debug, Default)]
+pub(crate) struct RemovalState {
+    /// Set once IOKit tells us the device has been unplugged.
+    disconnected: AtomicBool,
+
+    /// The signal of the [crate::device::Device] using the device, if any; which we notify
+    /// along with setting our flag.
+    signal: Mutex<Option<Arc<DisconnectSignal>>>,
+}
+
+impl RemovalState {
+    /// Returns true once IOKit has told us the device is gone.
+    pub(crate) fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::SeqCst)
+    }
+
+    /// Replaces the signal we notify on removal; returning the old one.
+    pub(crate) fn replace_signal(
+        &self,
+        signal: Option<Arc<DisconnectSignal>>,
+    ) -> Option<Arc<DisconnectSignal>> {
+        std::mem::replace(&mut *self.signal.lock().unwrap(), signal)
+    }
+
+    /// Marks the device as gone, and notifies our signal, if we have one.
+    fn mark_removed(&self) {
+        self.disconnected.store(true, Ordering::SeqCst);
+
+        // Notifying runs user callbacks; so we'll do it without holding our lock.
+        let signal = self.signal.lock().unwrap().clone();
+        if let Some(signal) = signal {
+            signal.notify();
+        }
+    }
+}
+
+/// Subscription to a device's general-interest notifications, which lets us notice when
+/// it's been unplugged.
+#[derive(Debug)]
+pub(crate) struct RemovalNotification {
+    /// The port our notifications are delivered through.
+    port: IONotificationPortRef,
+
+    /// The notification object itself.
+    notification: io_object_t,
+
+    /// The state we update once the device goes away; leaked to IOKit as our refcon.
+    state: *const RemovalState,
+}
+
+impl RemovalNotification {
+    /// Subscribes to removal of the given service; updating the provided state once it's gone.
+    ///
+    /// Returns the subscription, and the notification source its events arrive on.
+    pub(crate) fn new(
+        service: io_service_t,
+        state: &Arc<RemovalState>,
+    ) -> UsbResult<(Self, NotificationSource)> {
+        unsafe {
+            let port = IONotificationPortCreate(kIOMasterPortDefault);
+            if port.is_null() {
+                return Err(Error::UnspecifiedOsError);
+            }
+
+            // Hand IOKit its own reference to our state...
+            let state = Arc::into_raw(Arc::clone(state));
+
+            // ... and ask it to tell us about anything that happens to the device.
+            let mut notification: io_object_t = 0;
+            let rc = IOServiceAddInterestNotification(
+                port,
+                service,
+                kIOGeneralInterest as *mut c_char,
+                handle_removal_notification,
+                state as *mut c_void,
+                &mut notification,
+            );
+            if rc != kIOReturnSuccess {
+                IONotificationPortDestroy(port);
+                drop(Arc::from_raw(state));
+                return Err(io_return_to_error(rc));
+            }
+
+            let source = NotificationSource::new(IONotificationPortGetRunLoopSource(port));
+            Ok((
+                Self {
+                    port,
+                    notification,
+                    state,
+                },
+                source,
+            ))
+        }
+    }
+}
+
+impl Drop for RemovalNotification {
+    fn drop(&mut self) {
+        unsafe {
+            IOObjectRelease(self.notification);
+            IONotificationPortDestroy(self.port);
+            drop(Arc::from_raw(self.state));
+        }
+    }
+}
+
+// Our port and notification are only ever released, which IOKit allows from any thread;
+// and our state is only touched through its atomic flag and lock.
+unsafe impl Send for RemovalNotification {}
+unsafe impl Sync for RemovalNotification {}
+
+/// Subscription to USB devices being connected or disconnected; which bumps a counter on
+/// each change, so a cached device list knows it's stale.
+#[derive(Debug)]
+pub(crate) struct DeviceChangeNotification {
+    /// The port our notifications are delivered through.
+    port: IONotificationPortRef,
+
+    /// The iterators that deliver our matching (connect) and terminated (disconnect) events.
+    iterators: Vec<IoIterator>,
+
+    /// The counter we bump on each change; leaked to IOKit as our refcon.
+    generation: *const AtomicU64,
+}
+
+impl DeviceChangeNotification {
+    /// Subscribes to USB devices arriving and leaving; bumping the provided counter each time.
+    ///
+    /// Returns the subscription, and the notification source its events arrive on.
+    pub(crate) fn new(generation: &Arc<AtomicU64>) -> UsbResult<(Self, NotificationSource)> {
+        unsafe {
+            let port = IONotificationPortCreate(kIOMasterPortDefault);
+            if port.is_null() {
+                return Err(Error::UnspecifiedOsError);
+            }
+
+            // Hand IOKit its own reference to our counter. From here on, dropping the
+            // subscription cleans up after us; even if we fail part-way through.
+            let mut subscription = Self {
+                port,
+                iterators: vec![],
+                generation: Arc::into_raw(Arc::clone(generation)),
+            };
+
+            for notification_type in [kIOFirstMatchNotification, kIOTerminatedNotification] {
+                // Each registration consumes its own matching dictionary...
+                let matcher = IOServiceMatching(kIOUSBDeviceClassName);
+                if matcher.is_null() {
+                    return Err(Error::UnspecifiedOsError);
+                }
+
+                let mut iterator: io_iterator_t = 0;
+                let rc = IOServiceAddMatchingNotification(
+                    port,
+                    notification_type as *mut c_char,
+                    matcher,
+                    handle_device_change,
+                    subscription.generation as *mut c_void,
+                    &mut iterator,
+                );
+                if rc != kIOReturnSuccess {
+                    return Err(io_return_to_error(rc));
+                }
+
+                // ... and isn't armed until we've drained the devices that already match.
+                drain_iterator(iterator);
+                subscription.iterators.push(IoIterator::new(iterator));
+            }
+
+            let source = NotificationSource::new(IONotificationPortGetRunLoopSource(port));
+            Ok((subscription, source))
+        }
+    }
+}
+
+impl Drop for DeviceChangeNotification {
+    fn drop(&mut self) {
+        self.iterators.clear();
+
+        unsafe {
+            IONotificationPortDestroy(self.port);
+            drop(Arc::from_raw(self.generation));
+        }
+    }
+}
+
+// As with our removal notifications, these are only touched on creation and destruction.
+unsafe impl Send for DeviceChangeNotification {}
+unsafe impl Sync for DeviceChangeNotification {}
+
+/// Callback for our device change notifications; which notes that the device list changed.
+unsafe extern "C" fn handle_device_change(
+    refcon: *mut c_void, // Actually an AtomicU64.
+    iterator: io_iterator_t,
+) {
+    // We don't care which devices changed; but we do need to drain the iterator to re-arm it.
+    drain_iterator(iterator);
+
+    let generation = &*(refcon as *const AtomicU64);
+    generation.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Releases every object left in an IOKit iterator.
+unsafe fn drain_iterator(iterator: io_iterator_t) {
+    loop {
+        let object = IOIteratorNext(iterator);
+        if object == 0 {
+            break;
+        }
+        IOObjectRelease(object);
+    }
+}
+
+/// Callback for our removal notifications; which marks the device as disconnected.
+unsafe extern "C" fn handle_removal_notification(
+    refcon: *mut c_void, // Actually a RemovalState.
+    _service: io_service_t,
+    message_type: u32,
+    _message_argument: *mut c_void,
+) {
+    if message_type == kIOMessageServiceIsTerminated {
+        let state = &*(refcon as *const RemovalState);
+        state.mark_removed();
+    }
+}
+
 // Wrapper around a **UsbDevice that helps us poke at its innards.
 #[derive(Debug)]
 pub(crate) struct OsDevice {
@@ -212,6 +528,11 @@ impl OsDevice {
         }
     }
 
+    /// Returns the underlying IOKit device interface; which remains ours.
+    pub(crate) fn raw(&self) -> *mut *mut UsbDevice {
+        self.device
+    }
+
     /// Opens the device, allowing the other functions on this type to be used.
     pub fn open(&mut self) -> UsbResult<()> {
         // If we're already open, we're done!
@@ -226,7 +547,56 @@ impl OsDevice {
         Ok(())
     }
 
-    /// Applies a configuration to the device.
+    /// Opens the device for exclusive access; taking it from anyone else who has it open.
+    pub fn open_seize(&mut self) -> UsbResult<()> {
+        if self.is_open {
+            return Ok(());
+        }
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(self.device, USBDeviceOpenSeize))?;
+
+        self.is_open = true;
+        Ok(())
+    }
+
+    /// Returns the number of configurations the device has.
+    pub fn num_configurations(&self) -> UsbResult<u8> {
+        let mut count: UInt8 = 0;
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.device,
+            GetNumberOfConfigurations,
+            &mut count
+        ))?;
+
+        Ok(count)
+    }
+
+    /// Returns a copy of IOKit's cached configuration descriptor with the given index;
+    /// including all of its subordinate descriptors.
+    pub fn configuration_descriptor(&self, index: u8) -> UsbResult<Vec<u8>> {
+        let mut descriptor: IOUSBConfigurationDescriptorPtr = std::ptr::null_mut();
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.device,
+            GetConfigurationDescriptorPtr,
+            index,
+            &mut descriptor
+        ))?;
+
+        if descriptor.is_null() {
+            return Err(Error::UnspecifiedOsError);
+        }
+
+        // The descriptor is kept in bus order; and it's followed by the rest of the configuration.
+        unsafe {
+            let length = u16::from_le((*descriptor).wTotalLength) as usize;
+            Ok(std::slice::from_raw_parts(descriptor as *const u8, length).to_vec())
+        }
+    }
+
+    /// Returns the bConfigurationValue of the device's active configuration; or 0 if the device
+    /// is unconfigured.
     pub fn get_configuration(&self) -> UsbResult<u8> {
         let mut configuration: UInt8 = 0;
 
@@ -239,12 +609,12 @@ impl OsDevice {
         Ok(configuration)
     }
 
-    /// Applies a configuration to the device.
-    pub fn set_configuration(&self, index: u8) -> UsbResult<()> {
+    /// Applies a configuration to the device, by its bConfigurationValue.
+    pub fn set_configuration(&self, configuration_value: u8) -> UsbResult<()> {
         UsbResult::from_io_return(call_unsafe_iokit_function!(
             self.device,
             SetConfiguration,
-            index
+            configuration_value
         ))
     }
 
@@ -270,6 +640,16 @@ impl OsDevice {
         UsbResult::from_io_return(call_unsafe_iokit_function!(self.device, ResetDevice))
     }
 
+    /// Asks macOS to re-enumerate the device; which tears down this handle, and creates
+    /// a new device in its place. Options are kUSBReEnumerate*Mask values.
+    pub fn reenumerate(&self, options: u32) -> UsbResult<()> {
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.device,
+            USBDeviceReEnumerate,
+            options
+        ))
+    }
+
     /// Performs a control request on the device, without wrapping the unsafe behavior of
     /// the contained IOUSbDevRequest. See also [device_request_with_timeout].
     pub fn device_request(&self, request: &mut IOUSBDevRequest) -> UsbResult<()> {
@@ -325,7 +705,7 @@ impl OsDevice {
     }
 
     /// Aborts any active transfer on EP0.
-    pub fn abort_ep0(&mut self) -> UsbResult<()> {
+    pub fn abort_ep0(&self) -> UsbResult<()> {
         UsbResult::from_io_return(call_unsafe_iokit_function!(
             self.device,
             USBDeviceAbortPipeZero
@@ -334,7 +714,7 @@ impl OsDevice {
 
     /// Places the device into power-save mode, or takes it out.
     /// A value of true places the device into suspend.
-    pub fn suspend(&mut self, suspend: bool) -> UsbResult<()> {
+    pub fn suspend(&self, suspend: bool) -> UsbResult<()> {
         UsbResult::from_io_return(call_unsafe_iokit_function!(
             self.device,
             USBDeviceSuspend,
@@ -342,8 +722,61 @@ impl OsDevice {
         ))
     }
 
+    /// Returns the power available to the device from its port, in milliamps.
+    pub fn bus_power_available(&self) -> UsbResult<u32> {
+        let mut power: UInt32 = 0;
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.device,
+            GetDeviceBusPowerAvailable,
+            &mut power
+        ))?;
+
+        // IOKit reports this in units of 2mA, like bMaxPower.
+        Ok(power * 2)
+    }
+
+    /// Asks for power beyond the USB specification's limits, in milliamps.
+    /// Returns the amount of power actually granted.
+    pub fn request_extra_power(&self, power_type: u32, requested: u32) -> UsbResult<u32> {
+        let mut granted: UInt32 = 0;
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.device,
+            RequestExtraPower,
+            power_type,
+            requested,
+            &mut granted
+        ))?;
+
+        Ok(granted)
+    }
+
+    /// Returns extra power previously granted by [request_extra_power], in milliamps.
+    pub fn return_extra_power(&self, power_type: u32, returned: u32) -> UsbResult<()> {
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.device,
+            ReturnExtraPower,
+            power_type,
+            returned
+        ))
+    }
+
+    /// Returns the bandwidth still available to the device, in bytes per (micro)frame.
+    pub fn bandwidth_available(&self) -> UsbResult<u32> {
+        let mut bandwidth: UInt32 = 0;
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.device,
+            GetBandwidthAvailableForDevice,
+            &mut bandwidth
+        ))?;
+
+        Ok(bandwidth)
+    }
+
     /// Returns an IOKit iterator that can be used to iterate over all interfaces on this device.
-    pub fn create_interface_iterator(&mut self) -> UsbResult<IoObject> {
+    pub fn create_interface_iterator(&self) -> UsbResult<IoObject> {
         let mut iterator: io_iterator_t = 0;
 
         // For our purposes, we don't want macOS to filter the interface list
@@ -446,6 +879,10 @@ pub(crate) struct OsInterface {
 
     /// True iff the interface is currently open.
     is_open: bool,
+
+    /// Shared with each buffer from [OsInterface::create_transfer_buffer]; which IOKit would
+    /// free if we closed the interface. While any are alive, we leave the interface open.
+    buffer_leases: Arc<()>,
 }
 
 // We really only have a pointer to something that's already Send,
@@ -461,6 +898,7 @@ impl OsInterface {
             interface_number,
             deny_all: false,
             is_open: false,
+            buffer_leases: Arc::default(),
         }
     }
 
@@ -470,6 +908,7 @@ impl OsInterface {
             interface_number,
             deny_all: true,
             is_open: false,
+            buffer_leases: Arc::default(),
         }
     }
 
@@ -477,6 +916,11 @@ impl OsInterface {
         Ok(self.interface_number)
     }
 
+    /// Returns the underlying IOKit interface; which remains ours. Null for placeholders.
+    pub(crate) fn raw(&self) -> *mut *mut UsbInterface {
+        self.interface
+    }
+
     /// Opens the interface, allowing the other functions on this type to be used.
     pub fn open(&mut self) -> UsbResult<()> {
         if self.deny_all {
@@ -491,7 +935,15 @@ impl OsInterface {
         UsbResult::from_io_return(call_unsafe_iokit_function!(
             self.interface,
             USBInterfaceOpen
-        ))
+        ))?;
+
+        self.is_open = true;
+        Ok(())
+    }
+
+    /// Returns true iff the interface is currently open.
+    pub fn is_open(&self) -> bool {
+        self.is_open
     }
 
     /// Returns the number of endpoints associated with the interface.
@@ -513,7 +965,7 @@ impl OsInterface {
         Ok(count as u8)
     }
 
-    pub fn endpoint_properties(&mut self, pipe_ref: u8) -> UsbResult<EndpointMetadata> {
+    pub fn endpoint_properties(&self, pipe_ref: u8) -> UsbResult<EndpointMetadata> {
         if self.deny_all {
             return Err(Error::PermissionDenied);
         }
@@ -586,15 +1038,21 @@ impl OsInterface {
     }
 
     /// Performs a write, with an associated timeout.
-    pub fn write_with_timeout(&self, pipe_ref: u8, data: &[u8], timeout: u32) -> UsbResult<()> {
+    pub fn write_with_timeout(
+        &self,
+        pipe_ref: u8,
+        data: &[u8],
+        no_data_timeout: u32,
+        completion_timeout: u32,
+    ) -> UsbResult<()> {
         UsbResult::from_io_return(call_unsafe_iokit_function!(
             self.interface,
             WritePipeTO,
             pipe_ref,
             data.as_ptr() as *mut c_void,
             data.len() as u32,
-            timeout,
-            timeout
+            no_data_timeout,
+            completion_timeout
         ))
     }
 
@@ -656,12 +1114,145 @@ impl OsInterface {
         ))
     }
 
+    /// Performs an async isochronous read; one packet per entry in the frame list, which
+    /// IOKit fills in as the packets complete. Both must outlive the transfer.
+    pub fn read_isochronous_nonblocking(
+        &self,
+        pipe_ref: u8,
+        data: *mut c_void,
+        start_frame: u64,
+        frames: &mut [IOUSBIsocFrame],
+        callback: IOAsyncCallback1,
+        callback_arg: *mut c_void,
+    ) -> UsbResult<()> {
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.interface,
+            ReadIsochPipeAsync,
+            pipe_ref,
+            data,
+            start_frame,
+            frames.len() as u32,
+            frames.as_mut_ptr(),
+            callback,
+            callback_arg
+        ))
+    }
+
+    /// Performs an async isochronous write; see [read_isochronous_nonblocking].
+    pub fn write_isochronous_nonblocking(
+        &self,
+        pipe_ref: u8,
+        data: *mut c_void,
+        start_frame: u64,
+        frames: &mut [IOUSBIsocFrame],
+        callback: IOAsyncCallback1,
+        callback_arg: *mut c_void,
+    ) -> UsbResult<()> {
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.interface,
+            WriteIsochPipeAsync,
+            pipe_ref,
+            data,
+            start_frame,
+            frames.len() as u32,
+            frames.as_mut_ptr(),
+            callback,
+            callback_arg
+        ))
+    }
+
+    /// Creates a buffer that IOKit keeps mapped for this interface, for low-latency
+    /// isochronous transfers. `kind` is one of the kUSBLowLatency*Buffer constants.
+    pub fn create_low_latency_buffer(&self, size: usize, kind: u32) -> UsbResult<LowLatencyBuffer> {
+        let mut buffer: *mut c_void = std::ptr::null_mut();
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.interface,
+            LowLatencyCreateBuffer,
+            &mut buffer,
+            size as _,
+            kind
+        ))?;
+
+        // The buffer can only be destroyed through its interface; so hold a reference to it.
+        call_unsafe_iokit_function!(self.interface, AddRef);
+        Ok(LowLatencyBuffer {
+            interface: self.interface,
+            buffer,
+            size,
+            _lease: None,
+        })
+    }
+
+    /// Creates a low-latency buffer that can outlive any one transfer; e.g. for a buffer pool.
+    /// The interface stays open for as long as the buffer is alive.
+    pub fn create_transfer_buffer(&self, size: usize, kind: u32) -> UsbResult<LowLatencyBuffer> {
+        if self.deny_all {
+            return Err(Error::PermissionDenied);
+        }
+
+        let mut buffer = self.create_low_latency_buffer(size, kind)?;
+        buffer._lease = Some(Arc::clone(&self.buffer_leases));
+        Ok(buffer)
+    }
+
+    /// Performs an async low-latency isochronous read into a buffer from
+    /// [create_low_latency_buffer]; with a frame list that lives in a frame-list buffer.
+    /// IOKit timestamps each frame as it completes.
+    pub fn read_isochronous_low_latency(
+        &self,
+        pipe_ref: u8,
+        data: *mut c_void,
+        start_frame: u64,
+        frames: &mut [IOUSBLowLatencyIsocFrame],
+        callback: IOAsyncCallback1,
+        callback_arg: *mut c_void,
+    ) -> UsbResult<()> {
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.interface,
+            LowLatencyReadIsochPipeAsync,
+            pipe_ref,
+            data,
+            start_frame,
+            frames.len() as u32,
+            LOW_LATENCY_UPDATE_FREQUENCY,
+            frames.as_mut_ptr(),
+            callback,
+            callback_arg
+        ))
+    }
+
+    /// Performs an async low-latency isochronous write; see [read_isochronous_low_latency].
+    pub fn write_isochronous_low_latency(
+        &self,
+        pipe_ref: u8,
+        data: *mut c_void,
+        start_frame: u64,
+        frames: &mut [IOUSBLowLatencyIsocFrame],
+        callback: IOAsyncCallback1,
+        callback_arg: *mut c_void,
+    ) -> UsbResult<()> {
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.interface,
+            LowLatencyWriteIsochPipeAsync,
+            pipe_ref,
+            data,
+            start_frame,
+            frames.len() as u32,
+            LOW_LATENCY_UPDATE_FREQUENCY,
+            frames.as_mut_ptr(),
+            callback,
+            callback_arg
+        ))
+    }
+
     /// Performs a write, with an associated timeout.
     pub fn read_with_timeout(
         &self,
         pipe_ref: u8,
         buffer: &mut [u8],
-        timeout: u32,
+        no_data_timeout: u32,
+        completion_timeout: u32,
     ) -> UsbResult<usize> {
         let mut size: UInt32 = buffer.len() as u32;
 
@@ -671,8 +1262,8 @@ impl OsInterface {
             pipe_ref,
             buffer.as_mut_ptr() as *mut c_void,
             &mut size,
-            timeout,
-            timeout
+            no_data_timeout,
+            completion_timeout
         ))?;
 
         Ok(size as usize)
@@ -701,7 +1292,40 @@ impl OsInterface {
         ))
     }
 
-    /// Clears the stall condition on the provided PipeRef.
+    /// Aborts any active transfers on the provided PipeRef.
+    pub fn abort_pipe(&self, pipe_ref: u8) -> UsbResult<()> {
+        if self.deny_all {
+            return Err(Error::PermissionDenied);
+        }
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.interface,
+            AbortPipe,
+            pipe_ref
+        ))
+    }
+
+    /// Clears the stall condition on the provided PipeRef; both in the host's pipe state, and
+    /// on the device, via CLEAR_FEATURE(ENDPOINT_HALT).
+    pub fn set_pipe_policy(
+        &self,
+        pipe_ref: u8,
+        max_packet_size: u16,
+        interval: u8,
+    ) -> UsbResult<()> {
+        if self.deny_all {
+            return Err(Error::PermissionDenied);
+        }
+
+        UsbResult::from_io_return(call_unsafe_iokit_function!(
+            self.interface,
+            SetPipePolicy,
+            pipe_ref,
+            max_packet_size,
+            interval
+        ))
+    }
+
     pub fn clear_stall(&self, pipe_ref: u8) -> UsbResult<()> {
         if self.deny_all {
             return Err(Error::PermissionDenied);
@@ -709,7 +1333,7 @@ impl OsInterface {
 
         UsbResult::from_io_return(call_unsafe_iokit_function!(
             self.interface,
-            ClearPipeStall,
+            ClearPipeStallBothEnds,
             pipe_ref
         ))
     }
@@ -757,6 +1381,12 @@ impl OsInterface {
             return;
         }
 
+        // Closing the interface would free any transfer buffers still out there; so they keep
+        // it open. The last of them to go releases the interface for good.
+        if Arc::strong_count(&self.buffer_leases) > 1 {
+            return;
+        }
+
         if self.deny_all {
             panic!("internal consistency: somehow, we have an open deny_all interface? what have we _done_");
         }
@@ -777,6 +1407,87 @@ impl Drop for OsInterface {
     }
 }
 
+/// How often, in milliseconds, IOKit updates a low-latency frame list while its transfer is
+/// in flight. We only look at the list once the transfer completes; so zero, for "at the end".
+const LOW_LATENCY_UPDATE_FREQUENCY: UInt32 = 0;
+
+/// A buffer IOKit keeps mapped for an interface; see [OsInterface::create_low_latency_buffer].
+/// Destroyed when dropped.
+pub(crate) struct LowLatencyBuffer {
+    /// The interface the buffer belongs to; which we hold a reference to.
+    interface: *mut *mut UsbInterface,
+
+    /// The buffer itself.
+    buffer: *mut c_void,
+
+    /// The buffer's size, in bytes.
+    size: usize,
+
+    /// For buffers from [OsInterface::create_transfer_buffer], what keeps the interface open.
+    _lease: Option<Arc<()>>,
+}
+
+// The buffer is plain memory, and the interface is already Send and Sync; see OsInterface.
+// Its contents are only ever touched through &self or &mut self.
+unsafe impl Send for LowLatencyBuffer {}
+unsafe impl Sync for LowLatencyBuffer {}
+
+impl LowLatencyBuffer {
+    /// Returns a raw pointer to the buffer, for handing to IOKit.
+    pub fn as_mut_ptr(&mut self) -> *mut c_void {
+        self.buffer
+    }
+
+    /// Views the buffer as a slice of as many `T`s as fit in it.
+    ///
+    /// # Safety
+    /// Any bit pattern must be a valid `T`; and IOKit must not be writing to the buffer.
+    pub unsafe fn as_mut_slice<T>(&mut self) -> &mut [T] {
+        std::slice::from_raw_parts_mut(self.buffer as *mut T, self.size / std::mem::size_of::<T>())
+    }
+}
+
+impl AsRef<[u8]> for LowLatencyBuffer {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.buffer as *const u8, self.size) }
+    }
+}
+
+impl AsMut<[u8]> for LowLatencyBuffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.buffer as *mut u8, self.size) }
+    }
+}
+
+impl Drop for LowLatencyBuffer {
+    fn drop(&mut self) {
+        // If the interface has been closed, IOKit has already freed the buffer; and will
+        // just tell us it's never heard of it. Either way, we're done with the interface.
+        _ = call_unsafe_iokit_function!(self.interface, LowLatencyDestroyBuffer, self.buffer);
+        call_unsafe_iokit_function!(self.interface, Release);
+    }
+}
+
+/// Converts a mach AbsoluteTime, as IOKit timestamps things, into time since boot.
+pub(crate) fn absolute_time_to_duration(time: AbsoluteTime) -> Duration {
+    static TIMEBASE: OnceLock<iokit_c::mach_timebase_info_data_t> = OnceLock::new();
+
+    // AbsoluteTime counts ticks of a clock whose rate the kernel tells us, as a fraction
+    // of a nanosecond.
+    let timebase = TIMEBASE.get_or_init(|| {
+        let mut timebase = iokit_c::mach_timebase_info_data_t::default();
+        unsafe { iokit_c::mach_timebase_info(&mut timebase) };
+        timebase
+    });
+    if timebase.denom == 0 {
+        return Duration::ZERO;
+    }
+
+    let ticks = (time.hi as u128) << 32 | (time.lo as u128);
+    let nanoseconds = ticks * timebase.numer as u128 / timebase.denom as u128;
+    Duration::from_nanos(nanoseconds as u64)
+}
+
 //
 // Helpers for working with CoreFoundation / IOKit types.
 //
@@ -796,16 +1507,25 @@ fn io_return_to_error(rc: IOReturn) -> error::Error {
     match rc {
         // Substitute IOKit messages for our equivalent...
         kIOReturnNotOpen => Error::DeviceNotOpen,
-        kIOReturnNoDevice => Error::DeviceNotFound,
+        kIOReturnNoDevice => Error::Disconnected,
         kIOReturnExclusiveAccess => Error::DeviceReserved,
         kIOReturnBadArgument => Error::InvalidArgument,
         kIOReturnAborted => Error::Aborted,
         kIOReturnOverrun => Error::Overrun,
         kIOReturnNoResources => Error::PermissionDenied,
+        kIOReturnNotPrivileged => Error::PermissionDenied,
         kIOUSBNoAsyncPortErr => Error::DeviceNotOpen,
         kIOUSBUnknownPipeErr => Error::InvalidEndpoint,
         kIOUSBPipeStalled => Error::Stalled,
         kIOUSBTransactionTimeout => Error::TimedOut,
+        kIOReturnNotResponding => Error::NotResponding,
+
+        // ... and sort the controller's link-level errors into what went wrong on the bus.
+        kIOReturnUnderrun | kIOUSBBufferUnderrunErr | kIOUSBBufferOverrunErr => Error::Underrun,
+        kIOUSBCRCErr | kIOUSBBitstufErr | kIOUSBDataToggleErr | kIOUSBPIDCheckErr
+        | kIOUSBWrongPIDErr | kIOUSBLinkErr | kIOUSBNotSent1Err | kIOUSBNotSent2Err => {
+            Error::ProtocolError
+        }
         _ => Error::OsError(rc as i64),
     }
 }
@@ -951,6 +1671,92 @@ pub(crate) fn to_iokit_timeout(timeout: Duration) -> u32 {
     timeout_ms
 }
 
+/// Returns the IOKit class name of the given object; e.g. "IOUSBHostInterface".
+pub(crate) fn class_name(object: io_object_t) -> UsbResult<String> {
+    let mut class_name: [c_char; 128] = [0; 128];
+
+    unsafe {
+        UsbResult::from_io_return(IOObjectGetClass(object, class_name.as_mut_ptr()))?;
+        Ok(CStr::from_ptr(class_name.as_ptr())
+            .to_string_lossy()
+            .into_owned())
+    }
+}
+
+/// Returns the services directly beneath the given one, in the IOService plane.
+pub(crate) fn child_services(service: io_service_t) -> UsbResult<Vec<IoObject>> {
+    let mut children = vec![];
+
+    unsafe {
+        let mut raw_iterator: io_iterator_t = 0;
+        UsbResult::from_io_return(IORegistryEntryGetChildIterator(
+            service,
+            kIOServicePlane as *mut c_char,
+            &mut raw_iterator,
+        ))?;
+        let iterator = IoIterator::new(raw_iterator);
+
+        loop {
+            let child = IoObject::new(IOIteratorNext(iterator.get()));
+            if child.is_invalid() {
+                return Ok(children);
+            }
+
+            children.push(child);
+        }
+    }
+}
+
+/// Returns the class name of the driver attached to the given service, if any.
+///
+/// User clients -- which is how we, and other userland programs, attach -- don't count.
+pub(crate) fn attached_driver_name(service: io_service_t) -> UsbResult<Option<String>> {
+    for child in child_services(service)? {
+        let class_name = class_name(child.get())?;
+        if !class_name.contains("UserClient") {
+            return Ok(Some(class_name));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The entitlement that allows a (non-root) program to capture devices from their kernel drivers.
+pub(crate) const DEVICE_ACCESS_ENTITLEMENT: &str = "com.apple.vm.device-access";
+
+/// Returns true iff macOS will let this process capture devices from their kernel drivers.
+///
+/// That's allowed for root, and for binaries signed with the [DEVICE_ACCESS_ENTITLEMENT].
+pub(crate) fn process_can_capture_devices() -> bool {
+    unsafe {
+        if iokit_c::geteuid() == 0 {
+            return true;
+        }
+
+        let task = iokit_c::SecTaskCreateFromSelf(kCFAllocatorDefault);
+        if task.is_null() {
+            return false;
+        }
+
+        let value = iokit_c::SecTaskCopyValueForEntitlement(
+            task,
+            cfstr!(DEVICE_ACCESS_ENTITLEMENT),
+            std::ptr::null_mut(),
+        );
+        CFRelease(task);
+
+        if value.is_null() {
+            return false;
+        }
+
+        let entitled =
+            CFGetTypeID(value) == CFBooleanGetTypeID() && CFBooleanGetValue(value as CFBooleanRef);
+        CFRelease(value);
+
+        entitled
+    }
+}
+
 /// Helper function that moves an object out of Rust's memory model, for use by IOKit.
 pub(crate) fn leak_to_iokit<T>(object: T) -> *mut c_void {
     Box::into_raw(Box::new(object)) as *mut c_void
@@ -10,10 +10,12 @@
 use std::ffi::{c_int, c_void};
 
 use core_foundation_sys::{
-    base::{kCFAllocatorSystemDefault, mach_port_t, SInt32},
+    base::{kCFAllocatorSystemDefault, mach_port_t, CFTypeRef, SInt32},
     dictionary::CFDictionaryRef,
+    error::CFErrorRef,
     mach_port::CFAllocatorRef,
     runloop::CFRunLoopSourceRef,
+    string::CFStringRef,
     uuid::{CFUUIDBytes, CFUUIDRef},
 };
 use io_kit_sys::{
@@ -56,8 +58,33 @@ pub(crate) const kIOUSBTooManyTransactionsPending: c_int = SYS_IOKIT | SUB_IOKIT
 pub(crate) const kIOUSBTransactionReturned: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x50;
 pub(crate) const kIOUSBTransactionTimeout: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x51;
 
+// Link-level errors, reported by the host controller for an individual transaction.
+pub(crate) const kIOUSBLinkErr: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x10; // 0xe0004010
+pub(crate) const kIOUSBNotSent2Err: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x0f; // 0xe000400f  Transaction not sent
+pub(crate) const kIOUSBNotSent1Err: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x0e; // 0xe000400e  Transaction not sent
+pub(crate) const kIOUSBBufferUnderrunErr: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x0d; // 0xe000400d  Buffer Underrun (Host hardware failure on data out, PCI busy?)
+pub(crate) const kIOUSBBufferOverrunErr: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x0c; // 0xe000400c  Buffer Overrun (Host hardware failure on data out, PCI busy?)
+pub(crate) const kIOUSBWrongPIDErr: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x07; // 0xe0004007  Pipe stall, Bad or wrong PID
+pub(crate) const kIOUSBPIDCheckErr: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x06; // 0xe0004006  Pipe stall, PID CRC error
+pub(crate) const kIOUSBDataToggleErr: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x03; // 0xe0004003  Pipe stall, Bad data toggle
+pub(crate) const kIOUSBBitstufErr: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x02; // 0xe0004002  Pipe stall, bitstuffing
+pub(crate) const kIOUSBCRCErr: c_int = SYS_IOKIT | SUB_IOKIT_USB | 0x01; // 0xe0004001  Pipe stall, bad CRC
+
 pub(crate) const kIOUSBFindInterfaceDontCare: UInt16 = 0xFFFF;
 
+// Options for USBDeviceReEnumerate.
+pub(crate) const kUSBReEnumerateCaptureDeviceMask: UInt32 = 1 << 30;
+pub(crate) const kUSBReEnumerateReleaseDeviceMask: UInt32 = 1 << 29;
+
+// Types of extra power, for RequestExtraPower and friends.
+pub(crate) const kUSBPowerDuringSleep: UInt32 = 0;
+pub(crate) const kUSBPowerDuringWake: UInt32 = 1;
+
+// Kinds of buffer, for LowLatencyCreateBuffer.
+pub(crate) const kUSBLowLatencyWriteBuffer: UInt32 = 0;
+pub(crate) const kUSBLowLatencyReadBuffer: UInt32 = 1;
+pub(crate) const kUSBLowLatencyFrameListBuffer: UInt32 = 2;
+
 //
 
 //
@@ -92,6 +119,13 @@ pub struct UnsignedWide {
     pub hi: UInt32,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct mach_timebase_info_data_t {
+    pub numer: UInt32,
+    pub denom: UInt32,
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct IOUSBDevRequest {
@@ -192,6 +226,10 @@ pub struct IOCFPlugInInterfaceStruct {
 pub type IOCFPlugInInterface = IOCFPlugInInterfaceStruct;
 
 extern "C" {
+    pub fn geteuid() -> u32;
+
+    pub fn mach_timebase_info(info: *mut mach_timebase_info_data_t) -> kern_return_t;
+
     pub fn CFUUIDGetUUIDBytes(uuid: CFUUIDRef) -> CFUUIDBytes;
 
     pub fn IOCreatePlugInInterfaceForService(
@@ -1005,3 +1043,19 @@ pub struct IOUSBInterfaceStruct500 {
 // device in Mutex somewhere up from here.)
 unsafe impl Send for IOUSBInterfaceStruct500 {}
 unsafe impl Sync for IOUSBInterfaceStruct500 {}
+
+//
+// Security framework functions, used to check our entitlements.
+//
+pub(crate) type SecTaskRef = *const c_void;
+
+#[link(name = "Security", kind = "framework")]
+extern "C" {
+    pub fn SecTaskCreateFromSelf(allocator: CFAllocatorRef) -> SecTaskRef;
+
+    pub fn SecTaskCopyValueForEntitlement(
+        task: SecTaskRef,
+        entitlement: CFStringRef,
+        error: *mut CFErrorRef,
+    ) -> CFTypeRef;
+}
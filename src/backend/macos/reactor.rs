@@ -0,0 +1,160 @@
+//! Shared event reactor, which runs the IOKit event callbacks for every open device.
+//!
+//! Rather than giving each device its own event thread, every device opened by a backend
+//! registers its notification sources with a single runloop, running on a single thread. The
+//! reactor starts when the first device is opened, and stops once the last one is dropped.
+
+use std::{
+    ffi::c_void,
+    sync::{mpsc, Arc},
+    thread::ThreadId,
+};
+
+use core_foundation_sys::{
+    base::CFRelease,
+    runloop::{
+        kCFRunLoopDefaultMode, CFRunLoopAddSource, CFRunLoopGetCurrent, CFRunLoopRef,
+        CFRunLoopRemoveSource, CFRunLoopSourceContext, CFRunLoopSourceCreate, CFRunLoopSourceRef,
+        CFRunLoopWakeUp,
+    },
+};
+
+use super::iokit::{EventLoopHandle, NotificationSource};
+use crate::{Error, UsbResult};
+
+/// Wrapper that lets us hand the reactor's runloop between threads.
+///
+/// We only ever use it to add and remove sources, and to wake the loop; all of which
+/// CoreFoundation allows from any thread.
+#[derive(Debug, Clone, Copy)]
+struct RunLoop(CFRunLoopRef);
+
+unsafe impl Send for RunLoop {}
+unsafe impl Sync for RunLoop {}
+
+/// A single event thread, shared by every device opened from the same backend.
+#[derive(Debug)]
+pub(crate) struct EventReactor {
+    /// The runloop our thread is running.
+    runloop: RunLoop,
+
+    /// Handle used to stop our thread, once we're no longer needed.
+    handle: Arc<EventLoopHandle>,
+
+    /// The thread running our runloop.
+    thread: ThreadId,
+}
+
+impl EventReactor {
+    /// Spins up a new reactor thread, and waits for its runloop to be ready.
+    pub(crate) fn start() -> UsbResult<Arc<Self>> {
+        let handle = Arc::new(EventLoopHandle::default());
+        let (sender, receiver) = mpsc::channel();
+
+        let thread_handle = Arc::clone(&handle);
+        let thread = std::thread::spawn(move || unsafe {
+            // A runloop with no sources returns immediately; so we give ours a source that never
+            // fires, to keep it running even when no devices are registered.
+            let keepalive = create_keepalive_source();
+
+            // Let our creator know where to find us...
+            _ = sender.send(RunLoop(CFRunLoopGetCurrent()));
+
+            // ... and run until we're stopped.
+            _ = NotificationSource::run_event_loop(
+                vec![NotificationSource::new(keepalive)],
+                thread_handle,
+            );
+            CFRelease(keepalive as *const c_void);
+        });
+
+        let runloop = receiver.recv().map_err(|_| Error::UnspecifiedOsError)?;
+        Ok(Arc::new(Self {
+            runloop,
+            handle,
+            thread: thread.thread().id(),
+        }))
+    }
+
+    /// Returns true iff we're being called from the reactor's own thread; e.g. from within a
+    /// completion callback. Anything waiting on an event would wait forever, there.
+    pub(crate) fn is_current_thread(&self) -> bool {
+        std::thread::current().id() == self.thread
+    }
+
+    /// Attaches a device's notification sources to our runloop. The sources remain attached
+    /// until the returned registration is dropped.
+    pub(crate) fn register(
+        self: &Arc<Self>,
+        sources: Vec<NotificationSource>,
+    ) -> EventRegistration {
+        unsafe {
+            for source in &sources {
+                CFRunLoopAddSource(self.runloop.0, source.source(), kCFRunLoopDefaultMode);
+            }
+            CFRunLoopWakeUp(self.runloop.0);
+        }
+
+        EventRegistration {
+            reactor: Arc::clone(self),
+            sources,
+        }
+    }
+}
+
+impl Drop for EventReactor {
+    fn drop(&mut self) {
+        // No one is registered with us anymore; so our thread can go.
+        self.handle.stop();
+    }
+}
+
+/// A device's registration with an event reactor; which keeps the reactor alive, and detaches
+/// the device's sources from it when dropped.
+#[derive(Debug)]
+pub(crate) struct EventRegistration {
+    reactor: Arc<EventReactor>,
+    sources: Vec<NotificationSource>,
+}
+
+impl EventRegistration {
+    /// Returns the reactor we're registered with.
+    pub(crate) fn reactor(&self) -> &Arc<EventReactor> {
+        &self.reactor
+    }
+}
+
+impl Drop for EventRegistration {
+    fn drop(&mut self) {
+        unsafe {
+            for source in &self.sources {
+                CFRunLoopRemoveSource(
+                    self.reactor.runloop.0,
+                    source.source(),
+                    kCFRunLoopDefaultMode,
+                );
+            }
+        }
+    }
+}
+
+/// Callback for our keepalive source; which is never signaled, and thus never called.
+extern "C" fn keepalive_perform(_info: *const c_void) {}
+
+/// Creates a runloop source that never fires.
+unsafe fn create_keepalive_source() -> CFRunLoopSourceRef {
+    let mut context = CFRunLoopSourceContext {
+        version: 0,
+        info: std::ptr::null_mut(),
+        retain: None,
+        release: None,
+        copyDescription: None,
+        equal: None,
+        hash: None,
+        schedule: None,
+        cancel: None,
+        perform: keepalive_perform,
+    };
+
+    CFRunLoopSourceCreate(std::ptr::null(), 0, &mut context)
+}
@@ -0,0 +1,557 @@
+//! Mock backend, which replays a scripted (or recorded) sequence of operations.
+//!
+//! The mock backend doesn't talk to any hardware; each operation performed on one of its
+//! devices is checked against the next record in its script, and answered with whatever
+//! that record says happened. This lets bugs seen on someone else's hardware be reproduced
+//! from a log captured with [super::record::RecordingBackend].
+
+use std::{
+    any::Any,
+    collections::VecDeque,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use log::error;
+
+use super::{
+    record::{read_log, Operation, Record},
+    Backend, BackendDevice, BackendDeviceOps, TransferCallback,
+};
+use crate::{
+    device::{DeviceInformation, ExtraPowerKind, ReenumerateOptions, WriteOptions},
+    Error, ReadBuffer, UsbResult, WriteBuffer,
+};
+
+/// Per-device data for the mock backend. Everything happens in the backend's script;
+/// so that's all we hold.
+#[derive(Debug)]
+pub struct MockDevice {
+    /// The script shared with the backend that opened us.
+    script: Arc<Script>,
+}
+
+impl BackendDevice for MockDevice {
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Backend that answers each operation from a script of records.
+#[derive(Debug)]
+pub struct MockBackend {
+    /// The records we have yet to replay.
+    script: Arc<Script>,
+
+    /// The devices we'll claim are connected; one for each device opened in the script.
+    devices: Vec<(u16, u16)>,
+}
+
+impl MockBackend {
+    /// Creates a mock backend that will replay the given records, in order.
+    pub fn new(records: Vec<Record>) -> Self {
+        let mut devices = vec![];
+        for record in &records {
+            if let Operation::Open {
+                vendor_id,
+                product_id,
+            } = record.operation
+            {
+                if !devices.contains(&(vendor_id, product_id)) {
+                    devices.push((vendor_id, product_id));
+                }
+            }
+        }
+
+        Self {
+            script: Arc::new(Script {
+                records: Mutex::new(records.into()),
+            }),
+            devices,
+        }
+    }
+
+    /// Creates a mock backend that will replay a log captured by a RecordingBackend.
+    pub fn from_log(path: impl AsRef<Path>) -> UsbResult<Self> {
+        Ok(Self::new(read_log(path)?))
+    }
+
+    /// Returns the number of records that haven't yet been replayed.
+    ///
+    /// A test that has exercised everything it recorded should see zero, here.
+    pub fn remaining(&self) -> usize {
+        self.script.records.lock().unwrap().len()
+    }
+}
+
+/// The records a mock backend has yet to replay; shared between it and its devices, since
+/// their operations are all checked against the one script.
+#[derive(Debug)]
+struct Script {
+    /// The records themselves, in order.
+    records: Mutex<VecDeque<Record>>,
+}
+
+impl Script {
+    /// Takes the next record from the script, and checks it against the operation being performed.
+    ///
+    /// If the code being tested has diverged from the script, we'll complain loudly, and fail
+    /// the operation with [Error::InvalidArgument].
+    fn replay(&self, operation: Operation) -> UsbResult<Vec<u8>> {
+        let mut script = self.records.lock().unwrap();
+
+        match script.pop_front() {
+            Some(record) if record.operation.matches(&operation) => record.result,
+            Some(record) => {
+                error!(
+                    "mock backend: expected `{}`, but got {:?}",
+                    record.to_line(),
+                    operation
+                );
+                script.push_front(record);
+                Err(Error::InvalidArgument)
+            }
+            None => {
+                error!("mock backend: script is over, but got {:?}", operation);
+                Err(Error::InvalidArgument)
+            }
+        }
+    }
+
+    /// Replays an operation that doesn't produce data.
+    fn replay_unit(&self, operation: Operation) -> UsbResult<()> {
+        self.replay(operation).map(|_| ())
+    }
+
+    /// Replays an operation that produces a single, little-endian number.
+    fn replay_u32(&self, operation: Operation) -> UsbResult<u32> {
+        let data = self.replay(operation)?;
+        let bytes = data.get(..4).ok_or(Error::InvalidArgument)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Replays an operation that reads data, copying as much as will fit into the target.
+    fn replay_into(&self, operation: Operation, target: &mut [u8]) -> UsbResult<usize> {
+        let data = self.replay(operation)?;
+        let length = data.len().min(target.len());
+
+        target[..length].copy_from_slice(&data[..length]);
+        Ok(length)
+    }
+}
+
+impl Backend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn get_devices(&self) -> UsbResult<Vec<DeviceInformation>> {
+        Ok(self
+            .devices
+            .iter()
+            .map(|&(vendor_id, product_id)| {
+                DeviceInformation::new(vendor_id, product_id, None, None, None)
+            })
+            .collect())
+    }
+
+    fn open(&self, information: &DeviceInformation) -> UsbResult<Box<dyn BackendDevice>> {
+        self.script.replay_unit(Operation::Open {
+            vendor_id: information.vendor_id,
+            product_id: information.product_id,
+        })?;
+
+        Ok(Box::new(MockDevice {
+            script: Arc::clone(&self.script),
+        }))
+    }
+}
+
+impl BackendDeviceOps for MockDevice {
+    fn release_kernel_driver(&mut self, interface: u8) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::ReleaseKernelDriver(interface))
+    }
+
+    fn attach_kernel_driver(&mut self, interface: u8) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::AttachKernelDriver(interface))
+    }
+
+    fn kernel_driver_name(&self, interface: u8) -> UsbResult<Option<String>> {
+        let name = self.script.replay(Operation::KernelDriverName(interface))?;
+        Ok((!name.is_empty()).then(|| String::from_utf8_lossy(&name).into_owned()))
+    }
+
+    fn claim_interface(&mut self, interface: u8) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::ClaimInterface(interface))
+    }
+
+    fn unclaim_interface(&mut self, interface: u8) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::UnclaimInterface(interface))
+    }
+
+    fn active_configuration(&self) -> UsbResult<u8> {
+        let data = self.script.replay(Operation::ActiveConfiguration)?;
+        data.first().copied().ok_or(Error::InvalidArgument)
+    }
+
+    fn num_configurations(&self) -> UsbResult<u8> {
+        let data = self.script.replay(Operation::NumConfigurations)?;
+        data.first().copied().ok_or(Error::InvalidArgument)
+    }
+
+    fn cached_configuration_descriptor(&self, configuration_index: u8) -> UsbResult<Vec<u8>> {
+        self.script.replay(Operation::CachedConfigurationDescriptor(
+            configuration_index,
+        ))
+    }
+
+    fn set_active_configuration(&mut self, configuration_value: u8) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::SetActiveConfiguration(configuration_value))
+    }
+
+    fn reset_device(&self) -> UsbResult<()> {
+        self.script.replay_unit(Operation::ResetDevice)
+    }
+
+    fn set_suspended(&self, suspended: bool) -> UsbResult<()> {
+        self.script.replay_unit(Operation::SetSuspended(suspended))
+    }
+
+    fn bus_power_available(&self) -> UsbResult<u32> {
+        self.script.replay_u32(Operation::BusPowerAvailable)
+    }
+
+    fn request_extra_power(&self, kind: ExtraPowerKind, milliamps: u32) -> UsbResult<u32> {
+        self.script
+            .replay_u32(Operation::RequestExtraPower { kind, milliamps })
+    }
+
+    fn return_extra_power(&self, kind: ExtraPowerKind, milliamps: u32) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::ReturnExtraPower { kind, milliamps })
+    }
+
+    fn bandwidth_available(&self) -> UsbResult<u32> {
+        self.script.replay_u32(Operation::BandwidthAvailable)
+    }
+
+    fn reenumerate(&mut self, options: &ReenumerateOptions) -> UsbResult<()> {
+        self.script.replay_unit(Operation::Reenumerate {
+            capture: options.capture,
+        })
+    }
+
+    fn abort_endpoint(&self, endpoint_address: u8) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::AbortEndpoint(endpoint_address))
+    }
+
+    fn clear_stall(&self, endpoint_address: u8) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::ClearStall(endpoint_address))
+    }
+
+    fn set_alternate_setting(&mut self, interface: u8, setting: u8) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::SetAlternateSetting { interface, setting })
+    }
+
+    fn current_bus_frame(&self) -> UsbResult<(u64, SystemTime)> {
+        Err(Error::Unsupported)
+    }
+
+    fn control_read(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: &mut [u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let operation = Operation::ControlRead {
+            request_type,
+            request_number,
+            value,
+            index,
+            length: target.len(),
+        };
+        self.script.replay_into(operation, target)
+    }
+
+    fn control_read_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: ReadBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        // Replayed operations complete immediately; so we can just complete the callback inline.
+        let result = self.control_read(
+            request_type,
+            request_number,
+            value,
+            index,
+            target.write().as_mut(),
+            timeout,
+        );
+        callback(result);
+
+        Ok(())
+    }
+
+    fn control_write(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        self.script.replay_unit(Operation::ControlWrite {
+            request_type,
+            request_number,
+            value,
+            index,
+            data: data.to_vec(),
+        })
+    }
+
+    fn control_write_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let data = data.as_ref().as_ref();
+        let result = self.control_write(request_type, request_number, value, index, data, timeout);
+        callback(result.map(|_| data.len()));
+
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let operation = Operation::Read {
+            endpoint,
+            length: buffer.len(),
+        };
+        self.script.replay_into(operation, buffer)
+    }
+
+    fn write(&self, endpoint: u8, data: &[u8], _timeout: Option<Duration>) -> UsbResult<usize> {
+        self.script.replay_unit(Operation::Write {
+            endpoint,
+            data: data.to_vec(),
+        })?;
+
+        // Our scripts only record whether a write succeeded; so successful writes are complete.
+        Ok(data.len())
+    }
+
+    fn write_with_options(
+        &self,
+        endpoint: u8,
+        data: &[u8],
+        options: &WriteOptions,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        if !options.append_zlp {
+            return self.write(endpoint, data, timeout);
+        }
+
+        self.script.replay_unit(Operation::WriteWithZlp {
+            endpoint,
+            data: data.to_vec(),
+        })?;
+        Ok(data.len())
+    }
+
+    fn alloc_streams(&self, endpoints: &[u8], count: u32) -> UsbResult<u32> {
+        self.script.replay_u32(Operation::AllocStreams {
+            endpoints: endpoints.to_vec(),
+            count,
+        })
+    }
+
+    fn free_streams(&self, endpoints: &[u8]) -> UsbResult<()> {
+        self.script
+            .replay_unit(Operation::FreeStreams(endpoints.to_vec()))
+    }
+
+    fn read_stream(
+        &self,
+        endpoint: u8,
+        stream_id: u32,
+        buffer: &mut [u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let operation = Operation::ReadStream {
+            endpoint,
+            stream_id,
+            length: buffer.len(),
+        };
+        self.script.replay_into(operation, buffer)
+    }
+
+    fn write_stream(
+        &self,
+        endpoint: u8,
+        stream_id: u32,
+        data: &[u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        self.script.replay_unit(Operation::WriteStream {
+            endpoint,
+            stream_id,
+            data: data.to_vec(),
+        })?;
+        Ok(data.len())
+    }
+
+    fn read_nonblocking(
+        &self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let result = self.read(endpoint, buffer.write().as_mut(), timeout);
+        callback(result);
+
+        Ok(())
+    }
+
+    fn write_nonblocking(
+        &self,
+        endpoint: u8,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let data = data.as_ref().as_ref();
+        let result = self.write(endpoint, data, timeout);
+        callback(result);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opens the only device in a script made of an open, followed by the given records.
+    fn open_scripted(records: Vec<Record>) -> (MockBackend, Box<dyn BackendDevice>) {
+        let mut script = vec![Record {
+            operation: Operation::Open {
+                vendor_id: 0x1209,
+                product_id: 0x0001,
+            },
+            result: Ok(vec![]),
+        }];
+        script.extend(records);
+
+        let backend = MockBackend::new(script);
+        let information = backend.get_devices().unwrap().remove(0);
+        let device = backend.open(&information).unwrap();
+        (backend, device)
+    }
+
+    /// A record of a successful write of the given data to endpoint 0x01.
+    fn write(data: &[u8]) -> Record {
+        Record {
+            operation: Operation::Write {
+                endpoint: 0x01,
+                data: data.to_vec(),
+            },
+            result: Ok(vec![]),
+        }
+    }
+
+    #[test]
+    fn reads_are_answered_from_the_script() {
+        let (backend, device) = open_scripted(vec![Record {
+            operation: Operation::Read {
+                endpoint: 0x81,
+                length: 4,
+            },
+            result: Ok(vec![1, 2, 3, 4, 5]),
+        }]);
+
+        // Reads only need to match by endpoint; and get as much of the data as fits.
+        let mut buffer = [0; 2];
+        assert_eq!(device.read(0x81, &mut buffer, None), Ok(2));
+        assert_eq!(buffer, [1, 2]);
+        assert_eq!(backend.remaining(), 0);
+    }
+
+    #[test]
+    fn diverging_from_the_script_fails_without_consuming_it() {
+        let (backend, device) = open_scripted(vec![write(&[1, 2])]);
+
+        // The wrong kind of operation...
+        let mut buffer = [0; 2];
+        assert_eq!(
+            device.read(0x81, &mut buffer, None),
+            Err(Error::InvalidArgument)
+        );
+
+        // ... or the right one, with the wrong data, both leave the record where it was...
+        assert_eq!(
+            device.write(0x01, &[3, 4], None),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            device.write(0x02, &[1, 2], None),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(backend.remaining(), 1);
+
+        // ... so the code under test can still perform it.
+        assert_eq!(device.write(0x01, &[1, 2], None), Ok(2));
+        assert_eq!(backend.remaining(), 0);
+    }
+
+    #[test]
+    fn running_past_the_script_fails() {
+        let (backend, device) = open_scripted(vec![]);
+
+        assert_eq!(device.write(0x01, &[1], None), Err(Error::InvalidArgument));
+        assert_eq!(device.active_configuration(), Err(Error::InvalidArgument));
+        assert_eq!(backend.remaining(), 0);
+    }
+
+    #[test]
+    fn scripted_errors_are_replayed() {
+        let (backend, device) = open_scripted(vec![Record {
+            operation: Operation::ClearStall(0x81),
+            result: Err(Error::Stalled),
+        }]);
+
+        assert_eq!(device.clear_stall(0x81), Err(Error::Stalled));
+        assert_eq!(backend.remaining(), 0);
+    }
+}
@@ -0,0 +1,29 @@
+//! Everything needed to implement a backend outside of this crate; `use usrs::backend::prelude::*`.
+//!
+//! A backend implements [Backend], to find and open devices; and [BackendDevice] (with its
+//! [BackendDeviceOps]), for each device it opens. Devices are described with [DeviceInformation],
+//! whose setters record where the backend found each one. Once it works, point
+//! [run_conformance_tests] at it; then hand it to [Host::new_from_backend].
+//!
+//! What's exported here is kept stable across minor versions; new trait methods only ever
+//! arrive with default implementations.
+
+pub use super::conformance::run_conformance_tests;
+pub use super::{
+    Backend, BackendDevice, BackendDeviceOps, DeviceInformationIterator, DeviceWatch,
+    DisconnectSignal,
+};
+pub use crate::compliance::{ComplianceReport, TestOutcome, TestResult};
+pub use crate::descriptors::DeviceDescriptor;
+pub use crate::device::{
+    DeviceInformation, DeviceSelector, DeviceSpeed, EndpointPolicy, EnumerationOptions,
+    ExtraPowerKind, InterfaceClass, OpenMode, OpenOptions, RawHandle, ReenumerateOptions,
+    TransferTimeout, WriteOptions,
+};
+pub use crate::diagnostics::AccessProblem;
+pub use crate::error::{Error, UsbResult};
+pub use crate::host::Host;
+#[cfg(feature = "async")]
+pub use crate::iso::{IsoCallback, IsoOptions, IsoPacketStatus};
+pub use crate::topology::PortPath;
+pub use crate::{ReadBuffer, ReadBufferGuard, WriteBuffer};
@@ -0,0 +1,1351 @@
+//! Backend wrapper that records every operation performed on a device, for later replay.
+//!
+//! Logs are plain text, one operation per line, so they can be attached to bug reports and
+//! read (or edited) by humans. Each line describes an operation, followed by its outcome:
+//!
+//! ```text
+//! open 1d50:615c
+//! control_read 80 06 0100 0000 18 => ok 12010002ef020140501d5c61040101020301
+//! write 01 deadbeef => ok
+//! read 81 512 => err TimedOut
+//! ```
+//!
+//! Lines starting with `#` are comments. Logs can be replayed via [super::mock::MockBackend].
+
+use std::{
+    any::Any,
+    fmt::Write as _,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    Backend, BackendDevice, BackendDeviceOps, DeviceInformationIterator, DeviceWatch,
+    DisconnectSignal, TransferCallback, TransferMemory,
+};
+use crate::{
+    device::{
+        DeviceInformation, DeviceSelector, EndpointPolicy, EnumerationOptions, ExtraPowerKind,
+        OpenOptions, RawHandle, ReenumerateOptions, TransferTimeout, WriteOptions,
+    },
+    diagnostics::AccessProblem,
+    error::io_error,
+    Error, ReadBuffer, UsbResult, WriteBuffer,
+};
+
+#[cfg(feature = "async")]
+use crate::iso::{IsoCallback, IsoOptions};
+
+/// A single operation performed on a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Open {
+        vendor_id: u16,
+        product_id: u16,
+    },
+    ReleaseKernelDriver(u8),
+    AttachKernelDriver(u8),
+    KernelDriverName(u8),
+    ClaimInterface(u8),
+    UnclaimInterface(u8),
+    ActiveConfiguration,
+    NumConfigurations,
+    CachedConfigurationDescriptor(u8),
+    SetActiveConfiguration(u8),
+    ResetDevice,
+    Reenumerate {
+        capture: bool,
+    },
+    SetSuspended(bool),
+    BusPowerAvailable,
+    RequestExtraPower {
+        kind: ExtraPowerKind,
+        milliamps: u32,
+    },
+    ReturnExtraPower {
+        kind: ExtraPowerKind,
+        milliamps: u32,
+    },
+    BandwidthAvailable,
+    AbortEndpoint(u8),
+    ClearStall(u8),
+    SetAlternateSetting {
+        interface: u8,
+        setting: u8,
+    },
+    ControlRead {
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        length: usize,
+    },
+    ControlWrite {
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: Vec<u8>,
+    },
+    Read {
+        endpoint: u8,
+        length: usize,
+    },
+    Write {
+        endpoint: u8,
+        data: Vec<u8>,
+    },
+    WriteWithZlp {
+        endpoint: u8,
+        data: Vec<u8>,
+    },
+    AllocStreams {
+        endpoints: Vec<u8>,
+        count: u32,
+    },
+    FreeStreams(Vec<u8>),
+    ReadStream {
+        endpoint: u8,
+        stream_id: u32,
+        length: usize,
+    },
+    WriteStream {
+        endpoint: u8,
+        stream_id: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl Operation {
+    /// Returns true iff a replayed operation is close enough to a recorded one to stand in for it.
+    ///
+    /// Read lengths are ignored; code under test often uses a different buffer size than the code
+    /// that made the recording, and that's not what we're trying to catch.
+    pub fn matches(&self, other: &Operation) -> bool {
+        match (self, other) {
+            (
+                Operation::ControlRead {
+                    request_type,
+                    request_number,
+                    value,
+                    index,
+                    ..
+                },
+                Operation::ControlRead {
+                    request_type: other_type,
+                    request_number: other_number,
+                    value: other_value,
+                    index: other_index,
+                    ..
+                },
+            ) => {
+                (request_type, request_number, value, index)
+                    == (other_type, other_number, other_value, other_index)
+            }
+            (
+                Operation::Read { endpoint, .. },
+                Operation::Read {
+                    endpoint: other_endpoint,
+                    ..
+                },
+            ) => endpoint == other_endpoint,
+            (
+                Operation::ReadStream {
+                    endpoint,
+                    stream_id,
+                    ..
+                },
+                Operation::ReadStream {
+                    endpoint: other_endpoint,
+                    stream_id: other_stream_id,
+                    ..
+                },
+            ) => (endpoint, stream_id) == (other_endpoint, other_stream_id),
+            _ => self == other,
+        }
+    }
+}
+
+/// A single recorded operation, and its outcome.
+///
+/// For operations that read data, a successful result carries the data read. For
+/// [Operation::ActiveConfiguration] and [Operation::NumConfigurations], it carries a single byte; and for [Operation::KernelDriverName],
+/// the driver's name, which is empty if no driver is bound. Operations that return power or
+/// bandwidth figures carry them as four little-endian bytes. Otherwise, it's empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The operation that was performed.
+    pub operation: Operation,
+
+    /// What the device (or OS) told us about it.
+    pub result: UsbResult<Vec<u8>>,
+}
+
+impl Record {
+    /// Renders the record into a single line of our log format, without a trailing newline.
+    pub fn to_line(&self) -> String {
+        let mut line = match &self.operation {
+            Operation::Open {
+                vendor_id,
+                product_id,
+            } => format!("open {vendor_id:04x}:{product_id:04x}"),
+            Operation::ReleaseKernelDriver(interface) => {
+                format!("release_kernel_driver {interface}")
+            }
+            Operation::AttachKernelDriver(interface) => {
+                format!("attach_kernel_driver {interface}")
+            }
+            Operation::KernelDriverName(interface) => format!("kernel_driver_name {interface}"),
+            Operation::ClaimInterface(interface) => format!("claim_interface {interface}"),
+            Operation::UnclaimInterface(interface) => format!("unclaim_interface {interface}"),
+            Operation::ActiveConfiguration => "active_configuration".to_owned(),
+            Operation::NumConfigurations => "num_configurations".to_owned(),
+            Operation::CachedConfigurationDescriptor(index) => {
+                format!("cached_configuration_descriptor {index}")
+            }
+            Operation::SetActiveConfiguration(configuration) => {
+                format!("set_active_configuration {configuration}")
+            }
+            Operation::ResetDevice => "reset_device".to_owned(),
+            Operation::SetSuspended(suspended) => format!("set_suspended {}", *suspended as u8),
+            Operation::BusPowerAvailable => "bus_power_available".to_owned(),
+            Operation::RequestExtraPower { kind, milliamps } => {
+                format!("request_extra_power {} {milliamps}", power_kind_name(*kind))
+            }
+            Operation::ReturnExtraPower { kind, milliamps } => {
+                format!("return_extra_power {} {milliamps}", power_kind_name(*kind))
+            }
+            Operation::BandwidthAvailable => "bandwidth_available".to_owned(),
+            Operation::Reenumerate { capture } => format!("reenumerate {}", *capture as u8),
+            Operation::AbortEndpoint(endpoint) => format!("abort_endpoint {endpoint:02x}"),
+            Operation::ClearStall(endpoint) => format!("clear_stall {endpoint:02x}"),
+            Operation::SetAlternateSetting { interface, setting } => {
+                format!("set_alternate_setting {interface} {setting}")
+            }
+            Operation::ControlRead {
+                request_type,
+                request_number,
+                value,
+                index,
+                length,
+            } => format!(
+                "control_read {request_type:02x} {request_number:02x} {value:04x} {index:04x} {length}"
+            ),
+            Operation::ControlWrite {
+                request_type,
+                request_number,
+                value,
+                index,
+                data,
+            } => format!(
+                "control_write {request_type:02x} {request_number:02x} {value:04x} {index:04x} {}",
+                to_hex(data)
+            ),
+            Operation::Read { endpoint, length } => format!("read {endpoint:02x} {length}"),
+            Operation::Write { endpoint, data } => {
+                format!("write {endpoint:02x} {}", to_hex(data))
+            }
+            Operation::WriteWithZlp { endpoint, data } => {
+                format!("write_zlp {endpoint:02x} {}", to_hex(data))
+            }
+            Operation::AllocStreams { endpoints, count } => {
+                format!("alloc_streams {} {count}", to_hex(endpoints))
+            }
+            Operation::FreeStreams(endpoints) => format!("free_streams {}", to_hex(endpoints)),
+            Operation::ReadStream {
+                endpoint,
+                stream_id,
+                length,
+            } => format!("read_stream {endpoint:02x} {stream_id} {length}"),
+            Operation::WriteStream {
+                endpoint,
+                stream_id,
+                data,
+            } => format!("write_stream {endpoint:02x} {stream_id} {}", to_hex(data)),
+        };
+
+        match &self.result {
+            Ok(data) if data.is_empty() => line.push_str(" => ok"),
+            Ok(data) => {
+                let _ = write!(line, " => ok {}", to_hex(data));
+            }
+            Err(error) => {
+                let _ = write!(line, " => err {error:?}");
+            }
+        }
+
+        line
+    }
+
+    /// Parses a single line of our log format.
+    pub fn parse_line(line: &str) -> UsbResult<Self> {
+        let (operation, outcome) = line.split_once(" => ").ok_or(Error::InvalidArgument)?;
+
+        // Parse the operation...
+        let mut words = operation.split_whitespace();
+        let name = words.next().ok_or(Error::InvalidArgument)?;
+        let arguments: Vec<&str> = words.collect();
+        let argument = |position: usize| -> UsbResult<&str> {
+            arguments
+                .get(position)
+                .copied()
+                .ok_or(Error::InvalidArgument)
+        };
+        let decimal = |position: usize| -> UsbResult<u8> {
+            argument(position)?
+                .parse()
+                .map_err(|_| Error::InvalidArgument)
+        };
+        let hex_u8 = |position: usize| -> UsbResult<u8> {
+            u8::from_str_radix(argument(position)?, 16).map_err(|_| Error::InvalidArgument)
+        };
+        let hex_u16 = |position: usize| -> UsbResult<u16> {
+            u16::from_str_radix(argument(position)?, 16).map_err(|_| Error::InvalidArgument)
+        };
+        let length = |position: usize| -> UsbResult<usize> {
+            argument(position)?
+                .parse()
+                .map_err(|_| Error::InvalidArgument)
+        };
+
+        let decimal_u32 = |position: usize| -> UsbResult<u32> {
+            argument(position)?
+                .parse()
+                .map_err(|_| Error::InvalidArgument)
+        };
+        let milliamps = |position: usize| -> UsbResult<u32> {
+            argument(position)?
+                .parse()
+                .map_err(|_| Error::InvalidArgument)
+        };
+        let power_kind = |position: usize| -> UsbResult<ExtraPowerKind> {
+            match argument(position)? {
+                "wake" => Ok(ExtraPowerKind::Wake),
+                "sleep" => Ok(ExtraPowerKind::Sleep),
+                _ => Err(Error::InvalidArgument),
+            }
+        };
+
+        let operation = match name {
+            "open" => {
+                let (vendor_id, product_id) =
+                    argument(0)?.split_once(':').ok_or(Error::InvalidArgument)?;
+                Operation::Open {
+                    vendor_id: u16::from_str_radix(vendor_id, 16)
+                        .map_err(|_| Error::InvalidArgument)?,
+                    product_id: u16::from_str_radix(product_id, 16)
+                        .map_err(|_| Error::InvalidArgument)?,
+                }
+            }
+            "release_kernel_driver" => Operation::ReleaseKernelDriver(decimal(0)?),
+            "attach_kernel_driver" => Operation::AttachKernelDriver(decimal(0)?),
+            "kernel_driver_name" => Operation::KernelDriverName(decimal(0)?),
+            "claim_interface" => Operation::ClaimInterface(decimal(0)?),
+            "unclaim_interface" => Operation::UnclaimInterface(decimal(0)?),
+            "active_configuration" => Operation::ActiveConfiguration,
+            "num_configurations" => Operation::NumConfigurations,
+            "cached_configuration_descriptor" => {
+                Operation::CachedConfigurationDescriptor(decimal(0)?)
+            }
+            "set_active_configuration" => Operation::SetActiveConfiguration(decimal(0)?),
+            "reset_device" => Operation::ResetDevice,
+            "set_suspended" => Operation::SetSuspended(decimal(0)? != 0),
+            "bus_power_available" => Operation::BusPowerAvailable,
+            "request_extra_power" => Operation::RequestExtraPower {
+                kind: power_kind(0)?,
+                milliamps: milliamps(1)?,
+            },
+            "return_extra_power" => Operation::ReturnExtraPower {
+                kind: power_kind(0)?,
+                milliamps: milliamps(1)?,
+            },
+            "bandwidth_available" => Operation::BandwidthAvailable,
+            "reenumerate" => Operation::Reenumerate {
+                capture: decimal(0)? != 0,
+            },
+            "abort_endpoint" => Operation::AbortEndpoint(hex_u8(0)?),
+            "clear_stall" => Operation::ClearStall(hex_u8(0)?),
+            "set_alternate_setting" => Operation::SetAlternateSetting {
+                interface: decimal(0)?,
+                setting: decimal(1)?,
+            },
+            "control_read" => Operation::ControlRead {
+                request_type: hex_u8(0)?,
+                request_number: hex_u8(1)?,
+                value: hex_u16(2)?,
+                index: hex_u16(3)?,
+                length: length(4)?,
+            },
+            "control_write" => Operation::ControlWrite {
+                request_type: hex_u8(0)?,
+                request_number: hex_u8(1)?,
+                value: hex_u16(2)?,
+                index: hex_u16(3)?,
+                data: from_hex(argument(4).unwrap_or(""))?,
+            },
+            "read" => Operation::Read {
+                endpoint: hex_u8(0)?,
+                length: length(1)?,
+            },
+            "write" => Operation::Write {
+                endpoint: hex_u8(0)?,
+                data: from_hex(argument(1).unwrap_or(""))?,
+            },
+            "write_zlp" => Operation::WriteWithZlp {
+                endpoint: hex_u8(0)?,
+                data: from_hex(argument(1).unwrap_or(""))?,
+            },
+            "alloc_streams" => Operation::AllocStreams {
+                endpoints: from_hex(argument(0).unwrap_or(""))?,
+                count: decimal_u32(1)?,
+            },
+            "free_streams" => Operation::FreeStreams(from_hex(argument(0).unwrap_or(""))?),
+            "read_stream" => Operation::ReadStream {
+                endpoint: hex_u8(0)?,
+                stream_id: decimal_u32(1)?,
+                length: length(2)?,
+            },
+            "write_stream" => Operation::WriteStream {
+                endpoint: hex_u8(0)?,
+                stream_id: decimal_u32(1)?,
+                data: from_hex(argument(2).unwrap_or(""))?,
+            },
+            _ => return Err(Error::InvalidArgument),
+        };
+
+        // ... and then its outcome.
+        let mut words = outcome.split_whitespace();
+        let result = match (words.next(), words.next()) {
+            (Some("ok"), data) => Ok(from_hex(data.unwrap_or(""))?),
+            (Some("err"), Some(error)) => Err(parse_error(error)?),
+            _ => return Err(Error::InvalidArgument),
+        };
+
+        Ok(Self { operation, result })
+    }
+}
+
+/// Reads every record from a log file.
+pub fn read_log(path: impl AsRef<Path>) -> UsbResult<Vec<Record>> {
+    let file = File::open(path).map_err(io_error)?;
+
+    let mut records = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(io_error)?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        records.push(Record::parse_line(line)?);
+    }
+
+    Ok(records)
+}
+
+/// Renders bytes as a contiguous hex string.
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Parses a contiguous hex string back into bytes.
+fn from_hex(text: &str) -> UsbResult<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).map_err(|_| Error::InvalidArgument)?;
+            match pair.len() {
+                2 => u8::from_str_radix(pair, 16).map_err(|_| Error::InvalidArgument),
+                _ => Err(Error::InvalidArgument),
+            }
+        })
+        .collect()
+}
+
+/// Parses an error, as rendered by its Debug implementation.
+fn parse_error(text: &str) -> UsbResult<Error> {
+    use Error::*;
+
+    if let Some(errno) = text
+        .strip_prefix("OsError(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        return errno
+            .parse()
+            .map(OsError)
+            .map_err(|_| Error::InvalidArgument);
+    }
+
+    if let Some(length) = text
+        .strip_prefix("PartialTransfer(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        return length
+            .parse()
+            .map(PartialTransfer)
+            .map_err(|_| Error::InvalidArgument);
+    }
+
+    Ok(match text {
+        "Unsupported" => Unsupported,
+        "DeviceNotFound" => DeviceNotFound,
+        "DeviceNotOpen" => DeviceNotOpen,
+        "Disconnected" => Disconnected,
+        "DeviceNotReal" => DeviceNotReal,
+        "DeviceReserved" => DeviceReserved,
+        "Stalled" => Stalled,
+        "InvalidEndpoint" => InvalidEndpoint,
+        "InvalidInterface" => InvalidInterface,
+        "TimedOut" => TimedOut,
+        "InvalidArgument" => InvalidArgument,
+        "Aborted" => Aborted,
+        "Overrun" => Overrun,
+        "Babble" => Babble,
+        "ProtocolError" => ProtocolError,
+        "Underrun" => Underrun,
+        "NotResponding" => NotResponding,
+        "PermissionDenied" => PermissionDenied,
+        "MalformedDescriptor" => MalformedDescriptor,
+        "UnspecifiedOsError" => UnspecifiedOsError,
+        _ => return Err(Error::InvalidArgument),
+    })
+}
+
+/// Backend that wraps another backend, and logs every operation performed through it.
+#[derive(Debug)]
+pub struct RecordingBackend {
+    /// The backend that actually does the work.
+    inner: Arc<dyn Backend>,
+
+    /// Where our log goes.
+    log: Arc<Mutex<File>>,
+}
+
+impl RecordingBackend {
+    /// Wraps a backend, logging each of its operations to the file at the given path.
+    pub fn new(inner: Arc<dyn Backend>, path: impl AsRef<Path>) -> UsbResult<Self> {
+        let mut file = File::create(path).map_err(io_error)?;
+        writeln!(file, "# usrs transfer log").map_err(io_error)?;
+
+        Ok(Self {
+            inner,
+            log: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Adds a record to our log.
+    fn record(&self, operation: Operation, result: UsbResult<Vec<u8>>) {
+        append_record(&self.log, Record { operation, result });
+    }
+
+    /// Wraps a device opened by our inner backend, so its operations are logged, too.
+    fn wrap(&self, opened: UsbResult<Box<dyn BackendDevice>>) -> UsbResult<Box<dyn BackendDevice>> {
+        Ok(Box::new(RecordingDevice {
+            inner: opened?,
+            log: Arc::clone(&self.log),
+        }))
+    }
+}
+
+/// Per-device data for the recording backend; which wraps the inner backend's device.
+#[derive(Debug)]
+pub struct RecordingDevice {
+    /// The device that actually does the work.
+    inner: Box<dyn BackendDevice>,
+
+    /// Where our log goes; shared with the backend that opened us.
+    log: Arc<Mutex<File>>,
+}
+
+impl RecordingDevice {
+    /// Adds a record to our log.
+    fn record(&self, operation: Operation, result: UsbResult<Vec<u8>>) {
+        append_record(&self.log, Record { operation, result });
+    }
+
+    /// Adds a record for an operation that produces a single number; stored little-endian.
+    fn record_u32(&self, operation: Operation, result: &UsbResult<u32>) {
+        self.record(
+            operation,
+            result.clone().map(|value| value.to_le_bytes().to_vec()),
+        );
+    }
+
+    /// Adds a record for an operation that doesn't produce any data.
+    fn record_unit(&self, operation: Operation, result: &UsbResult<()>) {
+        self.record(operation, result.clone().map(|_| vec![]));
+    }
+}
+
+impl BackendDevice for RecordingDevice {
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.inner.is_disconnected()
+    }
+
+    fn set_disconnect_signal(&mut self, signal: Arc<DisconnectSignal>) {
+        self.inner.set_disconnect_signal(signal)
+    }
+
+    fn raw_handle(&self) -> Option<RawHandle> {
+        self.inner.raw_handle()
+    }
+
+    fn raw_interface_handle(&self, interface: u8) -> Option<RawHandle> {
+        self.inner.raw_interface_handle(interface)
+    }
+}
+
+/// Returns the name we use for a kind of extra power in our log format.
+fn power_kind_name(kind: ExtraPowerKind) -> &'static str {
+    match kind {
+        ExtraPowerKind::Wake => "wake",
+        ExtraPowerKind::Sleep => "sleep",
+    }
+}
+
+/// Appends a record to a log; shared with our nonblocking callbacks, which outlive their calls.
+///
+/// Logging is best-effort: failing to write the log shouldn't change what the code under test sees.
+fn append_record(log: &Mutex<File>, record: Record) {
+    let mut log = log.lock().unwrap();
+    let _ = writeln!(log, "{}", record.to_line());
+}
+
+impl Backend for RecordingBackend {
+    fn name(&self) -> &'static str {
+        "record"
+    }
+
+    fn get_devices(&self) -> UsbResult<Vec<DeviceInformation>> {
+        self.inner.get_devices()
+    }
+
+    fn devices_iter(
+        &self,
+        selector: &DeviceSelector,
+        options: &EnumerationOptions,
+    ) -> UsbResult<DeviceInformationIterator<'_>> {
+        self.inner.devices_iter(selector, options)
+    }
+
+    fn fetch_device_strings(&self, information: &mut DeviceInformation) -> UsbResult<()> {
+        self.inner.fetch_device_strings(information)
+    }
+
+    fn watch_devices(&self) -> UsbResult<DeviceWatch> {
+        self.inner.watch_devices()
+    }
+
+    fn open(&self, information: &DeviceInformation) -> UsbResult<Box<dyn BackendDevice>> {
+        let result = self.inner.open(information);
+
+        let operation = Operation::Open {
+            vendor_id: information.vendor_id,
+            product_id: information.product_id,
+        };
+        self.record(
+            operation,
+            result.as_ref().map(|_| vec![]).map_err(Clone::clone),
+        );
+
+        self.wrap(result)
+    }
+
+    fn open_with(
+        &self,
+        information: &DeviceInformation,
+        options: &OpenOptions,
+    ) -> UsbResult<Box<dyn BackendDevice>> {
+        let result = self.inner.open_with(information, options);
+
+        let operation = Operation::Open {
+            vendor_id: information.vendor_id,
+            product_id: information.product_id,
+        };
+        self.record(
+            operation,
+            result.as_ref().map(|_| vec![]).map_err(Clone::clone),
+        );
+
+        self.wrap(result)
+    }
+
+    fn diagnose_access(&self, information: &DeviceInformation) -> UsbResult<Vec<AccessProblem>> {
+        self.inner.diagnose_access(information)
+    }
+}
+
+impl BackendDeviceOps for RecordingDevice {
+    fn release_kernel_driver(&mut self, interface: u8) -> UsbResult<()> {
+        let result = self.inner.release_kernel_driver(interface);
+        self.record_unit(Operation::ReleaseKernelDriver(interface), &result);
+        result
+    }
+
+    fn attach_kernel_driver(&mut self, interface: u8) -> UsbResult<()> {
+        let result = self.inner.attach_kernel_driver(interface);
+        self.record_unit(Operation::AttachKernelDriver(interface), &result);
+        result
+    }
+
+    fn kernel_driver_name(&self, interface: u8) -> UsbResult<Option<String>> {
+        let result = self.inner.kernel_driver_name(interface);
+        self.record(
+            Operation::KernelDriverName(interface),
+            result
+                .clone()
+                .map(|name| name.map(String::into_bytes).unwrap_or_default()),
+        );
+        result
+    }
+
+    fn claim_interface(&mut self, interface: u8) -> UsbResult<()> {
+        let result = self.inner.claim_interface(interface);
+        self.record_unit(Operation::ClaimInterface(interface), &result);
+        result
+    }
+
+    fn unclaim_interface(&mut self, interface: u8) -> UsbResult<()> {
+        let result = self.inner.unclaim_interface(interface);
+        self.record_unit(Operation::UnclaimInterface(interface), &result);
+        result
+    }
+
+    fn active_configuration(&self) -> UsbResult<u8> {
+        let result = self.inner.active_configuration();
+        self.record(
+            Operation::ActiveConfiguration,
+            result.clone().map(|configuration| vec![configuration]),
+        );
+        result
+    }
+
+    fn num_configurations(&self) -> UsbResult<u8> {
+        let result = self.inner.num_configurations();
+        self.record(
+            Operation::NumConfigurations,
+            result.clone().map(|count| vec![count]),
+        );
+        result
+    }
+
+    fn cached_configuration_descriptor(&self, configuration_index: u8) -> UsbResult<Vec<u8>> {
+        let result = self
+            .inner
+            .cached_configuration_descriptor(configuration_index);
+        self.record(
+            Operation::CachedConfigurationDescriptor(configuration_index),
+            result.clone(),
+        );
+        result
+    }
+
+    fn set_active_configuration(&mut self, configuration_value: u8) -> UsbResult<()> {
+        let result = self.inner.set_active_configuration(configuration_value);
+        self.record_unit(
+            Operation::SetActiveConfiguration(configuration_value),
+            &result,
+        );
+        result
+    }
+
+    fn reset_device(&self) -> UsbResult<()> {
+        let result = self.inner.reset_device();
+        self.record_unit(Operation::ResetDevice, &result);
+        result
+    }
+
+    fn set_suspended(&self, suspended: bool) -> UsbResult<()> {
+        let result = self.inner.set_suspended(suspended);
+        self.record_unit(Operation::SetSuspended(suspended), &result);
+        result
+    }
+
+    fn bus_power_available(&self) -> UsbResult<u32> {
+        let result = self.inner.bus_power_available();
+        self.record_u32(Operation::BusPowerAvailable, &result);
+        result
+    }
+
+    fn request_extra_power(&self, kind: ExtraPowerKind, milliamps: u32) -> UsbResult<u32> {
+        let result = self.inner.request_extra_power(kind, milliamps);
+        self.record_u32(Operation::RequestExtraPower { kind, milliamps }, &result);
+        result
+    }
+
+    fn return_extra_power(&self, kind: ExtraPowerKind, milliamps: u32) -> UsbResult<()> {
+        let result = self.inner.return_extra_power(kind, milliamps);
+        self.record_unit(Operation::ReturnExtraPower { kind, milliamps }, &result);
+        result
+    }
+
+    fn bandwidth_available(&self) -> UsbResult<u32> {
+        let result = self.inner.bandwidth_available();
+        self.record_u32(Operation::BandwidthAvailable, &result);
+        result
+    }
+
+    fn reenumerate(&mut self, options: &ReenumerateOptions) -> UsbResult<()> {
+        let result = self.inner.reenumerate(options);
+        self.record_unit(
+            Operation::Reenumerate {
+                capture: options.capture,
+            },
+            &result,
+        );
+        result
+    }
+
+    fn abort_endpoint(&self, endpoint_address: u8) -> UsbResult<()> {
+        let result = self.inner.abort_endpoint(endpoint_address);
+        self.record_unit(Operation::AbortEndpoint(endpoint_address), &result);
+        result
+    }
+
+    fn clear_stall(&self, endpoint_address: u8) -> UsbResult<()> {
+        let result = self.inner.clear_stall(endpoint_address);
+        self.record_unit(Operation::ClearStall(endpoint_address), &result);
+        result
+    }
+
+    fn set_endpoint_policy(&self, endpoint_address: u8, policy: &EndpointPolicy) -> UsbResult<()> {
+        self.inner.set_endpoint_policy(endpoint_address, policy)
+    }
+
+    fn set_alternate_setting(&mut self, interface: u8, setting: u8) -> UsbResult<()> {
+        let result = self.inner.set_alternate_setting(interface, setting);
+        self.record_unit(
+            Operation::SetAlternateSetting { interface, setting },
+            &result,
+        );
+        result
+    }
+
+    fn current_bus_frame(&self) -> UsbResult<(u64, SystemTime)> {
+        self.inner.current_bus_frame()
+    }
+
+    fn control_read(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let result =
+            self.inner
+                .control_read(request_type, request_number, value, index, target, timeout);
+
+        let operation = Operation::ControlRead {
+            request_type,
+            request_number,
+            value,
+            index,
+            length: target.len(),
+        };
+        self.record(
+            operation,
+            result.clone().map(|length| target[..length].to_vec()),
+        );
+
+        result
+    }
+
+    fn control_read_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: ReadBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let log = Arc::clone(&self.log);
+        let buffer = Arc::clone(&target);
+        let callback = Box::new(move |result: UsbResult<usize>| {
+            // The buffer's only ours to look at once the transfer's done; so that's when we
+            // find out how much was asked for, as well as what came back.
+            let (operation, data) = {
+                let mut buffer = buffer.write();
+                let buffer = buffer.as_mut();
+                let operation = Operation::ControlRead {
+                    request_type,
+                    request_number,
+                    value,
+                    index,
+                    length: buffer.len(),
+                };
+                let data = result.clone().map(|length| buffer[..length].to_vec());
+                (operation, data)
+            };
+            append_record(
+                &log,
+                Record {
+                    operation,
+                    result: data,
+                },
+            );
+            callback(result)
+        });
+
+        self.inner.control_read_nonblocking(
+            request_type,
+            request_number,
+            value,
+            index,
+            target,
+            callback,
+            timeout,
+        )
+    }
+
+    fn control_write(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let result =
+            self.inner
+                .control_write(request_type, request_number, value, index, data, timeout);
+
+        let operation = Operation::ControlWrite {
+            request_type,
+            request_number,
+            value,
+            index,
+            data: data.to_vec(),
+        };
+        self.record_unit(operation, &result);
+
+        result
+    }
+
+    fn control_write_nonblocking(
+        &self,
+        request_type: u8,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let operation = Operation::ControlWrite {
+            request_type,
+            request_number,
+            value,
+            index,
+            data: data.as_ref().as_ref().to_vec(),
+        };
+
+        let log = Arc::clone(&self.log);
+        let callback = Box::new(move |result: UsbResult<usize>| {
+            append_record(
+                &log,
+                Record {
+                    operation,
+                    result: result.clone().map(|_| vec![]),
+                },
+            );
+            callback(result)
+        });
+
+        self.inner.control_write_nonblocking(
+            request_type,
+            request_number,
+            value,
+            index,
+            data,
+            callback,
+            timeout,
+        )
+    }
+
+    fn read(&self, endpoint: u8, buffer: &mut [u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        let result = self.inner.read(endpoint, buffer, timeout);
+
+        let operation = Operation::Read {
+            endpoint,
+            length: buffer.len(),
+        };
+        self.record(
+            operation,
+            result.clone().map(|length| buffer[..length].to_vec()),
+        );
+
+        result
+    }
+
+    fn write(&self, endpoint: u8, data: &[u8], timeout: Option<Duration>) -> UsbResult<usize> {
+        let result = self.inner.write(endpoint, data, timeout);
+
+        let operation = Operation::Write {
+            endpoint,
+            data: data.to_vec(),
+        };
+        self.record(operation, result.clone().map(|_| vec![]));
+
+        result
+    }
+
+    fn write_with_options(
+        &self,
+        endpoint: u8,
+        data: &[u8],
+        options: &WriteOptions,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let result = self
+            .inner
+            .write_with_options(endpoint, data, options, timeout);
+
+        let operation = if options.append_zlp {
+            Operation::WriteWithZlp {
+                endpoint,
+                data: data.to_vec(),
+            }
+        } else {
+            Operation::Write {
+                endpoint,
+                data: data.to_vec(),
+            }
+        };
+        self.record(operation, result.clone().map(|_| vec![]));
+
+        result
+    }
+
+    fn allocate_transfer_memory(
+        &self,
+        endpoint: u8,
+        length: usize,
+    ) -> UsbResult<Box<dyn TransferMemory>> {
+        self.inner.allocate_transfer_memory(endpoint, length)
+    }
+
+    fn alloc_streams(&self, endpoints: &[u8], count: u32) -> UsbResult<u32> {
+        let result = self.inner.alloc_streams(endpoints, count);
+        let operation = Operation::AllocStreams {
+            endpoints: endpoints.to_vec(),
+            count,
+        };
+        self.record_u32(operation, &result);
+        result
+    }
+
+    fn free_streams(&self, endpoints: &[u8]) -> UsbResult<()> {
+        let result = self.inner.free_streams(endpoints);
+        self.record_unit(Operation::FreeStreams(endpoints.to_vec()), &result);
+        result
+    }
+
+    fn read_stream(
+        &self,
+        endpoint: u8,
+        stream_id: u32,
+        buffer: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let result = self.inner.read_stream(endpoint, stream_id, buffer, timeout);
+
+        let operation = Operation::ReadStream {
+            endpoint,
+            stream_id,
+            length: buffer.len(),
+        };
+        self.record(
+            operation,
+            result.clone().map(|length| buffer[..length].to_vec()),
+        );
+
+        result
+    }
+
+    fn write_stream(
+        &self,
+        endpoint: u8,
+        stream_id: u32,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let result = self.inner.write_stream(endpoint, stream_id, data, timeout);
+
+        let operation = Operation::WriteStream {
+            endpoint,
+            stream_id,
+            data: data.to_vec(),
+        };
+        self.record(operation, result.clone().map(|_| vec![]));
+
+        result
+    }
+
+    fn read_with_timeouts(
+        &self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        timeout: TransferTimeout,
+    ) -> UsbResult<usize> {
+        let result = self.inner.read_with_timeouts(endpoint, buffer, timeout);
+
+        let operation = Operation::Read {
+            endpoint,
+            length: buffer.len(),
+        };
+        self.record(
+            operation,
+            result.clone().map(|length| buffer[..length].to_vec()),
+        );
+
+        result
+    }
+
+    fn write_with_timeouts(
+        &self,
+        endpoint: u8,
+        data: &[u8],
+        timeout: TransferTimeout,
+    ) -> UsbResult<usize> {
+        let result = self.inner.write_with_timeouts(endpoint, data, timeout);
+
+        let operation = Operation::Write {
+            endpoint,
+            data: data.to_vec(),
+        };
+        self.record(operation, result.clone().map(|_| vec![]));
+
+        result
+    }
+
+    fn read_nonblocking(
+        &self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let log = Arc::clone(&self.log);
+        let target = Arc::clone(&buffer);
+        let callback = Box::new(move |result: UsbResult<usize>| {
+            // As with control reads, we only look at the buffer once the read's done with it.
+            let (operation, data) = {
+                let mut target = target.write();
+                let target = target.as_mut();
+                let operation = Operation::Read {
+                    endpoint,
+                    length: target.len(),
+                };
+                let data = result.clone().map(|length| target[..length].to_vec());
+                (operation, data)
+            };
+            append_record(
+                &log,
+                Record {
+                    operation,
+                    result: data,
+                },
+            );
+            callback(result)
+        });
+
+        self.inner
+            .read_nonblocking(endpoint, buffer, callback, timeout)
+    }
+
+    fn write_nonblocking(
+        &self,
+        endpoint: u8,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let operation = Operation::Write {
+            endpoint,
+            data: data.as_ref().as_ref().to_vec(),
+        };
+
+        let log = Arc::clone(&self.log);
+        let callback = Box::new(move |result: UsbResult<usize>| {
+            append_record(
+                &log,
+                Record {
+                    operation,
+                    result: result.clone().map(|_| vec![]),
+                },
+            );
+            callback(result)
+        });
+
+        self.inner
+            .write_nonblocking(endpoint, data, callback, timeout)
+    }
+
+    // Isochronous transfers depend on bus timing, which a replay can't reproduce; so we
+    // pass them through without recording them.
+    #[cfg(feature = "async")]
+    fn read_isochronous_nonblocking(
+        &self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        packet_lengths: &[usize],
+        options: &IsoOptions,
+        callback: IsoCallback,
+    ) -> UsbResult<()> {
+        self.inner
+            .read_isochronous_nonblocking(endpoint, buffer, packet_lengths, options, callback)
+    }
+
+    #[cfg(feature = "async")]
+    fn write_isochronous_nonblocking(
+        &self,
+        endpoint: u8,
+        data: WriteBuffer,
+        packet_lengths: &[usize],
+        options: &IsoOptions,
+        callback: IsoCallback,
+    ) -> UsbResult<()> {
+        self.inner
+            .write_isochronous_nonblocking(endpoint, data, packet_lengths, options, callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+
+    /// A device descriptor, as a device might answer GET_DESCRIPTOR(Device) with.
+    const DEVICE_DESCRIPTOR: [u8; 18] = [
+        0x12, 0x01, 0x00, 0x02, 0xef, 0x02, 0x01, 0x40, 0x50, 0x1d, 0x5c, 0x61, 0x04, 0x01, 0x01,
+        0x02, 0x03, 0x01,
+    ];
+
+    /// A session with a device, covering data, errors, and values; in the order [exercise]
+    /// performs them.
+    fn session() -> Vec<Record> {
+        let record = |operation, result| Record { operation, result };
+
+        vec![
+            record(
+                Operation::Open {
+                    vendor_id: 0x1d50,
+                    product_id: 0x615c,
+                },
+                Ok(vec![]),
+            ),
+            record(
+                Operation::ControlRead {
+                    request_type: 0x80,
+                    request_number: 6,
+                    value: 0x0100,
+                    index: 0,
+                    length: DEVICE_DESCRIPTOR.len(),
+                },
+                Ok(DEVICE_DESCRIPTOR.to_vec()),
+            ),
+            record(
+                Operation::Write {
+                    endpoint: 0x01,
+                    data: vec![0xde, 0xad, 0xbe, 0xef],
+                },
+                Ok(vec![]),
+            ),
+            record(
+                Operation::Read {
+                    endpoint: 0x81,
+                    length: 512,
+                },
+                Err(Error::TimedOut),
+            ),
+            record(Operation::ActiveConfiguration, Ok(vec![1])),
+        ]
+    }
+
+    /// Opens the backend's only device, and performs the operations in [session] on it;
+    /// returning what each of them produced.
+    fn exercise(backend: &dyn Backend) -> Vec<UsbResult<Vec<u8>>> {
+        let information = backend.get_devices().unwrap().remove(0);
+        let device = backend.open(&information).unwrap();
+
+        let mut descriptor = [0; DEVICE_DESCRIPTOR.len()];
+        let mut buffer = [0; 512];
+        vec![
+            device
+                .control_read(0x80, 6, 0x0100, 0, &mut descriptor, None)
+                .map(|length| descriptor[..length].to_vec()),
+            device
+                .write(0x01, &[0xde, 0xad, 0xbe, 0xef], None)
+                .map(|length| vec![length as u8]),
+            device
+                .read(0x81, &mut buffer, None)
+                .map(|length| buffer[..length].to_vec()),
+            device
+                .active_configuration()
+                .map(|configuration| vec![configuration]),
+        ]
+    }
+
+    #[test]
+    fn lines_round_trip() {
+        for record in session() {
+            assert_eq!(Record::parse_line(&record.to_line()), Ok(record.clone()));
+        }
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        for line in [
+            "",
+            "frobnicate 01",
+            "write 01 deadbeef",
+            "write 01 deadbee => ok",
+            "read 81 512 => err Frobnicated",
+            "read 81 => ok",
+        ] {
+            assert_eq!(
+                Record::parse_line(line),
+                Err(Error::InvalidArgument),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn recorded_sessions_replay() {
+        let path = std::env::temp_dir().join(format!("usrs-record-{}.log", std::process::id()));
+
+        // Record a session with a scripted device...
+        let scripted = Arc::new(MockBackend::new(session()));
+        let recording = RecordingBackend::new(Arc::clone(&scripted) as _, &path).unwrap();
+        let recorded = exercise(&recording);
+        assert_eq!(scripted.remaining(), 0);
+
+        // ... which should log exactly what happened...
+        assert_eq!(read_log(&path), Ok(session()));
+
+        // ... and play back identically.
+        let replayed = MockBackend::from_log(&path).unwrap();
+        assert_eq!(exercise(&replayed), recorded);
+        assert_eq!(replayed.remaining(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+//! Batched transfer submission; for high rates of small transfers, possibly across several
+//! endpoints. See [crate::device::Device::submit_batch].
+//!
+//! A batch is submitted in one call, and its completions are collected into a single queue;
+//! which can then be reaped in bulk, rather than waiting on each transfer in turn.
+
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{ReadBuffer, UsbResult, WriteBuffer};
+
+/// A single transfer in a batch.
+#[derive(Clone)]
+pub enum TransferRequest {
+    /// Reads from an IN endpoint, into the provided buffer.
+    Read {
+        endpoint: u8,
+        buffer: ReadBuffer,
+        timeout: Option<Duration>,
+    },
+
+    /// Writes the provided data to an OUT endpoint.
+    Write {
+        endpoint: u8,
+        data: WriteBuffer,
+        timeout: Option<Duration>,
+    },
+}
+
+impl TransferRequest {
+    /// Creates a request to read from the given endpoint, using the device's default timeout.
+    pub fn read(endpoint: u8, buffer: ReadBuffer) -> Self {
+        Self::Read {
+            endpoint,
+            buffer,
+            timeout: None,
+        }
+    }
+
+    /// Creates a request to write to the given endpoint, using the device's default timeout.
+    pub fn write(endpoint: u8, data: WriteBuffer) -> Self {
+        Self::Write {
+            endpoint,
+            data,
+            timeout: None,
+        }
+    }
+
+    /// Sets how long the transfer may take.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        match &mut self {
+            Self::Read { timeout: t, .. } | Self::Write { timeout: t, .. } => *t = Some(timeout),
+        }
+        self
+    }
+
+    /// Returns the address of the endpoint this request targets.
+    pub fn endpoint_address(&self) -> u8 {
+        match self {
+            Self::Read { endpoint, .. } => endpoint | 0x80,
+            Self::Write { endpoint, .. } => endpoint & 0x7f,
+        }
+    }
+}
+
+/// A completed transfer from a batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchCompletion {
+    /// The position of the transfer's request in the submitted batch.
+    pub index: usize,
+
+    /// The address of the endpoint the transfer targeted.
+    pub endpoint: u8,
+
+    /// The number of bytes transferred; or why the transfer failed.
+    pub result: UsbResult<usize>,
+}
+
+/// Completion state shared between a batch and the backend performing its transfers.
+#[derive(Debug, Default)]
+struct BatchState {
+    /// Transfers that have completed, but haven't yet been reaped.
+    completed: Vec<BatchCompletion>,
+
+    /// The number of transfers that haven't yet completed.
+    outstanding: usize,
+}
+
+/// Collects the completions of a submitted batch; see [crate::device::Device::submit_batch].
+///
+/// Dropping the handle doesn't cancel anything; the transfers complete regardless.
+#[derive(Debug, Clone, Default)]
+pub struct BatchHandle {
+    /// Our completion state; and a condvar, signaled on each completion.
+    shared: Arc<(Mutex<BatchState>, Condvar)>,
+}
+
+impl BatchHandle {
+    /// Creates a handle expecting the given number of completions.
+    pub(crate) fn new(outstanding: usize) -> Self {
+        let handle = Self::default();
+        handle.state().outstanding = outstanding;
+        handle
+    }
+
+    /// Returns a callback that records the completion of the transfer at the given index.
+    pub(crate) fn completer(
+        &self,
+        index: usize,
+        endpoint: u8,
+    ) -> impl FnOnce(UsbResult<usize>) + Send + 'static {
+        let handle = self.clone();
+        move |result| {
+            handle.complete(BatchCompletion {
+                index,
+                endpoint,
+                result,
+            })
+        }
+    }
+
+    /// Records a completion, and wakes anyone waiting on the batch.
+    pub(crate) fn complete(&self, completion: BatchCompletion) {
+        let mut state = self.state();
+        state.outstanding = state.outstanding.saturating_sub(1);
+        state.completed.push(completion);
+        self.shared.1.notify_all();
+    }
+
+    /// Returns the number of transfers that haven't yet completed.
+    pub fn outstanding(&self) -> usize {
+        self.state().outstanding
+    }
+
+    /// Returns true iff every transfer in the batch has completed.
+    pub fn is_complete(&self) -> bool {
+        self.outstanding() == 0
+    }
+
+    /// Returns every completion that's arrived since the last reap, in the order they arrived;
+    /// without waiting for any more.
+    pub fn reap(&self) -> Vec<BatchCompletion> {
+        std::mem::take(&mut self.state().completed)
+    }
+
+    /// Waits until at least `count` completions are waiting to be reaped (or every transfer
+    /// has completed), then reaps them all. If the timeout passes first, reaps whatever's
+    /// arrived so far.
+    pub fn wait(&self, count: usize, timeout: Option<Duration>) -> Vec<BatchCompletion> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state();
+
+        while state.completed.len() < count && state.outstanding > 0 {
+            state = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    self.shared.1.wait_timeout(state, remaining).unwrap().0
+                }
+                None => self.shared.1.wait(state).unwrap(),
+            };
+        }
+
+        std::mem::take(&mut state.completed)
+    }
+
+    /// Waits until every transfer in the batch has completed, then reaps them all.
+    pub fn wait_all(&self) -> Vec<BatchCompletion> {
+        self.wait(usize::MAX, None)
+    }
+
+    /// Locks our shared state.
+    fn state(&self) -> MutexGuard<'_, BatchState> {
+        self.shared.0.lock().unwrap()
+    }
+}
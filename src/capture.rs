@@ -0,0 +1,293 @@
+//! Wire-level capture of the transfers a device performs, as pcap files that Wireshark can open.
+//!
+//! Captures use the Linux usbmon link type; so each transfer appears as a submission ('S')
+//! event, followed by a completion ('C') event carrying its result. Isochronous transfers are
+//! recorded as a single block of data, without usbmon's per-packet descriptors.
+//! See [crate::device::Device::start_capture].
+
+use std::{
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    descriptors::{EndpointDescriptor, TransferType},
+    device::DeviceInformation,
+    error::io_error,
+    Error, ReadBuffer, UsbResult,
+};
+
+/// The pcap link type for usbmon captures that include the full 64-byte header.
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+
+/// The most data we'll store for any one event.
+const SNAPSHOT_LENGTH: u32 = 0x40000;
+
+/// The bus number we report for devices whose bus the backend can't tell us.
+const FALLBACK_BUS_NUMBER: u16 = 1;
+
+/// The highest address a device can have on a USB bus.
+const MAX_DEVICE_ADDRESS: u32 = 127;
+
+/// A pcap file that one or more devices can record their transfers into.
+pub struct PcapCapture {
+    /// Where the capture is being written.
+    writer: Mutex<Box<dyn Write + Send>>,
+
+    /// The ID to give the next transfer we see; which lets Wireshark pair up events.
+    next_urb_id: AtomicU64,
+
+    /// How many devices have started capturing without an address of their own; which we
+    /// number in turn, from 1 to 127.
+    unaddressed_devices: AtomicU32,
+}
+
+impl std::fmt::Debug for PcapCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PcapCapture").finish_non_exhaustive()
+    }
+}
+
+impl PcapCapture {
+    /// Creates a capture file at the given path; replacing any file that's already there.
+    pub fn create(path: impl AsRef<Path>) -> UsbResult<Self> {
+        Self::new(File::create(path).map_err(io_error)?)
+    }
+
+    /// Creates a capture that's written to the provided writer.
+    pub fn new(mut writer: impl Write + Send + 'static) -> UsbResult<Self> {
+        // Write the pcap global header: magic, version 2.4, no timezone offset, no accuracy
+        // information, our snapshot length, and the link type.
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&SNAPSHOT_LENGTH.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_USB_LINUX_MMAPPED.to_le_bytes());
+        writer.write_all(&header).map_err(io_error)?;
+
+        Ok(Self {
+            writer: Mutex::new(Box::new(writer)),
+            next_urb_id: AtomicU64::new(1),
+            unaddressed_devices: AtomicU32::new(0),
+        })
+    }
+
+    /// Writes a single usbmon event to the capture.
+    fn write_event(&self, urb: &CapturedUrb, event: u8, status: i32, data: &[u8], length: usize) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let data = &data[..data.len().min(length).min(SNAPSHOT_LENGTH as usize - 64)];
+
+        // Setup packets only appear on control submissions; and usbmon marks data as present
+        // with a zero byte, or absent with the direction it would have travelled.
+        let (setup_flag, setup) = match (event, urb.setup) {
+            (b'S', Some(setup)) => (0, setup),
+            _ => (b'-', [0; 8]),
+        };
+        let data_flag = match (data.is_empty(), urb.endpoint & 0x80 != 0) {
+            (false, _) => 0,
+            (true, true) => b'<',
+            (true, false) => b'>',
+        };
+
+        // Build the 64-byte usbmon header...
+        let mut packet = Vec::with_capacity(64 + data.len());
+        packet.extend_from_slice(&urb.id.to_le_bytes());
+        packet.extend_from_slice(&[event, urb.transfer_type, urb.endpoint, urb.device_number]);
+        packet.extend_from_slice(&urb.bus_number.to_le_bytes());
+        packet.extend_from_slice(&[setup_flag, data_flag]);
+        packet.extend_from_slice(&(timestamp.as_secs() as i64).to_le_bytes());
+        packet.extend_from_slice(&(timestamp.subsec_micros() as i32).to_le_bytes());
+        packet.extend_from_slice(&status.to_le_bytes());
+        packet.extend_from_slice(&(length as u32).to_le_bytes());
+        packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&setup);
+        packet.extend_from_slice(&[0; 16]);
+
+        // ... follow it with the data ...
+        packet.extend_from_slice(data);
+
+        // ... and wrap the whole thing in a pcap record.
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend_from_slice(&(timestamp.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&timestamp.subsec_micros().to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&packet);
+
+        // A capture that can't be written shouldn't break the transfers it's watching;
+        // so we'll just drop the event.
+        let _ = self.writer.lock().unwrap().write_all(&record);
+    }
+}
+
+/// A single device's view of a capture.
+#[derive(Debug)]
+pub(crate) struct DeviceCapture {
+    /// The capture we're recording into.
+    capture: Arc<PcapCapture>,
+
+    /// The number of the bus our device is on.
+    bus_number: u16,
+
+    /// The capture's number for our device; its address, where we know it.
+    device_number: u8,
+
+    /// The address and usbmon transfer type of each of our device's endpoints.
+    endpoints: Vec<(u8, u8)>,
+}
+
+impl DeviceCapture {
+    /// Adds a device with the provided endpoints to a capture; recording it under the bus and
+    /// address in its information, where it has them.
+    pub(crate) fn new<'a>(
+        capture: Arc<PcapCapture>,
+        endpoints: impl Iterator<Item = &'a EndpointDescriptor>,
+        information: Option<&DeviceInformation>,
+    ) -> Self {
+        let endpoints = endpoints
+            .map(|endpoint| {
+                let transfer_type = match endpoint.transfer_type() {
+                    TransferType::Isochronous => 0,
+                    TransferType::Interrupt => 1,
+                    TransferType::Control => 2,
+                    TransferType::Bulk => 3,
+                };
+                (endpoint.address, transfer_type)
+            })
+            .collect();
+
+        let bus_number = information
+            .and_then(|information| information.port_path())
+            .map_or(FALLBACK_BUS_NUMBER, |path| path.bus() as u16);
+        let device_number = information
+            .and_then(|information| information.device_address())
+            .unwrap_or_else(|| {
+                let index = capture.unaddressed_devices.fetch_add(1, Ordering::Relaxed);
+                (index % MAX_DEVICE_ADDRESS + 1) as u8
+            });
+
+        Self {
+            capture,
+            bus_number,
+            device_number,
+            endpoints,
+        }
+    }
+
+    /// Records the submission of a transfer of `length` bytes; with its `data`, for OUT
+    /// transfers, and its setup packet, for control transfers.
+    pub(crate) fn submit(
+        &self,
+        endpoint: u8,
+        setup: Option<[u8; 8]>,
+        length: usize,
+        data: &[u8],
+    ) -> CapturedUrb {
+        // Anything that isn't a control transfer, on an endpoint we don't know, is most likely bulk.
+        let transfer_type = match setup {
+            Some(_) => 2,
+            None => self
+                .endpoints
+                .iter()
+                .find(|(address, _)| *address == endpoint)
+                .map_or(3, |(_, transfer_type)| *transfer_type),
+        };
+
+        let urb = CapturedUrb {
+            capture: Arc::clone(&self.capture),
+            id: self.capture.next_urb_id.fetch_add(1, Ordering::Relaxed),
+            transfer_type,
+            endpoint,
+            bus_number: self.bus_number,
+            device_number: self.device_number,
+            setup,
+            read_buffer: None,
+        };
+
+        // usbmon reports transfers that are still in flight as -EINPROGRESS.
+        self.capture.write_event(&urb, b'S', -115, data, length);
+        urb
+    }
+}
+
+/// A transfer that's been submitted, and recorded into a capture.
+pub(crate) struct CapturedUrb {
+    /// The capture we're recording into.
+    capture: Arc<PcapCapture>,
+
+    /// The ID that pairs our submission and completion events.
+    id: u64,
+
+    /// The usbmon transfer type of the transfer.
+    transfer_type: u8,
+
+    /// The address of the endpoint the transfer targets; 0x80 for IN control transfers.
+    endpoint: u8,
+
+    /// The number of the bus the device performing the transfer is on.
+    bus_number: u16,
+
+    /// The capture's number for the device performing the transfer.
+    device_number: u8,
+
+    /// The transfer's setup packet, for control transfers.
+    setup: Option<[u8; 8]>,
+
+    /// For asynchronous reads, the buffer the data will land in.
+    read_buffer: Option<ReadBuffer>,
+}
+
+impl CapturedUrb {
+    /// Notes the buffer an asynchronous read's data will land in; so we can capture it.
+    #[cfg(any(feature = "async", feature = "callbacks"))]
+    pub(crate) fn with_read_buffer(mut self, buffer: ReadBuffer) -> Self {
+        self.read_buffer = Some(buffer);
+        self
+    }
+
+    /// Records the completion of the transfer; with the buffer its data landed in, for IN
+    /// transfers.
+    pub(crate) fn complete(self, result: Result<usize, &Error>, data: &[u8]) {
+        let (status, length) = match result {
+            Ok(length) => (0, length),
+            Err(error) => (usbmon_status(error), 0),
+        };
+
+        // If we're holding onto an asynchronous read's buffer, that's where our data is.
+        match &self.read_buffer {
+            Some(buffer) => {
+                let mut buffer = buffer.write();
+                let data = buffer.as_mut();
+                self.capture.write_event(&self, b'C', status, data, length);
+            }
+            None => self.capture.write_event(&self, b'C', status, data, length),
+        }
+    }
+}
+
+/// Converts an error into the (negative, Linux) errno usbmon would report for it.
+fn usbmon_status(error: &Error) -> i32 {
+    match error {
+        Error::Stalled => -32,
+        Error::TimedOut | Error::PartialTransfer(_) => -110,
+        Error::Aborted => -104,
+        Error::Disconnected | Error::DeviceNotOpen => -19,
+        Error::Overrun | Error::Babble => -75,
+        Error::ProtocolError => -71,
+        Error::Underrun => -63,
+        Error::NotResponding => -62,
+        Error::InvalidArgument | Error::InvalidEndpoint | Error::InvalidInterface => -22,
+        _ => -5,
+    }
+}
@@ -0,0 +1,71 @@
+//! Delivers transfer completions to channels, rather than closures; so callback-model code can
+//! handle them in whatever event loop it already has. See e.g. [crate::device::Device::read_to_channel].
+//!
+//! Channels are used through [CompletionSender]; which is implemented for std's senders, and
+//! for crossbeam's and flume's behind the `crossbeam` and `flume` features. Any sender whose
+//! messages can be built `From` our events works; so completions can share a channel with the
+//! rest of an application's events.
+
+use std::sync::mpsc;
+
+use crate::{ReadBuffer, UsbResult, WriteBuffer};
+
+/// A completed read; see [crate::device::Device::read_to_channel].
+pub struct ReadEvent {
+    /// The address of the endpoint that was read.
+    pub endpoint: u8,
+
+    /// The buffer the data was read into.
+    pub buffer: ReadBuffer,
+
+    /// The number of bytes read, which are at the start of the buffer; or why the read failed.
+    pub result: UsbResult<usize>,
+}
+
+/// A completed write; see [crate::device::Device::write_to_channel].
+pub struct WriteEvent {
+    /// The address of the endpoint that was written.
+    pub endpoint: u8,
+
+    /// The data that was written.
+    pub buffer: WriteBuffer,
+
+    /// The number of bytes written; or why the write failed.
+    pub result: UsbResult<usize>,
+}
+
+/// Something that completion events can be sent to; typically a channel's sending half.
+///
+/// Events are sent from wherever the completion runs; usually the backend's event thread, or
+/// the device's completion executor. Senders that can block (e.g. full bounded channels) will
+/// hold up other completions while they do. Events sent after the receiver is gone are dropped.
+pub trait CompletionSender<T>: Send + 'static {
+    /// Sends a completion event.
+    fn send_completion(&self, event: T);
+}
+
+impl<T, M: From<T> + Send + 'static> CompletionSender<T> for mpsc::Sender<M> {
+    fn send_completion(&self, event: T) {
+        let _ = self.send(event.into());
+    }
+}
+
+impl<T, M: From<T> + Send + 'static> CompletionSender<T> for mpsc::SyncSender<M> {
+    fn send_completion(&self, event: T) {
+        let _ = self.send(event.into());
+    }
+}
+
+#[cfg(feature = "crossbeam")]
+impl<T, M: From<T> + Send + 'static> CompletionSender<T> for crossbeam_channel::Sender<M> {
+    fn send_completion(&self, event: T) {
+        let _ = self.send(event.into());
+    }
+}
+
+#[cfg(feature = "flume")]
+impl<T, M: From<T> + Send + 'static> CompletionSender<T> for flume::Sender<M> {
+    fn send_completion(&self, event: T) {
+        let _ = self.send(event.into());
+    }
+}
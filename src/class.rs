@@ -0,0 +1,10 @@
+//! Helpers for working with devices that implement standard USB classes.
+//!
+//! These are layered on top of the normal [crate::Device] API; so they'll work on
+//! any backend that supports the transfers the relevant class needs.
+
+pub mod ccid;
+pub mod hub;
+pub mod midi;
+pub mod uac;
+pub mod uvc;
@@ -0,0 +1,614 @@
+//! Helpers for working with CCID (smart card reader) class devices.
+//!
+//! CCID readers are driven entirely by bulk messages: the host sends a "PC_to_RDR" command
+//! on the bulk OUT endpoint, and the reader answers with a "RDR_to_PC" response on the bulk IN
+//! endpoint. Card insertion and removal are reported on an (optional) interrupt IN endpoint.
+
+use std::time::Duration;
+
+use crate::{
+    descriptors::{InterfaceDescriptor, TransferType},
+    device::Device,
+    request::{ClassCode, Direction},
+    Error, UsbResult,
+};
+
+/// The USB class code assigned to smart card readers.
+pub const CCID_CLASS: u8 = ClassCode::SmartCard as u8;
+
+/// The descriptor type of the CCID class descriptor, which follows the interface descriptor.
+const CCID_CLASS_DESCRIPTOR: u8 = 0x21;
+
+/// The size of the header that starts every CCID bulk message.
+const HEADER_LENGTH: usize = 10;
+
+/// The largest message we'll assume a reader supports if it doesn't tell us otherwise;
+/// this is enough for a short APDU, plus change.
+const DEFAULT_MAX_MESSAGE_LENGTH: usize = 271;
+
+/// The largest message the CCID specification allows a reader to support; a header, plus
+/// the largest possible extended APDU.
+const MAX_MESSAGE_LENGTH: usize = 65544;
+
+/// Message types for commands sent from the host to the reader.
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum CommandType {
+    IccPowerOn = 0x62,
+    IccPowerOff = 0x63,
+    GetSlotStatus = 0x65,
+    XfrBlock = 0x6F,
+}
+
+/// Message types for responses sent from the reader to the host.
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum ResponseType {
+    DataBlock = 0x80,
+    SlotStatus = 0x81,
+    Parameters = 0x82,
+    Escape = 0x83,
+    DataRateAndClockFrequency = 0x84,
+}
+
+/// The voltage requested when powering on a card.
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum Voltage {
+    Automatic = 0,
+    FiveVolts = 1,
+    ThreeVolts = 2,
+    OnePointEightVolts = 3,
+}
+
+/// The state of the card in a slot, as reported in a response's bStatus.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum IccStatus {
+    /// A card is present, and active (powered on).
+    PresentAndActive,
+
+    /// A card is present, but inactive (not powered on).
+    PresentAndInactive,
+
+    /// No card is present in the slot.
+    NotPresent,
+}
+
+/// The outcome of a command, as reported in a response's bStatus.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum CommandStatus {
+    /// The command completed successfully.
+    Succeeded,
+
+    /// The command failed; the response's error field says why.
+    Failed,
+
+    /// The reader needs more time; another response will follow.
+    TimeExtensionRequested,
+}
+
+/// A response received from the reader on its bulk IN endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// The type of message the reader sent.
+    pub message_type: u8,
+
+    /// The slot the response refers to.
+    pub slot: u8,
+
+    /// The sequence number of the command this responds to.
+    pub sequence: u8,
+
+    /// The state of the card in the slot.
+    pub icc_status: IccStatus,
+
+    /// Whether the command succeeded.
+    pub command_status: CommandStatus,
+
+    /// The slot error register (bError); meaningful only if the command failed.
+    pub error: u8,
+
+    /// The final message-specific header byte; e.g. bChainParameter or bClockStatus.
+    pub specific: u8,
+
+    /// The response's data; e.g. the card's ATR, or the response to an APDU.
+    pub data: Vec<u8>,
+}
+
+impl Response {
+    /// Parses a response from a complete bulk message.
+    pub fn parse(message: &[u8]) -> UsbResult<Self> {
+        if message.len() < HEADER_LENGTH {
+            return Err(Error::InvalidArgument);
+        }
+
+        let length = u32::from_le_bytes([message[1], message[2], message[3], message[4]]) as usize;
+        let data = message
+            .get(HEADER_LENGTH..HEADER_LENGTH + length)
+            .ok_or(Error::InvalidArgument)?;
+
+        let status = message[7];
+        let icc_status = match status & 0b11 {
+            0 => IccStatus::PresentAndActive,
+            1 => IccStatus::PresentAndInactive,
+            _ => IccStatus::NotPresent,
+        };
+        let command_status = match (status >> 6) & 0b11 {
+            0 => CommandStatus::Succeeded,
+            1 => CommandStatus::Failed,
+            _ => CommandStatus::TimeExtensionRequested,
+        };
+
+        Ok(Self {
+            message_type: message[0],
+            slot: message[5],
+            sequence: message[6],
+            icc_status,
+            command_status,
+            error: message[8],
+            specific: message[9],
+            data: data.to_vec(),
+        })
+    }
+
+    /// Returns true iff the command this responds to succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.command_status == CommandStatus::Succeeded
+    }
+}
+
+/// A notification received from the reader on its interrupt IN endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    /// One or more slots have had a card inserted or removed.
+    SlotChange(Vec<SlotChange>),
+
+    /// The reader encountered a hardware error while handling a command.
+    HardwareError { slot: u8, sequence: u8, code: u8 },
+}
+
+/// The state of a single slot, as reported in a slot change notification.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct SlotChange {
+    /// The slot this state refers to.
+    pub slot: u8,
+
+    /// True iff a card is currently present in the slot.
+    pub card_present: bool,
+
+    /// True iff the slot's state has changed since the last notification.
+    pub changed: bool,
+}
+
+impl Notification {
+    /// Message type for RDR_to_PC_NotifySlotChange.
+    const NOTIFY_SLOT_CHANGE: u8 = 0x50;
+
+    /// Message type for RDR_to_PC_HardwareError.
+    const HARDWARE_ERROR: u8 = 0x51;
+
+    /// Parses a notification from an interrupt IN packet.
+    pub fn parse(packet: &[u8]) -> UsbResult<Self> {
+        match packet.first() {
+            Some(&Self::NOTIFY_SLOT_CHANGE) => {
+                // Each slot gets two bits: one for presence, and one for "changed".
+                let mut slots = vec![];
+                for (byte_index, byte) in packet[1..].iter().enumerate() {
+                    for slot_in_byte in 0..4 {
+                        let bits = byte >> (slot_in_byte * 2);
+                        slots.push(SlotChange {
+                            slot: (byte_index * 4 + slot_in_byte) as u8,
+                            card_present: (bits & 0b01) != 0,
+                            changed: (bits & 0b10) != 0,
+                        });
+                    }
+                }
+
+                Ok(Notification::SlotChange(slots))
+            }
+            Some(&Self::HARDWARE_ERROR) if packet.len() >= 4 => Ok(Notification::HardwareError {
+                slot: packet[1],
+                sequence: packet[2],
+                code: packet[3],
+            }),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// A smart card reader, driven via the CCID bulk protocol.
+#[derive(Debug)]
+pub struct CcidReader {
+    /// The device we're driving.
+    device: Device,
+
+    /// The interface number of the CCID interface; which we hold claimed.
+    interface: u8,
+
+    /// The endpoint address of the bulk IN endpoint, on which responses arrive.
+    bulk_in: u8,
+
+    /// The endpoint address of the bulk OUT endpoint, on which we send commands.
+    bulk_out: u8,
+
+    /// The endpoint address of the interrupt IN endpoint, if the reader has one.
+    interrupt_in: Option<u8>,
+
+    /// The largest message the reader is willing to send or accept.
+    max_message_length: usize,
+
+    /// The highest slot index the reader supports.
+    max_slot_index: u8,
+
+    /// The sequence number to be used for our next command.
+    sequence: u8,
+
+    /// The timeout applied to each individual transfer.
+    timeout: Option<Duration>,
+}
+
+impl CcidReader {
+    /// Creates a CCID reader from an opened device, using its first CCID interface.
+    pub fn new(mut device: Device) -> UsbResult<Self> {
+        let configuration = device.read_active_configuration_descriptor()?;
+        let interface = configuration
+            .interfaces
+            .iter()
+            .find(|interface| {
+                interface.interface_class == CCID_CLASS && interface.alternate_setting == 0
+            })
+            .ok_or(Error::InvalidInterface)?
+            .clone();
+
+        Self::from_interface_descriptor(device, &interface)
+    }
+
+    /// Creates a CCID reader from an opened device, using the provided interface number.
+    pub fn from_interface(mut device: Device, interface_number: u8) -> UsbResult<Self> {
+        let interface = device
+            .read_active_configuration_descriptor()?
+            .interface(interface_number, 0)
+            .ok_or(Error::InvalidInterface)?
+            .clone();
+
+        Self::from_interface_descriptor(device, &interface)
+    }
+
+    /// Creates a CCID reader given the descriptor for its CCID interface.
+    fn from_interface_descriptor(
+        mut device: Device,
+        interface: &InterfaceDescriptor,
+    ) -> UsbResult<Self> {
+        // Find each of the endpoints we'll be talking to...
+        let bulk_in = interface
+            .find_endpoint(TransferType::Bulk, Direction::In)
+            .ok_or(Error::InvalidEndpoint)?
+            .address;
+        let bulk_out = interface
+            .find_endpoint(TransferType::Bulk, Direction::Out)
+            .ok_or(Error::InvalidEndpoint)?
+            .address;
+        let interrupt_in = interface
+            .find_endpoint(TransferType::Interrupt, Direction::In)
+            .map(|endpoint| endpoint.address);
+
+        // ... grab what we need from the class descriptor, if the reader provides one...
+        let mut max_message_length = DEFAULT_MAX_MESSAGE_LENGTH;
+        let mut max_slot_index = 0;
+        if let Some(class_descriptor) = interface
+            .extra
+            .iter()
+            .find(|descriptor| descriptor[1] == CCID_CLASS_DESCRIPTOR && descriptor.len() >= 48)
+        {
+            max_slot_index = class_descriptor[4];
+            max_message_length = checked_max_message_length(u32::from_le_bytes([
+                class_descriptor[44],
+                class_descriptor[45],
+                class_descriptor[46],
+                class_descriptor[47],
+            ]));
+        }
+
+        // ... and claim the interface, so we can talk to it.
+        device.claim_interface(interface.interface_number)?;
+
+        Ok(Self {
+            device,
+            interface: interface.interface_number,
+            bulk_in,
+            bulk_out,
+            interrupt_in,
+            max_message_length,
+            max_slot_index,
+            sequence: 0,
+            timeout: None,
+        })
+    }
+
+    /// Sets the timeout applied to each transfer; or None to wait indefinitely.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the highest slot index the reader supports.
+    pub fn max_slot_index(&self) -> u8 {
+        self.max_slot_index
+    }
+
+    /// Returns true iff the reader can notify us of card insertion and removal.
+    pub fn has_notifications(&self) -> bool {
+        self.interrupt_in.is_some()
+    }
+
+    /// Provides access to the underlying device.
+    pub fn device(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    /// Releases the CCID interface, and returns the underlying device.
+    pub fn into_device(mut self) -> UsbResult<Device> {
+        self.device.unclaim_interface(self.interface)?;
+        Ok(self.device)
+    }
+
+    /// Powers on the card in the given slot; returning its Answer To Reset in the response data.
+    pub fn power_on(&mut self, slot: u8, voltage: Voltage) -> UsbResult<Response> {
+        self.command(CommandType::IccPowerOn, slot, [voltage as u8, 0, 0], &[])
+    }
+
+    /// Powers off the card in the given slot.
+    pub fn power_off(&mut self, slot: u8) -> UsbResult<Response> {
+        self.command(CommandType::IccPowerOff, slot, [0, 0, 0], &[])
+    }
+
+    /// Fetches the status of the given slot.
+    pub fn slot_status(&mut self, slot: u8) -> UsbResult<Response> {
+        self.command(CommandType::GetSlotStatus, slot, [0, 0, 0], &[])
+    }
+
+    /// Sends a block of data (typically an APDU) to the card in the given slot, and returns
+    /// the card's reply in the response data.
+    pub fn transfer_block(&mut self, slot: u8, data: &[u8]) -> UsbResult<Response> {
+        self.command(CommandType::XfrBlock, slot, [0, 0, 0], data)
+    }
+
+    /// Waits for the next notification on the reader's interrupt endpoint.
+    ///
+    /// Returns [Error::Unsupported] if the reader doesn't have an interrupt endpoint.
+    pub fn read_notification(&mut self, timeout: Option<Duration>) -> UsbResult<Notification> {
+        let endpoint = self.interrupt_in.ok_or(Error::Unsupported)?;
+
+        // Slot change notifications are one byte, plus two bits per slot.
+        let max_length = 1 + (self.max_slot_index as usize / 4) + 1;
+        let packet = self
+            .device
+            .read_to_vec(endpoint, max_length.max(4), timeout)?;
+
+        Notification::parse(&packet)
+    }
+
+    /// Issues a command to the reader, and waits for its final response.
+    fn command(
+        &mut self,
+        command: CommandType,
+        slot: u8,
+        parameters: [u8; 3],
+        data: &[u8],
+    ) -> UsbResult<Response> {
+        if slot > self.max_slot_index || HEADER_LENGTH + data.len() > self.max_message_length {
+            return Err(Error::InvalidArgument);
+        }
+
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+
+        // A reader that gets only part of a message will wait for the rest forever; so we
+        // make sure all of it goes out.
+        let message = command_message(command, slot, sequence, parameters, data);
+        self.device
+            .write_all(self.bulk_out, &message, self.timeout)?;
+
+        // The reader can ask us for more time as many times as it wants; so we'll keep reading
+        // until we get a response that's actually final.
+        loop {
+            let response = self.read_response()?;
+
+            // Ignore any stale responses to commands that aren't ours.
+            if response.sequence != sequence {
+                continue;
+            }
+
+            if response.command_status != CommandStatus::TimeExtensionRequested {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Reads a single, complete response message from the bulk IN endpoint.
+    fn read_response(&mut self) -> UsbResult<Response> {
+        let mut message =
+            self.device
+                .read_to_vec(self.bulk_in, self.max_message_length, self.timeout)?;
+        if message.len() < HEADER_LENGTH {
+            return Err(Error::ShortRead(message.len()));
+        }
+
+        // Responses longer than a single transfer arrive in pieces; keep reading until
+        // we have everything the header promised.
+        let length = u32::from_le_bytes([message[1], message[2], message[3], message[4]]) as usize;
+        while message.len() < HEADER_LENGTH + length {
+            let remaining = HEADER_LENGTH + length - message.len();
+            let chunk = self
+                .device
+                .read_to_vec(self.bulk_in, remaining, self.timeout)?;
+
+            // A zero-length read means the reader's done talking; so the response it promised
+            // us was cut short.
+            if chunk.is_empty() {
+                return Err(Error::ShortRead(message.len()));
+            }
+            message.extend_from_slice(&chunk);
+        }
+
+        Response::parse(&message)
+    }
+}
+
+/// Builds a PC_to_RDR command message: the header, followed by the command's data.
+fn command_message(
+    command: CommandType,
+    slot: u8,
+    sequence: u8,
+    parameters: [u8; 3],
+    data: &[u8],
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_LENGTH + data.len());
+    message.push(command as u8);
+    message.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    message.push(slot);
+    message.push(sequence);
+    message.extend_from_slice(&parameters);
+    message.extend_from_slice(data);
+    message
+}
+
+/// Sanity-checks the dwMaxCCIDMessageLength a reader reports. Anything the specification
+/// doesn't allow -- too short to hold a header, or long enough to have us allocating
+/// gigabytes per command -- falls back to our default.
+fn checked_max_message_length(declared: u32) -> usize {
+    match declared as usize {
+        length @ HEADER_LENGTH..=MAX_MESSAGE_LENGTH => length,
+        _ => DEFAULT_MAX_MESSAGE_LENGTH,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_have_the_standard_header() {
+        let message = command_message(CommandType::XfrBlock, 1, 7, [2, 3, 4], &[0xa0, 0xb0]);
+
+        assert_eq!(
+            message,
+            [0x6f, 2, 0, 0, 0, 1, 7, 2, 3, 4, 0xa0, 0xb0],
+            "bMessageType, dwLength, bSlot, bSeq, parameters, then data"
+        );
+    }
+
+    #[test]
+    fn responses_parse() {
+        // An RDR_to_PC_DataBlock for sequence 7 on slot 1; whose card is present but inactive,
+        // and whose command failed with error 0xfe. A stray trailing byte isn't part of the data.
+        let message = [
+            0x80,
+            2,
+            0,
+            0,
+            0,
+            1,
+            7,
+            0b0100_0001,
+            0xfe,
+            0,
+            0x90,
+            0x00,
+            0xff,
+        ];
+
+        assert_eq!(
+            Response::parse(&message),
+            Ok(Response {
+                message_type: ResponseType::DataBlock as u8,
+                slot: 1,
+                sequence: 7,
+                icc_status: IccStatus::PresentAndInactive,
+                command_status: CommandStatus::Failed,
+                error: 0xfe,
+                specific: 0,
+                data: vec![0x90, 0x00],
+            })
+        );
+    }
+
+    #[test]
+    fn response_status_bits_are_decoded() {
+        let status = |status: u8| {
+            let response = Response::parse(&[0x81, 0, 0, 0, 0, 0, 0, status, 0, 0]).unwrap();
+            (response.icc_status, response.command_status)
+        };
+
+        assert_eq!(
+            status(0b0000_0000),
+            (IccStatus::PresentAndActive, CommandStatus::Succeeded)
+        );
+        assert_eq!(
+            status(0b0000_0010),
+            (IccStatus::NotPresent, CommandStatus::Succeeded)
+        );
+        assert_eq!(
+            status(0b1000_0000),
+            (
+                IccStatus::PresentAndActive,
+                CommandStatus::TimeExtensionRequested
+            )
+        );
+    }
+
+    #[test]
+    fn truncated_responses_are_rejected() {
+        // Too short for a header...
+        assert_eq!(
+            Response::parse(&[0x80, 0, 0, 0, 0]),
+            Err(Error::InvalidArgument)
+        );
+
+        // ... or shorter than dwLength says, by a little or a lot.
+        assert_eq!(
+            Response::parse(&[0x80, 4, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2]),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            Response::parse(&[0x80, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0]),
+            Err(Error::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn implausible_message_lengths_fall_back_to_the_default() {
+        assert_eq!(checked_max_message_length(271), 271);
+        assert_eq!(checked_max_message_length(65544), 65544);
+        assert_eq!(checked_max_message_length(0), DEFAULT_MAX_MESSAGE_LENGTH);
+        assert_eq!(checked_max_message_length(9), DEFAULT_MAX_MESSAGE_LENGTH);
+        assert_eq!(
+            checked_max_message_length(0xffff_ffff),
+            DEFAULT_MAX_MESSAGE_LENGTH
+        );
+    }
+
+    #[test]
+    fn slot_change_notifications_parse() {
+        // Slot 0 has a card that was just inserted; slot 1 is empty, but had its card removed.
+        let notification = Notification::parse(&[0x50, 0b0000_1011]).unwrap();
+
+        let Notification::SlotChange(slots) = notification else {
+            panic!("expected a slot change, got {notification:?}");
+        };
+        assert_eq!(
+            slots[..2],
+            [
+                SlotChange {
+                    slot: 0,
+                    card_present: true,
+                    changed: true
+                },
+                SlotChange {
+                    slot: 1,
+                    card_present: false,
+                    changed: true
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,514 @@
+//! Helpers for controlling USB hubs' downstream ports.
+//!
+//! This is mostly useful for test rigs: hubs that support per-port power switching can
+//! power-cycle a stuck device without anyone having to unplug it. We talk to the hub with
+//! class requests on its default control pipe, so we don't claim any of its interfaces; the
+//! OS's hub driver keeps running alongside us, and some OSes may refuse these requests.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    device::Device,
+    request::{ClassCode, Direction, Recipient, RequestType, Type},
+    Error, UsbResult,
+};
+
+/// The USB class code assigned to hubs.
+pub const HUB_CLASS: u8 = ClassCode::Hub as u8;
+
+/// Descriptor type of the USB 2.0 hub descriptor.
+const HUB_DESCRIPTOR: u8 = 0x29;
+
+/// Descriptor type of the SuperSpeed hub descriptor.
+const SUPERSPEED_HUB_DESCRIPTOR: u8 = 0x2a;
+
+/// How often we check whether a port reset has finished.
+const RESET_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long we'll wait for a port reset to finish before giving up.
+const RESET_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Request type for class requests to the hub itself.
+const CLASS_IN_FROM_HUB: RequestType = RequestType {
+    direction: Direction::In,
+    request_type: Type::Class,
+    recipient: Recipient::Device,
+};
+
+/// Request type for class requests from one of the hub's ports.
+const CLASS_IN_FROM_PORT: RequestType = RequestType {
+    direction: Direction::In,
+    request_type: Type::Class,
+    recipient: Recipient::Other,
+};
+
+/// Request type for class requests to one of the hub's ports.
+const CLASS_OUT_TO_PORT: RequestType = RequestType {
+    direction: Direction::Out,
+    request_type: Type::Class,
+    recipient: Recipient::Other,
+};
+
+/// Class-specific requests understood by hubs.
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum HubRequest {
+    GetStatus = 0,
+    ClearFeature = 1,
+    SetFeature = 3,
+    GetDescriptor = 6,
+}
+
+/// Features that can be set or cleared on a hub's downstream ports.
+#[repr(u16)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum PortFeature {
+    Connection = 0,
+    Enable = 1,
+    Suspend = 2,
+    OverCurrent = 3,
+    Reset = 4,
+    Power = 8,
+    LowSpeed = 9,
+    ConnectionChange = 16,
+    EnableChange = 17,
+    SuspendChange = 18,
+    OverCurrentChange = 19,
+    ResetChange = 20,
+    Test = 21,
+    Indicator = 22,
+}
+
+/// The colors a port's indicator LED can be set to; see [Hub::set_port_indicator].
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum PortIndicator {
+    /// Let the hub drive the indicator itself.
+    Automatic = 0,
+    Amber = 1,
+    Green = 2,
+    Off = 3,
+}
+
+/// How a hub switches power to its ports.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum PowerSwitching {
+    /// All ports are powered on and off together.
+    Ganged,
+
+    /// Each port can be powered on and off individually.
+    PerPort,
+
+    /// Port power is always on; the hub ignores power requests.
+    None,
+}
+
+/// The parts of a hub descriptor we care about.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct HubDescriptor {
+    /// The number of downstream ports the hub has.
+    pub number_of_ports: u8,
+
+    /// The hub's characteristics bitmap (wHubCharacteristics).
+    pub characteristics: u16,
+
+    /// How long a port takes to have good power after being powered on.
+    pub power_on_to_good: Duration,
+
+    /// True iff this is a SuperSpeed hub's descriptor.
+    pub superspeed: bool,
+}
+
+impl HubDescriptor {
+    /// Parses a USB 2.0 or SuperSpeed hub descriptor.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        if data.len() < 7 || data[0] as usize > data.len() {
+            return Err(Error::MalformedDescriptor);
+        }
+
+        let superspeed = match data[1] {
+            HUB_DESCRIPTOR => false,
+            SUPERSPEED_HUB_DESCRIPTOR => true,
+            _ => return Err(Error::MalformedDescriptor),
+        };
+
+        Ok(Self {
+            number_of_ports: data[2],
+            characteristics: u16::from_le_bytes([data[3], data[4]]),
+            power_on_to_good: Duration::from_millis(data[5] as u64 * 2),
+            superspeed,
+        })
+    }
+
+    /// Returns how the hub switches power to its ports.
+    pub fn power_switching(&self) -> PowerSwitching {
+        match self.characteristics & 0b11 {
+            0b00 => PowerSwitching::Ganged,
+            0b01 => PowerSwitching::PerPort,
+            _ => PowerSwitching::None,
+        }
+    }
+
+    /// Returns true iff the hub has port indicator LEDs. SuperSpeed hubs never do.
+    pub fn has_port_indicators(&self) -> bool {
+        !self.superspeed && self.characteristics & (1 << 7) != 0
+    }
+}
+
+/// The status of one of a hub's downstream ports, as returned by GET_STATUS.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct PortStatus {
+    /// The port's status bits (wPortStatus).
+    pub status: u16,
+
+    /// The port's status-change bits (wPortChange).
+    pub change: u16,
+
+    /// True iff this came from a SuperSpeed hub, whose power bit lives elsewhere.
+    superspeed: bool,
+}
+
+impl PortStatus {
+    /// Returns true iff a device is connected to the port.
+    pub fn is_connected(&self) -> bool {
+        self.status & (1 << 0) != 0
+    }
+
+    /// Returns true iff the port is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.status & (1 << 1) != 0
+    }
+
+    /// Returns true iff the port is reporting an over-current condition.
+    pub fn is_over_current(&self) -> bool {
+        self.status & (1 << 3) != 0
+    }
+
+    /// Returns true iff the port is being reset.
+    pub fn is_resetting(&self) -> bool {
+        self.status & (1 << 4) != 0
+    }
+
+    /// Returns true iff the port is powered.
+    pub fn is_powered(&self) -> bool {
+        let power_bit = if self.superspeed { 9 } else { 8 };
+        self.status & (1 << power_bit) != 0
+    }
+
+    /// Returns true iff the port has finished a reset since the change was last cleared.
+    pub fn reset_changed(&self) -> bool {
+        self.change & (1 << 4) != 0
+    }
+}
+
+/// A hub, whose downstream ports we control.
+#[derive(Debug)]
+pub struct Hub {
+    /// The hub device we're driving.
+    device: Device,
+
+    /// The hub's hub descriptor.
+    descriptor: HubDescriptor,
+
+    /// The timeout applied to each individual request.
+    timeout: Option<Duration>,
+}
+
+impl Hub {
+    /// Creates a hub helper from an opened hub device.
+    pub fn new(mut device: Device) -> UsbResult<Self> {
+        let device_descriptor = device.read_device_descriptor()?;
+        if device_descriptor.device_class != HUB_CLASS {
+            return Err(Error::InvalidArgument);
+        }
+
+        // SuperSpeed hubs have their own flavor of hub descriptor.
+        let descriptor_type = if device_descriptor.usb_version >= 0x0300 {
+            SUPERSPEED_HUB_DESCRIPTOR
+        } else {
+            HUB_DESCRIPTOR
+        };
+
+        let raw = device.control_read_to_vec(
+            CLASS_IN_FROM_HUB,
+            HubRequest::GetDescriptor as u8,
+            (descriptor_type as u16) << 8,
+            0,
+            u8::MAX as u16,
+            None,
+        )?;
+        let descriptor = HubDescriptor::parse(&raw)?;
+
+        Ok(Self {
+            device,
+            descriptor,
+            timeout: None,
+        })
+    }
+
+    /// Sets the timeout applied to each request; or None to wait indefinitely.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the hub's hub descriptor.
+    pub fn descriptor(&self) -> &HubDescriptor {
+        &self.descriptor
+    }
+
+    /// Returns the number of downstream ports the hub has; numbered from 1.
+    pub fn number_of_ports(&self) -> u8 {
+        self.descriptor.number_of_ports
+    }
+
+    /// Provides access to the underlying device.
+    pub fn device(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    /// Returns the underlying device.
+    pub fn into_device(self) -> Device {
+        self.device
+    }
+
+    /// Reads the status of one of the hub's ports.
+    pub fn port_status(&mut self, port: u8) -> UsbResult<PortStatus> {
+        self.check_port(port)?;
+
+        let mut raw = [0u8; 4];
+        let length = self.device.control_read(
+            CLASS_IN_FROM_PORT,
+            HubRequest::GetStatus as u8,
+            0,
+            port as u16,
+            &mut raw,
+            self.timeout,
+        )?;
+        if length < raw.len() {
+            return Err(Error::PartialTransfer(length));
+        }
+
+        Ok(PortStatus {
+            status: u16::from_le_bytes([raw[0], raw[1]]),
+            change: u16::from_le_bytes([raw[2], raw[3]]),
+            superspeed: self.descriptor.superspeed,
+        })
+    }
+
+    /// Sets a feature on one of the hub's ports.
+    pub fn set_port_feature(&mut self, port: u8, feature: PortFeature) -> UsbResult<()> {
+        self.port_feature_request(HubRequest::SetFeature, port, feature, 0)
+    }
+
+    /// Clears a feature on one of the hub's ports.
+    pub fn clear_port_feature(&mut self, port: u8, feature: PortFeature) -> UsbResult<()> {
+        self.port_feature_request(HubRequest::ClearFeature, port, feature, 0)
+    }
+
+    /// Turns power to one of the hub's ports on or off.
+    ///
+    /// On hubs with ganged power switching, this affects every port; and on hubs without
+    /// power switching, it does nothing. See [HubDescriptor::power_switching].
+    pub fn set_port_power(&mut self, port: u8, powered: bool) -> UsbResult<()> {
+        if powered {
+            self.set_port_feature(port, PortFeature::Power)
+        } else {
+            self.clear_port_feature(port, PortFeature::Power)
+        }
+    }
+
+    /// Powers a port off, waits for the given time, and then powers it back on; waiting until
+    /// the hub says power should be good again before returning.
+    pub fn power_cycle_port(&mut self, port: u8, off_time: Duration) -> UsbResult<()> {
+        self.set_port_power(port, false)?;
+        std::thread::sleep(off_time);
+        self.set_port_power(port, true)?;
+
+        std::thread::sleep(self.descriptor.power_on_to_good);
+        Ok(())
+    }
+
+    /// Resets one of the hub's ports, and waits for the reset to finish.
+    pub fn reset_port(&mut self, port: u8) -> UsbResult<()> {
+        self.set_port_feature(port, PortFeature::Reset)?;
+
+        // Wait for the hub to tell us the reset is done...
+        let deadline = Instant::now() + RESET_TIMEOUT;
+        while !self.port_status(port)?.reset_changed() {
+            if Instant::now() >= deadline {
+                return Err(Error::TimedOut);
+            }
+            std::thread::sleep(RESET_POLL_INTERVAL);
+        }
+
+        // ... and acknowledge it, so the change doesn't linger.
+        self.clear_port_feature(port, PortFeature::ResetChange)
+    }
+
+    /// Sets the color of a port's indicator LED; on hubs that have them.
+    pub fn set_port_indicator(&mut self, port: u8, indicator: PortIndicator) -> UsbResult<()> {
+        if !self.descriptor.has_port_indicators() {
+            return Err(Error::Unsupported);
+        }
+
+        self.port_feature_request(
+            HubRequest::SetFeature,
+            port,
+            PortFeature::Indicator,
+            indicator as u8,
+        )
+    }
+
+    /// Issues a SET_FEATURE or CLEAR_FEATURE request against a port. Some features take an
+    /// extra selector, which is carried in the upper byte of wIndex.
+    fn port_feature_request(
+        &mut self,
+        request: HubRequest,
+        port: u8,
+        feature: PortFeature,
+        selector: u8,
+    ) -> UsbResult<()> {
+        self.check_port(port)?;
+
+        let index = ((selector as u16) << 8) | port as u16;
+        self.device.control_write(
+            CLASS_OUT_TO_PORT,
+            request as u8,
+            feature as u16,
+            index,
+            &[],
+            self.timeout,
+        )
+    }
+
+    /// Ensures a port number refers to one of the hub's ports.
+    fn check_port(&self, port: u8) -> UsbResult<()> {
+        if port == 0 || port > self.descriptor.number_of_ports {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usb2_hub_descriptors_parse() {
+        // A four-port hub with per-port power switching, port indicators, and a 100ms power-on time.
+        let descriptor = HubDescriptor::parse(&[
+            0x09,
+            HUB_DESCRIPTOR,
+            0x04,
+            0x89,
+            0x00,
+            0x32,
+            0x64,
+            0x00,
+            0xFF,
+        ])
+        .unwrap();
+
+        assert_eq!(descriptor.number_of_ports, 4);
+        assert_eq!(descriptor.characteristics, 0x0089);
+        assert_eq!(descriptor.power_on_to_good, Duration::from_millis(100));
+        assert!(!descriptor.superspeed);
+        assert_eq!(descriptor.power_switching(), PowerSwitching::PerPort);
+        assert!(descriptor.has_port_indicators());
+    }
+
+    #[test]
+    fn superspeed_hub_descriptors_parse() {
+        // SuperSpeed hubs don't have indicators; even if that bit happens to be set.
+        let descriptor = HubDescriptor::parse(&[
+            0x0C,
+            SUPERSPEED_HUB_DESCRIPTOR,
+            0x07,
+            0x80,
+            0x00,
+            0x0A,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+        ])
+        .unwrap();
+
+        assert_eq!(descriptor.number_of_ports, 7);
+        assert!(descriptor.superspeed);
+        assert_eq!(descriptor.power_switching(), PowerSwitching::Ganged);
+        assert!(!descriptor.has_port_indicators());
+    }
+
+    #[test]
+    fn malformed_hub_descriptors_are_rejected() {
+        let valid = [
+            0x09,
+            HUB_DESCRIPTOR,
+            0x04,
+            0x02,
+            0x00,
+            0x32,
+            0x64,
+            0x00,
+            0xFF,
+        ];
+        assert_eq!(
+            HubDescriptor::parse(&valid).unwrap().power_switching(),
+            PowerSwitching::None
+        );
+
+        // Too short to hold the fields we read...
+        assert_eq!(
+            HubDescriptor::parse(&valid[..6]),
+            Err(Error::MalformedDescriptor)
+        );
+
+        // ... claiming to be longer than it is...
+        let mut truncated = valid;
+        truncated[0] = 0x0A;
+        assert_eq!(
+            HubDescriptor::parse(&truncated),
+            Err(Error::MalformedDescriptor)
+        );
+
+        // ... or not a hub descriptor at all.
+        let mut other = valid;
+        other[1] = 0x02;
+        assert_eq!(
+            HubDescriptor::parse(&other),
+            Err(Error::MalformedDescriptor)
+        );
+    }
+
+    #[test]
+    fn port_status_bits_are_decoded() {
+        let status = PortStatus {
+            status: 0x0113,
+            change: 0x0010,
+            superspeed: false,
+        };
+        assert!(status.is_connected());
+        assert!(status.is_enabled());
+        assert!(!status.is_over_current());
+        assert!(status.is_resetting());
+        assert!(status.is_powered());
+        assert!(status.reset_changed());
+
+        // SuperSpeed hubs report power on bit 9, rather than bit 8.
+        let superspeed = PortStatus {
+            superspeed: true,
+            ..status
+        };
+        assert!(!superspeed.is_powered());
+        assert!(PortStatus {
+            status: 0x0200,
+            ..superspeed
+        }
+        .is_powered());
+    }
+}
@@ -0,0 +1,746 @@
+//! Helpers for working with USB MIDI (MIDIStreaming) class devices.
+//!
+//! USB MIDI devices move MIDI data around in 4-byte "event packets" over a pair of bulk
+//! endpoints. Each packet carries a "cable number", which selects one of up to sixteen
+//! virtual MIDI cables multiplexed onto the endpoint; the class-specific endpoint descriptors
+//! tell us which of the device's embedded MIDI jacks each cable number is wired to.
+
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "async")]
+use futures_core::Stream;
+#[cfg(feature = "async")]
+use parking_lot::RwLock;
+
+#[cfg(feature = "async")]
+use crate::{
+    futures::{ReadCompletion, UsbFuture},
+    ReadBuffer,
+};
+
+use crate::{
+    descriptors::{ConfigurationDescriptor, EndpointDescriptor, InterfaceDescriptor, TransferType},
+    device::Device,
+    request::{ClassCode, Direction},
+    Error, UsbResult,
+};
+
+/// The USB class code assigned to audio devices, of which MIDI is a part.
+pub const AUDIO_CLASS: u8 = ClassCode::Audio as u8;
+
+/// The audio subclass code assigned to MIDIStreaming interfaces.
+pub const MIDI_STREAMING_SUBCLASS: u8 = 0x03;
+
+/// Descriptor type for class-specific interface descriptors.
+const CS_INTERFACE: u8 = 0x24;
+
+/// Descriptor type for class-specific endpoint descriptors.
+const CS_ENDPOINT: u8 = 0x25;
+
+/// Descriptor subtype for MIDI IN jack descriptors.
+const MIDI_IN_JACK: u8 = 0x02;
+
+/// Descriptor subtype for MIDI OUT jack descriptors.
+const MIDI_OUT_JACK: u8 = 0x03;
+
+/// Descriptor subtype for the class-specific MS_GENERAL endpoint descriptor.
+const MS_GENERAL: u8 = 0x01;
+
+/// The size of a single USB-MIDI event packet.
+pub const EVENT_PACKET_SIZE: usize = 4;
+
+/// Whether a jack is connected to something inside the USB function, or to the outside world.
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum JackType {
+    Embedded = 0x01,
+    External = 0x02,
+}
+
+/// A MIDI IN or OUT jack, as described by the MIDIStreaming interface.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct Jack {
+    /// The jack's identifier, unique within the interface.
+    pub id: u8,
+
+    /// Whether the jack is embedded (connected to an endpoint) or external.
+    pub jack_type: JackType,
+
+    /// The direction of the jack: IN jacks accept MIDI data, and OUT jacks emit it.
+    pub direction: Direction,
+}
+
+/// A bulk endpoint carrying USB-MIDI event packets, along with its virtual cables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MidiEndpoint {
+    /// The endpoint's address, including its direction bit.
+    pub address: u8,
+
+    /// The endpoint's maximum packet size.
+    pub packet_size: usize,
+
+    /// The embedded jacks associated with the endpoint, indexed by cable number.
+    pub jack_ids: Vec<u8>,
+}
+
+impl MidiEndpoint {
+    /// Builds our view of an endpoint from its descriptor and its MS_GENERAL descriptor.
+    fn from_descriptor(endpoint: &EndpointDescriptor) -> Self {
+        let jack_ids = endpoint
+            .extra
+            .iter()
+            .find(|descriptor| {
+                descriptor.len() >= 4 && descriptor[1] == CS_ENDPOINT && descriptor[2] == MS_GENERAL
+            })
+            .map(|descriptor| {
+                let count = descriptor[3] as usize;
+                descriptor[4..].iter().take(count).copied().collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            address: endpoint.address,
+            packet_size: endpoint.packet_size(),
+            jack_ids,
+        }
+    }
+
+    /// Returns the number of virtual cables carried on this endpoint.
+    pub fn cable_count(&self) -> usize {
+        self.jack_ids.len()
+    }
+
+    /// Returns the cable number used to reach the given embedded jack, if this endpoint carries it.
+    pub fn cable_for_jack(&self, jack_id: u8) -> Option<u8> {
+        self.jack_ids
+            .iter()
+            .position(|&id| id == jack_id)
+            .map(|cable| cable as u8)
+    }
+}
+
+/// Parsed form of a MIDIStreaming interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MidiStreamingInterface {
+    /// The interface number of the MIDIStreaming interface.
+    pub interface_number: u8,
+
+    /// Each of the jacks described by the interface.
+    pub jacks: Vec<Jack>,
+
+    /// The bulk IN endpoint, on which the device sends us events; if it has one.
+    pub in_endpoint: Option<MidiEndpoint>,
+
+    /// The bulk OUT endpoint, on which we send events to the device; if it has one.
+    pub out_endpoint: Option<MidiEndpoint>,
+}
+
+impl MidiStreamingInterface {
+    /// Finds and parses the first MIDIStreaming interface in a configuration.
+    pub fn find(configuration: &ConfigurationDescriptor) -> UsbResult<Self> {
+        let interface = configuration
+            .interfaces
+            .iter()
+            .find(|interface| Self::is_midi_streaming(interface))
+            .ok_or(Error::InvalidInterface)?;
+
+        Self::parse(interface)
+    }
+
+    /// Returns true iff the given interface descriptor describes a MIDIStreaming interface.
+    pub fn is_midi_streaming(interface: &InterfaceDescriptor) -> bool {
+        interface.interface_class == AUDIO_CLASS
+            && interface.interface_subclass == MIDI_STREAMING_SUBCLASS
+    }
+
+    /// Parses a MIDIStreaming interface from its (standard) interface descriptor.
+    pub fn parse(interface: &InterfaceDescriptor) -> UsbResult<Self> {
+        if !Self::is_midi_streaming(interface) {
+            return Err(Error::InvalidInterface);
+        }
+
+        // Collect each of the jacks from the class-specific interface descriptors...
+        let jacks = interface
+            .extra
+            .iter()
+            .filter(|descriptor| descriptor.len() >= 5 && descriptor[1] == CS_INTERFACE)
+            .filter_map(|descriptor| {
+                let direction = match descriptor[2] {
+                    MIDI_IN_JACK => Direction::In,
+                    MIDI_OUT_JACK => Direction::Out,
+                    _ => return None,
+                };
+                let jack_type = match descriptor[3] {
+                    0x01 => JackType::Embedded,
+                    0x02 => JackType::External,
+                    _ => return None,
+                };
+
+                Some(Jack {
+                    id: descriptor[4],
+                    jack_type,
+                    direction,
+                })
+            })
+            .collect();
+
+        // ... and figure out which endpoints carry which cables.
+        let in_endpoint = interface
+            .find_endpoint(TransferType::Bulk, Direction::In)
+            .map(MidiEndpoint::from_descriptor);
+        let out_endpoint = interface
+            .find_endpoint(TransferType::Bulk, Direction::Out)
+            .map(MidiEndpoint::from_descriptor);
+
+        Ok(Self {
+            interface_number: interface.interface_number,
+            jacks,
+            in_endpoint,
+            out_endpoint,
+        })
+    }
+
+    /// Returns the jack with the given ID, if the interface has one.
+    pub fn jack(&self, id: u8) -> Option<&Jack> {
+        self.jacks.iter().find(|jack| jack.id == id)
+    }
+}
+
+/// A single 4-byte USB-MIDI event packet.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct EventPacket {
+    /// The virtual cable this event travels on.
+    pub cable: u8,
+
+    /// The Code Index Number, which classifies the event (and determines its length).
+    pub code_index: u8,
+
+    /// The MIDI bytes carried by the event; padded with zeroes.
+    pub midi: [u8; 3],
+}
+
+impl EventPacket {
+    /// Creates an event packet from its raw, on-the-wire form.
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self {
+            cable: bytes[0] >> 4,
+            code_index: bytes[0] & 0x0F,
+            midi: [bytes[1], bytes[2], bytes[3]],
+        }
+    }
+
+    /// Converts the event packet into its raw, on-the-wire form.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        [
+            (self.cable << 4) | (self.code_index & 0x0F),
+            self.midi[0],
+            self.midi[1],
+            self.midi[2],
+        ]
+    }
+
+    /// Creates an event packet carrying a single (non-SysEx) MIDI message.
+    ///
+    /// Returns [Error::InvalidArgument] if the message isn't a complete channel, system common,
+    /// or real-time message; SysEx should be sent via [EventPacket::from_sysex].
+    pub fn from_message(cable: u8, message: &[u8]) -> UsbResult<Self> {
+        let status = *message.first().ok_or(Error::InvalidArgument)?;
+        if cable > 0x0F {
+            return Err(Error::InvalidArgument);
+        }
+
+        let code_index = match status {
+            // Channel messages use their upper nibble as their code index...
+            0x80..=0xEF => status >> 4,
+
+            // ... two-byte system common messages have their own code...
+            0xF1 | 0xF3 => 0x2,
+
+            // ... as does song position, the lone three-byte system common message...
+            0xF2 => 0x3,
+
+            // ... tune request is a single-byte system common message...
+            0xF6 => 0x5,
+
+            // ... and real-time messages are sent as plain single bytes.
+            0xF8..=0xFF => 0xF,
+
+            _ => return Err(Error::InvalidArgument),
+        };
+
+        let length = Self::length_for_code_index(code_index);
+        if message.len() != length {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut midi = [0; 3];
+        midi[..length].copy_from_slice(message);
+
+        Ok(Self {
+            cable,
+            code_index,
+            midi,
+        })
+    }
+
+    /// Splits a complete SysEx message (including its 0xF0 and 0xF7 framing) into event packets.
+    pub fn from_sysex(cable: u8, message: &[u8]) -> UsbResult<Vec<Self>> {
+        if cable > 0x0F
+            || message.len() < 2
+            || message.first() != Some(&0xF0)
+            || message.last() != Some(&0xF7)
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Every chunk but the last is a "SysEx starts or continues" packet; the last
+        // one's code index tells the device how many bytes of it are meaningful.
+        let chunks = message.chunks(3);
+        let count = chunks.len();
+
+        Ok(chunks
+            .enumerate()
+            .map(|(index, chunk)| {
+                let code_index = if index + 1 < count {
+                    0x4
+                } else {
+                    0x4 + chunk.len() as u8
+                };
+
+                let mut midi = [0; 3];
+                midi[..chunk.len()].copy_from_slice(chunk);
+
+                Self {
+                    cable,
+                    code_index,
+                    midi,
+                }
+            })
+            .collect())
+    }
+
+    /// Returns the number of meaningful MIDI bytes carried by a packet with the given code index.
+    fn length_for_code_index(code_index: u8) -> usize {
+        match code_index & 0x0F {
+            0x5 | 0xF => 1,
+            0x2 | 0x6 | 0xC | 0xD => 2,
+            0x0 | 0x1 => 0,
+            _ => 3,
+        }
+    }
+
+    /// Returns the meaningful MIDI bytes carried by this packet.
+    pub fn midi_bytes(&self) -> &[u8] {
+        &self.midi[..Self::length_for_code_index(self.code_index)]
+    }
+
+    /// Returns true iff this packet is padding, which devices use to fill out short transfers.
+    pub fn is_padding(&self) -> bool {
+        self.to_bytes() == [0; 4]
+    }
+
+    /// Parses each of the (non-padding) event packets in a transfer's worth of data.
+    pub fn parse_all(data: &[u8]) -> Vec<Self> {
+        data.chunks_exact(EVENT_PACKET_SIZE)
+            .map(|chunk| Self::from_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .filter(|packet| !packet.is_padding())
+            .collect()
+    }
+}
+
+/// A USB MIDI device, driven via its MIDIStreaming interface.
+#[derive(Debug)]
+pub struct MidiDevice {
+    /// The device we're driving.
+    device: Device,
+
+    /// The MIDIStreaming interface we're using; which we hold claimed.
+    interface: MidiStreamingInterface,
+
+    /// The timeout applied to each individual transfer.
+    timeout: Option<Duration>,
+}
+
+impl MidiDevice {
+    /// Creates a MIDI device from an opened device, using its first MIDIStreaming interface.
+    pub fn new(mut device: Device) -> UsbResult<Self> {
+        let configuration = device.read_active_configuration_descriptor()?;
+        let interface = MidiStreamingInterface::find(&configuration)?;
+
+        Self::from_streaming_interface(device, interface)
+    }
+
+    /// Creates a MIDI device from an opened device, using the provided interface number.
+    pub fn from_interface(mut device: Device, interface_number: u8) -> UsbResult<Self> {
+        let configuration = device.read_active_configuration_descriptor()?;
+        let interface = configuration
+            .interface(interface_number, 0)
+            .ok_or(Error::InvalidInterface)?;
+        let interface = MidiStreamingInterface::parse(interface)?;
+
+        Self::from_streaming_interface(device, interface)
+    }
+
+    /// Creates a MIDI device given its parsed MIDIStreaming interface.
+    fn from_streaming_interface(
+        mut device: Device,
+        interface: MidiStreamingInterface,
+    ) -> UsbResult<Self> {
+        if interface.in_endpoint.is_none() && interface.out_endpoint.is_none() {
+            return Err(Error::InvalidEndpoint);
+        }
+
+        device.claim_interface(interface.interface_number)?;
+
+        Ok(Self {
+            device,
+            interface,
+            timeout: None,
+        })
+    }
+
+    /// Sets the timeout applied to each transfer; or None to wait indefinitely.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the parsed MIDIStreaming interface; including its jacks and cable mappings.
+    pub fn streaming_interface(&self) -> &MidiStreamingInterface {
+        &self.interface
+    }
+
+    /// Provides access to the underlying device.
+    pub fn device(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    /// Releases the MIDIStreaming interface, and returns the underlying device.
+    pub fn into_device(mut self) -> UsbResult<Device> {
+        self.device
+            .unclaim_interface(self.interface.interface_number)?;
+        Ok(self.device)
+    }
+
+    /// Sends a collection of event packets to the device.
+    pub fn send(&mut self, events: &[EventPacket]) -> UsbResult<()> {
+        let endpoint = self
+            .interface
+            .out_endpoint
+            .as_ref()
+            .ok_or(Error::Unsupported)?;
+
+        let data: Vec<u8> = events.iter().flat_map(EventPacket::to_bytes).collect();
+        self.device.write(endpoint.address, &data, self.timeout)?;
+        Ok(())
+    }
+
+    /// Sends a single MIDI message (including SysEx) on the given virtual cable.
+    pub fn send_message(&mut self, cable: u8, message: &[u8]) -> UsbResult<()> {
+        let events = if message.first() == Some(&0xF0) {
+            EventPacket::from_sysex(cable, message)?
+        } else {
+            vec![EventPacket::from_message(cable, message)?]
+        };
+
+        self.send(&events)
+    }
+
+    /// Waits for the device to send us events, and returns every event in the transfer.
+    ///
+    /// Padding packets are dropped; so this can return an empty vector.
+    pub fn receive(&mut self) -> UsbResult<Vec<EventPacket>> {
+        let endpoint = self
+            .interface
+            .in_endpoint
+            .as_ref()
+            .ok_or(Error::Unsupported)?;
+
+        let data = self
+            .device
+            .read_to_vec(endpoint.address, endpoint.packet_size, self.timeout)?;
+        Ok(EventPacket::parse_all(&data))
+    }
+
+    /// Returns a stream of the events the device sends us.
+    ///
+    /// The stream keeps a read outstanding while it's being polled; the read is not cancelled
+    /// when the stream is dropped, so a dropped stream may still eat up a transfer's worth of events.
+    #[cfg(feature = "async")]
+    pub fn events(&mut self) -> UsbResult<MidiEventStream<'_>> {
+        let packet_size = self
+            .interface
+            .in_endpoint
+            .as_ref()
+            .ok_or(Error::Unsupported)?
+            .packet_size;
+
+        Ok(MidiEventStream {
+            midi: self,
+            buffer: Arc::new(RwLock::new(vec![0u8; packet_size])),
+            pending: None,
+            queued: VecDeque::new(),
+        })
+    }
+}
+
+/// Asynchronous stream of the events received from a [MidiDevice].
+#[cfg(feature = "async")]
+pub struct MidiEventStream<'a> {
+    /// The device we're receiving events from.
+    midi: &'a mut MidiDevice,
+
+    /// The buffer our outstanding read will fill.
+    buffer: ReadBuffer,
+
+    /// The read currently in flight, if there is one.
+    pending: Option<UsbFuture<ReadCompletion>>,
+
+    /// Events we've received, but not yet handed out.
+    queued: VecDeque<EventPacket>,
+}
+
+#[cfg(feature = "async")]
+impl Stream for MidiEventStream<'_> {
+    type Item = UsbResult<EventPacket>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            // If we already have events on hand, hand them out first.
+            if let Some(event) = this.queued.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+
+            // Otherwise, make sure we have a read in flight...
+            if this.pending.is_none() {
+                let endpoint = match &this.midi.interface.in_endpoint {
+                    Some(endpoint) => endpoint.address,
+                    None => return Poll::Ready(Some(Err(Error::Unsupported))),
+                };
+
+                match this.midi.device.read_async(
+                    endpoint,
+                    Arc::clone(&this.buffer),
+                    this.midi.timeout,
+                ) {
+                    Ok(future) => this.pending = Some(future),
+                    Err(error) => return Poll::Ready(Some(Err(error))),
+                }
+            }
+
+            // ... and see if it's finished.
+            let future = this.pending.as_mut().expect("read should be in flight");
+            let result = match Pin::new(future).poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(result) => result,
+            };
+            this.pending = None;
+
+            match result {
+                Ok(completion) => {
+                    let mut buffer = completion.buffer.write();
+                    let data = &buffer.as_mut()[..completion.length];
+                    this.queued.extend(EventPacket::parse_all(data));
+                }
+                Err(error) => return Poll::Ready(Some(Err(error))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a bulk endpoint, followed by an MS_GENERAL descriptor naming the given jacks.
+    fn bulk_endpoint(address: u8, jack_ids: &[u8]) -> EndpointDescriptor {
+        let mut general = vec![4 + jack_ids.len() as u8, CS_ENDPOINT, MS_GENERAL];
+        general.push(jack_ids.len() as u8);
+        general.extend(jack_ids);
+
+        EndpointDescriptor {
+            address,
+            attributes: 0x02,
+            max_packet_size: 64,
+            interval: 0,
+            extra: vec![general],
+        }
+    }
+
+    /// Builds a MIDIStreaming interface with an embedded and an external jack in each direction.
+    fn streaming_interface() -> InterfaceDescriptor {
+        InterfaceDescriptor {
+            interface_number: 1,
+            alternate_setting: 0,
+            num_endpoints: 2,
+            interface_class: AUDIO_CLASS,
+            interface_subclass: MIDI_STREAMING_SUBCLASS,
+            interface_protocol: 0,
+            interface_string_index: 0,
+            endpoints: vec![bulk_endpoint(0x02, &[1]), bulk_endpoint(0x81, &[3, 5])],
+            extra: vec![
+                // The MS header, which isn't a jack...
+                vec![0x07, CS_INTERFACE, 0x01, 0x00, 0x01, 0x41, 0x00],
+                // ... an embedded IN jack, and its external counterpart...
+                vec![0x06, CS_INTERFACE, MIDI_IN_JACK, 0x01, 0x01, 0x00],
+                vec![0x06, CS_INTERFACE, MIDI_IN_JACK, 0x02, 0x02, 0x00],
+                // ... and a pair of embedded OUT jacks.
+                vec![
+                    0x09,
+                    CS_INTERFACE,
+                    MIDI_OUT_JACK,
+                    0x01,
+                    0x03,
+                    0x01,
+                    0x02,
+                    0x01,
+                    0x00,
+                ],
+                vec![
+                    0x09,
+                    CS_INTERFACE,
+                    MIDI_OUT_JACK,
+                    0x01,
+                    0x05,
+                    0x01,
+                    0x02,
+                    0x01,
+                    0x00,
+                ],
+            ],
+        }
+    }
+
+    #[test]
+    fn streaming_interfaces_parse() {
+        let interface = MidiStreamingInterface::parse(&streaming_interface()).unwrap();
+        assert_eq!(interface.interface_number, 1);
+        assert_eq!(interface.jacks.len(), 4);
+
+        assert_eq!(
+            interface.jack(2),
+            Some(&Jack {
+                id: 2,
+                jack_type: JackType::External,
+                direction: Direction::In,
+            })
+        );
+        assert_eq!(interface.jack(5).unwrap().direction, Direction::Out);
+        assert_eq!(interface.jack(4), None);
+
+        let out_endpoint = interface.out_endpoint.unwrap();
+        assert_eq!(out_endpoint.address, 0x02);
+        assert_eq!(out_endpoint.packet_size, 64);
+        assert_eq!(out_endpoint.cable_for_jack(1), Some(0));
+
+        let in_endpoint = interface.in_endpoint.unwrap();
+        assert_eq!(in_endpoint.cable_count(), 2);
+        assert_eq!(in_endpoint.cable_for_jack(5), Some(1));
+        assert_eq!(in_endpoint.cable_for_jack(1), None);
+    }
+
+    #[test]
+    fn other_interfaces_arent_parsed() {
+        let mut interface = streaming_interface();
+        interface.interface_subclass = 0x01;
+        assert_eq!(
+            MidiStreamingInterface::parse(&interface),
+            Err(Error::InvalidInterface)
+        );
+    }
+
+    #[test]
+    fn truncated_descriptors_are_skipped() {
+        let mut interface = streaming_interface();
+        interface.extra[1].truncate(4);
+
+        // An MS_GENERAL descriptor that claims more jacks than it has only yields those it has.
+        interface.endpoints[1].extra[0].truncate(5);
+
+        let interface = MidiStreamingInterface::parse(&interface).unwrap();
+        assert_eq!(interface.jacks.len(), 3);
+        assert_eq!(interface.jack(1), None);
+        assert_eq!(interface.in_endpoint.unwrap().jack_ids, vec![3]);
+    }
+
+    #[test]
+    fn event_packets_round_trip() {
+        let packet = EventPacket::from_bytes([0x19, 0x90, 0x3C, 0x7F]);
+        assert_eq!(packet.cable, 1);
+        assert_eq!(packet.code_index, 0x9);
+        assert_eq!(packet.midi_bytes(), &[0x90, 0x3C, 0x7F]);
+        assert_eq!(packet.to_bytes(), [0x19, 0x90, 0x3C, 0x7F]);
+
+        assert_eq!(
+            EventPacket::from_message(1, &[0x90, 0x3C, 0x7F]),
+            Ok(packet)
+        );
+        assert_eq!(
+            EventPacket::from_message(0, &[0xF8]).unwrap().to_bytes(),
+            [0x0F, 0xF8, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        assert_eq!(
+            EventPacket::from_message(0, &[]),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            EventPacket::from_message(0, &[0x90, 0x3C]),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            EventPacket::from_message(0x10, &[0xF8]),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            EventPacket::from_message(0, &[0xF0, 0xF7]),
+            Err(Error::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn sysex_is_split_into_packets() {
+        let packets = EventPacket::from_sysex(2, &[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]).unwrap();
+        let bytes: Vec<_> = packets.iter().map(EventPacket::to_bytes).collect();
+        assert_eq!(
+            bytes,
+            vec![[0x24, 0xF0, 0x7E, 0x7F], [0x27, 0x06, 0x01, 0xF7]]
+        );
+
+        let packets = EventPacket::from_sysex(0, &[0xF0, 0x01, 0xF7, 0xF7]).unwrap();
+        assert_eq!(packets[1].midi_bytes(), &[0xF7]);
+        assert_eq!(packets[1].code_index, 0x5);
+
+        assert_eq!(
+            EventPacket::from_sysex(0, &[0xF0, 0x01]),
+            Err(Error::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn padding_is_dropped_when_parsing_transfers() {
+        let data = [
+            0x09, 0x90, 0x3C, 0x7F, // note on
+            0x00, 0x00, 0x00, 0x00, // padding
+            0x08, 0x80, 0x3C, 0x00, // note off
+            0x0F, 0xF8, // a trailing partial packet
+        ];
+
+        let packets = EventPacket::parse_all(&data);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].midi_bytes(), &[0x80, 0x3C, 0x00]);
+    }
+}
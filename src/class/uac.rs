@@ -0,0 +1,1295 @@
+//! Helpers for working with USB Audio Class (UAC) devices.
+//!
+//! Audio functions are split into an AudioControl interface, which describes the function's
+//! topology (terminals, units, and -- for UAC2 -- clocks), and one or more AudioStreaming
+//! interfaces, whose alternate settings each describe a single sample format. Audio data moves
+//! over isochronous endpoints; asynchronous playback endpoints are paired with a feedback
+//! endpoint, which tells us how fast the device is actually consuming samples.
+//!
+//! This module handles descriptors, format selection, and sample-rate control; and, with the
+//! `async` feature, moves the audio itself. Capture formats are read via an [IsoStream]; and
+//! playback formats are written via a [PlaybackStream], whose packets are sized by a
+//! [PacketScheduler] that tracks the device's feedback endpoint.
+
+use std::time::Duration;
+#[cfg(feature = "async")]
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "async")]
+use futures_io::AsyncWrite;
+#[cfg(feature = "async")]
+use parking_lot::RwLock;
+
+use crate::{
+    descriptors::{ConfigurationDescriptor, EndpointDescriptor, InterfaceDescriptor, TransferType},
+    device::Device,
+    request::{
+        ClassCode, Direction, Recipient, RequestType, Type, CLASS_IN_FROM_INTERFACE,
+        CLASS_OUT_TO_INTERFACE,
+    },
+    Error, UsbResult,
+};
+#[cfg(feature = "async")]
+use crate::{
+    device::DeviceSpeed,
+    futures::UsbFuture,
+    iso::{FrameSchedule, IsoOptions, IsoReadCompletion, IsoStream, IsoWriteCompletion},
+};
+
+/// The USB class code assigned to audio devices.
+pub const AUDIO_CLASS: u8 = ClassCode::Audio as u8;
+
+/// The number of packets in each isochronous transfer we queue; 8ms of audio at full speed.
+#[cfg(feature = "async")]
+const AUDIO_PACKETS_PER_TRANSFER: usize = 8;
+
+/// The number of transfers a [PlaybackStream] keeps queued.
+#[cfg(feature = "async")]
+const PLAYBACK_RING_DEPTH: usize = 3;
+
+/// The audio subclass code assigned to AudioControl interfaces.
+pub const AUDIO_CONTROL_SUBCLASS: u8 = 0x01;
+
+/// The audio subclass code assigned to AudioStreaming interfaces.
+pub const AUDIO_STREAMING_SUBCLASS: u8 = 0x02;
+
+/// The interface protocol code used by UAC2 interfaces.
+const UAC2_PROTOCOL: u8 = 0x20;
+
+/// Descriptor type for class-specific interface descriptors.
+const CS_INTERFACE: u8 = 0x24;
+
+/// AudioControl descriptor subtypes.
+const AC_INPUT_TERMINAL: u8 = 0x02;
+const AC_OUTPUT_TERMINAL: u8 = 0x03;
+const AC_CLOCK_SOURCE: u8 = 0x0A;
+
+/// AudioStreaming descriptor subtypes.
+const AS_GENERAL: u8 = 0x01;
+const AS_FORMAT_TYPE: u8 = 0x02;
+
+/// The class request that sets the current value of a control.
+const SET_CUR: u8 = 0x01;
+
+/// The class request that reads the current value of a UAC1 control.
+const GET_CUR: u8 = 0x81;
+
+/// The control selector for sample rate; in both UAC1 (endpoint) and UAC2 (clock source) forms.
+const SAMPLING_FREQUENCY_CONTROL: u8 = 0x01;
+
+/// Request type for class requests sent to an endpoint; which is how UAC1 sets sample rates.
+const CLASS_OUT_TO_ENDPOINT: RequestType = RequestType {
+    direction: Direction::Out,
+    request_type: Type::Class,
+    recipient: Recipient::Endpoint,
+};
+
+/// Request type for class requests read from an endpoint.
+const CLASS_IN_FROM_ENDPOINT: RequestType = RequestType {
+    direction: Direction::In,
+    request_type: Type::Class,
+    recipient: Recipient::Endpoint,
+};
+
+/// The version of the audio class specification a function implements.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum AudioClassVersion {
+    Uac1,
+    Uac2,
+}
+
+/// Whether a terminal brings audio into the function, or takes it out.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum TerminalKind {
+    Input,
+    Output,
+}
+
+/// An input or output terminal, as described by the AudioControl interface.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct Terminal {
+    /// The terminal's ID, unique within the audio function.
+    pub id: u8,
+
+    /// Whether this is an input or output terminal.
+    pub kind: TerminalKind,
+
+    /// The type of the terminal; e.g. 0x0101 for USB streaming, or 0x0201 for a microphone.
+    pub terminal_type: u16,
+
+    /// The ID of the clock source the terminal runs from; for UAC2 functions only.
+    pub clock_source: Option<u8>,
+}
+
+impl Terminal {
+    /// The terminal type shared by all terminals that connect to a USB streaming endpoint.
+    pub const USB_STREAMING: u16 = 0x0101;
+}
+
+/// Parsed form of an AudioControl interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioControlInterface {
+    /// The interface number of the AudioControl interface.
+    pub interface_number: u8,
+
+    /// The version of the audio class the function implements.
+    pub version: AudioClassVersion,
+
+    /// Each of the function's input and output terminals.
+    pub terminals: Vec<Terminal>,
+
+    /// The IDs of each of the function's clock sources; for UAC2 functions only.
+    pub clock_sources: Vec<u8>,
+}
+
+impl AudioControlInterface {
+    /// Parses an AudioControl interface from its (standard) interface descriptor.
+    pub fn parse(interface: &InterfaceDescriptor) -> UsbResult<Self> {
+        if interface.interface_class != AUDIO_CLASS
+            || interface.interface_subclass != AUDIO_CONTROL_SUBCLASS
+        {
+            return Err(Error::InvalidInterface);
+        }
+
+        let version = if interface.interface_protocol == UAC2_PROTOCOL {
+            AudioClassVersion::Uac2
+        } else {
+            AudioClassVersion::Uac1
+        };
+
+        let mut terminals = vec![];
+        let mut clock_sources = vec![];
+
+        for descriptor in interface
+            .extra
+            .iter()
+            .filter(|descriptor| descriptor.len() >= 4 && descriptor[1] == CS_INTERFACE)
+        {
+            match (descriptor[2], version) {
+                (AC_INPUT_TERMINAL, _) if descriptor.len() >= 8 => terminals.push(Terminal {
+                    id: descriptor[3],
+                    kind: TerminalKind::Input,
+                    terminal_type: u16::from_le_bytes([descriptor[4], descriptor[5]]),
+                    clock_source: (version == AudioClassVersion::Uac2).then_some(descriptor[7]),
+                }),
+                (AC_OUTPUT_TERMINAL, _) if descriptor.len() >= 9 => terminals.push(Terminal {
+                    id: descriptor[3],
+                    kind: TerminalKind::Output,
+                    terminal_type: u16::from_le_bytes([descriptor[4], descriptor[5]]),
+                    clock_source: (version == AudioClassVersion::Uac2).then_some(descriptor[8]),
+                }),
+                (AC_CLOCK_SOURCE, AudioClassVersion::Uac2) => clock_sources.push(descriptor[3]),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            interface_number: interface.interface_number,
+            version,
+            terminals,
+            clock_sources,
+        })
+    }
+
+    /// Returns the terminal with the given ID, if the function has one.
+    pub fn terminal(&self, id: u8) -> Option<&Terminal> {
+        self.terminals.iter().find(|terminal| terminal.id == id)
+    }
+}
+
+/// A single alternate setting of an AudioStreaming interface; which describes one sample format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamingFormat {
+    /// The interface number of the AudioStreaming interface.
+    pub interface_number: u8,
+
+    /// The alternate setting that selects this format.
+    pub alternate_setting: u8,
+
+    /// The ID of the terminal this interface's endpoint is connected to.
+    pub terminal_link: u8,
+
+    /// The number of audio channels in each frame.
+    pub channels: u8,
+
+    /// The number of bytes used to carry each sample.
+    pub subslot_size: u8,
+
+    /// The number of meaningful bits in each sample.
+    pub bit_resolution: u8,
+
+    /// The discrete sample rates this format supports. Empty for UAC2 functions, which describe
+    /// their sample rates via their clock sources, instead; and for UAC1 functions that support
+    /// a continuous range, which is described by [StreamingFormat::sample_rate_range].
+    pub sample_rates: Vec<u32>,
+
+    /// The continuous range of sample rates supported, for UAC1 functions that provide one.
+    pub sample_rate_range: Option<(u32, u32)>,
+
+    /// The isochronous endpoint that carries the audio data.
+    pub data_endpoint: EndpointDescriptor,
+
+    /// The isochronous endpoint that carries clock feedback, if the format uses one.
+    pub feedback_endpoint: Option<EndpointDescriptor>,
+}
+
+impl StreamingFormat {
+    /// Parses a (non-zero-bandwidth) alternate setting of an AudioStreaming interface.
+    ///
+    /// Returns Ok(None) for alternate settings with no data endpoint; such as the zero-bandwidth one.
+    pub fn parse(
+        interface: &InterfaceDescriptor,
+        version: AudioClassVersion,
+    ) -> UsbResult<Option<Self>> {
+        if interface.interface_class != AUDIO_CLASS
+            || interface.interface_subclass != AUDIO_STREAMING_SUBCLASS
+        {
+            return Err(Error::InvalidInterface);
+        }
+
+        // The data endpoint is the first isochronous endpoint that isn't a feedback endpoint;
+        // and the feedback endpoint, if present, runs in the other direction.
+        let is_feedback = |endpoint: &EndpointDescriptor| ((endpoint.attributes >> 4) & 0b11) == 1;
+        let data_endpoint = match interface.endpoints.iter().find(|endpoint| {
+            endpoint.transfer_type() == TransferType::Isochronous && !is_feedback(endpoint)
+        }) {
+            Some(endpoint) => endpoint.clone(),
+            None => return Ok(None),
+        };
+        let feedback_endpoint = interface
+            .endpoints
+            .iter()
+            .find(|endpoint| {
+                endpoint.transfer_type() == TransferType::Isochronous
+                    && endpoint.direction() != data_endpoint.direction()
+            })
+            .cloned();
+
+        let mut format = Self {
+            interface_number: interface.interface_number,
+            alternate_setting: interface.alternate_setting,
+            terminal_link: 0,
+            channels: 0,
+            subslot_size: 0,
+            bit_resolution: 0,
+            sample_rates: vec![],
+            sample_rate_range: None,
+            data_endpoint,
+            feedback_endpoint,
+        };
+
+        for descriptor in interface
+            .extra
+            .iter()
+            .filter(|descriptor| descriptor.len() >= 4 && descriptor[1] == CS_INTERFACE)
+        {
+            match (descriptor[2], version) {
+                (AS_GENERAL, AudioClassVersion::Uac1) => format.terminal_link = descriptor[3],
+                (AS_GENERAL, AudioClassVersion::Uac2) if descriptor.len() >= 11 => {
+                    format.terminal_link = descriptor[3];
+                    format.channels = descriptor[10];
+                }
+                (AS_FORMAT_TYPE, AudioClassVersion::Uac1) if descriptor.len() >= 8 => {
+                    format.channels = descriptor[4];
+                    format.subslot_size = descriptor[5];
+                    format.bit_resolution = descriptor[6];
+
+                    // UAC1 sample rates are three-byte values; either a discrete list, or
+                    // (if the count is zero) a minimum and maximum.
+                    let rates: Vec<u32> = descriptor[8..]
+                        .chunks_exact(3)
+                        .map(|rate| u32::from_le_bytes([rate[0], rate[1], rate[2], 0]))
+                        .collect();
+                    if descriptor[7] == 0 {
+                        if let [minimum, maximum, ..] = rates[..] {
+                            format.sample_rate_range = Some((minimum, maximum));
+                        }
+                    } else {
+                        format.sample_rates =
+                            rates.into_iter().take(descriptor[7] as usize).collect();
+                    }
+                }
+                (AS_FORMAT_TYPE, AudioClassVersion::Uac2) if descriptor.len() >= 6 => {
+                    format.subslot_size = descriptor[4];
+                    format.bit_resolution = descriptor[5];
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Some(format))
+    }
+
+    /// Returns the direction audio flows on this format's data endpoint.
+    /// IN formats capture audio; OUT formats play it back.
+    pub fn direction(&self) -> Direction {
+        self.data_endpoint.direction()
+    }
+
+    /// Returns the size of a single audio frame (one sample for each channel), in bytes.
+    pub fn frame_size(&self) -> usize {
+        self.channels as usize * self.subslot_size as usize
+    }
+}
+
+/// Parsed form of an entire audio function: its AudioControl interface, and each of its formats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioFunction {
+    /// The function's AudioControl interface.
+    pub control: AudioControlInterface,
+
+    /// Each format provided by any of the function's AudioStreaming interfaces.
+    pub formats: Vec<StreamingFormat>,
+}
+
+impl AudioFunction {
+    /// Finds and parses the first audio function in a configuration.
+    pub fn find(configuration: &ConfigurationDescriptor) -> UsbResult<Self> {
+        let control = configuration
+            .interfaces
+            .iter()
+            .find(|interface| {
+                interface.interface_class == AUDIO_CLASS
+                    && interface.interface_subclass == AUDIO_CONTROL_SUBCLASS
+            })
+            .ok_or(Error::InvalidInterface)?;
+        let control = AudioControlInterface::parse(control)?;
+
+        let mut formats = vec![];
+        for interface in configuration.interfaces.iter().filter(|interface| {
+            interface.interface_class == AUDIO_CLASS
+                && interface.interface_subclass == AUDIO_STREAMING_SUBCLASS
+        }) {
+            if let Some(format) = StreamingFormat::parse(interface, control.version)? {
+                formats.push(format);
+            }
+        }
+
+        Ok(Self { control, formats })
+    }
+
+    /// Returns each of the formats that move audio in the given direction.
+    pub fn formats_for(&self, direction: Direction) -> impl Iterator<Item = &StreamingFormat> {
+        self.formats
+            .iter()
+            .filter(move |format| format.direction() == direction)
+    }
+
+    /// Returns the ID of the clock source that drives the given format; for UAC2 functions only.
+    pub fn clock_source_for(&self, format: &StreamingFormat) -> Option<u8> {
+        self.control.terminal(format.terminal_link)?.clock_source
+    }
+}
+
+/// Decodes the contents of a feedback endpoint packet into a sample rate, in audio frames
+/// per (micro)frame.
+///
+/// Full-speed devices send a three-byte 10.14 fixed-point value; high-speed devices send a
+/// four-byte 16.16 value. We go by the length of the packet, which is what the devices
+/// actually get right.
+pub fn decode_feedback(packet: &[u8]) -> UsbResult<f64> {
+    match *packet {
+        [a, b, c] => Ok(u32::from_le_bytes([a, b, c, 0]) as f64 / (1 << 14) as f64),
+        [a, b, c, d, ..] => Ok(u32::from_le_bytes([a, b, c, d]) as f64 / (1 << 16) as f64),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+/// Works out how many audio frames go into each isochronous OUT packet; spreading the
+/// fractional part of the rate across packets, and tracking the device's clock feedback.
+#[derive(Debug, Clone)]
+pub struct PacketScheduler {
+    /// The number of audio frames per packet we're currently targeting.
+    frames_per_packet: f64,
+
+    /// The fractional audio frames we owe the device from previous packets.
+    remainder: f64,
+}
+
+impl PacketScheduler {
+    /// Creates a scheduler for the given sample rate, and packet rate (in packets per second;
+    /// e.g. 1000 for full speed, or 8000 for high speed with an interval of one).
+    pub fn new(sample_rate: u32, packets_per_second: u32) -> Self {
+        Self {
+            frames_per_packet: sample_rate as f64 / packets_per_second as f64,
+            remainder: 0.0,
+        }
+    }
+
+    /// Updates the scheduler with a packet received on the feedback endpoint.
+    pub fn apply_feedback(&mut self, packet: &[u8]) -> UsbResult<()> {
+        let frames_per_packet = decode_feedback(packet)?;
+
+        // Devices send zero feedback while they're still settling; ignore it rather than starve them.
+        if frames_per_packet > 0.0 {
+            self.frames_per_packet = frames_per_packet;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of audio frames that should be sent in the next packet.
+    pub fn next_packet_frames(&mut self) -> usize {
+        self.remainder += self.frames_per_packet;
+        let frames = self.remainder.floor();
+        self.remainder -= frames;
+
+        frames as usize
+    }
+
+    /// Returns the number of audio frames per packet the scheduler is currently targeting.
+    pub fn frames_per_packet(&self) -> f64 {
+        self.frames_per_packet
+    }
+}
+
+/// Works out the largest packet an isochronous endpoint moves, and how many packets it moves
+/// per second; assuming full speed if we don't know the device's speed.
+#[cfg(feature = "async")]
+fn iso_endpoint_timing(endpoint: &EndpointDescriptor, speed: Option<DeviceSpeed>) -> (usize, u32) {
+    // High-bandwidth endpoints can move up to three transactions per microframe.
+    let transactions = 1 + ((endpoint.max_packet_size >> 11) & 0b11) as usize;
+    let interval = endpoint
+        .service_interval(speed.unwrap_or(DeviceSpeed::Full))
+        .unwrap_or(Duration::from_millis(1));
+    let packets_per_second = (1_000_000 / interval.as_micros().max(1)) as u32;
+
+    (
+        endpoint.packet_size() * transactions,
+        packets_per_second.max(1),
+    )
+}
+
+/// Plays audio out of an isochronous OUT endpoint; as an [AsyncWrite] of raw audio frames.
+/// Created by [AudioDevice::playback_stream].
+///
+/// Keeps a ring of transfers queued back-to-back, each divided into packets by a
+/// [PacketScheduler]; which, for asynchronous endpoints, follows the rate the device reports
+/// on its feedback endpoint. Written data is collected until there's a full transfer's worth,
+/// or until the stream is flushed.
+#[cfg(feature = "async")]
+pub struct PlaybackStream<'a> {
+    /// The device the endpoint belongs to.
+    device: &'a mut Device,
+
+    /// The address of the endpoint we write to.
+    endpoint: u8,
+
+    /// The largest packet the endpoint accepts.
+    packet_size: usize,
+
+    /// The size of a single audio frame, in bytes.
+    frame_size: usize,
+
+    /// Works out how many audio frames go in each packet.
+    scheduler: PacketScheduler,
+
+    /// Which frame each of our transfers should start in.
+    schedule: FrameSchedule,
+
+    /// Our queued transfers, oldest first.
+    queued: VecDeque<UsbFuture<IsoWriteCompletion>>,
+
+    /// Data our caller has written, but that we haven't yet queued.
+    pending: Vec<u8>,
+
+    /// The feedback endpoint's address and packet size, if the format has one.
+    feedback_endpoint: Option<(u8, usize)>,
+
+    /// Our read of the feedback endpoint, if one is in flight.
+    feedback: Option<UsbFuture<IsoReadCompletion>>,
+}
+
+#[cfg(feature = "async")]
+impl<'a> PlaybackStream<'a> {
+    /// Creates a stream that plays the given format, at the given sample rate.
+    fn new(device: &'a mut Device, format: &StreamingFormat, sample_rate: u32) -> Self {
+        let (packet_size, packets_per_second) =
+            iso_endpoint_timing(&format.data_endpoint, device.speed());
+
+        let mut schedule = FrameSchedule::new();
+        schedule.set_packets_per_frame((packets_per_second / 1000) as usize);
+
+        Self {
+            device,
+            endpoint: format.data_endpoint.address & 0x7f,
+            packet_size,
+            frame_size: format.frame_size().max(1),
+            scheduler: PacketScheduler::new(sample_rate, packets_per_second),
+            schedule,
+            queued: VecDeque::new(),
+            pending: Vec::new(),
+            feedback_endpoint: format
+                .feedback_endpoint
+                .as_ref()
+                .map(|endpoint| (endpoint.address | 0x80, endpoint.packet_size())),
+            feedback: None,
+        }
+    }
+
+    /// Returns the scheduler sizing our packets; e.g. to see the rate the device asked for.
+    pub fn scheduler(&self) -> &PacketScheduler {
+        &self.scheduler
+    }
+
+    /// Works out the packet lengths for our next transfer. Unless `partial` is set, returns
+    /// None if we haven't collected enough data to fill every packet.
+    fn next_packet_lengths(&mut self, partial: bool) -> Option<Vec<usize>> {
+        // We only commit to the scheduler's new state once we know we're sending.
+        let mut scheduler = self.scheduler.clone();
+        let mut lengths = Vec::with_capacity(AUDIO_PACKETS_PER_TRANSFER);
+        let mut remaining = self.pending.len();
+
+        while lengths.len() < AUDIO_PACKETS_PER_TRANSFER && remaining > 0 {
+            let length = (scheduler.next_packet_frames() * self.frame_size).min(self.packet_size);
+            if length > remaining && !partial {
+                return None;
+            }
+
+            lengths.push(length.min(remaining));
+            remaining -= length.min(remaining);
+        }
+
+        if lengths.len() < AUDIO_PACKETS_PER_TRANSFER && !partial {
+            return None;
+        }
+
+        self.scheduler = scheduler;
+        Some(lengths)
+    }
+
+    /// Queues as many transfers as we have data and room for; sending whatever we have left,
+    /// even if it won't fill a transfer, if `partial` is set.
+    fn submit_ready(&mut self, partial: bool) -> io::Result<bool> {
+        let mut submitted = false;
+
+        while self.queued.len() < PLAYBACK_RING_DEPTH {
+            let lengths = match self.next_packet_lengths(partial) {
+                Some(lengths) if !lengths.is_empty() => lengths,
+                _ => break,
+            };
+            let total = lengths.iter().sum();
+
+            let options = IsoOptions {
+                start_frame: self.schedule.schedule(self.device, lengths.len()),
+                ..IsoOptions::default()
+            };
+            let data = Arc::new(self.pending[..total].to_vec());
+            let future =
+                self.device
+                    .write_isochronous_async(self.endpoint, data, &lengths, &options);
+
+            // If we couldn't submit, our schedule is probably stale; resynchronize next time.
+            match future {
+                Ok(future) => self.queued.push_back(future),
+                Err(error) => {
+                    self.schedule.resynchronize();
+                    return Err(error.into());
+                }
+            }
+
+            self.pending.drain(..total);
+            submitted = true;
+        }
+
+        Ok(submitted)
+    }
+
+    /// Reaps our completed transfers, oldest first; stopping at the first still in flight.
+    fn poll_completions(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        while let Some(future) = self.queued.front_mut() {
+            let Poll::Ready(result) = Pin::new(future).poll(cx) else {
+                break;
+            };
+            self.queued.pop_front();
+
+            // A failed transfer means we've lost our place in the schedule.
+            if let Err(error) = result {
+                self.schedule.resynchronize();
+                return Err(error.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies whatever the device has sent on its feedback endpoint, and keeps a read of it
+    /// in flight.
+    fn poll_feedback(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let Some((endpoint, packet_size)) = self.feedback_endpoint else {
+            return Ok(());
+        };
+
+        loop {
+            let future = match self.feedback.as_mut() {
+                Some(future) => future,
+                None => {
+                    let buffer = Arc::new(RwLock::new(vec![0; packet_size]));
+                    let future = self.device.read_isochronous_async(
+                        endpoint,
+                        buffer,
+                        &[packet_size],
+                        &IsoOptions::default(),
+                    )?;
+                    self.feedback.insert(future)
+                }
+            };
+
+            let Poll::Ready(result) = Pin::new(future).poll(cx) else {
+                return Ok(());
+            };
+            self.feedback = None;
+
+            // A lost feedback packet just means we keep our current rate for a little longer.
+            for packet in result?.to_packets() {
+                if packet.status.is_ok() {
+                    let _ = self.scheduler.apply_feedback(&packet.data);
+                }
+            }
+        }
+    }
+
+    /// Sends everything we've been given, and waits for it to finish playing.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            self.poll_feedback(cx)?;
+            self.submit_ready(true)?;
+            self.poll_completions(cx)?;
+
+            // If we still have a transfer in flight, it's already registered to wake us.
+            if !self.queued.is_empty() {
+                return Poll::Pending;
+            }
+            if self.pending.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncWrite for PlaybackStream<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let capacity = this.packet_size * AUDIO_PACKETS_PER_TRANSFER;
+
+        loop {
+            this.poll_feedback(cx)?;
+            this.poll_completions(cx)?;
+
+            // Take as much as we can hold, and send off any transfers that fills...
+            let length = capacity.saturating_sub(this.pending.len()).min(buf.len());
+            this.pending.extend_from_slice(&buf[..length]);
+            let submitted = this.submit_ready(false)?;
+
+            if length > 0 || buf.is_empty() {
+                return Poll::Ready(Ok(length));
+            }
+
+            // ... and if we couldn't take anything, and couldn't make room, our ring is full;
+            // and its oldest transfer will wake us once it's done.
+            if !submitted {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_drain(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_drain(cx)
+    }
+}
+
+/// A USB audio device, driven via its AudioControl and AudioStreaming interfaces.
+#[derive(Debug)]
+pub struct AudioDevice {
+    /// The device we're driving.
+    device: Device,
+
+    /// Our parsed view of the audio function.
+    function: AudioFunction,
+
+    /// The interfaces we've claimed.
+    claimed: Vec<u8>,
+
+    /// The timeout applied to each individual transfer.
+    timeout: Option<Duration>,
+}
+
+impl AudioDevice {
+    /// Creates an audio device from an opened device, using its first audio function.
+    pub fn new(mut device: Device) -> UsbResult<Self> {
+        let configuration = device.read_active_configuration_descriptor()?;
+        let function = AudioFunction::find(&configuration)?;
+
+        Ok(Self {
+            device,
+            function,
+            claimed: vec![],
+            timeout: None,
+        })
+    }
+
+    /// Sets the timeout applied to each transfer; or None to wait indefinitely.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns our parsed view of the audio function.
+    pub fn function(&self) -> &AudioFunction {
+        &self.function
+    }
+
+    /// Provides access to the underlying device.
+    pub fn device(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    /// Releases each of the interfaces we've claimed, and returns the underlying device.
+    pub fn into_device(mut self) -> UsbResult<Device> {
+        for interface in std::mem::take(&mut self.claimed) {
+            self.device.unclaim_interface(interface)?;
+        }
+
+        Ok(self.device)
+    }
+
+    /// Claims an interface, if we haven't already.
+    fn ensure_claimed(&mut self, interface_number: u8) -> UsbResult<()> {
+        if !self.claimed.contains(&interface_number) {
+            self.device.claim_interface(interface_number)?;
+            self.claimed.push(interface_number);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the sample rate used by the given format.
+    ///
+    /// UAC1 functions set sample rates per endpoint; UAC2 functions set them on the clock source
+    /// that drives the format, which may be shared with other formats.
+    pub fn set_sample_rate(&mut self, format: &StreamingFormat, sample_rate: u32) -> UsbResult<()> {
+        match self.function.control.version {
+            AudioClassVersion::Uac1 => {
+                self.ensure_claimed(format.interface_number)?;
+                self.device.control_write(
+                    CLASS_OUT_TO_ENDPOINT,
+                    SET_CUR,
+                    (SAMPLING_FREQUENCY_CONTROL as u16) << 8,
+                    format.data_endpoint.address as u16,
+                    &sample_rate.to_le_bytes()[..3],
+                    self.timeout,
+                )
+            }
+            AudioClassVersion::Uac2 => {
+                let clock = self
+                    .function
+                    .clock_source_for(format)
+                    .ok_or(Error::Unsupported)?;
+                let control_interface = self.function.control.interface_number;
+
+                self.ensure_claimed(control_interface)?;
+                self.device.control_write(
+                    CLASS_OUT_TO_INTERFACE,
+                    SET_CUR,
+                    (SAMPLING_FREQUENCY_CONTROL as u16) << 8,
+                    ((clock as u16) << 8) | control_interface as u16,
+                    &sample_rate.to_le_bytes(),
+                    self.timeout,
+                )
+            }
+        }
+    }
+
+    /// Reads back the sample rate currently used by the given format.
+    pub fn sample_rate(&mut self, format: &StreamingFormat) -> UsbResult<u32> {
+        let data = match self.function.control.version {
+            AudioClassVersion::Uac1 => {
+                self.ensure_claimed(format.interface_number)?;
+                self.device.control_read_to_vec(
+                    CLASS_IN_FROM_ENDPOINT,
+                    GET_CUR,
+                    (SAMPLING_FREQUENCY_CONTROL as u16) << 8,
+                    format.data_endpoint.address as u16,
+                    3,
+                    self.timeout,
+                )?
+            }
+            AudioClassVersion::Uac2 => {
+                let clock = self
+                    .function
+                    .clock_source_for(format)
+                    .ok_or(Error::Unsupported)?;
+                let control_interface = self.function.control.interface_number;
+
+                // UAC2 uses the same request number (CUR) for reading and writing.
+                self.ensure_claimed(control_interface)?;
+                self.device.control_read_to_vec(
+                    CLASS_IN_FROM_INTERFACE,
+                    SET_CUR,
+                    (SAMPLING_FREQUENCY_CONTROL as u16) << 8,
+                    ((clock as u16) << 8) | control_interface as u16,
+                    4,
+                    self.timeout,
+                )?
+            }
+        };
+
+        match data[..] {
+            [a, b, c] => Ok(u32::from_le_bytes([a, b, c, 0])),
+            [a, b, c, d] => Ok(u32::from_le_bytes([a, b, c, d])),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Selects the given format, which starts the device streaming on its endpoints.
+    pub fn start_stream(&mut self, format: &StreamingFormat) -> UsbResult<()> {
+        self.ensure_claimed(format.interface_number)?;
+        self.device
+            .set_alternate_setting(format.interface_number, format.alternate_setting)
+    }
+
+    /// Returns the given format's interface to its zero-bandwidth setting, stopping its stream.
+    pub fn stop_stream(&mut self, format: &StreamingFormat) -> UsbResult<()> {
+        self.device
+            .set_alternate_setting(format.interface_number, 0)
+    }
+
+    /// Selects the given capture format, and returns a stream of the packets it produces;
+    /// each of which carries whole audio frames. Fails with [Error::InvalidArgument] for
+    /// playback formats.
+    #[cfg(feature = "async")]
+    pub fn capture_stream(&mut self, format: &StreamingFormat) -> UsbResult<IsoStream<'_>> {
+        if format.direction() != Direction::In {
+            return Err(Error::InvalidArgument);
+        }
+        self.start_stream(format)?;
+
+        let (packet_size, packets_per_second) =
+            iso_endpoint_timing(&format.data_endpoint, self.device.speed());
+        let mut stream = self.device.iso_stream(
+            format.data_endpoint.address,
+            packet_size,
+            AUDIO_PACKETS_PER_TRANSFER,
+        );
+        stream.set_packets_per_frame((packets_per_second / 1000) as usize);
+
+        Ok(stream)
+    }
+
+    /// Selects the given playback format, and returns a stream that plays whatever audio
+    /// frames are written to it, at the given sample rate. Fails with [Error::InvalidArgument]
+    /// for capture formats.
+    ///
+    /// The sample rate should match the one set with [AudioDevice::set_sample_rate]; it's
+    /// used to size packets until the device sends its first feedback.
+    #[cfg(feature = "async")]
+    pub fn playback_stream(
+        &mut self,
+        format: &StreamingFormat,
+        sample_rate: u32,
+    ) -> UsbResult<PlaybackStream<'_>> {
+        if format.direction() != Direction::Out {
+            return Err(Error::InvalidArgument);
+        }
+        self.start_stream(format)?;
+
+        Ok(PlaybackStream::new(&mut self.device, format, sample_rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an audio interface descriptor with the given class-specific descriptors.
+    fn audio_interface(
+        subclass: u8,
+        version: AudioClassVersion,
+        endpoints: Vec<EndpointDescriptor>,
+        extra: Vec<Vec<u8>>,
+    ) -> InterfaceDescriptor {
+        InterfaceDescriptor {
+            interface_number: if subclass == AUDIO_CONTROL_SUBCLASS {
+                0
+            } else {
+                1
+            },
+            alternate_setting: if endpoints.is_empty() { 0 } else { 1 },
+            num_endpoints: endpoints.len() as u8,
+            interface_class: AUDIO_CLASS,
+            interface_subclass: subclass,
+            interface_protocol: match version {
+                AudioClassVersion::Uac1 => 0x00,
+                AudioClassVersion::Uac2 => UAC2_PROTOCOL,
+            },
+            interface_string_index: 0,
+            endpoints,
+            extra,
+        }
+    }
+
+    /// Builds an isochronous endpoint; with the given bmAttributes, to pick its sync and usage.
+    fn iso_endpoint(address: u8, attributes: u8) -> EndpointDescriptor {
+        EndpointDescriptor {
+            address,
+            attributes,
+            max_packet_size: 192,
+            interval: 1,
+            extra: vec![],
+        }
+    }
+
+    /// Builds a UAC2 AudioControl interface: a USB streaming input terminal and a speaker,
+    /// both driven by clock source 9.
+    fn uac2_control() -> InterfaceDescriptor {
+        audio_interface(
+            AUDIO_CONTROL_SUBCLASS,
+            AudioClassVersion::Uac2,
+            vec![],
+            vec![
+                vec![0x09, CS_INTERFACE, 0x01, 0x00, 0x02, 0x01, 0x40, 0x00, 0x00],
+                vec![
+                    0x08,
+                    CS_INTERFACE,
+                    AC_CLOCK_SOURCE,
+                    0x09,
+                    0x01,
+                    0x01,
+                    0x00,
+                    0x00,
+                ],
+                vec![
+                    0x11,
+                    CS_INTERFACE,
+                    AC_INPUT_TERMINAL,
+                    0x01,
+                    0x01,
+                    0x01,
+                    0x00,
+                    0x09,
+                    0x02,
+                    0x03,
+                    0x00,
+                    0x00,
+                    0x00,
+                    0x00,
+                    0x00,
+                    0x00,
+                    0x00,
+                ],
+                vec![
+                    0x0C,
+                    CS_INTERFACE,
+                    AC_OUTPUT_TERMINAL,
+                    0x03,
+                    0x01,
+                    0x03,
+                    0x00,
+                    0x01,
+                    0x09,
+                    0x00,
+                    0x00,
+                    0x00,
+                ],
+            ],
+        )
+    }
+
+    #[test]
+    fn uac1_control_interfaces_parse() {
+        let interface = audio_interface(
+            AUDIO_CONTROL_SUBCLASS,
+            AudioClassVersion::Uac1,
+            vec![],
+            vec![
+                vec![
+                    0x0C,
+                    CS_INTERFACE,
+                    AC_INPUT_TERMINAL,
+                    0x01,
+                    0x01,
+                    0x02,
+                    0x00,
+                    0x01,
+                    0x01,
+                    0x00,
+                    0x00,
+                    0x00,
+                ],
+                // Truncated terminals are skipped, rather than read past.
+                vec![
+                    0x07,
+                    CS_INTERFACE,
+                    AC_OUTPUT_TERMINAL,
+                    0x02,
+                    0x01,
+                    0x01,
+                    0x00,
+                ],
+                // Clock sources don't exist in UAC1; so this is ignored.
+                vec![
+                    0x08,
+                    CS_INTERFACE,
+                    AC_CLOCK_SOURCE,
+                    0x09,
+                    0x01,
+                    0x01,
+                    0x00,
+                    0x00,
+                ],
+            ],
+        );
+
+        let control = AudioControlInterface::parse(&interface).unwrap();
+        assert_eq!(control.version, AudioClassVersion::Uac1);
+        assert_eq!(
+            control.terminals,
+            vec![Terminal {
+                id: 1,
+                kind: TerminalKind::Input,
+                terminal_type: 0x0201,
+                clock_source: None,
+            }]
+        );
+        assert!(control.clock_sources.is_empty());
+    }
+
+    #[test]
+    fn uac2_control_interfaces_parse() {
+        let control = AudioControlInterface::parse(&uac2_control()).unwrap();
+        assert_eq!(control.version, AudioClassVersion::Uac2);
+        assert_eq!(control.clock_sources, vec![9]);
+
+        let input = control.terminal(1).unwrap();
+        assert_eq!(input.terminal_type, Terminal::USB_STREAMING);
+        assert_eq!(input.clock_source, Some(9));
+
+        let output = control.terminal(3).unwrap();
+        assert_eq!(output.kind, TerminalKind::Output);
+        assert_eq!(output.terminal_type, 0x0301);
+        assert_eq!(output.clock_source, Some(9));
+    }
+
+    #[test]
+    fn streaming_interfaces_arent_control_interfaces() {
+        let interface = audio_interface(
+            AUDIO_STREAMING_SUBCLASS,
+            AudioClassVersion::Uac1,
+            vec![],
+            vec![],
+        );
+        assert_eq!(
+            AudioControlInterface::parse(&interface),
+            Err(Error::InvalidInterface)
+        );
+        assert_eq!(
+            StreamingFormat::parse(&uac2_control(), AudioClassVersion::Uac2),
+            Err(Error::InvalidInterface)
+        );
+    }
+
+    #[test]
+    fn uac1_discrete_rates_parse() {
+        let interface = audio_interface(
+            AUDIO_STREAMING_SUBCLASS,
+            AudioClassVersion::Uac1,
+            vec![iso_endpoint(0x81, 0x05)],
+            vec![
+                vec![0x07, CS_INTERFACE, AS_GENERAL, 0x02, 0x01, 0x01, 0x00],
+                vec![
+                    0x0E,
+                    CS_INTERFACE,
+                    AS_FORMAT_TYPE,
+                    0x01,
+                    0x02,
+                    0x02,
+                    0x10,
+                    0x02,
+                    0x44,
+                    0xAC,
+                    0x00,
+                    0x80,
+                    0xBB,
+                    0x00,
+                ],
+            ],
+        );
+
+        let format = StreamingFormat::parse(&interface, AudioClassVersion::Uac1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(format.alternate_setting, 1);
+        assert_eq!(format.terminal_link, 2);
+        assert_eq!(format.direction(), Direction::In);
+        assert_eq!(format.frame_size(), 4);
+        assert_eq!(format.bit_resolution, 16);
+        assert_eq!(format.sample_rates, vec![44_100, 48_000]);
+        assert_eq!(format.sample_rate_range, None);
+        assert_eq!(format.feedback_endpoint, None);
+    }
+
+    #[test]
+    fn uac1_continuous_rates_parse() {
+        let interface = audio_interface(
+            AUDIO_STREAMING_SUBCLASS,
+            AudioClassVersion::Uac1,
+            vec![iso_endpoint(0x01, 0x09)],
+            vec![vec![
+                0x0E,
+                CS_INTERFACE,
+                AS_FORMAT_TYPE,
+                0x01,
+                0x01,
+                0x03,
+                0x18,
+                0x00,
+                0x40,
+                0x1F,
+                0x00,
+                0x00,
+                0x77,
+                0x01,
+            ]],
+        );
+
+        let format = StreamingFormat::parse(&interface, AudioClassVersion::Uac1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(format.frame_size(), 3);
+        assert!(format.sample_rates.is_empty());
+        assert_eq!(format.sample_rate_range, Some((8_000, 96_000)));
+    }
+
+    #[test]
+    fn uac2_formats_parse_with_feedback() {
+        let interface = audio_interface(
+            AUDIO_STREAMING_SUBCLASS,
+            AudioClassVersion::Uac2,
+            vec![iso_endpoint(0x81, 0x11), iso_endpoint(0x01, 0x05)],
+            vec![
+                vec![
+                    0x10,
+                    CS_INTERFACE,
+                    AS_GENERAL,
+                    0x01,
+                    0x00,
+                    0x01,
+                    0x01,
+                    0x00,
+                    0x00,
+                    0x00,
+                    0x02,
+                    0x03,
+                    0x00,
+                    0x00,
+                    0x00,
+                    0x00,
+                ],
+                vec![0x06, CS_INTERFACE, AS_FORMAT_TYPE, 0x01, 0x04, 0x18],
+            ],
+        );
+
+        // The feedback endpoint comes first; but it's not the one that carries the audio.
+        let format = StreamingFormat::parse(&interface, AudioClassVersion::Uac2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(format.direction(), Direction::Out);
+        assert_eq!(format.data_endpoint.address, 0x01);
+        assert_eq!(format.feedback_endpoint.as_ref().unwrap().address, 0x81);
+        assert_eq!(format.terminal_link, 1);
+        assert_eq!(format.channels, 2);
+        assert_eq!(format.frame_size(), 8);
+        assert_eq!(format.bit_resolution, 24);
+    }
+
+    #[test]
+    fn zero_bandwidth_settings_arent_formats() {
+        let interface = audio_interface(
+            AUDIO_STREAMING_SUBCLASS,
+            AudioClassVersion::Uac2,
+            vec![],
+            vec![],
+        );
+        assert_eq!(
+            StreamingFormat::parse(&interface, AudioClassVersion::Uac2),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn functions_link_formats_to_their_clocks() {
+        let streaming = audio_interface(
+            AUDIO_STREAMING_SUBCLASS,
+            AudioClassVersion::Uac2,
+            vec![iso_endpoint(0x01, 0x0D)],
+            vec![
+                vec![
+                    0x10,
+                    CS_INTERFACE,
+                    AS_GENERAL,
+                    0x01,
+                    0x00,
+                    0x01,
+                    0x01,
+                    0x00,
+                    0x00,
+                    0x00,
+                    0x02,
+                    0x03,
+                    0x00,
+                    0x00,
+                    0x00,
+                    0x00,
+                ],
+                vec![0x06, CS_INTERFACE, AS_FORMAT_TYPE, 0x01, 0x02, 0x10],
+            ],
+        );
+        let mut zero_bandwidth = streaming.clone();
+        zero_bandwidth.alternate_setting = 0;
+        zero_bandwidth.endpoints.clear();
+
+        let configuration = ConfigurationDescriptor {
+            total_length: 0,
+            num_interfaces: 2,
+            configuration_value: 1,
+            configuration_string_index: 0,
+            attributes: 0x80,
+            max_power: 50,
+            interfaces: vec![uac2_control(), zero_bandwidth, streaming],
+            extra: vec![],
+        };
+
+        let function = AudioFunction::find(&configuration).unwrap();
+        assert_eq!(function.formats.len(), 1);
+        assert_eq!(function.formats_for(Direction::In).count(), 0);
+
+        let format = function.formats_for(Direction::Out).next().unwrap();
+        assert_eq!(function.clock_source_for(format), Some(9));
+    }
+
+    #[test]
+    fn feedback_is_decoded_by_length() {
+        // 48 frames per millisecond, in full speed's 10.14 format...
+        assert_eq!(decode_feedback(&[0x00, 0x00, 0x0C]), Ok(48.0));
+
+        // ... and 6.125 frames per microframe, in high speed's 16.16 format.
+        assert_eq!(decode_feedback(&[0x00, 0x20, 0x06, 0x00]), Ok(6.125));
+
+        assert_eq!(decode_feedback(&[0x00, 0x0C]), Err(Error::InvalidArgument));
+    }
+}
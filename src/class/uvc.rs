@@ -0,0 +1,973 @@
+//! Helpers for working with USB Video Class (UVC) devices.
+//!
+//! Getting video out of a UVC camera happens in three steps: we negotiate a format with the
+//! camera via the VideoStreaming interface's probe and commit controls; we select an alternate
+//! setting with enough bandwidth for the payloads the camera told us to expect; and then we
+//! reassemble the payloads the camera sends us into complete frames.
+//!
+//! Cameras that stream over bulk endpoints are fully supported here. Isochronous cameras need
+//! the `async` feature; their frames are reassembled from an [crate::iso::IsoStream] by an
+//! [IsoFrameStream].
+
+use std::time::Duration;
+#[cfg(feature = "async")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "async")]
+use futures_core::Stream;
+
+use crate::{
+    descriptors::{ConfigurationDescriptor, InterfaceDescriptor, TransferType},
+    device::Device,
+    request::{ClassCode, Direction, CLASS_IN_FROM_INTERFACE, CLASS_OUT_TO_INTERFACE},
+    Error, UsbResult,
+};
+#[cfg(feature = "async")]
+use crate::{
+    futures::block_on,
+    iso::{IsoPacket, IsoStream},
+};
+
+/// The USB class code assigned to video devices.
+pub const VIDEO_CLASS: u8 = ClassCode::Video as u8;
+
+/// The video subclass code assigned to VideoControl interfaces.
+pub const VIDEO_CONTROL_SUBCLASS: u8 = 0x01;
+
+/// The video subclass code assigned to VideoStreaming interfaces.
+pub const VIDEO_STREAMING_SUBCLASS: u8 = 0x02;
+
+/// Descriptor type for class-specific interface descriptors.
+const CS_INTERFACE: u8 = 0x24;
+
+/// Descriptor subtype of the VideoControl interface's header descriptor.
+const VC_HEADER: u8 = 0x01;
+
+/// Control selector for the VideoStreaming probe control.
+const VS_PROBE_CONTROL: u8 = 0x01;
+
+/// Control selector for the VideoStreaming commit control.
+const VS_COMMIT_CONTROL: u8 = 0x02;
+
+/// The number of packets in each isochronous transfer we queue; 32 packets is 4ms of video
+/// on a high-speed camera.
+#[cfg(feature = "async")]
+const ISO_PACKETS_PER_TRANSFER: usize = 32;
+
+/// Class-specific requests used to read and write UVC controls.
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum VideoRequest {
+    SetCurrent = 0x01,
+    GetCurrent = 0x81,
+    GetMinimum = 0x82,
+    GetMaximum = 0x83,
+    GetResolution = 0x84,
+    GetLength = 0x85,
+    GetInfo = 0x86,
+    GetDefault = 0x87,
+}
+
+/// The video payload formats we know how to describe.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum FormatType {
+    /// Uncompressed video; e.g. YUY2 or NV12, as identified by the format's GUID.
+    Uncompressed,
+
+    /// Motion-JPEG; each frame is a standalone JPEG image.
+    Mjpeg,
+
+    /// Frame-based formats; e.g. H.264.
+    FrameBased,
+}
+
+impl FormatType {
+    /// Returns the format type described by a VS format descriptor subtype, if we know it.
+    fn from_format_subtype(subtype: u8) -> Option<Self> {
+        match subtype {
+            0x04 => Some(Self::Uncompressed),
+            0x06 => Some(Self::Mjpeg),
+            0x10 => Some(Self::FrameBased),
+            _ => None,
+        }
+    }
+
+    /// Returns the VS frame descriptor subtype that goes along with this format type.
+    fn frame_subtype(&self) -> u8 {
+        match self {
+            Self::Uncompressed => 0x05,
+            Self::Mjpeg => 0x07,
+            Self::FrameBased => 0x11,
+        }
+    }
+}
+
+/// A single frame size supported by a format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The index of this frame descriptor, as used in probe/commit.
+    pub index: u8,
+
+    /// The width of the frame, in pixels.
+    pub width: u16,
+
+    /// The height of the frame, in pixels.
+    pub height: u16,
+
+    /// The frame interval the camera would prefer, in 100ns units.
+    pub default_interval: u32,
+
+    /// Each discrete frame interval supported, in 100ns units. For cameras that
+    /// describe a continuous range instead, this is the minimum, maximum, and step.
+    pub intervals: Vec<u32>,
+}
+
+/// A video format supported by a VideoStreaming interface, along with its frame sizes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    /// The index of this format descriptor, as used in probe/commit.
+    pub index: u8,
+
+    /// What kind of format this is.
+    pub format_type: FormatType,
+
+    /// The format's GUID; for uncompressed and frame-based formats. All zeroes for MJPEG.
+    pub guid: [u8; 16],
+
+    /// Each of the frame sizes supported by the format.
+    pub frames: Vec<Frame>,
+}
+
+impl Format {
+    /// Returns the frame with the given index, if the format has one.
+    pub fn frame(&self, index: u8) -> Option<&Frame> {
+        self.frames.iter().find(|frame| frame.index == index)
+    }
+}
+
+/// Parses each of the formats (and their frames) from a VideoStreaming interface's descriptors.
+pub fn parse_formats(interface: &InterfaceDescriptor) -> Vec<Format> {
+    let mut formats: Vec<Format> = vec![];
+
+    for descriptor in interface
+        .extra
+        .iter()
+        .filter(|descriptor| descriptor.len() >= 4 && descriptor[1] == CS_INTERFACE)
+    {
+        // Format descriptors start a new format...
+        if let Some(format_type) = FormatType::from_format_subtype(descriptor[2]) {
+            let mut guid = [0; 16];
+            if format_type != FormatType::Mjpeg && descriptor.len() >= 21 {
+                guid.copy_from_slice(&descriptor[5..21]);
+            }
+
+            formats.push(Format {
+                index: descriptor[3],
+                format_type,
+                guid,
+                frames: vec![],
+            });
+            continue;
+        }
+
+        // ... and frame descriptors belong to whichever format most recently preceded them.
+        let format = match formats.last_mut() {
+            Some(format) if descriptor[2] == format.format_type.frame_subtype() => format,
+            _ => continue,
+        };
+        if let Some(frame) = parse_frame(descriptor, format.format_type) {
+            format.frames.push(frame);
+        }
+    }
+
+    formats
+}
+
+/// Parses a single VS frame descriptor.
+fn parse_frame(descriptor: &[u8], format_type: FormatType) -> Option<Frame> {
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = descriptor.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    // Frame-based frame descriptors lack dwMaxVideoFrameBufferSize; so everything after
+    // the bitrates sits four bytes earlier than in the uncompressed/MJPEG layout.
+    let interval_offset = match format_type {
+        FormatType::FrameBased => 17,
+        _ => 21,
+    };
+
+    let default_interval = read_u32(interval_offset)?;
+    let interval_type = *descriptor.get(interval_offset + 4)?;
+    let interval_count = if interval_type == 0 {
+        3
+    } else {
+        interval_type as usize
+    };
+    let first_interval = match format_type {
+        FormatType::FrameBased => interval_offset + 9,
+        _ => interval_offset + 5,
+    };
+
+    let intervals = (0..interval_count)
+        .filter_map(|index| read_u32(first_interval + index * 4))
+        .collect();
+
+    Some(Frame {
+        index: descriptor[3],
+        width: u16::from_le_bytes([descriptor[5], descriptor[6]]),
+        height: u16::from_le_bytes([descriptor[7], descriptor[8]]),
+        default_interval,
+        intervals,
+    })
+}
+
+/// The contents of the VideoStreaming probe and commit controls.
+#[derive(Copy, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamingControl {
+    /// Bitfield indicating which fields the camera should hold fixed during negotiation.
+    pub hint: u16,
+
+    /// The index of the format to use.
+    pub format_index: u8,
+
+    /// The index of the frame (size) to use.
+    pub frame_index: u8,
+
+    /// The frame interval to use, in 100ns units.
+    pub frame_interval: u32,
+
+    /// The key frame rate, for formats that have key frames.
+    pub key_frame_rate: u16,
+
+    /// The P-frame rate, for formats that have P-frames.
+    pub p_frame_rate: u16,
+
+    /// The compression quality, in abstract units from 0 to 10000.
+    pub compression_quality: u16,
+
+    /// The compression window size, in frames.
+    pub compression_window_size: u16,
+
+    /// The internal latency of the camera, in milliseconds.
+    pub delay: u16,
+
+    /// The largest frame the camera will send, in bytes.
+    pub max_video_frame_size: u32,
+
+    /// The largest payload the camera will send in a single transfer, in bytes.
+    pub max_payload_transfer_size: u32,
+}
+
+impl StreamingControl {
+    /// The size of the control in UVC 1.0. Later versions extend it, but we only
+    /// care about these fields; the rest are zeroed on the way out.
+    const MINIMUM_LENGTH: usize = 26;
+
+    /// Parses a probe/commit control from its raw bytes.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        if data.len() < Self::MINIMUM_LENGTH {
+            return Err(Error::InvalidArgument);
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+
+        Ok(Self {
+            hint: read_u16(0),
+            format_index: data[2],
+            frame_index: data[3],
+            frame_interval: read_u32(4),
+            key_frame_rate: read_u16(8),
+            p_frame_rate: read_u16(10),
+            compression_quality: read_u16(12),
+            compression_window_size: read_u16(14),
+            delay: read_u16(16),
+            max_video_frame_size: read_u32(18),
+            max_payload_transfer_size: read_u32(22),
+        })
+    }
+
+    /// Converts the control to its raw form, padded out to the given length.
+    pub fn to_bytes(&self, length: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(length.max(Self::MINIMUM_LENGTH));
+        data.extend_from_slice(&self.hint.to_le_bytes());
+        data.push(self.format_index);
+        data.push(self.frame_index);
+        data.extend_from_slice(&self.frame_interval.to_le_bytes());
+        data.extend_from_slice(&self.key_frame_rate.to_le_bytes());
+        data.extend_from_slice(&self.p_frame_rate.to_le_bytes());
+        data.extend_from_slice(&self.compression_quality.to_le_bytes());
+        data.extend_from_slice(&self.compression_window_size.to_le_bytes());
+        data.extend_from_slice(&self.delay.to_le_bytes());
+        data.extend_from_slice(&self.max_video_frame_size.to_le_bytes());
+        data.extend_from_slice(&self.max_payload_transfer_size.to_le_bytes());
+        data.resize(length.max(Self::MINIMUM_LENGTH), 0);
+
+        data
+    }
+
+    /// Returns the size of the probe/commit controls for a given UVC version (bcdUVC).
+    pub fn length_for_version(uvc_version: u16) -> usize {
+        match uvc_version {
+            0..=0x0100 => 26,
+            0x0101..=0x0110 => 34,
+            _ => 48,
+        }
+    }
+}
+
+/// Returns the smallest alternate setting of an interface whose isochronous endpoint can carry
+/// payloads of the given size each (micro)frame; or None if no alternate setting is big enough.
+///
+/// Alternate setting zero is never returned; by convention, it's the zero-bandwidth setting.
+pub fn select_alternate_setting(
+    configuration: &ConfigurationDescriptor,
+    interface_number: u8,
+    required_bandwidth: u32,
+) -> Option<u8> {
+    configuration
+        .interfaces
+        .iter()
+        .filter(|interface| {
+            interface.interface_number == interface_number && interface.alternate_setting != 0
+        })
+        .filter_map(|interface| {
+            let endpoint = interface.find_endpoint(TransferType::Isochronous, Direction::In)?;
+
+            // High-bandwidth endpoints can move up to three packets per microframe.
+            let transactions = 1 + ((endpoint.max_packet_size >> 11) & 0b11) as u32;
+            let bandwidth = endpoint.packet_size() as u32 * transactions;
+
+            (bandwidth >= required_bandwidth).then_some((bandwidth, interface.alternate_setting))
+        })
+        .min()
+        .map(|(_, alternate_setting)| alternate_setting)
+}
+
+/// The header that starts every UVC payload.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct PayloadHeader {
+    /// The length of the header, including this field.
+    pub length: usize,
+
+    /// The frame ID bit, which toggles each time a new frame starts.
+    pub frame_id: bool,
+
+    /// True iff this payload ends a frame.
+    pub end_of_frame: bool,
+
+    /// True iff the camera flagged an error in this payload.
+    pub error: bool,
+
+    /// The presentation timestamp, if the header carries one.
+    pub presentation_time: Option<u32>,
+}
+
+impl PayloadHeader {
+    /// Parses the header from the start of a payload.
+    pub fn parse(payload: &[u8]) -> UsbResult<Self> {
+        let length = *payload.first().ok_or(Error::InvalidArgument)? as usize;
+        if length < 2 || length > payload.len() {
+            return Err(Error::InvalidArgument);
+        }
+
+        let flags = payload[1];
+        let presentation_time = if (flags & 0x04) != 0 && length >= 6 {
+            Some(u32::from_le_bytes([
+                payload[2], payload[3], payload[4], payload[5],
+            ]))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            length,
+            frame_id: (flags & 0x01) != 0,
+            end_of_frame: (flags & 0x02) != 0,
+            error: (flags & 0x40) != 0,
+            presentation_time,
+        })
+    }
+}
+
+/// A complete video frame, reassembled from its payloads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoFrame {
+    /// The frame's data, in whatever format was negotiated.
+    pub data: Vec<u8>,
+
+    /// The presentation timestamp of the frame, if the camera provided one.
+    pub presentation_time: Option<u32>,
+
+    /// True iff any of the frame's payloads were flagged as erroneous.
+    pub error: bool,
+}
+
+/// Reassembles UVC payloads (e.g. the contents of each isochronous packet, or each bulk
+/// transfer) into complete frames.
+#[derive(Debug, Default)]
+pub struct FrameAssembler {
+    /// The frame currently being assembled.
+    current: Vec<u8>,
+
+    /// The frame ID of the frame currently being assembled, if we've started one.
+    frame_id: Option<bool>,
+
+    /// The presentation timestamp of the frame currently being assembled.
+    presentation_time: Option<u32>,
+
+    /// True iff any payload of the frame currently being assembled was erroneous.
+    error: bool,
+}
+
+impl FrameAssembler {
+    /// Creates a new, empty frame assembler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a single payload to the frame being assembled; returning a frame if one was completed.
+    ///
+    /// Empty payloads (which isochronous cameras send when they have nothing to say) are ignored.
+    pub fn push_payload(&mut self, payload: &[u8]) -> UsbResult<Option<VideoFrame>> {
+        if payload.is_empty() {
+            return Ok(None);
+        }
+        let header = PayloadHeader::parse(payload)?;
+
+        // If the frame ID has toggled, the previous frame ended without an EOF; which some
+        // cameras never send. Finish it off before we start on the new one.
+        let mut completed = None;
+        if self.frame_id.is_some_and(|id| id != header.frame_id) {
+            completed = self.take_frame();
+        }
+
+        self.frame_id = Some(header.frame_id);
+        self.error |= header.error;
+        if header.presentation_time.is_some() {
+            self.presentation_time = header.presentation_time;
+        }
+        self.current.extend_from_slice(&payload[header.length..]);
+
+        // If this payload ends the frame, we're done with it.
+        if header.end_of_frame {
+            let frame = self.take_frame();
+            self.frame_id = None;
+
+            // In the unlikely case we completed two frames at once, we prefer the newer one.
+            completed = frame.or(completed);
+        }
+
+        Ok(completed)
+    }
+
+    /// Adds a single isochronous packet to the frame being assembled; returning a frame if one
+    /// was completed.
+    ///
+    /// A packet that failed on the bus, or whose header doesn't parse, costs us part of the
+    /// frame; so it's dropped, and the frame is flagged as erroneous instead.
+    #[cfg(feature = "async")]
+    pub fn push_packet(&mut self, packet: &IsoPacket) -> Option<VideoFrame> {
+        if packet.status.is_ok() {
+            if let Ok(completed) = self.push_payload(&packet.data) {
+                return completed;
+            }
+        }
+
+        self.error = true;
+        None
+    }
+
+    /// Takes the frame currently being assembled, if it has any data in it.
+    fn take_frame(&mut self) -> Option<VideoFrame> {
+        let data = std::mem::take(&mut self.current);
+        let frame = VideoFrame {
+            data,
+            presentation_time: self.presentation_time.take(),
+            error: std::mem::take(&mut self.error),
+        };
+
+        (!frame.data.is_empty()).then_some(frame)
+    }
+}
+
+/// A [Stream] of video frames, reassembled from a stream of isochronous packets. Created by
+/// [UvcCamera::iso_frames]; or, for packets from elsewhere, by [IsoFrameStream::new].
+///
+/// A failed transfer is yielded as an error; and the frame it interrupted is flagged with
+/// [VideoFrame::error] when it completes.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct IsoFrameStream<S> {
+    /// The packets we're reassembling frames from.
+    packets: S,
+
+    /// Reassembles frames out of each packet's payload.
+    assembler: FrameAssembler,
+}
+
+#[cfg(feature = "async")]
+impl<S> IsoFrameStream<S> {
+    /// Creates a stream that reassembles frames out of the provided packets.
+    pub fn new(packets: S) -> Self {
+        Self {
+            packets,
+            assembler: FrameAssembler::new(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<S: Stream<Item = UsbResult<IsoPacket>> + Unpin> Stream for IsoFrameStream<S> {
+    type Item = UsbResult<VideoFrame>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            let packet = match std::task::ready!(Pin::new(&mut this.packets).poll_next(cx)) {
+                Some(Ok(packet)) => packet,
+                Some(Err(error)) => {
+                    this.assembler.error = true;
+                    return Poll::Ready(Some(Err(error)));
+                }
+                None => return Poll::Ready(None),
+            };
+
+            if let Some(frame) = this.assembler.push_packet(&packet) {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+        }
+    }
+}
+
+/// A UVC camera, driven via its VideoControl and VideoStreaming interfaces.
+#[derive(Debug)]
+pub struct UvcCamera {
+    /// The device we're driving.
+    device: Device,
+
+    /// The configuration we're working in.
+    configuration: ConfigurationDescriptor,
+
+    /// The version of UVC the camera claims to implement (bcdUVC).
+    uvc_version: u16,
+
+    /// The interface number of the VideoStreaming interface; which we hold claimed.
+    streaming_interface: u8,
+
+    /// The formats supported by the VideoStreaming interface.
+    formats: Vec<Format>,
+
+    /// The bulk IN endpoint the camera streams on, if it streams via bulk.
+    bulk_in: Option<u8>,
+
+    /// The control we most recently committed, if we have.
+    committed: Option<StreamingControl>,
+
+    /// Reassembles frames out of bulk payloads.
+    assembler: FrameAssembler,
+
+    /// The timeout applied to each individual transfer.
+    timeout: Option<Duration>,
+}
+
+impl UvcCamera {
+    /// Creates a UVC camera from an opened device, using its first VideoStreaming interface.
+    pub fn new(mut device: Device) -> UsbResult<Self> {
+        let configuration = device.read_active_configuration_descriptor()?;
+
+        // Figure out which version of UVC we're speaking from the VideoControl header...
+        let uvc_version = configuration
+            .interfaces
+            .iter()
+            .filter(|interface| {
+                interface.interface_class == VIDEO_CLASS
+                    && interface.interface_subclass == VIDEO_CONTROL_SUBCLASS
+            })
+            .flat_map(|interface| interface.extra.iter())
+            .find(|descriptor| {
+                descriptor.len() >= 5 && descriptor[1] == CS_INTERFACE && descriptor[2] == VC_HEADER
+            })
+            .map(|descriptor| u16::from_le_bytes([descriptor[3], descriptor[4]]))
+            .unwrap_or(0x0100);
+
+        // ... find our streaming interface, and what it can do...
+        let streaming = configuration
+            .interfaces
+            .iter()
+            .find(|interface| {
+                interface.interface_class == VIDEO_CLASS
+                    && interface.interface_subclass == VIDEO_STREAMING_SUBCLASS
+                    && interface.alternate_setting == 0
+            })
+            .ok_or(Error::InvalidInterface)?;
+        let streaming_interface = streaming.interface_number;
+        let formats = parse_formats(streaming);
+        let bulk_in = streaming
+            .find_endpoint(TransferType::Bulk, Direction::In)
+            .map(|endpoint| endpoint.address);
+
+        // ... and claim it, so we can talk to it.
+        device.claim_interface(streaming_interface)?;
+
+        Ok(Self {
+            device,
+            configuration,
+            uvc_version,
+            streaming_interface,
+            formats,
+            bulk_in,
+            committed: None,
+            assembler: FrameAssembler::new(),
+            timeout: None,
+        })
+    }
+
+    /// Sets the timeout applied to each transfer; or None to wait indefinitely.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the version of UVC the camera implements, in BCD.
+    pub fn uvc_version(&self) -> u16 {
+        self.uvc_version
+    }
+
+    /// Returns the formats (and frame sizes) the camera supports.
+    pub fn formats(&self) -> &[Format] {
+        &self.formats
+    }
+
+    /// Returns true iff the camera streams over a bulk endpoint, rather than isochronously.
+    pub fn uses_bulk_streaming(&self) -> bool {
+        self.bulk_in.is_some()
+    }
+
+    /// Provides access to the underlying device.
+    pub fn device(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    /// Releases the VideoStreaming interface, and returns the underlying device.
+    pub fn into_device(mut self) -> UsbResult<Device> {
+        self.device.unclaim_interface(self.streaming_interface)?;
+        Ok(self.device)
+    }
+
+    /// Issues a request against one of the VideoStreaming interface's probe/commit controls.
+    fn streaming_request(
+        &mut self,
+        request: VideoRequest,
+        selector: u8,
+        control: Option<&StreamingControl>,
+    ) -> UsbResult<StreamingControl> {
+        let length = StreamingControl::length_for_version(self.uvc_version);
+        let value = (selector as u16) << 8;
+        let index = self.streaming_interface as u16;
+
+        match control {
+            Some(control) => {
+                self.device.control_write(
+                    CLASS_OUT_TO_INTERFACE,
+                    request as u8,
+                    value,
+                    index,
+                    &control.to_bytes(length),
+                    self.timeout,
+                )?;
+                Ok(*control)
+            }
+            None => {
+                let data = self.device.control_read_to_vec(
+                    CLASS_IN_FROM_INTERFACE,
+                    request as u8,
+                    value,
+                    index,
+                    length as u16,
+                    self.timeout,
+                )?;
+                StreamingControl::parse(&data)
+            }
+        }
+    }
+
+    /// Proposes a set of streaming parameters, and returns what the camera is willing to do.
+    pub fn probe(&mut self, proposal: &StreamingControl) -> UsbResult<StreamingControl> {
+        self.streaming_request(VideoRequest::SetCurrent, VS_PROBE_CONTROL, Some(proposal))?;
+        self.streaming_request(VideoRequest::GetCurrent, VS_PROBE_CONTROL, None)
+    }
+
+    /// Commits a set of streaming parameters; usually the ones returned from [UvcCamera::probe].
+    pub fn commit(&mut self, control: &StreamingControl) -> UsbResult<()> {
+        self.streaming_request(VideoRequest::SetCurrent, VS_COMMIT_CONTROL, Some(control))?;
+        self.committed = Some(*control);
+        Ok(())
+    }
+
+    /// Negotiates and commits the given format, frame size, and frame interval (in 100ns units).
+    ///
+    /// If no interval is provided, the frame's default interval is used.
+    pub fn negotiate(
+        &mut self,
+        format_index: u8,
+        frame_index: u8,
+        frame_interval: Option<u32>,
+    ) -> UsbResult<StreamingControl> {
+        let frame = self
+            .formats
+            .iter()
+            .find(|format| format.index == format_index)
+            .and_then(|format| format.frame(frame_index))
+            .ok_or(Error::InvalidArgument)?;
+
+        // Ask for our format and frame, holding the frame interval fixed...
+        let proposal = StreamingControl {
+            hint: 0x0001,
+            format_index,
+            frame_index,
+            frame_interval: frame_interval.unwrap_or(frame.default_interval),
+            ..Default::default()
+        };
+
+        // ... and accept whatever the camera comes back with.
+        let accepted = self.probe(&proposal)?;
+        self.commit(&accepted)?;
+
+        Ok(accepted)
+    }
+
+    /// Starts the camera streaming, using the most recently committed parameters.
+    ///
+    /// For isochronous cameras, this selects the smallest alternate setting with enough bandwidth
+    /// for the negotiated payload size, and returns it; for bulk cameras, this returns zero.
+    pub fn start_streaming(&mut self) -> UsbResult<u8> {
+        let committed = self.committed.ok_or(Error::InvalidArgument)?;
+        self.assembler = FrameAssembler::new();
+
+        // Bulk cameras start streaming as soon as they've been committed.
+        if self.uses_bulk_streaming() {
+            return Ok(0);
+        }
+
+        let alternate_setting = select_alternate_setting(
+            &self.configuration,
+            self.streaming_interface,
+            committed.max_payload_transfer_size,
+        )
+        .ok_or(Error::Unsupported)?;
+
+        self.device
+            .set_alternate_setting(self.streaming_interface, alternate_setting)?;
+        Ok(alternate_setting)
+    }
+
+    /// Stops the camera streaming, returning its streaming interface to its zero-bandwidth setting.
+    pub fn stop_streaming(&mut self) -> UsbResult<()> {
+        self.device
+            .set_alternate_setting(self.streaming_interface, 0)
+    }
+
+    /// Reads payloads from the camera until a complete frame is available.
+    ///
+    /// Isochronous cameras need the `async` feature, and return [Error::Unsupported] without
+    /// it. Each call queues its own transfers, and drops them once it has a frame; so for
+    /// continuous capture from an isochronous camera, prefer [UvcCamera::iso_frames].
+    pub fn read_frame(&mut self) -> UsbResult<VideoFrame> {
+        let Some(endpoint) = self.bulk_in else {
+            return self.read_iso_frame();
+        };
+        let committed = self.committed.ok_or(Error::InvalidArgument)?;
+
+        loop {
+            let payload = self.device.read_to_vec(
+                endpoint,
+                committed.max_payload_transfer_size as usize,
+                self.timeout,
+            )?;
+
+            if let Some(frame) = self.assembler.push_payload(&payload)? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Reads a single frame from an isochronous camera; see [UvcCamera::read_frame].
+    #[cfg(feature = "async")]
+    fn read_iso_frame(&mut self) -> UsbResult<VideoFrame> {
+        let mut frames = self.iso_frames()?;
+        block_on(std::future::poll_fn(|cx| {
+            Pin::new(&mut frames).poll_next(cx)
+        }))
+        .unwrap_or(Err(Error::Aborted))
+    }
+
+    /// Reads a single frame from an isochronous camera; which needs the `async` feature.
+    #[cfg(not(feature = "async"))]
+    fn read_iso_frame(&mut self) -> UsbResult<VideoFrame> {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns a [Stream] of frames from an isochronous camera that's been started with
+    /// [UvcCamera::start_streaming]; which keeps transfers queued between frames, so none
+    /// of the camera's packets are missed. Fails with [Error::Unsupported] for bulk cameras.
+    #[cfg(feature = "async")]
+    pub fn iso_frames(&mut self) -> UsbResult<IsoFrameStream<IsoStream<'_>>> {
+        if self.uses_bulk_streaming() {
+            return Err(Error::Unsupported);
+        }
+        let committed = self.committed.ok_or(Error::InvalidArgument)?;
+
+        // Find the endpoint on the alternate setting start_streaming() picked...
+        let alternate_setting = select_alternate_setting(
+            &self.configuration,
+            self.streaming_interface,
+            committed.max_payload_transfer_size,
+        )
+        .ok_or(Error::Unsupported)?;
+        let endpoint = self
+            .configuration
+            .interfaces
+            .iter()
+            .find(|interface| {
+                interface.interface_number == self.streaming_interface
+                    && interface.alternate_setting == alternate_setting
+            })
+            .and_then(|interface| interface.find_endpoint(TransferType::Isochronous, Direction::In))
+            .ok_or(Error::Unsupported)?;
+
+        // ... work out how much it moves in each packet, and how many packets per frame...
+        let transactions = 1 + ((endpoint.max_packet_size >> 11) & 0b11) as usize;
+        let packet_size = endpoint.packet_size() * transactions;
+        let packets_per_frame = self
+            .device
+            .speed()
+            .and_then(|speed| endpoint.service_interval(speed))
+            .map(|interval| (1000 / interval.as_micros().max(1)) as usize)
+            .unwrap_or(1);
+        let address = endpoint.address;
+
+        // ... and keep it streaming.
+        let mut packets = self
+            .device
+            .iso_stream(address, packet_size, ISO_PACKETS_PER_TRANSFER);
+        packets.set_packets_per_frame(packets_per_frame);
+
+        Ok(IsoFrameStream::new(packets))
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use smol::stream::StreamExt;
+
+    /// Builds a packet carrying a two-byte payload header with the given flags.
+    fn packet(flags: u8, data: &[u8]) -> UsbResult<IsoPacket> {
+        let mut payload = vec![2, flags];
+        payload.extend_from_slice(data);
+        Ok(IsoPacket {
+            status: Ok(()),
+            data: payload,
+        })
+    }
+
+    /// Reassembles the provided packets into frames.
+    fn reassemble(packets: Vec<UsbResult<IsoPacket>>) -> Vec<UsbResult<VideoFrame>> {
+        let frames = IsoFrameStream::new(smol::stream::iter(packets));
+        smol::block_on(frames.collect())
+    }
+
+    fn frame(data: &[u8], error: bool) -> UsbResult<VideoFrame> {
+        Ok(VideoFrame {
+            data: data.to_vec(),
+            presentation_time: None,
+            error,
+        })
+    }
+
+    #[test]
+    fn frames_end_at_eof() {
+        let frames = reassemble(vec![
+            packet(0x00, &[1, 2]),
+            packet(0x00, &[3]),
+            packet(0x02, &[4]),
+            packet(0x01, &[5, 6]),
+            packet(0x03, &[7]),
+        ]);
+
+        assert_eq!(
+            frames,
+            vec![frame(&[1, 2, 3, 4], false), frame(&[5, 6, 7], false)]
+        );
+    }
+
+    #[test]
+    fn frames_end_when_fid_toggles() {
+        let frames = reassemble(vec![
+            packet(0x00, &[1]),
+            packet(0x00, &[2]),
+            packet(0x01, &[3]),
+            packet(0x01, &[4]),
+            packet(0x00, &[5]),
+        ]);
+
+        assert_eq!(frames, vec![frame(&[1, 2], false), frame(&[3, 4], false)]);
+    }
+
+    #[test]
+    fn empty_packets_are_skipped() {
+        let frames = reassemble(vec![
+            packet(0x00, &[1]),
+            Ok(IsoPacket {
+                status: Ok(()),
+                data: vec![],
+            }),
+            packet(0x00, &[]),
+            packet(0x02, &[2]),
+        ]);
+
+        assert_eq!(frames, vec![frame(&[1, 2], false)]);
+    }
+
+    #[test]
+    fn lost_packets_flag_their_frame() {
+        let frames = reassemble(vec![
+            packet(0x00, &[1]),
+            Ok(IsoPacket {
+                status: Err(Error::Overrun),
+                data: vec![2, 0x00, 0xff],
+            }),
+            packet(0x02, &[2]),
+            Ok(IsoPacket {
+                status: Ok(()),
+                data: vec![0xff, 0x01],
+            }),
+            packet(0x03, &[3]),
+            packet(0x00, &[4]),
+            Err(Error::Overrun),
+            packet(0x02, &[5]),
+        ]);
+
+        assert_eq!(
+            frames,
+            vec![
+                frame(&[1, 2], true),
+                frame(&[3], true),
+                Err(Error::Overrun),
+                frame(&[4, 5], true),
+            ]
+        );
+    }
+}
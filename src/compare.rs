@@ -0,0 +1,322 @@
+//! Runs the same transfers through two backends, against the same device, and reports where
+//! they disagree; for catching divergences like differing short-read or stall semantics.
+//! See [compare_backends].
+//!
+//! The device is opened through one backend at a time; so it must be free for both to claim,
+//! and should behave the same way each time it's opened (e.g. a loopback or test firmware).
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    backend::Backend, device::Device, request::RequestType, DeviceSelector, Host, UsbResult,
+};
+
+/// How long each step may take, unless the scenario says otherwise.
+const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A single operation in a [Scenario].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Claims an interface; which later reads and writes may need.
+    ClaimInterface(u8),
+
+    /// Performs a control IN request, with a `length`-byte data stage.
+    ControlRead {
+        request_type: RequestType,
+        request: u8,
+        value: u16,
+        index: u16,
+        length: usize,
+    },
+
+    /// Performs a control OUT request, with the given data stage.
+    ControlWrite {
+        request_type: RequestType,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: Vec<u8>,
+    },
+
+    /// Reads up to `length` bytes from an IN endpoint.
+    Read { endpoint: u8, length: usize },
+
+    /// Writes the given data to an OUT endpoint.
+    Write { endpoint: u8, data: Vec<u8> },
+}
+
+/// A sequence of operations to run through each backend; built up with e.g. [Scenario::read].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario {
+    /// The operations to run, in order.
+    pub steps: Vec<Step>,
+
+    /// How long each step may take.
+    pub timeout: Duration,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            steps: vec![],
+            timeout: DEFAULT_STEP_TIMEOUT,
+        }
+    }
+}
+
+impl Scenario {
+    /// Creates an empty scenario.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long each step may take.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adds a step that claims the given interface.
+    pub fn claim_interface(mut self, interface: u8) -> Self {
+        self.steps.push(Step::ClaimInterface(interface));
+        self
+    }
+
+    /// Adds a control IN request.
+    pub fn control_read(
+        mut self,
+        request_type: RequestType,
+        request: u8,
+        value: u16,
+        index: u16,
+        length: usize,
+    ) -> Self {
+        self.steps.push(Step::ControlRead {
+            request_type,
+            request,
+            value,
+            index,
+            length,
+        });
+        self
+    }
+
+    /// Adds a control OUT request.
+    pub fn control_write(
+        mut self,
+        request_type: RequestType,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+    ) -> Self {
+        self.steps.push(Step::ControlWrite {
+            request_type,
+            request,
+            value,
+            index,
+            data: data.to_vec(),
+        });
+        self
+    }
+
+    /// Adds a read of up to `length` bytes from the given endpoint.
+    pub fn read(mut self, endpoint: u8, length: usize) -> Self {
+        self.steps.push(Step::Read { endpoint, length });
+        self
+    }
+
+    /// Adds a write of the given data to the given endpoint.
+    pub fn write(mut self, endpoint: u8, data: &[u8]) -> Self {
+        self.steps.push(Step::Write {
+            endpoint,
+            data: data.to_vec(),
+        });
+        self
+    }
+}
+
+/// What happened when a single step ran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepOutcome {
+    /// The number of bytes moved; or why the step failed.
+    pub result: UsbResult<usize>,
+
+    /// For reads, the data that came back.
+    pub data: Vec<u8>,
+
+    /// How long the step took.
+    pub latency: Duration,
+}
+
+/// Runs each step of a scenario against the provided device; stopping at nothing, so a
+/// failure in one step shows up alongside whatever the following steps do.
+pub fn run_scenario(device: &mut Device, scenario: &Scenario) -> Vec<StepOutcome> {
+    let timeout = Some(scenario.timeout);
+
+    scenario
+        .steps
+        .iter()
+        .map(|step| {
+            let mut data = vec![];
+            let start = Instant::now();
+
+            let result = match step {
+                Step::ClaimInterface(interface) => device.claim_interface(*interface).map(|_| 0),
+                Step::ControlRead {
+                    request_type,
+                    request,
+                    value,
+                    index,
+                    length,
+                } => {
+                    data.resize(*length, 0);
+                    device.control_read(*request_type, *request, *value, *index, &mut data, timeout)
+                }
+                Step::ControlWrite {
+                    request_type,
+                    request,
+                    value,
+                    index,
+                    data: payload,
+                } => device
+                    .control_write(*request_type, *request, *value, *index, payload, timeout)
+                    .map(|_| payload.len()),
+                Step::Read { endpoint, length } => {
+                    data.resize(*length, 0);
+                    device.read(*endpoint, &mut data, timeout)
+                }
+                Step::Write {
+                    endpoint,
+                    data: payload,
+                } => device.write(*endpoint, payload, timeout),
+            };
+
+            let latency = start.elapsed();
+            data.truncate(*result.as_ref().unwrap_or(&0));
+            StepOutcome {
+                result,
+                data,
+                latency,
+            }
+        })
+        .collect()
+}
+
+/// A step on which two backends disagreed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The index of the step, in its scenario.
+    pub step: usize,
+
+    /// What differed.
+    pub description: String,
+}
+
+/// The outcome of running a scenario through two backends; see [compare_backends].
+#[derive(Debug, Clone)]
+pub struct BackendComparison {
+    /// The names of the two backends, in the order they were run.
+    pub backends: [&'static str; 2],
+
+    /// The steps that were run.
+    pub steps: Vec<Step>,
+
+    /// What happened at each step, for each backend.
+    pub outcomes: [Vec<StepOutcome>; 2],
+}
+
+impl BackendComparison {
+    /// Returns each step on which the backends' results or data differed. Latencies are
+    /// expected to differ, and so aren't counted; see the Display output for those.
+    pub fn divergences(&self) -> Vec<Divergence> {
+        let [first, second] = &self.outcomes;
+
+        first
+            .iter()
+            .zip(second)
+            .enumerate()
+            .filter_map(|(step, (first, second))| {
+                let description = if first.result != second.result {
+                    format!("{:?} vs {:?}", first.result, second.result)
+                } else if first.data != second.data {
+                    let offset = first
+                        .data
+                        .iter()
+                        .zip(&second.data)
+                        .position(|(a, b)| a != b)
+                        .unwrap_or(0);
+                    format!("data differs from byte {offset}")
+                } else {
+                    return None;
+                };
+
+                Some(Divergence { step, description })
+            })
+            .collect()
+    }
+
+    /// Returns true iff the backends agreed on every step.
+    pub fn agreed(&self) -> bool {
+        self.divergences().is_empty()
+    }
+}
+
+/// Prints each step's result and latency side by side, with divergent steps marked.
+impl fmt::Display for BackendComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [first_name, second_name] = self.backends;
+        let divergent: Vec<usize> = self.divergences().iter().map(|d| d.step).collect();
+        writeln!(f, "     {first_name:>28}  {second_name:>28}  step")?;
+
+        for (index, step) in self.steps.iter().enumerate() {
+            let marker = if divergent.contains(&index) {
+                "!!"
+            } else {
+                "  "
+            };
+            write!(f, "{marker}{index:3}")?;
+
+            for outcome in &self.outcomes {
+                let result = match outcome.get(index) {
+                    Some(outcome) => format!("{:?} {:.1?}", outcome.result, outcome.latency),
+                    None => "(not run)".to_owned(),
+                };
+                write!(f, "  {result:>28}")?;
+            }
+            writeln!(f, "  {step:?}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens the first device matching `selector` through each backend in turn, and runs the
+/// scenario against it; so the results can be compared. Fails if either backend can't open
+/// the device.
+pub fn compare_backends(
+    first: Arc<dyn Backend>,
+    second: Arc<dyn Backend>,
+    selector: &DeviceSelector,
+    scenario: &Scenario,
+) -> UsbResult<BackendComparison> {
+    let backends = [first.name(), second.name()];
+
+    // Each device is closed before the next backend opens it; so the backends don't fight
+    // over its interfaces.
+    let run = |backend: Arc<dyn Backend>| -> UsbResult<Vec<StepOutcome>> {
+        let mut device = Host::new_from_backend(backend)?.open_first(selector)?;
+        Ok(run_scenario(&mut device, scenario))
+    };
+    let outcomes = [run(first)?, run(second)?];
+
+    Ok(BackendComparison {
+        backends,
+        steps: scenario.steps.clone(),
+        outcomes,
+    })
+}
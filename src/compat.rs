@@ -0,0 +1,4 @@
+//! Facades that mimic other USB crates' APIs on top of our own; so code written against them
+//! can be moved over to usrs a piece at a time.
+
+pub mod rusb;
@@ -0,0 +1,668 @@
+//! Types named and shaped like [rusb](https://docs.rs/rusb)'s, backed by usrs; so a codebase
+//! can switch its imports over first, and then move to the native API one call site at a time.
+//!
+//! Only the commonly-used core of rusb is covered. Where code needs more, each type can hand
+//! out the usrs object it wraps; see e.g. [DeviceHandle::usrs_device].
+//!
+//! A few differences remain: devices don't know their bus address, so there's no `address()`;
+//! and string reads use the device's first language, rather than an explicit one.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    descriptors,
+    device::{Device as UsrsDevice, DeviceInformation, DeviceSpeed, ReenumerateOptions},
+    request::{Direction, RequestType},
+    Host, UsbResult,
+};
+
+/// The result of an rusb-style operation.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors, as rusb reports them; converted from our own [crate::Error].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Input/output error.
+    Io,
+
+    /// Invalid parameter.
+    InvalidParam,
+
+    /// Access denied; e.g. insufficient permissions.
+    Access,
+
+    /// The device has been disconnected.
+    NoDevice,
+
+    /// Entity not found.
+    NotFound,
+
+    /// Resource busy; e.g. someone else has the device.
+    Busy,
+
+    /// Operation timed out.
+    Timeout,
+
+    /// The device sent more data than we asked for.
+    Overflow,
+
+    /// The endpoint stalled.
+    Pipe,
+
+    /// The operation was interrupted.
+    Interrupted,
+
+    /// Insufficient memory.
+    NoMem,
+
+    /// Operation not supported, or unimplemented on this platform.
+    NotSupported,
+
+    /// The device returned a malformed descriptor.
+    BadDescriptor,
+
+    /// Some other error.
+    Other,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Error::Io => "Input/Output Error",
+            Error::InvalidParam => "Invalid parameter",
+            Error::Access => "Access denied (insufficient permissions)",
+            Error::NoDevice => "No such device (it may have been disconnected)",
+            Error::NotFound => "Entity not found",
+            Error::Busy => "Resource busy",
+            Error::Timeout => "Operation timed out",
+            Error::Overflow => "Overflow",
+            Error::Pipe => "Pipe error",
+            Error::Interrupted => "System call interrupted",
+            Error::NoMem => "Insufficient memory",
+            Error::NotSupported => "Operation not supported or unimplemented on this platform",
+            Error::BadDescriptor => "Malformed descriptor",
+            Error::Other => "Other error",
+        };
+
+        f.write_str(description)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<crate::Error> for Error {
+    fn from(error: crate::Error) -> Self {
+        use crate::Error::*;
+
+        match error {
+            Unsupported | DeviceNotReal => Error::NotSupported,
+            DeviceNotFound | InvalidEndpoint | InvalidInterface => Error::NotFound,
+            DeviceNotOpen | Disconnected | NotResponding => Error::NoDevice,
+            DeviceReserved => Error::Busy,
+            Stalled => Error::Pipe,
+            TimedOut | PartialTransfer(_) => Error::Timeout,
+            InvalidArgument => Error::InvalidParam,
+            Aborted => Error::Interrupted,
+            Overrun | Babble => Error::Overflow,
+            ProtocolError | Underrun | ShortRead(_) | OsError(_) => Error::Io,
+            PermissionDenied => Error::Access,
+            MalformedDescriptor => Error::BadDescriptor,
+            UnspecifiedOsError => Error::Other,
+        }
+    }
+}
+
+/// Converts an rusb-style timeout, where zero means "forever", into ours.
+fn to_timeout(timeout: Duration) -> Option<Duration> {
+    (!timeout.is_zero()).then_some(timeout)
+}
+
+/// Something that provides access to devices; see [GlobalContext] and [Context].
+pub trait UsbContext: Clone + fmt::Debug + Send + Sync + Sized {
+    /// Runs an operation against this context's host.
+    #[doc(hidden)]
+    fn with_host<T>(&self, operation: impl FnOnce(&Host) -> UsbResult<T>) -> UsbResult<T>;
+
+    /// Returns a list of the devices currently attached.
+    fn devices(&self) -> Result<DeviceList<Self>> {
+        let devices = self.with_host(|host| host.all_devices())?;
+
+        Ok(DeviceList {
+            devices: devices
+                .into_iter()
+                .map(|information| Device {
+                    context: self.clone(),
+                    information,
+                })
+                .collect(),
+        })
+    }
+
+    /// Opens the first device with the given VID and PID; or returns None if there isn't
+    /// one, or it can't be opened.
+    fn open_device_with_vid_pid(
+        &self,
+        vendor_id: u16,
+        product_id: u16,
+    ) -> Option<DeviceHandle<Self>> {
+        self.devices()
+            .ok()?
+            .iter()
+            .filter(|device| {
+                (device.information.vendor_id, device.information.product_id)
+                    == (vendor_id, product_id)
+            })
+            .find_map(|device| device.open().ok())
+    }
+}
+
+/// The context used by rusb's free functions; which creates a host as each operation needs it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalContext;
+
+impl UsbContext for GlobalContext {
+    fn with_host<T>(&self, operation: impl FnOnce(&Host) -> UsbResult<T>) -> UsbResult<T> {
+        operation(&Host::new()?)
+    }
+}
+
+/// A context that keeps its own host; and so e.g. its own device cache.
+#[derive(Clone)]
+pub struct Context {
+    host: Arc<Host>,
+}
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context").finish_non_exhaustive()
+    }
+}
+
+impl Context {
+    /// Creates a context with the default backend.
+    pub fn new() -> Result<Self> {
+        Ok(Self::from_host(Host::new()?))
+    }
+
+    /// Creates a context around a host we've already set up; e.g. with a specific backend.
+    pub fn from_host(host: Host) -> Self {
+        Self {
+            host: Arc::new(host),
+        }
+    }
+}
+
+impl UsbContext for Context {
+    fn with_host<T>(&self, operation: impl FnOnce(&Host) -> UsbResult<T>) -> UsbResult<T> {
+        operation(&self.host)
+    }
+}
+
+/// Returns a list of the devices currently attached.
+pub fn devices() -> Result<DeviceList<GlobalContext>> {
+    GlobalContext.devices()
+}
+
+/// Opens the first device with the given VID and PID; or returns None if there isn't one.
+pub fn open_device_with_vid_pid(
+    vendor_id: u16,
+    product_id: u16,
+) -> Option<DeviceHandle<GlobalContext>> {
+    GlobalContext.open_device_with_vid_pid(vendor_id, product_id)
+}
+
+/// The devices attached when [UsbContext::devices] was called.
+#[derive(Debug, Clone)]
+pub struct DeviceList<T: UsbContext> {
+    devices: Vec<Device<T>>,
+}
+
+impl<T: UsbContext> DeviceList<T> {
+    /// Returns the number of devices in the list.
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Returns true iff the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Iterates over the devices in the list.
+    pub fn iter(&self) -> impl Iterator<Item = Device<T>> + '_ {
+        self.devices.iter().cloned()
+    }
+}
+
+/// The speed a device is running at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Speed {
+    /// The backend couldn't tell us.
+    Unknown,
+
+    /// USB 1.x low speed; 1.5Mbps.
+    Low,
+
+    /// USB 1.x full speed; 12Mbps.
+    Full,
+
+    /// USB 2.0 high speed; 480Mbps.
+    High,
+
+    /// USB 3.x SuperSpeed; 5Gbps.
+    Super,
+
+    /// USB 3.x SuperSpeedPlus; 10Gbps or faster.
+    SuperPlus,
+}
+
+impl From<Option<DeviceSpeed>> for Speed {
+    fn from(speed: Option<DeviceSpeed>) -> Self {
+        match speed {
+            None => Speed::Unknown,
+            Some(DeviceSpeed::Low) => Speed::Low,
+            Some(DeviceSpeed::Full) => Speed::Full,
+            Some(DeviceSpeed::High) => Speed::High,
+            Some(DeviceSpeed::Super) => Speed::Super,
+            Some(DeviceSpeed::SuperPlus) => Speed::SuperPlus,
+        }
+    }
+}
+
+/// A device that's attached, but not necessarily open.
+#[derive(Debug, Clone)]
+pub struct Device<T: UsbContext> {
+    context: T,
+    information: DeviceInformation,
+}
+
+impl<T: UsbContext> Device<T> {
+    /// Returns the device's device descriptor; opening the device to read it, if the OS
+    /// didn't hand us a copy.
+    pub fn device_descriptor(&self) -> Result<DeviceDescriptor> {
+        let descriptor = self
+            .context
+            .with_host(|host| host.read_descriptors(&self.information))?;
+        Ok(DeviceDescriptor(descriptor))
+    }
+
+    /// Returns the number of the bus the device is on; or 0 if the backend can't tell.
+    pub fn bus_number(&self) -> u8 {
+        self.information.port_path().map_or(0, |path| path.bus())
+    }
+
+    /// Returns the number of the hub port the device is plugged into; or 0 if the backend
+    /// can't tell.
+    pub fn port_number(&self) -> u8 {
+        self.information
+            .port_path()
+            .and_then(|path| path.ports().last().copied())
+            .unwrap_or(0)
+    }
+
+    /// Returns the chain of hub ports leading to the device.
+    pub fn port_numbers(&self) -> Result<Vec<u8>> {
+        let path = self.information.port_path().ok_or(Error::NotSupported)?;
+        Ok(path.ports().to_vec())
+    }
+
+    /// Returns the speed the device is running at.
+    pub fn speed(&self) -> Speed {
+        self.information.speed().into()
+    }
+
+    /// Opens the device.
+    pub fn open(&self) -> Result<DeviceHandle<T>> {
+        let handle = self
+            .context
+            .with_host(|host| host.open(&self.information))?;
+
+        Ok(DeviceHandle {
+            device: self.clone(),
+            handle: Mutex::new(handle),
+            auto_detach: false,
+        })
+    }
+
+    /// Returns the usrs information this device was created from.
+    pub fn information(&self) -> &DeviceInformation {
+        &self.information
+    }
+}
+
+/// A USB version number, as decoded from a BCD field; e.g. 2.1.0 for USB 2.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version(pub u8, pub u8, pub u8);
+
+impl Version {
+    /// Decodes a version from its BCD form; e.g. 0x0210 for 2.1.0.
+    pub fn from_bcd(mut raw: u16) -> Self {
+        let sub_minor = (raw & 0x0f) as u8;
+        raw >>= 4;
+        let minor = (raw & 0x0f) as u8;
+        raw >>= 4;
+        let major = ((raw & 0x0f) + 10 * ((raw >> 4) & 0x0f)) as u8;
+
+        Version(major, minor, sub_minor)
+    }
+
+    /// The major version number.
+    pub fn major(self) -> u8 {
+        self.0
+    }
+
+    /// The minor version number.
+    pub fn minor(self) -> u8 {
+        self.1
+    }
+
+    /// The sub-minor version number.
+    pub fn sub_minor(self) -> u8 {
+        self.2
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// A device descriptor, with rusb's accessors.
+#[derive(Debug, Clone)]
+pub struct DeviceDescriptor(descriptors::DeviceDescriptor);
+
+impl DeviceDescriptor {
+    /// The USB specification version the device claims to follow.
+    pub fn usb_version(&self) -> Version {
+        Version::from_bcd(self.0.usb_version)
+    }
+
+    /// The device's release number.
+    pub fn device_version(&self) -> Version {
+        Version::from_bcd(self.0.device_version)
+    }
+
+    /// The device's class code.
+    pub fn class_code(&self) -> u8 {
+        self.0.device_class
+    }
+
+    /// The device's subclass code.
+    pub fn sub_class_code(&self) -> u8 {
+        self.0.device_subclass
+    }
+
+    /// The device's protocol code.
+    pub fn protocol_code(&self) -> u8 {
+        self.0.device_protocol
+    }
+
+    /// The maximum packet size for EP0.
+    pub fn max_packet_size(&self) -> u8 {
+        self.0.max_packet_size_ep0
+    }
+
+    /// The device's Vendor ID.
+    pub fn vendor_id(&self) -> u16 {
+        self.0.vendor_id
+    }
+
+    /// The device's Product ID.
+    pub fn product_id(&self) -> u16 {
+        self.0.product_id
+    }
+
+    /// The number of configurations the device supports.
+    pub fn num_configurations(&self) -> u8 {
+        self.0.num_configurations
+    }
+
+    /// String index for the manufacturer string, if there is one.
+    pub fn manufacturer_string_index(&self) -> Option<u8> {
+        Some(self.0.manufacturer_string_index).filter(|&index| index != 0)
+    }
+
+    /// String index for the product string, if there is one.
+    pub fn product_string_index(&self) -> Option<u8> {
+        Some(self.0.product_string_index).filter(|&index| index != 0)
+    }
+
+    /// String index for the serial number string, if there is one.
+    pub fn serial_number_string_index(&self) -> Option<u8> {
+        Some(self.0.serial_string_index).filter(|&index| index != 0)
+    }
+
+    /// Returns the usrs descriptor this wraps.
+    pub fn descriptor(&self) -> &descriptors::DeviceDescriptor {
+        &self.0
+    }
+}
+
+impl From<descriptors::DeviceDescriptor> for DeviceDescriptor {
+    fn from(descriptor: descriptors::DeviceDescriptor) -> Self {
+        Self(descriptor)
+    }
+}
+
+/// An open device.
+///
+/// Like rusb's, transfers take `&self`; so a handle can be shared between threads. They're
+/// serialized by a lock around the usrs [crate::device::Device] underneath.
+#[derive(Debug)]
+pub struct DeviceHandle<T: UsbContext> {
+    /// The device we were opened from.
+    device: Device<T>,
+
+    /// The open device itself.
+    handle: Mutex<UsrsDevice>,
+
+    /// If true, kernel drivers are detached from interfaces as we claim them.
+    auto_detach: bool,
+}
+
+impl<T: UsbContext> DeviceHandle<T> {
+    /// Returns the device this handle was opened from.
+    pub fn device(&self) -> Device<T> {
+        self.device.clone()
+    }
+
+    /// Gives access to the usrs device underneath; for anything this facade doesn't cover.
+    pub fn usrs_device(&self) -> MutexGuard<'_, UsrsDevice> {
+        self.handle.lock().unwrap()
+    }
+
+    /// Unwraps the usrs device underneath; for code that's done migrating.
+    pub fn into_usrs_device(self) -> UsrsDevice {
+        self.handle.into_inner().unwrap()
+    }
+
+    /// Returns the value of the active configuration.
+    pub fn active_configuration(&self) -> Result<u8> {
+        Ok(self.usrs_device().active_configuration()?)
+    }
+
+    /// Selects the configuration with the given value.
+    pub fn set_active_configuration(&mut self, config: u8) -> Result<()> {
+        Ok(self.usrs_device().set_active_configuration(config)?)
+    }
+
+    /// Resets the device; which re-enumerates it.
+    pub fn reset(&mut self) -> Result<()> {
+        Ok(self
+            .usrs_device()
+            .reenumerate(&ReenumerateOptions::default())?)
+    }
+
+    /// Returns true iff a kernel driver is bound to the given interface.
+    pub fn kernel_driver_active(&self, iface: u8) -> Result<bool> {
+        Ok(self.usrs_device().kernel_driver_active(iface)?)
+    }
+
+    /// Detaches the kernel driver bound to the given interface.
+    pub fn detach_kernel_driver(&mut self, iface: u8) -> Result<()> {
+        Ok(self.usrs_device().release_kernel_driver(iface)?)
+    }
+
+    /// Re-attaches the kernel driver for the given interface.
+    pub fn attach_kernel_driver(&mut self, iface: u8) -> Result<()> {
+        Ok(self.usrs_device().attach_kernel_driver(iface)?)
+    }
+
+    /// Sets whether kernel drivers are detached from interfaces as we claim them.
+    pub fn set_auto_detach_kernel_driver(&mut self, auto_detach: bool) -> Result<()> {
+        self.auto_detach = auto_detach;
+        Ok(())
+    }
+
+    /// Claims the given interface.
+    pub fn claim_interface(&mut self, iface: u8) -> Result<()> {
+        let mut handle = self.usrs_device();
+        if self.auto_detach {
+            handle.release_kernel_driver_if_possible(iface)?;
+        }
+
+        Ok(handle.claim_interface(iface)?)
+    }
+
+    /// Releases a claimed interface.
+    pub fn release_interface(&mut self, iface: u8) -> Result<()> {
+        Ok(self.usrs_device().unclaim_interface(iface)?)
+    }
+
+    /// Selects an alternate setting on a claimed interface.
+    pub fn set_alternate_setting(&mut self, iface: u8, setting: u8) -> Result<()> {
+        Ok(self.usrs_device().set_alternate_setting(iface, setting)?)
+    }
+
+    /// Clears a halt (stall) on the given endpoint.
+    pub fn clear_halt(&mut self, endpoint: u8) -> Result<()> {
+        Ok(self.usrs_device().clear_stall(endpoint)?)
+    }
+
+    /// Reads from a bulk IN endpoint. A zero timeout waits forever.
+    pub fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        Self::check_direction(endpoint, Direction::In)?;
+        Ok(self
+            .usrs_device()
+            .read(endpoint, buf, to_timeout(timeout))?)
+    }
+
+    /// Writes to a bulk OUT endpoint. A zero timeout waits forever.
+    pub fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> Result<usize> {
+        Self::check_direction(endpoint, Direction::Out)?;
+        Ok(self
+            .usrs_device()
+            .write(endpoint, buf, to_timeout(timeout))?)
+    }
+
+    /// Reads from an interrupt IN endpoint. A zero timeout waits forever.
+    pub fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.read_bulk(endpoint, buf, timeout)
+    }
+
+    /// Writes to an interrupt OUT endpoint. A zero timeout waits forever.
+    pub fn write_interrupt(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> Result<usize> {
+        Self::check_direction(endpoint, Direction::Out)?;
+        Ok(self
+            .usrs_device()
+            .write_interrupt(endpoint, buf, to_timeout(timeout))?)
+    }
+
+    /// Performs a control IN request; `request_type` is the raw bmRequestType.
+    /// A zero timeout waits forever.
+    pub fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        let request_type = Self::control_request_type(request_type, Direction::In)?;
+        Ok(self.usrs_device().control_read(
+            request_type,
+            request,
+            value,
+            index,
+            buf,
+            to_timeout(timeout),
+        )?)
+    }
+
+    /// Performs a control OUT request; `request_type` is the raw bmRequestType.
+    /// A zero timeout waits forever.
+    pub fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        let request_type = Self::control_request_type(request_type, Direction::Out)?;
+        self.usrs_device().control_write(
+            request_type,
+            request,
+            value,
+            index,
+            buf,
+            to_timeout(timeout),
+        )?;
+
+        Ok(buf.len())
+    }
+
+    /// Reads a string descriptor. Despite the name, non-ASCII characters are kept.
+    pub fn read_string_descriptor_ascii(&self, index: u8) -> Result<String> {
+        Ok(self.usrs_device().read_string_descriptor(index)?)
+    }
+
+    /// Reads the device's manufacturer string.
+    pub fn read_manufacturer_string_ascii(&self, device: &DeviceDescriptor) -> Result<String> {
+        let index = device
+            .manufacturer_string_index()
+            .ok_or(Error::InvalidParam)?;
+        self.read_string_descriptor_ascii(index)
+    }
+
+    /// Reads the device's product string.
+    pub fn read_product_string_ascii(&self, device: &DeviceDescriptor) -> Result<String> {
+        let index = device.product_string_index().ok_or(Error::InvalidParam)?;
+        self.read_string_descriptor_ascii(index)
+    }
+
+    /// Reads the device's serial number string.
+    pub fn read_serial_number_string_ascii(&self, device: &DeviceDescriptor) -> Result<String> {
+        let index = device
+            .serial_number_string_index()
+            .ok_or(Error::InvalidParam)?;
+        self.read_string_descriptor_ascii(index)
+    }
+
+    /// Fails with InvalidParam, as rusb does, if an endpoint address points the wrong way.
+    fn check_direction(endpoint: u8, direction: Direction) -> Result<()> {
+        let is_in = endpoint & 0x80 != 0;
+        if is_in != (direction == Direction::In) {
+            return Err(Error::InvalidParam);
+        }
+
+        Ok(())
+    }
+
+    /// Decodes a raw bmRequestType; which must point in the given direction.
+    fn control_request_type(raw: u8, direction: Direction) -> Result<RequestType> {
+        let request_type = RequestType::try_from(raw)?;
+        if request_type.direction != direction {
+            return Err(Error::InvalidParam);
+        }
+
+        Ok(request_type)
+    }
+}
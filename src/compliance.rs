@@ -0,0 +1,440 @@
+//! A lightweight USB 2.0 Chapter 9 compliance checker; for testing device firmware.
+//!
+//! The checks here exercise a device's handling of the standard requests: that its descriptors
+//! are consistent, that GET/SET_CONFIGURATION behave, that requests it doesn't support are
+//! met with a STALL, and that its strings are readable. See [run_compliance_tests].
+
+use std::time::Duration;
+
+use crate::{
+    descriptors::{self, ConfigurationDescriptor, DescriptorIssue, DeviceDescriptor},
+    device::{Device, DeviceInformation},
+    request::{DescriptorType, StandardDeviceRequest, STANDARD_IN_FROM_DEVICE},
+    Error, UsbResult,
+};
+
+/// How long we'll wait for the device to respond to any single request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A request number that Chapter 9 leaves reserved; which devices must refuse.
+const RESERVED_REQUEST: u8 = 2;
+
+/// A descriptor type that no specification defines; which devices must refuse to provide.
+const UNDEFINED_DESCRIPTOR_TYPE: u8 = 0x7f;
+
+/// How a single compliance check went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    /// The device behaved as the specification requires.
+    Passed,
+
+    /// The device didn't; with a description of what it did wrong.
+    Failed(String),
+
+    /// The check didn't apply to this device, or couldn't be run; with the reason why.
+    Skipped(String),
+}
+
+/// The result of a single compliance check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    /// A short name for the check; e.g. "get_configuration".
+    pub name: &'static str,
+
+    /// How it went.
+    pub outcome: TestOutcome,
+}
+
+/// The results of every check run against a device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComplianceReport {
+    /// The result of each check, in the order they were run.
+    pub results: Vec<TestResult>,
+}
+
+impl ComplianceReport {
+    /// Returns true iff no check failed.
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Returns the checks that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+        self.results
+            .iter()
+            .filter(|result| matches!(result.outcome, TestOutcome::Failed(_)))
+    }
+
+    /// Adds the result of a check to the report.
+    pub(crate) fn record(&mut self, name: &'static str, result: CheckResult) {
+        let outcome = result.unwrap_or_else(TestOutcome::Failed);
+        self.results.push(TestResult { name, outcome });
+    }
+}
+
+impl std::fmt::Display for ComplianceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for result in &self.results {
+            match &result.outcome {
+                TestOutcome::Passed => writeln!(f, "PASS  {}", result.name)?,
+                TestOutcome::Failed(reason) => writeln!(f, "FAIL  {}: {reason}", result.name)?,
+                TestOutcome::Skipped(reason) => writeln!(f, "SKIP  {}: {reason}", result.name)?,
+            }
+        }
+
+        writeln!(
+            f,
+            "{} checks, {} failed",
+            self.results.len(),
+            self.failures().count()
+        )
+    }
+}
+
+/// What a single check returns: how it went; or, if the device misbehaved, why it failed.
+pub(crate) type CheckResult = Result<TestOutcome, String>;
+
+/// Runs every compliance check against the provided device; returning a report of how each went.
+///
+/// The checks only issue standard requests, and leave the device in the configuration they
+/// found it in; but they do re-select that configuration, which resets the device's interfaces.
+/// They're meant for a device under test, rather than one other software is using.
+pub fn run_compliance_tests(device: &mut Device) -> ComplianceReport {
+    let mut report = ComplianceReport::default();
+
+    // Most checks need the descriptors; if we can't even get those, there's little else to do.
+    let device_descriptor = match check_device_descriptor(device) {
+        Ok(descriptor) => {
+            report.record("device_descriptor", Ok(TestOutcome::Passed));
+            descriptor
+        }
+        Err(reason) => {
+            report.record("device_descriptor", Err(reason));
+            return report;
+        }
+    };
+
+    let mut configurations = vec![];
+    report.record(
+        "configuration_descriptors",
+        check_configuration_descriptors(device, &device_descriptor, &mut configurations),
+    );
+    report.record("short_descriptor_reads", check_short_reads(device));
+    report.record("get_status", check_get_status(device));
+    report.record(
+        "get_configuration",
+        check_get_configuration(device, &configurations),
+    );
+    report.record("set_configuration", check_set_configuration(device));
+    report.record("reserved_request_stalls", check_reserved_request(device));
+    report.record(
+        "undefined_descriptor_stalls",
+        check_undefined_descriptor(device),
+    );
+    report.record(
+        "string_descriptors",
+        check_strings(device, &device_descriptor, &configurations),
+    );
+
+    report
+}
+
+/// Describes a failed request, for a failure message.
+fn request_failed(request: &str) -> impl FnOnce(Error) -> String + '_ {
+    move |error| format!("{request} failed: {error}")
+}
+
+/// Renders a list of descriptor issues into a single failure message.
+fn describe_issues(issues: &[DescriptorIssue]) -> String {
+    issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Checks the device descriptor is well-formed; and, if the OS has its own copy, that the two
+/// agree. Returns the parsed descriptor.
+fn check_device_descriptor(device: &mut Device) -> Result<DeviceDescriptor, String> {
+    let raw = device
+        .read_standard_descriptor(DescriptorType::Device, 0)
+        .map_err(request_failed("GET_DESCRIPTOR(Device)"))?;
+
+    let issues = descriptors::validate(&raw);
+    if !issues.is_empty() {
+        return Err(describe_issues(&issues));
+    }
+    let descriptor = DeviceDescriptor::parse(&raw).map_err(request_failed("parsing"))?;
+
+    // The OS read the descriptor during enumeration; a device that changes its answer
+    // afterwards is confused.
+    let cached = device
+        .information()
+        .and_then(DeviceInformation::cached_device_descriptor);
+    if cached.is_some_and(|cached| *cached != descriptor) {
+        return Err("descriptor differs from the one read during enumeration".into());
+    }
+
+    Ok(descriptor)
+}
+
+/// Checks each configuration descriptor is well-formed, and that their values are unique;
+/// adding each one we can parse to `configurations`.
+fn check_configuration_descriptors(
+    device: &mut Device,
+    device_descriptor: &DeviceDescriptor,
+    configurations: &mut Vec<ConfigurationDescriptor>,
+) -> CheckResult {
+    let mut problems = vec![];
+
+    for index in 0..device_descriptor.num_configurations {
+        let raw = match device.read_standard_descriptor(DescriptorType::Configuration, index) {
+            Ok(raw) => raw,
+            Err(error) => {
+                problems.push(format!(
+                    "configuration {index}: GET_DESCRIPTOR failed: {error}"
+                ));
+                continue;
+            }
+        };
+
+        let issues = descriptors::validate(&raw);
+        if !issues.is_empty() {
+            problems.push(format!(
+                "configuration {index}: {}",
+                describe_issues(&issues)
+            ));
+        }
+
+        let Ok(configuration) = ConfigurationDescriptor::parse(&raw) else {
+            continue;
+        };
+        let value = configuration.configuration_value;
+        if value == 0 {
+            problems.push(format!("configuration {index}: bConfigurationValue is 0"));
+        }
+        if configurations
+            .iter()
+            .any(|other| other.configuration_value == value)
+        {
+            problems.push(format!(
+                "configuration {index}: bConfigurationValue {value} is used more than once"
+            ));
+        }
+        configurations.push(configuration);
+    }
+
+    match problems.is_empty() {
+        true => Ok(TestOutcome::Passed),
+        false => Err(problems.join("; ")),
+    }
+}
+
+/// Checks the device honors a wLength shorter than the descriptor; as hosts do to read headers.
+fn check_short_reads(device: &mut Device) -> CheckResult {
+    for (descriptor_type, length) in [
+        (DescriptorType::Device, 8),
+        (
+            DescriptorType::Configuration,
+            ConfigurationDescriptor::LENGTH,
+        ),
+    ] {
+        let request = format!("GET_DESCRIPTOR({descriptor_type:?}) with wLength {length}");
+        let data = device
+            .control_read_to_vec(
+                STANDARD_IN_FROM_DEVICE,
+                StandardDeviceRequest::GetDescriptor.into(),
+                (descriptor_type as u16) << 8,
+                0,
+                length as u16,
+                Some(REQUEST_TIMEOUT),
+            )
+            .map_err(request_failed(&request))?;
+
+        if data.len() != length {
+            return Err(format!("{request} returned {} bytes", data.len()));
+        }
+    }
+
+    Ok(TestOutcome::Passed)
+}
+
+/// Checks GET_STATUS returns two bytes, with its reserved bits clear.
+fn check_get_status(device: &mut Device) -> CheckResult {
+    let status = device
+        .control_read_to_vec(
+            STANDARD_IN_FROM_DEVICE,
+            StandardDeviceRequest::GetStatus.into(),
+            0,
+            0,
+            2,
+            Some(REQUEST_TIMEOUT),
+        )
+        .map_err(request_failed("GET_STATUS"))?;
+
+    // Only self-powered and remote wakeup are defined for devices; the rest is reserved.
+    match status[..] {
+        [low, high] if low & !0b11 == 0 && high == 0 => Ok(TestOutcome::Passed),
+        [low, high] => Err(format!(
+            "reserved status bits are set: {:#06x}",
+            u16::from_le_bytes([low, high])
+        )),
+        _ => Err(format!("GET_STATUS returned {} bytes", status.len())),
+    }
+}
+
+/// Reads the device's current configuration value with GET_CONFIGURATION.
+fn read_configuration(device: &mut Device) -> Result<u8, String> {
+    let data = device
+        .control_read_to_vec(
+            STANDARD_IN_FROM_DEVICE,
+            StandardDeviceRequest::GetConfiguration.into(),
+            0,
+            0,
+            1,
+            Some(REQUEST_TIMEOUT),
+        )
+        .map_err(request_failed("GET_CONFIGURATION"))?;
+
+    match data[..] {
+        [value] => Ok(value),
+        _ => Err(format!("GET_CONFIGURATION returned {} bytes", data.len())),
+    }
+}
+
+/// Checks GET_CONFIGURATION returns a single byte, naming one of the device's configurations.
+fn check_get_configuration(
+    device: &mut Device,
+    configurations: &[ConfigurationDescriptor],
+) -> CheckResult {
+    let value = read_configuration(device)?;
+
+    // Zero means unconfigured, which is always allowed.
+    let known = configurations
+        .iter()
+        .any(|configuration| configuration.configuration_value == value);
+    if value != 0 && !known {
+        return Err(format!(
+            "returned {value}, which isn't any configuration's bConfigurationValue"
+        ));
+    }
+
+    Ok(TestOutcome::Passed)
+}
+
+/// Checks that re-selecting the current configuration works, and is reflected by
+/// GET_CONFIGURATION.
+fn check_set_configuration(device: &mut Device) -> CheckResult {
+    let current = match read_configuration(device) {
+        Ok(0) => return Ok(TestOutcome::Skipped("the device is unconfigured".into())),
+        Ok(value) => value,
+        Err(reason) => return Ok(TestOutcome::Skipped(reason)),
+    };
+
+    device
+        .set_active_configuration(current)
+        .map_err(request_failed("SET_CONFIGURATION"))?;
+
+    match read_configuration(device)? {
+        value if value == current => Ok(TestOutcome::Passed),
+        value => Err(format!(
+            "GET_CONFIGURATION returned {value} after selecting configuration {current}"
+        )),
+    }
+}
+
+/// Checks the device STALLs a request number Chapter 9 reserves.
+fn check_reserved_request(device: &mut Device) -> CheckResult {
+    let result = device.control_read_to_vec(
+        STANDARD_IN_FROM_DEVICE,
+        RESERVED_REQUEST,
+        0,
+        0,
+        2,
+        Some(REQUEST_TIMEOUT),
+    );
+    expect_stall(device, result)
+}
+
+/// Checks the device STALLs a request for a descriptor type that doesn't exist.
+fn check_undefined_descriptor(device: &mut Device) -> CheckResult {
+    let result = device.control_read_to_vec(
+        STANDARD_IN_FROM_DEVICE,
+        StandardDeviceRequest::GetDescriptor.into(),
+        (UNDEFINED_DESCRIPTOR_TYPE as u16) << 8,
+        0,
+        u16::MAX,
+        Some(REQUEST_TIMEOUT),
+    );
+    expect_stall(device, result)
+}
+
+/// Checks that a request was refused with a STALL; and that the device recovered from it.
+fn expect_stall(device: &mut Device, result: UsbResult<Vec<u8>>) -> CheckResult {
+    match result {
+        Err(Error::Stalled) => (),
+        Ok(data) => {
+            return Err(format!(
+                "expected a STALL, but the request succeeded with {} bytes",
+                data.len()
+            ))
+        }
+        Err(error) => return Err(format!("expected a STALL, but the request failed: {error}")),
+    }
+
+    // A protocol STALL only lasts until the next setup packet; so EP0 should work again.
+    read_configuration(device)
+        .map_err(|reason| format!("EP0 didn't recover from the STALL: {reason}"))?;
+    Ok(TestOutcome::Passed)
+}
+
+/// Checks the language table, and every string the descriptors refer to, can be read.
+fn check_strings(
+    device: &mut Device,
+    device_descriptor: &DeviceDescriptor,
+    configurations: &[ConfigurationDescriptor],
+) -> CheckResult {
+    // Gather every string index the descriptors mention...
+    let mut indices = vec![
+        device_descriptor.manufacturer_string_index,
+        device_descriptor.product_string_index,
+        device_descriptor.serial_string_index,
+    ];
+    for configuration in configurations {
+        indices.push(configuration.configuration_string_index);
+        indices.extend(
+            configuration
+                .interfaces
+                .iter()
+                .map(|interface| interface.interface_string_index),
+        );
+    }
+    indices.retain(|&index| index != 0);
+    indices.sort_unstable();
+    indices.dedup();
+
+    if indices.is_empty() {
+        return Ok(TestOutcome::Skipped("the device has no strings".into()));
+    }
+
+    // ... check the language table is a whole number of language IDs...
+    let languages = device
+        .read_standard_descriptor(DescriptorType::String, 0)
+        .map_err(request_failed("reading the language table"))?;
+    let length = languages.first().copied().unwrap_or(0) as usize;
+    if length < 4 || length != languages.len() || !length.is_multiple_of(2) {
+        return Err(format!(
+            "the language table is malformed: bLength is {length}, with {} bytes returned",
+            languages.len()
+        ));
+    }
+
+    // ... and make sure each string can be read.
+    for index in indices {
+        device
+            .read_string_descriptor(index)
+            .map_err(|error| format!("reading string {index} failed: {error}"))?;
+    }
+
+    Ok(TestOutcome::Passed)
+}
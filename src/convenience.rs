@@ -1,10 +1,30 @@
 //! Convenience functions to make working with the library easier.
 
-use std::{sync::Arc, sync::RwLock};
+use std::sync::Arc;
 
-use crate::ReadBuffer;
+use parking_lot::RwLock;
+
+use crate::{ReadBuffer, WriteBuffer};
 
 /// Convenience function that creates a read buffer suitable for use with our async functions.
 pub fn create_read_buffer(size: usize) -> ReadBuffer {
     Arc::new(RwLock::new(vec![0; size]))
 }
+
+/// Convenience function that creates a write buffer, suitable for use with our async functions,
+/// from e.g. a Vec or a slice.
+pub fn create_write_buffer(data: impl Into<Vec<u8>>) -> WriteBuffer {
+    Arc::new(data.into())
+}
+
+/// Convenience function that wraps static data (e.g. a `b"..."` literal) in a write buffer,
+/// without copying it.
+pub fn static_write_buffer(data: &'static [u8]) -> WriteBuffer {
+    Arc::new(data)
+}
+
+/// Convenience function that creates a write buffer holding each of the provided slices,
+/// one after another; e.g. for a header followed by a payload.
+pub fn create_write_buffer_from_slices(slices: &[&[u8]]) -> WriteBuffer {
+    Arc::new(slices.concat())
+}
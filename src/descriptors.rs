@@ -0,0 +1,785 @@
+//! Tools for parsing USB descriptors.
+
+use std::time::Duration;
+
+use crate::{
+    device::DeviceSpeed,
+    request::{DescriptorType, Direction},
+    Error, UsbResult,
+};
+
+mod validate;
+
+pub use validate::{validate, DescriptorIssue, DescriptorIssueKind};
+
+/// Iterator over the individual descriptors packed into a descriptor blob,
+/// such as the full configuration descriptor returned by GET_DESCRIPTOR.
+///
+/// Each item is the raw bytes of a single descriptor, including its two-byte header.
+/// Iteration stops at the first descriptor whose length field doesn't make sense.
+#[derive(Debug, Clone)]
+pub struct DescriptorIterator<'a> {
+    /// The portion of the blob we haven't yet handed out.
+    remaining: &'a [u8],
+}
+
+impl<'a> DescriptorIterator<'a> {
+    /// Creates an iterator over the descriptors in the given blob.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { remaining: data }
+    }
+}
+
+impl<'a> Iterator for DescriptorIterator<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        // Every descriptor needs at least a length and a type...
+        if self.remaining.len() < 2 {
+            return None;
+        }
+
+        // ... and a length that actually fits in what we have left. If we have a descriptor
+        // that claims to be shorter than its own header (or longer than our data), we can't
+        // trust anything after it; so we'll stop here rather than spin or read garbage.
+        let length = self.remaining[0] as usize;
+        if length < 2 || length > self.remaining.len() {
+            self.remaining = &[];
+            return None;
+        }
+
+        let (descriptor, rest) = self.remaining.split_at(length);
+        self.remaining = rest;
+        Some(descriptor)
+    }
+}
+
+/// The transfer type of an endpoint, as encoded in its bmAttributes.
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum TransferType {
+    Control = 0,
+    Isochronous = 1,
+    Bulk = 2,
+    Interrupt = 3,
+}
+
+/// Helper that reads a little-endian u16 out of a descriptor.
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Helper that ensures a descriptor is of the expected type and at least the expected length.
+fn check_header(
+    data: &[u8],
+    descriptor_type: DescriptorType,
+    minimum_length: usize,
+) -> UsbResult<()> {
+    if data.len() < minimum_length || (data[0] as usize) < minimum_length {
+        return Err(Error::MalformedDescriptor);
+    }
+    if data[1] != descriptor_type as u8 {
+        return Err(Error::MalformedDescriptor);
+    }
+
+    Ok(())
+}
+
+/// Parsed form of a standard device descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceDescriptor {
+    /// The USB specification version the device claims to follow, in BCD (bcdUSB).
+    pub usb_version: u16,
+
+    /// The device's class code (bDeviceClass).
+    pub device_class: u8,
+
+    /// The device's subclass code (bDeviceSubClass).
+    pub device_subclass: u8,
+
+    /// The device's protocol code (bDeviceProtocol).
+    pub device_protocol: u8,
+
+    /// The maximum packet size for EP0 (bMaxPacketSize0).
+    pub max_packet_size_ep0: u8,
+
+    /// The Vendor ID (idVendor) assigned to the device.
+    pub vendor_id: u16,
+
+    /// The Product ID (idProduct) associated with the device.
+    pub product_id: u16,
+
+    /// The device's release number, in BCD (bcdDevice).
+    pub device_version: u16,
+
+    /// String index for the manufacturer string; or 0 if there isn't one.
+    pub manufacturer_string_index: u8,
+
+    /// String index for the product string; or 0 if there isn't one.
+    pub product_string_index: u8,
+
+    /// String index for the serial number string; or 0 if there isn't one.
+    pub serial_string_index: u8,
+
+    /// The number of configurations the device supports.
+    pub num_configurations: u8,
+}
+
+impl DeviceDescriptor {
+    /// The length of a standard device descriptor.
+    pub const LENGTH: usize = 18;
+
+    /// Parses a device descriptor from its raw bytes.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        check_header(data, DescriptorType::Device, Self::LENGTH)?;
+
+        Ok(Self {
+            usb_version: read_u16(data, 2),
+            device_class: data[4],
+            device_subclass: data[5],
+            device_protocol: data[6],
+            max_packet_size_ep0: data[7],
+            vendor_id: read_u16(data, 8),
+            product_id: read_u16(data, 10),
+            device_version: read_u16(data, 12),
+            manufacturer_string_index: data[14],
+            product_string_index: data[15],
+            serial_string_index: data[16],
+            num_configurations: data[17],
+        })
+    }
+
+    /// Returns the descriptor's raw bytes, as a device would send them.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![Self::LENGTH as u8, DescriptorType::Device as u8];
+        data.extend(self.usb_version.to_le_bytes());
+        data.extend([
+            self.device_class,
+            self.device_subclass,
+            self.device_protocol,
+            self.max_packet_size_ep0,
+        ]);
+        data.extend(self.vendor_id.to_le_bytes());
+        data.extend(self.product_id.to_le_bytes());
+        data.extend(self.device_version.to_le_bytes());
+        data.extend([
+            self.manufacturer_string_index,
+            self.product_string_index,
+            self.serial_string_index,
+            self.num_configurations,
+        ]);
+        data
+    }
+}
+
+/// Parsed form of a configuration descriptor, including all of its subordinate descriptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigurationDescriptor {
+    /// The total length of the configuration, including all subordinate descriptors.
+    pub total_length: u16,
+
+    /// The number of interfaces in this configuration.
+    pub num_interfaces: u8,
+
+    /// The value used to select this configuration (bConfigurationValue).
+    pub configuration_value: u8,
+
+    /// String index describing this configuration; or 0 if there isn't one.
+    pub configuration_string_index: u8,
+
+    /// The configuration's characteristics (bmAttributes); e.g. self-powered or remote wakeup.
+    pub attributes: u8,
+
+    /// The maximum power draw of the device in this configuration, in 2mA units (or 8mA for SuperSpeed).
+    pub max_power: u8,
+
+    /// Each of the interface descriptors in this configuration, including each alternate setting.
+    pub interfaces: Vec<InterfaceDescriptor>,
+
+    /// Any non-standard descriptors that appear before the first interface.
+    pub extra: Vec<Vec<u8>>,
+}
+
+impl ConfigurationDescriptor {
+    /// The length of the configuration descriptor itself, without its subordinates.
+    pub const LENGTH: usize = 9;
+
+    /// Parses a full configuration descriptor, including its interfaces and endpoints.
+    ///
+    /// Class-specific descriptors are attached (raw) to whichever interface or endpoint
+    /// most recently preceded them, which is where their specifications place them.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        check_header(data, DescriptorType::Configuration, Self::LENGTH)?;
+
+        let mut configuration = Self {
+            total_length: read_u16(data, 2),
+            num_interfaces: data[4],
+            configuration_value: data[5],
+            configuration_string_index: data[6],
+            attributes: data[7],
+            max_power: data[8],
+            interfaces: vec![],
+            extra: vec![],
+        };
+
+        // Clamp ourselves to the length the configuration says it has, if we got extra...
+        let total_length = (configuration.total_length as usize).min(data.len());
+
+        // ... and walk each of the descriptors after the configuration descriptor itself.
+        for descriptor in DescriptorIterator::new(&data[..total_length]).skip(1) {
+            match descriptor[1] {
+                t if t == DescriptorType::Interface as u8 => {
+                    configuration
+                        .interfaces
+                        .push(InterfaceDescriptor::parse(descriptor)?);
+                }
+                t if t == DescriptorType::Endpoint as u8 => {
+                    let endpoint = EndpointDescriptor::parse(descriptor)?;
+                    configuration
+                        .interfaces
+                        .last_mut()
+                        .ok_or(Error::MalformedDescriptor)?
+                        .endpoints
+                        .push(endpoint);
+                }
+                _ => configuration.attach_extra(descriptor),
+            }
+        }
+
+        Ok(configuration)
+    }
+
+    /// Attaches a non-standard descriptor to whatever most recently preceded it.
+    fn attach_extra(&mut self, descriptor: &[u8]) {
+        let owned = descriptor.to_vec();
+
+        match self.interfaces.last_mut() {
+            Some(interface) => match interface.endpoints.last_mut() {
+                Some(endpoint) => endpoint.extra.push(owned),
+                None => interface.extra.push(owned),
+            },
+            None => self.extra.push(owned),
+        }
+    }
+
+    /// Returns the configuration's raw bytes, including its subordinate descriptors, as a device
+    /// would send them. The total length and interface count are worked out from what's there,
+    /// rather than taken from our fields.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut subordinates: Vec<u8> = self.extra.concat();
+        for interface in &self.interfaces {
+            subordinates.extend(interface.to_bytes());
+        }
+
+        let mut interface_numbers: Vec<u8> = self
+            .interfaces
+            .iter()
+            .map(|interface| interface.interface_number)
+            .collect();
+        interface_numbers.sort_unstable();
+        interface_numbers.dedup();
+
+        let total_length = (Self::LENGTH + subordinates.len()) as u16;
+        let mut data = vec![Self::LENGTH as u8, DescriptorType::Configuration as u8];
+        data.extend(total_length.to_le_bytes());
+        data.extend([
+            interface_numbers.len() as u8,
+            self.configuration_value,
+            self.configuration_string_index,
+            self.attributes,
+            self.max_power,
+        ]);
+        data.extend(subordinates);
+        data
+    }
+
+    /// Returns the interface descriptor for the given interface number and alternate setting.
+    pub fn interface(&self, number: u8, alternate_setting: u8) -> Option<&InterfaceDescriptor> {
+        self.interfaces.iter().find(|interface| {
+            interface.interface_number == number && interface.alternate_setting == alternate_setting
+        })
+    }
+}
+
+/// Parsed form of an interface descriptor, including its endpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceDescriptor {
+    /// The number of this interface.
+    pub interface_number: u8,
+
+    /// The alternate setting this descriptor describes.
+    pub alternate_setting: u8,
+
+    /// The number of endpoints this interface uses, excluding EP0.
+    pub num_endpoints: u8,
+
+    /// The interface's class code (bInterfaceClass).
+    pub interface_class: u8,
+
+    /// The interface's subclass code (bInterfaceSubClass).
+    pub interface_subclass: u8,
+
+    /// The interface's protocol code (bInterfaceProtocol).
+    pub interface_protocol: u8,
+
+    /// String index describing this interface; or 0 if there isn't one.
+    pub interface_string_index: u8,
+
+    /// The endpoints associated with this interface.
+    pub endpoints: Vec<EndpointDescriptor>,
+
+    /// Any class-specific descriptors that follow the interface, but precede its endpoints.
+    pub extra: Vec<Vec<u8>>,
+}
+
+impl InterfaceDescriptor {
+    /// The length of a standard interface descriptor.
+    pub const LENGTH: usize = 9;
+
+    /// Parses an interface descriptor from its raw bytes. Endpoints are left empty.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        check_header(data, DescriptorType::Interface, Self::LENGTH)?;
+
+        Ok(Self {
+            interface_number: data[2],
+            alternate_setting: data[3],
+            num_endpoints: data[4],
+            interface_class: data[5],
+            interface_subclass: data[6],
+            interface_protocol: data[7],
+            interface_string_index: data[8],
+            endpoints: vec![],
+            extra: vec![],
+        })
+    }
+
+    /// Returns the interface's raw bytes, followed by its class-specific descriptors and its
+    /// endpoints; in the order they appear in a configuration.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![
+            Self::LENGTH as u8,
+            DescriptorType::Interface as u8,
+            self.interface_number,
+            self.alternate_setting,
+            self.endpoints.len() as u8,
+            self.interface_class,
+            self.interface_subclass,
+            self.interface_protocol,
+            self.interface_string_index,
+        ];
+        data.extend(self.extra.concat());
+        for endpoint in &self.endpoints {
+            data.extend(endpoint.to_bytes());
+        }
+        data
+    }
+
+    /// Returns the first endpoint on this interface with the given transfer type and direction.
+    pub fn find_endpoint(
+        &self,
+        transfer_type: TransferType,
+        direction: Direction,
+    ) -> Option<&EndpointDescriptor> {
+        self.endpoints.iter().find(|endpoint| {
+            endpoint.transfer_type() == transfer_type && endpoint.direction() == direction
+        })
+    }
+}
+
+/// Parsed form of an endpoint descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointDescriptor {
+    /// The endpoint's address, including its direction bit.
+    pub address: u8,
+
+    /// The endpoint's attributes (bmAttributes); including its transfer type.
+    pub attributes: u8,
+
+    /// The endpoint's raw wMaxPacketSize, including any high-bandwidth multiplier bits.
+    pub max_packet_size: u16,
+
+    /// The endpoint's polling interval (bInterval), in the speed-dependent encoding.
+    pub interval: u8,
+
+    /// Any class-specific descriptors that follow the endpoint.
+    pub extra: Vec<Vec<u8>>,
+}
+
+impl EndpointDescriptor {
+    /// The length of a standard endpoint descriptor. (Audio endpoints tack on two more bytes.)
+    pub const LENGTH: usize = 7;
+
+    /// Parses an endpoint descriptor from its raw bytes.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        check_header(data, DescriptorType::Endpoint, Self::LENGTH)?;
+
+        Ok(Self {
+            address: data[2],
+            attributes: data[3],
+            max_packet_size: read_u16(data, 4),
+            interval: data[6],
+            extra: vec![],
+        })
+    }
+
+    /// Returns the endpoint's raw bytes, followed by any descriptors that accompany it
+    /// (e.g. SuperSpeed endpoint companions).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![
+            Self::LENGTH as u8,
+            DescriptorType::Endpoint as u8,
+            self.address,
+            self.attributes,
+        ];
+        data.extend(self.max_packet_size.to_le_bytes());
+        data.push(self.interval);
+        data.extend(self.extra.concat());
+        data
+    }
+
+    /// Returns the endpoint number, without its direction bit.
+    pub fn number(&self) -> u8 {
+        self.address & 0x7F
+    }
+
+    /// Returns the direction of the endpoint.
+    pub fn direction(&self) -> Direction {
+        if (self.address & 0x80) != 0 {
+            Direction::In
+        } else {
+            Direction::Out
+        }
+    }
+
+    /// Returns the transfer type of the endpoint.
+    pub fn transfer_type(&self) -> TransferType {
+        match self.attributes & 0b11 {
+            0 => TransferType::Control,
+            1 => TransferType::Isochronous,
+            2 => TransferType::Bulk,
+            _ => TransferType::Interrupt,
+        }
+    }
+
+    /// Returns the size of each packet, in bytes, without any high-bandwidth multiplier bits.
+    pub fn packet_size(&self) -> usize {
+        (self.max_packet_size & 0x7FF) as usize
+    }
+
+    /// Returns how often the bus services this endpoint, on a device running at the given
+    /// speed; decoded from its bInterval. None for bulk and control endpoints, whose bInterval
+    /// means something else.
+    pub fn service_interval(&self, speed: DeviceSpeed) -> Option<Duration> {
+        let transfer_type = self.transfer_type();
+
+        // Full- and low-speed interrupt endpoints count whole frames...
+        if transfer_type == TransferType::Interrupt && !speed.uses_microframes() {
+            return Some(Duration::from_millis(self.interval.max(1) as u64));
+        }
+
+        // ... while everything else periodic uses an exponent, in units of (micro)frames.
+        if !matches!(
+            transfer_type,
+            TransferType::Interrupt | TransferType::Isochronous
+        ) {
+            return None;
+        }
+        let exponent = self.interval.clamp(1, 16) as u32 - 1;
+        let unit = if speed.uses_microframes() {
+            Duration::from_micros(125)
+        } else {
+            Duration::from_millis(1)
+        };
+
+        Some(unit * 2u32.pow(exponent))
+    }
+}
+
+/// The platform capability UUID used by WebUSB; as it appears on the wire.
+pub const WEBUSB_PLATFORM_UUID: [u8; 16] = [
+    0x38, 0xb6, 0x08, 0x34, 0xa9, 0x09, 0xa0, 0x47, 0x8b, 0xfd, 0xa0, 0x76, 0x88, 0x15, 0xb6, 0x65,
+];
+
+/// The platform capability UUID used by Microsoft OS 2.0 descriptors; as it appears on the wire.
+pub const MS_OS_20_PLATFORM_UUID: [u8; 16] = [
+    0xdf, 0x60, 0xdd, 0xd8, 0x89, 0x45, 0xc7, 0x4c, 0x9c, 0xd2, 0x65, 0x9d, 0x9e, 0x64, 0x8a, 0x9f,
+];
+
+/// Parsed form of a Binary device Object Store (BOS) descriptor, including its device
+/// capability descriptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BosDescriptor {
+    /// The total length of the BOS descriptor and its capabilities (wTotalLength).
+    pub total_length: u16,
+
+    /// The number of capabilities the device claims to have (bNumDeviceCaps).
+    pub num_capabilities: u8,
+
+    /// The device's capabilities, in the order they appeared.
+    pub capabilities: Vec<DeviceCapability>,
+}
+
+impl BosDescriptor {
+    /// The length of the BOS descriptor itself, without its capabilities.
+    pub const LENGTH: usize = 5;
+
+    /// Parses a full BOS descriptor, including its device capabilities.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        check_header(data, DescriptorType::Bos, Self::LENGTH)?;
+
+        let total_length = read_u16(data, 2);
+        let num_capabilities = data[4];
+        let data = &data[..(total_length as usize).min(data.len())];
+
+        let capabilities = DescriptorIterator::new(data)
+            .skip(1)
+            .filter(|descriptor| descriptor[1] == DescriptorType::DeviceCapability as u8)
+            .map(DeviceCapability::parse)
+            .collect::<UsbResult<_>>()?;
+
+        Ok(Self {
+            total_length,
+            num_capabilities,
+            capabilities,
+        })
+    }
+
+    /// Returns the data from the platform capability with the given UUID, if the device has one;
+    /// e.g. [WEBUSB_PLATFORM_UUID] or [MS_OS_20_PLATFORM_UUID].
+    pub fn platform_capability(&self, uuid: &[u8; 16]) -> Option<&[u8]> {
+        self.capabilities
+            .iter()
+            .find_map(|capability| match capability {
+                DeviceCapability::Platform {
+                    uuid: candidate,
+                    data,
+                } if candidate == uuid => Some(data.as_slice()),
+                _ => None,
+            })
+    }
+}
+
+/// A single device capability descriptor, from a device's BOS descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceCapability {
+    /// The USB 2.0 extension capability; which mostly describes Link Power Management support.
+    Usb20Extension {
+        /// The capability's attributes (bmAttributes).
+        attributes: u32,
+    },
+
+    /// The SuperSpeed USB capability.
+    SuperSpeed {
+        /// The capability's attributes (bmAttributes); e.g. whether LTM is supported.
+        attributes: u8,
+
+        /// A bitmap of the speeds the device supports (wSpeedsSupported).
+        speeds_supported: u16,
+
+        /// The lowest speed at which the device is fully functional (bFunctionalitySupport).
+        functionality_support: u8,
+
+        /// The device's U1 exit latency, in microseconds (bU1DevExitLat).
+        u1_exit_latency: u8,
+
+        /// The device's U2 exit latency, in microseconds (wU2DevExitLat).
+        u2_exit_latency: u16,
+    },
+
+    /// A unique identifier for the device, which stays the same across all of its
+    /// connections (e.g. on both sides of a hub).
+    ContainerId([u8; 16]),
+
+    /// A platform-specific capability; e.g. WebUSB or Microsoft OS 2.0 descriptors.
+    Platform {
+        /// The UUID identifying the platform capability, as it appears on the wire.
+        uuid: [u8; 16],
+
+        /// The capability's platform-specific data.
+        data: Vec<u8>,
+    },
+
+    /// A capability we don't parse; with the data that follows its bDevCapabilityType.
+    Other {
+        /// The capability's type (bDevCapabilityType).
+        capability_type: u8,
+
+        /// The remainder of the capability descriptor.
+        data: Vec<u8>,
+    },
+}
+
+impl DeviceCapability {
+    /// Parses a single device capability descriptor from its raw bytes.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        check_header(data, DescriptorType::DeviceCapability, 3)?;
+        let data = &data[..(data[0] as usize).min(data.len())];
+
+        // Each capability type has its own length; so we'll check each against its own.
+        let check_length = |length: usize| {
+            if data.len() < length {
+                return Err(Error::MalformedDescriptor);
+            }
+            Ok(())
+        };
+
+        match data[2] {
+            0x02 => {
+                check_length(7)?;
+                Ok(Self::Usb20Extension {
+                    attributes: u32::from_le_bytes([data[3], data[4], data[5], data[6]]),
+                })
+            }
+            0x03 => {
+                check_length(10)?;
+                Ok(Self::SuperSpeed {
+                    attributes: data[3],
+                    speeds_supported: read_u16(data, 4),
+                    functionality_support: data[6],
+                    u1_exit_latency: data[7],
+                    u2_exit_latency: read_u16(data, 8),
+                })
+            }
+            0x04 => {
+                check_length(20)?;
+                Ok(Self::ContainerId(data[4..20].try_into().unwrap()))
+            }
+            0x05 => {
+                check_length(20)?;
+                Ok(Self::Platform {
+                    uuid: data[4..20].try_into().unwrap(),
+                    data: data[20..].to_vec(),
+                })
+            }
+            capability_type => Ok(Self::Other {
+                capability_type,
+                data: data[3..].to_vec(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A container ID capability, with the given ID's first byte.
+    fn container_id(first: u8) -> Vec<u8> {
+        let mut capability = vec![20, 0x10, 0x04, 0];
+        capability.push(first);
+        capability.extend([0; 15]);
+        capability
+    }
+
+    /// A platform capability with the given UUID and data.
+    fn platform(uuid: &[u8; 16], data: &[u8]) -> Vec<u8> {
+        let mut capability = vec![20 + data.len() as u8, 0x10, 0x05, 0];
+        capability.extend(uuid);
+        capability.extend(data);
+        capability
+    }
+
+    /// A BOS descriptor wrapping the given capabilities, with a correct wTotalLength.
+    fn bos(capabilities: &[Vec<u8>]) -> Vec<u8> {
+        let total_length = BosDescriptor::LENGTH + capabilities.iter().map(Vec::len).sum::<usize>();
+
+        let mut data = vec![5, 0x0f];
+        data.extend((total_length as u16).to_le_bytes());
+        data.push(capabilities.len() as u8);
+        for capability in capabilities {
+            data.extend(capability);
+        }
+        data
+    }
+
+    #[test]
+    fn bos_descriptors_parse() {
+        let usb2 = vec![7, 0x10, 0x02, 0x06, 0, 0, 0];
+        let bos = BosDescriptor::parse(&bos(&[usb2, container_id(0xaa)])).unwrap();
+
+        assert_eq!(bos.total_length, 32);
+        assert_eq!(bos.num_capabilities, 2);
+        assert_eq!(
+            bos.capabilities[0],
+            DeviceCapability::Usb20Extension { attributes: 0x06 }
+        );
+        assert!(matches!(
+            bos.capabilities[1],
+            DeviceCapability::ContainerId([0xaa, ..])
+        ));
+    }
+
+    #[test]
+    fn platform_capabilities_are_found_by_uuid() {
+        let bos = BosDescriptor::parse(&bos(&[
+            platform(&WEBUSB_PLATFORM_UUID, &[0x00, 0x01, 0x01, 0x01]),
+            platform(
+                &MS_OS_20_PLATFORM_UUID,
+                &[0x00, 0x00, 0x03, 0x06, 0xb2, 0x00, 0x01, 0x00],
+            ),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            bos.platform_capability(&WEBUSB_PLATFORM_UUID),
+            Some(&[0x00, 0x01, 0x01, 0x01][..])
+        );
+        assert_eq!(
+            bos.platform_capability(&MS_OS_20_PLATFORM_UUID)
+                .map(<[u8]>::len),
+            Some(8)
+        );
+        assert_eq!(bos.platform_capability(&[0; 16]), None);
+    }
+
+    #[test]
+    fn capabilities_past_the_total_length_are_ignored() {
+        let mut data = bos(&[container_id(1), container_id(2)]);
+
+        // Claim only the first capability is part of the BOS descriptor.
+        data[2] = 25;
+        let bos = BosDescriptor::parse(&data).unwrap();
+        assert_eq!(bos.total_length, 25);
+        assert_eq!(bos.capabilities.len(), 1);
+        assert!(matches!(
+            bos.capabilities[0],
+            DeviceCapability::ContainerId([1, ..])
+        ));
+    }
+
+    #[test]
+    fn truncated_capabilities_arent_parsed() {
+        // A capability that runs past the end of the data can't be trusted; so it's dropped...
+        let mut data = bos(&[container_id(1), container_id(2)]);
+        data.truncate(data.len() - 4);
+        assert_eq!(BosDescriptor::parse(&data).unwrap().capabilities.len(), 1);
+
+        // ... while one whose bLength is too short for its type makes the descriptor malformed.
+        let mut short = container_id(1);
+        short[0] = 8;
+        short.truncate(8);
+        assert_eq!(
+            BosDescriptor::parse(&bos(&[short])),
+            Err(Error::MalformedDescriptor)
+        );
+        assert_eq!(
+            DeviceCapability::parse(&[3, 0x10, 0x05]),
+            Err(Error::MalformedDescriptor)
+        );
+        assert_eq!(
+            DeviceCapability::parse(&[2, 0x10]),
+            Err(Error::MalformedDescriptor)
+        );
+    }
+
+    #[test]
+    fn unknown_capabilities_are_kept() {
+        assert_eq!(
+            DeviceCapability::parse(&[5, 0x10, 0x0a, 1, 2]),
+            Ok(DeviceCapability::Other {
+                capability_type: 0x0a,
+                data: vec![1, 2]
+            })
+        );
+    }
+}
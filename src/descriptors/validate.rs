@@ -0,0 +1,615 @@
+//! Checks for descriptor blobs that don't follow the rules; for people writing device firmware.
+//! See [validate].
+
+use std::collections::HashSet;
+
+use super::{
+    read_u16, ConfigurationDescriptor, DeviceDescriptor, EndpointDescriptor, TransferType,
+};
+use crate::request::DescriptorType;
+
+/// The length of a standard interface descriptor.
+const INTERFACE_LENGTH: usize = 9;
+
+/// Something wrong with a descriptor, as found by [validate].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorIssue {
+    /// Where the offending descriptor starts, in the blob that was validated.
+    pub offset: usize,
+
+    /// What's wrong with it.
+    pub kind: DescriptorIssueKind,
+}
+
+/// The ways a descriptor can be wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorIssueKind {
+    /// The blob is empty, or too short to hold even a descriptor header.
+    Empty,
+
+    /// The blob starts with something other than a device or configuration descriptor.
+    UnexpectedDescriptorType { descriptor_type: u8 },
+
+    /// The descriptor's bLength runs past the end of the data we have.
+    Truncated { length: u8, available: usize },
+
+    /// The descriptor's bLength is shorter than its type requires.
+    TooShort { length: u8, minimum: usize },
+
+    /// Data is left over after the last descriptor; but not enough to make up another.
+    TrailingBytes { count: usize },
+
+    /// A configuration's wTotalLength doesn't match the descriptors that actually follow it.
+    WrongTotalLength { declared: u16, actual: usize },
+
+    /// A configuration's bNumInterfaces doesn't match the interfaces that actually follow it.
+    WrongInterfaceCount { declared: u8, actual: usize },
+
+    /// An interface's bNumEndpoints doesn't match the endpoints that actually follow it.
+    WrongEndpointCount {
+        interface: u8,
+        alternate_setting: u8,
+        declared: u8,
+        actual: usize,
+    },
+
+    /// The same interface and alternate setting are described more than once.
+    DuplicateInterface {
+        interface: u8,
+        alternate_setting: u8,
+    },
+
+    /// The same endpoint address appears more than once in a single alternate setting.
+    DuplicateEndpoint {
+        interface: u8,
+        alternate_setting: u8,
+        address: u8,
+    },
+
+    /// An endpoint descriptor appears before any interface descriptor.
+    EndpointOutsideInterface { address: u8 },
+
+    /// An endpoint descriptor describes endpoint zero; which is never described.
+    EndpointZero { interface: u8 },
+
+    /// An endpoint descriptor claims to be a control endpoint; which interfaces can't have.
+    ControlEndpoint { address: u8 },
+
+    /// A non-isochronous endpoint has a wMaxPacketSize of zero; so it could never move data.
+    ZeroPacketSize { address: u8 },
+
+    /// An interrupt or isochronous endpoint has a bInterval of zero.
+    ZeroInterval { address: u8 },
+
+    /// The device descriptor's bMaxPacketSize0 isn't a size EP0 is allowed to have.
+    InvalidEp0PacketSize { size: u8 },
+
+    /// The device descriptor claims the device has no configurations.
+    NoConfigurations,
+
+    /// A configuration's bmAttributes has reserved bits set; or is missing bit 7, which
+    /// must always be set.
+    InvalidConfigurationAttributes { attributes: u8 },
+}
+
+impl std::fmt::Display for DescriptorIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "offset {}: {}", self.offset, self.kind)
+    }
+}
+
+impl std::fmt::Display for DescriptorIssueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no descriptor data"),
+            Self::UnexpectedDescriptorType { descriptor_type } => write!(
+                f,
+                "expected a device or configuration descriptor, but found type {descriptor_type:#04x}"
+            ),
+            Self::Truncated { length, available } => write!(
+                f,
+                "bLength is {length}, but only {available} bytes remain"
+            ),
+            Self::TooShort { length, minimum } => write!(
+                f,
+                "bLength is {length}, but this type of descriptor needs at least {minimum}"
+            ),
+            Self::TrailingBytes { count } => {
+                write!(f, "{count} stray bytes after the last descriptor")
+            }
+            Self::WrongTotalLength { declared, actual } => write!(
+                f,
+                "wTotalLength is {declared}, but the descriptors add up to {actual}"
+            ),
+            Self::WrongInterfaceCount { declared, actual } => write!(
+                f,
+                "bNumInterfaces is {declared}, but {actual} interfaces are described"
+            ),
+            Self::WrongEndpointCount {
+                interface,
+                alternate_setting,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "interface {interface} (alternate setting {alternate_setting}) has bNumEndpoints {declared}, but {actual} endpoints are described"
+            ),
+            Self::DuplicateInterface {
+                interface,
+                alternate_setting,
+            } => write!(
+                f,
+                "interface {interface} (alternate setting {alternate_setting}) is described more than once"
+            ),
+            Self::DuplicateEndpoint {
+                interface,
+                alternate_setting,
+                address,
+            } => write!(
+                f,
+                "endpoint {address:#04x} appears more than once in interface {interface} (alternate setting {alternate_setting})"
+            ),
+            Self::EndpointOutsideInterface { address } => write!(
+                f,
+                "endpoint {address:#04x} is described before any interface"
+            ),
+            Self::EndpointZero { interface } => {
+                write!(f, "interface {interface} describes endpoint zero")
+            }
+            Self::ControlEndpoint { address } => {
+                write!(f, "endpoint {address:#04x} claims to be a control endpoint")
+            }
+            Self::ZeroPacketSize { address } => {
+                write!(f, "endpoint {address:#04x} has a wMaxPacketSize of zero")
+            }
+            Self::ZeroInterval { address } => {
+                write!(f, "endpoint {address:#04x} has a bInterval of zero")
+            }
+            Self::InvalidEp0PacketSize { size } => write!(
+                f,
+                "bMaxPacketSize0 is {size}; it must be 8, 16, 32, or 64 (or 9, for SuperSpeed)"
+            ),
+            Self::NoConfigurations => write!(f, "bNumConfigurations is zero"),
+            Self::InvalidConfigurationAttributes { attributes } => write!(
+                f,
+                "bmAttributes is {attributes:#04x}; bit 7 must be set, and bits 4-0 must be clear"
+            ),
+        }
+    }
+}
+
+/// Checks a device descriptor, or a full configuration descriptor, for violations of the
+/// USB specification; e.g. bad lengths, miscounted interfaces or endpoints, or duplicate
+/// endpoint addresses. Returns everything we found; which is empty for a valid descriptor.
+///
+/// Unlike the parsers in this module, this keeps going after the first problem it finds;
+/// so firmware authors can fix everything in one pass.
+pub fn validate(data: &[u8]) -> Vec<DescriptorIssue> {
+    let mut validator = Validator::default();
+    if data.len() < 2 {
+        validator.report(0, DescriptorIssueKind::Empty);
+        return validator.issues;
+    }
+
+    match data[1] {
+        t if t == DescriptorType::Device as u8 => validator.validate_device(data),
+        t if t == DescriptorType::Configuration as u8
+            || t == DescriptorType::OtherSpeedConfiguration as u8 =>
+        {
+            validator.validate_configuration(data)
+        }
+        descriptor_type => validator.report(
+            0,
+            DescriptorIssueKind::UnexpectedDescriptorType { descriptor_type },
+        ),
+    }
+
+    validator.issues
+}
+
+/// Accumulates the issues found while validating a single blob.
+#[derive(Debug, Default)]
+struct Validator {
+    issues: Vec<DescriptorIssue>,
+}
+
+impl Validator {
+    /// Notes an issue with the descriptor at the given offset.
+    fn report(&mut self, offset: usize, kind: DescriptorIssueKind) {
+        self.issues.push(DescriptorIssue { offset, kind });
+    }
+
+    /// Checks that the descriptor at the given offset fits in the data, and is long enough
+    /// for its type. Returns the descriptor if it's safe to read `minimum` bytes of it.
+    fn check_length<'a>(
+        &mut self,
+        data: &'a [u8],
+        offset: usize,
+        minimum: usize,
+    ) -> Option<&'a [u8]> {
+        let length = data[offset];
+        let available = data.len() - offset;
+
+        if length as usize > available {
+            self.report(offset, DescriptorIssueKind::Truncated { length, available });
+            return None;
+        }
+        if (length as usize) < minimum {
+            self.report(offset, DescriptorIssueKind::TooShort { length, minimum });
+            return None;
+        }
+
+        Some(&data[offset..offset + length as usize])
+    }
+
+    /// Validates a standard device descriptor.
+    fn validate_device(&mut self, data: &[u8]) {
+        let Some(descriptor) = self.check_length(data, 0, DeviceDescriptor::LENGTH) else {
+            return;
+        };
+
+        // EP0 can only have a handful of sizes; SuperSpeed devices give theirs as a power of two.
+        if !matches!(descriptor[7], 8 | 16 | 32 | 64 | 9) {
+            self.report(
+                0,
+                DescriptorIssueKind::InvalidEp0PacketSize {
+                    size: descriptor[7],
+                },
+            );
+        }
+        if descriptor[17] == 0 {
+            self.report(0, DescriptorIssueKind::NoConfigurations);
+        }
+    }
+
+    /// Validates a configuration descriptor, and everything subordinate to it.
+    fn validate_configuration(&mut self, data: &[u8]) {
+        let Some(configuration) = self.check_length(data, 0, ConfigurationDescriptor::LENGTH)
+        else {
+            return;
+        };
+
+        let attributes = configuration[7];
+        if attributes & 0x80 == 0 || attributes & 0x1f != 0 {
+            self.report(
+                0,
+                DescriptorIssueKind::InvalidConfigurationAttributes { attributes },
+            );
+        }
+
+        // Walk each descriptor, keeping track of the interface we're in; and, for each one we
+        // finish, checking that it had as many endpoints as it claimed.
+        let mut offset = configuration.len();
+        let mut interfaces = HashSet::new();
+        let mut current: Option<InterfaceState> = None;
+        let mut complete = true;
+
+        while offset < data.len() {
+            if data.len() - offset < 2 {
+                self.report(
+                    offset,
+                    DescriptorIssueKind::TrailingBytes {
+                        count: data.len() - offset,
+                    },
+                );
+                complete = false;
+                break;
+            }
+
+            // A descriptor with a bogus length leaves us no way to find the next one; so we'll
+            // stop here.
+            let length = data[offset] as usize;
+            if length < 2 || length > data.len() - offset {
+                self.check_length(data, offset, 2);
+                complete = false;
+                break;
+            }
+
+            match data[offset + 1] {
+                t if t == DescriptorType::Interface as u8 => {
+                    if let Some(interface) = current.take() {
+                        self.finish_interface(interface);
+                    }
+
+                    if let Some(descriptor) = self.check_length(data, offset, INTERFACE_LENGTH) {
+                        let (number, alternate_setting) = (descriptor[2], descriptor[3]);
+                        if !interfaces.insert((number, alternate_setting)) {
+                            self.report(
+                                offset,
+                                DescriptorIssueKind::DuplicateInterface {
+                                    interface: number,
+                                    alternate_setting,
+                                },
+                            );
+                        }
+
+                        current = Some(InterfaceState {
+                            offset,
+                            number,
+                            alternate_setting,
+                            declared_endpoints: descriptor[4],
+                            endpoints: vec![],
+                        });
+                    }
+                }
+                t if t == DescriptorType::Endpoint as u8 => {
+                    if let Some(descriptor) =
+                        self.check_length(data, offset, EndpointDescriptor::LENGTH)
+                    {
+                        self.validate_endpoint(descriptor, offset, current.as_mut());
+                    }
+                }
+                _ => {}
+            }
+
+            offset += length;
+        }
+
+        if let Some(interface) = current.take() {
+            self.finish_interface(interface);
+        }
+
+        // Finally, check the configuration's own counts against what we found. If we couldn't
+        // walk every descriptor, we don't know the real total length; but we've already
+        // reported why.
+        let declared_length = read_u16(configuration, 2);
+        if complete && declared_length as usize != offset {
+            self.report(
+                0,
+                DescriptorIssueKind::WrongTotalLength {
+                    declared: declared_length,
+                    actual: offset,
+                },
+            );
+        }
+
+        // Alternate settings don't count as separate interfaces.
+        let interface_count = interfaces
+            .iter()
+            .map(|(number, _)| number)
+            .collect::<HashSet<_>>()
+            .len();
+        if configuration[4] as usize != interface_count {
+            self.report(
+                0,
+                DescriptorIssueKind::WrongInterfaceCount {
+                    declared: configuration[4],
+                    actual: interface_count,
+                },
+            );
+        }
+    }
+
+    /// Validates a single endpoint descriptor; noting it in the interface that contains it.
+    fn validate_endpoint(
+        &mut self,
+        descriptor: &[u8],
+        offset: usize,
+        interface: Option<&mut InterfaceState>,
+    ) {
+        let endpoint = EndpointDescriptor {
+            address: descriptor[2],
+            attributes: descriptor[3],
+            max_packet_size: read_u16(descriptor, 4),
+            interval: descriptor[6],
+            extra: vec![],
+        };
+        let address = endpoint.address;
+
+        let Some(interface) = interface else {
+            self.report(
+                offset,
+                DescriptorIssueKind::EndpointOutsideInterface { address },
+            );
+            return;
+        };
+
+        if endpoint.number() == 0 {
+            self.report(
+                offset,
+                DescriptorIssueKind::EndpointZero {
+                    interface: interface.number,
+                },
+            );
+        }
+        if interface.endpoints.contains(&address) {
+            self.report(
+                offset,
+                DescriptorIssueKind::DuplicateEndpoint {
+                    interface: interface.number,
+                    alternate_setting: interface.alternate_setting,
+                    address,
+                },
+            );
+        }
+        interface.endpoints.push(address);
+
+        // Isochronous endpoints can have zero-sized packets in their zero-bandwidth
+        // alternate settings; nothing else can.
+        match endpoint.transfer_type() {
+            TransferType::Control => {
+                self.report(offset, DescriptorIssueKind::ControlEndpoint { address })
+            }
+            TransferType::Bulk | TransferType::Interrupt if endpoint.packet_size() == 0 => {
+                self.report(offset, DescriptorIssueKind::ZeroPacketSize { address })
+            }
+            _ => {}
+        }
+        if matches!(
+            endpoint.transfer_type(),
+            TransferType::Interrupt | TransferType::Isochronous
+        ) && endpoint.interval == 0
+        {
+            self.report(offset, DescriptorIssueKind::ZeroInterval { address });
+        }
+    }
+
+    /// Checks an interface we've finished walking against what it declared.
+    fn finish_interface(&mut self, interface: InterfaceState) {
+        if interface.declared_endpoints as usize != interface.endpoints.len() {
+            self.report(
+                interface.offset,
+                DescriptorIssueKind::WrongEndpointCount {
+                    interface: interface.number,
+                    alternate_setting: interface.alternate_setting,
+                    declared: interface.declared_endpoints,
+                    actual: interface.endpoints.len(),
+                },
+            );
+        }
+    }
+}
+
+/// What we know about the interface we're currently walking.
+#[derive(Debug)]
+struct InterfaceState {
+    /// Where the interface's descriptor starts.
+    offset: usize,
+
+    /// The interface's number and alternate setting.
+    number: u8,
+    alternate_setting: u8,
+
+    /// The number of endpoints the interface claims to have.
+    declared_endpoints: u8,
+
+    /// The addresses of the endpoints we've seen so far.
+    endpoints: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A valid configuration, with one interface that has a bulk IN and a bulk OUT endpoint.
+    fn configuration() -> Vec<u8> {
+        vec![
+            9, 2, 32, 0, 1, 1, 0, 0x80, 50, // configuration
+            9, 4, 0, 0, 2, 0xff, 0, 0, 0, // interface 0
+            7, 5, 0x81, 0x02, 64, 0, 0, // bulk IN endpoint, at offset 18
+            7, 5, 0x01, 0x02, 64, 0, 0, // bulk OUT endpoint, at offset 25
+        ]
+    }
+
+    /// Builds an issue, for comparison against the ones we find.
+    fn issue(offset: usize, kind: DescriptorIssueKind) -> DescriptorIssue {
+        DescriptorIssue { offset, kind }
+    }
+
+    #[test]
+    fn valid_configurations_have_no_issues() {
+        assert_eq!(validate(&configuration()), vec![]);
+    }
+
+    #[test]
+    fn lengths_past_the_end_are_reported() {
+        let mut data = configuration();
+        data[25] = 9;
+
+        // We can't walk past the bad descriptor; so we can only count the endpoints before it.
+        assert_eq!(
+            validate(&data),
+            vec![
+                issue(
+                    25,
+                    DescriptorIssueKind::Truncated {
+                        length: 9,
+                        available: 7
+                    }
+                ),
+                issue(
+                    9,
+                    DescriptorIssueKind::WrongEndpointCount {
+                        interface: 0,
+                        alternate_setting: 0,
+                        declared: 2,
+                        actual: 1
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn lengths_too_short_for_the_type_are_reported() {
+        let mut device = vec![
+            18, 1, 0x00, 0x02, 0, 0, 0, 64, 0x09, 0x12, 0x01, 0x00, 0x00, 0x01, 1, 2, 3, 1,
+        ];
+        assert_eq!(validate(&device), vec![]);
+
+        device[0] = 8;
+        assert_eq!(
+            validate(&device),
+            vec![issue(
+                0,
+                DescriptorIssueKind::TooShort {
+                    length: 8,
+                    minimum: DeviceDescriptor::LENGTH
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn wrong_total_lengths_are_reported() {
+        let mut data = configuration();
+        data[2] = 40;
+
+        assert_eq!(
+            validate(&data),
+            vec![issue(
+                0,
+                DescriptorIssueKind::WrongTotalLength {
+                    declared: 40,
+                    actual: 32
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn duplicate_endpoints_are_reported() {
+        let mut data = configuration();
+        data[27] = 0x81;
+
+        assert_eq!(
+            validate(&data),
+            vec![issue(
+                25,
+                DescriptorIssueKind::DuplicateEndpoint {
+                    interface: 0,
+                    alternate_setting: 0,
+                    address: 0x81
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn every_issue_is_reported() {
+        let mut data = configuration();
+        data[2] = 40;
+        data[7] = 0x81;
+        data[27] = 0x81;
+
+        let kinds: Vec<_> = validate(&data)
+            .into_iter()
+            .map(|issue| issue.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DescriptorIssueKind::InvalidConfigurationAttributes { attributes: 0x81 },
+                DescriptorIssueKind::DuplicateEndpoint {
+                    interface: 0,
+                    alternate_setting: 0,
+                    address: 0x81
+                },
+                DescriptorIssueKind::WrongTotalLength {
+                    declared: 40,
+                    actual: 32
+                },
+            ]
+        );
+    }
+}
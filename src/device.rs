@@ -1,22 +1,62 @@
 //! Interface for working with USB devices.
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    any::Any,
+    collections::HashMap,
+    io::{IoSlice, IoSliceMut},
+    mem::MaybeUninit,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::warn;
 
 use crate::{
-    backend::{Backend, BackendDevice},
-    request::{DescriptorType, RequestType, StandardDeviceRequest, STANDARD_IN_FROM_DEVICE},
-    Error, ReadBuffer, UsbResult, WriteBuffer,
+    backend::{Backend, BackendDevice, BackendDeviceOps, DisconnectSignal, TransferCallback},
+    capture::{CapturedUrb, DeviceCapture, PcapCapture},
+    descriptors::{
+        BosDescriptor, ConfigurationDescriptor, DeviceDescriptor, EndpointDescriptor,
+        InterfaceDescriptor, TransferType,
+    },
+    io::{EndpointReader, EndpointWriter},
+    request::{
+        DescriptorType, Direction, FeatureSelector, Recipient, RequestType, SetupPacket,
+        StandardDeviceRequest, Type, STANDARD_IN_FROM_DEVICE, STANDARD_OUT_TO_DEVICE,
+    },
+    stats::DeviceStats,
+    topology::PortPath,
+    trace::Trace,
+    ContextError, Error, ReadBuffer, UsbResult, WriteBuffer,
 };
 
+#[cfg(feature = "typed-control")]
+use crate::typed::FromBytes;
 #[cfg(feature = "callbacks")]
-use crate::AsyncCallback;
+use crate::{
+    channel::{CompletionSender, ReadEvent, WriteEvent},
+    AsyncCallback, CompletionExecutor,
+};
 
 #[cfg(feature = "async")]
-use crate::futures::UsbFuture;
+use crate::{
+    backend::TransferMemory,
+    batch::{BatchCompletion, BatchHandle, TransferRequest},
+    futures::{DisconnectFuture, OwnedReadCompletion, ReadCompletion, UsbFuture, WriteCompletion},
+    iso::{iso_future, IsoOptions, IsoReadCompletion, IsoStream, IsoWriteCompletion},
+    stream::InterruptStream,
+};
+#[cfg(feature = "async")]
+use parking_lot::RwLock;
+
+/// How often we re-check the device list while waiting for a device to appear or disappear.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long [Device::close] waits for aborted transfers to report back.
+const CLOSE_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Contains known information for an unopened device.
 #[allow(dead_code)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct DeviceInformation {
     /// The Vendor ID (idVendor) assigned to the device.
     pub vendor_id: u16,
@@ -28,9 +68,11 @@ pub struct DeviceInformation {
     pub serial: Option<String>,
 
     /// The vendor string associated with the device, if and only if the OS has read it.
+    /// With the `usb-ids` feature, falls back to the vendor's name from the usb.ids database.
     pub vendor: Option<String>,
 
     /// The product string associated with the device, if and only if the OS has read it.
+    /// With the `usb-ids` feature, falls back to the product's name from the usb.ids database.
     pub product: Option<String>,
 
     /// Numeric field for backend use; can be used to contain a hint used to re-find the device for opening.
@@ -38,6 +80,57 @@ pub struct DeviceInformation {
 
     /// String field for backend use; can be used to contain a hint used to re-find the device for opening.
     pub(crate) backend_string_location: Option<String>,
+
+    /// The device descriptor, if the OS handed us a copy during enumeration.
+    pub(crate) device_descriptor: Option<DeviceDescriptor>,
+
+    /// True iff the backend skipped reading the device's strings; see [EnumerationOptions].
+    pub(crate) strings_deferred: bool,
+
+    /// Where the device is plugged in, if the backend can tell.
+    pub(crate) port_path: Option<PortPath>,
+
+    /// The speed the device is running at, if the backend can tell.
+    pub(crate) speed: Option<DeviceSpeed>,
+
+    /// The address the host assigned the device on its bus, if the backend can tell.
+    pub(crate) device_address: Option<u8>,
+
+    /// The device's class code (bDeviceClass), if the OS reported it during enumeration.
+    pub(crate) device_class: Option<u8>,
+
+    /// The device's subclass code (bDeviceSubClass), if the OS reported it.
+    pub(crate) device_subclass: Option<u8>,
+
+    /// The device's protocol code (bDeviceProtocol), if the OS reported it.
+    pub(crate) device_protocol: Option<u8>,
+
+    /// The class codes of each of the device's interfaces, if the OS reported them.
+    pub(crate) interface_classes: Option<Vec<InterfaceClass>>,
+}
+
+/// The class codes of one of a device's interfaces; see [DeviceInformation::interface_classes].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InterfaceClass {
+    /// The interface's class code (bInterfaceClass); e.g. 0x02 for CDC communications.
+    pub class: u8,
+
+    /// The interface's subclass code (bInterfaceSubClass); e.g. 0x02 for CDC-ACM.
+    pub subclass: u8,
+
+    /// The interface's protocol code (bInterfaceProtocol).
+    pub protocol: u8,
+}
+
+impl InterfaceClass {
+    /// Returns the class codes of the provided interface.
+    pub fn of(interface: &InterfaceDescriptor) -> Self {
+        Self {
+            class: interface.interface_class,
+            subclass: interface.interface_subclass,
+            protocol: interface.interface_protocol,
+        }
+    }
 }
 
 impl DeviceInformation {
@@ -65,10 +158,427 @@ impl DeviceInformation {
             ..Default::default()
         }
     }
+
+    /// Returns the device's device descriptor, if the OS provided a copy when it was enumerated.
+    ///
+    /// This doesn't require opening the device; so it's available even when we lack permission
+    /// to do so.
+    pub fn cached_device_descriptor(&self) -> Option<&DeviceDescriptor> {
+        self.device_descriptor.as_ref()
+    }
+
+    /// Returns where the device is plugged in; or None if the backend can't tell.
+    pub fn port_path(&self) -> Option<&PortPath> {
+        self.port_path.as_ref()
+    }
+
+    /// Returns where the hub this device is plugged into is plugged in; or None for root
+    /// hubs, or if the backend can't tell. See [crate::topology::Topology::parent_of].
+    pub fn parent(&self) -> Option<PortPath> {
+        self.port_path.as_ref()?.parent()
+    }
+
+    /// Returns the speed the device is running at; or None if the backend can't tell.
+    pub fn speed(&self) -> Option<DeviceSpeed> {
+        self.speed
+    }
+
+    /// Returns the address the host assigned the device on its bus; or None if the backend
+    /// can't tell. Addresses are reassigned whenever a device re-enumerates.
+    pub fn device_address(&self) -> Option<u8> {
+        self.device_address
+    }
+
+    /// Returns the device's class code (bDeviceClass); e.g. 0x09 for hubs, 0xef for composite
+    /// devices that use interface association, or 0xff for vendor-specific devices. None if
+    /// neither the OS nor a cached device descriptor could tell us.
+    pub fn device_class(&self) -> Option<u8> {
+        self.device_class
+            .or_else(|| Some(self.device_descriptor.as_ref()?.device_class))
+    }
+
+    /// Returns the device's subclass code (bDeviceSubClass); see [device_class].
+    pub fn device_subclass(&self) -> Option<u8> {
+        self.device_subclass
+            .or_else(|| Some(self.device_descriptor.as_ref()?.device_subclass))
+    }
+
+    /// Returns the device's protocol code (bDeviceProtocol); see [device_class].
+    pub fn device_protocol(&self) -> Option<u8> {
+        self.device_protocol
+            .or_else(|| Some(self.device_descriptor.as_ref()?.device_protocol))
+    }
+
+    /// Returns the class codes of each of the device's interfaces (including alternate
+    /// settings), as of enumeration; or None if the backend can't tell without opening it.
+    ///
+    /// These describe the configuration that was active at the time; or, on the BSDs, the
+    /// device's first configuration, which is the one the kernel selects.
+    pub fn interface_classes(&self) -> Option<&[InterfaceClass]> {
+        self.interface_classes.as_deref()
+    }
+
+    /// Returns true iff this device was enumerated without its strings, which can be fetched
+    /// with [crate::Host::fetch_strings].
+    pub fn strings_deferred(&self) -> bool {
+        self.strings_deferred
+    }
+
+    /// Returns true iff the other information describes the same physical device as this one;
+    /// e.g. because it's the same device, after it has re-enumerated.
+    ///
+    /// Where the backend knows which port the device is plugged into, that's what we compare;
+    /// otherwise, we fall back to comparing serial numbers.
+    pub fn is_same_physical_device(&self, other: &DeviceInformation) -> bool {
+        if let (Some(ours), Some(theirs)) = (&self.port_path, &other.port_path) {
+            return ours == theirs;
+        }
+        if let (Some(ours), Some(theirs)) = (
+            self.backend_numeric_location,
+            other.backend_numeric_location,
+        ) {
+            return ours == theirs;
+        }
+
+        self.serial.is_some() && self.serial == other.serial
+    }
+
+    /// Returns true iff we know enough about the device to recognize it again after it has
+    /// re-enumerated; see [is_same_physical_device].
+    fn can_be_recognized(&self) -> bool {
+        self.port_path.is_some() || self.backend_numeric_location.is_some() || self.serial.is_some()
+    }
+
+    //
+    // Accessors for backend implementers; see [crate::backend::prelude].
+    //
+
+    /// Returns the backend's numeric hint for re-finding the device; e.g. a registry ID.
+    pub fn backend_numeric_location(&self) -> Option<u64> {
+        self.backend_numeric_location
+    }
+
+    /// Sets the backend's numeric hint for re-finding the device. Devices with the same
+    /// numeric location are considered the same physical device; see [is_same_physical_device].
+    pub fn set_backend_numeric_location(&mut self, location: Option<u64>) {
+        self.backend_numeric_location = location;
+    }
+
+    /// Returns the backend's string hint for re-finding the device; e.g. a device node path.
+    pub fn backend_string_location(&self) -> Option<&str> {
+        self.backend_string_location.as_deref()
+    }
+
+    /// Sets the backend's string hint for re-finding the device.
+    pub fn set_backend_string_location(&mut self, location: Option<String>) {
+        self.backend_string_location = location;
+    }
+
+    /// Records the device descriptor the OS handed over during enumeration.
+    pub fn set_cached_device_descriptor(&mut self, descriptor: Option<DeviceDescriptor>) {
+        self.device_descriptor = descriptor;
+    }
+
+    /// Records where the device is plugged in.
+    pub fn set_port_path(&mut self, port_path: Option<PortPath>) {
+        self.port_path = port_path;
+    }
+
+    /// Records the speed the device is running at.
+    pub fn set_speed(&mut self, speed: Option<DeviceSpeed>) {
+        self.speed = speed;
+    }
+
+    /// Records the address the host assigned the device on its bus.
+    pub fn set_device_address(&mut self, address: Option<u8>) {
+        self.device_address = address;
+    }
+
+    /// Records the device's class, subclass, and protocol codes, if the OS reported them
+    /// separately from a device descriptor.
+    pub fn set_device_class_codes(&mut self, class: u8, subclass: u8, protocol: u8) {
+        self.device_class = Some(class);
+        self.device_subclass = Some(subclass);
+        self.device_protocol = Some(protocol);
+    }
+
+    /// Records the class codes of each of the device's interfaces.
+    pub fn set_interface_classes(&mut self, classes: Option<Vec<InterfaceClass>>) {
+        self.interface_classes = classes;
+    }
+
+    /// Marks whether enumeration skipped the device's strings; see [EnumerationOptions].
+    pub fn set_strings_deferred(&mut self, deferred: bool) {
+        self.strings_deferred = deferred;
+    }
+}
+
+/// The ways in which a device can be opened; see [OpenOptions].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Open the device normally, sharing it as much as the OS allows.
+    #[default]
+    Normal,
+
+    /// Open the device for exclusive access; taking it from anyone else who has it open,
+    /// where the OS allows.
+    Exclusive,
+
+    /// Don't open the device at all; only access what the OS allows without opening it.
+    /// Typically, that's descriptors, and other standard control requests.
+    DescriptorsOnly,
+}
+
+/// Options that control how devices are enumerated; see [crate::Host::set_enumeration_options].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnumerationOptions {
+    defer_strings: bool,
+}
+
+impl EnumerationOptions {
+    /// Options for enumerating normally; gathering everything the OS can tell us.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options for enumerating without reading each device's serial, vendor, and product
+    /// strings, which dominate enumeration time on some OSes. Strings needed to match a
+    /// selector are still read; the rest can be fetched with [crate::Host::fetch_strings].
+    pub fn deferred_strings() -> Self {
+        Self {
+            defer_strings: true,
+        }
+    }
+
+    /// Returns true iff backends may skip reading device strings.
+    pub fn defers_strings(&self) -> bool {
+        self.defer_strings
+    }
+}
+
+/// Options that control how a device is opened; see [crate::Host::open_with].
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    mode: OpenMode,
+}
+
+impl OpenOptions {
+    /// Options for opening a device normally; equivalent to [crate::Host::open].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options for opening a device for exclusive access.
+    pub fn exclusive() -> Self {
+        Self {
+            mode: OpenMode::Exclusive,
+        }
+    }
+
+    /// Options for read-only access to a device, e.g. to read its descriptors, without
+    /// opening it. This works even if someone else has the device open.
+    pub fn descriptors_only() -> Self {
+        Self {
+            mode: OpenMode::DescriptorsOnly,
+        }
+    }
+
+    /// Returns the mode in which the device should be opened.
+    pub fn mode(&self) -> OpenMode {
+        self.mode
+    }
+}
+
+/// Separate limits for the two ways a transfer can take too long; for
+/// [Device::read_with_timeouts] and [Device::write_with_timeouts].
+///
+/// Backends that can't tell the two apart use the completion timeout; or the no-data timeout,
+/// if that's all that's provided.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferTimeout {
+    /// How long the transfer can go without moving any data; or None for no limit.
+    pub no_data: Option<Duration>,
+
+    /// How long the transfer as a whole can take; or None for no limit.
+    pub completion: Option<Duration>,
+}
+
+impl TransferTimeout {
+    /// Creates a timeout that only limits how long the transfer can go without moving data;
+    /// for e.g. long streaming reads, which shouldn't be cut off while data's still flowing.
+    pub fn no_data(timeout: Duration) -> Self {
+        Self {
+            no_data: Some(timeout),
+            completion: None,
+        }
+    }
+
+    /// Returns the single timeout to use on backends that can't tell the two apart.
+    pub fn combined(&self) -> Option<Duration> {
+        self.completion.or(self.no_data)
+    }
+}
+
+impl From<Duration> for TransferTimeout {
+    /// Uses the same limit for both timeouts; which is what the plain transfer calls do.
+    fn from(timeout: Duration) -> Self {
+        Self {
+            no_data: Some(timeout),
+            completion: Some(timeout),
+        }
+    }
+}
+
+/// What a device should do when a read or write stalls; see [Device::set_stall_policy].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StallPolicy {
+    /// Report the stall as [Error::Stalled], and leave the endpoint halted.
+    #[default]
+    Report,
+
+    /// Clear the stall, and then report it as [Error::Stalled].
+    AutoClear,
+
+    /// Clear the stall, and then retry the transfer once; reporting the retry's result.
+    AutoClearAndRetry,
+}
+
+/// Options for [Device::reenumerate].
+#[derive(Debug, Clone, Default)]
+pub struct ReenumerateOptions {
+    /// If true, asks the OS to hand the device straight to us when it comes back, rather than
+    /// to its kernel drivers. Only supported on macOS; where it requires the same privileges
+    /// as [Device::release_kernel_driver].
+    pub capture: bool,
+}
+
+/// The data stage of a raw control request; see [Device::submit_setup].
+#[derive(Debug)]
+pub enum ControlData<'a> {
+    /// For IN requests: where the data the device sends should go.
+    In(&'a mut [u8]),
+
+    /// For OUT requests: the data to send to the device.
+    Out(&'a [u8]),
+}
+
+/// Options for [Device::read_with_options].
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// If false (the default), a read completes as soon as the device sends a short packet.
+    /// If true, the read keeps going until the buffer is full or the read times out; for
+    /// protocols whose framing doesn't line up with USB transfers.
+    pub fill_buffer: bool,
+}
+
+/// Options for [Device::write_with_options].
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// If true, follows any write that's an exact multiple of the endpoint's max packet size
+    /// with a zero-length packet; so the device can tell where the transfer ends.
+    ///
+    /// Only the Android (usbfs) backend can append the ZLP as part of the write itself. The
+    /// macOS and BSD backends can't; there, the ZLP is sent as a separate, empty write once
+    /// the data's been sent.
+    pub append_zlp: bool,
+
+    /// If true, waits until at least one of the endpoint's service intervals has passed since
+    /// the last paced write to it; see [Device::endpoint_interval]. For devices that misbehave
+    /// when interrupt OUT packets arrive back-to-back.
+    pub pace_to_interval: bool,
+}
+
+/// The speed a device is running at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DeviceSpeed {
+    /// USB 1.x low speed; 1.5Mbps.
+    Low,
+
+    /// USB 1.x full speed; 12Mbps.
+    Full,
+
+    /// USB 2.0 high speed; 480Mbps.
+    High,
+
+    /// USB 3.x SuperSpeed; 5Gbps.
+    Super,
+
+    /// USB 3.x SuperSpeedPlus; 10Gbps or faster.
+    SuperPlus,
+}
+
+impl DeviceSpeed {
+    /// Returns true iff the bus counts time in 125us microframes at this speed, rather than
+    /// in 1ms frames.
+    pub fn uses_microframes(self) -> bool {
+        self >= DeviceSpeed::High
+    }
+}
+
+/// An OS handle underlying an open device; see [Device::raw_handle].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RawHandle {
+    /// An `IOUSBDeviceInterface500 **`, as used by IOKit's device calls.
+    #[cfg(target_os = "macos")]
+    IoKitDevice(*mut std::ffi::c_void),
+
+    /// An `IOUSBInterfaceInterface500 **`, for an interface we've claimed.
+    #[cfg(target_os = "macos")]
+    IoKitInterface(*mut std::ffi::c_void),
+
+    /// A file descriptor; e.g. a usbfs device node, or a ugen control endpoint.
+    #[cfg(unix)]
+    FileDescriptor(std::os::unix::io::RawFd),
+}
+
+/// Tuning for a single endpoint; see [Device::set_endpoint_policy].
+///
+/// Each backend applies what its OS offers natively; the short-packet options are emulated
+/// elsewhere, and anything else the OS lacks is ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EndpointPolicy {
+    /// If true, reads made with [Device::read_with_options] don't complete on short packets;
+    /// as if [ReadOptions::fill_buffer] were set.
+    pub ignore_short_packets: bool,
+
+    /// If true, writes made with [Device::write_with_options] are terminated with a
+    /// zero-length packet where needed; as if [WriteOptions::append_zlp] were set.
+    pub short_packet_terminate: bool,
+
+    /// If true, writes made with [Device::write_with_options] or [Device::write_interrupt]
+    /// are paced to the endpoint's interval; as if [WriteOptions::pace_to_interval] were set.
+    pub pace_writes: bool,
+
+    /// The most data a single read or write hands to the OS; larger reads and writes transfer
+    /// at most this much, and report how much they moved. None for no limit.
+    pub max_transfer_size: Option<usize>,
+
+    /// If true, transfers are submitted straight to the host controller, bypassing the OS's
+    /// own queueing; where the OS allows this (e.g. WinUSB's RAW_IO).
+    pub raw_io: bool,
+
+    /// For interrupt and isochronous endpoints: the packet size to reserve bandwidth for,
+    /// if smaller than the endpoint's maximum (e.g. macOS's SetPipePolicy).
+    pub max_packet_size: Option<u16>,
+
+    /// For interrupt and isochronous endpoints: the polling interval to use, if longer than
+    /// the endpoint's own.
+    pub interval: Option<u8>,
+}
+
+/// The kinds of extra power a host can grant a device; see [Device::request_extra_power].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtraPowerKind {
+    /// Power drawn while the host is awake.
+    Wake,
+
+    /// Power drawn while the host is asleep; e.g. for charging.
+    Sleep,
 }
 
 /// Information used to find a specific device.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct DeviceSelector {
     /// If specified, searches for a device with the given VID.
     pub vendor_id: Option<u16>,
@@ -78,9 +588,42 @@ pub struct DeviceSelector {
 
     /// The serial string associated with the device.
     pub serial: Option<String>,
+
+    /// If specified, searches for a device with the given class code (bDeviceClass).
+    pub device_class: Option<u8>,
+
+    /// If specified, searches for a device with an interface of the given class code
+    /// (bInterfaceClass). Devices whose interfaces the backend can't see never match.
+    pub interface_class: Option<u8>,
+
+    /// If specified, searches for a device with an interface of the given subclass code; which
+    /// must be the same interface that matches [interface_class], if that's specified too.
+    pub interface_subclass: Option<u8>,
+
+    /// If specified, searches for a device with an interface of the given protocol code; which
+    /// must be the same interface that matches the other interface fields.
+    pub interface_protocol: Option<u8>,
+
+    /// If specified, searches for a device on the bus with the given number.
+    pub bus: Option<u8>,
+
+    /// If specified, searches for a device plugged in at the end of the given chain of hub
+    /// ports; e.g. `[2, 3]` for port 3 of the hub on the root hub's port 2. Port numbers are
+    /// fixed by the hardware, so this picks out the same physical socket across reboots.
+    /// Devices whose port the backend can't tell never match. See [PortPath::ports].
+    pub port_path: Option<Vec<u8>>,
 }
 
 impl DeviceSelector {
+    /// Creates a selector for whichever device is plugged in at the given port.
+    pub fn at_port(path: &PortPath) -> Self {
+        Self {
+            bus: Some(path.bus()),
+            port_path: Some(path.ports().to_vec()),
+            ..Default::default()
+        }
+    }
+
     pub fn matches(&self, device: &DeviceInformation) -> bool {
         // Oh, gods.
         //
@@ -108,8 +651,51 @@ impl DeviceSelector {
             }
         }
 
+        // Check class.
+        if self.device_class.is_some() && self.device_class != device.device_class() {
+            return false;
+        }
+
+        // Check bus and port.
+        if self.bus.is_some() && self.bus != device.port_path().map(PortPath::bus) {
+            return false;
+        }
+        if self.port_path.is_some()
+            && self.port_path.as_deref() != device.port_path().map(PortPath::ports)
+        {
+            return false;
+        }
+
+        // Check interfaces; any one interface has to match every code we were given.
+        if self.has_interface_criteria() {
+            let interfaces = device.interface_classes().unwrap_or_default();
+            if !interfaces
+                .iter()
+                .any(|interface| self.matches_interface(interface))
+            {
+                return false;
+            }
+        }
+
         true
     }
+
+    /// Returns true iff we have any criteria about the device's interfaces.
+    fn has_interface_criteria(&self) -> bool {
+        self.interface_class.is_some()
+            || self.interface_subclass.is_some()
+            || self.interface_protocol.is_some()
+    }
+
+    /// Returns true iff the given interface meets each of our interface criteria.
+    fn matches_interface(&self, interface: &InterfaceClass) -> bool {
+        let matches =
+            |criterion: Option<u8>, code: u8| criterion.is_none() || criterion == Some(code);
+
+        matches(self.interface_class, interface.class)
+            && matches(self.interface_subclass, interface.subclass)
+            && matches(self.interface_protocol, interface.protocol)
+    }
 }
 
 /// Object for working with an -opened- USB device.
@@ -121,14 +707,71 @@ pub struct Device {
 
     /// The per-backend inner device interface.
     backend_device: Box<dyn BackendDevice>,
+
+    /// Set once we've seen the device disappear; after which every operation fails fast.
+    disconnected: Arc<DisconnectSignal>,
+
+    /// The information this device was opened from, if it was opened from enumeration.
+    information: Option<DeviceInformation>,
+
+    /// What we do when a read or write stalls.
+    stall_policy: StallPolicy,
+
+    /// The policies set on each endpoint, by address.
+    endpoint_policies: HashMap<u8, EndpointPolicy>,
+
+    /// When we last submitted a paced write to each endpoint, by address.
+    paced_writes: HashMap<u8, Instant>,
+
+    /// The active configuration's endpoint descriptors, by address; so e.g. paced writes don't
+    /// re-read the configuration each time. Empty until we need them, and emptied whenever
+    /// the configuration or an alternate setting changes.
+    endpoint_descriptors: HashMap<u8, EndpointDescriptor>,
+
+    /// The timeout used for transfers that don't specify one; or None to wait forever.
+    default_timeout: Option<Duration>,
+
+    /// The interfaces we've claimed, and not yet released.
+    claimed_interfaces: Vec<u8>,
+
+    /// The alternate setting we've selected on each claimed interface, by interface number.
+    alternate_settings: HashMap<u8, u8>,
+
+    /// Keeps count of our asynchronous transfers that haven't yet completed.
+    transfers: Arc<TransferTracker>,
+
+    /// The most recent transfer failure, and what the transfer was.
+    last_error: Mutex<Option<ContextError>>,
+
+    /// The capture our transfers are being recorded into, if any.
+    capture: Option<DeviceCapture>,
+
+    /// Counters for everything we've done; shared with our in-flight transfers.
+    stats: Arc<Mutex<DeviceStats>>,
+
+    /// Where our completion callbacks are run; or None to run them on the backend's event thread.
+    #[cfg(feature = "callbacks")]
+    completion_executor: Option<ExecutorHandle>,
+}
+
+/// Holds a [CompletionExecutor]; which, being a closure, can't describe itself for Debug.
+#[cfg(feature = "callbacks")]
+struct ExecutorHandle(CompletionExecutor);
+
+#[cfg(feature = "callbacks")]
+impl std::fmt::Debug for ExecutorHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CompletionExecutor")
+    }
 }
 
 impl Device {
     /// Attempts to release the current device from its kernel driver.
     /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
     pub fn release_kernel_driver(&mut self, interface_number: u8) -> UsbResult<()> {
-        let backend = Arc::clone(&self.backend);
-        backend.release_kernel_driver(self, interface_number)
+        self.ensure_connected()?;
+        let result = self.backend_device.release_kernel_driver(interface_number);
+        self.note_result(result)
     }
 
     /// Attempts to release the current device from its kernel driver, if possible.
@@ -137,25 +780,70 @@ impl Device {
     /// operation; allowing this to be safely used for cases where you're more interested in
     /// failures that happen later, e.g. on first real device access.
     pub fn release_kernel_driver_if_possible(&mut self, interface_number: u8) -> UsbResult<()> {
-        let backend = Arc::clone(&self.backend);
-
-        match backend.release_kernel_driver(self, interface_number) {
+        self.ensure_connected()?;
+        let result = self.backend_device.release_kernel_driver(interface_number);
+        match self.note_result(result) {
             Err(Error::Unsupported) => Ok(()),
             other => other,
         }
     }
 
-    /// Fetches the "configuration number" for the active configuration.
+    /// Re-attaches the kernel driver to an interface, after it's been released with
+    /// [release_kernel_driver]. Not supported on all platforms; unsupported platforms
+    /// will return [Error::Unsupported].
+    ///
+    /// On macOS, kernel drivers are released by capturing the whole device; so this gives the
+    /// whole device back to the kernel, and the Device will need to be re-opened.
+    pub fn attach_kernel_driver(&mut self, interface_number: u8) -> UsbResult<()> {
+        self.ensure_connected()?;
+        let result = self.backend_device.attach_kernel_driver(interface_number);
+        self.note_result(result)
+    }
+
+    /// Returns true iff a kernel driver is bound to the given interface; in which case it'll
+    /// need to be released (see [release_kernel_driver]) before the interface can be claimed.
+    pub fn kernel_driver_active(&self, interface_number: u8) -> UsbResult<bool> {
+        Ok(self.kernel_driver_name(interface_number)?.is_some())
+    }
+
+    /// Returns the name of the kernel driver bound to the given interface, e.g. "usbhid" or
+    /// "AppleUSBCDCACM"; or None if no driver is bound to it.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn kernel_driver_name(&self, interface_number: u8) -> UsbResult<Option<String>> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.kernel_driver_name(interface_number))
+    }
+
+    /// Fetches the bConfigurationValue of the active configuration.
     /// A value of 0 means the device is not configured.
     pub fn active_configuration(&self) -> UsbResult<u8> {
-        self.backend.active_configuration(&self)
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.active_configuration())
+    }
+
+    /// Attempts to configure the device with the configuration whose bConfigurationValue is
+    /// provided. This is the value from the configuration descriptor, and _not_ the descriptor's
+    /// index; which is what e.g. [read_configuration_descriptor] takes.
+    /// A value of 0 will "unconfigure" the device.
+    pub fn set_active_configuration(&mut self, configuration_value: u8) -> UsbResult<()> {
+        self.ensure_connected()?;
+        self.endpoint_descriptors.clear();
+        let result = self
+            .backend_device
+            .set_active_configuration(configuration_value);
+        self.note_result(result)
+    }
+
+    /// Issues a standard SET_CONFIGURATION with the given bConfigurationValue.
+    /// Equivalent to [set_active_configuration].
+    pub fn set_configuration(&mut self, configuration_value: u8) -> UsbResult<()> {
+        self.set_active_configuration(configuration_value)
     }
 
-    /// Attempts to configure the device with the provided configuration number.
-    /// A configuration number of 0 will "unconfigure" the device.
-    pub fn set_active_configuration(&mut self, configuration_index: u8) -> UsbResult<()> {
-        self.backend
-            .set_active_configuration(&self, configuration_index)
+    /// Returns the bConfigurationValue of the active configuration, as a standard
+    /// GET_CONFIGURATION would. Equivalent to [active_configuration].
+    pub fn configuration(&self) -> UsbResult<u8> {
+        self.active_configuration()
     }
 
     /// Attempts to place the device into an unconfigured state, in which only EP0 is accessible.
@@ -166,57 +854,493 @@ impl Device {
 
     /// Attempts to take ownership of a given interface, claiming it for exclusive access.
     pub fn claim_interface(&mut self, interface_number: u8) -> UsbResult<()> {
-        let backend = Arc::clone(&self.backend);
-        backend.claim_interface(self, interface_number)
+        self.ensure_connected()?;
+        let trace = Trace::operation("claim_interface");
+        let result = self.backend_device.claim_interface(interface_number);
+        trace.finish(result.as_ref());
+        self.note_result(result)?;
+
+        if !self.claimed_interfaces.contains(&interface_number) {
+            self.claimed_interfaces.push(interface_number);
+        }
+        Ok(())
     }
 
     /// Releases ownership of a given interface, allowing it to be claimed by others.
     pub fn unclaim_interface(&mut self, interface_number: u8) -> UsbResult<()> {
-        let backend = Arc::clone(&self.backend);
-        backend.unclaim_interface(self, interface_number)
+        self.ensure_connected()?;
+        self.endpoint_descriptors.clear();
+        let result = self.backend_device.unclaim_interface(interface_number);
+        self.note_result(result)?;
+
+        self.claimed_interfaces
+            .retain(|&claimed| claimed != interface_number);
+        self.alternate_settings.remove(&interface_number);
+        Ok(())
     }
 
-    /// Performs an IN control request, with the following parameters:
-    /// - [request_type] specifies the USB control request type. It's recommended this is
-    /// - [request_number] is the request number. See e.g. USB 2.0 Chapter 9.
-    /// - [value] and [index] are arguments to the request. For requests with a recipient
-    ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
-    /// - [target] is the data to be transmitted as part of the request. It must be between [0, 65535]B.
-    /// - [timeout] is how long we should wait for the request. If not provided, we'll wait
-    ///   indefinitely.
+    /// Closes the device; cancelling any asynchronous transfers still in flight, and releasing
+    /// any interfaces we've claimed.
     ///
-    /// Returns the actual length read.
-    pub fn control_read(
-        &mut self,
-        request_type: RequestType,
-        request_number: u8,
-        value: u16,
-        index: u16,
-        target: &mut [u8],
-        timeout: Option<Duration>,
-    ) -> UsbResult<usize> {
-        self.backend.control_read(
-            self,
-            request_type.into(),
-            request_number,
-            value,
-            index,
-            target,
-            timeout,
-        )
+    /// Unlike just dropping the device, this waits for cancelled transfers to call back, and
+    /// reports the first thing that went wrong. A device that's already gone closes cleanly.
+    pub fn close(mut self) -> UsbResult<()> {
+        let mut outcome = Ok(());
+        let mut note = |result: UsbResult<()>| match result {
+            Ok(()) | Err(Error::Unsupported) | Err(Error::Disconnected) => {}
+            Err(error) => {
+                if outcome.is_ok() {
+                    outcome = Err(error);
+                }
+            }
+        };
+
+        // If anything's still in flight, cancel it on every endpoint it could be using...
+        if self.transfers.outstanding() > 0 {
+            for address in self.claimed_endpoints() {
+                note(self.abort_endpoint(address));
+            }
+            note(self.abort_ep0());
+
+            // ... and give the cancelled transfers a chance to call back.
+            if !self.transfers.wait_for_idle(CLOSE_DRAIN_TIMEOUT) {
+                note(Err(Error::TimedOut));
+            }
+        }
+
+        // Finally, let go of our interfaces.
+        for interface in self.claimed_interfaces.clone() {
+            note(self.unclaim_interface(interface));
+        }
+
+        outcome
     }
 
-    /// Performs an asynchronous IN control request, with the following parameters:
-    /// - [request_type] specifies the USB control request type. It's recommended this is
-    /// - [request_number] is the request number. See e.g. USB 2.0 Chapter 9.
-    /// - [value] and [index] are arguments to the request. For requests with a recipient
-    ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
-    /// - [target] is the data to be transmitted as part of the request. It must be between [0, 65535]B.
-    /// - [timeout] is how long we should wait for the request. If not provided, we'll wait
-    ///   indefinitely.
+    /// Returns the addresses of every endpoint on the interfaces we've claimed.
+    fn claimed_endpoints(&mut self) -> Vec<u8> {
+        let Ok(configuration) = self.read_active_configuration_descriptor() else {
+            return vec![];
+        };
+
+        let mut addresses: Vec<u8> = configuration
+            .interfaces
+            .iter()
+            .filter(|interface| {
+                self.claimed_interfaces
+                    .contains(&interface.interface_number)
+            })
+            .flat_map(|interface| interface.endpoints.iter())
+            .map(|endpoint| endpoint.address)
+            .collect();
+
+        // Each alternate setting lists its endpoints separately; so we may have duplicates.
+        addresses.sort_unstable();
+        addresses.dedup();
+        addresses
+    }
+
+    /// Claims an interface, first releasing any kernel driver bound to it.
     ///
-    /// The provided callback is called once the operation completes, and receives the actual
-    /// length read (or status, on failure).
+    /// The claim lasts as long as the returned guard: once it's dropped, the interface is
+    /// released, and the kernel driver is re-attached if we detached one. The guard derefs to
+    /// the Device, so it can be used to talk to the device in the meantime.
+    pub fn claim_interface_detached(
+        &mut self,
+        interface_number: u8,
+    ) -> UsbResult<InterfaceClaim<'_>> {
+        // Detach the kernel driver, if there is one; if we can't tell, there's nothing we can do.
+        let detached = match self.kernel_driver_active(interface_number) {
+            Ok(true) => {
+                self.release_kernel_driver(interface_number)?;
+                true
+            }
+            Ok(false) | Err(Error::Unsupported) => false,
+            Err(other) => return Err(other),
+        };
+
+        // Claim the interface; giving the driver back if we can't.
+        if let Err(error) = self.claim_interface(interface_number) {
+            if detached {
+                _ = self.attach_kernel_driver(interface_number);
+            }
+            return Err(error);
+        }
+
+        Ok(InterfaceClaim {
+            device: self,
+            interface_number,
+            detached,
+        })
+    }
+
+    /// Selects an alternate setting for a given (claimed) interface.
+    pub fn set_alternate_setting(&mut self, interface_number: u8, setting: u8) -> UsbResult<()> {
+        self.ensure_connected()?;
+        self.endpoint_descriptors.clear();
+        let result = self
+            .backend_device
+            .set_alternate_setting(interface_number, setting);
+        self.note_result(result)?;
+
+        self.alternate_settings.insert(interface_number, setting);
+        Ok(())
+    }
+
+    /// Issues a standard SET_INTERFACE, selecting the given bAlternateSetting for an interface.
+    /// Equivalent to [set_alternate_setting].
+    pub fn set_interface(&mut self, interface_number: u8, alternate_setting: u8) -> UsbResult<()> {
+        self.set_alternate_setting(interface_number, alternate_setting)
+    }
+
+    /// Clears the halt condition on the given endpoint; both on the host, and on the device,
+    /// via CLEAR_FEATURE(ENDPOINT_HALT).
+    pub fn clear_stall(&mut self, endpoint_address: u8) -> UsbResult<()> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.clear_stall(endpoint_address))?;
+
+        self.stats.lock().unwrap().stalls_cleared += 1;
+        Ok(())
+    }
+
+    /// Sets what happens when a blocking read or write stalls. By default, the stall is just
+    /// reported; see [StallPolicy] for the alternatives.
+    ///
+    /// Only [read] and [write] apply this policy; asynchronous transfers always report stalls.
+    pub fn set_stall_policy(&mut self, policy: StallPolicy) {
+        self.stall_policy = policy;
+    }
+
+    /// Returns the policy set by [set_stall_policy].
+    pub fn stall_policy(&self) -> StallPolicy {
+        self.stall_policy
+    }
+
+    /// Tunes how transfers on the given endpoint are performed; e.g. to let the OS keep more
+    /// data in flight on a high-throughput bulk endpoint. See [EndpointPolicy].
+    pub fn set_endpoint_policy(&mut self, address: u8, policy: EndpointPolicy) -> UsbResult<()> {
+        self.ensure_connected()?;
+
+        // Backends without native policies leave everything to our emulation.
+        match self.backend_device.set_endpoint_policy(address, &policy) {
+            Ok(()) | Err(Error::Unsupported) => {}
+            Err(error) => return self.note_result(Err(error)),
+        }
+
+        self.endpoint_policies.insert(address, policy);
+        Ok(())
+    }
+
+    /// Returns the policy set on the given endpoint by [set_endpoint_policy].
+    pub fn endpoint_policy(&self, address: u8) -> EndpointPolicy {
+        self.endpoint_policies
+            .get(&address)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the most data we should hand the OS in one transfer on the given endpoint.
+    fn transfer_limit(&self, address: u8) -> usize {
+        self.endpoint_policies
+            .get(&address)
+            .and_then(|policy| policy.max_transfer_size)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Sets the timeout used by transfers whose timeout is None. Without a default timeout,
+    /// those transfers wait forever; which can hang on a wedged device.
+    pub fn set_default_timeout(&mut self, timeout: Duration) {
+        self.default_timeout = Some(timeout);
+    }
+
+    /// Removes the default timeout; so transfers whose timeout is None wait forever again.
+    pub fn clear_default_timeout(&mut self) {
+        self.default_timeout = None;
+    }
+
+    /// Returns the timeout set by [set_default_timeout], if any.
+    pub fn default_timeout(&self) -> Option<Duration> {
+        self.default_timeout
+    }
+
+    /// Returns the most recent failure of a synchronous transfer or control request, along with
+    /// the endpoint, request and length involved; to help tell which of many transfers failed.
+    pub fn last_error(&self) -> Option<ContextError> {
+        self.last_error.lock().unwrap().clone()
+    }
+
+    /// Returns cumulative counters for every transfer this device has performed, since it was
+    /// opened or since [reset_stats] was last called.
+    pub fn stats(&self) -> DeviceStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Resets the counters returned by [stats] to zero.
+    pub fn reset_stats(&self) {
+        *self.stats.lock().unwrap() = DeviceStats::default();
+    }
+
+    /// Starts recording every transfer this device performs into the provided capture; which
+    /// can be shared with other devices, to record them all into the same file.
+    ///
+    /// Transfers are recorded as bulk or interrupt according to the active configuration's
+    /// endpoints, as they stand when the capture starts. The device is recorded under its
+    /// own bus number and address, where the backend can tell us them.
+    pub fn start_capture(&mut self, capture: Arc<PcapCapture>) {
+        let configuration = self.read_active_configuration_descriptor().ok();
+        let endpoints = configuration
+            .iter()
+            .flat_map(|configuration| configuration.interfaces.iter())
+            .flat_map(|interface| interface.endpoints.iter());
+
+        self.capture = Some(DeviceCapture::new(
+            capture,
+            endpoints,
+            self.information.as_ref(),
+        ));
+    }
+
+    /// Stops recording this device's transfers into its capture.
+    pub fn stop_capture(&mut self) {
+        self.capture = None;
+    }
+
+    /// Hands the callbacks passed to e.g. [read_and_call_back] to the provided executor, rather
+    /// than running them on the backend's event thread. Long-running callbacks otherwise hold
+    /// up every other completion on the device.
+    #[cfg(feature = "callbacks")]
+    pub fn set_completion_executor(&mut self, executor: CompletionExecutor) {
+        self.completion_executor = Some(ExecutorHandle(executor));
+    }
+
+    /// Goes back to running callbacks directly on the backend's event thread.
+    #[cfg(feature = "callbacks")]
+    pub fn clear_completion_executor(&mut self) {
+        self.completion_executor = None;
+    }
+
+    /// Wraps a user callback so it runs on our completion executor, if we have one.
+    #[cfg(feature = "callbacks")]
+    fn dispatched_callback(&self, callback: AsyncCallback) -> TransferCallback {
+        match &self.completion_executor {
+            Some(ExecutorHandle(executor)) => {
+                let executor = Arc::clone(executor);
+                Box::new(move |result| executor(Box::new(move || callback(result))))
+            }
+            None => callback,
+        }
+    }
+
+    /// Returns the timeout a transfer should actually use, given the one it was passed.
+    fn timeout_or_default(&self, timeout: Option<Duration>) -> Option<Duration> {
+        timeout.or(self.default_timeout)
+    }
+
+    /// Applies our stall policy to the result of a transfer on the given endpoint address.
+    /// Returns true iff the transfer should be retried.
+    fn recover_from_stall<T>(&mut self, endpoint_address: u8, result: &UsbResult<T>) -> bool {
+        if !matches!(result, Err(Error::Stalled)) || self.stall_policy == StallPolicy::Report {
+            return false;
+        }
+
+        // If we can't clear the stall, our caller should still hear about the original one.
+        if self.clear_stall(endpoint_address).is_err() {
+            return false;
+        }
+
+        self.stall_policy == StallPolicy::AutoClearAndRetry
+    }
+
+    /// Aborts every transfer in flight on the given endpoint; which complete with
+    /// [Error::Aborted]. Useful for recovering a stuck endpoint without resetting the device.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn abort_endpoint(&mut self, endpoint_address: u8) -> UsbResult<()> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.abort_endpoint(endpoint_address))
+    }
+
+    /// Aborts every transfer in flight on the control endpoint. See [abort_endpoint].
+    pub fn abort_ep0(&mut self) -> UsbResult<()> {
+        self.abort_endpoint(0)
+    }
+
+    /// Places the device into suspend; where it'll draw minimal power until it's resumed.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn suspend(&mut self) -> UsbResult<()> {
+        self.set_suspended(true)
+    }
+
+    /// Brings the device back out of suspend.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn resume(&mut self) -> UsbResult<()> {
+        self.set_suspended(false)
+    }
+
+    /// Helper for [suspend] and [resume].
+    fn set_suspended(&mut self, suspended: bool) -> UsbResult<()> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.set_suspended(suspended))
+    }
+
+    /// Allows or forbids the device from waking the host from suspend, via the standard
+    /// DEVICE_REMOTE_WAKEUP feature. Only works on devices that support remote wakeup; see
+    /// their configuration descriptor's attributes.
+    pub fn set_remote_wakeup(&mut self, enabled: bool) -> UsbResult<()> {
+        let request = if enabled {
+            StandardDeviceRequest::SetFeature
+        } else {
+            StandardDeviceRequest::ClearFeature
+        };
+
+        self.control_write(
+            STANDARD_OUT_TO_DEVICE,
+            request.into(),
+            FeatureSelector::DeviceRemoteWakeup.into(),
+            0,
+            &[],
+            None,
+        )
+    }
+
+    /// Returns the power the device's port can supply, in milliamps.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn bus_power_available(&mut self) -> UsbResult<u32> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.bus_power_available())
+    }
+
+    /// Asks the host for power beyond what the USB specification allows; for high-draw
+    /// devices. Returns the number of milliamps actually granted, which may be less than asked.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn request_extra_power(&mut self, kind: ExtraPowerKind, milliamps: u32) -> UsbResult<u32> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.request_extra_power(kind, milliamps))
+    }
+
+    /// Hands extra power granted by [request_extra_power] back to the host.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn return_extra_power(&mut self, kind: ExtraPowerKind, milliamps: u32) -> UsbResult<()> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.return_extra_power(kind, milliamps))
+    }
+
+    /// Returns the bus bandwidth still available to the device, in bytes per (micro)frame.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn bandwidth_available(&mut self) -> UsbResult<u32> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.bandwidth_available())
+    }
+
+    /// Asks the OS to drop the device, and then rediscover it; as if it had been unplugged and
+    /// plugged back in. Unlike a bus reset, this makes the OS re-read the device's descriptors
+    /// and re-bind its drivers; which is often needed after a bootloader switches modes.
+    ///
+    /// Once this succeeds, the device goes away; use [wait_for_reconnect] to open it again.
+    /// Not supported on all platforms; unsupported platforms will return [Error::Unsupported].
+    pub fn reenumerate(&mut self, options: &ReenumerateOptions) -> UsbResult<()> {
+        self.ensure_connected()?;
+        let result = self.backend_device.reenumerate(options);
+        self.note_result(result)
+    }
+
+    /// Performs an IN control request, with the following parameters:
+    /// - [request_type] specifies the USB control request type. It's recommended this is
+    /// - [request_number] is the request number. See e.g. USB 2.0 Chapter 9.
+    /// - [value] and [index] are arguments to the request. For requests with a recipient
+    ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
+    /// - [target] is the data to be transmitted as part of the request. It must be between [0, 65535]B.
+    ///   Anything longer fails with [Error::InvalidArgument].
+    /// - [timeout] is how long we should wait for the request. If not provided, we'll use the
+    ///   device's default timeout; or wait indefinitely, if it has none.
+    ///
+    /// Returns the actual length read.
+    pub fn control_read(
+        &mut self,
+        request_type: RequestType,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let length = control_length(target.len())?;
+        let setup = SetupPacket::new(request_type, request_number, value, index, length);
+        self.control_read_with_setup(&setup, target, timeout)
+    }
+
+    /// Performs the IN control request described by the given setup packet; tracing, capturing,
+    /// and keeping statistics on it, as with any other transfer.
+    fn control_read_with_setup(
+        &mut self,
+        setup: &SetupPacket,
+        target: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        self.ensure_connected()?;
+        let length = target.len();
+        let trace = self.trace_transfer("control_read", 0x80, length);
+        let urb = self.capture_submission(0x80, Some(setup.to_bytes()), length, &[]);
+        let result = self.backend_device.control_read(
+            setup.bmRequestType,
+            setup.bRequest,
+            setup.wValue,
+            setup.wIndex,
+            target,
+            self.timeout_or_default(timeout),
+        );
+
+        trace.finish_transfer(result.as_ref().copied());
+        if let Some(urb) = urb {
+            urb.complete(result.as_ref().copied(), target);
+        }
+        self.note_transfer(result, |error| {
+            ContextError::for_control(error, setup.bmRequestType, setup.bRequest, length)
+        })
+    }
+
+    /// Submits a raw setup packet to the device, exactly as given; for e.g. proxying requests.
+    ///
+    /// The data stage must go in the direction the packet says. For IN requests, up to wLength
+    /// bytes are read into the provided buffer; for OUT requests, the first wLength bytes of
+    /// the provided data are sent. Fails with [Error::InvalidArgument] if the direction doesn't
+    /// match, or if there's less than wLength bytes of buffer or data.
+    ///
+    /// Returns the number of bytes actually transferred.
+    pub fn submit_setup(
+        &mut self,
+        setup: &SetupPacket,
+        data: ControlData,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let length = setup.wLength as usize;
+
+        match (setup.direction(), data) {
+            (Direction::In, ControlData::In(target)) => {
+                let target = target.get_mut(..length).ok_or(Error::InvalidArgument)?;
+                self.control_read_with_setup(setup, target, timeout)
+            }
+
+            // Backends report OUT requests as all-or-nothing; so success means it all went.
+            (Direction::Out, ControlData::Out(data)) => {
+                let data = data.get(..length).ok_or(Error::InvalidArgument)?;
+                self.control_write_with_setup(setup, data, timeout)?;
+                Ok(length)
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Performs an asynchronous IN control request, with the following parameters:
+    /// - [request_type] specifies the USB control request type. It's recommended this is
+    /// - [request_number] is the request number. See e.g. USB 2.0 Chapter 9.
+    /// - [value] and [index] are arguments to the request. For requests with a recipient
+    ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
+    /// - [target] is the data to be transmitted as part of the request. It must be between [0, 65535]B.
+    ///   Anything longer fails with [Error::InvalidArgument].
+    /// - [timeout] is how long we should wait for the request. If not provided, we'll use the
+    ///   device's default timeout; or wait indefinitely, if it has none.
+    ///
+    /// The provided callback is called once the operation completes, and receives the actual
+    /// length read (or status, on failure).
     #[cfg(feature = "callbacks")]
     pub fn control_read_and_call_back(
         &mut self,
@@ -228,16 +1352,28 @@ impl Device {
         callback: AsyncCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
-        self.backend.control_read_nonblocking(
-            self,
+        self.ensure_connected()?;
+        let length = target.write().as_mut().len();
+        let setup = SetupPacket::new(
+            request_type,
+            request_number,
+            value,
+            index,
+            control_length(length)?,
+        );
+        let trace = self.trace_transfer("control_read", 0x80, length);
+        let urb = self
+            .capture_submission(0x80, Some(setup.to_bytes()), length, &[])
+            .map(|urb| urb.with_read_buffer(Arc::clone(&target)));
+        self.note_result(self.backend_device.control_read_nonblocking(
             request_type.into(),
             request_number,
             value,
             index,
             target,
-            callback,
-            timeout,
-        )
+            self.tracking_callback(trace, urb, self.dispatched_callback(callback)),
+            self.timeout_or_default(timeout),
+        ))
     }
 
     /// Performs an asynchronous IN control request, with the following parameters:
@@ -246,8 +1382,9 @@ impl Device {
     /// - [value] and [index] are arguments to the request. For requests with a recipient
     ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
     /// - [target] is the data to be transmitted as part of the request. It must be between [0, 65535]B.
-    /// - [timeout] is how long we should wait for the request. If not provided, we'll wait
-    ///   indefinitely.
+    ///   Anything longer fails with [Error::InvalidArgument].
+    /// - [timeout] is how long we should wait for the request. If not provided, we'll use the
+    ///   device's default timeout; or wait indefinitely, if it has none.
     ///
     /// Like a typical async function, this method returns a future. However, since _submission_
     /// can fail before the asynchronous component, the future is wrapped in a UsbResult.
@@ -260,30 +1397,73 @@ impl Device {
         index: u16,
         target: ReadBuffer,
         timeout: Option<Duration>,
-    ) -> UsbResult<UsbFuture> {
+    ) -> UsbResult<UsbFuture<ReadCompletion>> {
         // Create the future, and get a copy of it for our inner callback API,
         // because everyone needs to get themselves a copy.
-        let future = UsbFuture::new();
+        let future = UsbFuture::for_read(Arc::clone(&target));
         let shared_state = future.clone_state();
 
         // Convert our inner callback-API into an async API by having our callback just... complete the future.
         let callback = Box::new(move |result| shared_state.lock().unwrap().complete(result));
 
         // Finally, trigger the actual async control read.
-        self.backend.control_read_nonblocking(
-            self,
+        self.ensure_connected()?;
+        let length = target.write().as_mut().len();
+        let setup = SetupPacket::new(
+            request_type,
+            request_number,
+            value,
+            index,
+            control_length(length)?,
+        );
+        let trace = self.trace_transfer("control_read", 0x80, length);
+        let urb = self
+            .capture_submission(0x80, Some(setup.to_bytes()), length, &[])
+            .map(|urb| urb.with_read_buffer(Arc::clone(&target)));
+        self.note_result(self.backend_device.control_read_nonblocking(
             request_type.into(),
             request_number,
             value,
             index,
             target,
-            callback,
-            timeout,
-        )?;
+            self.tracking_callback(trace, urb, callback),
+            self.timeout_or_default(timeout),
+        ))?;
 
         Ok(future)
     }
 
+    /// Performs an asynchronous IN control request into a plain vector, which the completion
+    /// hands back once the request is done; see [control_read_async] for argument documentation.
+    ///
+    /// This only saves building a [ReadBuffer]; it's a thin wrapper around [control_read_async],
+    /// and treats the buffer the same way while the request is in flight. The buffer is handed
+    /// back even if the request fails, or can't be submitted; in which case the completion
+    /// carries the error. Only dropping the future before it completes loses the buffer.
+    #[cfg(feature = "async")]
+    pub fn control_read_owned_async(
+        &mut self,
+        request_type: RequestType,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: Vec<u8>,
+        timeout: Option<Duration>,
+    ) -> UsbFuture<OwnedReadCompletion> {
+        // Keep a handle onto the buffer, so we can take it back out once the read completes.
+        let shared = Arc::new(RwLock::new(target));
+        let submission = self.control_read_async(
+            request_type,
+            request_number,
+            value,
+            index,
+            Arc::clone(&shared) as ReadBuffer,
+            timeout,
+        );
+
+        UsbFuture::for_owned_read(shared, submission)
+    }
+
     /// Performs an IN control request, with the parameters below.
     /// This convenience variant generates a vector, for ease of use, but may be slower than
     /// e.g. re-using an appropriately sized buffer for multiple requests.
@@ -293,8 +1473,8 @@ impl Device {
     /// - [value] and [index] are arguments to the request. For requests with a recipient
     ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
     /// - [max_length] is the maximum length to be requested.
-    /// - [timeout] is how long we should wait for the request. If not provided, we'll wait
-    ///   indefinitely.
+    /// - [timeout] is how long we should wait for the request. If not provided, we'll use the
+    ///   device's default timeout; or wait indefinitely, if it has none.
     ///
     /// Returns a vector of the read response.
     pub fn control_read_to_vec(
@@ -308,9 +1488,8 @@ impl Device {
     ) -> UsbResult<Vec<u8>> {
         // Perform the request into a temporary buffer...
         let mut buffer = vec![0; max_length as usize];
-        let actual_size = self.backend.control_read(
-            self,
-            request_type.into(),
+        let actual_size = self.control_read(
+            request_type,
             request_number,
             value,
             index,
@@ -325,6 +1504,67 @@ impl Device {
         Ok(buffer)
     }
 
+    /// Like [control_read_to_vec], but reads into a caller-owned vector, replacing its
+    /// contents; so repeated requests reuse one allocation. The request is described by a
+    /// setup packet, whose wLength gives the maximum length to read.
+    ///
+    /// Returns the actual amount of data read; which is also the vector's new length.
+    pub fn control_read_into_vec(
+        &mut self,
+        setup: &SetupPacket,
+        buffer: &mut Vec<u8>,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let request_type = setup.request_type()?;
+        if request_type.direction != Direction::In {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Control reads go through the backend's initialized-buffer path; so we zero the
+        // vector, but only ever grow its allocation.
+        buffer.clear();
+        buffer.resize(setup.wLength as usize, 0);
+        let length = self.control_read(
+            request_type,
+            setup.bRequest,
+            setup.wValue,
+            setup.wIndex,
+            buffer,
+            timeout,
+        )?;
+
+        buffer.truncate(length);
+        Ok(length)
+    }
+
+    /// Performs an IN control request that returns a fixed-layout value; e.g. a vendor status
+    /// struct. Reads exactly `size_of::<T>()` bytes, and fails with [Error::ShortRead] if the
+    /// device sends fewer. Arguments are as for [control_read].
+    #[cfg(feature = "typed-control")]
+    pub fn control_read_struct<T: FromBytes>(
+        &mut self,
+        request_type: RequestType,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        timeout: Option<Duration>,
+    ) -> UsbResult<T> {
+        let length = std::mem::size_of::<T>();
+        if length > u16::MAX as usize {
+            return Err(Error::InvalidArgument);
+        }
+
+        let raw = self.control_read_to_vec(
+            request_type,
+            request_number,
+            value,
+            index,
+            length as u16,
+            timeout,
+        )?;
+        T::read_from(&raw).ok_or(Error::ShortRead(raw.len()))
+    }
+
     /// Performs an OUT control request, with the following parameters:
     /// - [request_type] specifies the USB control request type, which defines several parameters
     ///   of this request.
@@ -332,8 +1572,9 @@ impl Device {
     /// - [value] and [index] are arguments to the request. For requests with a recipient
     ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
     /// - [target] is the data to be transmitted as part of the request. It must be between [0, 65535]B.
-    /// - [timeout] is how long we should wait for the request. If not provided, we'll wait
-    ///   indefinitely.
+    ///   Anything longer fails with [Error::InvalidArgument].
+    /// - [timeout] is how long we should wait for the request. If not provided, we'll use the
+    ///   device's default timeout; or wait indefinitely, if it has none.
     pub fn control_write(
         &mut self,
         request_type: RequestType,
@@ -343,15 +1584,38 @@ impl Device {
         data: &[u8],
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
-        self.backend.control_write(
-            self,
-            request_type.into(),
-            request_number,
-            value,
-            index,
+        let length = control_length(data.len())?;
+        let setup = SetupPacket::new(request_type, request_number, value, index, length);
+        self.control_write_with_setup(&setup, data, timeout)
+    }
+
+    /// Performs the OUT control request described by the given setup packet; tracing,
+    /// capturing, and keeping statistics on it, as with any other transfer.
+    fn control_write_with_setup(
+        &mut self,
+        setup: &SetupPacket,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        self.ensure_connected()?;
+        let trace = self.trace_transfer("control_write", 0, data.len());
+        let urb = self.capture_submission(0, Some(setup.to_bytes()), data.len(), data);
+        let result = self.backend_device.control_write(
+            setup.bmRequestType,
+            setup.bRequest,
+            setup.wValue,
+            setup.wIndex,
             data,
-            timeout,
-        )
+            self.timeout_or_default(timeout),
+        );
+
+        trace.finish_transfer(result.as_ref().map(|_| data.len()));
+        if let Some(urb) = urb {
+            urb.complete(result.as_ref().map(|_| data.len()), &[]);
+        }
+        self.note_transfer(result, |error| {
+            ContextError::for_control(error, setup.bmRequestType, setup.bRequest, data.len())
+        })
     }
 
     /// Performs an asynchronous OUT control request, with the following parameters:
@@ -360,8 +1624,9 @@ impl Device {
     /// - [value] and [index] are arguments to the request. For requests with a recipient
     ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
     /// - [data] is the data to be transmitted as part of the request. It must be between [0, 65535]B.
-    /// - [timeout] is how long we should wait for the request. If not provided, we'll wait
-    ///   indefinitely.
+    ///   Anything longer fails with [Error::InvalidArgument].
+    /// - [timeout] is how long we should wait for the request. If not provided, we'll use the
+    ///   device's default timeout; or wait indefinitely, if it has none.
     ///
     /// The provided callback is called once the operation completes, and receives the actual
     /// length written (or status, on failure).
@@ -376,16 +1641,27 @@ impl Device {
         callback: AsyncCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
-        self.backend.control_write_nonblocking(
-            self,
+        self.ensure_connected()?;
+        let length = data.as_ref().as_ref().len();
+        let setup = SetupPacket::new(
+            request_type,
+            request_number,
+            value,
+            index,
+            control_length(length)?,
+        );
+        let trace = self.trace_transfer("control_write", 0, length);
+        let urb =
+            self.capture_submission(0, Some(setup.to_bytes()), length, data.as_ref().as_ref());
+        self.note_result(self.backend_device.control_write_nonblocking(
             request_type.into(),
             request_number,
             value,
             index,
             data,
-            callback,
-            timeout,
-        )
+            self.tracking_callback(trace, urb, self.dispatched_callback(callback)),
+            self.timeout_or_default(timeout),
+        ))
     }
 
     /// Performs an asynchronous IN control request, with the following parameters:
@@ -394,8 +1670,9 @@ impl Device {
     /// - [value] and [index] are arguments to the request. For requests with a recipient
     ///   other than the device, [index] is usually the index of the target. See USB 2.0 Chapter 9.
     /// - [target] is the data to be transmitted as part of the request. It must be between [0, 65535]B.
-    /// - [timeout] is how long we should wait for the request. If not provided, we'll wait
-    ///   indefinitely.
+    ///   Anything longer fails with [Error::InvalidArgument].
+    /// - [timeout] is how long we should wait for the request. If not provided, we'll use the
+    ///   device's default timeout; or wait indefinitely, if it has none.
     ///
     /// Like a typical async function, this method returns a future. However, since _submission_
     /// can fail before the asynchronous component, the future is wrapped in a UsbResult.
@@ -408,26 +1685,37 @@ impl Device {
         index: u16,
         target: WriteBuffer,
         timeout: Option<Duration>,
-    ) -> UsbResult<UsbFuture> {
+    ) -> UsbResult<UsbFuture<WriteCompletion>> {
         // Create the future, and get a copy of it for our inner callback API,
         // because everyone needs to get themselves a copy.
-        let future = UsbFuture::new();
+        let future = UsbFuture::for_write(Arc::clone(&target));
         let shared_state = future.clone_state();
 
         // Convert our inner callback-API into an async API by having our callback just... complete the future.
         let callback = Box::new(move |result| shared_state.lock().unwrap().complete(result));
 
         // Finally, trigger the actual async control write.
-        self.backend.control_write_nonblocking(
-            self,
+        self.ensure_connected()?;
+        let length = target.as_ref().as_ref().len();
+        let setup = SetupPacket::new(
+            request_type,
+            request_number,
+            value,
+            index,
+            control_length(length)?,
+        );
+        let trace = self.trace_transfer("control_write", 0, length);
+        let urb =
+            self.capture_submission(0, Some(setup.to_bytes()), length, target.as_ref().as_ref());
+        self.note_result(self.backend_device.control_write_nonblocking(
             request_type.into(),
             request_number,
             value,
             index,
             target,
-            callback,
-            timeout,
-        )?;
+            self.tracking_callback(trace, urb, callback),
+            self.timeout_or_default(timeout),
+        ))?;
 
         Ok(future)
     }
@@ -446,15 +1734,15 @@ impl Device {
         target: &mut [u8],
         timeout: Option<Duration>,
     ) -> UsbResult<usize> {
-        self.backend.control_read(
-            self,
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.control_read(
             request_type,
             request_number,
             value,
             index,
             target,
-            timeout,
-        )
+            self.timeout_or_default(timeout),
+        ))
     }
 
     /// Performs an unchecked OUT control request.
@@ -471,34 +1759,178 @@ impl Device {
         target: &mut [u8],
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
-        self.backend.control_write(
-            self,
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.control_write(
             request_type,
             request_number,
             value,
             index,
             target,
-            timeout,
-        )
+            self.timeout_or_default(timeout),
+        ))
     }
 
-    /// Reads a device-level, non-string descriptor from the target device.
-    ///
-    /// (Technically, this can get string descriptors, too, but it'll use the Not Strictly Correct
-    ///  default language ID, langID '0').
-    ///
-    pub fn read_descriptor(
+    /// Performs an IN control request, which must finish before the given deadline.
+    /// See [control_read] for documentation on the remaining arguments.
+    pub fn control_read_until(
         &mut self,
-        descriptor_type: u8,
-        descriptor_index: u8,
-    ) -> UsbResult<Vec<u8>> {
-        let value = ((descriptor_type as u16) << 8) | (descriptor_index as u16);
-        self.control_read_to_vec(
-            STANDARD_IN_FROM_DEVICE,
-            StandardDeviceRequest::GetDescriptor.into(),
-            value,
-            0,
-            u16::MAX,
+        request_type: RequestType,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        target: &mut [u8],
+        deadline: Instant,
+    ) -> UsbResult<usize> {
+        let timeout = timeout_until(deadline)?;
+        self.control_read(
+            request_type,
+            request_number,
+            value,
+            index,
+            target,
+            Some(timeout),
+        )
+    }
+
+    /// Performs an OUT control request, which must finish before the given deadline.
+    /// See [control_write] for documentation on the remaining arguments.
+    pub fn control_write_until(
+        &mut self,
+        request_type: RequestType,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        deadline: Instant,
+    ) -> UsbResult<()> {
+        let timeout = timeout_until(deadline)?;
+        self.control_write(
+            request_type,
+            request_number,
+            value,
+            index,
+            data,
+            Some(timeout),
+        )
+    }
+
+    /// Performs an IN control request addressed to an interface; placing its number in wIndex.
+    /// See [control_read] for documentation on the remaining arguments.
+    pub fn control_read_interface(
+        &mut self,
+        request_type: Type,
+        request_number: u8,
+        value: u16,
+        interface_number: u8,
+        target: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        self.control_read(
+            RequestType {
+                direction: Direction::In,
+                request_type,
+                recipient: Recipient::Interface,
+            },
+            request_number,
+            value,
+            interface_number as u16,
+            target,
+            timeout,
+        )
+    }
+
+    /// Performs an OUT control request addressed to an interface; placing its number in wIndex.
+    /// See [control_write] for documentation on the remaining arguments.
+    pub fn control_write_interface(
+        &mut self,
+        request_type: Type,
+        request_number: u8,
+        value: u16,
+        interface_number: u8,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        self.control_write(
+            RequestType {
+                direction: Direction::Out,
+                request_type,
+                recipient: Recipient::Interface,
+            },
+            request_number,
+            value,
+            interface_number as u16,
+            data,
+            timeout,
+        )
+    }
+
+    /// Performs an IN control request addressed to an endpoint; placing its address in wIndex.
+    /// See [control_read] for documentation on the remaining arguments.
+    pub fn control_read_endpoint(
+        &mut self,
+        request_type: Type,
+        request_number: u8,
+        value: u16,
+        endpoint_address: u8,
+        target: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        self.control_read(
+            RequestType {
+                direction: Direction::In,
+                request_type,
+                recipient: Recipient::Endpoint,
+            },
+            request_number,
+            value,
+            endpoint_address as u16,
+            target,
+            timeout,
+        )
+    }
+
+    /// Performs an OUT control request addressed to an endpoint; placing its address in wIndex.
+    /// See [control_write] for documentation on the remaining arguments.
+    pub fn control_write_endpoint(
+        &mut self,
+        request_type: Type,
+        request_number: u8,
+        value: u16,
+        endpoint_address: u8,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        self.control_write(
+            RequestType {
+                direction: Direction::Out,
+                request_type,
+                recipient: Recipient::Endpoint,
+            },
+            request_number,
+            value,
+            endpoint_address as u16,
+            data,
+            timeout,
+        )
+    }
+
+    /// Reads a device-level, non-string descriptor from the target device.
+    ///
+    /// (Technically, this can get string descriptors, too, but it'll use the Not Strictly Correct
+    ///  default language ID, langID '0').
+    ///
+    pub fn read_descriptor(
+        &mut self,
+        descriptor_type: u8,
+        descriptor_index: u8,
+    ) -> UsbResult<Vec<u8>> {
+        let value = ((descriptor_type as u16) << 8) | (descriptor_index as u16);
+        self.control_read_to_vec(
+            STANDARD_IN_FROM_DEVICE,
+            StandardDeviceRequest::GetDescriptor.into(),
+            value,
+            0,
+            u16::MAX,
             None,
         )
     }
@@ -515,6 +1947,149 @@ impl Device {
         self.read_descriptor(descriptor_type.into(), descriptor_index)
     }
 
+    /// Reads a string descriptor from the device, in the device's first supported language.
+    ///
+    /// String index 0 is reserved for the language table, and so isn't a valid argument.
+    pub fn read_string_descriptor(&mut self, string_index: u8) -> UsbResult<String> {
+        if string_index == 0 {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Figure out which language to ask for, from the language table...
+        let languages = self.read_standard_descriptor(DescriptorType::String, 0)?;
+        let language = match languages[..] {
+            [_, _, low, high, ..] => u16::from_le_bytes([low, high]),
+            _ => return Err(Error::MalformedDescriptor),
+        };
+
+        // ... fetch the string itself...
+        let value = ((DescriptorType::String as u16) << 8) | (string_index as u16);
+        let raw = self.control_read_to_vec(
+            STANDARD_IN_FROM_DEVICE,
+            StandardDeviceRequest::GetDescriptor.into(),
+            value,
+            language,
+            u16::MAX,
+            None,
+        )?;
+
+        // ... and convert it from the UTF-16 the device speaks.
+        let length = (*raw.first().ok_or(Error::MalformedDescriptor)? as usize).min(raw.len());
+        let characters: Vec<u16> = raw
+            .get(2..length)
+            .ok_or(Error::MalformedDescriptor)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+
+        Ok(String::from_utf16_lossy(&characters))
+    }
+
+    /// Reads and parses the device's device descriptor.
+    pub fn read_device_descriptor(&mut self) -> UsbResult<DeviceDescriptor> {
+        let raw = self.read_standard_descriptor(DescriptorType::Device, 0)?;
+        DeviceDescriptor::parse(&raw)
+    }
+
+    /// Reads and parses the device's BOS descriptor, including its device capabilities.
+    /// Only devices that report USB 2.1 or later are expected to have one.
+    pub fn read_bos_descriptor(&mut self) -> UsbResult<BosDescriptor> {
+        let raw = self.read_standard_descriptor(DescriptorType::Bos, 0)?;
+        BosDescriptor::parse(&raw)
+    }
+
+    /// Reads and parses one of the device's configuration descriptors, including its
+    /// interfaces and endpoints.
+    ///
+    /// Note that [configuration_index] is the _index_ of the configuration descriptor,
+    /// and not its bConfigurationValue.
+    pub fn read_configuration_descriptor(
+        &mut self,
+        configuration_index: u8,
+    ) -> UsbResult<ConfigurationDescriptor> {
+        let raw =
+            self.read_standard_descriptor(DescriptorType::Configuration, configuration_index)?;
+        ConfigurationDescriptor::parse(&raw)
+    }
+
+    /// Returns the number of configurations the device has (bNumConfigurations).
+    ///
+    /// Where possible, this comes from the OS's copy of the device descriptor, rather than
+    /// from the device itself.
+    pub fn num_configurations(&mut self) -> UsbResult<u8> {
+        self.ensure_connected()?;
+        let count = self.backend_device.num_configurations();
+
+        match self.note_result(count) {
+            Err(Error::Unsupported) => (),
+            result => return result,
+        }
+
+        // If the backend can't tell us, we'll fall back to the device descriptor; ideally
+        // the copy we were given during enumeration.
+        let cached = self
+            .information
+            .as_ref()
+            .and_then(DeviceInformation::cached_device_descriptor)
+            .map(|descriptor| descriptor.num_configurations);
+
+        match cached {
+            Some(count) => Ok(count),
+            None => Ok(self.read_device_descriptor()?.num_configurations),
+        }
+    }
+
+    /// Fetches and parses each of the device's configuration descriptors, in index order;
+    /// e.g. to pick between the modes of a multi-configuration device.
+    pub fn configurations(&mut self) -> UsbResult<Vec<ConfigurationDescriptor>> {
+        let count = self.num_configurations()?;
+        (0..count)
+            .map(|index| self.configuration_descriptor(index))
+            .collect()
+    }
+
+    /// Fetches and parses one of the device's configuration descriptors, by index.
+    ///
+    /// Where the OS keeps its own copy of the device's descriptors, this uses it, rather than
+    /// asking the device; otherwise, it's equivalent to [read_configuration_descriptor].
+    pub fn configuration_descriptor(
+        &mut self,
+        configuration_index: u8,
+    ) -> UsbResult<ConfigurationDescriptor> {
+        self.ensure_connected()?;
+        let cached = self
+            .backend_device
+            .cached_configuration_descriptor(configuration_index);
+
+        match self.note_result(cached) {
+            Ok(raw) => ConfigurationDescriptor::parse(&raw),
+            Err(Error::Unsupported) => self.read_configuration_descriptor(configuration_index),
+            Err(other) => Err(other),
+        }
+    }
+
+    /// Reads and parses the configuration descriptor for the device's active configuration.
+    ///
+    /// Returns [Error::InvalidArgument] if the device isn't currently configured.
+    pub fn read_active_configuration_descriptor(&mut self) -> UsbResult<ConfigurationDescriptor> {
+        let active_configuration = self.active_configuration()?;
+        if active_configuration == 0 {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Configuration descriptors are read by index, but the device reports its active
+        // configuration by value; so we'll need to go hunting for the matching descriptor.
+        let configuration_count = self.num_configurations()?;
+        for index in 0..configuration_count {
+            let configuration = self.configuration_descriptor(index)?;
+            if configuration.configuration_value == active_configuration {
+                return Ok(configuration);
+            }
+        }
+
+        Err(Error::MalformedDescriptor)
+    }
+
     #[cfg(feature = "async")]
     ///
     /// (Technically, this can get string descriptors, too, but it'll use the Not Strictly Correct
@@ -524,7 +2099,7 @@ impl Device {
         descriptor_type: DescriptorType,
         descriptor_index: u8,
         buffer: ReadBuffer,
-    ) -> UsbResult<UsbFuture> {
+    ) -> UsbResult<UsbFuture<ReadCompletion>> {
         let value = ((descriptor_type as u16) << 8) | (descriptor_index as u16);
 
         self.control_read_async(
@@ -544,8 +2119,8 @@ impl Device {
     /// - [max_length]: The maximum length we'll try to read. The actual amount read can be anywhere
     ///   from 0 to this length.
     /// - [timeout]: If provided, the maximum amount of time that will be spent performing this
-    ///   read. If not provided, this read will be allowed to continue indefinitely until data
-    ///   arrives or an error arises.
+    ///   read. If not provided, the device's default timeout is used; and if there's none, this
+    ///   read will be allowed to continue indefinitely until data arrives or an error arises.
     ///
     /// Returns the actual amount of data read.
     pub fn read(
@@ -554,7 +2129,15 @@ impl Device {
         buffer: &mut [u8],
         timeout: Option<Duration>,
     ) -> UsbResult<usize> {
-        self.backend.read(self, endpoint, buffer, timeout)
+        let limit = buffer.len().min(self.transfer_limit(endpoint | 0x80));
+        let buffer = &mut buffer[..limit];
+        let timeout = self.timeout_or_default(timeout);
+
+        // Reads always target IN endpoints; so we can find the address from either form.
+        let transfer = self.perform_transfer("read", endpoint | 0x80, limit, &[], |backend| {
+            backend.read(endpoint, buffer, timeout)
+        })?;
+        self.finish_transfer(transfer, buffer)
     }
 
     /// Performs an asynchronous write to the provided endpoint.
@@ -567,8 +2150,54 @@ impl Device {
         callback: AsyncCallback,
         timeout: Option<Duration>,
     ) -> UsbResult<()> {
-        self.backend
-            .read_nonblocking(self, endpoint, buffer, callback, timeout)
+        self.ensure_connected()?;
+        let length = buffer.write().as_mut().len();
+        let trace = self.trace_transfer("read", endpoint | 0x80, length);
+        let urb = self
+            .capture_submission(endpoint | 0x80, None, length, &[])
+            .map(|urb| urb.with_read_buffer(Arc::clone(&buffer)));
+        self.note_result(self.backend_device.read_nonblocking(
+            endpoint,
+            buffer,
+            self.tracking_callback(trace, urb, self.dispatched_callback(callback)),
+            self.timeout_or_default(timeout),
+        ))
+    }
+
+    /// Performs an asynchronous read from the provided endpoint; sending a [ReadEvent] to the
+    /// provided sender (e.g. a [std::sync::mpsc::Sender]) once it completes.
+    #[cfg(feature = "callbacks")]
+    pub fn read_to_channel(
+        &mut self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        sender: impl CompletionSender<ReadEvent>,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let completion_buffer = Arc::clone(&buffer);
+        let callback = Box::new(move |result| {
+            sender.send_completion(ReadEvent {
+                endpoint: endpoint | 0x80,
+                buffer: completion_buffer,
+                result,
+            })
+        });
+        self.read_and_call_back(endpoint, buffer, callback, timeout)
+    }
+
+    /// Allocates `length` bytes of memory for transfers on the given endpoint; in whatever
+    /// form our backend prefers. See [crate::BufferPool::for_endpoint].
+    #[cfg(feature = "async")]
+    pub(crate) fn allocate_transfer_memory(
+        &self,
+        endpoint: u8,
+        length: usize,
+    ) -> UsbResult<Box<dyn TransferMemory>> {
+        self.ensure_connected()?;
+        let result = self
+            .backend_device
+            .allocate_transfer_memory(endpoint, length);
+        self.note_result(result)
     }
 
     /// Performs an asynchronous read to the provided endpoint.
@@ -579,34 +2208,65 @@ impl Device {
         endpoint: u8,
         buffer: ReadBuffer,
         timeout: Option<Duration>,
-    ) -> UsbResult<UsbFuture> {
+    ) -> UsbResult<UsbFuture<ReadCompletion>> {
         // Create the future, and get a copy of it for our inner callback API,
         // because everyone needs to get themselves a copy.
-        let future = UsbFuture::new();
+        let future = UsbFuture::for_read(Arc::clone(&buffer));
         let shared_state = future.clone_state();
 
         // Convert our inner callback-API into an async API by having our callback just... complete the future.
         let callback = Box::new(move |result| shared_state.lock().unwrap().complete(result));
 
         // Finally, trigger the actual async read.
-        self.backend
-            .read_nonblocking(self, endpoint, buffer, callback, timeout)?;
+        self.ensure_connected()?;
+        let length = buffer.write().as_mut().len();
+        let trace = self.trace_transfer("read", endpoint | 0x80, length);
+        let urb = self
+            .capture_submission(endpoint | 0x80, None, length, &[])
+            .map(|urb| urb.with_read_buffer(Arc::clone(&buffer)));
+        self.note_result(self.backend_device.read_nonblocking(
+            endpoint,
+            buffer,
+            self.tracking_callback(trace, urb, callback),
+            self.timeout_or_default(timeout),
+        ))?;
 
         Ok(future)
     }
 
+    /// Performs an asynchronous read from the provided endpoint into a plain vector, which the
+    /// completion hands back once the read is done. Usable for bulk and interrupt reads.
+    ///
+    /// This only saves building a [ReadBuffer]; it's a thin wrapper around [read_async], and
+    /// treats the buffer the same way while the read is in flight. The buffer is handed back
+    /// even if the read fails, or can't be submitted; in which case the completion carries the
+    /// error. Only dropping the future before it completes loses the buffer.
+    #[cfg(feature = "async")]
+    pub fn read_owned_async(
+        &mut self,
+        endpoint: u8,
+        buffer: Vec<u8>,
+        timeout: Option<Duration>,
+    ) -> UsbFuture<OwnedReadCompletion> {
+        // Keep a handle onto the buffer, so we can take it back out once the read completes.
+        let shared = Arc::new(RwLock::new(buffer));
+        let submission = self.read_async(endpoint, Arc::clone(&shared) as ReadBuffer, timeout);
+
+        UsbFuture::for_owned_read(shared, submission)
+    }
+
     /// Performs a read from the provided endpoint.
     /// Usable for bulk and interrupt reads.
     ///
     /// This convenience variant generates a vector, for ease of use, but may be slower than
-    /// e.g. re-using an appropriately sized buffer for multiple reads.
+    /// e.g. re-using an appropriately sized buffer for multiple reads; see [read_into_vec].
     ///
     /// - [endpoint]: The endpoint number (or address) to read from.
     /// - [max_length]: The maximum length we'll try to read. The actual amount read can be anywhere
     ///   from 0 to this length.
     /// - [timeout]: If provided, the maximum amount of time that will be spent performing this
-    ///   read. If not provided, this read will be allowed to continue indefinitely until data
-    ///   arrives or an error arises.
+    ///   read. If not provided, the device's default timeout is used; and if there's none, this
+    ///   read will be allowed to continue indefinitely until data arrives or an error arises.
     ///
     /// Returns the actual amount of data read.
     pub fn read_to_vec(
@@ -615,7 +2275,7 @@ impl Device {
         max_length: usize,
         timeout: Option<Duration>,
     ) -> UsbResult<Vec<u8>> {
-        let mut buffer = vec![0; max_length as usize];
+        let mut buffer = vec![0; max_length];
 
         // Perform our core read...
         let actual_size = self.read(endpoint, &mut buffer, timeout)?;
@@ -627,77 +2287,1653 @@ impl Device {
         Ok(buffer)
     }
 
-    /// Performs a write to the provided endpoint.
-    /// Usable for bulk and interrupt writes.
-    pub fn write(&mut self, endpoint: u8, data: &[u8], timeout: Option<Duration>) -> UsbResult<()> {
-        self.backend.write(self, endpoint, data, timeout)
+    /// Like [read_to_vec], but doesn't zero the new vector before reading into it.
+    pub fn read_to_uninit_vec(
+        &mut self,
+        endpoint: u8,
+        max_length: usize,
+        timeout: Option<Duration>,
+    ) -> UsbResult<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(max_length);
+        self.read_into_vec(endpoint, &mut buffer, max_length, timeout)?;
+        Ok(buffer)
     }
 
-    /// Performs an asynchronous write to the provided endpoint.
-    /// Usable for bulk and interrupt writes.
-    #[cfg(feature = "callbacks")]
-    pub fn write_and_call_back(
+    /// Reads up to `max_length` bytes from the provided endpoint into a caller-owned vector;
+    /// replacing its contents. The vector's allocation is reused, and only grown if it's too
+    /// small; so a polling loop can read into the same vector without allocating, or zeroing.
+    ///
+    /// Returns the actual amount of data read; which is also the vector's new length.
+    pub fn read_into_vec(
         &mut self,
         endpoint: u8,
-        data: WriteBuffer,
-        callback: AsyncCallback,
+        buffer: &mut Vec<u8>,
+        max_length: usize,
         timeout: Option<Duration>,
-    ) -> UsbResult<()> {
-        self.backend
-            .write_nonblocking(self, endpoint, data, callback, timeout)
+    ) -> UsbResult<usize> {
+        buffer.clear();
+        buffer.reserve(max_length);
+
+        let length = self
+            .read_uninit(
+                endpoint,
+                &mut buffer.spare_capacity_mut()[..max_length],
+                timeout,
+            )?
+            .len();
+
+        // Safety: read_uninit has initialized the first `length` bytes of our spare capacity.
+        unsafe { buffer.set_len(length) };
+        Ok(length)
     }
 
-    /// Performs an asynchronous write to the provided endpoint.
-    /// Usable for bulk and interrupt writes.
-    #[cfg(feature = "async")]
-    pub fn write_async(
+    /// Performs a write to the provided endpoint.
+    /// Usable for bulk and interrupt writes. Returns the number of bytes actually written;
+    /// which may be less than `data.len()` if the transfer was cut short.
+    pub fn write(
         &mut self,
         endpoint: u8,
-        data: WriteBuffer,
+        data: &[u8],
         timeout: Option<Duration>,
-    ) -> UsbResult<UsbFuture> {
-        // Create the future, and get a copy of it for our inner callback API,
-        // because everyone needs to get themselves a copy.
-        let future = UsbFuture::new();
-        let shared_state = future.clone_state();
+    ) -> UsbResult<usize> {
+        let data = &data[..data.len().min(self.transfer_limit(endpoint & 0x7f))];
+        let timeout = self.timeout_or_default(timeout);
+
+        let transfer =
+            self.perform_transfer("write", endpoint & 0x7f, data.len(), data, |backend| {
+                backend.write(endpoint, data, timeout)
+            })?;
+        self.finish_transfer(transfer, &[])
+    }
 
-        // Convert our inner callback-API into an async API by having our callback just... complete the future.
-        let callback = Box::new(move |result| shared_state.lock().unwrap().complete(result));
+    /// Reads from the provided endpoint until the buffer is completely full; issuing as many
+    /// transfers as it takes. The timeout (or default timeout) applies to the operation as a whole.
+    ///
+    /// Returns [Error::TimedOut] if no data arrived in time, and [Error::PartialTransfer] if
+    /// only some did, or if the device ended the transfer with a zero-length packet; in which
+    /// case the received data is at the start of the buffer.
+    pub fn read_exact(
+        &mut self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let deadline = self
+            .timeout_or_default(timeout)
+            .map(|timeout| Instant::now() + timeout);
+        let packet_size = self.endpoint_packet_size(endpoint | 0x80);
+
+        let mut received = 0;
+        while received < buffer.len() {
+            let result = deadline.map(timeout_until).transpose().and_then(|timeout| {
+                self.read_packets(endpoint, &mut buffer[received..], packet_size, timeout)
+            });
+
+            // A read that completes empty means the device has nothing more to send; so
+            // asking again would just spin.
+            match result {
+                Ok(0) => return Err(Error::PartialTransfer(received)),
+                Ok(length) => received += length,
+                Err(Error::TimedOut) if received > 0 => {
+                    return Err(Error::PartialTransfer(received))
+                }
+                Err(error) => return Err(error),
+            }
+        }
 
-        // Finally, trigger the actual async write.
-        self.backend
-            .write_nonblocking(self, endpoint, data, callback, timeout)?;
+        Ok(())
+    }
 
-        Ok(future)
+    /// Returns a [std::io::Read] adapter over the provided IN endpoint.
+    pub fn endpoint_reader(&mut self, endpoint: u8) -> EndpointReader<'_> {
+        EndpointReader::new(self, endpoint)
     }
 
-    /// Gains access to the device's per-backend data.
+    /// Returns a [std::io::Write] adapter over the provided OUT endpoint.
+    pub fn endpoint_writer(&mut self, endpoint: u8) -> EndpointWriter<'_> {
+        EndpointWriter::new(self, endpoint)
+    }
+
+    /// Performs a read from the provided endpoint into a buffer that hasn't been initialized;
+    /// saving the cost of zeroing large receive buffers before each transfer.
+    /// See [read] for documentation on the remaining arguments.
     ///
-    /// Generically, the only reason this should be used _outside of this library_
-    /// is if you are implementing your own backend!
-    pub unsafe fn backend_data_mut(&mut self) -> &mut dyn BackendDevice {
-        self.backend_device.as_mut()
+    /// Returns the portion of the buffer that was filled in.
+    pub fn read_uninit<'a>(
+        &mut self,
+        endpoint: u8,
+        buffer: &'a mut [MaybeUninit<u8>],
+        timeout: Option<Duration>,
+    ) -> UsbResult<&'a mut [u8]> {
+        let limit = buffer.len().min(self.transfer_limit(endpoint | 0x80));
+        let buffer = &mut buffer[..limit];
+        let timeout = self.timeout_or_default(timeout);
+
+        let transfer = self.perform_transfer("read", endpoint | 0x80, limit, &[], |backend| {
+            backend.read_uninit(endpoint, buffer, timeout)
+        })?;
+
+        // Safety: our backend has initialized the first `length` bytes of the buffer.
+        let length = transfer
+            .result
+            .as_ref()
+            .map_or(0, |&length| length.min(limit));
+        let filled: &'a mut [u8] =
+            unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, length) };
+
+        self.finish_transfer(transfer, filled)?;
+        Ok(filled)
     }
 
-    /// Gains access to the device's per-backend data.
+    /// Performs a single read from the provided endpoint, scattering the data across the provided
+    /// buffers in order. Returns the total number of bytes read.
     ///
-    /// Generically, the only reason this should be used _outside of this library_
-    /// is if you are implementing your own backend!
-    pub unsafe fn backend_data(&self) -> &dyn BackendDevice {
-        self.backend_device.as_ref()
+    /// This saves assembling a buffer, not a copy: none of our backends can scatter a transfer
+    /// natively, so the data is read into a single buffer and copied out.
+    pub fn read_vectored(
+        &mut self,
+        endpoint: u8,
+        buffers: &mut [IoSliceMut],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let mut limit = self.transfer_limit(endpoint | 0x80);
+        let mut buffers: Vec<IoSliceMut> = buffers
+            .iter_mut()
+            .map(|buffer| {
+                let length = buffer.len().min(limit);
+                limit -= length;
+                IoSliceMut::new(&mut buffer[..length])
+            })
+            .collect();
+        let length = buffers.iter().map(|buffer| buffer.len()).sum();
+        let timeout = self.timeout_or_default(timeout);
+
+        let transfer = self.perform_transfer("read", endpoint | 0x80, length, &[], |backend| {
+            backend.read_vectored(endpoint, &mut buffers, timeout)
+        })?;
+
+        // Our capture wants the data in one piece; so we'll only gather it if we're capturing.
+        let received = match transfer.urb {
+            Some(_) => gather(buffers.iter().map(|buffer| &**buffer)),
+            None => vec![],
+        };
+        self.finish_transfer(transfer, &received)
     }
 
-    /// Wraps a backend device abstraction with our user-facing device.
+    /// Performs a single write to the provided endpoint, gathering its data from the provided
+    /// buffers in order; for e.g. payloads built from separate headers and bodies.
     ///
-    /// This should only be used if you're implementing a custom device backend; otherwise,
-    /// you should get your Device from Host::open().
-    pub fn from_backend_device(
-        backend_device: Box<dyn BackendDevice>,
-        backend: Arc<dyn Backend>,
-    ) -> Device {
-        Device {
-            backend,
-            backend_device,
+    /// As with [read_vectored], the buffers are copied together before they're written.
+    pub fn write_vectored(
+        &mut self,
+        endpoint: u8,
+        data: &[IoSlice],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let mut limit = self.transfer_limit(endpoint & 0x7f);
+        let data: Vec<IoSlice> = data
+            .iter()
+            .map(|slice| {
+                let length = slice.len().min(limit);
+                limit -= length;
+                IoSlice::new(&slice[..length])
+            })
+            .collect();
+        let length = data.iter().map(|slice| slice.len()).sum();
+        let timeout = self.timeout_or_default(timeout);
+
+        let sent = match self.capture {
+            Some(_) => gather(data.iter().map(|slice| &**slice)),
+            None => vec![],
+        };
+        let transfer =
+            self.perform_transfer("write", endpoint & 0x7f, length, &sent, |backend| {
+                backend.write_vectored(endpoint, &data, timeout)
+            })?;
+        self.finish_transfer(transfer, &[])
+    }
+
+    /// Performs a read from the provided endpoint, with the provided options.
+    /// See [read] for documentation on the remaining arguments.
+    ///
+    /// None of our OSes can keep a single transfer going past a short packet; so
+    /// [ReadOptions::fill_buffer] is implemented by issuing as many reads as it takes. Its
+    /// timeout applies to the operation as a whole; and if it expires after some data has
+    /// arrived, the read returns that data rather than an error.
+    pub fn read_with_options(
+        &mut self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        options: &ReadOptions,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let fill_buffer =
+            options.fill_buffer || self.endpoint_policy(endpoint | 0x80).ignore_short_packets;
+        if !fill_buffer {
+            return self.read(endpoint, buffer, timeout);
         }
+
+        match self.read_exact(endpoint, buffer, timeout) {
+            Ok(()) => Ok(buffer.len()),
+            Err(Error::PartialTransfer(length)) => Ok(length),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Writes all of the provided data to the provided endpoint; issuing as many transfers as it
+    /// takes. The timeout (or default timeout) applies to the operation as a whole.
+    ///
+    /// Returns [Error::TimedOut] if no data was sent in time, and [Error::PartialTransfer] if
+    /// only some was; including if a write completes without sending anything.
+    pub fn write_all(
+        &mut self,
+        endpoint: u8,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let deadline = self
+            .timeout_or_default(timeout)
+            .map(|timeout| Instant::now() + timeout);
+
+        let mut sent = 0;
+        while sent < data.len() {
+            let result = deadline
+                .map(timeout_until)
+                .transpose()
+                .and_then(|timeout| self.write(endpoint, &data[sent..], timeout));
+
+            // A write that moves nothing won't fare any better if we repeat it.
+            match result {
+                Ok(0) => return Err(Error::PartialTransfer(sent)),
+                Ok(length) => sent += length,
+                Err(Error::TimedOut) if sent > 0 => return Err(Error::PartialTransfer(sent)),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Performs a single read on behalf of [read_exact], without ever asking for a partial packet.
+    ///
+    /// A device is always free to send a full packet; so if we asked for less, we'd risk an
+    /// overrun. Instead, we read whole packets directly into the buffer, and read any trailing
+    /// partial packet via a packet-sized bounce buffer.
+    fn read_packets(
+        &mut self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        packet_size: Option<usize>,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let packet_size = match packet_size {
+            Some(packet_size) if !buffer.len().is_multiple_of(packet_size) => packet_size,
+            _ => return self.read(endpoint, buffer, timeout),
+        };
+
+        if buffer.len() > packet_size {
+            let whole_packets = buffer.len() - (buffer.len() % packet_size);
+            return self.read(endpoint, &mut buffer[..whole_packets], timeout);
+        }
+
+        let mut packet = vec![0; packet_size];
+        let length = self.read(endpoint, &mut packet, timeout)?;
+        if length > buffer.len() {
+            return Err(Error::Overrun);
+        }
+
+        buffer[..length].copy_from_slice(&packet[..length]);
+        Ok(length)
+    }
+
+    /// Returns the max packet size of the given endpoint on the active configuration;
+    /// or None if we can't find it.
+    fn endpoint_packet_size(&mut self, endpoint_address: u8) -> Option<usize> {
+        self.endpoint_descriptor(endpoint_address)
+            .map(|endpoint| endpoint.packet_size())
+            .filter(|&packet_size| packet_size > 0)
+    }
+
+    /// Performs a write to the provided endpoint, with the provided options.
+    /// See [write] for documentation on the remaining arguments.
+    ///
+    /// Where the backend can't append a ZLP itself (see [WriteOptions::append_zlp]), we need
+    /// the endpoint's max packet size to know whether one is due; so this returns
+    /// [Error::InvalidEndpoint], without writing anything, if the active configuration
+    /// doesn't describe the endpoint. If the data is sent but the ZLP that should follow
+    /// it fails, this returns [Error::PartialTransfer] with the amount of data sent.
+    pub fn write_with_options(
+        &mut self,
+        endpoint: u8,
+        data: &[u8],
+        options: &WriteOptions,
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        self.ensure_connected()?;
+        let timeout = self.timeout_or_default(timeout);
+        let limit = data.len().min(self.transfer_limit(endpoint & 0x7f));
+
+        // The endpoint's policy can ask for ZLPs, or pacing, on every write; though if it's
+        // also cutting this write short, the write isn't over, and mustn't be terminated yet.
+        let policy = self.endpoint_policy(endpoint & 0x7f);
+        let options = &WriteOptions {
+            append_zlp: (options.append_zlp || policy.short_packet_terminate)
+                && limit == data.len(),
+            pace_to_interval: options.pace_to_interval || policy.pace_writes,
+        };
+        let data = &data[..limit];
+
+        // If we're pacing, we'll hold off until the endpoint's interval has passed.
+        if options.pace_to_interval {
+            self.wait_for_interval(endpoint & 0x7f);
+        }
+
+        // If our backend can't append a ZLP for us, we'll follow the data with an empty write
+        // of our own; which we count as part of this one transfer.
+        let packet_size = match options.append_zlp {
+            true => self.endpoint_packet_size(endpoint & 0x7f),
+            false => None,
+        };
+        let transfer =
+            self.perform_transfer(
+                "write",
+                endpoint & 0x7f,
+                limit,
+                data,
+                |backend| match backend.write_with_options(endpoint, data, options, timeout) {
+                    Err(Error::Unsupported) if options.append_zlp => {
+                        let packet_size = packet_size.ok_or(Error::InvalidEndpoint)?;
+                        let length = backend.write(endpoint, data, timeout)?;
+                        if length > 0 && length.is_multiple_of(packet_size) {
+                            backend
+                                .write(endpoint, &[], timeout)
+                                .map_err(|_| Error::PartialTransfer(length))?;
+                        }
+                        Ok(length)
+                    }
+                    result => result,
+                },
+            )?;
+        self.finish_transfer(transfer, &[])
+    }
+
+    /// Performs a write to the provided interrupt OUT endpoint; paced to the endpoint's
+    /// interval if its policy asks for that. See [write] for documentation on the remaining
+    /// arguments.
+    ///
+    /// Returns [Error::InvalidEndpoint] if the active configuration doesn't have an interrupt
+    /// OUT endpoint at the given address.
+    pub fn write_interrupt(
+        &mut self,
+        endpoint: u8,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let descriptor = self
+            .endpoint_descriptor(endpoint & 0x7f)
+            .ok_or(Error::InvalidEndpoint)?;
+        if descriptor.transfer_type() != TransferType::Interrupt {
+            return Err(Error::InvalidEndpoint);
+        }
+
+        self.write_with_options(endpoint, data, &WriteOptions::default(), timeout)
+    }
+
+    /// Returns how often the bus services the given interrupt or isochronous endpoint on the
+    /// active configuration; or None for other endpoints, or if we can't find it. Honors any
+    /// longer interval set by the endpoint's policy.
+    ///
+    /// If the backend can't tell us the device's speed, we assume full speed; which gives
+    /// the longer interval for the small bInterval values interrupt endpoints usually have.
+    pub fn endpoint_interval(&mut self, endpoint_address: u8) -> Option<Duration> {
+        let mut descriptor = self.endpoint_descriptor(endpoint_address)?;
+        if let Some(interval) = self.endpoint_policy(endpoint_address).interval {
+            descriptor.interval = descriptor.interval.max(interval);
+        }
+
+        let speed = self.speed().unwrap_or(DeviceSpeed::Full);
+        descriptor.service_interval(speed)
+    }
+
+    /// Returns the speed the device is running at; or None if the backend can't tell.
+    pub fn speed(&self) -> Option<DeviceSpeed> {
+        self.information.as_ref()?.speed()
+    }
+
+    /// Returns the OS handle the backend is using for this device; for calling platform APIs
+    /// we don't wrap. Fails with [Error::Unsupported] if the backend doesn't have one.
+    ///
+    /// # Safety
+    /// The handle still belongs to this device: it must not be closed or released, and must
+    /// not be used once the device is dropped. Anything done through it (e.g. changing the
+    /// configuration, or claiming interfaces) happens behind our back; and can leave our view
+    /// of the device out of date.
+    pub unsafe fn raw_handle(&self) -> UsbResult<RawHandle> {
+        self.backend_device.raw_handle().ok_or(Error::Unsupported)
+    }
+
+    /// Returns the OS handle the backend is using for one of our claimed interfaces, on
+    /// backends that have one per interface; see [Device::raw_handle].
+    ///
+    /// # Safety
+    /// As for [Device::raw_handle].
+    pub unsafe fn raw_interface_handle(&self, interface: u8) -> UsbResult<RawHandle> {
+        self.backend_device
+            .raw_interface_handle(interface)
+            .ok_or(Error::Unsupported)
+    }
+
+    /// Returns the descriptor of the given endpoint on the active configuration; or None if
+    /// we can't find it.
+    ///
+    /// Reading the configuration costs several control requests; so we read it once, and
+    /// keep every endpoint's descriptor until the configuration changes.
+    fn endpoint_descriptor(&mut self, endpoint_address: u8) -> Option<EndpointDescriptor> {
+        if self.endpoint_descriptors.is_empty() {
+            let configuration = self.read_active_configuration_descriptor().ok()?;
+            for endpoint in configuration
+                .interfaces
+                .into_iter()
+                .flat_map(|interface| interface.endpoints)
+            {
+                // Where alternate settings share an address, the first one wins.
+                self.endpoint_descriptors
+                    .entry(endpoint.address)
+                    .or_insert(endpoint);
+            }
+        }
+
+        self.endpoint_descriptors.get(&endpoint_address).cloned()
+    }
+
+    /// Waits until a paced write to the given endpoint is due; and notes that one's going out.
+    fn wait_for_interval(&mut self, endpoint_address: u8) {
+        if let Some(interval) = self.endpoint_interval(endpoint_address) {
+            if let Some(last_write) = self.paced_writes.get(&endpoint_address) {
+                let due = *last_write + interval;
+                std::thread::sleep(due.saturating_duration_since(Instant::now()));
+            }
+        }
+
+        self.paced_writes.insert(endpoint_address, Instant::now());
+    }
+
+    /// Allocates USB3 bulk streams on the provided endpoints; e.g. the data pipes of a UASP
+    /// device. Each endpoint gets up to `count` streams, numbered from 1; the endpoints' interfaces
+    /// must already be claimed. Returns the number of streams actually allocated, which may be
+    /// fewer than requested if the device or host controller supports fewer.
+    ///
+    /// Streams are currently only supported on Android, via usbfs. Elsewhere, this and the other
+    /// stream functions return [Error::Unsupported]; on macOS, streams need IOKit's newer
+    /// interface (CreateStreams, ReadStreamsPipeTo and friends), which we don't yet use.
+    pub fn alloc_streams(&mut self, endpoints: &[u8], count: u32) -> UsbResult<u32> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.alloc_streams(endpoints, count))
+    }
+
+    /// Frees the streams allocated on the provided endpoints by [Device::alloc_streams].
+    pub fn free_streams(&mut self, endpoints: &[u8]) -> UsbResult<()> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.free_streams(endpoints))
+    }
+
+    /// Performs a read from a single stream of the provided bulk endpoint; which must have had
+    /// streams allocated by [Device::alloc_streams]. See [read] for the remaining arguments.
+    pub fn read_stream(
+        &mut self,
+        endpoint: u8,
+        stream_id: u32,
+        buffer: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let limit = buffer.len().min(self.transfer_limit(endpoint | 0x80));
+        let buffer = &mut buffer[..limit];
+        let timeout = self.timeout_or_default(timeout);
+
+        let transfer =
+            self.perform_transfer("read_stream", endpoint | 0x80, limit, &[], |backend| {
+                backend.read_stream(endpoint, stream_id, buffer, timeout)
+            })?;
+        self.finish_transfer(transfer, buffer)
+    }
+
+    /// Performs a write to a single stream of the provided bulk endpoint; which must have had
+    /// streams allocated by [Device::alloc_streams]. See [write] for the remaining arguments.
+    pub fn write_stream(
+        &mut self,
+        endpoint: u8,
+        stream_id: u32,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        let data = &data[..data.len().min(self.transfer_limit(endpoint & 0x7f))];
+        let timeout = self.timeout_or_default(timeout);
+
+        let transfer = self.perform_transfer(
+            "write_stream",
+            endpoint & 0x7f,
+            data.len(),
+            data,
+            |backend| backend.write_stream(endpoint, stream_id, data, timeout),
+        )?;
+        self.finish_transfer(transfer, &[])
+    }
+
+    /// Performs a read from the provided endpoint, which must finish before the given deadline;
+    /// for e.g. retry loops that want a budget for the operation as a whole.
+    /// Returns [Error::TimedOut] without touching the device if the deadline has already passed.
+    pub fn read_until(
+        &mut self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        deadline: Instant,
+    ) -> UsbResult<usize> {
+        let timeout = timeout_until(deadline)?;
+        self.read(endpoint, buffer, Some(timeout))
+    }
+
+    /// Performs a write to the provided endpoint, which must finish before the given deadline.
+    /// Returns [Error::TimedOut] without touching the device if the deadline has already passed.
+    pub fn write_until(
+        &mut self,
+        endpoint: u8,
+        data: &[u8],
+        deadline: Instant,
+    ) -> UsbResult<usize> {
+        let timeout = timeout_until(deadline)?;
+        self.write(endpoint, data, Some(timeout))
+    }
+
+    /// Performs a read from the provided endpoint, with separate no-data and completion
+    /// timeouts. See [read] for documentation on the remaining arguments.
+    pub fn read_with_timeouts(
+        &mut self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        timeout: TransferTimeout,
+    ) -> UsbResult<usize> {
+        let limit = buffer.len().min(self.transfer_limit(endpoint | 0x80));
+        let buffer = &mut buffer[..limit];
+
+        let transfer = self.perform_transfer("read", endpoint | 0x80, limit, &[], |backend| {
+            backend.read_with_timeouts(endpoint, buffer, timeout)
+        })?;
+        self.finish_transfer(transfer, buffer)
+    }
+
+    /// Performs a write to the provided endpoint, with separate no-data and completion
+    /// timeouts. See [write] for documentation on the remaining arguments.
+    pub fn write_with_timeouts(
+        &mut self,
+        endpoint: u8,
+        data: &[u8],
+        timeout: TransferTimeout,
+    ) -> UsbResult<usize> {
+        let data = &data[..data.len().min(self.transfer_limit(endpoint & 0x7f))];
+
+        let transfer =
+            self.perform_transfer("write", endpoint & 0x7f, data.len(), data, |backend| {
+                backend.write_with_timeouts(endpoint, data, timeout)
+            })?;
+        self.finish_transfer(transfer, &[])
+    }
+
+    /// Performs an asynchronous write to the provided endpoint.
+    /// Usable for bulk and interrupt writes.
+    #[cfg(feature = "callbacks")]
+    pub fn write_and_call_back(
+        &mut self,
+        endpoint: u8,
+        data: WriteBuffer,
+        callback: AsyncCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        self.ensure_connected()?;
+        let length = data.as_ref().as_ref().len();
+        let trace = self.trace_transfer("write", endpoint & 0x7f, length);
+        let urb = self.capture_submission(endpoint & 0x7f, None, length, data.as_ref().as_ref());
+        self.note_result(self.backend_device.write_nonblocking(
+            endpoint,
+            data,
+            self.tracking_callback(trace, urb, self.dispatched_callback(callback)),
+            self.timeout_or_default(timeout),
+        ))
+    }
+
+    /// Performs an asynchronous write to the provided endpoint; sending a [WriteEvent] to the
+    /// provided sender (e.g. a [std::sync::mpsc::Sender]) once it completes.
+    #[cfg(feature = "callbacks")]
+    pub fn write_to_channel(
+        &mut self,
+        endpoint: u8,
+        data: WriteBuffer,
+        sender: impl CompletionSender<WriteEvent>,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        let buffer = Arc::clone(&data);
+        let callback = Box::new(move |result| {
+            sender.send_completion(WriteEvent {
+                endpoint: endpoint & 0x7f,
+                buffer,
+                result,
+            })
+        });
+        self.write_and_call_back(endpoint, data, callback, timeout)
+    }
+
+    /// Performs an asynchronous write to the provided endpoint.
+    /// Usable for bulk and interrupt writes.
+    #[cfg(feature = "async")]
+    pub fn write_async(
+        &mut self,
+        endpoint: u8,
+        data: WriteBuffer,
+        timeout: Option<Duration>,
+    ) -> UsbResult<UsbFuture<WriteCompletion>> {
+        // Create the future, and get a copy of it for our inner callback API,
+        // because everyone needs to get themselves a copy.
+        let future = UsbFuture::for_write(Arc::clone(&data));
+        let shared_state = future.clone_state();
+
+        // Convert our inner callback-API into an async API by having our callback just... complete the future.
+        let callback = Box::new(move |result| shared_state.lock().unwrap().complete(result));
+
+        // Finally, trigger the actual async write.
+        self.ensure_connected()?;
+        let length = data.as_ref().as_ref().len();
+        let trace = self.trace_transfer("write", endpoint & 0x7f, length);
+        let urb = self.capture_submission(endpoint & 0x7f, None, length, data.as_ref().as_ref());
+        self.note_result(self.backend_device.write_nonblocking(
+            endpoint,
+            data,
+            self.tracking_callback(trace, urb, callback),
+            self.timeout_or_default(timeout),
+        ))?;
+
+        Ok(future)
+    }
+
+    /// Returns a stream of `report_length`-byte reports from the provided interrupt IN endpoint;
+    /// which keeps several reads queued, and yields each report in order.
+    #[cfg(feature = "async")]
+    pub fn interrupt_stream(&mut self, endpoint: u8, report_length: usize) -> InterruptStream<'_> {
+        InterruptStream::new(self, endpoint, report_length)
+    }
+
+    /// Returns the bus's current frame number, and roughly when that frame began; for
+    /// scheduling isochronous transfers. Not every backend can provide this.
+    pub fn current_bus_frame(&self) -> UsbResult<(u64, SystemTime)> {
+        self.ensure_connected()?;
+        self.note_result(self.backend_device.current_bus_frame())
+    }
+
+    /// Performs an asynchronous isochronous read from the provided endpoint. The buffer is
+    /// divided into one packet per entry of `packet_lengths`, each of which succeeds or fails
+    /// on its own; see [crate::iso].
+    ///
+    /// The options choose when the transfer starts, and how the backend carries it out.
+    #[cfg(feature = "async")]
+    pub fn read_isochronous_async(
+        &mut self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        packet_lengths: &[usize],
+        options: &IsoOptions,
+    ) -> UsbResult<UsbFuture<IsoReadCompletion>> {
+        let length = buffer.write().as_mut().len();
+        if packet_lengths.iter().sum::<usize>() > length {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.ensure_connected()?;
+        let trace = self.trace_transfer("read_isochronous", endpoint | 0x80, length);
+        let urb = self
+            .capture_submission(endpoint | 0x80, None, length, &[])
+            .map(|urb| urb.with_read_buffer(Arc::clone(&buffer)));
+        let completion_buffer = Arc::clone(&buffer);
+        let lengths = packet_lengths.to_vec();
+        let (future, callback) = iso_future(
+            |completion| self.tracking_callback(trace, urb, completion),
+            move |packets| IsoReadCompletion {
+                buffer: completion_buffer,
+                packet_lengths: lengths,
+                packets,
+            },
+        );
+
+        self.note_result(self.backend_device.read_isochronous_nonblocking(
+            endpoint,
+            buffer,
+            packet_lengths,
+            options,
+            callback,
+        ))?;
+
+        Ok(future)
+    }
+
+    /// Performs an asynchronous isochronous write to the provided endpoint. The data is
+    /// divided into one packet per entry of `packet_lengths`; see [read_isochronous_async].
+    #[cfg(feature = "async")]
+    pub fn write_isochronous_async(
+        &mut self,
+        endpoint: u8,
+        data: WriteBuffer,
+        packet_lengths: &[usize],
+        options: &IsoOptions,
+    ) -> UsbResult<UsbFuture<IsoWriteCompletion>> {
+        let length = data.as_ref().as_ref().len();
+        if packet_lengths.iter().sum::<usize>() > length {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.ensure_connected()?;
+        let trace = self.trace_transfer("write_isochronous", endpoint & 0x7f, length);
+        let urb = self.capture_submission(endpoint & 0x7f, None, length, data.as_ref().as_ref());
+        let completion_buffer = Arc::clone(&data);
+        let (future, callback) = iso_future(
+            |completion| self.tracking_callback(trace, urb, completion),
+            move |packets| IsoWriteCompletion {
+                buffer: completion_buffer,
+                packets,
+            },
+        );
+
+        self.note_result(self.backend_device.write_isochronous_nonblocking(
+            endpoint,
+            data,
+            packet_lengths,
+            options,
+            callback,
+        ))?;
+
+        Ok(future)
+    }
+
+    /// Returns a stream of packets from the provided isochronous IN endpoint; which keeps a
+    /// ring of `packets_per_transfer`-packet transfers scheduled back-to-back, and yields each
+    /// packet in order.
+    #[cfg(feature = "async")]
+    pub fn iso_stream(
+        &mut self,
+        endpoint: u8,
+        packet_size: usize,
+        packets_per_transfer: usize,
+    ) -> IsoStream<'_> {
+        IsoStream::new(self, endpoint, packet_size, packets_per_transfer)
+    }
+
+    /// Submits an asynchronous read on behalf of a [crate::Transfer].
+    #[cfg(feature = "async")]
+    pub(crate) fn submit_read(
+        &mut self,
+        endpoint: u8,
+        buffer: ReadBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        self.ensure_connected()?;
+        let length = buffer.write().as_mut().len();
+        let trace = self.trace_transfer("read", endpoint | 0x80, length);
+        let urb = self
+            .capture_submission(endpoint | 0x80, None, length, &[])
+            .map(|urb| urb.with_read_buffer(Arc::clone(&buffer)));
+        self.note_result(self.backend_device.read_nonblocking(
+            endpoint,
+            buffer,
+            self.tracking_callback(trace, urb, callback),
+            self.timeout_or_default(timeout),
+        ))
+    }
+
+    /// Submits an asynchronous write on behalf of a [crate::Transfer].
+    #[cfg(feature = "async")]
+    pub(crate) fn submit_write(
+        &mut self,
+        endpoint: u8,
+        data: WriteBuffer,
+        callback: TransferCallback,
+        timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        self.ensure_connected()?;
+        let length = data.as_ref().as_ref().len();
+        let trace = self.trace_transfer("write", endpoint & 0x7f, length);
+        let urb = self.capture_submission(endpoint & 0x7f, None, length, data.as_ref().as_ref());
+        self.note_result(self.backend_device.write_nonblocking(
+            endpoint,
+            data,
+            self.tracking_callback(trace, urb, callback),
+            self.timeout_or_default(timeout),
+        ))
+    }
+
+    /// Submits each of the provided transfers, in order, and returns a handle that collects
+    /// their completions; so they can be reaped in bulk. See [crate::batch].
+    ///
+    /// A transfer that can't be submitted doesn't stop the rest; it shows up as a completion
+    /// with its submission error instead, so every request gets exactly one completion.
+    #[cfg(feature = "async")]
+    pub fn submit_batch(&mut self, requests: &[TransferRequest]) -> UsbResult<BatchHandle> {
+        self.ensure_connected()?;
+        let batch = BatchHandle::new(requests.len());
+
+        for (index, request) in requests.iter().enumerate() {
+            let endpoint = request.endpoint_address();
+            let callback = Box::new(batch.completer(index, endpoint));
+
+            let submission = match request {
+                TransferRequest::Read {
+                    endpoint,
+                    buffer,
+                    timeout,
+                } => self.submit_read(*endpoint, Arc::clone(buffer), callback, *timeout),
+                TransferRequest::Write {
+                    endpoint,
+                    data,
+                    timeout,
+                } => self.submit_write(*endpoint, Arc::clone(data), callback, *timeout),
+            };
+
+            if let Err(error) = submission {
+                batch.complete(BatchCompletion {
+                    index,
+                    endpoint,
+                    result: Err(error),
+                });
+            }
+        }
+
+        Ok(batch)
+    }
+
+    /// Gains access to the device's per-backend data.
+    ///
+    /// Generically, the only reason this should be used _outside of this library_
+    /// is if you are implementing your own backend!
+    pub unsafe fn backend_data_mut(&mut self) -> &mut dyn BackendDevice {
+        self.backend_device.as_mut()
+    }
+
+    /// Gains access to the device's per-backend data.
+    ///
+    /// Generically, the only reason this should be used _outside of this library_
+    /// is if you are implementing your own backend!
+    pub unsafe fn backend_data(&self) -> &dyn BackendDevice {
+        self.backend_device.as_ref()
+    }
+
+    /// Wraps a backend device abstraction with our user-facing device.
+    ///
+    /// This should only be used if you're implementing a custom device backend; otherwise,
+    /// you should get your Device from Host::open().
+    pub fn from_backend_device(
+        mut backend_device: Box<dyn BackendDevice>,
+        backend: Arc<dyn Backend>,
+    ) -> Device {
+        let disconnected = Arc::new(DisconnectSignal::default());
+        backend_device.set_disconnect_signal(Arc::clone(&disconnected));
+
+        Device {
+            backend,
+            backend_device,
+            disconnected,
+            information: None,
+            stall_policy: StallPolicy::default(),
+            endpoint_policies: HashMap::new(),
+            paced_writes: HashMap::new(),
+            endpoint_descriptors: HashMap::new(),
+            default_timeout: None,
+            claimed_interfaces: vec![],
+            alternate_settings: HashMap::new(),
+            transfers: Arc::default(),
+            last_error: Mutex::new(None),
+            capture: None,
+            stats: Arc::default(),
+            #[cfg(feature = "callbacks")]
+            completion_executor: None,
+        }
+    }
+
+    /// Records the information this device was opened from; which lets us find it again later.
+    pub(crate) fn with_information(mut self, information: DeviceInformation) -> Device {
+        self.information = Some(information);
+        self
+    }
+
+    /// Returns the information this device was opened from, if it was opened from enumeration.
+    pub fn information(&self) -> Option<&DeviceInformation> {
+        self.information.as_ref()
+    }
+
+    /// Returns the information we'll need to find this device again once it re-enumerates;
+    /// or [Error::Unsupported] if we won't be able to tell it apart from other devices.
+    fn recognizable_information(&self) -> UsbResult<DeviceInformation> {
+        self.information
+            .clone()
+            .filter(DeviceInformation::can_be_recognized)
+            .ok_or(Error::Unsupported)
+    }
+
+    /// Waits for this device to disconnect and then re-appear, and opens it again.
+    ///
+    /// This is the usual pattern after asking a device to reboot itself, e.g. after a DFU
+    /// detach; the device is matched by the port it's plugged into, where the backend can
+    /// tell us that, or by its serial number otherwise. This device is closed in the process.
+    ///
+    /// Returns [Error::TimedOut] if the device doesn't come back in time; and
+    /// [Error::Unsupported] if we don't know what to look for, because the device wasn't
+    /// opened from enumeration, or the backend reported neither where it's plugged in nor
+    /// its serial number.
+    pub fn wait_for_reconnect(self, timeout: Option<Duration>) -> UsbResult<Device> {
+        let information = self.recognizable_information()?;
+        let backend = Arc::clone(&self.backend);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        // Find our device in the backend's current device list, if it's there.
+        let find_device = || -> UsbResult<Option<DeviceInformation>> {
+            Ok(backend
+                .get_devices()?
+                .into_iter()
+                .find(|candidate| information.is_same_physical_device(candidate)))
+        };
+
+        // First, wait for the device to go away...
+        poll_until(deadline, || {
+            let gone = !self.is_connected() || find_device()?.is_none();
+            Ok(gone.then_some(()))
+        })?;
+        drop(self);
+
+        // ... then wait for it to come back, and open it anew.
+        let information = poll_until(deadline, find_device)?;
+        let backend_device = backend.open(&information)?;
+
+        Ok(
+            Device::from_backend_device(backend_device, Arc::clone(&backend))
+                .with_information(information),
+        )
+    }
+
+    /// Resets the device, and then opens it again once it's back; re-selecting the configuration
+    /// it was in, re-claiming the interfaces this device had claimed, and re-selecting the
+    /// alternate settings they were in.
+    ///
+    /// A bus reset often invalidates the OS's handle to the device (e.g. on Linux, where the
+    /// device re-enumerates); so rather than keep using it, we find the device again at the
+    /// same port, and swap a fresh handle in for the old one. Everything else about this
+    /// device, e.g. its timeouts, policies, and capture, carries over.
+    ///
+    /// If the reset itself fails, the device is left as it was. If the device doesn't come
+    /// back in time, this returns [Error::TimedOut], and the device is treated as
+    /// disconnected. Returns [Error::Unsupported], without resetting the device, if we
+    /// wouldn't know what to look for; see [wait_for_reconnect].
+    ///
+    /// Once the device is back, we try to restore each part of its state, even if an earlier
+    /// part fails; and then return the first error we hit. Anything that failed to restore is
+    /// forgotten, e.g. an interface we couldn't re-claim is no longer considered claimed.
+    pub fn reset_and_reopen(&mut self, timeout: Option<Duration>) -> UsbResult<()> {
+        let information = self.recognizable_information()?;
+        let backend = Arc::clone(&self.backend);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        self.ensure_connected()?;
+
+        // Remember which configuration the device was in; a reset leaves it unconfigured, and not
+        // every OS configures it again on its own. If we can't tell, we'll leave it to the OS.
+        let configuration = self.backend_device.active_configuration().ok();
+
+        let trace = Trace::operation("reset_device");
+        let result = self.backend_device.reset_device();
+        trace.finish(result.as_ref());
+
+        // A device that re-enumerates as it resets can vanish before the OS tells us the reset
+        // worked; that's what we're waiting for anyway. Anything else leaves the device as it was.
+        match result {
+            Ok(()) | Err(Error::Disconnected | Error::DeviceNotFound) => {}
+            Err(error) => return self.note_result(Err(error)),
+        }
+
+        // Let go of the old handle, so it can't keep the device from being opened again...
+        drop(std::mem::replace(
+            &mut self.backend_device,
+            Box::new(DetachedDevice),
+        ));
+
+        // ... wait for the device to show up at the same port, and open it anew. Until it's
+        // finished re-enumerating, opening it can fail; so we keep trying until the deadline...
+        let reopened = poll_until(deadline, || {
+            let found = backend
+                .get_devices()?
+                .into_iter()
+                .find(|candidate| information.is_same_physical_device(candidate));
+            let Some(found) = found else {
+                return Ok(None);
+            };
+            Ok(backend.open(&found).ok().map(|opened| (found, opened)))
+        });
+        let (information, mut backend_device) = match reopened {
+            Ok(reopened) => reopened,
+            Err(error) => {
+                self.disconnected.notify();
+                return Err(error);
+            }
+        };
+
+        // ... swap the new handle in; with a fresh disconnect signal, if the old handle saw the
+        // device leave as it re-enumerated...
+        if self.disconnected.is_disconnected() {
+            self.disconnected = Arc::default();
+        }
+        backend_device.set_disconnect_signal(Arc::clone(&self.disconnected));
+        self.backend_device = backend_device;
+        self.information = Some(information);
+        self.paced_writes.clear();
+        self.endpoint_descriptors.clear();
+
+        // ... and put back what the old handle had set up; as much of it as we can.
+        let mut first_error = None;
+        let mut restore = |result: UsbResult<()>| {
+            if let Err(error) = result {
+                first_error.get_or_insert(error);
+            }
+        };
+
+        if let Some(configuration) = configuration.filter(|&configuration| configuration != 0) {
+            if self.active_configuration() != Ok(configuration) {
+                restore(self.set_active_configuration(configuration));
+            }
+        }
+        for interface in std::mem::take(&mut self.claimed_interfaces) {
+            restore(self.claim_interface(interface));
+        }
+        for (interface, setting) in std::mem::take(&mut self.alternate_settings) {
+            restore(self.set_alternate_setting(interface, setting));
+        }
+        for (address, policy) in std::mem::take(&mut self.endpoint_policies) {
+            restore(self.set_endpoint_policy(address, policy));
+        }
+
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Returns false once the device has been unplugged (or otherwise gone away).
+    ///
+    /// A disconnected device never comes back; to talk to it again once it re-appears,
+    /// open it anew.
+    pub fn is_connected(&self) -> bool {
+        !(self.disconnected.is_disconnected() || self.backend_device.is_disconnected())
+    }
+
+    /// Arranges for the callback to run once the device goes away; or runs it right away, if
+    /// it already has. The callback runs on whichever thread notices; e.g. a backend's event
+    /// thread.
+    ///
+    /// Where the OS provides removal notifications (e.g. on macOS), this fires as soon as the
+    /// device is unplugged. Elsewhere, it fires once a transfer fails because the device is gone.
+    #[cfg(feature = "callbacks")]
+    pub fn on_disconnect(&self, callback: impl FnOnce() + Send + 'static) {
+        self.disconnected.subscribe(Box::new(callback))
+    }
+
+    /// Returns a future that completes once the device goes away; see [on_disconnect] for when
+    /// that's noticed. The future doesn't borrow the device, so it can outlive it.
+    #[cfg(feature = "async")]
+    pub fn disconnected(&self) -> DisconnectFuture {
+        DisconnectFuture::new(Arc::clone(&self.disconnected))
+    }
+
+    /// Fails with [Error::Disconnected] if we already know the device is gone.
+    fn ensure_connected(&self) -> UsbResult<()> {
+        if self.is_connected() {
+            Ok(())
+        } else {
+            Err(Error::Disconnected)
+        }
+    }
+
+    /// Passes through the result of a backend operation; noting if it's told us the device is gone.
+    fn note_result<T>(&self, result: UsbResult<T>) -> UsbResult<T> {
+        if let Err(Error::Disconnected) = result {
+            self.disconnected.notify();
+        }
+
+        result
+    }
+
+    /// Starts tracing a transfer on the given endpoint; counting it in our statistics.
+    fn trace_transfer(&self, operation: &'static str, endpoint: u8, length: usize) -> Trace {
+        Trace::transfer(operation, endpoint, length).counted_in(&self.stats, endpoint)
+    }
+
+    /// Records the submission of a transfer into our capture, if we're capturing.
+    /// See [DeviceCapture::submit].
+    fn capture_submission(
+        &self,
+        endpoint: u8,
+        setup: Option<[u8; 8]>,
+        length: usize,
+        data: &[u8],
+    ) -> Option<CapturedUrb> {
+        let capture = self.capture.as_ref()?;
+        Some(capture.submit(endpoint, setup, length, data))
+    }
+
+    /// Performs a synchronous transfer of up to `length` bytes on behalf of one of our reads
+    /// or writes; tracing, capturing and counting it, and applying our stall policy. `data` is
+    /// what's being sent, for OUT transfers. The caller hands the result to [finish_transfer].
+    fn perform_transfer(
+        &mut self,
+        operation: &'static str,
+        endpoint_address: u8,
+        length: usize,
+        data: &[u8],
+        mut transfer: impl FnMut(&dyn BackendDevice) -> UsbResult<usize>,
+    ) -> UsbResult<PerformedTransfer> {
+        self.ensure_connected()?;
+        let trace = self.trace_transfer(operation, endpoint_address, length);
+        let urb = self.capture_submission(endpoint_address, None, length, data);
+
+        let mut result = self.note_result(transfer(&*self.backend_device));
+        if self.recover_from_stall(endpoint_address, &result) {
+            result = transfer(&*self.backend_device);
+        }
+
+        trace.finish_transfer(result.as_ref().copied());
+        Ok(PerformedTransfer {
+            result,
+            urb,
+            endpoint_address,
+            length,
+        })
+    }
+
+    /// Finishes off a transfer performed by [perform_transfer]; capturing the data it
+    /// `received`, for IN transfers, and remembering what it was if it failed.
+    fn finish_transfer(&self, transfer: PerformedTransfer, received: &[u8]) -> UsbResult<usize> {
+        let PerformedTransfer {
+            result,
+            urb,
+            endpoint_address,
+            length,
+        } = transfer;
+
+        if let Some(urb) = urb {
+            urb.complete(result.as_ref().copied(), received);
+        }
+        self.note_transfer(result, |error| {
+            ContextError::for_transfer(error, endpoint_address, length)
+        })
+    }
+
+    /// Like [note_result], but also remembers what a failing transfer was; for [last_error].
+    fn note_transfer<T>(
+        &self,
+        result: UsbResult<T>,
+        describe: impl FnOnce(Error) -> ContextError,
+    ) -> UsbResult<T> {
+        let result = self.note_result(result);
+        if let Err(error) = &result {
+            *self.last_error.lock().unwrap() = Some(describe(error.clone()));
+        }
+
+        result
+    }
+
+    /// Wraps a callback for an asynchronous operation, so we can trace and capture its
+    /// completion, and note if its result tells us the device has gone away.
+    #[cfg(any(feature = "async", feature = "callbacks"))]
+    fn tracking_callback(
+        &self,
+        trace: Trace,
+        urb: Option<CapturedUrb>,
+        callback: TransferCallback,
+    ) -> TransferCallback {
+        let disconnected = Arc::clone(&self.disconnected);
+
+        // The guard counts the transfer as outstanding until the callback has either run, or
+        // been dropped because the transfer was never submitted.
+        let guard = self.transfers.begin();
+
+        Box::new(move |result| {
+            let _guard = guard;
+            trace.finish_transfer(result.as_ref().copied());
+            if let Some(urb) = urb {
+                urb.complete(result.as_ref().copied(), &[]);
+            }
+
+            if let Err(Error::Disconnected) = result {
+                disconnected.notify();
+            }
+            callback(result)
+        })
+    }
+}
+
+/// A synchronous transfer that's been performed, but not yet finished; see
+/// [Device::perform_transfer].
+struct PerformedTransfer {
+    /// How the transfer went.
+    result: UsbResult<usize>,
+
+    /// The transfer's record in our capture, if we're capturing.
+    urb: Option<CapturedUrb>,
+
+    /// The address of the endpoint the transfer targeted.
+    endpoint_address: u8,
+
+    /// The amount of data the transfer asked to move.
+    length: usize,
+}
+
+/// Copies a list of slices into a single buffer; for capturing vectored transfers.
+fn gather<'a>(slices: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+    slices.flat_map(|slice| slice.iter().copied()).collect()
+}
+
+/// Converts the length of a control request's data stage into its wLength; failing if it's
+/// too long to describe.
+fn control_length(length: usize) -> UsbResult<u16> {
+    u16::try_from(length).map_err(|_| Error::InvalidArgument)
+}
+
+/// Converts a deadline into the timeout for a single transfer; failing if it's already passed.
+fn timeout_until(deadline: Instant) -> UsbResult<Duration> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(Error::TimedOut);
+    }
+
+    // Some backends treat timeouts that round down to zero as "wait forever"; so we'll never
+    // hand them anything shorter than a millisecond.
+    Ok(remaining.max(Duration::from_millis(1)))
+}
+
+/// Repeatedly evaluates the provided check until it produces a value, or the deadline passes.
+pub(crate) fn poll_until<T>(
+    deadline: Option<Instant>,
+    mut check: impl FnMut() -> UsbResult<Option<T>>,
+) -> UsbResult<T> {
+    loop {
+        if let Some(value) = check()? {
+            return Ok(value);
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(Error::TimedOut);
+        }
+
+        std::thread::sleep(DEVICE_POLL_INTERVAL);
+    }
+}
+
+/// Counts a device's asynchronous transfers that haven't yet completed.
+#[derive(Debug, Default)]
+struct TransferTracker {
+    /// The number of outstanding transfers.
+    outstanding: Mutex<usize>,
+
+    /// Signaled whenever the last outstanding transfer completes.
+    idle: Condvar,
+}
+
+impl TransferTracker {
+    /// Counts a new transfer as outstanding, until the returned guard is dropped.
+    #[cfg(any(feature = "async", feature = "callbacks"))]
+    fn begin(self: &Arc<Self>) -> TransferGuard {
+        *self.outstanding.lock().unwrap() += 1;
+        TransferGuard(Arc::clone(self))
+    }
+
+    /// Returns the number of outstanding transfers.
+    fn outstanding(&self) -> usize {
+        *self.outstanding.lock().unwrap()
+    }
+
+    /// Waits for every outstanding transfer to complete. Returns false if they didn't in time.
+    fn wait_for_idle(&self, timeout: Duration) -> bool {
+        let outstanding = self.outstanding.lock().unwrap();
+        let (_outstanding, result) = self
+            .idle
+            .wait_timeout_while(outstanding, timeout, |outstanding| *outstanding > 0)
+            .unwrap();
+
+        !result.timed_out()
+    }
+}
+
+/// Marks a transfer as outstanding, for as long as it lives.
+#[cfg(any(feature = "async", feature = "callbacks"))]
+struct TransferGuard(Arc<TransferTracker>);
+
+#[cfg(any(feature = "async", feature = "callbacks"))]
+impl Drop for TransferGuard {
+    fn drop(&mut self) {
+        let mut outstanding = self.0.outstanding.lock().unwrap();
+        *outstanding -= 1;
+
+        if *outstanding == 0 {
+            self.0.idle.notify_all();
+        }
+    }
+}
+
+/// A claim on an interface made with [Device::claim_interface_detached].
+///
+/// Releases the interface when dropped; re-attaching its kernel driver, if we detached one.
+#[derive(Debug)]
+pub struct InterfaceClaim<'a> {
+    /// The device whose interface we've claimed.
+    device: &'a mut Device,
+
+    /// The interface we've claimed.
+    interface_number: u8,
+
+    /// True iff we detached a kernel driver to make our claim.
+    detached: bool,
+}
+
+impl InterfaceClaim<'_> {
+    /// Returns the number of the claimed interface.
+    pub fn interface_number(&self) -> u8 {
+        self.interface_number
+    }
+}
+
+impl std::ops::Deref for InterfaceClaim<'_> {
+    type Target = Device;
+
+    fn deref(&self) -> &Device {
+        self.device
+    }
+}
+
+impl std::ops::DerefMut for InterfaceClaim<'_> {
+    fn deref_mut(&mut self) -> &mut Device {
+        self.device
+    }
+}
+
+impl Drop for InterfaceClaim<'_> {
+    fn drop(&mut self) {
+        // If the device is gone, there's no one left to give the interface back to.
+        if !self.device.is_connected() {
+            return;
+        }
+
+        if let Err(error) = self.device.unclaim_interface(self.interface_number) {
+            warn!(
+                "failed to release interface {}: {error}",
+                self.interface_number
+            );
+        }
+
+        if self.detached {
+            if let Err(error) = self.device.attach_kernel_driver(self.interface_number) {
+                warn!(
+                    "failed to re-attach kernel driver to interface {}: {error}",
+                    self.interface_number
+                );
+            }
+        }
+    }
+}
+
+/// Stands in for a device's backend handle once it's been closed, e.g. while
+/// [Device::reset_and_reopen] waits for the device to come back; everything fails with
+/// [Error::Disconnected].
+#[derive(Debug)]
+struct DetachedDevice;
+
+impl BackendDevice for DetachedDevice {
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn is_disconnected(&self) -> bool {
+        true
+    }
+}
+
+impl BackendDeviceOps for DetachedDevice {
+    fn release_kernel_driver(&mut self, _interface: u8) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn claim_interface(&mut self, _interface: u8) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn unclaim_interface(&mut self, _interface: u8) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn active_configuration(&self) -> UsbResult<u8> {
+        Err(Error::Disconnected)
+    }
+
+    fn set_active_configuration(&mut self, _configuration_value: u8) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn reset_device(&self) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn clear_stall(&self, _endpoint_address: u8) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn set_alternate_setting(&mut self, _interface: u8, _setting: u8) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn current_bus_frame(&self) -> UsbResult<(u64, SystemTime)> {
+        Err(Error::Disconnected)
+    }
+
+    fn control_read(
+        &self,
+        _request_type: u8,
+        _request_number: u8,
+        _value: u16,
+        _index: u16,
+        _target: &mut [u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        Err(Error::Disconnected)
+    }
+
+    fn control_read_nonblocking(
+        &self,
+        _request_type: u8,
+        _request_number: u8,
+        _value: u16,
+        _index: u16,
+        _target: ReadBuffer,
+        _callback: TransferCallback,
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn control_write(
+        &self,
+        _request_type: u8,
+        _request_number: u8,
+        _value: u16,
+        _index: u16,
+        _data: &[u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn control_write_nonblocking(
+        &self,
+        _request_type: u8,
+        _request_number: u8,
+        _value: u16,
+        _index: u16,
+        _data: WriteBuffer,
+        _callback: TransferCallback,
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn read(
+        &self,
+        _endpoint: u8,
+        _buffer: &mut [u8],
+        _timeout: Option<Duration>,
+    ) -> UsbResult<usize> {
+        Err(Error::Disconnected)
+    }
+
+    fn write(&self, _endpoint: u8, _data: &[u8], _timeout: Option<Duration>) -> UsbResult<usize> {
+        Err(Error::Disconnected)
+    }
+
+    fn read_nonblocking(
+        &self,
+        _endpoint: u8,
+        _buffer: ReadBuffer,
+        _callback: TransferCallback,
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+
+    fn write_nonblocking(
+        &self,
+        _endpoint: u8,
+        _data: WriteBuffer,
+        _callback: TransferCallback,
+        _timeout: Option<Duration>,
+    ) -> UsbResult<()> {
+        Err(Error::Disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the information a backend might report for a CDC-ACM serial adapter; whose
+    /// interfaces are reported only if `interfaces_reported` is set.
+    fn serial_adapter(interfaces_reported: bool) -> DeviceInformation {
+        let mut information = DeviceInformation::new(0x1209, 0x0001, None, None, None);
+        information.set_device_class_codes(0xef, 0x02, 0x01);
+
+        if interfaces_reported {
+            information.set_interface_classes(Some(vec![
+                InterfaceClass {
+                    class: 0x02,
+                    subclass: 0x02,
+                    protocol: 0x01,
+                },
+                InterfaceClass {
+                    class: 0x0a,
+                    subclass: 0x00,
+                    protocol: 0x00,
+                },
+            ]));
+        }
+        information
+    }
+
+    #[test]
+    fn selectors_match_any_interface() {
+        let device = serial_adapter(true);
+
+        let data_interface = DeviceSelector {
+            interface_class: Some(0x0a),
+            ..Default::default()
+        };
+        let acm_interface = DeviceSelector {
+            interface_class: Some(0x02),
+            interface_subclass: Some(0x02),
+            interface_protocol: Some(0x01),
+            ..Default::default()
+        };
+        let hid_interface = DeviceSelector {
+            interface_class: Some(0x03),
+            ..Default::default()
+        };
+
+        assert!(data_interface.matches(&device));
+        assert!(acm_interface.matches(&device));
+        assert!(!hid_interface.matches(&device));
+    }
+
+    #[test]
+    fn interface_criteria_must_match_a_single_interface() {
+        // Each of these codes belongs to one of the device's interfaces; but not the same one.
+        let mixed = DeviceSelector {
+            interface_class: Some(0x0a),
+            interface_subclass: Some(0x02),
+            ..Default::default()
+        };
+        assert!(!mixed.matches(&serial_adapter(true)));
+    }
+
+    #[test]
+    fn unreported_interfaces_never_match() {
+        let device = serial_adapter(false);
+
+        let data_interface = DeviceSelector {
+            interface_class: Some(0x0a),
+            ..Default::default()
+        };
+        assert!(!data_interface.matches(&device));
+
+        // Selectors that don't care about interfaces still match it, though.
+        let by_class = DeviceSelector {
+            vendor_id: Some(0x1209),
+            device_class: Some(0xef),
+            ..Default::default()
+        };
+        assert!(by_class.matches(&device));
+    }
+
+    /// Builds the information for a device plugged in at the given bus and ports; or somewhere
+    /// the backend can't tell, if `bus` is None.
+    fn device_at(bus: Option<u8>, ports: &[u8]) -> DeviceInformation {
+        let mut information = DeviceInformation::new(0x1209, 0x0001, None, None, None);
+        information.set_port_path(bus.map(|bus| PortPath::new(bus, ports.to_vec())));
+        information
+    }
+
+    #[test]
+    fn selectors_match_by_port() {
+        let selector = DeviceSelector::at_port(&PortPath::new(1, vec![2, 3]));
+
+        assert!(selector.matches(&device_at(Some(1), &[2, 3])));
+        assert!(!selector.matches(&device_at(Some(2), &[2, 3])));
+        assert!(!selector.matches(&device_at(Some(1), &[2])));
+        assert!(!selector.matches(&device_at(Some(1), &[2, 3, 1])));
+    }
+
+    #[test]
+    fn selectors_match_by_bus_alone() {
+        let selector = DeviceSelector {
+            bus: Some(1),
+            ..Default::default()
+        };
+
+        assert!(selector.matches(&device_at(Some(1), &[4])));
+        assert!(!selector.matches(&device_at(Some(2), &[4])));
+    }
+
+    #[test]
+    fn unknown_ports_never_match() {
+        let device = device_at(None, &[]);
+
+        assert!(!DeviceSelector::at_port(&PortPath::new(1, vec![2])).matches(&device));
+        assert!(DeviceSelector::default().matches(&device));
     }
 }
@@ -0,0 +1,354 @@
+//! Tools for producing human-readable reports about devices; e.g. for bug reports.
+
+use std::fmt::Write;
+
+use crate::{
+    descriptors::{
+        ConfigurationDescriptor, DeviceDescriptor, EndpointDescriptor, InterfaceDescriptor,
+        TransferType,
+    },
+    device::{Device, DeviceInformation, DeviceSelector},
+    request::Direction,
+    Host, UsbResult,
+};
+
+/// Reads every descriptor from a device, and renders it into an `lsusb -v` style report.
+///
+/// Strings are fetched on a best-effort basis; if a device won't give us one, we'll just
+/// leave it out of the report rather than failing the whole thing.
+pub fn describe_device(device: &mut Device) -> UsbResult<String> {
+    let device_descriptor = device.read_device_descriptor()?;
+
+    let mut configurations = vec![];
+    for index in 0..device_descriptor.num_configurations {
+        configurations.push(device.read_configuration_descriptor(index)?);
+    }
+
+    Ok(render_descriptors(
+        &device_descriptor,
+        &configurations,
+        |index| device.read_string_descriptor(index).ok(),
+    ))
+}
+
+/// Renders an already-read descriptor tree into an `lsusb -v` style report.
+///
+/// The [strings] function is used to look up string descriptors by index; it can just return
+/// None if the strings aren't available (e.g. when rendering descriptors from a bug report).
+pub fn render_descriptors(
+    device: &DeviceDescriptor,
+    configurations: &[ConfigurationDescriptor],
+    mut strings: impl FnMut(u8) -> Option<String>,
+) -> String {
+    let mut report = Report::default();
+
+    report.line(0, "Device Descriptor:");
+    report.field(2, "bLength", DeviceDescriptor::LENGTH);
+    report.field(2, "bDescriptorType", 1);
+    report.field(2, "bcdUSB", bcd(device.usb_version));
+    report.field(2, "bDeviceClass", device.device_class);
+    report.field(2, "bDeviceSubClass", device.device_subclass);
+    report.field(2, "bDeviceProtocol", device.device_protocol);
+    report.field(2, "bMaxPacketSize0", device.max_packet_size_ep0);
+    report.field(2, "idVendor", format!("0x{:04x}", device.vendor_id));
+    report.field(2, "idProduct", format!("0x{:04x}", device.product_id));
+    report.field(2, "bcdDevice", bcd(device.device_version));
+    report.string(
+        2,
+        "iManufacturer",
+        device.manufacturer_string_index,
+        &mut strings,
+    );
+    report.string(2, "iProduct", device.product_string_index, &mut strings);
+    report.string(2, "iSerial", device.serial_string_index, &mut strings);
+    report.field(2, "bNumConfigurations", device.num_configurations);
+
+    for configuration in configurations {
+        render_configuration(&mut report, configuration, &mut strings);
+    }
+
+    report.text
+}
+
+/// Renders a configuration descriptor, and everything beneath it.
+fn render_configuration(
+    report: &mut Report,
+    configuration: &ConfigurationDescriptor,
+    strings: &mut impl FnMut(u8) -> Option<String>,
+) {
+    report.line(2, "Configuration Descriptor:");
+    report.field(4, "bLength", ConfigurationDescriptor::LENGTH);
+    report.field(4, "bDescriptorType", 2);
+    report.field(
+        4,
+        "wTotalLength",
+        format!("0x{:04x}", configuration.total_length),
+    );
+    report.field(4, "bNumInterfaces", configuration.num_interfaces);
+    report.field(4, "bConfigurationValue", configuration.configuration_value);
+    report.string(
+        4,
+        "iConfiguration",
+        configuration.configuration_string_index,
+        strings,
+    );
+    report.field(
+        4,
+        "bmAttributes",
+        format!("0x{:02x}", configuration.attributes),
+    );
+    if (configuration.attributes & 0x40) != 0 {
+        report.line(6, "Self Powered");
+    }
+    if (configuration.attributes & 0x20) != 0 {
+        report.line(6, "Remote Wakeup");
+    }
+    report.field(
+        4,
+        "MaxPower",
+        format!("{}mA", configuration.max_power as u32 * 2),
+    );
+    report.extra(4, &configuration.extra);
+
+    for interface in &configuration.interfaces {
+        render_interface(report, interface, strings);
+    }
+}
+
+/// Renders an interface descriptor, and everything beneath it.
+fn render_interface(
+    report: &mut Report,
+    interface: &InterfaceDescriptor,
+    strings: &mut impl FnMut(u8) -> Option<String>,
+) {
+    report.line(4, "Interface Descriptor:");
+    report.field(6, "bLength", InterfaceDescriptor::LENGTH);
+    report.field(6, "bDescriptorType", 4);
+    report.field(6, "bInterfaceNumber", interface.interface_number);
+    report.field(6, "bAlternateSetting", interface.alternate_setting);
+    report.field(6, "bNumEndpoints", interface.num_endpoints);
+    report.field(6, "bInterfaceClass", interface.interface_class);
+    report.field(6, "bInterfaceSubClass", interface.interface_subclass);
+    report.field(6, "bInterfaceProtocol", interface.interface_protocol);
+    report.string(6, "iInterface", interface.interface_string_index, strings);
+    report.extra(6, &interface.extra);
+
+    for endpoint in &interface.endpoints {
+        render_endpoint(report, endpoint);
+    }
+}
+
+/// Renders an endpoint descriptor, and any class-specific descriptors that follow it.
+fn render_endpoint(report: &mut Report, endpoint: &EndpointDescriptor) {
+    let direction = match endpoint.direction() {
+        Direction::In => "IN",
+        Direction::Out => "OUT",
+    };
+    let transfer_type = match endpoint.transfer_type() {
+        TransferType::Control => "Control",
+        TransferType::Isochronous => "Isochronous",
+        TransferType::Bulk => "Bulk",
+        TransferType::Interrupt => "Interrupt",
+    };
+    let transactions = 1 + ((endpoint.max_packet_size >> 11) & 0b11);
+
+    report.line(6, "Endpoint Descriptor:");
+    report.field(8, "bLength", EndpointDescriptor::LENGTH);
+    report.field(8, "bDescriptorType", 5);
+    report.field(
+        8,
+        "bEndpointAddress",
+        format!(
+            "0x{:02x}  EP {} {}",
+            endpoint.address,
+            endpoint.number(),
+            direction
+        ),
+    );
+    report.field(8, "bmAttributes", endpoint.attributes);
+    report.line(10, &format!("Transfer Type            {transfer_type}"));
+    report.field(
+        8,
+        "wMaxPacketSize",
+        format!(
+            "0x{:04x}  {}x {} bytes",
+            endpoint.max_packet_size,
+            transactions,
+            endpoint.packet_size()
+        ),
+    );
+    report.field(8, "bInterval", endpoint.interval);
+    report.extra(8, &endpoint.extra);
+}
+
+/// A likely reason we aren't allowed to access a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessProblem {
+    /// A kernel driver is bound to one of the device's interfaces.
+    KernelDriverBound { interface: u8, driver: String },
+
+    /// We'd need to capture the device from its kernel drivers; but this program lacks the
+    /// entitlement that allows it to do so, and isn't running as root.
+    MissingEntitlement { entitlement: &'static str },
+
+    /// The permissions on the device's node don't allow us to open it.
+    DeviceNodePermissions { path: String },
+}
+
+impl AccessProblem {
+    /// Returns a suggestion for how the user can fix the problem.
+    pub fn remediation(&self) -> String {
+        match self {
+            AccessProblem::KernelDriverBound { interface, .. } => format!(
+                "release the driver before claiming the interface, e.g. with Device::release_kernel_driver({interface})"
+            ),
+            AccessProblem::MissingEntitlement { entitlement } => format!(
+                "sign this program with the `{entitlement}` entitlement, or run it as root"
+            ),
+            AccessProblem::DeviceNodePermissions { path } => format!(
+                "grant your user access to {path} (e.g. by adding yourself to its group), or run as root"
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for AccessProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessProblem::KernelDriverBound { interface, driver } => {
+                write!(f, "interface {interface} is in use by the kernel driver `{driver}`")
+            }
+            AccessProblem::MissingEntitlement { entitlement } => write!(
+                f,
+                "this program isn't allowed to take devices from their kernel drivers (it lacks `{entitlement}`)"
+            ),
+            AccessProblem::DeviceNodePermissions { path } => {
+                write!(f, "you don't have permission to open {path}")
+            }
+        }
+    }
+}
+
+/// A user-presentable explanation of why a device can't be accessed; see [explain_access_error].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessExplanation {
+    /// Everything we found that could be keeping us out; in no particular order.
+    pub problems: Vec<AccessProblem>,
+}
+
+impl AccessExplanation {
+    /// Returns true iff we couldn't find anything that would explain an access error.
+    pub fn is_empty(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl std::fmt::Display for AccessExplanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.problems.is_empty() {
+            return writeln!(
+                f,
+                "couldn't find a specific cause; the device may be in use by another program"
+            );
+        }
+
+        for problem in &self.problems {
+            writeln!(f, "- {problem}")?;
+            writeln!(f, "  to fix: {}", problem.remediation())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Inspects the state of the system to figure out why a device can't be accessed; e.g. after
+/// an operation fails with [crate::Error::PermissionDenied].
+///
+/// Convenience form that implicitly constructs (and destroys) a Host object.
+pub fn explain_access_error(information: &DeviceInformation) -> UsbResult<AccessExplanation> {
+    Host::new()?.explain_access_error(information)
+}
+
+/// Generates the udev rule that grants unprivileged users access to the devices matching
+/// a selector, on Linux.
+///
+/// The rule uses `TAG+="uaccess"`, which gives access to whoever is logged in at the machine.
+/// It should be saved into a file in `/etc/udev/rules.d/` (e.g. `70-my-device.rules`), after
+/// which `udevadm control --reload-rules && udevadm trigger` will apply it.
+pub fn generate_udev_rule(selector: &DeviceSelector) -> String {
+    let mut rule = String::from("SUBSYSTEM==\"usb\"");
+
+    if let Some(vendor_id) = selector.vendor_id {
+        let _ = write!(rule, ", ATTRS{{idVendor}}==\"{vendor_id:04x}\"");
+    }
+    if let Some(product_id) = selector.product_id {
+        let _ = write!(rule, ", ATTRS{{idProduct}}==\"{product_id:04x}\"");
+    }
+    if let Some(serial) = &selector.serial {
+        // udev strings can't contain quotes; but serial numbers shouldn't, anyway.
+        let _ = write!(rule, ", ATTRS{{serial}}==\"{}\"", serial.replace('"', ""));
+    }
+
+    rule.push_str(", MODE=\"0660\", TAG+=\"uaccess\"\n");
+    rule
+}
+
+/// Formats a BCD version number (e.g. bcdUSB) the way humans expect to read it.
+fn bcd(value: u16) -> String {
+    format!("{:x}.{:02x}", value >> 8, value & 0xFF)
+}
+
+/// Accumulates the text of a report, one line at a time.
+#[derive(Default)]
+struct Report {
+    text: String,
+}
+
+impl Report {
+    /// Adds a line of free-form text, at the given indentation.
+    fn line(&mut self, indent: usize, text: &str) {
+        let _ = writeln!(self.text, "{:indent$}{text}", "");
+    }
+
+    /// Adds a field, with its name and value lined up in columns.
+    fn field(&mut self, indent: usize, name: &str, value: impl std::fmt::Display) {
+        let _ = writeln!(self.text, "{:indent$}{name:<20}{value:>6}", "");
+    }
+
+    /// Adds a string index field, followed by the string it refers to (if we can get it).
+    fn string(
+        &mut self,
+        indent: usize,
+        name: &str,
+        index: u8,
+        strings: &mut impl FnMut(u8) -> Option<String>,
+    ) {
+        let string = match index {
+            0 => None,
+            index => strings(index),
+        };
+
+        match string {
+            Some(string) => self.field(indent, name, format!("{index} {string}")),
+            None => self.field(indent, name, index),
+        }
+    }
+
+    /// Adds a hex dump of each of the non-standard descriptors we don't know how to decode.
+    fn extra(&mut self, indent: usize, descriptors: &[Vec<u8>]) {
+        for descriptor in descriptors {
+            let bytes: Vec<String> = descriptor.iter().map(|b| format!("{b:02x}")).collect();
+
+            self.line(
+                indent,
+                &format!(
+                    "Unknown Descriptor (type 0x{:02x}):",
+                    descriptor.get(1).copied().unwrap_or(0)
+                ),
+            );
+            for row in bytes.chunks(16) {
+                self.line(indent + 2, &row.join(" "));
+            }
+        }
+    }
+}
@@ -3,7 +3,7 @@
 /// Alias to simplify implementing the results of USRs functions.
 pub type UsbResult<T> = Result<T, Error>;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     /// An operation isn't supported; e.g. by this backend or device.
     Unsupported,
@@ -14,6 +14,9 @@ pub enum Error {
     /// Error for when a device is not yet, or no longer, open.
     DeviceNotOpen,
 
+    /// The device was unplugged (or otherwise went away) while we were using it.
+    Disconnected,
+
     /// Error representing a device that has no real USB representation;
     /// generated if we try to open e.g. a billboard device that the OS won't talk to.
     DeviceNotReal,
@@ -33,6 +36,14 @@ pub enum Error {
     /// An operation exceeded the timeout interval.
     TimedOut,
 
+    /// A multi-part transfer timed out after moving only some of its data;
+    /// with the number of bytes that did make it.
+    PartialTransfer(usize),
+
+    /// A read that needed a fixed amount of data got less; with the number of bytes that
+    /// did arrive.
+    ShortRead(usize),
+
     /// An argument was provided with an inalid/non-allowed value.
     InvalidArgument,
 
@@ -42,9 +53,25 @@ pub enum Error {
     /// The response wouldn't fit in the provided buffer.
     Overrun,
 
+    /// The device sent more data than it was allowed to ("babble"); e.g. more than a packet's
+    /// worth, or more than was left in the buffer.
+    Babble,
+
+    /// A transaction was corrupted on the bus; e.g. by a CRC, bit-stuffing, or PID error.
+    ProtocolError,
+
+    /// The host controller couldn't move a transfer's data to or from memory quickly enough.
+    Underrun,
+
+    /// The device didn't respond to the host at all.
+    NotResponding,
+
     /// The OS won't let us touch this resource.
     PermissionDenied,
 
+    /// A descriptor didn't parse; e.g. because its length or type fields were nonsensical.
+    MalformedDescriptor,
+
     /// An unspecified error, with associated OS error number.
     OsError(i64),
 
@@ -60,6 +87,7 @@ impl std::fmt::Display for Error {
             Unsupported => write!(f, "operation is not supported")?,
             DeviceNotFound => write!(f, "no device found")?,
             DeviceNotOpen => write!(f, "tried to perform an operation on a non-open device")?,
+            Disconnected => write!(f, "device was disconnected")?,
             DeviceNotReal => write!(
                 f,
                 "tried to work with a device that isn't real to your OS (like a billboard class device)"
@@ -69,9 +97,16 @@ impl std::fmt::Display for Error {
             InvalidEndpoint => write!(f, "invalid endpoint")?,
             InvalidInterface => write!(f, "invalid interface")?,
             TimedOut => write!(f, "timed out")?,
+            PartialTransfer(length) => write!(f, "timed out after transferring {length} bytes")?,
+            ShortRead(length) => write!(f, "expected more data, but got only {length} bytes")?,
             Overrun => write!(f, "buffer overrun")?,
+            Babble => write!(f, "device sent more data than expected (babble)")?,
+            ProtocolError => write!(f, "USB protocol error (e.g. CRC or bit-stuffing)")?,
+            Underrun => write!(f, "host controller buffer underrun")?,
+            NotResponding => write!(f, "device not responding")?,
             InvalidArgument => write!(f, "invalid argument")?,
             PermissionDenied => write!(f, "permission denied")?,
+            MalformedDescriptor => write!(f, "malformed descriptor")?,
             Aborted => write!(f, "aborted")?,
             OsError(errno) => write!(f, "operating system IO error {errno}")?,
             UnspecifiedOsError => write!(
@@ -85,3 +120,162 @@ impl std::fmt::Display for Error {
 }
 
 impl std::error::Error for Error {}
+
+impl Error {
+    /// Returns true iff this error is likely to go away on its own; i.e. retrying the same
+    /// operation, unchanged, has a reasonable chance of succeeding.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Error::TimedOut
+                | Error::PartialTransfer(_)
+                | Error::Aborted
+                | Error::ProtocolError
+                | Error::Underrun
+        )
+    }
+
+    /// Returns true iff this error means the device is gone; so the way forward is to find and
+    /// open it again, rather than to retry.
+    pub fn is_disconnect(&self) -> bool {
+        matches!(self, Error::Disconnected | Error::NotResponding)
+    }
+
+    /// Returns true iff this error means we're not allowed to use the device; either because
+    /// of OS permissions, or because someone else has it.
+    pub fn is_permission(&self) -> bool {
+        matches!(self, Error::PermissionDenied | Error::DeviceReserved)
+    }
+
+    /// Returns the raw OS error code behind this error, if it's one we couldn't translate;
+    /// an errno value on Linux/Android and the BSDs, or an IOReturn on macOS.
+    pub fn os_error_code(&self) -> Option<i64> {
+        match self {
+            Error::OsError(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Returns the OS's own description of the error behind this one, if there is one.
+    pub fn os_description(&self) -> Option<String> {
+        self.os_error_code().map(describe_os_error)
+    }
+}
+
+/// Converts an IO error (e.g. from working with a file) into our error type.
+pub(crate) fn io_error(error: std::io::Error) -> Error {
+    match error.raw_os_error() {
+        Some(errno) => Error::OsError(errno as i64),
+        None => Error::UnspecifiedOsError,
+    }
+}
+
+/// Asks the OS to describe an IOReturn code.
+#[cfg(target_os = "macos")]
+fn describe_os_error(code: i64) -> String {
+    extern "C" {
+        fn mach_error_string(error_value: std::os::raw::c_int) -> *const std::os::raw::c_char;
+    }
+
+    // SAFETY: mach_error_string accepts any value, and always returns a static string.
+    let description = unsafe { std::ffi::CStr::from_ptr(mach_error_string(code as i32)) };
+    description.to_string_lossy().into_owned()
+}
+
+/// Asks the OS to describe an errno value.
+#[cfg(not(target_os = "macos"))]
+fn describe_os_error(code: i64) -> String {
+    std::io::Error::from_raw_os_error(code as i32).to_string()
+}
+
+/// An error, along with a description of the transfer that produced it.
+/// See [crate::device::Device::last_error].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextError {
+    /// The error itself.
+    pub error: Error,
+
+    /// The address of the endpoint the transfer targeted; 0 for control requests.
+    pub endpoint: u8,
+
+    /// For control requests, the request type (bmRequestType) and number (bRequest).
+    pub request: Option<(u8, u8)>,
+
+    /// The amount of data the transfer asked to move.
+    pub length: usize,
+}
+
+impl ContextError {
+    /// Describes an error from a bulk or interrupt transfer.
+    pub fn for_transfer(error: Error, endpoint: u8, length: usize) -> Self {
+        Self {
+            error,
+            endpoint,
+            request: None,
+            length,
+        }
+    }
+
+    /// Describes an error from a control request.
+    pub fn for_control(error: Error, request_type: u8, request_number: u8, length: usize) -> Self {
+        Self {
+            error,
+            endpoint: 0,
+            request: Some((request_type, request_number)),
+            length,
+        }
+    }
+}
+
+impl std::fmt::Display for ContextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.request {
+            Some((request_type, request_number)) => write!(
+                f,
+                "{} (control request {request_type:#04x}/{request_number:#04x}, {} bytes)",
+                self.error, self.length
+            ),
+            None => write!(
+                f,
+                "{} (endpoint {:#04x}, {} bytes)",
+                self.error, self.endpoint, self.length
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ContextError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ContextError> for Error {
+    fn from(error: ContextError) -> Self {
+        error.error
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match error {
+            Error::Unsupported => ErrorKind::Unsupported,
+            Error::DeviceNotFound => ErrorKind::NotFound,
+            Error::DeviceNotOpen | Error::Disconnected => ErrorKind::NotConnected,
+            Error::TimedOut | Error::PartialTransfer(_) => ErrorKind::TimedOut,
+            Error::InvalidArgument | Error::InvalidEndpoint | Error::InvalidInterface => {
+                ErrorKind::InvalidInput
+            }
+            Error::Aborted => ErrorKind::Interrupted,
+            Error::PermissionDenied => ErrorKind::PermissionDenied,
+            Error::DeviceReserved => ErrorKind::ResourceBusy,
+            Error::MalformedDescriptor => ErrorKind::InvalidData,
+            Error::OsError(errno) => return std::io::Error::from_raw_os_error(errno as i32),
+            _ => ErrorKind::Other,
+        };
+
+        std::io::Error::new(kind, error)
+    }
+}
@@ -5,10 +5,16 @@ use std::{
     pin::Pin,
     sync::{Arc, Mutex},
     task::Context,
-    task::{Poll, Waker},
+    task::{Poll, Wake, Waker},
+    thread::Thread,
 };
 
-use crate::UsbResult;
+use parking_lot::RwLock;
+
+use crate::{backend::DisconnectSignal, ReadBuffer, UsbResult, WriteBuffer};
+
+/// Converts the result reported by a completed transfer into a future's output.
+type Finisher<T> = Box<dyn FnOnce(UsbResult<usize>) -> UsbResult<T> + Send + Sync>;
 
 // Shared state between a UsbFuture and the backend performing its action.
 pub(crate) struct UsbFutureState {
@@ -47,17 +53,85 @@ impl UsbFutureState {
     }
 }
 
+/// The result of a completed asynchronous read; which hands back the buffer that was read into.
+pub struct ReadCompletion {
+    /// The buffer the data was read into.
+    pub buffer: ReadBuffer,
+
+    /// The number of bytes actually read; which are at the start of the buffer.
+    pub length: usize,
+}
+
+impl ReadCompletion {
+    /// Returns a copy of the data that was read.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut buffer = self.buffer.write();
+        buffer.as_mut()[..self.length].to_vec()
+    }
+}
+
+/// The result of a completed owned read (e.g. [crate::device::Device::read_owned_async]);
+/// which hands back the buffer the caller gave up when submitting it, whether or not the read
+/// succeeded.
+#[derive(Debug)]
+pub struct OwnedReadCompletion {
+    /// The buffer the data was read into.
+    pub buffer: Vec<u8>,
+
+    /// The result of the read: the number of bytes actually read, which are at the start of
+    /// the buffer; or the reason the read failed.
+    pub result: UsbResult<usize>,
+}
+
+impl OwnedReadCompletion {
+    /// Takes the buffer back out of the lock it was read through, once the transfer's complete.
+    pub(crate) fn take_from(buffer: &RwLock<Vec<u8>>, result: UsbResult<usize>) -> Self {
+        let buffer = std::mem::take(&mut *buffer.write());
+        Self { buffer, result }
+    }
+
+    /// Returns the data that was read; or the reason the read failed.
+    pub fn data(&self) -> UsbResult<&[u8]> {
+        let length = self.result.clone()?;
+        Ok(&self.buffer[..length])
+    }
+
+    /// Returns the data that was read, giving up the rest of the buffer; or the reason the
+    /// read failed, giving up the buffer entirely.
+    pub fn into_data(mut self) -> UsbResult<Vec<u8>> {
+        self.buffer.truncate(self.result?);
+        Ok(self.buffer)
+    }
+}
+
+/// The result of a completed asynchronous write; which hands back the data that was written.
+pub struct WriteCompletion {
+    /// The data that was written.
+    pub buffer: WriteBuffer,
+
+    /// The number of bytes actually written.
+    pub length: usize,
+}
+
 /// Core asynchronous Future that waits on the results of USB operations.
-pub struct UsbFuture {
+///
+/// The output depends on the operation: reads produce a [ReadCompletion], and writes a
+/// [WriteCompletion].
+pub struct UsbFuture<T> {
     /// The state shared between the future and the backend.
     state: Arc<Mutex<UsbFutureState>>,
+
+    /// Builds our output from the backend's result; taken once we're complete.
+    finish: Option<Finisher<T>>,
 }
 
-impl UsbFuture {
-    /// Creates a new UsbFuture, which waits on completion of a USB event.
-    pub(crate) fn new() -> UsbFuture {
+impl<T> UsbFuture<T> {
+    /// Creates a new UsbFuture, which waits on completion of a USB event, and then builds its
+    /// output from the length the event reports.
+    pub(crate) fn new(finish: impl FnOnce(usize) -> T + Send + Sync + 'static) -> UsbFuture<T> {
         UsbFuture {
             state: Arc::new(Mutex::new(UsbFutureState::new())),
+            finish: Some(Box::new(move |result| result.map(finish))),
         }
     }
 
@@ -67,11 +141,64 @@ impl UsbFuture {
     }
 }
 
-impl Future for UsbFuture {
-    type Output = UsbResult<usize>;
+impl<T: 'static> UsbFuture<T> {
+    /// Converts this future into one that waits on the same event, but always produces an
+    /// output from it; which gets to see whether the event succeeded.
+    pub(crate) fn map_result<U>(
+        mut self,
+        f: impl FnOnce(UsbResult<T>) -> U + Send + Sync + 'static,
+    ) -> UsbFuture<U> {
+        let finish = self
+            .finish
+            .take()
+            .expect("future was mapped after completion");
+
+        UsbFuture {
+            state: self.state,
+            finish: Some(Box::new(move |result| Ok(f(finish(result))))),
+        }
+    }
+}
+
+impl UsbFuture<ReadCompletion> {
+    /// Creates a future for a read into the provided buffer.
+    pub(crate) fn for_read(buffer: ReadBuffer) -> Self {
+        Self::new(move |length| ReadCompletion { buffer, length })
+    }
+}
+
+impl UsbFuture<OwnedReadCompletion> {
+    /// Creates a future for a read into a shared vector, from the read's submission; which
+    /// hands the vector back however the read ends, including if it was never submitted.
+    pub(crate) fn for_owned_read(
+        buffer: Arc<RwLock<Vec<u8>>>,
+        submission: UsbResult<UsbFuture<ReadCompletion>>,
+    ) -> Self {
+        let future = submission.unwrap_or_else(|error| {
+            let future = UsbFuture::for_read(Arc::clone(&buffer) as ReadBuffer);
+            future.state.lock().unwrap().complete(Err(error));
+            future
+        });
+
+        future.map_result(move |completion| {
+            OwnedReadCompletion::take_from(&buffer, completion.map(|completion| completion.length))
+        })
+    }
+}
+
+impl UsbFuture<WriteCompletion> {
+    /// Creates a future for a write of the provided data.
+    pub(crate) fn for_write(buffer: WriteBuffer) -> Self {
+        Self::new(move |length| WriteCompletion { buffer, length })
+    }
+}
+
+impl<T> Future for UsbFuture<T> {
+    type Output = UsbResult<T>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let mut state = self.state.lock().unwrap();
+        let this = self.get_mut();
+        let mut state = this.state.lock().unwrap();
 
         // If our transaction is still pending, we'll need to capture the waker,
         // and indicate that we're not done.
@@ -84,14 +211,90 @@ impl Future for UsbFuture {
         }
         // Otherwise, return our result, since we're done.
         else {
-            Poll::Ready(
-                state
-                    .result
-                    .take()
-                    .expect("future was complete without result"),
-            )
+            let result = state
+                .result
+                .take()
+                .expect("future was complete without result");
+            let finish = this
+                .finish
+                .take()
+                .expect("future was polled after completion");
+
+            Poll::Ready(finish(result))
+        }
+    }
+}
+
+/// Future that completes once a device has gone away; see [crate::device::Device::disconnected].
+#[derive(Debug)]
+pub struct DisconnectFuture {
+    /// The signal of the device we're waiting on.
+    signal: Arc<DisconnectSignal>,
+
+    /// The ID our wakers are registered with the signal under.
+    id: u64,
+}
+
+impl DisconnectFuture {
+    /// Creates a future that waits on the provided signal.
+    pub(crate) fn new(signal: Arc<DisconnectSignal>) -> Self {
+        let id = signal.waiter_id();
+        Self { signal, id }
+    }
+}
+
+impl Drop for DisconnectFuture {
+    fn drop(&mut self) {
+        self.signal.deregister_waker(self.id);
+    }
+}
+
+impl Future for DisconnectFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.signal.register_waker(self.id, cx.waker()) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Wakes a thread parked in [block_on].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs a future to completion on the current thread; for synchronous APIs that are built on
+/// top of our asynchronous ones.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
         }
+        std::thread::park();
     }
 }
 
-unsafe impl Send for UsbFuture {}
+// UsbFutures are handed to arbitrary executors, including multi-threaded ones; so they need
+// to be Send and 'static. Their state is a plain Arc<Mutex<..>> of Send types, and the
+// callbacks that complete them are Send, so they are, without any unsafe promises; this makes
+// sure that stays true. See tests/executors.rs for the same, at runtime.
+const _: () = {
+    const fn assert_send_static<T: Send + Sync + 'static>() {}
+    assert_send_static::<UsbFuture<ReadCompletion>>();
+    assert_send_static::<UsbFuture<WriteCompletion>>();
+    assert_send_static::<UsbFuture<OwnedReadCompletion>>();
+    assert_send_static::<UsbFuture<crate::iso::IsoReadCompletion>>();
+    assert_send_static::<UsbFuture<crate::iso::IsoWriteCompletion>>();
+    assert_send_static::<DisconnectFuture>();
+};
@@ -0,0 +1,295 @@
+//! Device-side USB on Linux: presents a USB function through FunctionFS, described with the
+//! same descriptor types we parse on the host side. With a UDC that loops back to the same
+//! machine (e.g. dummy_hcd), usrs can play both host and device; for self-tests, or proxies.
+//!
+//! FunctionFS only provides a function's interfaces. The device around it -- its device
+//! descriptor, configurations, and strings -- is composed with the kernel's gadget configfs,
+//! which needs to be set up separately: create an `ffs.<name>` function, mount it
+//! (`mount -t functionfs <name> <mount point>`), and then open a [FunctionFsGadget] on the
+//! mount point before binding the gadget to a UDC.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    descriptors::InterfaceDescriptor,
+    error::{io_error, Error, UsbResult},
+    request::{Direction, SetupPacket},
+};
+
+/// Magic number that starts a FunctionFS descriptor blob (FUNCTIONFS_DESCRIPTORS_MAGIC_V2).
+const DESCRIPTORS_MAGIC_V2: u32 = 3;
+
+/// Magic number that starts a FunctionFS string blob (FUNCTIONFS_STRINGS_MAGIC).
+const STRINGS_MAGIC: u32 = 2;
+
+/// Descriptor blob flags, announcing which speeds we have descriptors for (FUNCTIONFS_HAS_*).
+const HAS_FULL_SPEED_DESCRIPTORS: u32 = 1 << 0;
+const HAS_HIGH_SPEED_DESCRIPTORS: u32 = 1 << 1;
+const HAS_SUPER_SPEED_DESCRIPTORS: u32 = 1 << 2;
+
+/// The language our strings are in; US English.
+const LANGUAGE_ID: u16 = 0x0409;
+
+/// The errno FunctionFS reports once it's stalled a control request on our behalf (EL2HLT).
+const STALLED_ERRNO: i32 = 51;
+
+/// The size of a struct usb_functionfs_event; which is what each read from ep0 returns.
+const EVENT_LENGTH: usize = 12;
+
+/// The interfaces and strings of a function; see [FunctionFsGadget::new].
+///
+/// Each speed gets its own copy of the interfaces, since e.g. bulk packet sizes differ between
+/// speeds; speeds left empty aren't offered. Each list's endpoints must appear in the same
+/// order, since the endpoint files are numbered by that order. SuperSpeed endpoints need their
+/// companion descriptors in [crate::descriptors::EndpointDescriptor::extra].
+#[derive(Debug, Clone, Default)]
+pub struct GadgetFunction {
+    /// The function's interfaces, as described at full speed.
+    pub full_speed: Vec<InterfaceDescriptor>,
+
+    /// The function's interfaces, as described at high speed.
+    pub high_speed: Vec<InterfaceDescriptor>,
+
+    /// The function's interfaces, as described at SuperSpeed.
+    pub super_speed: Vec<InterfaceDescriptor>,
+
+    /// The function's strings; string index N in the descriptors refers to `strings[N - 1]`.
+    pub strings: Vec<String>,
+}
+
+impl GadgetFunction {
+    /// Builds the descriptor blob FunctionFS expects to find written to ep0.
+    fn descriptor_blob(&self) -> Vec<u8> {
+        let speeds = [
+            (HAS_FULL_SPEED_DESCRIPTORS, &self.full_speed),
+            (HAS_HIGH_SPEED_DESCRIPTORS, &self.high_speed),
+            (HAS_SUPER_SPEED_DESCRIPTORS, &self.super_speed),
+        ];
+        let speeds: Vec<_> = speeds
+            .into_iter()
+            .filter(|(_, interfaces)| !interfaces.is_empty())
+            .collect();
+
+        // The header gives our flags, then the number of descriptors for each speed...
+        let flags = speeds.iter().fold(0, |flags, (flag, _)| flags | flag);
+        let mut counts = vec![];
+        let mut descriptors = vec![];
+        for (_, interfaces) in &speeds {
+            let count: usize = interfaces
+                .iter()
+                .map(|interface| 1 + interface.extra.len() + endpoint_descriptor_count(interface))
+                .sum();
+            counts.extend((count as u32).to_le_bytes());
+            descriptors.extend(interfaces.iter().flat_map(InterfaceDescriptor::to_bytes));
+        }
+
+        // ... and the descriptors themselves follow, one speed after another.
+        let length = 12 + counts.len() + descriptors.len();
+        let mut blob = vec![];
+        blob.extend(DESCRIPTORS_MAGIC_V2.to_le_bytes());
+        blob.extend((length as u32).to_le_bytes());
+        blob.extend(flags.to_le_bytes());
+        blob.extend(counts);
+        blob.extend(descriptors);
+        blob
+    }
+
+    /// Builds the string blob FunctionFS expects to find written to ep0, after the descriptors.
+    fn string_blob(&self) -> Vec<u8> {
+        let mut strings = vec![];
+        for string in &self.strings {
+            strings.extend(string.as_bytes());
+            strings.push(0);
+        }
+
+        let language_count: u32 = if self.strings.is_empty() { 0 } else { 1 };
+        let length = 16 + language_count as usize * 2 + strings.len();
+
+        let mut blob = vec![];
+        blob.extend(STRINGS_MAGIC.to_le_bytes());
+        blob.extend((length as u32).to_le_bytes());
+        blob.extend((self.strings.len() as u32).to_le_bytes());
+        blob.extend(language_count.to_le_bytes());
+        if language_count > 0 {
+            blob.extend(LANGUAGE_ID.to_le_bytes());
+            blob.extend(strings);
+        }
+        blob
+    }
+
+    /// Returns the number of endpoints the function has; and so, the number of endpoint files.
+    fn endpoint_count(&self) -> usize {
+        [&self.full_speed, &self.high_speed, &self.super_speed]
+            .into_iter()
+            .find(|interfaces| !interfaces.is_empty())
+            .map_or(0, |interfaces| {
+                interfaces
+                    .iter()
+                    .map(|interface| interface.endpoints.len())
+                    .sum()
+            })
+    }
+}
+
+/// Counts the descriptors an interface's endpoints contribute, including their companions.
+fn endpoint_descriptor_count(interface: &InterfaceDescriptor) -> usize {
+    interface
+        .endpoints
+        .iter()
+        .map(|endpoint| 1 + endpoint.extra.len())
+        .sum()
+}
+
+/// Something that happened to our function; see [FunctionFsGadget::next_event].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GadgetEvent {
+    /// The function was bound to a UDC.
+    Bind,
+
+    /// The function was unbound from its UDC.
+    Unbind,
+
+    /// The host selected a configuration including our function; its endpoints can be used.
+    Enable,
+
+    /// Our function's configuration was deselected, or the device was reset.
+    Disable,
+
+    /// The host sent a control request to our function; which needs a response.
+    Setup(SetupPacket),
+
+    /// The bus was suspended.
+    Suspend,
+
+    /// The bus was resumed.
+    Resume,
+}
+
+/// A USB function presented to a host through FunctionFS.
+///
+/// Endpoints are numbered from 1, in the order their descriptors appear; e.g. endpoint 1 is the
+/// first endpoint of the first interface, whatever its address.
+#[derive(Debug)]
+pub struct FunctionFsGadget {
+    /// Where the function's FunctionFS instance is mounted.
+    mount_point: PathBuf,
+
+    /// The function's control endpoint; which carries events and control requests.
+    ep0: File,
+
+    /// The function's other endpoints, in order.
+    endpoints: Vec<File>,
+}
+
+impl FunctionFsGadget {
+    /// Presents the given function through the FunctionFS instance mounted at `mount_point`.
+    /// The function becomes visible to the host once its gadget is bound to a UDC.
+    pub fn new(mount_point: impl AsRef<Path>, function: &GadgetFunction) -> UsbResult<Self> {
+        let mount_point = mount_point.as_ref().to_owned();
+        let open = |name: String| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(mount_point.join(name))
+                .map_err(io_error)
+        };
+
+        // Describe ourselves to FunctionFS through ep0; which creates our endpoint files...
+        let mut ep0 = open("ep0".to_owned())?;
+        ep0.write_all(&function.descriptor_blob())
+            .map_err(io_error)?;
+        ep0.write_all(&function.string_blob()).map_err(io_error)?;
+
+        // ... which we can then open.
+        let endpoints = (1..=function.endpoint_count())
+            .map(|number| open(format!("ep{number}")))
+            .collect::<UsbResult<_>>()?;
+
+        Ok(Self {
+            mount_point,
+            ep0,
+            endpoints,
+        })
+    }
+
+    /// Returns where our FunctionFS instance is mounted.
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    /// Returns the number of endpoints we have, excluding ep0.
+    pub fn endpoint_count(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Waits for the next event on our function. [GadgetEvent::Setup] events must be answered
+    /// with [respond], [receive] or [stall] before the next event arrives.
+    pub fn next_event(&mut self) -> UsbResult<GadgetEvent> {
+        let mut event = [0; EVENT_LENGTH];
+        self.ep0.read_exact(&mut event).map_err(io_error)?;
+
+        // The setup packet (if any) comes first, then the event type.
+        let setup = SetupPacket::from_bytes(event[..SetupPacket::LENGTH].try_into().unwrap());
+        match event[SetupPacket::LENGTH] {
+            0 => Ok(GadgetEvent::Bind),
+            1 => Ok(GadgetEvent::Unbind),
+            2 => Ok(GadgetEvent::Enable),
+            3 => Ok(GadgetEvent::Disable),
+            4 => Ok(GadgetEvent::Setup(setup)),
+            5 => Ok(GadgetEvent::Suspend),
+            6 => Ok(GadgetEvent::Resume),
+            _ => Err(Error::ProtocolError),
+        }
+    }
+
+    /// Answers an IN control request with the given data.
+    pub fn respond(&mut self, data: &[u8]) -> UsbResult<()> {
+        self.ep0.write_all(data).map_err(io_error)
+    }
+
+    /// Receives the data stage of an OUT control request; which also acknowledges it.
+    /// Returns the number of bytes received.
+    pub fn receive(&mut self, buffer: &mut [u8]) -> UsbResult<usize> {
+        self.ep0.read(buffer).map_err(io_error)
+    }
+
+    /// Refuses a control request, with a STALL.
+    pub fn stall(&mut self, setup: &SetupPacket) -> UsbResult<()> {
+        // FunctionFS stalls ep0 when we move data the wrong way for the request; which it
+        // reports back to us as an error, since that's what it is.
+        let result = match setup.direction() {
+            Direction::In => self.ep0.read(&mut []),
+            Direction::Out => self.ep0.write(&[]),
+        };
+
+        match result {
+            Err(error) if error.raw_os_error() == Some(STALLED_ERRNO) => Ok(()),
+            Err(error) => Err(io_error(error)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Reads from one of our OUT endpoints; blocking until the host sends something.
+    /// Returns the number of bytes read.
+    pub fn read(&mut self, endpoint: usize, buffer: &mut [u8]) -> UsbResult<usize> {
+        self.endpoint(endpoint)?.read(buffer).map_err(io_error)
+    }
+
+    /// Writes to one of our IN endpoints; blocking until the host reads it.
+    /// Returns the number of bytes written.
+    pub fn write(&mut self, endpoint: usize, data: &[u8]) -> UsbResult<usize> {
+        self.endpoint(endpoint)?.write(data).map_err(io_error)
+    }
+
+    /// Finds the file for the given endpoint.
+    fn endpoint(&mut self, endpoint: usize) -> UsbResult<&mut File> {
+        endpoint
+            .checked_sub(1)
+            .and_then(|index| self.endpoints.get_mut(index))
+            .ok_or(Error::InvalidEndpoint)
+    }
+}
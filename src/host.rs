@@ -1,23 +1,95 @@
 //! Abstraction over the OS/host's USB functionality.
 
-use std::sync::Arc;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
 
-use crate::backend::{create_default_backend, Backend};
-use crate::device::{Device, DeviceInformation, DeviceSelector};
+use log::debug;
+
+use crate::backend::{
+    create_default_backends, select_backend, Backend, DeviceWatch, BACKEND_ENVIRONMENT_VARIABLE,
+};
+use crate::descriptors::DeviceDescriptor;
+use crate::device::{
+    poll_until, Device, DeviceInformation, DeviceSelector, EnumerationOptions, OpenOptions,
+};
+use crate::diagnostics::AccessExplanation;
 use crate::error::{self, UsbResult};
+use crate::topology::Topology;
+use crate::trace::Trace;
+
+/// The backend set with [set_default_backend], if any.
+static DEFAULT_BACKEND: RwLock<Option<Arc<dyn Backend>>> = RwLock::new(None);
 
 /// Representation of a USB host: that is, the thing (e.g. the OS) that talks to
 /// USB devices. This is typically an encapsulation of your OS connection.
+///
+/// A Host can be shared between threads, e.g. in an Arc; so one device cache and one set of
+/// hotplug registrations can serve a whole program.
 pub struct Host {
     /// The backend used to provide the functions for this Host.
     backend: Arc<dyn Backend>,
+
+    /// If we're caching enumeration results, our cache.
+    cache: Mutex<Option<DeviceCache>>,
+
+    /// The options we pass to the backend whenever we enumerate.
+    enumeration_options: RwLock<EnumerationOptions>,
+}
+
+// Hosts are meant to be shared; make sure nothing we add to them stops that.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Host>();
+};
+
+/// A snapshot of the devices on the system; see [Host::enable_device_cache].
+#[derive(Debug)]
+struct DeviceCache {
+    /// Every device on the system, as of our last scan; or None, if we need a new scan.
+    devices: Option<Vec<DeviceInformation>>,
+
+    /// Our subscription to hotplug events, if the backend provides them.
+    watch: Option<DeviceWatch>,
+
+    /// The watch's generation as of our last scan.
+    generation: u64,
+}
+
+/// The outcome of opening every device matching a selector; see [Host::open_all].
+#[derive(Debug, Default)]
+pub struct OpenedDevices {
+    /// The devices we managed to open, in enumeration order.
+    pub devices: Vec<Device>,
+
+    /// The devices we couldn't open, and why.
+    pub failures: Vec<(DeviceInformation, error::Error)>,
+}
+
+impl OpenedDevices {
+    /// Returns true iff every matching device was opened.
+    pub fn all_opened(&self) -> bool {
+        self.failures.is_empty()
+    }
 }
 
 impl Host {
     /// Creates a new Host, using the backend appropriate for the current platform.
+    ///
+    /// If the `USRS_BACKEND` environment variable is set, the backend it names is used instead;
+    /// and if a backend was set with [set_default_backend], that one takes precedence over both.
+    /// See [create_default_backends] for the backends `USRS_BACKEND` can name.
     pub fn new() -> UsbResult<Self> {
-        let backend = create_default_backend()?;
-        Self::new_from_backend(backend)
+        if let Some(backend) = DEFAULT_BACKEND.read().unwrap().clone() {
+            return Self::new_from_backend(backend);
+        }
+
+        Self::with_backends(create_default_backends()?)
     }
 
     /// Creates a new Host, from a custom backend; this allows the library to be
@@ -26,57 +98,379 @@ impl Host {
     ///
     /// Most of the time, you want [new].
     pub fn new_from_backend(backend: Arc<dyn Backend>) -> UsbResult<Self> {
-        Ok(Host { backend })
+        Ok(Host {
+            backend,
+            cache: Mutex::new(None),
+            enumeration_options: RwLock::new(EnumerationOptions::default()),
+        })
+    }
+
+    /// Creates a new Host from a list of candidate backends, given in priority order.
+    ///
+    /// If the `USRS_BACKEND` environment variable names one of the candidates, that one is used;
+    /// otherwise, we'll use the first.
+    pub fn with_backends(backends: Vec<Arc<dyn Backend>>) -> UsbResult<Self> {
+        let preferred = std::env::var(BACKEND_ENVIRONMENT_VARIABLE).ok();
+        Self::with_preferred_backend(backends, preferred.as_deref())
+    }
+
+    /// Creates a new Host from a list of candidate backends, using the one with the given name.
+    ///
+    /// This overrides the `USRS_BACKEND` environment variable; pass None to just take the first.
+    pub fn with_preferred_backend(
+        backends: Vec<Arc<dyn Backend>>,
+        preferred: Option<&str>,
+    ) -> UsbResult<Self> {
+        Self::new_from_backend(select_backend(backends, preferred)?)
+    }
+
+    /// Sets the options used for all future enumeration; e.g. to skip reading device strings
+    /// until they're needed. Discards any cached devices.
+    pub fn set_enumeration_options(&self, options: EnumerationOptions) {
+        *self.enumeration_options.write().unwrap() = options;
+        self.refresh();
+    }
+
+    /// Reads the strings of a device enumerated with [EnumerationOptions::deferred_strings].
+    /// Does nothing if the device already has its strings.
+    pub fn fetch_strings(&self, information: &mut DeviceInformation) -> UsbResult<()> {
+        if !information.strings_deferred {
+            return Ok(());
+        }
+
+        self.backend.fetch_device_strings(information)?;
+        information.strings_deferred = false;
+
+        // The OS may not have names for everything; fill in from our database, if we can.
+        *information = with_names(std::mem::take(information));
+        Ok(())
+    }
+
+    /// Returns the name of the backend this Host is using.
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
     }
 
     /// Helper for [device] and [devices]; enumerates one or more devices matching a selector.
     fn enumerate_devices(
-        &mut self,
+        &self,
         selector: &DeviceSelector,
         single_device: bool,
     ) -> UsbResult<Vec<DeviceInformation>> {
-        let mut matching_devices: Vec<DeviceInformation> = vec![];
+        let trace = Trace::operation("enumerate");
+
+        // If we're only returning a single device, we can stop at the first match.
+        let limit = if single_device { 1 } else { usize::MAX };
+        let matching_devices = match self.cached_devices(selector, limit) {
+            Some(devices) => devices,
+            None => self
+                .devices_iter(selector)
+                .and_then(|devices| devices.take(limit).collect::<UsbResult<Vec<_>>>()),
+        };
+
+        trace.finish(matching_devices.as_ref().map(Vec::len));
+        matching_devices
+    }
 
-        // Get a list of all devices...
-        let all_devices = self.backend.get_devices()?;
+    /// If caching is enabled, returns up to `limit` devices from our snapshot of the system's
+    /// devices that match the selector; re-scanning first if the snapshot is stale.
+    fn cached_devices(
+        &self,
+        selector: &DeviceSelector,
+        limit: usize,
+    ) -> Option<UsbResult<Vec<DeviceInformation>>> {
+        // We hold the lock through any re-scan; so threads racing to use a stale cache
+        // only scan once.
+        let mut cache = self.cache.lock().unwrap();
+        let cache = cache.as_mut()?;
 
-        // .... and then filter it down.
-        for device in all_devices {
-            if selector.matches(&device) {
-                matching_devices.push(device);
+        // If a device has come or gone since our last scan, our snapshot is stale.
+        let generation = cache.watch.as_ref().map_or(0, DeviceWatch::generation);
+        if generation != cache.generation {
+            cache.devices = None;
+        }
 
-                // If we're only returning a single device, end here.
-                if single_device {
-                    return Ok(matching_devices);
+        if cache.devices.is_none() {
+            let options = *self.enumeration_options.read().unwrap();
+            let devices = self
+                .backend
+                .devices_iter(&DeviceSelector::default(), &options)
+                .and_then(|devices| devices.map(|device| device.map(with_names)).collect());
+            match devices {
+                Ok(devices) => {
+                    cache.devices = Some(devices);
+                    cache.generation = generation;
                 }
+                Err(error) => return Some(Err(error)),
             }
         }
 
-        Ok(matching_devices)
+        let devices = cache.devices.as_ref()?;
+        Some(Ok(devices
+            .iter()
+            .filter(|device| selector.matches(device))
+            .take(limit)
+            .cloned()
+            .collect()))
+    }
+
+    /// Has [devices], [device], and [all_devices] serve their results from a snapshot of the
+    /// system's devices, rather than asking the OS each time; for e.g. GUIs that poll for
+    /// devices frequently.
+    ///
+    /// Where the backend supports hotplug notifications, the snapshot is refreshed whenever
+    /// a device is connected or disconnected. Otherwise, it's only refreshed by [refresh].
+    /// Either way, [devices_iter] and [wait_for_device] always ask the OS.
+    pub fn enable_device_cache(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.is_some() {
+            return;
+        }
+
+        // If the backend can't tell us about changes, we'll rely on our user to refresh us.
+        let watch = match self.backend.watch_devices() {
+            Ok(watch) => Some(watch),
+            Err(error) => {
+                debug!("device cache won't update automatically: {error}");
+                None
+            }
+        };
+
+        *cache = Some(DeviceCache {
+            devices: None,
+            generation: watch.as_ref().map_or(0, DeviceWatch::generation),
+            watch,
+        });
+    }
+
+    /// Stops caching enumeration results; see [enable_device_cache].
+    pub fn disable_device_cache(&self) {
+        *self.cache.lock().unwrap() = None;
+    }
+
+    /// Forces the device cache to be rebuilt, the next time it's used. Does nothing if
+    /// caching isn't enabled.
+    pub fn refresh(&self) {
+        if let Some(cache) = self.cache.lock().unwrap().as_mut() {
+            cache.devices = None;
+        }
+    }
+
+    /// Returns an iterator over the devices matching the given selector.
+    ///
+    /// Devices are pulled from the OS as the iterator advances; so e.g. looking for the first
+    /// match doesn't pay to query every device on a busy system. Each item is an error if
+    /// the OS failed to tell us about a device.
+    pub fn devices_iter(
+        &self,
+        selector: &DeviceSelector,
+    ) -> UsbResult<impl Iterator<Item = UsbResult<DeviceInformation>> + '_> {
+        let selector = selector.clone();
+        let options = *self.enumeration_options.read().unwrap();
+
+        let devices = self
+            .backend
+            .devices_iter(&selector, &options)?
+            .filter_map(move |device| match device {
+                Ok(device) => {
+                    let device = with_names(device);
+                    selector.matches(&device).then_some(Ok(device))
+                }
+                Err(error) => Some(Err(error)),
+            });
+
+        Ok(devices)
     }
 
     /// Returns the first device matching the given selector.
-    pub fn device(&mut self, selector: &DeviceSelector) -> UsbResult<DeviceInformation> {
+    pub fn device(&self, selector: &DeviceSelector) -> UsbResult<DeviceInformation> {
         let mut candidates = self.enumerate_devices(selector, true)?;
         candidates.pop().ok_or(error::Error::DeviceNotFound)
     }
 
+    /// Waits for a device matching the given selector to appear, and returns it.
+    ///
+    /// Returns immediately if a matching device is already connected; or [error::Error::TimedOut]
+    /// if none shows up before the timeout elapses. A timeout of None waits forever.
+    pub fn wait_for_device(
+        &self,
+        selector: &DeviceSelector,
+        timeout: Option<Duration>,
+    ) -> UsbResult<DeviceInformation> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        // Skip the device cache; without hotplug events, it'd never see the device arrive.
+        poll_until(deadline, || self.devices_iter(selector)?.next().transpose())
+    }
+
     /// Finds devices attached to the system, filtering by one or more criteria.
-    pub fn devices(&mut self, selector: &DeviceSelector) -> UsbResult<Vec<DeviceInformation>> {
+    pub fn devices(&self, selector: &DeviceSelector) -> UsbResult<Vec<DeviceInformation>> {
         self.enumerate_devices(selector, false)
     }
 
     /// Returns all devices currently connected to the system.
-    pub fn all_devices(&mut self) -> UsbResult<Vec<DeviceInformation>> {
+    pub fn all_devices(&self) -> UsbResult<Vec<DeviceInformation>> {
         self.devices(&Default::default())
     }
 
+    /// Returns the physical layout of the system's buses: which devices are plugged into
+    /// which hub ports. Devices the backend can't place are listed in [Topology::unplaced].
+    pub fn topology(&self) -> UsbResult<Topology> {
+        Ok(Topology::from_devices(self.all_devices()?))
+    }
+
+    /// Returns a device's device descriptor, while touching the device as little as possible.
+    ///
+    /// Where the OS keeps a copy of the descriptor, that's what we return; otherwise, we'll
+    /// ask the device, without opening it for access (see [OpenOptions::descriptors_only]).
+    /// Useful for e.g. inventory tools, which may not have permission to open devices.
+    pub fn read_descriptors(&self, information: &DeviceInformation) -> UsbResult<DeviceDescriptor> {
+        if let Some(descriptor) = information.cached_device_descriptor() {
+            return Ok(descriptor.clone());
+        }
+
+        self.open_with(information, &OpenOptions::descriptors_only())?
+            .read_device_descriptor()
+    }
+
+    /// Inspects the state of the system to figure out why a device can't be accessed;
+    /// e.g. after an operation fails with [error::Error::PermissionDenied].
+    pub fn explain_access_error(
+        &self,
+        information: &DeviceInformation,
+    ) -> UsbResult<AccessExplanation> {
+        Ok(AccessExplanation {
+            problems: self.backend.diagnose_access(information)?,
+        })
+    }
+
+    /// Allows or forbids the OS from using the given device; e.g. so security tooling can
+    /// quarantine a device, or re-admit it once it's been vetted.
+    ///
+    /// Only supported on Linux, where this writes the device's sysfs `authorized` attribute;
+    /// which usually requires root. Returns [error::Error::Unsupported] elsewhere, or if the
+    /// backend couldn't tell us where the device is plugged in.
+    pub fn set_device_authorized(
+        &self,
+        information: &DeviceInformation,
+        authorized: bool,
+    ) -> UsbResult<()> {
+        #[cfg(target_os = "linux")]
+        return crate::sysfs::set_device_authorized(
+            Path::new(crate::sysfs::USB_DEVICES),
+            information,
+            authorized,
+        );
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (information, authorized);
+            Err(error::Error::Unsupported)
+        }
+    }
+
+    /// Sets whether devices newly connected to the given bus are authorized automatically;
+    /// see [set_device_authorized]. Only supported on Linux, where this writes the sysfs
+    /// `authorized_default` attribute of the bus's root hub.
+    pub fn set_bus_authorized_default(&self, bus: u8, authorized: bool) -> UsbResult<()> {
+        #[cfg(target_os = "linux")]
+        return crate::sysfs::set_bus_authorized_default(
+            Path::new(crate::sysfs::USB_DEVICES),
+            bus,
+            authorized,
+        );
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (bus, authorized);
+            Err(error::Error::Unsupported)
+        }
+    }
+
     /// Opens a device given its device information.
-    pub fn open(&mut self, information: &DeviceInformation) -> UsbResult<Device> {
+    pub fn open(&self, information: &DeviceInformation) -> UsbResult<Device> {
         // Ask our backend to open a device for us...
-        let backend_device = self.backend.open(information)?;
+        let trace = Trace::operation("open");
+        let backend_device = self.backend.open(information);
+        trace.finish(backend_device.as_ref().map(|_| ()));
+        let backend_device = backend_device?;
 
         // FIXME: actually open the device, here, instead of having the backend do it?
+        Ok(
+            Device::from_backend_device(backend_device, Arc::clone(&self.backend))
+                .with_information(information.clone()),
+        )
+    }
+
+    /// Finds the first device matching the given selector, and opens it.
+    ///
+    /// Returns [error::Error::DeviceNotFound] if no device matches.
+    pub fn open_first(&self, selector: &DeviceSelector) -> UsbResult<Device> {
+        let information = self.device(selector)?;
+        self.open(&information)
+    }
+
+    /// Opens every device matching the given selector; e.g. to drive a rack of identical
+    /// devices.
+    ///
+    /// A device that fails to open doesn't stop us from opening the rest; its error is
+    /// reported in [OpenedDevices::failures]. We only fail outright if we can't enumerate.
+    pub fn open_all(&self, selector: &DeviceSelector) -> UsbResult<OpenedDevices> {
+        let mut opened = OpenedDevices::default();
+
+        for information in self.devices(selector)? {
+            match self.open(&information) {
+                Ok(device) => opened.devices.push(device),
+                Err(error) => opened.failures.push((information, error)),
+            }
+        }
+
+        Ok(opened)
+    }
+
+    /// Opens a device given its device information, with the provided options; e.g.
+    /// `host.open_with(&info, &OpenOptions::exclusive())`.
+    ///
+    /// Backends that don't support a given mode will return [error::Error::Unsupported].
+    pub fn open_with(
+        &self,
+        information: &DeviceInformation,
+        options: &OpenOptions,
+    ) -> UsbResult<Device> {
+        let trace = Trace::operation("open");
+        let backend_device = self.backend.open_with(information, options);
+        trace.finish(backend_device.as_ref().map(|_| ()));
+        let backend_device = backend_device?;
+
+        Ok(
+            Device::from_backend_device(backend_device, Arc::clone(&self.backend))
+                .with_information(information.clone()),
+        )
+    }
+
+    /// Creates a device from a file descriptor the OS has already opened for us.
+    ///
+    /// This is how devices are opened on Android, where apps can't open devices themselves:
+    /// pass in the result of `UsbDeviceConnection.getFileDescriptor()`. The connection keeps
+    /// ownership of the descriptor, and must be kept open for as long as the Device is in use.
+    #[cfg(unix)]
+    pub fn device_from_fd(&self, fd: RawFd) -> UsbResult<Device> {
+        let backend_device = self.backend.device_from_fd(fd)?;
+
+        Ok(Device::from_backend_device(
+            backend_device,
+            Arc::clone(&self.backend),
+        ))
+    }
+
+    /// Creates a device from an IOKit `io_service_t` for a USB device; for sandboxed apps
+    /// whose devices are found for them, e.g. by a privileged helper or a DriverKit
+    /// extension. We take our own reference to the service; the caller should still
+    /// release theirs.
+    #[cfg(target_os = "macos")]
+    pub fn device_from_service(&self, service: u32) -> UsbResult<Device> {
+        let backend_device = self.backend.device_from_service(service)?;
+
         Ok(Device::from_backend_device(
             backend_device,
             Arc::clone(&self.backend),
@@ -84,12 +478,34 @@ impl Host {
     }
 }
 
+/// Sets the backend used by [Host::new]; and so by every convenience function that creates
+/// its own Host, like [open_first]. Useful for running code built on those functions against
+/// e.g. a [crate::backend::mock::MockBackend].
+///
+/// This is process-wide; Hosts that already exist keep the backend they were created with.
+pub fn set_default_backend(backend: Arc<dyn Backend>) {
+    *DEFAULT_BACKEND.write().unwrap() = Some(backend);
+}
+
+/// Undoes [set_default_backend]; so new Hosts go back to using the platform's backend.
+pub fn clear_default_backend() {
+    *DEFAULT_BACKEND.write().unwrap() = None;
+}
+
 /// Returns the first device matching the given selector.
 /// Convenience form that implicitly constructs (and destroys) a Host object.
 pub fn device(selector: &DeviceSelector) -> UsbResult<DeviceInformation> {
     Host::new()?.device(selector)
 }
 
+/// Fills in any vendor or product names the OS didn't provide, where we have a database of them.
+fn with_names(device: DeviceInformation) -> DeviceInformation {
+    #[cfg(feature = "usb-ids")]
+    let device = crate::usb_ids::with_names(device);
+
+    device
+}
+
 /// Finds devices matching the given selector.
 /// Convenience form that implicitly constructs (and destroys) a Host object.
 pub fn devices(selector: &DeviceSelector) -> UsbResult<Vec<DeviceInformation>> {
@@ -102,8 +518,35 @@ pub fn all_devices() -> UsbResult<Vec<DeviceInformation>> {
     Host::new()?.all_devices()
 }
 
+/// Waits for a device matching the given selector to appear, and returns it.
+/// Convenience form that implicitly constructs (and destroys) a Host object.
+pub fn wait_for_device(
+    selector: &DeviceSelector,
+    timeout: Option<Duration>,
+) -> UsbResult<DeviceInformation> {
+    Host::new()?.wait_for_device(selector, timeout)
+}
+
 /// Opens a device given its device information.
 /// Convenience form that implicitly constructs (and destroys) a Host object.
 pub fn open(info: &DeviceInformation) -> UsbResult<Device> {
     Host::new()?.open(info)
 }
+
+/// Finds the first device matching the given selector, and opens it.
+/// Convenience form that implicitly constructs (and destroys) a Host object.
+pub fn open_first(selector: &DeviceSelector) -> UsbResult<Device> {
+    Host::new()?.open_first(selector)
+}
+
+/// Opens every device matching the given selector.
+/// Convenience form that implicitly constructs (and destroys) a Host object.
+pub fn open_all(selector: &DeviceSelector) -> UsbResult<OpenedDevices> {
+    Host::new()?.open_all(selector)
+}
+
+/// Opens a device given its device information, with the provided options.
+/// Convenience form that implicitly constructs (and destroys) a Host object.
+pub fn open_with(info: &DeviceInformation, options: &OpenOptions) -> UsbResult<Device> {
+    Host::new()?.open_with(info, options)
+}
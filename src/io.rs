@@ -0,0 +1,102 @@
+//! Blocking [std::io] adapters over endpoints; so synchronous code can use e.g. `BufReader`
+//! and `std::io::copy` with a device.
+
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use crate::device::Device;
+
+/// Reads from an IN endpoint via [std::io::Read]. Created by [Device::endpoint_reader].
+pub struct EndpointReader<'a> {
+    /// The device the endpoint belongs to.
+    device: &'a mut Device,
+
+    /// The address of the endpoint we read from.
+    endpoint: u8,
+
+    /// The timeout for each read; or None to use the device's default.
+    timeout: Option<Duration>,
+}
+
+impl<'a> EndpointReader<'a> {
+    /// Creates a reader for the provided endpoint.
+    pub(crate) fn new(device: &'a mut Device, endpoint: u8) -> Self {
+        Self {
+            device,
+            endpoint: endpoint | 0x80,
+            timeout: None,
+        }
+    }
+
+    /// Sets the timeout for each read. If None, the device's default timeout is used.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the timeout used for each read.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+impl Read for EndpointReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // A zero-length packet isn't an end-of-file; so we'll keep reading until we get data.
+        loop {
+            match self.device.read(self.endpoint, buf, self.timeout)? {
+                0 => continue,
+                length => return Ok(length),
+            }
+        }
+    }
+}
+
+/// Writes to an OUT endpoint via [std::io::Write]. Created by [Device::endpoint_writer].
+pub struct EndpointWriter<'a> {
+    /// The device the endpoint belongs to.
+    device: &'a mut Device,
+
+    /// The address of the endpoint we write to.
+    endpoint: u8,
+
+    /// The timeout for each write; or None to use the device's default.
+    timeout: Option<Duration>,
+}
+
+impl<'a> EndpointWriter<'a> {
+    /// Creates a writer for the provided endpoint.
+    pub(crate) fn new(device: &'a mut Device, endpoint: u8) -> Self {
+        Self {
+            device,
+            endpoint: endpoint & 0x7f,
+            timeout: None,
+        }
+    }
+
+    /// Sets the timeout for each write. If None, the device's default timeout is used.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the timeout used for each write.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+impl Write for EndpointWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(self.device.write(self.endpoint, buf, self.timeout)?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Every write goes straight to the device; so there's nothing to flush.
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+//! Isochronous transfers; and [IsoStream], which keeps an isochronous IN endpoint fed with
+//! transfers scheduled back-to-back against the bus's frame counter.
+//!
+//! An isochronous transfer is made up of packets, each of which is sent in its own (micro)frame
+//! and succeeds or fails on its own. Each packet's data lives at the offset given by the sum of
+//! the lengths requested for the packets before it; so a short packet leaves a gap behind it.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use parking_lot::RwLock;
+
+use crate::{
+    backend::TransferCallback, device::Device, futures::UsbFuture, ReadBuffer, UsbResult,
+    WriteBuffer,
+};
+
+/// How many frames ahead of the bus we schedule a stream's first transfer; which gives us
+/// time to submit it before its frame comes around.
+const SCHEDULING_LATENCY_FRAMES: u64 = 8;
+
+/// The number of transfers an [IsoStream] keeps queued, unless told otherwise.
+const DEFAULT_RING_DEPTH: usize = 4;
+
+/// Callback for a completed isochronous transfer; given the outcome of each packet, in order.
+pub type IsoCallback = Box<dyn FnOnce(UsbResult<Vec<IsoPacketStatus>>) + Send>;
+
+/// What happened to a single packet of an isochronous transfer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsoPacketStatus {
+    /// Whether the packet made it across the bus.
+    pub status: UsbResult<()>,
+
+    /// How much of the packet's data was actually moved.
+    pub actual_length: usize,
+
+    /// When the packet completed, as time since boot on the host's monotonic clock; for
+    /// transfers made with [IsoOptions::low_latency], on backends that record it.
+    pub timestamp: Option<Duration>,
+}
+
+/// How an isochronous transfer should be carried out; see [Device::read_isochronous_async].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IsoOptions {
+    /// The bus frame the transfer should start in; or None to start as soon as possible.
+    pub start_frame: Option<u64>,
+
+    /// If true, the transfer's data and packet list live in buffers the OS keeps mapped for
+    /// the device; which saves a copy and a mapping per transfer, and lets each packet be
+    /// timestamped. Currently only used on macOS; other backends ignore it.
+    pub low_latency: bool,
+}
+
+impl IsoOptions {
+    /// Options for a transfer that starts in the given bus frame.
+    pub fn starting_at(frame: u64) -> Self {
+        Self {
+            start_frame: Some(frame),
+            ..Self::default()
+        }
+    }
+}
+
+/// A single packet read from an isochronous IN endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsoPacket {
+    /// Whether the packet made it across the bus.
+    pub status: UsbResult<()>,
+
+    /// The data the packet carried; which may be partial, or empty, if it failed.
+    pub data: Vec<u8>,
+}
+
+/// The result of a completed isochronous read; see [Device::read_isochronous_async].
+pub struct IsoReadCompletion {
+    /// The buffer the packets were read into.
+    pub buffer: ReadBuffer,
+
+    /// The length requested for each packet.
+    pub packet_lengths: Vec<usize>,
+
+    /// What happened to each packet.
+    pub packets: Vec<IsoPacketStatus>,
+}
+
+impl IsoReadCompletion {
+    /// Splits the transfer into its individual packets, in order.
+    pub fn to_packets(&self) -> Vec<IsoPacket> {
+        let mut buffer = self.buffer.write();
+        let buffer = buffer.as_mut();
+
+        let mut offset = 0;
+        self.packets
+            .iter()
+            .zip(&self.packet_lengths)
+            .map(|(packet, &requested)| {
+                let start = offset.min(buffer.len());
+                let end = (offset + packet.actual_length.min(requested)).min(buffer.len());
+                offset += requested;
+
+                IsoPacket {
+                    status: packet.status.clone(),
+                    data: buffer[start..end].to_vec(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The result of a completed isochronous write; see [Device::write_isochronous_async].
+pub struct IsoWriteCompletion {
+    /// The data that was written.
+    pub buffer: WriteBuffer,
+
+    /// What happened to each packet.
+    pub packets: Vec<IsoPacketStatus>,
+}
+
+/// Builds the callback and future for an isochronous transfer. The callback records each
+/// packet's status where the future can find it, and then completes the transfer with the
+/// total length moved.
+pub(crate) fn iso_future<T: 'static>(
+    complete: impl FnOnce(TransferCallback) -> TransferCallback,
+    finish: impl FnOnce(Vec<IsoPacketStatus>) -> T + Send + Sync + 'static,
+) -> (UsbFuture<T>, IsoCallback) {
+    let packets: Arc<Mutex<Vec<IsoPacketStatus>>> = Arc::default();
+
+    let finished_packets = Arc::clone(&packets);
+    let future = UsbFuture::new(move |_| finish(finished_packets.lock().unwrap().split_off(0)));
+
+    // The caller gets to wrap our completion; e.g. so it can trace the transfer.
+    let state = future.clone_state();
+    let completion = complete(Box::new(move |result| {
+        state.lock().unwrap().complete(result)
+    }));
+
+    let callback: IsoCallback = Box::new(move |result| {
+        let result = result.map(|statuses| {
+            let total = statuses.iter().map(|packet| packet.actual_length).sum();
+            *packets.lock().unwrap() = statuses;
+            total
+        });
+        completion(result)
+    });
+
+    (future, callback)
+}
+
+/// Tracks which bus frame an endpoint's next isochronous transfer should start in; so that
+/// a stream's transfers run back-to-back.
+#[derive(Debug)]
+pub(crate) struct FrameSchedule {
+    /// How many packets the endpoint moves per frame; e.g. 8 for a high-speed endpoint
+    /// with an interval of one microframe.
+    packets_per_frame: usize,
+
+    /// The frame our next transfer should start in; or None if we need to (re-)synchronize
+    /// with the bus.
+    next_frame: Option<u64>,
+}
+
+impl FrameSchedule {
+    /// Creates a schedule for an endpoint that moves one packet per frame.
+    pub(crate) fn new() -> Self {
+        Self {
+            packets_per_frame: 1,
+            next_frame: None,
+        }
+    }
+
+    /// Sets how many packets the endpoint moves per bus frame.
+    pub(crate) fn set_packets_per_frame(&mut self, packets: usize) {
+        self.packets_per_frame = packets.max(1);
+    }
+
+    /// Works out which frame a transfer of `packets` packets should start in, and advances
+    /// past it. Returns None if the backend can't tell us the bus's frame number.
+    pub(crate) fn schedule(&mut self, device: &Device, packets: usize) -> Option<u64> {
+        // If we've lost our place (or never had one), find out where the bus is now.
+        if self.next_frame.is_none() {
+            self.next_frame = device
+                .current_bus_frame()
+                .ok()
+                .map(|(frame, _)| frame + SCHEDULING_LATENCY_FRAMES);
+        }
+
+        let start = self.next_frame?;
+        let frames = packets.div_ceil(self.packets_per_frame) as u64;
+        self.next_frame = Some(start + frames);
+        Some(start)
+    }
+
+    /// Forgets our place in the schedule; so we resynchronize with the bus before our next
+    /// transfer.
+    pub(crate) fn resynchronize(&mut self) {
+        self.next_frame = None;
+    }
+}
+
+/// A [Stream] of packets from an isochronous IN endpoint. Created by [Device::iso_stream].
+///
+/// Keeps a ring of transfers queued, each scheduled to start in the frame right after the
+/// previous one ends; so no packets are missed between polls. Packets are yielded in the
+/// order they crossed the bus, each with its own status.
+///
+/// Where the backend can't tell us the bus's frame number, transfers are scheduled "as soon
+/// as possible" instead; which is usually, but not always, back-to-back.
+pub struct IsoStream<'a> {
+    /// The device the endpoint belongs to.
+    device: &'a mut Device,
+
+    /// The address of the endpoint we read from.
+    endpoint: u8,
+
+    /// The size of each packet; usually the endpoint's max packet size times its mult.
+    packet_size: usize,
+
+    /// The number of packets in each transfer.
+    packets_per_transfer: usize,
+
+    /// The number of transfers we try to keep queued.
+    ring_depth: usize,
+
+    /// Which frame each of our transfers should start in.
+    schedule: FrameSchedule,
+
+    /// Our queued transfers, oldest first.
+    queued: VecDeque<UsbFuture<IsoReadCompletion>>,
+
+    /// Packets we've received, but not yet handed out.
+    ready: VecDeque<IsoPacket>,
+
+    /// True iff our transfers should use the backend's low-latency path.
+    low_latency: bool,
+}
+
+impl<'a> IsoStream<'a> {
+    /// Creates a stream that reads `packets_per_transfer` packets of `packet_size` bytes at
+    /// a time from the provided endpoint.
+    pub(crate) fn new(
+        device: &'a mut Device,
+        endpoint: u8,
+        packet_size: usize,
+        packets_per_transfer: usize,
+    ) -> Self {
+        Self {
+            device,
+            endpoint: endpoint | 0x80,
+            packet_size,
+            packets_per_transfer: packets_per_transfer.max(1),
+            ring_depth: DEFAULT_RING_DEPTH,
+            schedule: FrameSchedule::new(),
+            queued: VecDeque::new(),
+            ready: VecDeque::new(),
+            low_latency: false,
+        }
+    }
+
+    /// Sets the number of transfers to keep queued; which must be at least one.
+    /// Takes effect as queued transfers complete.
+    pub fn set_ring_depth(&mut self, depth: usize) {
+        self.ring_depth = depth.max(1);
+    }
+
+    /// Sets how many packets the endpoint moves per bus frame; for high-speed endpoints,
+    /// which move up to eight per frame. Defaults to one.
+    pub fn set_packets_per_frame(&mut self, packets: usize) {
+        self.schedule.set_packets_per_frame(packets);
+    }
+
+    /// Sets whether transfers use the backend's low-latency path; see
+    /// [IsoOptions::low_latency]. Takes effect for transfers queued after this call.
+    pub fn set_low_latency(&mut self, low_latency: bool) {
+        self.low_latency = low_latency;
+    }
+
+    /// Returns the number of transfers we try to keep queued.
+    pub fn ring_depth(&self) -> usize {
+        self.ring_depth
+    }
+
+    /// Queues transfers until we have as many in flight as we'd like.
+    fn fill_queue(&mut self) -> UsbResult<()> {
+        while self.queued.len() < self.ring_depth {
+            let packet_lengths = vec![self.packet_size; self.packets_per_transfer];
+            let buffer = Arc::new(RwLock::new(vec![
+                0;
+                self.packet_size * packet_lengths.len()
+            ]));
+
+            let options = IsoOptions {
+                start_frame: self
+                    .schedule
+                    .schedule(self.device, self.packets_per_transfer),
+                low_latency: self.low_latency,
+            };
+            let future = self.device.read_isochronous_async(
+                self.endpoint,
+                buffer,
+                &packet_lengths,
+                &options,
+            );
+
+            // If we couldn't submit, our schedule is probably stale; resynchronize next time.
+            match future {
+                Ok(future) => self.queued.push_back(future),
+                Err(error) => {
+                    self.schedule.resynchronize();
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Stream for IsoStream<'_> {
+    type Item = UsbResult<IsoPacket>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(packet) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(packet)));
+            }
+
+            // Make sure we have transfers in flight; if we can't queue any, report why.
+            if let Err(error) = this.fill_queue() {
+                if this.queued.is_empty() {
+                    return Poll::Ready(Some(Err(error)));
+                }
+            }
+
+            // Transfers complete in the order we queued them; so we only wait on the oldest.
+            let future = this.queued.front_mut().unwrap();
+            let result = std::task::ready!(Pin::new(future).poll(cx));
+            this.queued.pop_front();
+
+            match result {
+                Ok(completion) => this.ready.extend(completion.to_packets()),
+
+                // A failed transfer means we've lost our place in the schedule; so we'll
+                // resynchronize with the bus before queueing any more.
+                Err(error) => {
+                    this.schedule.resynchronize();
+                    return Poll::Ready(Some(Err(error)));
+                }
+            }
+        }
+    }
+}
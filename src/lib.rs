@@ -1,32 +1,106 @@
 //! Universal Serial Rust -- tools for working with USB from Rust.
 
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
-pub use device::{DeviceInformation, DeviceSelector};
-pub use error::{Error, UsbResult};
-pub use host::{all_devices, device, devices, open, Host};
+use parking_lot::RwLock;
 
+pub use capture::PcapCapture;
+pub use device::{
+    ControlData, DeviceInformation, DeviceSelector, DeviceSpeed, EndpointPolicy,
+    EnumerationOptions, ExtraPowerKind, InterfaceClass, OpenMode, OpenOptions, RawHandle,
+    ReadOptions, ReenumerateOptions, StallPolicy, TransferTimeout, WriteOptions,
+};
+pub use error::{ContextError, Error, UsbResult};
+pub use host::{
+    all_devices, clear_default_backend, device, devices, open, open_all, open_first, open_with,
+    set_default_backend, wait_for_device, Host, OpenedDevices,
+};
+pub use stats::{DeviceStats, EndpointStats};
+
+#[cfg(feature = "async")]
+pub use batch::{BatchCompletion, BatchHandle, TransferRequest};
+#[cfg(feature = "async")]
+pub use convenience::{
+    create_read_buffer, create_write_buffer, create_write_buffer_from_slices, static_write_buffer,
+};
+#[cfg(feature = "async")]
+pub use pool::{BufferPool, PoolMemory, PooledBuffer};
+#[cfg(feature = "async")]
+pub use stream::{EndpointStream, InterruptStream};
 #[cfg(feature = "async")]
-pub use convenience::create_read_buffer;
+pub use transfer::Transfer;
 
 pub mod backend;
+pub mod capture;
+pub mod class;
+pub mod compare;
+pub mod compliance;
 pub mod convenience;
+pub mod descriptors;
 pub mod device;
+pub mod diagnostics;
 pub mod error;
 pub mod host;
+pub mod io;
+pub mod msos;
 pub mod request;
+pub mod stats;
+pub mod topology;
 
+mod trace;
+
+#[cfg(feature = "async")]
+pub mod batch;
+#[cfg(feature = "callbacks")]
+pub mod channel;
+#[cfg(feature = "rusb-compat")]
+pub mod compat;
 #[cfg(feature = "async")]
 pub mod futures;
+#[cfg(target_os = "linux")]
+pub mod gadget;
+#[cfg(feature = "async")]
+pub mod iso;
+#[cfg(feature = "async")]
+pub mod pool;
+#[cfg(feature = "async")]
+pub mod stream;
+#[cfg(target_os = "linux")]
+mod sysfs;
+#[cfg(feature = "async")]
+pub mod transfer;
+#[cfg(feature = "typed-control")]
+pub mod typed;
+#[cfg(feature = "usb-ids")]
+pub mod usb_ids;
 
 /// Type used for asynchronous read operations.
-#[cfg(feature = "async")]
+///
+/// Once a read is submitted, the buffer belongs to the transfer until its completion arrives;
+/// then, it's the caller's again. Until then, its contents are unspecified.
+///
+/// Backends that read straight into the buffer's memory hold its write lock (as a
+/// [ReadBufferGuard]) for as long as the read is in flight; so locking it early, e.g. with
+/// `buffer.write()`, waits for the read to complete, rather than racing it. Backends that read
+/// into memory of their own only lock the buffer to copy the data in. Either way, the lock is
+/// released before the completion is delivered; so completions are free to lock the buffer.
+/// The lock can't be poisoned; a buffer's bytes are still just bytes.
 pub type ReadBuffer = Arc<RwLock<dyn AsMut<[u8]> + Send + Sync>>;
 
+/// Exclusive access to a [ReadBuffer]; held by a backend for as long as it's reading straight
+/// into the buffer's memory. Take one with `buffer.write_arc()`.
+pub type ReadBufferGuard =
+    parking_lot::ArcRwLockWriteGuard<parking_lot::RawRwLock, dyn AsMut<[u8]> + Send + Sync>;
+
 /// Type used for asynchronous write operations.
-#[cfg(feature = "async")]
 pub type WriteBuffer = Arc<dyn AsRef<[u8]> + Send + Sync>;
 
 /// Type used for callbacks in the callback-model async functions.
 #[cfg(feature = "callbacks")]
-pub type AsyncCallback = Box<dyn FnOnce(UsbResult<usize>)>;
+pub type AsyncCallback = Box<dyn FnOnce(UsbResult<usize>) + Send>;
+
+/// Type used to run callbacks somewhere other than a backend's event thread; e.g. on a thread
+/// pool. Called with each completion, which it should run as soon as it's convenient.
+/// See [device::Device::set_completion_executor].
+#[cfg(feature = "callbacks")]
+pub type CompletionExecutor = Arc<dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync>;
@@ -0,0 +1,502 @@
+//! Helpers for Microsoft OS descriptors; which let devices ask Windows to bind a driver
+//! (typically WinUSB) without an INF file.
+//!
+//! Devices advertise MS OS 1.0 descriptors via a special string descriptor at index 0xEE, and
+//! MS OS 2.0 descriptors via a platform capability in their BOS descriptor. Either way, the
+//! descriptors themselves are fetched with a device-chosen vendor request. None of this is
+//! Windows-specific to read; so bring-up tools can check their descriptors from any host.
+
+use crate::{
+    descriptors::{read_u16, BosDescriptor, MS_OS_20_PLATFORM_UUID},
+    device::Device,
+    request::{
+        DescriptorType, Direction, Recipient, RequestType, StandardDeviceRequest, Type,
+        STANDARD_IN_FROM_DEVICE, VENDOR_IN_FROM_DEVICE,
+    },
+    Error, UsbResult,
+};
+
+/// The string descriptor index where devices place their MS OS 1.0 string.
+pub const OS_STRING_INDEX: u8 = 0xee;
+
+/// The signature that starts a valid MS OS 1.0 string; "MSFT100" in UTF-16LE.
+const OS_STRING_SIGNATURE: &[u8] = b"M\0S\0F\0T\x001\x000\x000\0";
+
+/// The wIndex used to request the MS OS 1.0 extended compat ID descriptor.
+const EXTENDED_COMPAT_ID_INDEX: u16 = 0x0004;
+
+/// The wIndex used to request MS OS 1.0 extended properties.
+const EXTENDED_PROPERTIES_INDEX: u16 = 0x0005;
+
+/// The wIndex used to request an MS OS 2.0 descriptor set.
+const MS_OS_20_DESCRIPTOR_INDEX: u16 = 0x0007;
+
+/// The MS OS 2.0 descriptor type of a configuration subset header.
+const MS_OS_20_SUBSET_HEADER_CONFIGURATION: u16 = 0x0001;
+
+/// The MS OS 2.0 descriptor type of a function subset header.
+const MS_OS_20_SUBSET_HEADER_FUNCTION: u16 = 0x0002;
+
+/// The MS OS 2.0 descriptor type of a compatible ID descriptor.
+const MS_OS_20_FEATURE_COMPATIBLE_ID: u16 = 0x0003;
+
+/// A function listed in an MS OS 1.0 extended compat ID descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatibleFunction {
+    /// The first interface of the function.
+    pub first_interface: u8,
+
+    /// The function's compatible ID; e.g. "WINUSB".
+    pub compatible_id: String,
+
+    /// The function's sub-compatible ID; usually empty.
+    pub sub_compatible_id: String,
+}
+
+/// A single MS OS 1.0 extended property; e.g. a DeviceInterfaceGUIDs registry entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedProperty {
+    /// The registry type of the property's data (dwPropertyDataType); e.g. 1 for REG_SZ.
+    pub data_type: u32,
+
+    /// The name of the property.
+    pub name: String,
+
+    /// The property's raw data.
+    pub data: Vec<u8>,
+}
+
+/// Where to find an MS OS 2.0 descriptor set; from the device's BOS platform capability.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorSetInfo {
+    /// The minimum Windows version the set applies to (dwWindowsVersion).
+    pub windows_version: u32,
+
+    /// The total length of the descriptor set.
+    pub total_length: u16,
+
+    /// The bRequest used to fetch the descriptor set.
+    pub vendor_code: u8,
+
+    /// Nonzero if the device supports alternate enumeration (bAltEnumCode).
+    pub alternate_enumeration_code: u8,
+}
+
+/// A single descriptor from an MS OS 2.0 descriptor set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MsOs20Descriptor {
+    /// The descriptor's type (wDescriptorType).
+    pub descriptor_type: u16,
+
+    /// The descriptor's raw bytes, including its four-byte header.
+    pub data: Vec<u8>,
+}
+
+/// An MS OS 2.0 descriptor set, split into its individual descriptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MsOs20DescriptorSet {
+    /// The minimum Windows version the set applies to, from its header.
+    pub windows_version: u32,
+
+    /// Each descriptor after the set header, in order.
+    pub descriptors: Vec<MsOs20Descriptor>,
+}
+
+impl MsOs20DescriptorSet {
+    /// Parses an MS OS 2.0 descriptor set from its raw bytes.
+    pub fn parse(data: &[u8]) -> UsbResult<Self> {
+        // The set header is ten bytes: wLength, wDescriptorType (0), dwWindowsVersion, wTotalLength.
+        if data.len() < 10 || read_u16(data, 0) != 10 || read_u16(data, 2) != 0 {
+            return Err(Error::MalformedDescriptor);
+        }
+
+        let total_length = (read_u16(data, 8) as usize).min(data.len());
+        if total_length < 10 {
+            return Err(Error::MalformedDescriptor);
+        }
+
+        let mut descriptors = vec![];
+        let mut remaining = &data[10..total_length];
+
+        while remaining.len() >= 4 {
+            let length = read_u16(remaining, 0) as usize;
+            if length < 4 || length > remaining.len() {
+                return Err(Error::MalformedDescriptor);
+            }
+
+            let (descriptor, rest) = remaining.split_at(length);
+            descriptors.push(MsOs20Descriptor {
+                descriptor_type: read_u16(descriptor, 2),
+                data: descriptor.to_vec(),
+            });
+            remaining = rest;
+        }
+
+        Ok(Self {
+            windows_version: read_u32(data, 4),
+            descriptors,
+        })
+    }
+
+    /// Returns each compatible ID in the set (e.g. "WINUSB"), along with the first interface
+    /// of the function it applies to; or None, if it applies to the whole device.
+    pub fn compatible_ids(&self) -> Vec<(Option<u8>, String)> {
+        let mut current_function = None;
+        let mut compatible_ids = vec![];
+
+        for descriptor in &self.descriptors {
+            match descriptor.descriptor_type {
+                MS_OS_20_SUBSET_HEADER_CONFIGURATION => current_function = None,
+                MS_OS_20_SUBSET_HEADER_FUNCTION if descriptor.data.len() >= 5 => {
+                    current_function = Some(descriptor.data[4]);
+                }
+                MS_OS_20_FEATURE_COMPATIBLE_ID if descriptor.data.len() >= 12 => {
+                    compatible_ids.push((current_function, ascii_id(&descriptor.data[4..12])));
+                }
+                _ => (),
+            }
+        }
+
+        compatible_ids
+    }
+}
+
+/// Reads the device's MS OS 1.0 string descriptor; returning the vendor code it specifies,
+/// or None if the device doesn't have a valid one.
+pub fn read_os_string_vendor_code(device: &mut Device) -> UsbResult<Option<u8>> {
+    // The OS string is always requested with language ID 0.
+    let value = ((DescriptorType::String as u16) << 8) | OS_STRING_INDEX as u16;
+    let raw = match device.control_read_to_vec(
+        STANDARD_IN_FROM_DEVICE,
+        StandardDeviceRequest::GetDescriptor.into(),
+        value,
+        0,
+        0x12,
+        None,
+    ) {
+        Ok(raw) => raw,
+
+        // Devices without an OS string usually just stall the request.
+        Err(Error::Stalled) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+
+    // A valid OS string is 18 bytes: a header, the signature, the vendor code, and a pad byte.
+    if raw.len() < 18
+        || raw[1] != DescriptorType::String as u8
+        || &raw[2..16] != OS_STRING_SIGNATURE
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(raw[16]))
+}
+
+/// Reads and parses the device's MS OS 1.0 extended compat ID descriptor, using the vendor
+/// code from its OS string.
+pub fn read_extended_compat_ids(
+    device: &mut Device,
+    vendor_code: u8,
+) -> UsbResult<Vec<CompatibleFunction>> {
+    let raw = device.control_read_to_vec(
+        VENDOR_IN_FROM_DEVICE,
+        vendor_code,
+        0,
+        EXTENDED_COMPAT_ID_INDEX,
+        u16::MAX,
+        None,
+    )?;
+
+    parse_extended_compat_ids(&raw)
+}
+
+/// Parses an MS OS 1.0 extended compat ID descriptor from its raw bytes.
+pub fn parse_extended_compat_ids(raw: &[u8]) -> UsbResult<Vec<CompatibleFunction>> {
+    // The header is sixteen bytes, and is followed by a 24-byte section per function.
+    if raw.len() < 16 || read_u16(raw, 6) != EXTENDED_COMPAT_ID_INDEX {
+        return Err(Error::MalformedDescriptor);
+    }
+
+    let length = (read_u32(raw, 0) as usize).min(raw.len());
+    if length < 16 {
+        return Err(Error::MalformedDescriptor);
+    }
+
+    Ok(raw[16..length]
+        .chunks_exact(24)
+        .map(|function| CompatibleFunction {
+            first_interface: function[0],
+            compatible_id: ascii_id(&function[2..10]),
+            sub_compatible_id: ascii_id(&function[10..18]),
+        })
+        .collect())
+}
+
+/// Reads and parses the MS OS 1.0 extended properties for the given interface, using the
+/// vendor code from the device's OS string.
+pub fn read_extended_properties(
+    device: &mut Device,
+    vendor_code: u8,
+    interface_number: u8,
+) -> UsbResult<Vec<ExtendedProperty>> {
+    let request_type = RequestType {
+        direction: Direction::In,
+        request_type: Type::Vendor,
+        recipient: Recipient::Interface,
+    };
+    let raw = device.control_read_to_vec(
+        request_type,
+        vendor_code,
+        interface_number as u16,
+        EXTENDED_PROPERTIES_INDEX,
+        u16::MAX,
+        None,
+    )?;
+
+    parse_extended_properties(&raw)
+}
+
+/// Parses an MS OS 1.0 extended properties descriptor from its raw bytes.
+pub fn parse_extended_properties(raw: &[u8]) -> UsbResult<Vec<ExtendedProperty>> {
+    // The header is ten bytes; and each property carries its own size.
+    if raw.len() < 10 || read_u16(raw, 6) != EXTENDED_PROPERTIES_INDEX {
+        return Err(Error::MalformedDescriptor);
+    }
+
+    let length = (read_u32(raw, 0) as usize).min(raw.len());
+    if length < 10 {
+        return Err(Error::MalformedDescriptor);
+    }
+
+    let mut properties = vec![];
+    let mut remaining = &raw[10..length];
+
+    while remaining.len() >= 14 {
+        let size = read_u32(remaining, 0) as usize;
+        let name_length = read_u16(remaining, 8) as usize;
+        if size > remaining.len() || 10 + name_length + 4 > size {
+            return Err(Error::MalformedDescriptor);
+        }
+
+        let name = &remaining[10..10 + name_length];
+        let data_length = read_u32(remaining, 10 + name_length) as usize;
+        let data_start = 14 + name_length;
+        let data = remaining
+            .get(data_start..data_start + data_length)
+            .ok_or(Error::MalformedDescriptor)?;
+
+        properties.push(ExtendedProperty {
+            data_type: read_u32(remaining, 4),
+            name: utf16_string(name),
+            data: data.to_vec(),
+        });
+        remaining = &remaining[size..];
+    }
+
+    Ok(properties)
+}
+
+/// Returns the MS OS 2.0 descriptor sets a device advertises in its BOS descriptor.
+pub fn descriptor_set_info(bos: &BosDescriptor) -> Vec<DescriptorSetInfo> {
+    let Some(data) = bos.platform_capability(&MS_OS_20_PLATFORM_UUID) else {
+        return vec![];
+    };
+
+    data.chunks_exact(8)
+        .map(|info| DescriptorSetInfo {
+            windows_version: read_u32(info, 0),
+            total_length: read_u16(info, 4),
+            vendor_code: info[6],
+            alternate_enumeration_code: info[7],
+        })
+        .collect()
+}
+
+/// Fetches and parses the MS OS 2.0 descriptor set described by the provided information.
+pub fn read_descriptor_set(
+    device: &mut Device,
+    info: &DescriptorSetInfo,
+) -> UsbResult<MsOs20DescriptorSet> {
+    let raw = device.control_read_to_vec(
+        VENDOR_IN_FROM_DEVICE,
+        info.vendor_code,
+        0,
+        MS_OS_20_DESCRIPTOR_INDEX,
+        info.total_length,
+        None,
+    )?;
+
+    MsOs20DescriptorSet::parse(&raw)
+}
+
+/// Reads the device's BOS descriptor, and fetches the first MS OS 2.0 descriptor set it
+/// advertises; or returns None, if it doesn't advertise any.
+pub fn read_ms_os_20_descriptors(device: &mut Device) -> UsbResult<Option<MsOs20DescriptorSet>> {
+    let bos = device.read_bos_descriptor()?;
+    match descriptor_set_info(&bos).first() {
+        Some(info) => Ok(Some(read_descriptor_set(device, info)?)),
+        None => Ok(None),
+    }
+}
+
+/// Helper that reads a little-endian u32.
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Converts a null-padded ASCII ID (e.g. a compatible ID) into a string.
+fn ascii_id(raw: &[u8]) -> String {
+    raw.iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| byte as char)
+        .collect()
+}
+
+/// Converts a null-terminated UTF-16LE string into a Rust string.
+fn utf16_string(raw: &[u8]) -> String {
+    let characters: Vec<u16> = raw
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|&character| character != 0)
+        .collect();
+    String::from_utf16_lossy(&characters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an MS OS 2.0 set header, with the given wTotalLength.
+    fn set_header(total_length: u16) -> Vec<u8> {
+        let mut header = vec![10, 0, 0, 0, 0, 0, 3, 6];
+        header.extend(total_length.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn descriptor_set_parses() {
+        let mut data = set_header(30);
+        data.extend([20, 0, 3, 0]);
+        data.extend(b"WINUSB\0\0");
+        data.extend([0; 8]);
+
+        let set = MsOs20DescriptorSet::parse(&data).unwrap();
+        assert_eq!(set.windows_version, 0x0603_0000);
+        assert_eq!(set.compatible_ids(), vec![(None, "WINUSB".to_string())]);
+    }
+
+    #[test]
+    fn descriptor_set_with_short_total_length_is_malformed() {
+        for total_length in 0..10 {
+            let mut data = set_header(total_length);
+            data.extend([0, 0]);
+            assert_eq!(
+                MsOs20DescriptorSet::parse(&data),
+                Err(Error::MalformedDescriptor)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_descriptor_set_is_malformed() {
+        // The compatible ID claims twenty bytes, but the device only sent eight of them.
+        let mut data = set_header(30);
+        data.extend([20, 0, 3, 0]);
+        data.extend(b"WINU");
+
+        assert_eq!(
+            MsOs20DescriptorSet::parse(&data),
+            Err(Error::MalformedDescriptor)
+        );
+        assert_eq!(
+            MsOs20DescriptorSet::parse(&data[..6]),
+            Err(Error::MalformedDescriptor)
+        );
+    }
+
+    #[test]
+    fn extended_compat_ids_parse() {
+        let mut data = vec![40, 0, 0, 0, 0, 1, 4, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        data.extend([0, 1]);
+        data.extend(b"WINUSB\0\0");
+        data.extend([0; 14]);
+
+        let functions = parse_extended_compat_ids(&data).unwrap();
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].compatible_id, "WINUSB");
+    }
+
+    #[test]
+    fn extended_compat_ids_with_short_length_are_malformed() {
+        for length in 0..16u32 {
+            let mut data = length.to_le_bytes().to_vec();
+            data.extend([0, 1, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            assert_eq!(
+                parse_extended_compat_ids(&data),
+                Err(Error::MalformedDescriptor)
+            );
+        }
+        assert_eq!(
+            parse_extended_compat_ids(&[40, 0, 0, 0, 0, 1, 4]),
+            Err(Error::MalformedDescriptor)
+        );
+    }
+
+    #[test]
+    fn extended_properties_with_short_length_are_malformed() {
+        for length in 0..10u32 {
+            let mut data = length.to_le_bytes().to_vec();
+            data.extend([0, 1, 5, 0, 0, 0]);
+            assert_eq!(
+                parse_extended_properties(&data),
+                Err(Error::MalformedDescriptor)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_extended_property_is_malformed() {
+        // A property claiming 30 bytes, with a 4-byte name; cut off in the middle of its data.
+        let mut data = vec![34, 0, 0, 0, 0, 1, 5, 0, 1, 0];
+        data.extend([30, 0, 0, 0, 1, 0, 0, 0, 4, 0]);
+        data.extend(b"A\0\0\0");
+        data.extend([12, 0, 0, 0, 1, 2]);
+
+        assert_eq!(
+            parse_extended_properties(&data),
+            Err(Error::MalformedDescriptor)
+        );
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic() {
+        // A small xorshift generator; so the inputs are arbitrary, but the same every run.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..10_000 {
+            let length = (next() % 64) as usize;
+            let mut data: Vec<u8> = (0..length).map(|_| next() as u8).collect();
+
+            // Make most inputs past the signature checks, so the length handling is exercised.
+            if data.len() >= 8 {
+                data[0..4].copy_from_slice(&[10, 0, 0, 0]);
+            }
+            let _ = MsOs20DescriptorSet::parse(&data);
+            if data.len() >= 8 {
+                data[6] = 4;
+                data[7] = 0;
+            }
+            let _ = parse_extended_compat_ids(&data);
+            if data.len() >= 8 {
+                data[6] = 5;
+            }
+            let _ = parse_extended_properties(&data);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Pools of reusable read buffers; for high-bandwidth capture, where allocating a fresh
+//! buffer for every transfer adds up.
+
+use std::{
+    fmt::Debug,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, Weak},
+};
+
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{backend::TransferMemory, device::Device, ReadBuffer, UsbResult};
+
+/// The storage behind each pooled buffer.
+type SharedBuffer = Arc<RwLock<PoolMemory>>;
+
+/// The buffers a pool has ready to hand out.
+type FreeList = Mutex<Vec<SharedBuffer>>;
+
+/// A pool of equally-sized read buffers, which return to the pool once they're dropped.
+///
+/// Pools created with [BufferPool::for_endpoint] are filled with memory from the device's
+/// backend; e.g. usbfs-mapped memory on Android, which the kernel reads into directly, or
+/// IOKit low-latency buffers on macOS. Pools created with [BufferPool::new] use ordinary heap
+/// memory; which still saves an allocation per transfer.
+#[derive(Debug)]
+pub struct BufferPool {
+    /// The size of each buffer we hand out.
+    buffer_size: usize,
+
+    /// The most buffers we'll keep around while they're not in use.
+    capacity: usize,
+
+    /// The buffers that aren't currently in use.
+    free: Arc<FreeList>,
+}
+
+impl BufferPool {
+    /// Creates a pool of `buffer_size`-byte buffers; allocating `capacity` of them up front.
+    /// The pool can hand out more than `capacity` buffers, but won't keep more than that.
+    pub fn new(buffer_size: usize, capacity: usize) -> Self {
+        let free = (0..capacity)
+            .map(|_| PoolMemory::heap(buffer_size))
+            .collect();
+
+        Self {
+            buffer_size,
+            capacity,
+            free: Arc::new(Mutex::new(free)),
+        }
+    }
+
+    /// Creates a pool of `buffer_size`-byte buffers for reads from the given endpoint;
+    /// allocating `capacity` of them up front, from whatever memory the device's backend
+    /// transfers into most cheaply. Buffers handed out beyond `capacity` are heap memory.
+    ///
+    /// The backend's memory can stay tied to the device; e.g. on macOS, the endpoint's
+    /// interface stays open until every buffer from its pool has been dropped.
+    pub fn for_endpoint(
+        device: &Device,
+        endpoint: u8,
+        buffer_size: usize,
+        capacity: usize,
+    ) -> UsbResult<Self> {
+        let free = (0..capacity)
+            .map(|_| {
+                let memory = device.allocate_transfer_memory(endpoint | 0x80, buffer_size)?;
+                Ok(Arc::new(RwLock::new(PoolMemory(memory))))
+            })
+            .collect::<UsbResult<_>>()?;
+
+        Ok(Self {
+            buffer_size,
+            capacity,
+            free: Arc::new(Mutex::new(free)),
+        })
+    }
+
+    /// Returns the size of each buffer in the pool.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Returns the number of buffers ready to be handed out without allocating.
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    /// Takes a buffer from the pool; allocating a new one if none are free.
+    pub fn get(&self) -> PooledBuffer {
+        let buffer = self
+            .free
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| PoolMemory::heap(self.buffer_size));
+
+        PooledBuffer {
+            buffer,
+            pool: Arc::downgrade(&self.free),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// The memory behind a pooled buffer; which derefs to its bytes.
+pub struct PoolMemory(Box<dyn TransferMemory>);
+
+impl PoolMemory {
+    /// Allocates ordinary heap memory for a pool.
+    fn heap(size: usize) -> SharedBuffer {
+        Arc::new(RwLock::new(Self(Box::new(vec![0; size]))))
+    }
+}
+
+impl Debug for PoolMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolMemory")
+            .field("length", &self.len())
+            .finish()
+    }
+}
+
+impl Deref for PoolMemory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        (*self.0).as_ref()
+    }
+}
+
+impl DerefMut for PoolMemory {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        (*self.0).as_mut()
+    }
+}
+
+impl AsMut<[u8]> for PoolMemory {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// A buffer borrowed from a [BufferPool]; which goes back to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledBuffer {
+    /// The buffer itself.
+    buffer: SharedBuffer,
+
+    /// The pool to return to; which may have been dropped before we were.
+    pool: Weak<FreeList>,
+
+    /// The most buffers our pool will keep around.
+    capacity: usize,
+}
+
+impl PooledBuffer {
+    /// Returns a handle to this buffer that can be passed to e.g. [crate::device::Device::read_async].
+    pub fn read_buffer(&self) -> ReadBuffer {
+        self.buffer.clone()
+    }
+
+    /// Provides read access to the buffer's contents.
+    pub fn read(&self) -> RwLockReadGuard<'_, PoolMemory> {
+        self.buffer.read()
+    }
+
+    /// Provides write access to the buffer's contents.
+    pub fn write(&self) -> RwLockWriteGuard<'_, PoolMemory> {
+        self.buffer.write()
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        // If a transfer is still using our buffer, it's not safe to hand it out again;
+        // so we'll let it be freed once that transfer is done with it.
+        if Arc::strong_count(&self.buffer) > 1 {
+            return;
+        }
+
+        if let Some(pool) = self.pool.upgrade() {
+            let mut free = pool.lock().unwrap();
+            if free.len() < self.capacity {
+                free.push(Arc::clone(&self.buffer));
+            }
+        }
+    }
+}
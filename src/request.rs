@@ -1,5 +1,12 @@
 //! Tools for working with USB device requests.
 
+use std::time::Duration;
+
+use crate::{device::Device, Error, UsbResult};
+
+#[cfg(feature = "typed-control")]
+use crate::typed::FromBytes;
+
 /// Specifies the direction of a request.
 #[repr(u8)]
 #[derive(Copy, Debug, Clone, PartialEq, Eq)]
@@ -64,6 +71,277 @@ impl From<RequestType> for u8 {
     }
 }
 
+impl TryFrom<u8> for RequestType {
+    type Error = crate::Error;
+
+    /// Decodes a raw bmRequestType; failing if it uses the reserved request type.
+    fn try_from(raw: u8) -> Result<Self, Self::Error> {
+        let direction = if raw & 0x80 != 0 {
+            Direction::In
+        } else {
+            Direction::Out
+        };
+        let request_type = match (raw >> 5) & 0b11 {
+            0 => Type::Standard,
+            1 => Type::Class,
+            2 => Type::Vendor,
+            _ => return Err(crate::Error::InvalidArgument),
+        };
+        let recipient = match raw & 0b11111 {
+            0 => Recipient::Device,
+            1 => Recipient::Interface,
+            2 => Recipient::Endpoint,
+            3 => Recipient::Other,
+            _ => return Err(crate::Error::InvalidArgument),
+        };
+
+        Ok(Self {
+            direction,
+            request_type,
+            recipient,
+        })
+    }
+}
+
+/// A raw USB setup packet; laid out exactly as it's sent on the wire.
+///
+/// Mostly useful for tools that forward requests they didn't create; e.g. proxies.
+#[allow(non_snake_case)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct SetupPacket {
+    pub bmRequestType: u8,
+    pub bRequest: u8,
+    pub wValue: u16,
+    pub wIndex: u16,
+    pub wLength: u16,
+}
+
+impl SetupPacket {
+    /// The size of a setup packet on the wire.
+    pub const LENGTH: usize = 8;
+
+    /// Creates a new setup packet from its individual fields.
+    pub fn new(
+        request_type: RequestType,
+        request_number: u8,
+        value: u16,
+        index: u16,
+        length: u16,
+    ) -> Self {
+        Self {
+            bmRequestType: request_type.into(),
+            bRequest: request_number,
+            wValue: value,
+            wIndex: index,
+            wLength: length,
+        }
+    }
+
+    /// Parses a setup packet, as captured from the wire.
+    pub fn from_bytes(raw: &[u8; Self::LENGTH]) -> Self {
+        Self {
+            bmRequestType: raw[0],
+            bRequest: raw[1],
+            wValue: u16::from_le_bytes([raw[2], raw[3]]),
+            wIndex: u16::from_le_bytes([raw[4], raw[5]]),
+            wLength: u16::from_le_bytes([raw[6], raw[7]]),
+        }
+    }
+
+    /// Returns the setup packet, as it'd be sent on the wire.
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let [value_low, value_high] = self.wValue.to_le_bytes();
+        let [index_low, index_high] = self.wIndex.to_le_bytes();
+        let [length_low, length_high] = self.wLength.to_le_bytes();
+
+        [
+            self.bmRequestType,
+            self.bRequest,
+            value_low,
+            value_high,
+            index_low,
+            index_high,
+            length_low,
+            length_high,
+        ]
+    }
+
+    /// Returns the decoded request type; or an error if it uses reserved values.
+    pub fn request_type(&self) -> UsbResult<RequestType> {
+        self.bmRequestType.try_into()
+    }
+
+    /// Returns the direction of the request's data stage.
+    pub fn direction(&self) -> Direction {
+        if self.bmRequestType & 0x80 != 0 {
+            Direction::In
+        } else {
+            Direction::Out
+        }
+    }
+}
+
+/// A control request, described once and issued as often as needed; so a protocol's commands
+/// can be defined up front, rather than spelled out at each call site.
+///
+/// The builder methods are `const`, so commands can be constants; e.g.
+/// `const GET_STATUS: RequestBuilder = RequestBuilder::vendor_in(0x01).with_index(2);`, issued
+/// with `GET_STATUS.read_to_vec(&mut device, 64)`. Requests without a timeout use the
+/// device's default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestBuilder {
+    request_type: RequestType,
+    request_number: u8,
+    value: u16,
+    index: u16,
+    timeout: Option<Duration>,
+}
+
+impl RequestBuilder {
+    /// Describes a request of the given type and number; with zero for its value and index.
+    pub const fn new(request_type: RequestType, request_number: u8) -> Self {
+        Self {
+            request_type,
+            request_number,
+            value: 0,
+            index: 0,
+            timeout: None,
+        }
+    }
+
+    /// Describes a vendor request that reads from the device.
+    pub const fn vendor_in(request_number: u8) -> Self {
+        Self::new(VENDOR_IN_FROM_DEVICE, request_number)
+    }
+
+    /// Describes a vendor request that writes to the device.
+    pub const fn vendor_out(request_number: u8) -> Self {
+        Self::new(VENDOR_OUT_TO_DEVICE, request_number)
+    }
+
+    /// Describes a class request that reads from the given interface.
+    pub const fn class_in(request_number: u8, interface: u8) -> Self {
+        Self::new(CLASS_IN_FROM_INTERFACE, request_number).with_index(interface as u16)
+    }
+
+    /// Describes a class request that writes to the given interface.
+    pub const fn class_out(request_number: u8, interface: u8) -> Self {
+        Self::new(CLASS_OUT_TO_INTERFACE, request_number).with_index(interface as u16)
+    }
+
+    /// Sets the request's value (wValue).
+    pub const fn with_value(mut self, value: u16) -> Self {
+        self.value = value;
+        self
+    }
+
+    /// Sets the request's index (wIndex).
+    pub const fn with_index(mut self, index: u16) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Sets who the request is for; e.g. an interface, whose number goes in the index.
+    pub const fn with_recipient(mut self, recipient: Recipient) -> Self {
+        self.request_type.recipient = recipient;
+        self
+    }
+
+    /// Sets how long the request may take.
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the request's type.
+    pub const fn request_type(&self) -> RequestType {
+        self.request_type
+    }
+
+    /// Returns the setup packet for this request, with the given data-stage length.
+    pub fn setup_packet(&self, length: u16) -> SetupPacket {
+        SetupPacket::new(
+            self.request_type,
+            self.request_number,
+            self.value,
+            self.index,
+            length,
+        )
+    }
+
+    /// Issues the request, reading into the provided buffer. Returns the length read.
+    pub fn read(&self, device: &mut Device, buffer: &mut [u8]) -> UsbResult<usize> {
+        self.expect_direction(Direction::In)?;
+        device.control_read(
+            self.request_type,
+            self.request_number,
+            self.value,
+            self.index,
+            buffer,
+            self.timeout,
+        )
+    }
+
+    /// Issues the request, reading up to `max_length` bytes into a new vector.
+    pub fn read_to_vec(&self, device: &mut Device, max_length: u16) -> UsbResult<Vec<u8>> {
+        self.expect_direction(Direction::In)?;
+        device.control_read_to_vec(
+            self.request_type,
+            self.request_number,
+            self.value,
+            self.index,
+            max_length,
+            self.timeout,
+        )
+    }
+
+    /// Issues the request, reading up to `max_length` bytes into a caller-owned vector; see
+    /// [Device::control_read_into_vec]. Returns the length read.
+    pub fn read_into_vec(
+        &self,
+        device: &mut Device,
+        buffer: &mut Vec<u8>,
+        max_length: u16,
+    ) -> UsbResult<usize> {
+        device.control_read_into_vec(&self.setup_packet(max_length), buffer, self.timeout)
+    }
+
+    /// Issues the request, reading a fixed-layout value; see [Device::control_read_struct].
+    #[cfg(feature = "typed-control")]
+    pub fn read_struct<T: FromBytes>(&self, device: &mut Device) -> UsbResult<T> {
+        self.expect_direction(Direction::In)?;
+        device.control_read_struct(
+            self.request_type,
+            self.request_number,
+            self.value,
+            self.index,
+            self.timeout,
+        )
+    }
+
+    /// Issues the request, sending the provided data.
+    pub fn write(&self, device: &mut Device, data: &[u8]) -> UsbResult<()> {
+        self.expect_direction(Direction::Out)?;
+        device.control_write(
+            self.request_type,
+            self.request_number,
+            self.value,
+            self.index,
+            data,
+            self.timeout,
+        )
+    }
+
+    /// Fails with [Error::InvalidArgument] if this request's data goes the other way.
+    fn expect_direction(&self, direction: Direction) -> UsbResult<()> {
+        if self.request_type.direction != direction {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(())
+    }
+}
+
 //
 // Helper constants for common request types.
 //
@@ -141,6 +419,21 @@ impl From<StandardDeviceRequest> for u8 {
     }
 }
 
+/// Feature selectors for the standard SET_FEATURE and CLEAR_FEATURE requests.
+#[repr(u16)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum FeatureSelector {
+    EndpointHalt = 0,
+    DeviceRemoteWakeup = 1,
+    TestMode = 2,
+}
+
+impl From<FeatureSelector> for u16 {
+    fn from(feature: FeatureSelector) -> u16 {
+        feature as u16
+    }
+}
+
 #[repr(u8)]
 #[derive(Copy, Debug, Clone, PartialEq, Eq)]
 pub enum DescriptorType {
@@ -149,6 +442,97 @@ pub enum DescriptorType {
     String = 3,
     Interface = 4,
     Endpoint = 5,
+    DeviceQualifier = 6,
+    OtherSpeedConfiguration = 7,
+    InterfacePower = 8,
+    Otg = 9,
+    Debug = 10,
+    InterfaceAssociation = 11,
+    Bos = 15,
+    DeviceCapability = 16,
+
+    // Class-specific descriptors.
+    Hid = 0x21,
+    Report = 0x22,
+    Physical = 0x23,
+    Hub = 0x29,
+    SuperSpeedHub = 0x2a,
+    SuperSpeedEndpointCompanion = 0x30,
+}
+
+/// Base class codes, as used in bDeviceClass and bInterfaceClass.
+/// See the USB-IF's "Defined Class Codes" list.
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClassCode {
+    /// The device's class is defined by each of its interfaces.
+    PerInterface = 0x00,
+    Audio = 0x01,
+    /// Communications Device Class control interfaces; e.g. CDC-ACM serial ports.
+    Cdc = 0x02,
+    Hid = 0x03,
+    Physical = 0x05,
+    Image = 0x06,
+    Printer = 0x07,
+    MassStorage = 0x08,
+    Hub = 0x09,
+    CdcData = 0x0a,
+    SmartCard = 0x0b,
+    ContentSecurity = 0x0d,
+    Video = 0x0e,
+    PersonalHealthcare = 0x0f,
+    AudioVideo = 0x10,
+    Billboard = 0x11,
+    TypeCBridge = 0x12,
+    I3c = 0x3c,
+    Diagnostic = 0xdc,
+    WirelessController = 0xe0,
+    Miscellaneous = 0xef,
+    /// Application-specific interfaces; e.g. DFU, IrDA bridges, and USBTMC.
+    ApplicationSpecific = 0xfe,
+    VendorSpecific = 0xff,
+}
+
+impl From<ClassCode> for u8 {
+    fn from(class: ClassCode) -> u8 {
+        class as u8
+    }
+}
+
+impl TryFrom<u8> for ClassCode {
+    type Error = crate::Error;
+
+    /// Decodes a raw class code; failing if it isn't one the USB-IF has defined.
+    fn try_from(raw: u8) -> Result<Self, Self::Error> {
+        use ClassCode::*;
+
+        Ok(match raw {
+            0x00 => PerInterface,
+            0x01 => Audio,
+            0x02 => Cdc,
+            0x03 => Hid,
+            0x05 => Physical,
+            0x06 => Image,
+            0x07 => Printer,
+            0x08 => MassStorage,
+            0x09 => Hub,
+            0x0a => CdcData,
+            0x0b => SmartCard,
+            0x0d => ContentSecurity,
+            0x0e => Video,
+            0x0f => PersonalHealthcare,
+            0x10 => AudioVideo,
+            0x11 => Billboard,
+            0x12 => TypeCBridge,
+            0x3c => I3c,
+            0xdc => Diagnostic,
+            0xe0 => WirelessController,
+            0xef => Miscellaneous,
+            0xfe => ApplicationSpecific,
+            0xff => VendorSpecific,
+            _ => return Err(crate::Error::InvalidArgument),
+        })
+    }
 }
 
 impl From<&DescriptorType> for u8 {
@@ -0,0 +1,110 @@
+//! Cumulative statistics about the transfers a device has performed; for exporting health
+//! metrics from long-running services. See [crate::device::Device::stats].
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::Error;
+
+/// Counters for the transfers performed on a single endpoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EndpointStats {
+    /// The number of transfers that have completed, successfully or otherwise.
+    pub transfers: u64,
+
+    /// The number of transfers that failed.
+    pub errors: u64,
+
+    /// The total data moved; into the host for IN endpoints, or out of it for OUT endpoints.
+    pub bytes: u64,
+
+    /// The total time transfers took, from submission to completion.
+    pub total_latency: Duration,
+}
+
+impl EndpointStats {
+    /// Returns the average time from submission to completion; or None if nothing has completed.
+    pub fn average_latency(&self) -> Option<Duration> {
+        let transfers = u32::try_from(self.transfers)
+            .ok()
+            .filter(|&count| count > 0)?;
+        Some(self.total_latency / transfers)
+    }
+}
+
+/// Cumulative counters for everything a device has done since it was opened, or since its
+/// statistics were last reset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceStats {
+    /// Counters for each endpoint, by address. Control requests count against 0x00 or 0x80,
+    /// depending on their direction.
+    pub endpoints: BTreeMap<u8, EndpointStats>,
+
+    /// The number of failed transfers, by the kind of error; e.g. "TimedOut" or "Stalled".
+    pub errors: BTreeMap<String, u64>,
+
+    /// The number of stalls that have been cleared; explicitly, or by our stall policy.
+    pub stalls_cleared: u64,
+}
+
+impl DeviceStats {
+    /// Returns the total data read from the device.
+    pub fn bytes_in(&self) -> u64 {
+        self.endpoints
+            .iter()
+            .filter(|(address, _)| *address & 0x80 != 0)
+            .map(|(_, stats)| stats.bytes)
+            .sum()
+    }
+
+    /// Returns the total data written to the device.
+    pub fn bytes_out(&self) -> u64 {
+        self.endpoints
+            .iter()
+            .filter(|(address, _)| *address & 0x80 == 0)
+            .map(|(_, stats)| stats.bytes)
+            .sum()
+    }
+
+    /// Returns the number of transfers that have completed, on any endpoint.
+    pub fn transfers(&self) -> u64 {
+        self.endpoints.values().map(|stats| stats.transfers).sum()
+    }
+
+    /// Returns the average completion latency across every endpoint; or None if nothing
+    /// has completed.
+    pub fn average_latency(&self) -> Option<Duration> {
+        let total = self
+            .endpoints
+            .values()
+            .fold(EndpointStats::default(), |total, stats| EndpointStats {
+                transfers: total.transfers + stats.transfers,
+                total_latency: total.total_latency + stats.total_latency,
+                ..total
+            });
+        total.average_latency()
+    }
+
+    /// Counts a completed transfer on the given endpoint.
+    pub(crate) fn record_transfer(
+        &mut self,
+        endpoint: u8,
+        result: Result<usize, &Error>,
+        latency: Duration,
+    ) {
+        let stats = self.endpoints.entry(endpoint).or_default();
+        stats.transfers += 1;
+        stats.total_latency += latency;
+
+        match result {
+            Ok(length) => stats.bytes += length as u64,
+            Err(error) => {
+                stats.errors += 1;
+
+                // We count errors by variant, so e.g. every OsError lands in the same bucket.
+                let name = format!("{error:?}");
+                let kind = name.split('(').next().unwrap_or_default().to_owned();
+                *self.errors.entry(kind).or_default() += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Stream adapters over endpoints; for plugging devices into existing async codec and
+//! framing stacks, and for consuming interrupt reports.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use futures_io::{AsyncRead, AsyncWrite};
+use parking_lot::RwLock;
+
+use crate::{
+    device::Device,
+    futures::{ReadCompletion, UsbFuture, WriteCompletion},
+    UsbResult,
+};
+
+/// The number of reads an [InterruptStream] keeps queued, unless told otherwise.
+const DEFAULT_QUEUE_DEPTH: usize = 4;
+
+/// A [Stream] of reports from an interrupt IN endpoint. Created by [Device::interrupt_stream].
+///
+/// Keeps several reads queued at once, so no reports are missed between polls; and yields
+/// each report in the order it arrived.
+pub struct InterruptStream<'a> {
+    /// The device the endpoint belongs to.
+    device: &'a mut Device,
+
+    /// The address of the endpoint we read from.
+    endpoint: u8,
+
+    /// The size of each read we queue.
+    report_length: usize,
+
+    /// The number of reads we try to keep queued.
+    queue_depth: usize,
+
+    /// Our queued reads, oldest first.
+    queued: VecDeque<UsbFuture<ReadCompletion>>,
+}
+
+impl<'a> InterruptStream<'a> {
+    /// Creates a stream that reads `report_length`-byte reports from the provided endpoint.
+    pub(crate) fn new(device: &'a mut Device, endpoint: u8, report_length: usize) -> Self {
+        Self {
+            device,
+            endpoint: endpoint | 0x80,
+            report_length,
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            queued: VecDeque::new(),
+        }
+    }
+
+    /// Sets the number of reads to keep queued; which must be at least one.
+    /// Takes effect as queued reads complete.
+    pub fn set_queue_depth(&mut self, depth: usize) {
+        self.queue_depth = depth.max(1);
+    }
+
+    /// Returns the number of reads we try to keep queued.
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    /// Queues reads until we have as many in flight as we'd like.
+    fn fill_queue(&mut self) -> UsbResult<()> {
+        while self.queued.len() < self.queue_depth {
+            let buffer = Arc::new(RwLock::new(vec![0; self.report_length]));
+            let future = self.device.read_async(self.endpoint, buffer, None)?;
+            self.queued.push_back(future);
+        }
+
+        Ok(())
+    }
+}
+
+impl Stream for InterruptStream<'_> {
+    type Item = UsbResult<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        // Make sure we have reads in flight; if we can't queue any, report why.
+        if let Err(error) = this.fill_queue() {
+            if this.queued.is_empty() {
+                return Poll::Ready(Some(Err(error)));
+            }
+        }
+
+        // Reports come back in the order we queued their reads; so we only wait on the oldest.
+        let future = this.queued.front_mut().unwrap();
+        let result = std::task::ready!(Pin::new(future).poll(cx));
+        this.queued.pop_front();
+
+        Poll::Ready(Some(result.map(|completion| completion.to_vec())))
+    }
+}
+
+/// Adapts a bulk IN/OUT endpoint pair into an [AsyncRead] + [AsyncWrite] byte stream.
+///
+/// Reads are issued `buffer_size` bytes at a time, with any data the caller doesn't consume
+/// held until the next read. Writes are collected until `buffer_size` bytes are waiting, or
+/// until the stream is flushed.
+pub struct EndpointStream {
+    /// The device our endpoints belong to.
+    device: Device,
+
+    /// The address of the endpoint we read from.
+    in_endpoint: u8,
+
+    /// The address of the endpoint we write to.
+    out_endpoint: u8,
+
+    /// How much data we read, or collect before writing, at a time.
+    buffer_size: usize,
+
+    /// The buffer our reads land in.
+    read_target: Arc<RwLock<Vec<u8>>>,
+
+    /// Data we've read, but haven't yet handed to our caller.
+    read_pending: Vec<u8>,
+
+    /// How much of `read_pending` our caller has already consumed.
+    read_position: usize,
+
+    /// The read we're currently waiting on, if any.
+    read_future: Option<UsbFuture<ReadCompletion>>,
+
+    /// Data our caller has written, but that we haven't yet sent.
+    write_pending: Vec<u8>,
+
+    /// The write we're currently waiting on, if any.
+    write_future: Option<UsbFuture<WriteCompletion>>,
+}
+
+impl EndpointStream {
+    /// Creates a stream over the provided endpoints, moving `buffer_size` bytes at a time.
+    /// For best throughput, `buffer_size` should be a multiple of the endpoints' packet size.
+    pub fn new(device: Device, in_endpoint: u8, out_endpoint: u8, buffer_size: usize) -> Self {
+        Self {
+            device,
+            in_endpoint: in_endpoint | 0x80,
+            out_endpoint: out_endpoint & 0x7f,
+            buffer_size,
+            read_target: Arc::new(RwLock::new(vec![0; buffer_size])),
+            read_pending: Vec::with_capacity(buffer_size),
+            read_position: 0,
+            read_future: None,
+            write_pending: Vec::with_capacity(buffer_size),
+            write_future: None,
+        }
+    }
+
+    /// Returns the device underlying this stream.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns the device underlying this stream, mutably; e.g. to issue control requests.
+    pub fn device_mut(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    /// Gives back the device underlying this stream. Any data that was buffered, but not yet
+    /// read or sent, is discarded.
+    pub fn into_inner(self) -> Device {
+        self.device
+    }
+
+    /// Drives our outgoing data towards the device, until it's all been sent.
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            // If we have a write in flight, wait for it to finish...
+            if let Some(future) = self.write_future.as_mut() {
+                let result = std::task::ready!(Pin::new(future).poll(cx));
+                self.write_future = None;
+
+                let sent = result.map_err(io::Error::from)?.length;
+                self.write_pending
+                    .drain(..sent.min(self.write_pending.len()));
+            }
+
+            // ... and if we've nothing left to send, we're done.
+            if self.write_pending.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let data = Arc::new(self.write_pending.clone());
+            let future = self
+                .device
+                .write_async(self.out_endpoint, data, None)
+                .map_err(io::Error::from)?;
+            self.write_future = Some(future);
+        }
+    }
+}
+
+impl AsyncRead for EndpointStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        // If we don't have any data waiting for our caller, go get some.
+        while this.read_position >= this.read_pending.len() {
+            let future = match this.read_future.as_mut() {
+                Some(future) => future,
+                None => {
+                    let future = this
+                        .device
+                        .read_async(this.in_endpoint, this.read_target.clone(), None)
+                        .map_err(io::Error::from)?;
+                    this.read_future.insert(future)
+                }
+            };
+
+            let result = std::task::ready!(Pin::new(future).poll(cx));
+            this.read_future = None;
+            let length = result.map_err(io::Error::from)?.length;
+
+            // A zero-length packet carries no data; so we'll just go read again.
+            let target = this.read_target.read();
+            this.read_pending.clear();
+            this.read_pending
+                .extend_from_slice(&target[..length.min(target.len())]);
+            this.read_position = 0;
+        }
+
+        let available = &this.read_pending[this.read_position..];
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        this.read_position += length;
+
+        Poll::Ready(Ok(length))
+    }
+}
+
+impl AsyncWrite for EndpointStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        // If our buffer's full, we'll need to send it before we can take any more.
+        if this.write_pending.len() >= this.buffer_size {
+            std::task::ready!(this.poll_send(cx))?;
+        }
+
+        let length = (this.buffer_size - this.write_pending.len()).min(buf.len());
+        this.write_pending.extend_from_slice(&buf[..length]);
+
+        Poll::Ready(Ok(length))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send(cx)
+    }
+}
@@ -0,0 +1,141 @@
+//! Linux's sysfs controls over USB devices; which work on any device, whichever backend
+//! found it. See [crate::Host::set_device_authorized].
+
+use std::{
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+use crate::{
+    device::DeviceInformation,
+    error::{io_error, Error, UsbResult},
+};
+
+/// Where sysfs presents each USB device, and each bus's root hub.
+pub(crate) const USB_DEVICES: &str = "/sys/bus/usb/devices";
+
+/// Allows or forbids the kernel from using the given device, via its `authorized` attribute.
+/// Devices live in directories named for their port path; e.g. `1-2.3`.
+pub(crate) fn set_device_authorized(
+    root: &Path,
+    information: &DeviceInformation,
+    authorized: bool,
+) -> UsbResult<()> {
+    // Without knowing where the device is plugged in, we can't find its directory.
+    let port_path = information.port_path().ok_or(Error::Unsupported)?;
+    write_flag(
+        &root.join(port_path.to_string()).join("authorized"),
+        authorized,
+    )
+}
+
+/// Sets whether devices newly connected to the given bus are authorized, via the
+/// `authorized_default` attribute of the bus's root hub; which lives in e.g. `usb1`.
+pub(crate) fn set_bus_authorized_default(root: &Path, bus: u8, authorized: bool) -> UsbResult<()> {
+    write_flag(
+        &root.join(format!("usb{bus}")).join("authorized_default"),
+        authorized,
+    )
+}
+
+/// Writes a boolean attribute, as sysfs expects it. The attribute must already exist;
+/// sysfs doesn't let us create files, so one that's missing means the device is gone.
+fn write_flag(path: &Path, value: bool) -> UsbResult<()> {
+    let write = || {
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .write_all(if value { b"1" } else { b"0" })
+    };
+
+    write().map_err(|error| match error.kind() {
+        ErrorKind::NotFound => Error::DeviceNotFound,
+        ErrorKind::PermissionDenied => Error::PermissionDenied,
+        _ => io_error(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::topology::PortPath;
+
+    /// Creates a scratch sysfs tree, with a device at port 1-2.3 and the root hub of bus 1;
+    /// whose attributes start out as `1`.
+    fn sysfs_tree(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("usrs-sysfs-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        fs::create_dir_all(root.join("1-2.3")).unwrap();
+        fs::create_dir_all(root.join("usb1")).unwrap();
+        fs::write(root.join("1-2.3/authorized"), "1").unwrap();
+        fs::write(root.join("usb1/authorized_default"), "1").unwrap();
+        root
+    }
+
+    /// Builds the information for a device at the given port path, if any.
+    fn device_at(port_path: Option<PortPath>) -> DeviceInformation {
+        let mut information = DeviceInformation::new(0x1209, 0x0001, None, None, None);
+        information.set_port_path(port_path);
+        information
+    }
+
+    #[test]
+    fn devices_are_found_by_port_path() {
+        let root = sysfs_tree("device");
+        let device = device_at(Some(PortPath::new(1, vec![2, 3])));
+
+        set_device_authorized(&root, &device, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("1-2.3/authorized")).unwrap(),
+            "0"
+        );
+        set_device_authorized(&root, &device, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("1-2.3/authorized")).unwrap(),
+            "1"
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn devices_we_cant_find_are_reported() {
+        let root = sysfs_tree("missing");
+
+        let elsewhere = device_at(Some(PortPath::new(1, vec![4])));
+        assert_eq!(
+            set_device_authorized(&root, &elsewhere, false),
+            Err(Error::DeviceNotFound)
+        );
+        assert!(!root.join("1-4").exists());
+
+        let nowhere = device_at(None);
+        assert_eq!(
+            set_device_authorized(&root, &nowhere, false),
+            Err(Error::Unsupported)
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn bus_defaults_are_set_on_the_root_hub() {
+        let root = sysfs_tree("bus");
+
+        set_bus_authorized_default(&root, 1, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("usb1/authorized_default")).unwrap(),
+            "0"
+        );
+        assert_eq!(
+            set_bus_authorized_default(&root, 2, false),
+            Err(Error::DeviceNotFound)
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
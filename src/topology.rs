@@ -0,0 +1,239 @@
+//! The physical layout of the USB buses: which devices are plugged into which hub ports.
+//! See [crate::Host::topology].
+
+use std::fmt;
+
+use crate::{device::DeviceInformation, request::ClassCode};
+
+/// Where a device is plugged in: its bus, and the chain of hub ports leading to it from the
+/// bus's root hub. The root hub itself has an empty chain.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PortPath {
+    bus: u8,
+    ports: Vec<u8>,
+}
+
+impl PortPath {
+    /// Creates a port path from a bus number and a chain of port numbers.
+    pub fn new(bus: u8, ports: Vec<u8>) -> Self {
+        Self { bus, ports }
+    }
+
+    /// Returns the number of the bus the device is on.
+    pub fn bus(&self) -> u8 {
+        self.bus
+    }
+
+    /// Returns the port numbers leading from the root hub to the device; e.g. `[2, 3]` for
+    /// port 3 of the hub plugged into the root hub's port 2.
+    pub fn ports(&self) -> &[u8] {
+        &self.ports
+    }
+
+    /// Returns the path of the hub this device is plugged into; or None for a root hub.
+    pub fn parent(&self) -> Option<PortPath> {
+        let (_, parent_ports) = self.ports.split_last()?;
+        Some(Self::new(self.bus, parent_ports.to_vec()))
+    }
+}
+
+/// Paths print as e.g. "1-2.3"; the same form Linux uses in sysfs.
+impl fmt::Display for PortPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.bus)?;
+
+        for (index, port) in self.ports.iter().enumerate() {
+            let separator = if index == 0 { '-' } else { '.' };
+            write!(f, "{separator}{port}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A device in the topology, and everything plugged into it.
+#[derive(Debug, Clone)]
+pub struct TopologyNode {
+    /// Where the device is plugged in.
+    pub path: PortPath,
+
+    /// The device's information; or None for hubs the OS didn't show us, e.g. root hubs.
+    pub information: Option<DeviceInformation>,
+
+    /// The devices plugged into this one's ports, ordered by port.
+    pub children: Vec<TopologyNode>,
+}
+
+impl TopologyNode {
+    /// Returns the number of the parent hub port this device is plugged into; or None for
+    /// a root hub.
+    pub fn port(&self) -> Option<u8> {
+        self.path.ports().last().copied()
+    }
+
+    /// Returns true iff this device is a hub.
+    pub fn is_hub(&self) -> bool {
+        let is_hub_class = self
+            .information
+            .as_ref()
+            .and_then(DeviceInformation::cached_device_descriptor)
+            .is_some_and(|descriptor| descriptor.device_class == ClassCode::Hub as u8);
+
+        is_hub_class || !self.children.is_empty()
+    }
+
+    /// Finds the node for the given path beneath this one.
+    fn find(&self, path: &PortPath) -> Option<&TopologyNode> {
+        if self.path == *path {
+            return Some(self);
+        }
+
+        // Only descend along the chain that leads to our target.
+        let next_port = *path.ports().get(self.path.ports().len())?;
+        self.children
+            .iter()
+            .find(|child| child.port() == Some(next_port))?
+            .find(path)
+    }
+
+    /// Finds the node for the given path beneath this one, creating it and any missing
+    /// hubs along the way.
+    fn find_or_insert(&mut self, path: &PortPath) -> &mut TopologyNode {
+        if self.path == *path {
+            return self;
+        }
+
+        let depth = self.path.ports().len() + 1;
+        let child_path = PortPath::new(path.bus(), path.ports()[..depth].to_vec());
+
+        let index = match self
+            .children
+            .binary_search_by(|child| child.path.cmp(&child_path))
+        {
+            Ok(index) => index,
+            Err(index) => {
+                self.children.insert(index, TopologyNode::empty(child_path));
+                index
+            }
+        };
+
+        self.children[index].find_or_insert(path)
+    }
+
+    /// Creates a node for a device we don't (yet) have information about.
+    fn empty(path: PortPath) -> Self {
+        Self {
+            path,
+            information: None,
+            children: vec![],
+        }
+    }
+}
+
+/// The layout of every bus on the system; see [crate::Host::topology].
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    /// The root hub of each bus, ordered by bus number.
+    pub buses: Vec<TopologyNode>,
+
+    /// Devices whose place in the topology the backend couldn't tell us.
+    pub unplaced: Vec<DeviceInformation>,
+}
+
+impl Topology {
+    /// Arranges the given devices into a tree, by their port paths.
+    pub(crate) fn from_devices(devices: Vec<DeviceInformation>) -> Self {
+        let mut topology = Self::default();
+
+        for device in devices {
+            let Some(path) = device.port_path().cloned() else {
+                topology.unplaced.push(device);
+                continue;
+            };
+
+            // Find the bus's root hub, adding it if this is the first we've seen of the bus...
+            let index = match topology
+                .buses
+                .binary_search_by_key(&path.bus(), |root| root.path.bus())
+            {
+                Ok(index) => index,
+                Err(index) => {
+                    let root = TopologyNode::empty(PortPath::new(path.bus(), vec![]));
+                    topology.buses.insert(index, root);
+                    index
+                }
+            };
+
+            // ... and hang the device off of it.
+            topology.buses[index].find_or_insert(&path).information = Some(device);
+        }
+
+        topology
+    }
+
+    /// Returns the node plugged in at the given path; e.g. to find "the device on hub 2,
+    /// port 3".
+    pub fn find(&self, path: &PortPath) -> Option<&TopologyNode> {
+        self.buses
+            .iter()
+            .find(|root| root.path.bus() == path.bus())?
+            .find(path)
+    }
+
+    /// Returns the hub the given device is plugged into.
+    pub fn parent_of(&self, device: &DeviceInformation) -> Option<&TopologyNode> {
+        self.find(&device.parent()?)
+    }
+
+    /// Returns every device in the topology, in depth-first order.
+    pub fn devices(&self) -> impl Iterator<Item = &DeviceInformation> {
+        let mut pending: Vec<&TopologyNode> = self.buses.iter().rev().collect();
+
+        std::iter::from_fn(move || loop {
+            let node = pending.pop()?;
+            pending.extend(node.children.iter().rev());
+
+            if let Some(information) = &node.information {
+                return Some(information);
+            }
+        })
+    }
+}
+
+/// Prints the topology as an indented tree; e.g. for lsusb-style tools.
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_node(f: &mut fmt::Formatter<'_>, node: &TopologyNode) -> fmt::Result {
+            let indent = node.path.ports().len() * 2;
+            write!(f, "{:indent$}{}", "", node.path)?;
+
+            match &node.information {
+                Some(information) => {
+                    write!(
+                        f,
+                        " {:04x}:{:04x}",
+                        information.vendor_id, information.product_id
+                    )?;
+                    if let Some(product) = &information.product {
+                        write!(f, " {product}")?;
+                    }
+                    writeln!(f)?;
+                }
+                None => writeln!(f, " (hub)")?,
+            }
+
+            node.children
+                .iter()
+                .try_for_each(|child| write_node(f, child))
+        }
+
+        for root in &self.buses {
+            write_node(f, root)?;
+        }
+        for device in &self.unplaced {
+            writeln!(f, "? {:04x}:{:04x}", device.vendor_id, device.product_id)?;
+        }
+
+        Ok(())
+    }
+}
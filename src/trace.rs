@@ -0,0 +1,97 @@
+//! Instrumentation for operations: optional spans via the `tracing` crate, enabled by the
+//! `tracing` feature; and, for transfers, the device's statistics.
+//!
+//! Each traced operation gets a span carrying what it was and what it targeted; once it's
+//! done, we record how long it took and how it went. Without the feature, the spans compile
+//! away to nothing.
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{stats::DeviceStats, Error};
+
+/// Tracks a single operation, from when it starts until its result is known.
+#[derive(Debug)]
+pub(crate) struct Trace {
+    /// The span the operation runs in.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+
+    /// When the operation started.
+    start: Instant,
+
+    /// For transfers, the statistics to count the transfer in, and the endpoint it targets.
+    stats: Option<(Arc<Mutex<DeviceStats>>, u8)>,
+}
+
+impl Trace {
+    /// Starts tracing a non-transfer operation; e.g. enumeration, or claiming an interface.
+    #[allow(unused_variables)]
+    pub(crate) fn operation(operation: &'static str) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "usb_operation",
+                operation,
+                duration_us = tracing::field::Empty,
+            ),
+            start: Instant::now(),
+            stats: None,
+        }
+    }
+
+    /// Starts tracing a transfer of up to `length` bytes, to or from the given endpoint.
+    #[allow(unused_variables)]
+    pub(crate) fn transfer(operation: &'static str, endpoint: u8, length: usize) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "usb_transfer",
+                operation,
+                endpoint = format_args!("{endpoint:#04x}"),
+                length,
+                duration_us = tracing::field::Empty,
+            ),
+            start: Instant::now(),
+            stats: None,
+        }
+    }
+
+    /// Counts the transfer being traced, once it finishes, in the provided statistics.
+    pub(crate) fn counted_in(mut self, stats: &Arc<Mutex<DeviceStats>>, endpoint: u8) -> Self {
+        self.stats = Some((Arc::clone(stats), endpoint));
+        self
+    }
+
+    /// Records that a transfer has finished; with the amount of data it moved, on success.
+    pub(crate) fn finish_transfer(mut self, result: Result<usize, &Error>) {
+        if let Some((stats, endpoint)) = self.stats.take() {
+            let latency = self.start.elapsed();
+            stats
+                .lock()
+                .unwrap()
+                .record_transfer(endpoint, result, latency);
+        }
+
+        self.finish(result);
+    }
+
+    /// Records that the operation has finished, with the provided result.
+    #[allow(unused_variables)]
+    pub(crate) fn finish<T: Debug>(self, result: Result<T, &Error>) {
+        #[cfg(feature = "tracing")]
+        {
+            let duration = self.start.elapsed().as_micros() as u64;
+            self.span.record("duration_us", duration);
+
+            let _entered = self.span.enter();
+            match result {
+                Ok(value) => tracing::debug!(result = ?value, "completed"),
+                Err(error) => tracing::debug!(%error, "failed"),
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Reusable transfers; for high-rate bulk and interrupt loops.
+//!
+//! Each call to e.g. [Device::read_async] creates its own buffer and future state. A [Transfer]
+//! owns both, instead; so it can be submitted over and over, with only a small completion
+//! callback allocated per submission.
+
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use parking_lot::RwLock;
+
+use crate::{device::Device, Error, UsbResult};
+
+/// The buffer owned by a transfer; which depends on its direction.
+#[derive(Debug)]
+enum TransferBuffer {
+    /// A buffer for data read from an IN endpoint.
+    In(Arc<RwLock<Vec<u8>>>),
+
+    /// The data to be written to an OUT endpoint.
+    Out(Arc<Vec<u8>>),
+}
+
+/// Completion state shared between a transfer and the backend performing it.
+#[derive(Debug, Default)]
+struct Completion {
+    /// The result of the most recent submission; or None if it hasn't completed yet.
+    result: Mutex<Option<UsbResult<usize>>>,
+
+    /// Signaled each time a submission completes.
+    condvar: Condvar,
+}
+
+impl Completion {
+    /// Records the result of a submission, and wakes anyone waiting on it.
+    fn complete(&self, result: UsbResult<usize>) {
+        *self.result.lock().unwrap() = Some(result);
+        self.condvar.notify_all();
+    }
+}
+
+/// A bulk or interrupt transfer that owns its buffer, and can be submitted repeatedly.
+#[derive(Debug)]
+pub struct Transfer {
+    /// The address of the endpoint this transfer targets.
+    endpoint: u8,
+
+    /// The data to be written, or the space for data to be read.
+    buffer: TransferBuffer,
+
+    /// Our completion state; shared with the backend while we're in flight.
+    completion: Arc<Completion>,
+
+    /// True iff we've been submitted, and haven't yet been waited on.
+    in_flight: bool,
+
+    /// The timeout applied to each submission; or None to use the device's default.
+    timeout: Option<Duration>,
+}
+
+impl Transfer {
+    /// Creates a transfer that reads up to `length` bytes from the given IN endpoint.
+    pub fn new_read(endpoint: u8, length: usize) -> Self {
+        Self::new(
+            endpoint | 0x80,
+            TransferBuffer::In(Arc::new(RwLock::new(vec![0; length]))),
+        )
+    }
+
+    /// Creates a transfer that writes the provided data to the given OUT endpoint.
+    pub fn new_write(endpoint: u8, data: Vec<u8>) -> Self {
+        Self::new(endpoint & 0x7f, TransferBuffer::Out(Arc::new(data)))
+    }
+
+    /// Creates an idle transfer around the provided buffer.
+    fn new(endpoint: u8, buffer: TransferBuffer) -> Self {
+        Self {
+            endpoint,
+            buffer,
+            completion: Arc::default(),
+            in_flight: false,
+            timeout: None,
+        }
+    }
+
+    /// Returns the address of the endpoint this transfer targets.
+    pub fn endpoint(&self) -> u8 {
+        self.endpoint
+    }
+
+    /// Sets the timeout applied to each submission. If None, the device's default is used.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns true iff the transfer has been submitted, and not yet waited on.
+    pub fn is_in_flight(&self) -> bool {
+        self.in_flight
+    }
+
+    /// Returns true iff the transfer's most recent submission has completed.
+    pub fn is_complete(&self) -> bool {
+        self.completion.result.lock().unwrap().is_some()
+    }
+
+    /// Submits the transfer to the provided device. Fails with [Error::InvalidArgument] if
+    /// it's already in flight.
+    pub fn submit(&mut self, device: &mut Device) -> UsbResult<()> {
+        if self.in_flight {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Clear out our previous result, so we can tell when this submission is done...
+        *self.completion.result.lock().unwrap() = None;
+
+        let completion = Arc::clone(&self.completion);
+        let callback = Box::new(move |result| completion.complete(result));
+
+        // ... and hand the backend another reference to our buffer.
+        match &self.buffer {
+            TransferBuffer::In(buffer) => {
+                device.submit_read(self.endpoint, buffer.clone(), callback, self.timeout)?
+            }
+            TransferBuffer::Out(data) => {
+                device.submit_write(self.endpoint, data.clone(), callback, self.timeout)?
+            }
+        }
+
+        self.in_flight = true;
+        Ok(())
+    }
+
+    /// Blocks until the transfer's current submission completes, and returns its result.
+    /// Fails with [Error::InvalidArgument] if the transfer isn't in flight.
+    pub fn wait(&mut self) -> UsbResult<usize> {
+        if !self.in_flight {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut result = self.completion.result.lock().unwrap();
+        while result.is_none() {
+            result = self.completion.condvar.wait(result).unwrap();
+        }
+
+        self.in_flight = false;
+        result.clone().unwrap()
+    }
+
+    /// Provides access to the transfer's buffer; which, for reads, holds the data from the most
+    /// recent submission. Only the first `length` bytes of a completed read are meaningful.
+    pub fn with_data<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        match &self.buffer {
+            TransferBuffer::In(buffer) => f(&buffer.read()),
+            TransferBuffer::Out(data) => f(data),
+        }
+    }
+
+    /// Replaces the data sent by a write transfer, reusing its buffer where possible.
+    /// Fails with [Error::InvalidArgument] for read transfers, or if the transfer is in flight.
+    pub fn set_data(&mut self, new_data: &[u8]) -> UsbResult<()> {
+        if self.in_flight {
+            return Err(Error::InvalidArgument);
+        }
+
+        match &mut self.buffer {
+            TransferBuffer::In(_) => Err(Error::InvalidArgument),
+            TransferBuffer::Out(data) => {
+                // Once our last submission is done, we should hold the only reference; if a
+                // backend hasn't quite let go yet, this falls back to making a fresh copy.
+                let data = Arc::make_mut(data);
+                data.clear();
+                data.extend_from_slice(new_data);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Fixed-layout values read straight out of transfers; for e.g. the vendor status structs
+//! many devices return from a control request. See [crate::device::Device::control_read_struct].
+
+use std::mem::size_of;
+
+/// Types that can be built from any sequence of `size_of::<Self>()` bytes; in the spirit of
+/// zerocopy's trait of the same name.
+///
+/// Implemented for the integer types, and arrays of them. Integers are read in the host's
+/// byte order; USB sends everything little-endian, so big-endian hosts will want to convert
+/// with e.g. [u16::from_le] after reading.
+///
+/// # Safety
+/// Every bit pattern of the right size must be a valid value of the type. Implement this only
+/// for `#[repr(C)]` (or `#[repr(transparent)]`) types made up entirely of other `FromBytes`
+/// types; e.g. not for types containing bools, enums, references, or pointers. Padding bytes
+/// are allowed, but use `#[repr(C, packed)]` if the device's layout doesn't have them.
+pub unsafe trait FromBytes: Sized {
+    /// Builds a value from exactly `size_of::<Self>()` bytes; or returns None if the slice is
+    /// any other length.
+    fn read_from(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != size_of::<Self>() {
+            return None;
+        }
+
+        // SAFETY: our implementers promise any bytes make a valid Self, and we've checked we
+        // have exactly enough; read_unaligned doesn't care where they live.
+        Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+macro_rules! impl_from_bytes {
+    ($($type:ty),*) => {
+        $(unsafe impl FromBytes for $type {})*
+    };
+}
+
+impl_from_bytes!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+unsafe impl<T: FromBytes, const N: usize> FromBytes for [T; N] {}
@@ -0,0 +1,116 @@
+//! Friendly vendor and product names from the usb.ids database; for devices that don't
+//! provide string descriptors of their own.
+//!
+//! We read the copy of the database installed alongside e.g. `lsusb`; so names are only
+//! available where one is present.
+
+use std::{collections::HashMap, fs, sync::OnceLock};
+
+use crate::device::DeviceInformation;
+
+/// The places the usb.ids database is typically installed.
+const DATABASE_PATHS: &[&str] = &[
+    "/usr/share/hwdata/usb.ids",
+    "/usr/share/misc/usb.ids",
+    "/usr/share/usb.ids",
+    "/var/lib/usbutils/usb.ids",
+    "/usr/local/share/hwdata/usb.ids",
+    "/usr/local/share/misc/usb.ids",
+    "/opt/homebrew/share/usb.ids",
+];
+
+/// A vendor's name, and the names of its products.
+#[derive(Debug, Default)]
+struct Vendor {
+    name: String,
+    products: HashMap<u16, String>,
+}
+
+/// The parsed database; loaded the first time it's needed.
+static DATABASE: OnceLock<HashMap<u16, Vendor>> = OnceLock::new();
+
+/// Returns the database, loading it if we haven't yet. Empty if no database is installed.
+fn database() -> &'static HashMap<u16, Vendor> {
+    DATABASE.get_or_init(|| {
+        DATABASE_PATHS
+            .iter()
+            .find_map(|path| fs::read(path).ok())
+            .map(|raw| parse(&String::from_utf8_lossy(&raw)))
+            .unwrap_or_default()
+    })
+}
+
+/// Parses the vendor section of a usb.ids file.
+fn parse(text: &str) -> HashMap<u16, Vendor> {
+    let mut vendors: HashMap<u16, Vendor> = HashMap::new();
+    let mut current_vendor = None;
+
+    for line in text.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Products are indented by one tab beneath their vendor; interfaces, by two.
+        if let Some(product_line) = line.strip_prefix('\t') {
+            if let (Some(vendor_id), Some((product_id, name))) =
+                (current_vendor, parse_entry(product_line))
+            {
+                if let Some(vendor) = vendors.get_mut(&vendor_id) {
+                    vendor.products.insert(product_id, name.to_owned());
+                }
+            }
+            continue;
+        }
+
+        // Anything else at the top level is a vendor; or, once the vendors are done, the
+        // start of another section (device classes, HID usages, ...) that we don't use.
+        match parse_entry(line) {
+            Some((vendor_id, name)) => {
+                vendors.entry(vendor_id).or_default().name = name.to_owned();
+                current_vendor = Some(vendor_id);
+            }
+            None => break,
+        }
+    }
+
+    vendors
+}
+
+/// Parses a "1d50  OpenMoko, Inc." style entry.
+fn parse_entry(line: &str) -> Option<(u16, &str)> {
+    let (id, name) = line.split_once("  ")?;
+    if id.len() != 4 {
+        return None;
+    }
+
+    Some((u16::from_str_radix(id, 16).ok()?, name.trim()))
+}
+
+/// Returns the name the database gives the provided vendor, if any.
+pub fn vendor_name(vendor_id: u16) -> Option<&'static str> {
+    Some(database().get(&vendor_id)?.name.as_str())
+}
+
+/// Returns the name the database gives the provided product, if any.
+pub fn product_name(vendor_id: u16, product_id: u16) -> Option<&'static str> {
+    Some(
+        database()
+            .get(&vendor_id)?
+            .products
+            .get(&product_id)?
+            .as_str(),
+    )
+}
+
+/// Fills in any vendor or product names the OS didn't provide.
+pub(crate) fn with_names(mut information: DeviceInformation) -> DeviceInformation {
+    if information.vendor.is_none() {
+        information.vendor = vendor_name(information.vendor_id).map(str::to_owned);
+    }
+    if information.product.is_none() {
+        information.product =
+            product_name(information.vendor_id, information.product_id).map(str::to_owned);
+    }
+
+    information
+}
@@ -0,0 +1,70 @@
+//! Fixtures shared by our integration tests; which drive devices backed by a scripted mock.
+//! Each test binary uses only some of these.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use usrs::{
+    backend::{
+        mock::MockBackend,
+        record::{Operation, Record},
+    },
+    device::Device,
+    Error, Host,
+};
+
+/// The IDs our scripted devices claim.
+pub const VENDOR_ID: u16 = 0x1209;
+pub const PRODUCT_ID: u16 = 0x0001;
+
+/// Builds a record of the given operation, which succeeds with the given data.
+pub fn record(operation: Operation, result: &[u8]) -> Record {
+    Record {
+        operation,
+        result: Ok(result.to_vec()),
+    }
+}
+
+/// Builds a record of the given operation, which fails with the given error.
+pub fn failure(operation: Operation, error: Error) -> Record {
+    Record {
+        operation,
+        result: Err(error),
+    }
+}
+
+/// A read of up to `length` bytes from endpoint 0x81.
+pub fn read(length: usize) -> Operation {
+    Operation::Read {
+        endpoint: 0x81,
+        length,
+    }
+}
+
+/// A write of the provided data to endpoint 0x01.
+pub fn write(data: &[u8]) -> Operation {
+    Operation::Write {
+        endpoint: 0x01,
+        data: data.to_vec(),
+    }
+}
+
+/// Opens a device backed by a script of the provided records; which it opens before
+/// anything else. Returns the backend, too, so tests can check the script was used up.
+pub fn open_device(mut records: Vec<Record>) -> (Device, Arc<MockBackend>) {
+    records.insert(
+        0,
+        record(
+            Operation::Open {
+                vendor_id: VENDOR_ID,
+                product_id: PRODUCT_ID,
+            },
+            &[],
+        ),
+    );
+
+    let backend = Arc::new(MockBackend::new(records));
+    let host = Host::new_from_backend(Arc::clone(&backend) as _).unwrap();
+    let information = host.all_devices().unwrap().remove(0);
+    (host.open(&information).unwrap(), backend)
+}
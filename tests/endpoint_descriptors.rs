@@ -0,0 +1,57 @@
+//! Checks that a device only reads its configuration once for the endpoint descriptors its
+//! transfers need; and reads it again once the configuration may have changed. Each device is
+//! backed by a scripted mock, which fails any operation it wasn't expecting.
+
+mod common;
+
+use common::{open_device, record, write};
+use usrs::backend::record::{Operation, Record};
+
+/// A configuration with a single interface, which has a single interrupt OUT endpoint.
+const CONFIGURATION: [u8; 25] = [
+    9, 2, 25, 0, 1, 1, 0, 0x80, 50, // configuration
+    9, 4, 0, 0, 1, 0xff, 0, 0, 0, // interface
+    7, 5, 0x01, 0x03, 8, 0, 1, // interrupt OUT endpoint
+];
+
+/// The records for reading the active configuration's descriptor.
+fn configuration_read() -> Vec<Record> {
+    vec![
+        record(Operation::ActiveConfiguration, &[1]),
+        record(Operation::NumConfigurations, &[1]),
+        record(Operation::CachedConfigurationDescriptor(0), &CONFIGURATION),
+    ]
+}
+
+#[test]
+fn interrupt_writes_read_the_configuration_once() {
+    let mut records = configuration_read();
+    records.extend((1..=3).map(|data| record(write(&[data]), &[])));
+    let (mut device, backend) = open_device(records);
+
+    for data in 1..=3 {
+        assert_eq!(device.write_interrupt(0x01, &[data], None), Ok(1));
+    }
+    assert_eq!(backend.remaining(), 0);
+}
+
+#[test]
+fn changing_alternate_settings_rereads_the_configuration() {
+    let mut records = configuration_read();
+    records.push(record(write(&[1]), &[]));
+    records.push(record(
+        Operation::SetAlternateSetting {
+            interface: 0,
+            setting: 0,
+        },
+        &[],
+    ));
+    records.extend(configuration_read());
+    records.push(record(write(&[2]), &[]));
+    let (mut device, backend) = open_device(records);
+
+    assert_eq!(device.write_interrupt(0x01, &[1], None), Ok(1));
+    device.set_alternate_setting(0, 0).unwrap();
+    assert_eq!(device.write_interrupt(0x01, &[2], None), Ok(1));
+    assert_eq!(backend.remaining(), 0);
+}
@@ -0,0 +1,119 @@
+//! Checks that our futures can be spawned onto multi-threaded executors; which needs them,
+//! and the callbacks behind them, to be Send. Each device is backed by a scripted mock.
+
+#![cfg(feature = "async")]
+
+mod common;
+
+use std::sync::Arc;
+
+use common::{read, record, write};
+use usrs::{backend::record::Record, convenience::create_read_buffer, device::Device};
+
+/// The number of devices we drive at once.
+const TASKS: usize = 8;
+
+/// The number of read/write round trips each task performs.
+const ROUNDS: usize = 16;
+
+/// Builds a script that answers `ROUNDS` writes and reads; each read echoing back the
+/// preceding write.
+fn script(task: usize) -> Vec<Record> {
+    (0..ROUNDS)
+        .flat_map(|round| {
+            let data = [task as u8, round as u8];
+            [record(write(&data), &[]), record(read(64), &data)]
+        })
+        .collect()
+}
+
+/// Opens a device backed by the script for the given task.
+fn open_device(task: usize) -> Device {
+    common::open_device(script(task)).0
+}
+
+/// Runs a task's script against its device; checking that each read echoes its write.
+async fn echo(mut device: Device, task: usize) {
+    for round in 0..ROUNDS {
+        let data = Arc::new(vec![task as u8, round as u8]);
+        let written = device.write_async(0x01, data, None).unwrap().await.unwrap();
+        assert_eq!(written.length, 2);
+
+        let buffer = create_read_buffer(64);
+        let read = device
+            .read_async(0x81, buffer, None)
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(read.length, 2);
+        assert_eq!(
+            &read.buffer.write().as_mut()[..2],
+            &[task as u8, round as u8]
+        );
+    }
+}
+
+#[test]
+fn futures_run_on_tokio_multi_thread() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let tasks: Vec<_> = (0..TASKS)
+            .map(|task| tokio::spawn(echo(open_device(task), task)))
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+    });
+}
+
+#[test]
+fn futures_run_on_smol_executor() {
+    let executor = Arc::new(smol::Executor::new());
+    let (stop, stopped) = smol::channel::unbounded::<()>();
+
+    // Give the executor a few threads of its own; so tasks hop between them.
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let executor = Arc::clone(&executor);
+            let stopped = stopped.clone();
+            std::thread::spawn(move || smol::block_on(executor.run(stopped.recv())))
+        })
+        .collect();
+
+    smol::block_on(async {
+        let tasks: Vec<_> = (0..TASKS)
+            .map(|task| executor.spawn(echo(open_device(task), task)))
+            .collect();
+
+        for task in tasks {
+            task.await;
+        }
+    });
+
+    drop(stop);
+    for thread in threads {
+        let _ = thread.join().unwrap();
+    }
+}
+
+#[test]
+fn futures_can_be_awaited_on_another_thread() {
+    let mut device = open_device(0);
+
+    // The future is created on this thread, but its output is taken on a tokio worker.
+    let future = device
+        .write_async(0x01, Arc::new(vec![0, 0]), None)
+        .unwrap();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .build()
+        .unwrap();
+
+    let written = runtime.block_on(async { tokio::spawn(future).await.unwrap() });
+    assert_eq!(written.unwrap().length, 2);
+}
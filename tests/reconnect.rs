@@ -0,0 +1,35 @@
+//! Checks that waiting for a device to come back fails right away when there's nothing to
+//! recognize it by. The mock backend reports neither port paths nor serial numbers; so
+//! without that check, these would wait forever.
+
+mod common;
+
+use std::sync::Arc;
+
+use common::record;
+use usrs::{
+    backend::{mock::MockBackend, record::Operation},
+    device::Device,
+    Error,
+};
+
+/// Opens a device backed by a script that expects it to be reset; which it shouldn't be.
+fn open_device() -> (Device, Arc<MockBackend>) {
+    common::open_device(vec![record(Operation::ResetDevice, &[])])
+}
+
+#[test]
+fn unrecognizable_devices_cant_be_waited_for() {
+    let (device, _backend) = open_device();
+    assert!(matches!(
+        device.wait_for_reconnect(None),
+        Err(Error::Unsupported)
+    ));
+}
+
+#[test]
+fn unrecognizable_devices_arent_reset() {
+    let (mut device, backend) = open_device();
+    assert_eq!(device.reset_and_reopen(None), Err(Error::Unsupported));
+    assert_eq!(backend.remaining(), 1);
+}
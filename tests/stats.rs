@@ -0,0 +1,104 @@
+//! Checks that a device's statistics count every kind of synchronous transfer; not just plain
+//! reads and writes. Each device is backed by a scripted mock.
+
+mod common;
+
+use std::io::{IoSlice, IoSliceMut};
+
+use common::{failure, open_device, read, record, write};
+use usrs::{
+    backend::record::Operation,
+    device::{TransferTimeout, WriteOptions},
+    Error,
+};
+
+#[test]
+fn every_transfer_kind_is_counted() {
+    let (mut device, _) = open_device(vec![
+        record(read(8), &[1, 2, 3]),
+        record(read(4), &[4, 5]),
+        record(read(8), &[6]),
+        record(read(8), &[7, 8, 9, 10]),
+        record(write(&[1, 2, 3, 4]), &[]),
+        record(write(&[5, 6]), &[]),
+        record(
+            Operation::WriteWithZlp {
+                endpoint: 0x01,
+                data: vec![7; 3],
+            },
+            &[],
+        ),
+    ]);
+
+    // Uninitialized reads...
+    assert_eq!(device.read_to_uninit_vec(0x81, 8, None).unwrap(), [1, 2, 3]);
+
+    // ... vectored reads ...
+    let (mut first, mut second) = ([0; 2], [0; 2]);
+    let mut buffers = [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)];
+    assert_eq!(device.read_vectored(0x81, &mut buffers, None).unwrap(), 2);
+
+    // ... and reads with separate timeouts, into a vector and a slice.
+    let mut buffer = Vec::new();
+    assert_eq!(device.read_into_vec(0x81, &mut buffer, 8, None).unwrap(), 1);
+    let mut buffer = [0; 8];
+    let timeout = TransferTimeout::default();
+    assert_eq!(
+        device
+            .read_with_timeouts(0x81, &mut buffer, timeout)
+            .unwrap(),
+        4
+    );
+
+    // Likewise for writes.
+    let data = [IoSlice::new(&[1, 2]), IoSlice::new(&[3, 4])];
+    assert_eq!(device.write_vectored(0x01, &data, None).unwrap(), 4);
+    assert_eq!(
+        device
+            .write_with_timeouts(0x01, &[5, 6], TransferTimeout::default())
+            .unwrap(),
+        2
+    );
+    let options = WriteOptions {
+        append_zlp: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        device
+            .write_with_options(0x01, &[7; 3], &options, None)
+            .unwrap(),
+        3
+    );
+
+    let stats = device.stats();
+    assert_eq!(stats.endpoints[&0x81].transfers, 4);
+    assert_eq!(stats.bytes_in(), 10);
+    assert_eq!(stats.endpoints[&0x01].transfers, 3);
+    assert_eq!(stats.bytes_out(), 9);
+    assert_eq!(stats.errors.values().sum::<u64>(), 0);
+}
+
+#[test]
+fn failed_transfers_are_counted_as_errors() {
+    let (mut device, _) = open_device(vec![
+        failure(read(4), Error::TimedOut),
+        failure(write(&[1, 2]), Error::Stalled),
+    ]);
+
+    let mut buffer = [0; 4];
+    let mut buffers = [IoSliceMut::new(&mut buffer)];
+    assert_eq!(
+        device.read_vectored(0x81, &mut buffers, None),
+        Err(Error::TimedOut)
+    );
+    assert_eq!(
+        device.write_vectored(0x01, &[IoSlice::new(&[1, 2])], None),
+        Err(Error::Stalled)
+    );
+
+    let stats = device.stats();
+    assert_eq!(stats.endpoints[&0x81].errors, 1);
+    assert_eq!(stats.endpoints[&0x01].errors, 1);
+    assert_eq!(stats.errors["TimedOut"], 1);
+    assert_eq!(stats.errors["Stalled"], 1);
+}
@@ -0,0 +1,145 @@
+//! Checks how our transfer helpers handle transfers that come up short, and requests they
+//! can't describe. Each device is backed by a scripted mock.
+
+mod common;
+
+use common::{open_device, read, record};
+use usrs::{
+    backend::record::{Operation, Record},
+    device::{ControlData, Device},
+    request::{SetupPacket, VENDOR_IN_FROM_DEVICE, VENDOR_OUT_TO_DEVICE},
+    Error,
+};
+
+/// Opens a device that answers reads from endpoint 0x81 with each of the provided packets.
+fn device_sending(packets: &[&[u8]]) -> Device {
+    let records = packets
+        .iter()
+        .map(|packet| record(read(packet.len()), packet))
+        .collect();
+
+    open_device(records).0
+}
+
+/// A record of a vendor IN request, which the device answers with the provided data.
+fn vendor_read(data: &[u8]) -> Record {
+    record(
+        Operation::ControlRead {
+            request_type: VENDOR_IN_FROM_DEVICE.into(),
+            request_number: 1,
+            value: 0,
+            index: 0,
+            length: data.len(),
+        },
+        data,
+    )
+}
+
+#[test]
+fn read_exact_gathers_several_reads() {
+    let mut device = device_sending(&[&[1, 2], &[3, 4]]);
+
+    let mut buffer = [0; 4];
+    assert_eq!(device.read_exact(0x81, &mut buffer, None), Ok(()));
+    assert_eq!(buffer, [1, 2, 3, 4]);
+}
+
+#[test]
+fn read_exact_stops_at_an_empty_read() {
+    let mut device = device_sending(&[&[1, 2], &[]]);
+
+    let mut buffer = [0; 4];
+    assert_eq!(
+        device.read_exact(0x81, &mut buffer, None),
+        Err(Error::PartialTransfer(2))
+    );
+    assert_eq!(buffer[..2], [1, 2]);
+}
+
+#[test]
+fn oversized_control_requests_are_rejected() {
+    let (mut device, backend) = open_device(vec![vendor_read(&[])]);
+
+    // wLength can't describe this buffer; so it mustn't reach the device at all.
+    let mut target = vec![0; usize::from(u16::MAX) + 1];
+    assert_eq!(
+        device.control_read(VENDOR_IN_FROM_DEVICE, 1, 0, 0, &mut target, None),
+        Err(Error::InvalidArgument)
+    );
+    assert_eq!(backend.remaining(), 1);
+}
+
+#[test]
+fn raw_setup_packets_report_what_was_transferred() {
+    let (mut device, backend) = open_device(vec![vendor_read(&[1, 2])]);
+
+    let setup = SetupPacket::new(VENDOR_IN_FROM_DEVICE, 1, 0, 0, 4);
+    let mut target = [0; 4];
+    assert_eq!(
+        device.submit_setup(&setup, ControlData::In(&mut target), None),
+        Ok(2)
+    );
+    assert_eq!(target, [1, 2, 0, 0]);
+
+    // A data stage that doesn't go the way the packet says is a mistake on the caller's part.
+    let setup = SetupPacket::new(VENDOR_OUT_TO_DEVICE, 1, 0, 0, 2);
+    assert_eq!(
+        device.submit_setup(&setup, ControlData::In(&mut target), None),
+        Err(Error::InvalidArgument)
+    );
+    assert_eq!(backend.remaining(), 0);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn owned_reads_hand_back_their_buffers() {
+    let (mut device, _backend) = open_device(vec![
+        record(read(64), &[1, 2, 3]),
+        common::failure(read(64), Error::Disconnected),
+    ]);
+
+    let completion = smol::block_on(device.read_owned_async(0x81, vec![0; 64], None)).unwrap();
+    assert_eq!(completion.data(), Ok(&[1, 2, 3][..]));
+    assert_eq!(completion.buffer.len(), 64);
+
+    // A read that fails still gives its buffer back...
+    let completion = smol::block_on(device.read_owned_async(0x81, vec![0; 64], None)).unwrap();
+    assert_eq!(completion.result, Err(Error::Disconnected));
+    assert_eq!(completion.buffer.len(), 64);
+
+    // ... as does one that couldn't be submitted at all, now that the device is gone.
+    assert!(!device.is_connected());
+    let completion = smol::block_on(device.read_owned_async(0x81, vec![0; 32], None)).unwrap();
+    assert_eq!(completion.result, Err(Error::Disconnected));
+    assert_eq!(completion.buffer.len(), 32);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn owned_control_reads_hand_back_their_buffers() {
+    let (mut device, _backend) = open_device(vec![vendor_read(&[0xAA, 0xBB])]);
+
+    let completion = smol::block_on(device.control_read_owned_async(
+        VENDOR_IN_FROM_DEVICE,
+        1,
+        0,
+        0,
+        vec![0; 2],
+        None,
+    ))
+    .unwrap();
+    assert_eq!(completion.into_data(), Ok(vec![0xAA, 0xBB]));
+
+    // This request isn't in the script; so it fails, but the buffer still comes back.
+    let completion = smol::block_on(device.control_read_owned_async(
+        VENDOR_IN_FROM_DEVICE,
+        2,
+        0,
+        0,
+        vec![0; 8],
+        None,
+    ))
+    .unwrap();
+    assert!(completion.result.is_err());
+    assert_eq!(completion.buffer, vec![0; 8]);
+}